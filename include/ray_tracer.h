@@ -0,0 +1,87 @@
+#ifndef RAY_TRACER_H
+#define RAY_TRACER_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A renderer owned by C code. The GPU device is only opened once a scene is loaded.
+typedef struct RtRenderer RtRenderer;
+
+// A camera as C sees it, see [`Camera`].
+typedef struct RtCamera {
+  float eye[3];
+  float target[3];
+  float up[3];
+  // Vertical field of view in degrees.
+  float fovy;
+  float znear;
+  float zfar;
+} RtCamera;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a renderer without a scene. Free it with [`rt_renderer_destroy`].
+RtRenderer *rt_renderer_create(void);
+
+// Frees a renderer from [`rt_renderer_create`]. Null is ignored.
+//
+// # Safety
+//
+// `renderer` must be null or a renderer from [`rt_renderer_create`] not yet destroyed.
+void rt_renderer_destroy(RtRenderer *renderer);
+
+// Loads the scene file at `path`, replacing any scene loaded before. The camera moves to
+// the scene's own unless one was set before the first scene was loaded.
+//
+// # Safety
+//
+// `renderer` must come from [`rt_renderer_create`] and `path` must be a NUL-terminated
+// UTF-8 string.
+int rt_renderer_load_scene(RtRenderer *renderer, const char *path);
+
+// Moves the camera. Its projection is kept.
+//
+// # Safety
+//
+// `renderer` must come from [`rt_renderer_create`] and `camera` must point to an
+// [`RtCamera`].
+int rt_renderer_set_camera(RtRenderer *renderer, const RtCamera *camera);
+
+// Writes the current camera to `camera`.
+//
+// # Safety
+//
+// `renderer` must come from [`rt_renderer_create`] and `camera` must point to writable
+// memory for an [`RtCamera`].
+int rt_renderer_get_camera(const RtRenderer *renderer, RtCamera *camera);
+
+// Draws a `width` by `height` frame of the loaded scene into `pixels`, as rows of sRGB RGBA
+// bytes with premultiplied alpha, `stride` bytes apart from the top row down.
+//
+// # Safety
+//
+// `renderer` must come from [`rt_renderer_create`] and `pixels` must point to `length`
+// writable bytes.
+int rt_renderer_render(RtRenderer *renderer,
+                       uint32_t width,
+                       uint32_t height,
+                       uint8_t *pixels,
+                       size_t stride,
+                       size_t length);
+
+// Why the last failing call on this thread failed, or null if none has. The string stays
+// valid until the next failing call on the thread.
+const char *rt_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RAY_TRACER_H */
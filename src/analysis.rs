@@ -0,0 +1,55 @@
+use std::{fmt, str::FromStr};
+
+/// Luminance of middle grey, the centre of the false colour scale.
+const MIDDLE_GREY: f32 = 0.18;
+
+/// Exposure stops relative to middle grey and the colour each is drawn in. Must match
+/// `FALSE_COLOR_STOPS` in `shader.wgsl`; values in between are blended, and values beyond the
+/// ends clip to black and white.
+const FALSE_COLOR_STOPS: [(f32, [f32; 3]); 5] = [
+    (-4.0, [0.0, 0.0, 1.0]),
+    (-2.0, [0.0, 1.0, 1.0]),
+    (0.0, [0.0, 1.0, 0.0]),
+    (2.0, [1.0, 1.0, 0.0]),
+    (4.0, [1.0, 0.0, 0.0]),
+];
+
+/// Replaces shaded colours with a visualisation of some property of the image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AnalysisView {
+    /// Luminance in stops around middle grey, see [`AnalysisView::legend`].
+    Luminance,
+}
+
+impl AnalysisView {
+    /// The colour key of the view, as luminance values and the colour drawn at each.
+    pub fn legend(self) -> Vec<(f32, [f32; 3])> {
+        match self {
+            AnalysisView::Luminance => FALSE_COLOR_STOPS.iter()
+                .map(|&(stops, color)| (MIDDLE_GREY * stops.exp2(), color))
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for AnalysisView {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "luminance" | "false-color" => Ok(AnalysisView::Luminance),
+            _ => Err(format!("unknown analysis view {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for AnalysisView {
+    /// Prints the legend, one luminance value and colour per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?} legend:")?;
+        for (luminance, [r, g, b]) in self.legend() {
+            write!(f, "\n  {luminance:>8.4}  rgb({r}, {g}, {b})")?;
+        }
+        Ok(())
+    }
+}
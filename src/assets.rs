@@ -0,0 +1,262 @@
+//! Where scene files, and the buffers and images they refer to, are read from.
+//!
+//! Importers resolve every path through an [`AssetSource`], so a scene reads the same from
+//! disk, from files compiled into the binary, from a zip archive packaging it with its textures,
+//! or from a web server.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Signature of a zip archive's end of central directory record.
+const ZIP_END: u32 = 0x06054b50;
+/// Signature of a zip central directory entry.
+const ZIP_ENTRY: u32 = 0x02014b50;
+/// Bytes in an end of central directory record without its comment.
+const ZIP_END_SIZE: usize = 22;
+
+/// Reads files by path.
+pub trait AssetSource {
+    /// Reads the whole file at `path`, relative to the source's root.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// When the file at `path` last changed, or `None` if the source cannot tell. Hot reloading
+    /// watches sources that can.
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        let _ = path;
+        None
+    }
+}
+
+/// `path` as `/`-separated components, with `.` and `..` resolved.
+fn normalize(path: &Path) -> String {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy()),
+            Component::ParentDir => {
+                components.pop();
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    components.join("/")
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+/// Files on disk below a root directory, the working directory by default.
+#[derive(Clone, Debug, Default)]
+pub struct FileSystem {
+    root: PathBuf,
+}
+
+impl FileSystem {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl AssetSource for FileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(self.root.join(path))
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        fs::metadata(self.root.join(path)).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+/// Files held in memory, e.g. compiled in with `include_bytes!`.
+#[derive(Clone, Debug, Default)]
+pub struct Embedded {
+    files: HashMap<String, Cow<'static, [u8]>>,
+}
+
+impl Embedded {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file at `path`, replacing any file already there.
+    pub fn insert<P: AsRef<Path>>(&mut self, path: P, bytes: impl Into<Cow<'static, [u8]>>) {
+        self.files.insert(normalize(path.as_ref()), bytes.into());
+    }
+}
+
+impl AssetSource for Embedded {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.files.get(&normalize(path))
+            .map(|bytes| bytes.to_vec())
+            .ok_or_else(|| not_found(path))
+    }
+}
+
+/// Where a file's data is in a zip archive.
+#[derive(Copy, Clone, Debug)]
+struct ZipEntry {
+    /// 0 for stored, 8 for deflated.
+    method: u16,
+    /// Offset of the entry's local header.
+    offset: usize,
+    compressed_size: usize,
+}
+
+fn zip_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("zip: {message}"))
+}
+
+/// Files in a zip archive, stored or deflated, such as a scene packaged with its buffers and
+/// textures. Zip64 archives are not supported.
+pub struct ZipArchive {
+    bytes: Vec<u8>,
+    entries: HashMap<String, ZipEntry>,
+}
+
+impl ZipArchive {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    /// Reads the archive's central directory.
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        let u16_at = |offset: usize| bytes.get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| zip_error("truncated archive"));
+        let u32_at = |offset: usize| bytes.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| zip_error("truncated archive"));
+
+        // The end record is followed by a comment of at most 64 KiB.
+        let last = bytes.len().checked_sub(ZIP_END_SIZE).ok_or_else(|| zip_error("too short"))?;
+        let end = (last.saturating_sub(u16::MAX as usize)..=last).rev()
+            .find(|&offset| u32_at(offset).ok() == Some(ZIP_END))
+            .ok_or_else(|| zip_error("missing end of central directory"))?;
+        let count = u16_at(end + 10)?;
+        let mut offset = u32_at(end + 16)? as usize;
+
+        let mut entries = HashMap::with_capacity(count);
+        for _ in 0..count {
+            if u32_at(offset)? != ZIP_ENTRY {
+                return Err(zip_error("malformed central directory"));
+            }
+            let compressed_size = u32_at(offset + 20)?;
+            if compressed_size == u32::MAX {
+                return Err(zip_error("zip64 archives are not supported"));
+            }
+            let name_length = u16_at(offset + 28)?;
+            let name = bytes.get(offset + 46..offset + 46 + name_length)
+                .ok_or_else(|| zip_error("truncated archive"))?;
+            entries.insert(normalize(Path::new(&*String::from_utf8_lossy(name))), ZipEntry {
+                method: u16_at(offset + 10)? as u16,
+                offset: u32_at(offset + 42)? as usize,
+                compressed_size: compressed_size as usize,
+            });
+            offset += 46 + name_length + u16_at(offset + 30)? + u16_at(offset + 32)?;
+        }
+        Ok(Self { bytes, entries })
+    }
+
+    /// Paths of the files in the archive.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+}
+
+impl AssetSource for ZipArchive {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let entry = self.entries.get(&normalize(path)).ok_or_else(|| not_found(path))?;
+        let u16_at = |offset: usize| self.bytes.get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| zip_error("truncated archive"));
+        // The local header repeats the name, and its extra field may differ from the central one.
+        let start = entry.offset + 30 + u16_at(entry.offset + 26)? + u16_at(entry.offset + 28)?;
+        let data = self.bytes.get(start..start + entry.compressed_size)
+            .ok_or_else(|| zip_error("truncated archive"))?;
+        match entry.method {
+            0 => Ok(data.to_vec()),
+            8 => miniz_oxide::inflate::decompress_to_vec(data)
+                .map_err(|err| zip_error(&format!("{err:?}"))),
+            method => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("zip: compression method {method} is not supported"),
+            )),
+        }
+    }
+}
+
+/// Files served over plain HTTP below a base URL, for streaming scenes from a server.
+///
+/// Each file is fetched with its own request. HTTPS and redirects are not supported; sources
+/// that need them can be built on an HTTP client by implementing [`AssetSource`].
+#[derive(Clone, Debug)]
+pub struct Http {
+    /// `host:port` to connect to.
+    address: String,
+    host: String,
+    /// Path of the base URL, without a trailing `/`.
+    base: String,
+}
+
+impl Http {
+    /// Serves files below `url`, e.g. `http://localhost:8000/scenes`.
+    pub fn new(url: &str) -> io::Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{url} is not a plain http:// URL"),
+        ))?;
+        let (authority, base) = rest.split_once('/').unwrap_or((rest, ""));
+        let address = if authority.contains(':') {
+            authority.to_owned()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok(Self {
+            address,
+            host: authority.to_owned(),
+            base: base.trim_end_matches('/').to_owned(),
+        })
+    }
+}
+
+impl AssetSource for Http {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let file = normalize(path)
+            .split('/')
+            .map(|segment| urlencoding::encode(segment).into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        let target = if self.base.is_empty() {
+            format!("/{file}")
+        } else {
+            format!("/{}/{file}", self.base)
+        };
+
+        // HTTP/1.0 responses end with the connection, so they are never chunked.
+        let mut stream = TcpStream::connect(&self.address)?;
+        write!(stream, "GET {target} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", self.host)?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+
+        let header_end = response.windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "HTTP: malformed response"))?;
+        let status = String::from_utf8_lossy(&response[..header_end])
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse::<u16>().ok());
+        match status {
+            Some(200) => Ok(response.split_off(header_end + 4)),
+            Some(404) => Err(not_found(path)),
+            status => Err(io::Error::other(format!("HTTP: GET {target} returned {status:?}"))),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// Rayleigh scattering coefficients relative to green, for red, green and blue light.
+const RAYLEIGH: [f32; 3] = [0.43, 1.0, 2.45];
+
+/// Exponential height fog with wavelength-dependent scattering, applied to every shaded surface.
+///
+/// Light is attenuated along the view ray by the integrated fog density and replaced by light
+/// scattered in from the fog, so distant geometry fades towards `color` and blue fades first.
+/// Scenes can supply one as `extras.atmosphere` on their glTF scene.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Atmosphere {
+    /// Extinction per scene unit at height zero.
+    pub density: f32,
+    /// Rate at which density falls off with height. `0.0` gives uniform fog.
+    pub falloff: f32,
+    /// Colour of the light scattered towards the camera.
+    pub color: [f32; 3],
+    /// Strength of the wavelength-dependent part of the extinction.
+    pub rayleigh: f32,
+    /// Brightening of the fog towards the sun, when a sky is set.
+    pub sun_glow: f32,
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Self {
+            density: 0.01,
+            falloff: 0.1,
+            color: [0.7, 0.78, 0.88],
+            rayleigh: 0.5,
+            sun_glow: 1.0,
+        }
+    }
+}
+
+impl Atmosphere {
+    /// Per-channel extinction at height zero.
+    pub(crate) fn extinction(&self) -> [f32; 3] {
+        RAYLEIGH.map(|rayleigh| self.density * (1.0 + self.rayleigh * rayleigh))
+    }
+}
@@ -0,0 +1,449 @@
+//! Drives scene parameters from what the system is playing, for live visuals.
+//!
+//! The scene file maps frequency bands of the audio to parameters in its `extras.audio`:
+//!
+//! ```text
+//! {"audio": {"bands": 8, "mappings": [
+//!     {"band": 0, "target": "scale", "mesh": "Speaker", "min": 1, "max": 1.3},
+//!     {"band": 5, "target": "brightness", "material": "Neon", "min": 0.2, "max": 4}
+//! ]}}
+//! ```
+//!
+//! Audio is captured with `cpal` from the default input device, in builds with the `audio`
+//! feature. To follow what the system plays rather than a microphone, make the monitor of the
+//! output the default input, e.g. with `pactl set-default-source` on PulseAudio and PipeWire.
+
+use std::{
+    f32::consts::TAU,
+    sync::mpsc::{Receiver, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ObjectId,
+    Scene,
+    Vec3,
+};
+
+/// Samples analysed at once, about 46 ms at 44.1 kHz.
+const WINDOW: usize = 2048;
+
+/// Frequency range in hertz the bands split up, evenly on a logarithmic scale.
+const LOWEST: f32 = 40.0;
+const HIGHEST: f32 = 16000.0;
+
+/// Longest step a frame lets the levels fall by, so a stalled frame doesn't drop them.
+const MAX_STEP: Duration = Duration::from_millis(100);
+
+/// How a scene's parameters follow the audio, read from the scene's `extras.audio`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioReactive {
+    /// Frequency bands the audio is split into, from low to high.
+    pub bands: usize,
+    /// Multiplies the levels before they are clamped to one.
+    pub gain: f32,
+    /// Level in decibels below full scale that counts as silence.
+    pub floor: f32,
+    /// Seconds a level takes to fall to about a third once the sound stops; rises are immediate.
+    pub release: f32,
+    pub mappings: Vec<AudioMapping>,
+}
+
+impl Default for AudioReactive {
+    fn default() -> Self {
+        Self {
+            bands: 8,
+            gain: 1.0,
+            floor: -60.0,
+            release: 0.2,
+            mappings: Vec::new(),
+        }
+    }
+}
+
+/// A parameter following the level of a band, `min` in silence and `max` at full level.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AudioMapping {
+    pub band: usize,
+    #[serde(flatten)]
+    pub target: AudioTarget,
+    #[serde(default)]
+    pub min: f32,
+    #[serde(default = "one")]
+    pub max: f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+/// What an [`AudioMapping`] drives.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "target", rename_all = "lowercase")]
+pub enum AudioTarget {
+    /// Factor on the colour of the named material, which the raster path draws unlit, so it
+    /// stands in for emissive strength.
+    Brightness { material: String },
+    /// Size of the named mesh about the centre of its bounds.
+    Scale { mesh: String },
+    /// Offset of the named mesh along `by`, which the mapped value multiplies.
+    Move { mesh: String, by: Vec3 },
+    /// [`Settings::explode`](crate::Settings::explode).
+    Explode,
+}
+
+/// Parameters to apply for a frame, only those that changed.
+#[derive(Default)]
+pub(crate) struct AudioFrame {
+    pub(crate) explode: Option<f32>,
+    /// Materials with their new colour.
+    pub(crate) colors: Vec<(usize, [f32; 4])>,
+    /// Meshes with their vertices in their new place.
+    pub(crate) meshes: Vec<(usize, Vec<Vec3>)>,
+}
+
+/// A mesh the mappings move, as loaded.
+struct DrivenMesh {
+    mesh: usize,
+    vertices: Vec<Vec3>,
+    center: Vec3,
+    /// Scale and offset it was last drawn with.
+    shown: (f32, Vec3),
+}
+
+/// A material the mappings brighten, with its colour as loaded.
+struct DrivenMaterial {
+    material: usize,
+    color: [f32; 4],
+    shown: f32,
+}
+
+/// What a mapping drives, resolved to indices into the driver's meshes and materials.
+#[derive(Copy, Clone)]
+enum Driven {
+    Brightness(usize),
+    Scale(usize),
+    Move(usize, Vec3),
+    Explode,
+}
+
+#[derive(Copy, Clone)]
+struct Mapped {
+    band: usize,
+    min: f32,
+    max: f32,
+    driven: Driven,
+}
+
+/// Captures audio and maps its levels to the loaded scene's parameters.
+pub(crate) struct AudioDriver {
+    capture: Capture,
+    /// Latest samples, oldest first.
+    window: Vec<f32>,
+    config: AudioReactive,
+    /// Level of each band from zero to one.
+    levels: Vec<f32>,
+    mappings: Vec<Mapped>,
+    meshes: Vec<DrivenMesh>,
+    materials: Vec<DrivenMaterial>,
+    explode: Option<f32>,
+    last_step: Instant,
+}
+
+impl AudioDriver {
+    /// Starts capturing, or returns `None` if there is nothing to capture from.
+    pub(crate) fn new() -> Option<Self> {
+        let capture = capture()?;
+        Some(Self {
+            capture,
+            window: vec![0.0; WINDOW],
+            config: AudioReactive::default(),
+            levels: Vec::new(),
+            mappings: Vec::new(),
+            meshes: Vec::new(),
+            materials: Vec::new(),
+            explode: None,
+            last_step: Instant::now(),
+        })
+    }
+
+    /// Maps the parameters of `scene`'s `extras.audio`, if it has any, and forgets those of
+    /// the scene before. Mappings naming nothing in the scene are skipped.
+    pub(crate) fn load(&mut self, scene: &Scene) {
+        self.config = scene.audio.clone().unwrap_or_default();
+        self.levels = vec![0.0; self.config.bands];
+        self.mappings.clear();
+        self.meshes.clear();
+        self.materials.clear();
+        self.explode = None;
+        for mapping in &self.config.mappings {
+            let driven = match &mapping.target {
+                AudioTarget::Brightness { material: name } => {
+                    let Some(material) = find(scene, name, |object| match object {
+                        ObjectId::Material(material) => Some(material),
+                        _ => None,
+                    }) else {
+                        log::warn!("Audio mapping refers to no material called {name:?}");
+                        continue;
+                    };
+                    let index = self.materials.iter().position(|driven| driven.material == material).unwrap_or_else(|| {
+                        self.materials.push(DrivenMaterial {
+                            material,
+                            color: scene.materials().nth(material).map_or([1.0; 4], |info| info.color),
+                            shown: 1.0,
+                        });
+                        self.materials.len() - 1
+                    });
+                    Driven::Brightness(index)
+                }
+                AudioTarget::Scale { mesh: name } | AudioTarget::Move { mesh: name, .. } => {
+                    let Some(mesh) = find(scene, name, |object| match object {
+                        ObjectId::Mesh(mesh) => Some(mesh),
+                        _ => None,
+                    }) else {
+                        log::warn!("Audio mapping refers to no mesh called {name:?}");
+                        continue;
+                    };
+                    let index = self.meshes.iter().position(|driven| driven.mesh == mesh).unwrap_or_else(|| {
+                        let vertices = scene.mesh_vertices(mesh).to_vec();
+                        let (min, max) = vertices.iter().fold(
+                            (vec3![f32::MAX, f32::MAX, f32::MAX], vec3![f32::MIN, f32::MIN, f32::MIN]),
+                            |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)),
+                        );
+                        self.meshes.push(DrivenMesh {
+                            mesh,
+                            vertices,
+                            center: (min + max) * 0.5,
+                            shown: (1.0, Vec3::default()),
+                        });
+                        self.meshes.len() - 1
+                    });
+                    match mapping.target {
+                        AudioTarget::Move { by, .. } => Driven::Move(index, by),
+                        _ => Driven::Scale(index),
+                    }
+                }
+                AudioTarget::Explode => Driven::Explode,
+            };
+            self.mappings.push(Mapped {
+                band: mapping.band,
+                min: mapping.min,
+                max: mapping.max,
+                driven,
+            });
+        }
+        if !self.mappings.is_empty() {
+            log::info!("Driving {} parameters from audio", self.mappings.len());
+        }
+    }
+
+    /// Level of each band from zero to one, as of the last frame.
+    pub(crate) fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+
+    /// Analyses the latest audio and returns the parameters whose mapped values changed.
+    pub(crate) fn drive(&mut self) -> AudioFrame {
+        let now = Instant::now();
+        let step = (now - self.last_step).min(MAX_STEP).as_secs_f32();
+        self.last_step = now;
+        loop {
+            match self.capture.samples.try_recv() {
+                Ok(samples) => self.window.extend(samples),
+                Err(TryRecvError::Empty) => break,
+                // The capture stopped, which sounds like silence from now on.
+                Err(TryRecvError::Disconnected) => {
+                    self.window.fill(0.0);
+                    break;
+                }
+            }
+        }
+        self.window.drain(..self.window.len() - WINDOW);
+
+        let spectrum = spectrum(&self.window);
+        let fall = (-step / self.config.release.max(0.001)).exp();
+        let bands = self.levels.len();
+        for (band, level) in self.levels.iter_mut().enumerate() {
+            let sample_rate = self.capture.sample_rate;
+            *level = band_level(&spectrum, sample_rate, band, bands, &self.config).max(*level * fall);
+        }
+
+        // Mappings onto the same mesh or material combine: scales and brightness multiply and
+        // offsets add.
+        let mut placements = vec![(1.0, Vec3::default()); self.meshes.len()];
+        let mut brightness = vec![1.0; self.materials.len()];
+        let mut explode = None;
+        for mapped in &self.mappings {
+            let level = self.levels.get(mapped.band).copied().unwrap_or(0.0);
+            let value = mapped.min + (mapped.max - mapped.min) * level;
+            match mapped.driven {
+                Driven::Brightness(index) => brightness[index] *= value,
+                Driven::Scale(index) => placements[index].0 *= value,
+                Driven::Move(index, by) => placements[index].1 = placements[index].1 + by * value,
+                Driven::Explode => explode = Some(value),
+            }
+        }
+
+        let mut frame = AudioFrame::default();
+        if explode.is_some() && explode != self.explode {
+            self.explode = explode;
+            frame.explode = explode;
+        }
+        for (driven, value) in self.materials.iter_mut().zip(brightness) {
+            if value != driven.shown {
+                driven.shown = value;
+                let [r, g, b, a] = driven.color;
+                frame.colors.push((driven.material, [r * value, g * value, b * value, a]));
+            }
+        }
+        for (driven, placement) in self.meshes.iter_mut().zip(placements) {
+            if placement != driven.shown {
+                driven.shown = placement;
+                let (scale, offset) = placement;
+                let center = driven.center;
+                let vertices = driven.vertices.iter().map(|&v| center + (v - center) * scale + offset).collect();
+                frame.meshes.push((driven.mesh, vertices));
+            }
+        }
+        frame
+    }
+}
+
+/// The first object called `name` that `pick` accepts.
+fn find(scene: &Scene, name: &str, pick: impl Fn(ObjectId) -> Option<usize>) -> Option<usize> {
+    scene.find(name).into_iter().find_map(pick)
+}
+
+/// Audio being captured, mixed down to mono.
+struct Capture {
+    /// Stops capturing when dropped.
+    #[cfg(feature = "audio")]
+    _stream: cpal::Stream,
+    samples: Receiver<Vec<f32>>,
+    /// Samples per second.
+    sample_rate: f32,
+}
+
+/// Starts capturing from the default input device.
+#[cfg(feature = "audio")]
+fn capture() -> Option<Capture> {
+    use cpal::traits::HostTrait;
+
+    let Some(device) = cpal::default_host().default_input_device() else {
+        log::warn!("Can't capture audio: there is no input device");
+        return None;
+    };
+    capture_from(&device)
+        .inspect(|capture| log::info!("Capturing audio at {} Hz", capture.sample_rate))
+        .inspect_err(|err| log::warn!("Can't capture audio: {err}"))
+        .ok()
+}
+
+#[cfg(feature = "audio")]
+fn capture_from(device: &cpal::Device) -> Result<Capture, String> {
+    use cpal::{
+        SampleFormat,
+        traits::{DeviceTrait, StreamTrait},
+    };
+
+    let config = device.default_input_config().map_err(|err| err.to_string())?;
+    let (sender, samples) = std::sync::mpsc::channel();
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => stream::<f32>(device, config.config(), sender),
+        SampleFormat::I16 => stream::<i16>(device, config.config(), sender),
+        SampleFormat::U16 => stream::<u16>(device, config.config(), sender),
+        SampleFormat::I32 => stream::<i32>(device, config.config(), sender),
+        format => return Err(format!("unsupported sample format {format}")),
+    }
+    .map_err(|err| err.to_string())?;
+    stream.play().map_err(|err| err.to_string())?;
+    Ok(Capture {
+        _stream: stream,
+        samples,
+        sample_rate: config.sample_rate() as f32,
+    })
+}
+
+#[cfg(not(feature = "audio"))]
+fn capture() -> Option<Capture> {
+    log::warn!("Capturing audio needs the viewer built with the `audio` feature");
+    None
+}
+
+/// Input stream from `device` sending its samples, averaged over the channels, as they arrive.
+#[cfg(feature = "audio")]
+fn stream<T>(
+    device: &cpal::Device,
+    config: cpal::StreamConfig,
+    sender: std::sync::mpsc::Sender<Vec<f32>>,
+) -> Result<cpal::Stream, cpal::Error>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    use cpal::traits::DeviceTrait;
+
+    let channels = usize::from(config.channels).max(1);
+    let data = move |data: &[T], _: &cpal::InputCallbackInfo| {
+        let samples = data.chunks(channels)
+            .map(|frame| frame.iter().map(|&sample| sample.to_sample::<f32>()).sum::<f32>() / channels as f32)
+            .collect();
+        // The driver was dropped, which stops the stream soon after.
+        let _ = sender.send(samples);
+    };
+    device.build_input_stream(config, data, |err| log::warn!("Audio capture failed: {err}"), None)
+}
+
+/// Magnitudes of the lower half of the spectrum of `samples`, whose length is a power of two,
+/// under a Hann window.
+fn spectrum(samples: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    let mut re: Vec<f32> = samples.iter().enumerate()
+        .map(|(i, sample)| sample * (0.5 - 0.5 * (TAU * i as f32 / n as f32).cos()))
+        .collect();
+    let mut im = vec![0.0; n];
+    // Radix-2 FFT over the samples in bit-reversed order.
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let half = len / 2;
+        let angle = -TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..half {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + half);
+                let (tr, ti) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len *= 2;
+    }
+    (0..n / 2).map(|k| re[k].hypot(im[k])).collect()
+}
+
+/// Level of band `band` of `bands` of audio at `sample_rate`, from zero to one: its loudest
+/// frequency between the floor and full scale, times the gain.
+fn band_level(spectrum: &[f32], sample_rate: f32, band: usize, bands: usize, config: &AudioReactive) -> f32 {
+    let resolution = sample_rate / (2 * spectrum.len()) as f32;
+    let edge = |band: usize| LOWEST * (HIGHEST / LOWEST).powf(band as f32 / bands as f32) / resolution;
+    let first = (edge(band).ceil() as usize).min(spectrum.len() - 1);
+    let last = (edge(band + 1).ceil() as usize).clamp(first + 1, spectrum.len());
+    let peak = spectrum[first..last].iter().copied().fold(0.0, f32::max);
+    // A full-scale sine peaks at a quarter of the window under the Hann window.
+    let amplitude = peak * 4.0 / (2 * spectrum.len()) as f32;
+    let decibels = 20.0 * amplitude.max(1e-9).log10();
+    let floor = config.floor.min(-1.0);
+    ((decibels - floor) / -floor * config.gain).clamp(0.0, 1.0)
+}
@@ -0,0 +1,229 @@
+//! A photograph drawn behind the scene, fixed to the camera.
+
+use std::path::Path;
+
+use bytemuck::{Pod, Zeroable};
+
+use wgpu::{
+    util::{
+        BufferInitDescriptor,
+        DeviceExt,
+        TextureDataOrder,
+    },
+    AddressMode,
+    BindGroup,
+    BindGroupDescriptor,
+    BindGroupEntry,
+    BindGroupLayout,
+    BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry,
+    BindingResource,
+    BindingType,
+    BufferBindingType,
+    BufferUsages,
+    ColorTargetState,
+    Device,
+    Extent3d,
+    FilterMode,
+    FragmentState,
+    MultisampleState,
+    PipelineCompilationOptions,
+    PipelineLayoutDescriptor,
+    PrimitiveState,
+    Queue,
+    RenderPass,
+    RenderPipeline,
+    RenderPipelineDescriptor,
+    SamplerBindingType,
+    SamplerDescriptor,
+    ShaderStages,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureSampleType,
+    TextureUsages,
+    TextureViewDescriptor,
+    TextureViewDimension,
+    VertexState,
+};
+
+use crate::{
+    memory::MemoryTracker,
+    shaders,
+    stereo::Channels,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct BackplateUniform {
+    /// Width over height of the image.
+    aspect: f32,
+    _padding: [f32; 3],
+}
+
+/// Fills each viewport with an image, scaled to cover it and cropped to its aspect ratio.
+pub(crate) struct BackplatePass {
+    /// One pipeline per [`Channels`] variant.
+    pipelines: [RenderPipeline; 3],
+    bind_group: BindGroup,
+}
+
+impl BackplatePass {
+    /// Loads the image at `path`, logging why and returning `None` if it can't be read.
+    pub(crate) fn load(
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        camera_bind_group_layout: &BindGroupLayout,
+        memory: &mut MemoryTracker,
+        path: &Path,
+    ) -> Option<Self> {
+        let image = match image::open(path) {
+            Ok(image) => image.to_rgba8(),
+            Err(err) => {
+                log::warn!("Ignoring backplate {}: {err}", path.display());
+                return None;
+            }
+        };
+        let shader = shaders::create_module(device, "Backplate shader", include_str!("backplate.wgsl"), &[], &[]);
+
+        let (width, height) = image.dimensions();
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Backplate buffer"),
+            contents: bytemuck::cast_slice(&[BackplateUniform {
+                aspect: width as f32 / height as f32,
+                _padding: [0.0; 3],
+            }]),
+            usage: BufferUsages::UNIFORM,
+        });
+        memory.track(&buffer);
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("Backplate texture"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            image.as_raw(),
+        );
+        memory.track_bytes(image.as_raw().len() as u64);
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Backplate sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("backplate_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("backplate_bind_group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Backplate Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = Channels::ALL.map(|channels| device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Backplate Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: channels.write_mask(),
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        }));
+
+        Some(Self {
+            pipelines,
+            bind_group,
+        })
+    }
+
+    /// Fills the current viewport with the image, using the aspect ratio of the camera bound at
+    /// `camera_bind_group`.
+    pub(crate) fn draw(&self, render_pass: &mut RenderPass, camera_bind_group: &BindGroup, channels: Channels) {
+        render_pass.set_pipeline(&self.pipelines[channels as usize]);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
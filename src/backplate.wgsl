@@ -0,0 +1,29 @@
+#include "camera.wgsl"
+#include "fullscreen.wgsl"
+
+struct Backplate {
+    aspect: f32,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> backplate: Backplate;
+@group(1) @binding(1) var image: texture_2d<f32>;
+@group(1) @binding(2) var image_sampler: sampler;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+) -> VertexOutput {
+    return fullscreen_triangle(index, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    // Covers the viewport, cropping whichever side of the image is too long for it.
+    var scale = vec2f(1.0, camera.aspect / backplate.aspect);
+    if camera.aspect < backplate.aspect {
+        scale = vec2f(backplate.aspect / camera.aspect, 1.0);
+    }
+    let uv = vec2f(in.ndc.x, -in.ndc.y) / scale * 0.5 + 0.5;
+    return vec4f(textureSample(image, image_sampler, uv).rgb, 1.0);
+}
@@ -0,0 +1,134 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use wgpu::{
+    Extent3d,
+    Maintain,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureUsages,
+    TextureViewDescriptor,
+};
+
+use crate::{
+    Scene,
+    Settings,
+    renderer::{self, Renderer},
+};
+
+pub struct BenchOptions {
+    pub frames: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Writes per-frame timings to this file as CSV.
+    pub csv_path: Option<PathBuf>,
+    /// Degrees the camera orbits the scene over the whole run, for a camera path that exercises
+    /// every side of it. `None` keeps the camera still.
+    pub turntable: Option<f32>,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            frames: 500,
+            width: 1920,
+            height: 1080,
+            csv_path: None,
+            turntable: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BenchReport {
+    pub frames: u32,
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub p99_ms: f64,
+    /// Millions of primary rays per second, one per pixel per frame.
+    pub mrays_per_second: f64,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "frames:  {}", self.frames)?;
+        writeln!(f, "min:     {:.3} ms", self.min_ms)?;
+        writeln!(f, "avg:     {:.3} ms", self.avg_ms)?;
+        writeln!(f, "p99:     {:.3} ms", self.p99_ms)?;
+        write!(f, "Mrays/s: {:.2}", self.mrays_per_second)
+    }
+}
+
+/// Renders `scene` offscreen for a fixed number of frames and reports frame times.
+///
+/// Each frame waits for the GPU to finish, so the timings cover CPU encoding and GPU execution.
+pub fn run(scene: Scene, settings: Settings, options: &BenchOptions) -> io::Result<BenchReport> {
+    let (device, queue, max_buffer_size) = renderer::headless_device();
+
+    let format = TextureFormat::Rgba8UnormSrgb;
+    let target = device.create_texture(&TextureDescriptor {
+        label: Some("Bench target"),
+        size: Extent3d {
+            width: options.width,
+            height: options.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let view = target.create_view(&TextureViewDescriptor::default());
+
+    let mut renderer = Renderer::new(
+        device,
+        queue,
+        format,
+        max_buffer_size,
+        scene,
+        settings,
+    );
+
+    let camera = renderer.camera;
+    let center = renderer.scene_stats.center();
+    let mut frame_times = Vec::with_capacity(options.frames as usize);
+    for frame in 0..options.frames {
+        if let Some(degrees) = options.turntable {
+            renderer.camera = camera.orbit(center, degrees * frame as f32 / options.frames as f32);
+        }
+        let start = Instant::now();
+        renderer.render(&view, options.width, options.height, renderer.clock());
+        renderer.device.poll(Maintain::Wait);
+        frame_times.push(start.elapsed().as_secs_f64() * 1e3);
+    }
+
+    if let Some(path) = &options.csv_path {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "frame,milliseconds")?;
+        for (frame, milliseconds) in frame_times.iter().enumerate() {
+            writeln!(out, "{frame},{milliseconds:.4}")?;
+        }
+        out.flush()?;
+    }
+
+    let total: f64 = frame_times.iter().sum();
+    let mut sorted = frame_times;
+    sorted.sort_by(f64::total_cmp);
+    let frames = sorted.len().max(1);
+    let pixels = options.width as f64 * options.height as f64 * sorted.len() as f64;
+    Ok(BenchReport {
+        frames: options.frames,
+        min_ms: sorted.first().copied().unwrap_or_default(),
+        avg_ms: total / frames as f64,
+        p99_ms: sorted.get(((frames - 1) as f64 * 0.99).round() as usize).copied().unwrap_or_default(),
+        mrays_per_second: if total > 0.0 { pixels / (total / 1e3) / 1e6 } else { 0.0 },
+    })
+}
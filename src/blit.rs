@@ -0,0 +1,239 @@
+use image::RgbaImage;
+use wgpu::{
+    util::{DeviceExt, TextureDataOrder},
+    AddressMode,
+    BindGroup,
+    BindGroupDescriptor,
+    BindGroupEntry,
+    BindGroupLayout,
+    BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry,
+    BindingResource,
+    BindingType,
+    ColorTargetState,
+    ColorWrites,
+    CommandEncoder,
+    Device,
+    Extent3d,
+    FilterMode,
+    FragmentState,
+    LoadOp,
+    MultisampleState,
+    Operations,
+    Queue,
+    PipelineCompilationOptions,
+    PipelineLayoutDescriptor,
+    PrimitiveState,
+    RenderPassColorAttachment,
+    RenderPassDescriptor,
+    RenderPipeline,
+    RenderPipelineDescriptor,
+    Sampler,
+    SamplerBindingType,
+    SamplerDescriptor,
+    ShaderStages,
+    StoreOp,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureSampleType,
+    TextureUsages,
+    TextureView,
+    TextureViewDescriptor,
+    TextureViewDimension,
+    VertexState,
+    include_wgsl,
+};
+
+/// An offscreen colour target or an image that can be stretched onto another view.
+pub(crate) struct BlitSource {
+    pub(crate) view: TextureView,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    bind_group: BindGroup,
+}
+
+/// Copies a texture onto a render target of any size with bilinear filtering.
+pub(crate) struct Blitter {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+    format: TextureFormat,
+}
+
+impl Blitter {
+    pub(crate) fn new(device: &Device, format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("blit.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("blit_bind_group_layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Blit Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Blit sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            format,
+        }
+    }
+
+    /// Creates an offscreen target in the blitter's format.
+    pub(crate) fn source(&self, device: &Device, width: u32, height: u32) -> BlitSource {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Blit source"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: self.format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        self.bind(device, texture.create_view(&TextureViewDescriptor::default()), width, height)
+    }
+
+    /// Uploads `image`, in sRGB, to be stretched onto targets.
+    pub(crate) fn image_source(&self, device: &Device, queue: &Queue, image: &RgbaImage) -> BlitSource {
+        let (width, height) = image.dimensions();
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("Blit image"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                // Targets without sRGB encoding take the encoded colours as they are.
+                format: if self.format.is_srgb() { TextureFormat::Rgba8UnormSrgb } else { TextureFormat::Rgba8Unorm },
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            image.as_raw(),
+        );
+        self.bind(device, texture.create_view(&TextureViewDescriptor::default()), width, height)
+    }
+
+    fn bind(&self, device: &Device, view: TextureView, width: u32, height: u32) -> BlitSource {
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("blit_bind_group"),
+        });
+        BlitSource {
+            view,
+            width,
+            height,
+            bind_group,
+        }
+    }
+
+    pub(crate) fn blit(&self, encoder: &mut CommandEncoder, source: &BlitSource, target: &TextureView) {
+        self.blit_inner(encoder, source, target, None);
+    }
+
+    /// Stretches `source` over `target` as [`Blitter::blit`] does, but only draws inside
+    /// `scissor`, given as x, y, width and height in pixels.
+    pub(crate) fn blit_clipped(&self, encoder: &mut CommandEncoder, source: &BlitSource, target: &TextureView, scissor: [u32; 4]) {
+        self.blit_inner(encoder, source, target, Some(scissor));
+    }
+
+    fn blit_inner(&self, encoder: &mut CommandEncoder, source: &BlitSource, target: &TextureView, scissor: Option<[u32; 4]>) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Blit Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        if let Some([x, y, width, height]) = scissor {
+            render_pass.set_scissor_rect(x, y, width, height);
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &source.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
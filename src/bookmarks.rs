@@ -0,0 +1,85 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+
+/// A named camera pose.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub camera: Camera,
+}
+
+/// Camera bookmarks of a scene, persisted to a JSON file next to it.
+#[derive(Default)]
+pub(crate) struct Bookmarks {
+    path: PathBuf,
+    entries: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// Loads the bookmarks stored beside `scene_path`, starting empty if there are none.
+    pub(crate) fn load(scene_path: &Path) -> Self {
+        let path = scene_path.with_extension("bookmarks.json");
+        let entries = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                log::warn!("Ignoring invalid bookmarks in {}: {err}", path.display());
+                vec![]
+            }),
+            Err(_) => vec![],
+        };
+        Self { path, entries }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.entries)
+            .map_err(|err| err.to_string())
+            .and_then(|json| fs::write(&self.path, json).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            log::error!("Failed to save bookmarks to {}: {err}", self.path.display());
+        }
+    }
+
+    pub(crate) fn entries(&self) -> &[Bookmark] {
+        &self.entries
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Bookmark> {
+        self.entries.iter().find(|bookmark| bookmark.name == name)
+    }
+
+    /// Stores `camera` under `name`, replacing any bookmark with that name.
+    pub(crate) fn set(&mut self, name: String, camera: Camera) {
+        match self.entries.iter_mut().find(|bookmark| bookmark.name == name) {
+            Some(bookmark) => bookmark.camera = camera,
+            None => self.entries.push(Bookmark { name, camera }),
+        }
+        self.save();
+    }
+
+    /// Stores `camera` in the bookmark at `index`, appending a new one past the end.
+    pub(crate) fn set_slot(&mut self, index: usize, camera: Camera) {
+        match self.entries.get_mut(index) {
+            Some(bookmark) => bookmark.camera = camera,
+            None => self.entries.push(Bookmark {
+                name: format!("Bookmark {}", self.entries.len() + 1),
+                camera,
+            }),
+        }
+        self.save();
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|bookmark| bookmark.name != name);
+        let removed = self.entries.len() != len;
+        if removed {
+            self.save();
+        }
+        removed
+    }
+}
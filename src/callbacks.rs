@@ -0,0 +1,48 @@
+use crate::SceneStats;
+
+/// A frame of the interactive viewer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameEvent {
+    /// Frames drawn since the window opened, counting this one.
+    pub frame: u64,
+    /// Resolution scale the frame is drawn at, below `1.0` while refining after interaction.
+    pub scale: f32,
+}
+
+type FrameCallback = Box<dyn FnMut(FrameEvent)>;
+type SceneCallback = Box<dyn FnMut(&SceneStats)>;
+
+/// Closures an embedding application registered on [`RayTracer`](crate::RayTracer).
+#[derive(Default)]
+pub(crate) struct Callbacks {
+    pub(crate) frame_start: Option<FrameCallback>,
+    pub(crate) scene_loaded: Option<SceneCallback>,
+    pub(crate) sample_complete: Option<FrameCallback>,
+    pub(crate) render_finished: Option<FrameCallback>,
+}
+
+impl Callbacks {
+    pub(crate) fn frame_start(&mut self, event: FrameEvent) {
+        if let Some(callback) = &mut self.frame_start {
+            callback(event);
+        }
+    }
+
+    pub(crate) fn scene_loaded(&mut self, stats: &SceneStats) {
+        if let Some(callback) = &mut self.scene_loaded {
+            callback(stats);
+        }
+    }
+
+    pub(crate) fn sample_complete(&mut self, event: FrameEvent) {
+        if let Some(callback) = &mut self.sample_complete {
+            callback(event);
+        }
+    }
+
+    pub(crate) fn render_finished(&mut self, event: FrameEvent) {
+        if let Some(callback) = &mut self.render_finished {
+            callback(event);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{CameraUniform, Vec3};
+
+/// Column-major 4x4 matrix, laid out the way WGSL expects a `mat4x4f`.
+pub(crate) type Mat4 = [[f32; 4]; 4];
+
+pub(crate) const IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// How the camera maps view directions onto the image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Parallel projection framing what the perspective view shows at the target distance.
+    Orthographic,
+    /// Equidistant fisheye covering `fovy` degrees across the image height.
+    Fisheye,
+    /// Full 360° by 180° latitude-longitude panorama, e.g. for environment maps.
+    Equirectangular,
+}
+
+impl Projection {
+    fn index(self) -> u32 {
+        match self {
+            Projection::Perspective => 0,
+            Projection::Orthographic => 1,
+            Projection::Fisheye => 2,
+            Projection::Equirectangular => 3,
+        }
+    }
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" | "ortho" => Ok(Projection::Orthographic),
+            "fisheye" => Ok(Projection::Fisheye),
+            "equirectangular" | "equirect" | "360" => Ok(Projection::Equirectangular),
+            _ => Err(format!("unknown projection {s:?}")),
+        }
+    }
+}
+
+/// A camera looking from `eye` towards `target`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view in degrees.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    #[serde(default)]
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    /// Frames the `[-1, 1]` square on the `z = 0` plane vertically, matching clip space.
+    fn default() -> Self {
+        Self {
+            eye: vec3![0.0, 0.0, 2.0],
+            target: vec3![0.0, 0.0, 0.0],
+            up: vec3![0.0, 1.0, 0.0],
+            fovy: 2.0 * 0.5f32.atan().to_degrees(),
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        }
+    }
+}
+
+impl Camera {
+    fn view(&self) -> Mat4 {
+        let f = (self.target - self.eye).normalize();
+        let s = f.cross(self.up).normalize();
+        let u = s.cross(f);
+        [
+            [s.x, u.x, -f.x, 0.0],
+            [s.y, u.y, -f.y, 0.0],
+            [s.z, u.z, -f.z, 0.0],
+            [-s.dot(self.eye), -u.dot(self.eye), f.dot(self.eye), 1.0],
+        ]
+    }
+
+    fn perspective(&self, fovy: f32, aspect: f32) -> Mat4 {
+        let f = 1.0 / (fovy.to_radians() / 2.0).tan();
+        let range = self.znear - self.zfar;
+        [
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, self.zfar / range, -1.0],
+            [0.0, 0.0, self.znear * self.zfar / range, 0.0],
+        ]
+    }
+
+    fn orthographic(&self, aspect: f32) -> Mat4 {
+        let height = (self.target - self.eye).length() * (self.fovy.to_radians() / 2.0).tan();
+        let width = height * aspect;
+        let range = self.znear - self.zfar;
+        [
+            [1.0 / width, 0.0, 0.0, 0.0],
+            [0.0, 1.0 / height, 0.0, 0.0],
+            [0.0, 0.0, 1.0 / range, 0.0],
+            [0.0, 0.0, self.znear / range, 1.0],
+        ]
+    }
+
+    /// Linear projection of the camera. The spherical projections cannot be expressed as a
+    /// matrix, so they get a wide perspective approximation, good enough for culling and LOD.
+    fn projection_matrix(&self, aspect: f32) -> Mat4 {
+        match self.projection {
+            Projection::Perspective => self.perspective(self.fovy, aspect),
+            Projection::Orthographic => self.orthographic(aspect),
+            Projection::Fisheye | Projection::Equirectangular => {
+                self.perspective(self.fovy.min(120.0), aspect)
+            }
+        }
+    }
+
+    /// A default camera placed by `transform`, looking down its local -Z axis with +Y up, with
+    /// its target `distance` ahead. This is how glTF and USD place cameras.
+    pub(crate) fn from_transform(transform: &Mat4, distance: f32) -> Self {
+        let eye = transform_point(transform, vec3![0.0, 0.0, 0.0]);
+        let forward = (transform_point(transform, vec3![0.0, 0.0, -1.0]) - eye).normalize();
+        let up = (transform_point(transform, vec3![0.0, 1.0, 0.0]) - eye).normalize();
+        Self {
+            eye,
+            target: eye + forward * distance,
+            up,
+            ..Self::default()
+        }
+    }
+
+    /// Returns this camera looking the same way at the centre of the box `min`..`max`, far
+    /// enough back for the sphere around it to fill the height of the view, with its clip
+    /// planes set to the size of the box.
+    pub fn frame(&self, min: Vec3, max: Vec3) -> Self {
+        let center = (min + max) * 0.5;
+        let radius = ((max - min).length() * 0.5).max(1e-3);
+        let forward = self.target - self.eye;
+        let forward = if forward.length() > 0.0 { forward.normalize() } else { vec3![0.0, 0.0, -1.0] };
+        let distance = radius / (self.fovy.to_radians() * 0.5).sin();
+        Self {
+            eye: center - forward * distance,
+            target: center,
+            znear: radius * 0.01,
+            zfar: (distance + radius) * 2.0,
+            ..*self
+        }
+    }
+
+    /// Returns this camera rotated by `degrees` around the vertical axis through `center`.
+    pub fn orbit(&self, center: Vec3, degrees: f32) -> Self {
+        self.rotated(center, self.up.normalize(), degrees)
+    }
+
+    /// Returns this camera rotated by `degrees` around its horizontal axis through `center`,
+    /// positive looking further down, stopping short of looking straight up or down.
+    pub fn tilt(&self, center: Vec3, degrees: f32) -> Self {
+        let forward = (self.target - self.eye).normalize();
+        let up = self.up.normalize();
+        // Angle between looking straight down and looking along `forward`.
+        let pitch = forward.dot(up).clamp(-1.0, 1.0).acos().to_degrees();
+        let degrees = degrees.clamp(1.0 - pitch, 179.0 - pitch);
+        self.rotated(center, up.cross(forward).normalize(), degrees)
+    }
+
+    /// Returns this camera moved towards its target, `factor` times as far from it.
+    pub fn dolly(&self, factor: f32) -> Self {
+        Self {
+            eye: self.target + (self.eye - self.target) * factor,
+            ..*self
+        }
+    }
+
+    /// Returns this camera moved right by `x` and up by `y` times the height it sees at its
+    /// target, keeping the view direction.
+    pub fn pan(&self, x: f32, y: f32) -> Self {
+        let forward = self.target - self.eye;
+        let height = forward.length() * (self.fovy.to_radians() * 0.5).tan() * 2.0;
+        let right = forward.cross(self.up).normalize();
+        let up = right.cross(forward).normalize();
+        let offset = (right * x + up * y) * height;
+        Self {
+            eye: self.eye + offset,
+            target: self.target + offset,
+            ..*self
+        }
+    }
+
+    /// Returns this camera moved `forward` along its view direction and `right` across it, in
+    /// world units.
+    pub fn walk(&self, forward: f32, right: f32) -> Self {
+        let direction = (self.target - self.eye).normalize();
+        let offset = direction * forward + direction.cross(self.up).normalize() * right;
+        Self {
+            eye: self.eye + offset,
+            target: self.target + offset,
+            ..*self
+        }
+    }
+
+    /// This camera rotated by `degrees` around the unit `axis` through `center`.
+    fn rotated(&self, center: Vec3, axis: Vec3, degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        // Rodrigues' rotation formula.
+        let rotate = |p: Vec3| {
+            let v = p - center;
+            center + v * cos + axis.cross(v) * sin + axis * (axis.dot(v) * (1.0 - cos))
+        };
+        Self {
+            eye: rotate(self.eye),
+            target: rotate(self.target),
+            ..*self
+        }
+    }
+
+    /// Returns this camera moved sideways by `offset` along its right vector, keeping the view
+    /// direction parallel.
+    pub(crate) fn shifted(&self, offset: f32) -> Self {
+        let right = (self.target - self.eye).cross(self.up).normalize() * offset;
+        Self {
+            eye: self.eye + right,
+            target: self.target + right,
+            ..*self
+        }
+    }
+
+    /// World-space ray through `ndc`, matching `view_direction` in the shaders.
+    ///
+    /// Returns the ray origin and its normalized direction.
+    pub(crate) fn ray(&self, ndc: [f32; 2], aspect: f32) -> (Vec3, Vec3) {
+        let f = (self.target - self.eye).normalize();
+        let s = f.cross(self.up).normalize();
+        let u = s.cross(f);
+        let [x, y] = ndc;
+        let (origin, [dx, dy, dz]) = match self.projection {
+            Projection::Perspective => {
+                let scale = (self.fovy.to_radians() / 2.0).tan();
+                (self.eye, [x * scale * aspect, y * scale, -1.0])
+            }
+            Projection::Orthographic => {
+                let height = (self.target - self.eye).length() * (self.fovy.to_radians() / 2.0).tan();
+                (self.eye + s * (x * height * aspect) + u * (y * height), [0.0, 0.0, -1.0])
+            }
+            Projection::Fisheye => {
+                let (ox, oy) = (x * aspect, y);
+                let radius = (ox * ox + oy * oy).sqrt();
+                let theta = (radius * self.fovy.to_radians() * 0.5).min(std::f32::consts::PI);
+                let (rx, ry) = if radius > 1e-6 { (ox / radius, oy / radius) } else { (0.0, 0.0) };
+                (self.eye, [rx * theta.sin(), ry * theta.sin(), -theta.cos()])
+            }
+            Projection::Equirectangular => {
+                let longitude = x * std::f32::consts::PI;
+                let latitude = y * std::f32::consts::FRAC_PI_2;
+                let direction = [
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                ];
+                (self.eye, direction)
+            }
+        };
+        (origin, (s * dx + u * dy - f * dz).normalize())
+    }
+
+    pub(crate) fn view_proj(&self, aspect: f32) -> Mat4 {
+        mul(&self.projection_matrix(aspect), &self.view())
+    }
+
+    pub(crate) fn uniform(&self, aspect: f32) -> CameraUniform {
+        CameraUniform {
+            view: self.view(),
+            proj: self.projection_matrix(aspect),
+            projection: self.projection.index(),
+            fov: self.fovy.to_radians(),
+            aspect,
+            znear: self.znear,
+            zfar: self.zfar,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+pub(crate) fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+    for (column, out) in out.iter_mut().enumerate() {
+        for (row, value) in out.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    out
+}
+
+/// Transforms a point by an affine or projective matrix, dividing by `w`.
+pub(crate) fn transform_point(m: &Mat4, p: Vec3) -> Vec3 {
+    let [x, y, z, w] = transform(m, p);
+    vec3![x / w, y / w, z / w]
+}
+
+/// Transforms a point, returning homogeneous clip coordinates.
+pub(crate) fn transform(m: &Mat4, p: Vec3) -> [f32; 4] {
+    let mut out = [0.0; 4];
+    for (row, value) in out.iter_mut().enumerate() {
+        *value = m[0][row] * p.x + m[1][row] * p.y + m[2][row] * p.z + m[3][row];
+    }
+    out
+}
@@ -0,0 +1,58 @@
+//! Section planes cutting away part of the scene to show its inside.
+//!
+//! Planes clip meshes, point clouds and volumes as they are drawn, and the rays cast for
+//! picking, inspection and measurement pass through what they cut away. Shadows are still
+//! cast by the whole scene.
+
+use serde::{Deserialize, Serialize};
+
+use crate::Vec3;
+
+/// Planes beyond this many are ignored.
+pub const MAX_CLIP_PLANES: usize = 4;
+
+/// Removes everything on the side of a plane that its normal points to.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClipPlane {
+    /// Any point on the plane.
+    pub point: Vec3,
+    /// Points away from the part of the scene that is kept. It needn't be normalized.
+    pub normal: Vec3,
+}
+
+impl ClipPlane {
+    /// Whether `position` is cut away.
+    pub(crate) fn clips(&self, position: Vec3) -> bool {
+        (position - self.point).dot(self.normal) > 0.0
+    }
+
+    /// Unit normal with the plane's distance from the origin along it in w. Zero for a plane
+    /// without a normal, like the plane itself clipping nothing.
+    fn uniform(&self) -> [f32; 4] {
+        if self.normal == Vec3::default() {
+            return [0.0; 4];
+        }
+        let normal = self.normal.normalize();
+        [normal.x, normal.y, normal.z, normal.dot(self.point)]
+    }
+}
+
+/// The first [`MAX_CLIP_PLANES`] of `planes` for the shaders. Unused planes are zero, which
+/// clips nothing.
+pub(crate) fn uniforms(planes: &[ClipPlane]) -> [[f32; 4]; MAX_CLIP_PLANES] {
+    let mut uniforms = [[0.0; 4]; MAX_CLIP_PLANES];
+    for (uniform, plane) in uniforms.iter_mut().zip(planes) {
+        *uniform = plane.uniform();
+    }
+    uniforms
+}
+
+/// Whether any of the first [`MAX_CLIP_PLANES`] of `planes` can clip anything.
+pub(crate) fn any_active(planes: &[ClipPlane]) -> bool {
+    planes.iter().take(MAX_CLIP_PLANES).any(|plane| plane.normal != Vec3::default())
+}
+
+/// Whether any of the first [`MAX_CLIP_PLANES`] of `planes` cuts away `position`.
+pub(crate) fn clipped(planes: &[ClipPlane], position: Vec3) -> bool {
+    planes.iter().take(MAX_CLIP_PLANES).any(|plane| plane.clips(position))
+}
@@ -0,0 +1,23 @@
+//! Copies images to the system clipboard with `arboard`, on the desktop platforms it supports.
+
+use std::io;
+
+use image::RgbaImage;
+
+/// Puts `image`, in sRGB with premultiplied alpha, on the clipboard.
+#[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+pub(crate) fn copy_image(image: &RgbaImage) -> io::Result<()> {
+    // The clipboard takes straight alpha.
+    let image = crate::headless::straight_alpha(image);
+    let data = arboard::ImageData {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        bytes: image.into_raw().into(),
+    };
+    arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_image(data)).map_err(io::Error::other)
+}
+
+#[cfg(any(target_arch = "wasm32", target_os = "android"))]
+pub(crate) fn copy_image(_image: &RgbaImage) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "there is no clipboard on this platform"))
+}
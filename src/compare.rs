@@ -0,0 +1,517 @@
+//! Compares renders with a reference image, for validating changes to the renderer.
+//!
+//! The viewer lays the reference over the view in one of the [`CompareMode`]s and reports how
+//! far each finished frame is from it: the root-mean-square error of its linear colours, and the
+//! mean error of LDR-FLIP, which models how visible differences are to someone flipping between
+//! the two images (Andersson et al., "FLIP: A Difference Evaluator for Alternating Images", 2020).
+
+use std::{
+    array,
+    borrow::Cow,
+    f32::consts::{PI, SQRT_2},
+    fmt,
+    path::Path,
+    str::FromStr,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::Instant,
+};
+
+use image::{
+    ImageResult,
+    Rgba,
+    RgbaImage,
+    imageops::{self, FilterType},
+};
+use wgpu::{CommandEncoder, Device, Queue, TextureView};
+
+use crate::{
+    blit::{BlitSource, Blitter},
+    exr,
+};
+
+/// Pixels per degree of the viewer's field of view, FLIP's default of a 0.7 m wide 4K monitor
+/// seen from 0.7 m.
+const PIXELS_PER_DEGREE: f32 = 67.0;
+
+/// Seconds each image is shown for when flipping between them.
+const FLIP_INTERVAL: f32 = 0.5;
+
+/// Contrast sensitivity of the achromatic, red-green and blue-yellow channels, each a sum of
+/// Gaussians given by their amplitude and width.
+const CONTRAST_SENSITIVITY: [&[(f32, f32)]; 3] = [&[(1.0, 0.0047)], &[(1.0, 0.0053)], &[(34.1, 0.04), (13.5, 0.025)]];
+
+/// Width of the edge and point detectors, in degrees.
+const FEATURE_WIDTH: f32 = 0.082;
+
+/// Exponent compressing colour differences.
+const COLOR_EXPONENT: f32 = 0.7;
+
+/// Exponent compressing feature differences.
+const FEATURE_EXPONENT: f32 = 0.5;
+
+/// Fraction of the largest colour difference mapped to [`ERROR_KNEE`], beyond which differences
+/// are compressed.
+const COLOR_KNEE: f32 = 0.4;
+
+const ERROR_KNEE: f32 = 0.95;
+
+/// CIE XYZ of linear sRGB white.
+const WHITE: [f32; 3] = [0.950_456, 1.0, 1.089_058];
+
+/// Colours of the difference heatmap from no error to the most, the magma colour map.
+const MAGMA: [[u8; 3]; 10] = [
+    [0x00, 0x00, 0x04],
+    [0x18, 0x0f, 0x3d],
+    [0x44, 0x0f, 0x76],
+    [0x72, 0x1f, 0x81],
+    [0x9e, 0x2f, 0x7f],
+    [0xcd, 0x40, 0x71],
+    [0xf1, 0x60, 0x5d],
+    [0xfd, 0x96, 0x68],
+    [0xfe, 0xc9, 0x8d],
+    [0xfc, 0xfd, 0xbf],
+];
+
+/// How the reference is laid over the view.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CompareMode {
+    /// The reference left of the cursor, the render right of it.
+    #[default]
+    Wipe,
+    /// The reference and the render in turn, twice a second.
+    Flip,
+    /// The FLIP error of each pixel of the last finished frame, from black through purple to
+    /// pale yellow for the most visible.
+    Difference,
+}
+
+impl CompareMode {
+    /// The mode after `self`, going back to the first after the last.
+    pub fn next(self) -> Self {
+        match self {
+            CompareMode::Wipe => CompareMode::Flip,
+            CompareMode::Flip => CompareMode::Difference,
+            CompareMode::Difference => CompareMode::Wipe,
+        }
+    }
+}
+
+impl FromStr for CompareMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wipe" => Ok(CompareMode::Wipe),
+            "flip" => Ok(CompareMode::Flip),
+            "difference" | "diff" => Ok(CompareMode::Difference),
+            _ => Err(format!("unknown comparison mode {s:?}, expected wipe, flip or difference")),
+        }
+    }
+}
+
+impl fmt::Display for CompareMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CompareMode::Wipe => "wipe",
+            CompareMode::Flip => "flip",
+            CompareMode::Difference => "difference",
+        })
+    }
+}
+
+/// How far an image is from a reference, both from 0 for identical images.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImageMetrics {
+    /// Root-mean-square difference of the linear colour channels.
+    pub rmse: f32,
+    /// Mean FLIP error, up to 1 where every pixel differs as much as can be seen.
+    pub flip: f32,
+}
+
+impl fmt::Display for ImageMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RMSE {:.5}, FLIP {:.5}", self.rmse, self.flip)
+    }
+}
+
+/// An image compared with a reference.
+pub struct ImageDifference {
+    pub metrics: ImageMetrics,
+    /// FLIP error of each pixel in the magma colour map.
+    pub heatmap: RgbaImage,
+}
+
+/// Compares `image` with `reference`, which is stretched to its size if they differ. Both are
+/// sRGB with straight alpha, as PNG files are, and compared over black.
+pub fn difference(reference: &RgbaImage, image: &RgbaImage) -> ImageDifference {
+    let (width, height) = image.dimensions();
+    let reference = match reference.dimensions() == (width, height) {
+        true => Cow::Borrowed(reference),
+        false => Cow::Owned(imageops::resize(reference, width, height, FilterType::Triangle)),
+    };
+    let (reference, image) = (linear(&reference), linear(image));
+    let count = reference.len().max(1) as f32;
+    let squared: f32 = reference.iter().zip(&image)
+        .flat_map(|(a, b)| (0..3).map(move |c| (a[c] - b[c]) * (a[c] - b[c])))
+        .sum();
+    let errors = flip(&reference, &image, width as usize);
+    ImageDifference {
+        metrics: ImageMetrics {
+            rmse: (squared / (3.0 * count)).sqrt(),
+            flip: errors.iter().sum::<f32>() / count,
+        },
+        heatmap: RgbaImage::from_fn(width, height, |x, y| magma(errors[(y * width + x) as usize])),
+    }
+}
+
+/// Linear colours of `image` over black.
+fn linear(image: &RgbaImage) -> Vec<[f32; 3]> {
+    image.pixels()
+        .map(|pixel| [0, 1, 2].map(|c| exr::srgb_to_linear(pixel[c]) * pixel[3] as f32 / 255.0))
+        .collect()
+}
+
+fn magma(error: f32) -> Rgba<u8> {
+    let position = error.clamp(0.0, 1.0) * (MAGMA.len() - 1) as f32;
+    let index = (position as usize).min(MAGMA.len() - 2);
+    let t = position - index as f32;
+    let [from, to] = [MAGMA[index], MAGMA[index + 1]];
+    let [r, g, b] = array::from_fn(|c| (from[c] as f32 + (to[c] as f32 - from[c] as f32) * t).round() as u8);
+    Rgba([r, g, b, 255])
+}
+
+/// FLIP error of each pixel of `image`, `width` pixels wide, against `reference`.
+fn flip(reference: &[[f32; 3]], image: &[[f32; 3]], width: usize) -> Vec<f32> {
+    let (reference_lab, reference_features) = perceive(reference, width);
+    let (lab, features) = perceive(image, width);
+    let max_color = hyab(hunt_lab([0.0, 1.0, 0.0]), hunt_lab([0.0, 0.0, 1.0])).powf(COLOR_EXPONENT);
+    let knee = COLOR_KNEE * max_color;
+    (0..image.len())
+        .map(|i| {
+            let color = hyab(reference_lab[i], lab[i]).powf(COLOR_EXPONENT);
+            let color = match color < knee {
+                true => ERROR_KNEE / knee * color,
+                false => ERROR_KNEE + (color - knee) / (max_color - knee) * (1.0 - ERROR_KNEE),
+            };
+            let [edges, points] = [0, 1].map(|f| (reference_features[f][i] - features[f][i]).abs());
+            let feature = (edges.max(points) / SQRT_2).powf(FEATURE_EXPONENT);
+            color.powf(1.0 - feature)
+        })
+        .collect()
+}
+
+/// Hunt-adjusted L*a*b* colours of an image as the eye resolves them at [`PIXELS_PER_DEGREE`],
+/// and the strength of the edges and points in its lightness.
+fn perceive(image: &[[f32; 3]], width: usize) -> (Vec<[f32; 3]>, [Vec<f32>; 2]) {
+    let opponent: Vec<[f32; 3]> = image.iter().map(|&rgb| ycxcz(rgb)).collect();
+    let channels: [Vec<f32>; 3] = array::from_fn(|c| {
+        let channel: Vec<f32> = opponent.iter().map(|pixel| pixel[c]).collect();
+        contrast_filter(&channel, width, CONTRAST_SENSITIVITY[c])
+    });
+    let lab = (0..image.len())
+        .map(|i| hunt_lab(from_ycxcz([channels[0][i], channels[1][i], channels[2][i]]).map(|c| c.clamp(0.0, 1.0))))
+        .collect();
+    let lightness: Vec<f32> = opponent.iter().map(|pixel| (pixel[0] + 16.0) / 116.0).collect();
+    (lab, [features(&lightness, width, false), features(&lightness, width, true)])
+}
+
+/// Blurs a channel by the contrast sensitivity function made of `gaussians`, normalised over
+/// the filter's footprint.
+fn contrast_filter(channel: &[f32], width: usize, gaussians: &[(f32, f32)]) -> Vec<f32> {
+    let widest = CONTRAST_SENSITIVITY.iter().flat_map(|gaussians| gaussians.iter().map(|&(_, b)| b)).fold(0.0, f32::max);
+    let radius = (3.0 * (widest / (2.0 * PI * PI)).sqrt() * PIXELS_PER_DEGREE).ceil() as i32;
+    let mut filtered = vec![0.0; channel.len()];
+    let mut total = 0.0;
+    for &(a, b) in gaussians {
+        let kernel: Vec<f32> = (-radius..=radius)
+            .map(|x| (-PI * PI * (x as f32 / PIXELS_PER_DEGREE).powi(2) / b).exp())
+            .collect();
+        let sum: f32 = kernel.iter().sum();
+        // Each Gaussian is separable; their sum is blurred by each in turn, weighted by its
+        // share of the two-dimensional filter.
+        let weight = a * (PI / b).sqrt() * sum * sum;
+        let kernel: Vec<f32> = kernel.iter().map(|w| w / sum).collect();
+        for (out, value) in filtered.iter_mut().zip(convolve(channel, width, &kernel, &kernel)) {
+            *out += weight * value;
+        }
+        total += weight;
+    }
+    filtered.iter().map(|value| value / total).collect()
+}
+
+/// Strength of edges in a lightness channel by its first derivative, or of points by its
+/// second.
+fn features(lightness: &[f32], width: usize, points: bool) -> Vec<f32> {
+    let sd = 0.5 * FEATURE_WIDTH * PIXELS_PER_DEGREE;
+    let radius = (3.0 * sd).ceil() as i32;
+    let gaussian: Vec<f32> = (-radius..=radius).map(|x| (-(x * x) as f32 / (2.0 * sd * sd)).exp()).collect();
+    let derivative: Vec<f32> = (-radius..=radius).zip(&gaussian)
+        .map(|(x, g)| match points {
+            true => ((x * x) as f32 / (sd * sd) - 1.0) * g,
+            false => -x as f32 * g,
+        })
+        .collect();
+    // Positive and negative weights each sum to one, so flat regions have no features.
+    let positive: f32 = derivative.iter().filter(|&&w| w > 0.0).sum();
+    let negative: f32 = -derivative.iter().filter(|&&w| w < 0.0).sum::<f32>();
+    let derivative: Vec<f32> = derivative.iter().map(|&w| if w > 0.0 { w / positive } else { w / negative }).collect();
+    let sum: f32 = gaussian.iter().sum();
+    let gaussian: Vec<f32> = gaussian.iter().map(|g| g / sum).collect();
+    let x = convolve(lightness, width, &derivative, &gaussian);
+    let y = convolve(lightness, width, &gaussian, &derivative);
+    x.iter().zip(&y).map(|(x, y)| x.hypot(*y)).collect()
+}
+
+/// Convolves a `width` wide channel with `row` along its rows and `column` down its columns,
+/// repeating the edge pixels beyond its borders.
+fn convolve(channel: &[f32], width: usize, row: &[f32], column: &[f32]) -> Vec<f32> {
+    if channel.is_empty() {
+        return Vec::new();
+    }
+    let height = channel.len() / width;
+    let tap = |position: usize, offset: usize, radius: usize, size: usize| (position + offset).saturating_sub(radius).min(size - 1);
+    let mut rows = vec![0.0; channel.len()];
+    for (y, line) in channel.chunks_exact(width).enumerate() {
+        for x in 0..width {
+            rows[y * width + x] = row.iter().enumerate().map(|(i, w)| w * line[tap(x, i, row.len() / 2, width)]).sum();
+        }
+    }
+    let mut out = vec![0.0; channel.len()];
+    for y in 0..height {
+        for x in 0..width {
+            out[y * width + x] = column.iter().enumerate()
+                .map(|(i, w)| w * rows[tap(y, i, column.len() / 2, height) * width + x])
+                .sum();
+        }
+    }
+    out
+}
+
+/// Linear sRGB to CIE XYZ.
+fn xyz([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+        0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b,
+        0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b,
+    ]
+}
+
+/// CIE XYZ to linear sRGB.
+fn linear_rgb([x, y, z]: [f32; 3]) -> [f32; 3] {
+    [
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    ]
+}
+
+/// Linear sRGB to YCxCz, an opponent space like L*a*b* but linear in XYZ, so it can be blurred.
+fn ycxcz(rgb: [f32; 3]) -> [f32; 3] {
+    let [x, y, z] = array::from_fn(|c| xyz(rgb)[c] / WHITE[c]);
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+fn from_ycxcz([lightness, cx, cz]: [f32; 3]) -> [f32; 3] {
+    let y = (lightness + 16.0) / 116.0;
+    let [x, z] = [cx / 500.0 + y, y - cz / 200.0];
+    linear_rgb([x * WHITE[0], y, z * WHITE[2]])
+}
+
+/// Linear sRGB to L*a*b* with the chroma scaled by lightness, as the Hunt effect makes dark
+/// colours look less saturated.
+fn hunt_lab(rgb: [f32; 3]) -> [f32; 3] {
+    let xyz = xyz(rgb);
+    let [x, y, z] = array::from_fn(|c| {
+        let t = xyz[c] / WHITE[c];
+        let delta: f32 = 6.0 / 29.0;
+        if t > delta.powi(3) { t.cbrt() } else { t / (3.0 * delta * delta) + 4.0 / 29.0 }
+    });
+    let lightness = 116.0 * y - 16.0;
+    [lightness, 0.01 * lightness * 500.0 * (x - y), 0.01 * lightness * 200.0 * (y - z)]
+}
+
+/// Distance between two L*a*b* colours, better matching large differences than the Euclidean.
+fn hyab(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).abs() + (a[1] - b[1]).hypot(a[2] - b[2])
+}
+
+/// A reference image laid over the viewer's view, with the difference of its last finished
+/// frame.
+pub(crate) struct Comparison {
+    reference: Arc<RgbaImage>,
+    source: BlitSource,
+    heatmap: Option<BlitSource>,
+    metrics: Option<ImageMetrics>,
+    /// Difference being computed on another thread.
+    pending: Option<Receiver<ImageDifference>>,
+    /// When flipping started.
+    start: Instant,
+}
+
+impl Comparison {
+    pub(crate) fn load(path: &Path, device: &Device, queue: &Queue, blitter: &Blitter) -> ImageResult<Self> {
+        let reference = image::open(path)?.to_rgba8();
+        Ok(Self {
+            source: blitter.image_source(device, queue, &reference),
+            reference: Arc::new(reference),
+            heatmap: None,
+            metrics: None,
+            pending: None,
+            start: Instant::now(),
+        })
+    }
+
+    /// Metrics of the last finished frame, or `None` while one is being drawn or measured.
+    pub(crate) fn metrics(&self) -> Option<ImageMetrics> {
+        self.metrics
+    }
+
+    /// Starts comparing `image`, a finished frame with straight alpha, with the reference.
+    pub(crate) fn measure(&mut self, image: RgbaImage) {
+        let reference = self.reference.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(difference(&reference, &image));
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Forgets the difference of a frame no longer shown.
+    pub(crate) fn invalidate(&mut self) {
+        self.heatmap = None;
+        self.metrics = None;
+        self.pending = None;
+    }
+
+    /// Takes in the difference measured since the last call, if any, and returns its metrics.
+    pub(crate) fn poll(&mut self, device: &Device, queue: &Queue, blitter: &Blitter) -> Option<ImageMetrics> {
+        let difference = self.pending.as_ref()?.try_recv().ok()?;
+        self.pending = None;
+        self.heatmap = Some(blitter.image_source(device, queue, &difference.heatmap));
+        self.metrics = Some(difference.metrics);
+        self.metrics
+    }
+
+    /// Lays the reference over `target`, `size` pixels large, as `mode` shows it. The wipe is
+    /// `wipe` pixels from the left.
+    pub(crate) fn draw(&self, encoder: &mut CommandEncoder, blitter: &Blitter, target: &TextureView, size: (u32, u32), mode: CompareMode, wipe: u32) {
+        match mode {
+            CompareMode::Wipe if wipe > 0 => blitter.blit_clipped(encoder, &self.source, target, [0, 0, wipe.min(size.0), size.1]),
+            CompareMode::Flip if (self.start.elapsed().as_secs_f32() / FLIP_INTERVAL) as u32 % 2 == 1 => {
+                blitter.blit(encoder, &self.source, target);
+            }
+            CompareMode::Difference => {
+                if let Some(heatmap) = &self.heatmap {
+                    blitter.blit(encoder, heatmap, target);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64 by 64 gradient: red across, green down and half blue.
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(64, 64, |x, y| Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255]))
+    }
+
+    fn map(image: &RgbaImage, f: impl Fn(u32, u32, [u8; 3]) -> [u8; 3]) -> RgbaImage {
+        RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+            let [r, g, b, a] = image.get_pixel(x, y).0;
+            let [r, g, b] = f(x, y, [r, g, b]);
+            Rgba([r, g, b, a])
+        })
+    }
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn identical_images_do_not_differ() {
+        let image = gradient();
+        let difference = difference(&image, &image);
+        assert_eq!(difference.metrics, ImageMetrics { rmse: 0.0, flip: 0.0 });
+        let [r, g, b] = MAGMA[0];
+        assert!(difference.heatmap.pixels().all(|pixel| pixel.0 == [r, g, b, 255]));
+    }
+
+    /// Mean errors of the same pairs from NVIDIA's reference C++ implementation of LDR-FLIP
+    /// (as bundled by the `nv-flip` 0.1 crate), at 67 pixels per degree.
+    #[test]
+    fn flip_matches_the_reference_implementation() {
+        let reference = gradient();
+        let pairs = [
+            // A white square over a quarter of the image's width.
+            (map(&reference, |x, y, rgb| if (16..32).contains(&x) && (16..32).contains(&y) { [255; 3] } else { rgb }), 0.099047),
+            // A slight shift towards red.
+            (map(&reference, |_, _, [r, g, b]| [r.saturating_add(12), g, b.saturating_sub(20)]), 0.244403),
+            // A checkerboard of four pixel squares brightened by 40.
+            (map(&reference, |x, y, rgb| rgb.map(|c| c.saturating_add(if (x / 4 + y / 4) % 2 == 0 { 40 } else { 0 }))), 0.335911),
+        ];
+        for (image, flip) in pairs {
+            assert_near(difference(&reference, &image).metrics.flip, flip, 2e-4);
+        }
+    }
+
+    #[test]
+    fn flat_greys() {
+        let [dark, light] = [100, 128].map(|value| RgbaImage::from_pixel(16, 16, Rgba([value, value, value, 255])));
+        let difference = difference(&dark, &light);
+        // sRGB 100 and 128 are 0.12744 and 0.21586 in linear light.
+        let linear = |c: f32| ((c / 255.0 + 0.055) / 1.055).powf(2.4);
+        assert_near(difference.metrics.rmse, linear(128.0) - linear(100.0), 1e-5);
+        // Without features every pixel has the reference's colour error, 0.312423.
+        assert_near(difference.metrics.flip, 0.312423, 2e-4);
+    }
+
+    #[test]
+    fn rmse_of_black_and_white_is_one() {
+        let [black, white] = [0, 255].map(|value| RgbaImage::from_pixel(8, 8, Rgba([value, value, value, 255])));
+        let difference = difference(&black, &white);
+        assert_eq!(difference.metrics.rmse, 1.0);
+        assert!(difference.metrics.flip > 0.9);
+    }
+
+    #[test]
+    fn transparent_pixels_are_black() {
+        let black = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 255]));
+        let clear = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 0]));
+        assert_eq!(difference(&black, &clear).metrics.rmse, 0.0);
+    }
+
+    #[test]
+    fn references_are_stretched_to_the_image() {
+        let small = RgbaImage::from_pixel(4, 4, Rgba([60, 120, 180, 255]));
+        let large = RgbaImage::from_pixel(16, 8, Rgba([60, 120, 180, 255]));
+        let difference = difference(&small, &large);
+        assert_eq!(difference.heatmap.dimensions(), (16, 8));
+        assert_eq!(difference.metrics.rmse, 0.0);
+    }
+
+    #[test]
+    fn heatmap_runs_through_magma() {
+        assert_eq!(magma(0.0).0[..3], MAGMA[0]);
+        assert_eq!(magma(1.0).0[..3], MAGMA[9]);
+        assert_eq!(magma(2.0), magma(1.0));
+        assert_eq!(magma(-1.0), magma(0.0));
+    }
+
+    #[test]
+    fn modes() {
+        for mode in [CompareMode::Wipe, CompareMode::Flip, CompareMode::Difference] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+            assert_eq!(mode.next().next().next(), mode);
+        }
+        assert_eq!("diff".parse(), Ok(CompareMode::Difference));
+        assert!("overlay".parse::<CompareMode>().is_err());
+    }
+}
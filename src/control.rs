@@ -0,0 +1,681 @@
+//! Drives the running viewer over HTTP or a WebSocket, for remote automation and test harnesses.
+//!
+//! Requests are answered on the viewer's thread between frames. The endpoints are:
+//!
+//! - `GET /camera`: the camera as JSON. `PUT /camera` replaces it with the JSON body.
+//! - `POST /orbit`: turns the camera around the scene by the degrees in the body.
+//! - `GET /settings/...` and `GET /camera/...`: what scripts read at that path as JSON, e.g.
+//!   `/settings/explode` or `/camera/eye`. `PUT` assigns the JSON body to it.
+//! - `POST /scene`: opens the scene at the path in the body.
+//! - `GET /screenshot`: the current view as a PNG.
+//! - `GET /stats`: statistics of the scene.
+//!
+//! `GET /ws` upgrades to a WebSocket taking the same requests as text messages of JSON, e.g.
+//! `{"method": "PUT", "path": "/settings/explode", "body": 0.5}`. Each is answered with a text
+//! message such as `{"status": 204, "body": null}`, or a binary message holding a screenshot.
+//!
+//! Requests from a web page of another origin, or naming a host other than loopback or the
+//! address served on, are refused so a browser can't be used to drive the viewer.
+
+use std::{
+    io::{self, BufRead, BufReader, Cursor, Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::{
+    Camera,
+    RayTracer,
+    headless,
+    script::{self, Value},
+};
+
+/// Longest a connection waits for the viewer to answer, which it only does while drawing.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Appended to a client's key to accept its WebSocket, see RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest request body or WebSocket message read.
+const MAX_BODY: u64 = 16 << 20;
+
+// WebSocket frame opcodes.
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+pub(crate) struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+pub(crate) struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    fn empty() -> Self {
+        Self::new(204, "text/plain", Vec::new())
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", message.into())
+    }
+
+    fn json(value: &impl Serialize) -> Self {
+        Self::new(200, "application/json", serde_json::to_vec(value).unwrap())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        _ => "Service Unavailable",
+    }
+}
+
+/// A request waiting for the viewer, with where to send its response.
+pub(crate) type Pending = (Request, Sender<Response>);
+
+/// Connections being served on their own threads, handing requests to the viewer.
+pub(crate) struct ControlServer {
+    requests: Receiver<Pending>,
+}
+
+impl ControlServer {
+    pub(crate) fn new(address: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        log::info!("Serving the control API on http://{}", listener.local_addr()?);
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve(stream, &sender) {
+                        log::debug!("Control connection closed: {err}");
+                    }
+                });
+            }
+        });
+        Ok(Self { requests })
+    }
+
+    /// Requests received since the last call.
+    pub(crate) fn pending(&self) -> impl Iterator<Item = Pending> + '_ {
+        self.requests.try_iter()
+    }
+}
+
+/// Answers `request` for the viewer.
+pub(crate) fn handle(tracer: &mut RayTracer, request: &Request) -> Response {
+    if (request.method.as_str(), request.path.as_str()) == ("POST", "/scene") {
+        return match tracer.open_scene(text(&request.body)) {
+            Ok(()) => Response::empty(),
+            Err(err) => Response::error(400, err.to_string()),
+        };
+    }
+    let Some(state) = &mut tracer.state else {
+        return Response::error(503, "the window isn't open");
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/camera") => Response::json(&state.renderer.camera),
+        ("PUT" | "POST", "/camera") => match serde_json::from_slice::<Camera>(&request.body) {
+            Ok(camera) => {
+                state.set_camera(camera);
+                Response::empty()
+            }
+            Err(err) => Response::error(400, err.to_string()),
+        },
+        ("POST", "/orbit") => match text(&request.body).parse::<f32>() {
+            Ok(degrees) => {
+                let camera = state.renderer.camera.orbit(state.renderer.scene_stats.center(), degrees);
+                state.set_camera(camera);
+                Response::empty()
+            }
+            Err(err) => Response::error(400, err.to_string()),
+        },
+        ("GET", "/screenshot") => {
+            let image = headless::straight_alpha(&state.capture());
+            let mut bytes = Vec::new();
+            match image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png) {
+                Ok(()) => Response::new(200, "image/png", bytes),
+                Err(err) => Response::error(500, err.to_string()),
+            }
+        }
+        ("GET", "/stats") => Response::new(200, "text/plain; charset=utf-8", state.renderer.scene_stats.to_string()),
+        (method, path) if path.starts_with("/settings/") || path.starts_with("/camera/") => {
+            let path = path.trim_start_matches('/').replace('/', ".");
+            let mut camera = state.renderer.camera;
+            let settings = &mut state.renderer.settings;
+            match method {
+                "GET" => match script::query(&path, settings, &mut camera) {
+                    Some(value) => Response::json(&to_json(value)),
+                    None => Response::error(404, format!("{path} doesn't exist")),
+                },
+                "PUT" | "POST" => {
+                    // Bodies that aren't JSON are taken as strings, such as a material override's name.
+                    let body = serde_json::from_slice(&request.body).unwrap_or_else(|_| Json::String(text(&request.body)));
+                    match script::control(&path, vec![from_json(body)], settings, &mut camera) {
+                        Ok(()) => {
+                            if camera != state.renderer.camera {
+                                state.set_camera(camera);
+                            }
+                            Response::empty()
+                        }
+                        Err(err) => Response::error(400, err),
+                    }
+                }
+                _ => Response::error(404, "not found"),
+            }
+        }
+        _ => Response::error(404, "not found"),
+    }
+}
+
+/// A body as text: a JSON string's contents, or else the trimmed body itself.
+fn text(body: &[u8]) -> String {
+    match serde_json::from_slice(body) {
+        Ok(Json::String(text)) => text,
+        _ => String::from_utf8_lossy(body).trim().to_owned(),
+    }
+}
+
+fn to_json(value: Value) -> Json {
+    match value {
+        Value::Nil => Json::Null,
+        Value::Bool(value) => value.into(),
+        Value::Number(number) => serde_json::Number::from_f64(number).map_or(Json::Null, Json::Number),
+        Value::Text(text) => text.into(),
+        Value::Array(items) => items.into_iter().map(to_json).collect(),
+    }
+}
+
+fn from_json(json: Json) -> Value {
+    match json {
+        Json::Bool(value) => Value::Bool(value),
+        Json::Number(number) => Value::Number(number.as_f64().unwrap_or_default()),
+        Json::String(text) => Value::Text(text),
+        Json::Array(items) => Value::Array(items.into_iter().map(from_json).collect()),
+        Json::Null | Json::Object(_) => Value::Nil,
+    }
+}
+
+/// Has the viewer answer `request` and waits for its response.
+fn ask(sender: &Sender<Pending>, request: Request) -> Response {
+    let (reply, response) = mpsc::channel();
+    if sender.send((request, reply)).is_err() {
+        return Response::error(503, "the viewer has closed");
+    }
+    response.recv_timeout(REPLY_TIMEOUT).unwrap_or_else(|_| Response::error(503, "the viewer didn't answer in time"))
+}
+
+/// The request line and the headers the server acts on.
+#[derive(Debug, Default, PartialEq)]
+struct Head {
+    method: String,
+    /// The target without its query.
+    path: String,
+    content_length: u64,
+    websocket_key: Option<String>,
+    origin: Option<String>,
+    host: Option<String>,
+}
+
+/// Reads the request line and headers, up to the blank line before the body.
+fn read_head(reader: &mut impl BufRead) -> io::Result<Head> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let mut head = Head {
+        method: method.to_owned(),
+        path: target.split('?').next().unwrap_or_default().to_owned(),
+        ..Head::default()
+    };
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_owned();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => head.content_length = value.parse().unwrap_or(0),
+            "sec-websocket-key" => head.websocket_key = Some(value),
+            "origin" => head.origin = Some(value),
+            "host" => head.host = Some(value),
+            _ => {}
+        }
+    }
+    Ok(head)
+}
+
+/// Whether a request with `head` may drive the viewer, served at `local`. Clients other than
+/// browsers send no origin and are let through.
+fn allowed(head: &Head, local: SocketAddr) -> bool {
+    let trusted = |authority: &str| {
+        let host = match authority.strip_prefix('[') {
+            Some(rest) => rest.split(']').next().unwrap_or_default(),
+            None => authority.split(':').next().unwrap_or_default(),
+        };
+        host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback() || ip == local.ip())
+    };
+    let origin = head.origin.as_deref()
+        .is_none_or(|origin| origin.split_once("://").is_some_and(|(_, authority)| trusted(authority)));
+    origin && head.host.as_deref().is_none_or(trusted)
+}
+
+/// Serves one request on `stream`, or a WebSocket until the client closes it.
+fn serve(mut stream: TcpStream, sender: &Sender<Pending>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let head = read_head(&mut reader)?;
+    if !allowed(&head, stream.local_addr()?) {
+        log::warn!("Refused a control request from {:?} for host {:?}", head.origin, head.host);
+        return respond(&mut stream, Response::error(403, "requests from other origins aren't allowed"));
+    }
+    if let Some(key) = &head.websocket_key
+        && head.path == "/ws"
+    {
+        return websocket(reader, stream, key, sender);
+    }
+    let response = if head.content_length > MAX_BODY {
+        Response::error(413, "request body too large")
+    } else {
+        let mut body = vec![0; head.content_length as usize];
+        reader.read_exact(&mut body)?;
+        ask(sender, Request {
+            method: head.method,
+            path: head.path,
+            body,
+        })
+    };
+    respond(&mut stream, response)
+}
+
+fn respond(stream: &mut impl Write, response: Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len(),
+    )?;
+    stream.write_all(&response.body)
+}
+
+/// A request sent over the WebSocket.
+#[derive(Deserialize)]
+struct Message {
+    #[serde(default = "get")]
+    method: String,
+    path: String,
+    #[serde(default)]
+    body: Json,
+}
+
+fn get() -> String {
+    "GET".to_owned()
+}
+
+/// Accepts the WebSocket with `key` and answers its messages until the client closes it.
+fn websocket(mut reader: BufReader<TcpStream>, mut stream: TcpStream, key: &str, sender: &Sender<Pending>) -> io::Result<()> {
+    let accept = accept_key(key);
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n",
+    )?;
+    let mut message = Vec::new();
+    loop {
+        let (last, opcode, payload) = read_frame(&mut reader)?;
+        match opcode {
+            CLOSE => return write_frame(&mut stream, CLOSE, &payload),
+            PING => {
+                write_frame(&mut stream, PONG, &payload)?;
+                continue;
+            }
+            TEXT | BINARY | CONTINUATION => message.extend(payload),
+            _ => continue,
+        }
+        if message.len() as u64 > MAX_BODY {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "message too large"));
+        }
+        if !last {
+            continue;
+        }
+
+        let response = match serde_json::from_slice::<Message>(&std::mem::take(&mut message)) {
+            Ok(message) => {
+                let body = match message.body {
+                    Json::Null => Vec::new(),
+                    body => body.to_string().into_bytes(),
+                };
+                ask(sender, Request {
+                    method: message.method.to_uppercase(),
+                    path: message.path,
+                    body,
+                })
+            }
+            Err(err) => Response::error(400, err.to_string()),
+        };
+        if response.content_type.starts_with("image/") {
+            write_frame(&mut stream, BINARY, &response.body)?;
+            continue;
+        }
+        let body = match response.content_type {
+            "application/json" => serde_json::from_slice(&response.body).unwrap_or_default(),
+            _ if response.body.is_empty() => Json::Null,
+            _ => Json::String(String::from_utf8_lossy(&response.body).into_owned()),
+        };
+        let reply = serde_json::json!({ "status": response.status, "body": body });
+        write_frame(&mut stream, TEXT, reply.to_string().as_bytes())?;
+    }
+}
+
+/// The `Sec-WebSocket-Accept` answering a client's `Sec-WebSocket-Key`.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{key}{WEBSOCKET_GUID}").as_bytes()))
+}
+
+/// Reads a frame from the client, returning whether it ends its message, its opcode and its
+/// unmasked payload.
+fn read_frame(reader: &mut impl Read) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head)?;
+    let length = match head[1] & 0x7f {
+        126 => {
+            let mut length = [0; 2];
+            reader.read_exact(&mut length)?;
+            u16::from_be_bytes(length) as u64
+        }
+        127 => {
+            let mut length = [0; 8];
+            reader.read_exact(&mut length)?;
+            u64::from_be_bytes(length)
+        }
+        length => length as u64,
+    };
+    if length > MAX_BODY {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message too large"));
+    }
+    let mut mask = [0; 4];
+    if head[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+    for (byte, mask) in payload.iter_mut().zip(mask.iter().cycle()) {
+        *byte ^= mask;
+    }
+    Ok((head[0] & 0x80 != 0, head[0] & 0x0f, payload))
+}
+
+/// Writes `payload` as a whole, unmasked message, as servers send them.
+fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut head = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..126 => head.push(length as u8),
+        length @ 126..=0xffff => {
+            head.push(126);
+            head.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            head.push(127);
+            head.extend((length as u64).to_be_bytes());
+        }
+    }
+    stream.write_all(&head)?;
+    stream.write_all(payload)?;
+    stream.flush()
+}
+
+/// SHA-1 digest of `data`, which the WebSocket handshake needs.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend((data.len() as u64 * 8).to_be_bytes());
+    for block in padded.chunks_exact(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.into_iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5a827999),
+                20..40 => (b ^ c ^ d, 0x6ed9eba1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let next = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, next);
+        }
+        for (state, value) in state.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            text.push(match i <= chunk.len() {
+                true => ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char,
+                false => '=',
+            });
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(request: &str) -> Head {
+        read_head(&mut Cursor::new(request.as_bytes())).unwrap()
+    }
+
+    /// Serves one connection on a loopback port, returning the client's end and the requests
+    /// the viewer would get.
+    fn connect() -> (TcpStream, Receiver<Pending>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = serve(stream, &sender);
+        });
+        (TcpStream::connect(address).unwrap(), requests)
+    }
+
+    /// Answers the next request with `response`, returning it.
+    fn answer(requests: &Receiver<Pending>, response: Response) -> Request {
+        let (request, reply) = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        reply.send(response).unwrap();
+        request
+    }
+
+    fn masked(opcode: u8, last: bool, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![(last as u8) << 7 | opcode, 0x80 | payload.len() as u8];
+        frame.extend(mask);
+        frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(byte, mask)| byte ^ mask));
+        frame
+    }
+
+    #[test]
+    fn accept_key_matches_rfc_6455() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn masked_frames_are_unmasked() {
+        // The masked "Hello" of RFC 6455, section 5.7.
+        let frame = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        assert_eq!(read_frame(&mut &frame[..]).unwrap(), (true, TEXT, b"Hello".to_vec()));
+        let frame = masked(CONTINUATION, false, b"abc");
+        assert_eq!(read_frame(&mut &frame[..]).unwrap(), (false, CONTINUATION, b"abc".to_vec()));
+    }
+
+    #[test]
+    fn frames_round_trip_at_every_length_encoding() {
+        for length in [0, 125, 126, 0xffff, 0x10000] {
+            let payload: Vec<u8> = (0..length).map(|i| i as u8).collect();
+            let mut frame = Vec::new();
+            write_frame(&mut frame, BINARY, &payload).unwrap();
+            assert_eq!(read_frame(&mut &frame[..]).unwrap(), (true, BINARY, payload), "{length} bytes");
+        }
+    }
+
+    #[test]
+    fn oversized_and_truncated_frames_are_errors() {
+        let mut frame = vec![0x82, 127];
+        frame.extend((MAX_BODY + 1).to_be_bytes());
+        assert_eq!(read_frame(&mut &frame[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let frame = masked(TEXT, true, b"Hello");
+        assert!(read_frame(&mut &frame[..frame.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn heads_are_parsed() {
+        let head = head("POST /settings/explode?x=1 HTTP/1.1\r\nHost: localhost:7879\r\ncontent-LENGTH: 3\r\nOrigin: http://localhost:7879\r\n\r\n0.5");
+        assert_eq!(head, Head {
+            method: "POST".into(),
+            path: "/settings/explode".into(),
+            content_length: 3,
+            websocket_key: None,
+            origin: Some("http://localhost:7879".into()),
+            host: Some("localhost:7879".into()),
+        });
+    }
+
+    #[test]
+    fn foreign_origins_and_hosts_are_refused() {
+        let loopback: SocketAddr = "127.0.0.1:7879".parse().unwrap();
+        let lan: SocketAddr = "192.168.1.5:7879".parse().unwrap();
+        let request = |headers: &str| head(&format!("GET /camera HTTP/1.1\r\n{headers}\r\n"));
+        for headers in ["", "Host: localhost:7879\r\n", "Host: 127.0.0.1\r\n", "Host: [::1]:7879\r\n", "Host: 127.0.0.1:7879\r\nOrigin: http://localhost:8080\r\n"] {
+            assert!(allowed(&request(headers), loopback), "{headers:?}");
+        }
+        for headers in ["Host: evil.example:7879\r\n", "Host: localhost\r\nOrigin: https://evil.example\r\n", "Origin: null\r\n", "Host: 192.168.1.5:7879\r\n"] {
+            assert!(!allowed(&request(headers), loopback), "{headers:?}");
+        }
+        // Serving on another interface, the viewer can be reached by that address.
+        assert!(allowed(&request("Host: 192.168.1.5:7879\r\nOrigin: http://192.168.1.5:7879\r\n"), lan));
+        assert!(!allowed(&request("Host: 192.168.1.6:7879\r\n"), lan));
+    }
+
+    #[test]
+    fn http_requests_reach_the_viewer() {
+        let (mut client, requests) = connect();
+        client.write_all(b"PUT /camera/eye?now HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 9\r\n\r\n[1, 2, 3]").unwrap();
+        let request = answer(&requests, Response::json(&[1, 2, 3]));
+        assert_eq!((request.method.as_str(), request.path.as_str(), request.body.as_slice()), ("PUT", "/camera/eye", &b"[1, 2, 3]"[..]));
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with("\r\n\r\n[1,2,3]"), "{response}");
+    }
+
+    #[test]
+    fn refused_requests_never_reach_the_viewer() {
+        let (mut client, requests) = connect();
+        client.write_all(b"POST /scene HTTP/1.1\r\nHost: evil.example\r\nContent-Length: 4\r\n\r\na.gltf").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"), "{response}");
+        assert!(requests.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn websocket_messages_reach_the_viewer() {
+        let (mut client, requests) = connect();
+        client.write_all(b"GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n").unwrap();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut handshake = String::new();
+        while !handshake.ends_with("\r\n\r\n") {
+            reader.read_line(&mut handshake).unwrap();
+        }
+        assert!(handshake.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"), "{handshake}");
+
+        // A message split over two frames, with a ping between them.
+        client.write_all(&masked(TEXT, false, br#"{"method": "put", "path": "/settings/explode","#)).unwrap();
+        client.write_all(&masked(PING, true, b"hi")).unwrap();
+        assert_eq!(read_frame(&mut reader).unwrap(), (true, PONG, b"hi".to_vec()));
+        client.write_all(&masked(CONTINUATION, true, br#" "body": 0.5}"#)).unwrap();
+        let request = answer(&requests, Response::empty());
+        assert_eq!((request.method.as_str(), request.path.as_str(), request.body.as_slice()), ("PUT", "/settings/explode", &b"0.5"[..]));
+        assert_eq!(read_frame(&mut reader).unwrap(), (true, TEXT, br#"{"body":null,"status":204}"#.to_vec()));
+
+        client.write_all(&masked(TEXT, true, b"not json")).unwrap();
+        let (_, _, reply) = read_frame(&mut reader).unwrap();
+        assert_eq!(serde_json::from_slice::<Json>(&reply).unwrap()["status"], 400);
+        client.write_all(&masked(CLOSE, true, b"")).unwrap();
+        assert_eq!(read_frame(&mut reader).unwrap(), (true, CLOSE, Vec::new()));
+    }
+
+    #[test]
+    fn requests_are_routed() {
+        let mut tracer = RayTracer::default();
+        let request = |method: &str, path: &str, body: &str| Request {
+            method: method.into(),
+            path: path.into(),
+            body: body.into(),
+        };
+        // Opening a scene works before the window does; the rest needs it.
+        assert_eq!(handle(&mut tracer, &request("POST", "/scene", r#""models/box.gltf""#)).status, 204);
+        assert_eq!(tracer.scene_path, std::path::Path::new("models/box.gltf"));
+        for (method, path) in [("GET", "/camera"), ("GET", "/settings/explode"), ("GET", "/nothing")] {
+            assert_eq!(handle(&mut tracer, &request(method, path, "")).status, 503, "{method} {path}");
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Conversion of imported scenes to the renderer's conventions: metres, Y up, and optionally
+//! centred on the origin and scaled to a known size.
+
+use std::str::FromStr;
+
+use crate::{
+    AudioTarget,
+    Scene,
+    Vec3,
+    camera::{self, Mat4},
+};
+
+/// Length unit of a scene's coordinates.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Units {
+    Millimeters,
+    Centimeters,
+    Meters,
+    Inches,
+}
+
+impl Units {
+    /// Metres per unit.
+    pub(crate) fn scale(self) -> f32 {
+        match self {
+            Units::Millimeters => 0.001,
+            Units::Centimeters => 0.01,
+            Units::Meters => 1.0,
+            Units::Inches => 0.0254,
+        }
+    }
+}
+
+impl FromStr for Units {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mm" | "millimeters" => Ok(Units::Millimeters),
+            "cm" | "centimeters" => Ok(Units::Centimeters),
+            "m" | "meters" => Ok(Units::Meters),
+            "in" | "inches" => Ok(Units::Inches),
+            _ => Err(format!("unknown units {s:?}")),
+        }
+    }
+}
+
+/// How the scene path is placed in the world after loading, see [`Scene::convert`].
+///
+/// The defaults leave the scene as the file has it, as glTF is already in metres and Y up.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ImportOptions {
+    /// Units of the file's coordinates, scaled to metres.
+    pub units: Option<Units>,
+    /// Turns the scene from Z-up, the CAD and 3ds Max convention, to the renderer's Y-up.
+    pub z_up: bool,
+    /// Moves the centre of the scene bounds to the origin.
+    pub center: bool,
+    /// Scales the scene about the centre of its bounds so that their longest side has this
+    /// length.
+    pub fit: Option<f32>,
+}
+
+/// A uniform scale, an axis swap and a translation, applied in that order.
+#[derive(Copy, Clone)]
+struct Placement {
+    scale: f32,
+    z_up: bool,
+    offset: Vec3,
+}
+
+impl Placement {
+    fn direction(&self, v: Vec3) -> Vec3 {
+        if self.z_up { vec3![v.x, v.z, -v.y] } else { v }
+    }
+
+    fn point(&self, v: Vec3) -> Vec3 {
+        self.direction(v) * self.scale + self.offset
+    }
+
+    fn matrix(&self) -> Mat4 {
+        let [x, y, z] = [vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], vec3![0.0, 0.0, 1.0]]
+            .map(|axis| self.direction(axis) * self.scale);
+        let offset = self.offset;
+        [[x.x, x.y, x.z, 0.0], [y.x, y.y, y.z, 0.0], [z.x, z.y, z.z, 0.0], [offset.x, offset.y, offset.z, 1.0]]
+    }
+
+    /// The box around the placed corners of `min`..`max`.
+    fn bounds(&self, min: Vec3, max: Vec3) -> (Vec3, Vec3) {
+        let (a, b) = (self.point(min), self.point(max));
+        (Vec3::min(a, b), Vec3::max(a, b))
+    }
+}
+
+pub(crate) fn convert(scene: &mut Scene, options: &ImportOptions) {
+    let scale = options.units.map_or(1.0, Units::scale);
+    if scale != 1.0 || options.z_up {
+        place(scene, Placement { scale, z_up: options.z_up, offset: Vec3::default() });
+    }
+    if !options.center && options.fit.is_none() {
+        return;
+    }
+    let Some((min, max)) = scene.bounds() else {
+        return;
+    };
+    let center = (min + max) * 0.5;
+    let extent = max - min;
+    let longest = extent.x.max(extent.y).max(extent.z);
+    let scale = match options.fit {
+        Some(size) if longest > 0.0 => size / longest,
+        _ => 1.0,
+    };
+    // Scaling about the centre keeps it in place unless centring moves it to the origin.
+    let target = if options.center { Vec3::default() } else { center };
+    place(scene, Placement { scale, z_up: false, offset: target - center * scale });
+}
+
+fn place(scene: &mut Scene, placement: Placement) {
+    for vertex in &mut scene.vertices {
+        *vertex = placement.point(*vertex);
+    }
+    for mesh in &mut scene.meshes {
+        (mesh.min, mesh.max) = placement.bounds(mesh.min, mesh.max);
+        for tangent in &mut mesh.tangents {
+            let [x, y, z, w] = *tangent;
+            let direction = placement.direction(vec3![x, y, z]);
+            *tangent = [direction.x, direction.y, direction.z, w];
+        }
+        for meshlet in mesh.lods.iter_mut().flat_map(|lod| &mut lod.meshlets) {
+            (meshlet.min, meshlet.max) = placement.bounds(meshlet.min, meshlet.max);
+        }
+    }
+    for splat in &mut scene.splats {
+        let position = placement.point(splat.position.into());
+        let normal = placement.direction(splat.normal.into());
+        splat.position = [position.x, position.y, position.z];
+        splat.normal = [normal.x, normal.y, normal.z];
+        splat.radius *= placement.scale;
+    }
+    for emitter in &mut scene.emitters {
+        let (min, max) = placement.bounds(emitter.position - emitter.extent * 0.5, emitter.position + emitter.extent * 0.5);
+        emitter.position = placement.point(emitter.position);
+        emitter.extent = max - min;
+        emitter.velocity = placement.direction(emitter.velocity) * placement.scale;
+        emitter.acceleration = placement.direction(emitter.acceleration) * placement.scale;
+        emitter.spread *= placement.scale;
+        emitter.size *= placement.scale;
+    }
+    for mapping in scene.audio.iter_mut().flat_map(|audio| &mut audio.mappings) {
+        if let AudioTarget::Move { by, .. } = &mut mapping.target {
+            *by = placement.direction(*by) * placement.scale;
+        }
+    }
+    if let Some(volume) = &mut scene.volume {
+        (volume.min, volume.max) = placement.bounds(volume.min, volume.max);
+        if placement.z_up {
+            volume.swap_z_up();
+        }
+    }
+    for body in scene.world.bodies.values_mut() {
+        body.velocity = placement.direction(body.velocity) * placement.scale;
+    }
+    for camera in scene.world.cameras.values_mut() {
+        camera.eye = placement.point(camera.eye);
+        camera.target = placement.point(camera.target);
+        camera.up = placement.direction(camera.up);
+        camera.znear *= placement.scale;
+        camera.zfar *= placement.scale;
+    }
+    for light in scene.world.lights.values_mut() {
+        light.position = placement.point(light.position);
+        light.direction = placement.direction(light.direction);
+        light.range = light.range.map(|range| range * placement.scale);
+    }
+    let matrix = placement.matrix();
+    for transform in scene.world.transforms.values_mut() {
+        *transform = camera::mul(&matrix, transform);
+    }
+}
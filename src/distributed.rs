@@ -0,0 +1,495 @@
+//! Splits an offline render into tiles rendered by worker processes on other machines.
+//!
+//! Workers listen with [`work`]; the coordinator calls [`render`] with their addresses, hands
+//! out tiles as workers become free and assembles the image. A tile whose worker fails or
+//! disconnects goes back in the queue for another worker.
+//!
+//! Jobs name the scene by path rather than carrying it, so every worker must be able to load
+//! that path, e.g. from shared storage. Workers only load scenes inside the root directory they
+//! were given, with relative paths taken from it. Each request is a line of JSON, answered by a line of
+//! JSON followed by the tile's RGBA pixels.
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use image::{RgbaImage, imageops};
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    Extent3d,
+    Texture,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureUsages,
+    TextureViewDescriptor,
+};
+
+use crate::{
+    Camera,
+    CropRect,
+    Scene,
+    Settings,
+    headless,
+    renderer::{self, Renderer},
+};
+
+/// How long the coordinator waits on a worker before counting the tile as failed.
+const WORKER_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug)]
+pub struct DistributedOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Side of the square tiles handed out, in pixels.
+    pub tile_size: u32,
+    /// `host:port` of each worker.
+    pub workers: Vec<String>,
+    /// Times a tile is handed out again after failing before the render gives up.
+    pub retries: u32,
+}
+
+impl Default for DistributedOptions {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            tile_size: 256,
+            workers: Vec::new(),
+            retries: 3,
+        }
+    }
+}
+
+/// Pixel rectangle of the final image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Job {
+    scene: PathBuf,
+    camera: Camera,
+    width: u32,
+    height: u32,
+    tile: Tile,
+}
+
+/// Header of a worker's answer, followed by `width * height * 4` bytes of pixels on success.
+#[derive(Debug, Serialize, Deserialize)]
+struct Reply {
+    error: Option<String>,
+}
+
+/// Tiles waiting to be rendered, shared by the threads talking to the workers.
+struct Queue {
+    tiles: VecDeque<(Tile, u32)>,
+    /// Tiles not yet rendered, including those being rendered right now.
+    remaining: usize,
+    /// Workers still taking jobs.
+    workers: usize,
+    error: Option<String>,
+}
+
+/// Renders `scene_path` from `camera` on the workers in `options` and assembles the image.
+///
+/// Fails if a tile fails more than `options.retries` times or every worker becomes unreachable.
+pub fn render(scene_path: PathBuf, camera: Camera, options: &DistributedOptions) -> io::Result<RgbaImage> {
+    if options.workers.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no workers to render on"));
+    }
+    let size = options.tile_size.max(1);
+    let mut tiles = VecDeque::new();
+    for y in (0..options.height).step_by(size as usize) {
+        for x in (0..options.width).step_by(size as usize) {
+            let tile = Tile {
+                x,
+                y,
+                width: size.min(options.width - x),
+                height: size.min(options.height - y),
+            };
+            tiles.push_back((tile, 0));
+        }
+    }
+    let queue = Mutex::new(Queue {
+        remaining: tiles.len(),
+        tiles,
+        workers: options.workers.len(),
+        error: None,
+    });
+    let changed = Condvar::new();
+    let image = Mutex::new(RgbaImage::new(options.width, options.height));
+
+    thread::scope(|scope| {
+        for address in &options.workers {
+            let (queue, changed, image) = (&queue, &changed, &image);
+            let scene = scene_path.clone();
+            scope.spawn(move || {
+                let mut connection = None;
+                while let Some((tile, attempts)) = next_tile(queue, changed) {
+                    let job = Job {
+                        scene: scene.clone(),
+                        camera,
+                        width: options.width,
+                        height: options.height,
+                        tile,
+                    };
+                    match request(address, &mut connection, &job) {
+                        Ok(pixels) => {
+                            imageops::replace(&mut *lock(image), &pixels, tile.x as i64, tile.y as i64);
+                            lock(queue).remaining -= 1;
+                            changed.notify_all();
+                        }
+                        Err(err) => {
+                            log::warn!("Worker {address} failed on {tile:?}: {err}");
+                            connection = None;
+                            let mut queue = lock(queue);
+                            if attempts >= options.retries {
+                                queue.error = Some(format!("tile {tile:?} failed {} times: {err}", attempts + 1));
+                            } else {
+                                queue.tiles.push_back((tile, attempts + 1));
+                            }
+                            // A worker that cannot be reached stops taking jobs.
+                            if err.kind() == io::ErrorKind::ConnectionRefused {
+                                queue.workers -= 1;
+                                if queue.workers == 0 {
+                                    queue.error.get_or_insert_with(|| "every worker is unreachable".to_owned());
+                                }
+                                changed.notify_all();
+                                return;
+                            }
+                            changed.notify_all();
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    match queue.into_inner().unwrap_or_else(PoisonError::into_inner).error {
+        Some(error) => Err(io::Error::other(error)),
+        None => Ok(image.into_inner().unwrap_or_else(PoisonError::into_inner)),
+    }
+}
+
+/// Locks `mutex`, carrying on if a thread panicked while holding it: the queue and image stay
+/// consistent between statements, and a lost tile is caught by the remaining count.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Takes the next tile to render, waiting while tiles another worker holds may come back.
+/// Returns `None` once every tile is rendered or the render failed.
+fn next_tile(queue: &Mutex<Queue>, changed: &Condvar) -> Option<(Tile, u32)> {
+    let mut queue = lock(queue);
+    loop {
+        if queue.error.is_some() || queue.remaining == 0 {
+            return None;
+        }
+        if let Some(tile) = queue.tiles.pop_front() {
+            return Some(tile);
+        }
+        queue = changed.wait(queue).unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+/// Sends `job` to the worker at `address`, connecting first if needed, and reads the tile back.
+fn request(address: &str, connection: &mut Option<BufReader<TcpStream>>, job: &Job) -> io::Result<RgbaImage> {
+    if connection.is_none() {
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(WORKER_TIMEOUT))?;
+        *connection = Some(BufReader::new(stream));
+    }
+    let reader = connection.as_mut().unwrap();
+    let mut line = serde_json::to_string(job)?;
+    line.push('\n');
+    reader.get_mut().write_all(line.as_bytes())?;
+
+    let mut reply = String::new();
+    if reader.read_line(&mut reply)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "worker closed the connection"));
+    }
+    let reply: Reply = serde_json::from_str(&reply)?;
+    if let Some(error) = reply.error {
+        return Err(io::Error::other(error));
+    }
+    let mut pixels = vec![0; job.tile.width as usize * job.tile.height as usize * 4];
+    reader.read_exact(&mut pixels)?;
+    Ok(RgbaImage::from_raw(job.tile.width, job.tile.height, pixels).unwrap())
+}
+
+/// Renders tiles for coordinators connecting to `address` until the process exits, loading
+/// only scenes inside `root`.
+///
+/// Connections are served one at a time on one device, keeping the last scene loaded between
+/// jobs.
+pub fn work(address: &str, root: &Path, settings: Settings) -> io::Result<()> {
+    let root = root.canonicalize()?;
+    let listener = TcpListener::bind(address)?;
+    log::info!("Waiting for jobs on {} for scenes in {}", listener.local_addr()?, root.display());
+    let mut worker = Worker {
+        root,
+        settings,
+        loaded: None,
+        target: None,
+    };
+    for stream in listener.incoming() {
+        let stream = stream?;
+        if let Err(err) = worker.serve(stream) {
+            log::warn!("Coordinator connection closed: {err}");
+        }
+    }
+    Ok(())
+}
+
+struct Worker {
+    /// Directory holding the scenes jobs may name, canonical.
+    root: PathBuf,
+    settings: Settings,
+    /// The scene last rendered and the renderer holding it.
+    loaded: Option<(PathBuf, Renderer)>,
+    target: Option<Texture>,
+}
+
+impl Worker {
+    fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut stream = stream;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            let result = serde_json::from_str::<Job>(&line)
+                .map_err(|err| err.to_string())
+                .and_then(|job| self.render(&job));
+            line.clear();
+            match result {
+                Ok(pixels) => {
+                    writeln!(stream, "{}", serde_json::to_string(&Reply { error: None })?)?;
+                    stream.write_all(pixels.as_raw())?;
+                }
+                Err(error) => {
+                    log::warn!("Failed to render a tile: {error}");
+                    writeln!(stream, "{}", serde_json::to_string(&Reply { error: Some(error) })?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the full frame cropped to the job's tile and returns the tile's pixels.
+    fn render(&mut self, job: &Job) -> Result<RgbaImage, String> {
+        if self.loaded.as_ref().is_none_or(|(path, _)| *path != job.scene) {
+            let path = resolve(&self.root, &job.scene)?;
+            let scene = Scene::load(&path).map_err(|err| format!("{}: {err}", job.scene.display()))?;
+            let renderer = match self.loaded.take() {
+                Some((_, mut renderer)) => {
+                    renderer.reload(scene);
+                    renderer
+                }
+                None => {
+                    let (device, queue, max_buffer_size) = renderer::headless_device();
+                    let format = TextureFormat::Rgba8UnormSrgb;
+                    Renderer::new(device, queue, format, max_buffer_size, scene, self.settings.clone())
+                }
+            };
+            self.loaded = Some((job.scene.clone(), renderer));
+        }
+        let (_, renderer) = self.loaded.as_mut().unwrap();
+
+        let (width, height) = (job.width.max(1), job.height.max(1));
+        if self.target.as_ref().is_none_or(|target| (target.width(), target.height()) != (width, height)) {
+            self.target = Some(renderer.device.create_texture(&TextureDescriptor {
+                label: Some("Worker target"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+                view_formats: &[],
+            }));
+        }
+        let target = self.target.as_ref().unwrap();
+
+        let tile = job.tile;
+        if tile.width == 0 || tile.height == 0 || tile.x + tile.width > width || tile.y + tile.height > height {
+            return Err(format!("tile {tile:?} is outside the {width}x{height} frame"));
+        }
+        renderer.camera = job.camera;
+        renderer.settings.crop = Some(CropRect {
+            x: tile.x as f32 / width as f32,
+            y: tile.y as f32 / height as f32,
+            width: tile.width as f32 / width as f32,
+            height: tile.height as f32 / height as f32,
+        });
+        renderer.render(&target.create_view(&TextureViewDescriptor::default()), width, height, None);
+        let frame = headless::read_back(renderer, target);
+        Ok(imageops::crop_imm(&frame, tile.x, tile.y, tile.width, tile.height).to_image())
+    }
+}
+
+/// Path of the job's `scene` under `root`, refusing paths that lead outside it.
+fn resolve(root: &Path, scene: &Path) -> Result<PathBuf, String> {
+    let path = root.join(scene).canonicalize().map_err(|err| format!("{}: {err}", scene.display()))?;
+    match path.starts_with(root) {
+        true => Ok(path),
+        false => Err(format!("{} is outside the worker's root", scene.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Frame pixel the fake workers draw at `x`, `y`.
+    fn pixel(x: u32, y: u32) -> [u8; 4] {
+        [x as u8, y as u8, 7, 255]
+    }
+
+    /// Address of a worker answering jobs without a GPU, failing its first `failures` jobs.
+    fn fake_worker(mut failures: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 {
+                    let job: Job = serde_json::from_str(&line).unwrap();
+                    line.clear();
+                    if failures > 0 {
+                        failures -= 1;
+                        writeln!(stream, r#"{{"error": "out of memory"}}"#).unwrap();
+                        continue;
+                    }
+                    writeln!(stream, r#"{{"error": null}}"#).unwrap();
+                    let tile = job.tile;
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            stream.write_all(&pixel(x, y)).unwrap();
+                        }
+                    }
+                }
+            }
+        });
+        address
+    }
+
+    fn job(tile: Tile) -> Job {
+        Job {
+            scene: "scenes/box.gltf".into(),
+            camera: Camera::default(),
+            width: 64,
+            height: 32,
+            tile,
+        }
+    }
+
+    #[test]
+    fn jobs_are_single_lines_of_json() {
+        let job = job(Tile { x: 16, y: 8, width: 16, height: 8 });
+        let line = serde_json::to_string(&job).unwrap();
+        assert!(!line.contains('\n'));
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["scene"], "scenes/box.gltf");
+        assert_eq!(json["tile"], serde_json::json!({ "x": 16, "y": 8, "width": 16, "height": 8 }));
+        assert_eq!(serde_json::from_str::<Job>(&line).unwrap(), job);
+    }
+
+    #[test]
+    fn requests_read_back_the_tile() {
+        let address = fake_worker(1);
+        let mut connection = None;
+        let tile = Tile { x: 3, y: 5, width: 4, height: 2 };
+        let err = request(&address, &mut connection, &job(tile)).unwrap_err();
+        assert_eq!(err.to_string(), "out of memory");
+
+        // The connection is kept for the next job.
+        assert!(connection.is_some());
+        let image = request(&address, &mut connection, &job(tile)).unwrap();
+        assert_eq!(image.dimensions(), (4, 2));
+        assert_eq!(image.get_pixel(1, 1).0, pixel(4, 6));
+    }
+
+    #[test]
+    fn closed_connections_are_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        // The worker reads the job and hangs up without answering.
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            BufReader::new(stream).read_line(&mut String::new()).unwrap();
+        });
+        let err = request(&address, &mut None, &job(Tile { x: 0, y: 0, width: 1, height: 1 })).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn tiles_are_assembled_and_retried() {
+        // A worker that can't be reached drops out, and failed tiles go to the others.
+        let unreachable = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let options = DistributedOptions {
+            width: 50,
+            height: 30,
+            tile_size: 16,
+            workers: vec![fake_worker(2), unreachable, fake_worker(0)],
+            retries: 3,
+        };
+        let image = render("scenes/box.gltf".into(), Camera::default(), &options).unwrap();
+        assert_eq!(image.dimensions(), (50, 30));
+        for (x, y, color) in image.enumerate_pixels() {
+            assert_eq!(color.0, pixel(x, y), "at {x}, {y}");
+        }
+    }
+
+    #[test]
+    fn tiles_failing_too_often_fail_the_render() {
+        let options = DistributedOptions {
+            width: 8,
+            height: 8,
+            workers: vec![fake_worker(usize::MAX)],
+            retries: 2,
+            ..DistributedOptions::default()
+        };
+        let err = render("scenes/box.gltf".into(), Camera::default(), &options).unwrap_err();
+        assert!(err.to_string().contains("failed 3 times: out of memory"), "{err}");
+    }
+
+    #[test]
+    fn scenes_outside_the_root_are_refused() {
+        let dir = std::env::temp_dir().join(format!("distributed-{}", std::process::id()));
+        fs::create_dir_all(dir.join("root/scenes")).unwrap();
+        fs::write(dir.join("root/scenes/box.gltf"), "{}").unwrap();
+        fs::write(dir.join("secret.gltf"), "{}").unwrap();
+        let root = dir.join("root").canonicalize().unwrap();
+
+        let resolved = resolve(&root, Path::new("scenes/box.gltf"));
+        let absolute = resolve(&root, &root.join("scenes/../scenes/box.gltf"));
+        let escaping = resolve(&root, Path::new("../secret.gltf"));
+        let outside = resolve(&root, &dir.join("secret.gltf"));
+        let missing = resolve(&root, Path::new("scenes/missing.gltf"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(resolved, Ok(root.join("scenes/box.gltf")));
+        assert_eq!(absolute, Ok(root.join("scenes/box.gltf")));
+        assert_eq!(escaping, Err("../secret.gltf is outside the worker's root".to_owned()));
+        assert!(outside.unwrap_err().ends_with("is outside the worker's root"));
+        assert!(missing.unwrap_err().starts_with("scenes/missing.gltf: "));
+    }
+}
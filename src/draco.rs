@@ -0,0 +1,1591 @@
+//! Decoder for meshes compressed with Draco, as `KHR_draco_mesh_compression` stores them in glTF.
+//!
+//! Only what drawing needs is decoded: the triangles, from sequential or Edgebreaker
+//! connectivity, and their positions. Attributes stored before the positions are read past and
+//! those after them are left alone. Streams must be version 2.2, which current encoders write.
+
+use std::{
+    collections::HashMap,
+    io,
+};
+
+use crate::Vec3;
+
+/// Marks a missing corner or vertex.
+const INVALID: u32 = u32::MAX;
+
+/// Header flag set when the stream carries metadata.
+const METADATA_FLAG: u16 = 0x8000;
+
+// Edgebreaker symbols.
+const SYMBOL_C: u32 = 0;
+const SYMBOL_S: u32 = 1;
+const SYMBOL_L: u32 = 3;
+const SYMBOL_R: u32 = 5;
+const SYMBOL_E: u32 = 7;
+
+/// Edgebreaker symbols in the order the valence coder numbers them.
+const VALENCE_SYMBOLS: [u32; 5] = [SYMBOL_C, SYMBOL_S, SYMBOL_L, SYMBOL_R, SYMBOL_E];
+
+/// Valences the valence coder tells apart, each with its own context.
+const MIN_VALENCE: u32 = 2;
+const MAX_VALENCE: u32 = 7;
+
+// Prediction methods.
+const PREDICTION_NONE: i8 = -2;
+const PREDICTION_PARALLELOGRAM: i8 = 1;
+const PREDICTION_MULTI_PARALLELOGRAM: i8 = 2;
+const PREDICTION_TEX_COORDS_DEPRECATED: i8 = 3;
+const PREDICTION_CONSTRAINED_MULTI_PARALLELOGRAM: i8 = 4;
+const PREDICTION_TEX_COORDS_PORTABLE: i8 = 5;
+const PREDICTION_GEOMETRIC_NORMAL: i8 = 6;
+
+// Prediction transforms.
+const TRANSFORM_WRAP: i8 = 1;
+const TRANSFORM_OCTAHEDRON: i8 = 2;
+const TRANSFORM_OCTAHEDRON_CANONICALIZED: i8 = 3;
+
+// Attribute decoders.
+const DECODER_GENERIC: u8 = 0;
+const DECODER_INTEGER: u8 = 1;
+const DECODER_QUANTIZATION: u8 = 2;
+const DECODER_NORMALS: u8 = 3;
+
+/// Most parallelograms the constrained multi-parallelogram prediction averages.
+const MAX_PARALLELOGRAMS: usize = 4;
+
+/// Decodes a Draco mesh into a triangle list of the positions in the attribute `position_id`.
+pub(crate) fn decode(bytes: &[u8], position_id: u32) -> io::Result<Vec<Vec3>> {
+    let mut buffer = Buffer::new(bytes);
+    if buffer.bytes(5)? != b"DRACO" {
+        return Err(invalid("not a Draco stream"));
+    }
+    let (major, minor) = (buffer.u8()?, buffer.u8()?);
+    if (major, minor) != (2, 2) {
+        return Err(invalid(&format!("unsupported version {major}.{minor}")));
+    }
+    if buffer.u8()? != 1 {
+        return Err(invalid("not a triangle mesh"));
+    }
+    let edgebreaker = match buffer.u8()? {
+        0 => false,
+        1 => true,
+        method => return Err(invalid(&format!("unknown connectivity method {method}"))),
+    };
+    if buffer.u16()? & METADATA_FLAG != 0 {
+        skip_metadata(&mut buffer)?;
+    }
+
+    let connectivity = if edgebreaker {
+        Connectivity::Edgebreaker(decode_edgebreaker(&mut buffer)?)
+    } else {
+        decode_sequential(&mut buffer)?
+    };
+
+    let num_decoders = buffer.u8()?;
+    if num_decoders == 0 {
+        return Err(invalid("no attributes"));
+    }
+    let mut traversal = 0;
+    if edgebreaker {
+        for i in 0..num_decoders {
+            let attribute_data = buffer.i8()?;
+            let decoder_type = buffer.u8()?;
+            let method = buffer.u8()?;
+            if i == 0 {
+                if attribute_data >= 0 || decoder_type != 0 {
+                    return Err(invalid("positions with seams of their own are not supported"));
+                }
+                traversal = method;
+            }
+        }
+    }
+    let mut decoders = Vec::with_capacity(num_decoders as usize);
+    for _ in 0..num_decoders {
+        decoders.push(read_attributes(&mut buffer)?);
+    }
+    let attributes = &decoders[0];
+    let Some(position) = attributes.iter().position(|attribute| attribute.unique_id == position_id) else {
+        return Err(invalid("positions are not in the first attribute decoder"));
+    };
+    if attributes[position].components != 3 {
+        return Err(invalid("positions do not have three components"));
+    }
+
+    let (mesh, entries) = match &connectivity {
+        Connectivity::Sequential { num_points, .. } => (None, *num_points as usize),
+        Connectivity::Edgebreaker(table) => {
+            let sequence = traverse(table, traversal)?;
+            let entries = sequence.value_corners.len();
+            (Some(Mesh { table, sequence }), entries)
+        }
+    };
+
+    let mut values = None;
+    for (i, attribute) in attributes.iter().enumerate().take(position + 1) {
+        let is_position = i == position;
+        let decoded = match attribute.decoder {
+            DECODER_GENERIC => {
+                let size = data_type_size(attribute.data_type)? * attribute.components;
+                let bytes = buffer.bytes(size.checked_mul(entries).ok_or_else(|| invalid("too many values"))?)?;
+                Values::Raw(bytes)
+            }
+            DECODER_INTEGER | DECODER_QUANTIZATION => Values::Integers(
+                decode_integers(&mut buffer, attribute.decoder, attribute.components, entries, mesh.as_ref(), is_position)?,
+            ),
+            DECODER_NORMALS if !is_position => {
+                decode_integers(&mut buffer, attribute.decoder, 2, entries, mesh.as_ref(), false)?;
+                continue;
+            }
+            decoder => return Err(invalid(&format!("unsupported attribute decoder {decoder}"))),
+        };
+        if is_position {
+            values = Some(decoded);
+        }
+    }
+    let Some(values) = values else {
+        unreachable!("positions are decoded last");
+    };
+
+    // Transform parameters follow all the values.
+    for attribute in &attributes[..position] {
+        match attribute.decoder {
+            DECODER_QUANTIZATION => {
+                buffer.bytes(4 * attribute.components + 5)?;
+            }
+            DECODER_NORMALS => {
+                buffer.u8()?;
+            }
+            _ => (),
+        }
+    }
+    let attribute = &attributes[position];
+    let positions: Vec<Vec3> = match values {
+        Values::Raw(bytes) => {
+            let size = data_type_size(attribute.data_type)?;
+            bytes.chunks_exact(3 * size)
+                .map(|entry| Vec3::from(std::array::from_fn(|c| component(attribute.data_type, &entry[c * size..]))))
+                .collect()
+        }
+        Values::Integers(integers) if attribute.decoder == DECODER_QUANTIZATION => {
+            let min = [buffer.f32()?, buffer.f32()?, buffer.f32()?];
+            let range = buffer.f32()?;
+            let bits = buffer.u8()?;
+            if !(1..=30).contains(&bits) {
+                return Err(invalid("invalid quantization"));
+            }
+            let step = range / ((1u32 << bits) - 1) as f32;
+            integers.chunks_exact(3)
+                .map(|entry| Vec3::from(std::array::from_fn(|c| entry[c] as f32 * step + min[c])))
+                .collect()
+        }
+        Values::Integers(integers) => integers.chunks_exact(3)
+            .map(|entry| vec3![entry[0] as f32, entry[1] as f32, entry[2] as f32])
+            .collect(),
+    };
+
+    let points: Vec<u32> = match &connectivity {
+        Connectivity::Sequential { faces, .. } => faces.clone(),
+        Connectivity::Edgebreaker(table) => {
+            let sequence = &mesh.as_ref().unwrap().sequence;
+            table.vertices.iter()
+                .map(|&vertex| sequence.vertex_values.get(vertex as usize).copied().unwrap_or(INVALID))
+                .collect()
+        }
+    };
+    points.iter()
+        .map(|&point| positions.get(point as usize).copied())
+        .collect::<Option<_>>()
+        .ok_or_else(|| invalid("face refers to a missing vertex"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Draco: {message}"))
+}
+
+fn broken() -> io::Error {
+    invalid("broken connectivity")
+}
+
+/// Little-endian reader over a stream.
+struct Buffer<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Buffer<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.remaining().get(..count).ok_or_else(|| invalid("unexpected end of data"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn i8(&mut self) -> io::Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        self.array().map(i32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        self.array().map(f32::from_le_bytes)
+    }
+
+    /// An unsigned integer in seven-bit groups, lowest first.
+    fn varint(&mut self) -> io::Result<u32> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return u32::try_from(value).map_err(|_| invalid("number out of range"));
+            }
+        }
+        Err(invalid("number out of range"))
+    }
+
+    /// Starts reading single bits from the current position.
+    fn bits(&self) -> BitReader<'a> {
+        BitReader { data: self.remaining(), position: 0 }
+    }
+
+    /// Moves past the whole bytes `bits` read from.
+    fn end_bits(&mut self, bits: BitReader) {
+        self.position += bits.position.div_ceil(8);
+    }
+}
+
+/// Reader of bits, lowest bit of each byte first. Reads past the end give zeros.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for bit in 0..count {
+            let Some(byte) = self.data.get(self.position / 8) else {
+                break;
+            };
+            value |= u32::from((byte >> (self.position % 8)) & 1) << bit;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+/// Reads the initial state of an rANS decoder from the end of `data`, where the top two bits
+/// of the last byte give how many of up to `max_bytes` bytes it takes.
+fn rans_state(data: &[u8], max_bytes: usize) -> io::Result<(usize, u32)> {
+    let &last = data.last().ok_or_else(|| invalid("empty entropy coded data"))?;
+    let bytes = (last >> 6) as usize + 1;
+    if bytes > max_bytes || bytes > data.len() {
+        return Err(invalid("invalid entropy coded data"));
+    }
+    let offset = data.len() - bytes;
+    let state = data[offset..].iter().rev().fold(0, |state, &byte| state << 8 | u32::from(byte));
+    Ok((offset, state & ((1 << (8 * bytes - 2)) - 1)))
+}
+
+/// rANS decoder of bits that are zero with a fixed probability.
+struct BitDecoder<'a> {
+    probability_zero: u8,
+    data: &'a [u8],
+    offset: usize,
+    state: u32,
+}
+
+impl<'a> BitDecoder<'a> {
+    const BASE: u32 = 4096;
+
+    fn read(buffer: &mut Buffer<'a>) -> io::Result<Self> {
+        let probability_zero = buffer.u8()?;
+        let size = buffer.varint()? as usize;
+        let data = buffer.bytes(size)?;
+        let (offset, state) = rans_state(data, 3)?;
+        let state = state + Self::BASE;
+        if state >= Self::BASE * 256 {
+            return Err(invalid("invalid entropy coded data"));
+        }
+        Ok(Self { probability_zero, data, offset, state })
+    }
+
+    fn bit(&mut self) -> bool {
+        if self.state < Self::BASE && self.offset > 0 {
+            self.offset -= 1;
+            self.state = self.state * 256 + u32::from(self.data[self.offset]);
+        }
+        let p = 256 - u32::from(self.probability_zero);
+        let (quotient, remainder) = (self.state / 256, self.state % 256);
+        let scaled = quotient * p;
+        if remainder < p {
+            self.state = scaled + remainder;
+            true
+        } else {
+            self.state -= scaled + p;
+            false
+        }
+    }
+}
+
+/// rANS decoder of symbols with a probability table stored ahead of the data.
+struct SymbolDecoder<'a> {
+    precision: u32,
+    probabilities: Vec<u32>,
+    cumulative: Vec<u32>,
+    /// Symbol for each slot of the precision range.
+    lookup: Vec<u32>,
+    data: &'a [u8],
+    offset: usize,
+    state: u32,
+}
+
+impl<'a> SymbolDecoder<'a> {
+    /// Reads the table and data of symbols up to `max_bit_length` bits long.
+    fn read(buffer: &mut Buffer<'a>, max_bit_length: u32) -> io::Result<Self> {
+        let precision = 1 << (3 * max_bit_length / 2).clamp(12, 20);
+        let num_symbols = buffer.varint()? as usize;
+        if num_symbols == 0 || num_symbols / 64 > buffer.remaining().len() {
+            return Err(invalid("invalid symbol table"));
+        }
+        let mut probabilities = vec![0; num_symbols];
+        let mut i = 0;
+        while i < num_symbols {
+            let byte = buffer.u8()?;
+            if byte & 3 == 3 {
+                // A run of symbols that never occur.
+                i += (byte >> 2) as usize + 1;
+                if i > num_symbols {
+                    return Err(invalid("invalid symbol table"));
+                }
+                continue;
+            }
+            let mut probability = u32::from(byte >> 2);
+            for extra in 0..u32::from(byte & 3) {
+                probability |= u32::from(buffer.u8()?) << (8 * (extra + 1) - 2);
+            }
+            probabilities[i] = probability;
+            i += 1;
+        }
+
+        let mut cumulative = Vec::with_capacity(num_symbols);
+        let mut lookup = Vec::with_capacity(precision as usize);
+        let mut total = 0u32;
+        for (symbol, &probability) in probabilities.iter().enumerate() {
+            cumulative.push(total);
+            total = total.saturating_add(probability);
+            if total > precision {
+                return Err(invalid("invalid symbol table"));
+            }
+            lookup.resize(total as usize, symbol as u32);
+        }
+        if total != precision {
+            return Err(invalid("invalid symbol table"));
+        }
+
+        let size = buffer.varint()? as usize;
+        let data = buffer.bytes(size)?;
+        let (offset, state) = rans_state(data, 4)?;
+        let base = 4 * precision;
+        let state = state + base;
+        if state >= base * 256 {
+            return Err(invalid("invalid entropy coded data"));
+        }
+        Ok(Self { precision, probabilities, cumulative, lookup, data, offset, state })
+    }
+
+    fn symbol(&mut self) -> u32 {
+        while self.state < 4 * self.precision && self.offset > 0 {
+            self.offset -= 1;
+            self.state = self.state * 256 + u32::from(self.data[self.offset]);
+        }
+        let (quotient, remainder) = (self.state / self.precision, self.state % self.precision);
+        let symbol = self.lookup[remainder as usize];
+        self.state = quotient * self.probabilities[symbol as usize] + remainder - self.cumulative[symbol as usize];
+        symbol
+    }
+}
+
+/// Decodes `num_values` entropy coded values in groups of `components`.
+fn decode_symbols(buffer: &mut Buffer, num_values: usize, components: usize) -> io::Result<Vec<u32>> {
+    if num_values == 0 {
+        return Ok(Vec::new());
+    }
+    match buffer.u8()? {
+        // Each group is coded as a bit length, followed by that many bits per value.
+        0 => {
+            let mut lengths = SymbolDecoder::read(buffer, 5)?;
+            let mut bits = buffer.bits();
+            let mut values = Vec::new();
+            while values.len() < num_values {
+                let length = lengths.symbol();
+                if length > 32 {
+                    return Err(invalid("invalid value length"));
+                }
+                values.extend((0..components).map(|_| bits.read(length)));
+            }
+            buffer.end_bits(bits);
+            values.truncate(num_values);
+            Ok(values)
+        }
+        1 => {
+            let max_bit_length = u32::from(buffer.u8()?);
+            if !(1..=18).contains(&max_bit_length) {
+                return Err(invalid("invalid value length"));
+            }
+            let mut decoder = SymbolDecoder::read(buffer, max_bit_length)?;
+            Ok((0..num_values).map(|_| decoder.symbol()).collect())
+        }
+        scheme => Err(invalid(&format!("unknown symbol coding {scheme}"))),
+    }
+}
+
+/// Skips the metadata of the attributes and of the mesh.
+fn skip_metadata(buffer: &mut Buffer) -> io::Result<()> {
+    for _ in 0..buffer.varint()? {
+        buffer.varint()?;
+        skip_metadata_entries(buffer, 0)?;
+    }
+    skip_metadata_entries(buffer, 0)
+}
+
+fn skip_metadata_entries(buffer: &mut Buffer, depth: usize) -> io::Result<()> {
+    if depth > 32 {
+        return Err(invalid("metadata nested too deeply"));
+    }
+    for _ in 0..buffer.varint()? {
+        let name = buffer.u8()?;
+        buffer.bytes(name as usize)?;
+        let size = buffer.varint()?;
+        buffer.bytes(size as usize)?;
+    }
+    for _ in 0..buffer.varint()? {
+        let name = buffer.u8()?;
+        buffer.bytes(name as usize)?;
+        skip_metadata_entries(buffer, depth + 1)?;
+    }
+    Ok(())
+}
+
+enum Connectivity {
+    /// Triangles as point indices, three per triangle.
+    Sequential { faces: Vec<u32>, num_points: u32 },
+    Edgebreaker(CornerTable),
+}
+
+fn decode_sequential(buffer: &mut Buffer) -> io::Result<Connectivity> {
+    let num_faces = buffer.varint()?;
+    let num_points = buffer.varint()?;
+    let num_indices = num_faces.checked_mul(3).ok_or_else(|| invalid("too many faces"))? as usize;
+    let faces = match buffer.u8()? {
+        // Differences between consecutive indices, entropy coded.
+        0 => {
+            let mut last = 0u32;
+            decode_symbols(buffer, num_indices, 1)?.into_iter()
+                .map(|value| {
+                    let difference = value >> 1;
+                    last = if value & 1 == 1 { last.checked_sub(difference) } else { last.checked_add(difference) }
+                        .ok_or_else(|| invalid("index out of range"))?;
+                    Ok(last)
+                })
+                .collect::<io::Result<_>>()?
+        }
+        1 => {
+            let mut faces = Vec::new();
+            for _ in 0..num_indices {
+                faces.push(match num_points {
+                    0..256 => u32::from(buffer.u8()?),
+                    256..65536 => u32::from(buffer.u16()?),
+                    65536..0x20_0000 => buffer.varint()?,
+                    _ => u32::from_le_bytes(buffer.array()?),
+                });
+            }
+            faces
+        }
+        method => return Err(invalid(&format!("unknown index coding {method}"))),
+    };
+    Ok(Connectivity::Sequential { faces, num_points })
+}
+
+fn next(corner: u32) -> u32 {
+    match corner {
+        INVALID => INVALID,
+        corner if corner % 3 == 2 => corner - 2,
+        corner => corner + 1,
+    }
+}
+
+fn previous(corner: u32) -> u32 {
+    match corner {
+        INVALID => INVALID,
+        corner if corner % 3 == 0 => corner + 2,
+        corner => corner - 1,
+    }
+}
+
+/// Triangles as corners, three per face, with the vertex at each corner and the corner across
+/// the opposite edge.
+struct CornerTable {
+    vertices: Vec<u32>,
+    opposites: Vec<u32>,
+    /// For each vertex, the corner furthest to the left, where swinging left stops at a border.
+    left_most: Vec<u32>,
+}
+
+impl CornerTable {
+    fn new(num_faces: u32) -> Self {
+        let corners = 3 * num_faces as usize;
+        Self {
+            vertices: vec![INVALID; corners],
+            opposites: vec![INVALID; corners],
+            left_most: Vec::new(),
+        }
+    }
+
+    fn vertex(&self, corner: u32) -> u32 {
+        self.vertices.get(corner as usize).copied().unwrap_or(INVALID)
+    }
+
+    fn opposite(&self, corner: u32) -> u32 {
+        self.opposites.get(corner as usize).copied().unwrap_or(INVALID)
+    }
+
+    fn left_most(&self, vertex: u32) -> u32 {
+        self.left_most.get(vertex as usize).copied().unwrap_or(INVALID)
+    }
+
+    fn swing_left(&self, corner: u32) -> u32 {
+        next(self.opposite(next(corner)))
+    }
+
+    /// Corner across the edge after `corner`.
+    fn right_corner(&self, corner: u32) -> u32 {
+        self.opposite(next(corner))
+    }
+
+    /// Corner across the edge before `corner`.
+    fn left_corner(&self, corner: u32) -> u32 {
+        self.opposite(previous(corner))
+    }
+
+    fn on_border(&self, vertex: u32) -> bool {
+        self.swing_left(self.left_most(vertex)) == INVALID
+    }
+
+    fn num_vertices(&self) -> usize {
+        self.left_most.len()
+    }
+
+    fn add_vertex(&mut self) -> u32 {
+        self.left_most.push(INVALID);
+        (self.left_most.len() - 1) as u32
+    }
+
+    fn set_vertex(&mut self, corner: u32, vertex: u32) {
+        self.vertices[corner as usize] = vertex;
+    }
+
+    fn set_left_most(&mut self, vertex: u32, corner: u32) {
+        if let Some(left_most) = self.left_most.get_mut(vertex as usize) {
+            *left_most = corner;
+        }
+    }
+
+    fn set_opposites(&mut self, a: u32, b: u32) -> io::Result<()> {
+        if a as usize >= self.opposites.len() || b as usize >= self.opposites.len() {
+            return Err(broken());
+        }
+        self.opposites[a as usize] = b;
+        self.opposites[b as usize] = a;
+        Ok(())
+    }
+}
+
+/// Where the symbols of an Edgebreaker traversal come from.
+enum Symbols<'a> {
+    /// One bit for C, three for the others.
+    Standard(BitReader<'a>),
+    /// Entropy coded in a context picked by the valence of the vertex the traversal reaches.
+    Valence {
+        /// Symbols of each context, taken from the back.
+        contexts: Vec<Vec<u32>>,
+        valences: Vec<u32>,
+        context: Option<usize>,
+        last: u32,
+    },
+}
+
+impl Symbols<'_> {
+    fn next(&mut self) -> u32 {
+        match self {
+            Self::Standard(bits) => match bits.read(1) {
+                0 => SYMBOL_C,
+                _ => 1 | bits.read(2) << 1,
+            },
+            Self::Valence { contexts, context, last, .. } => {
+                *last = match context {
+                    Some(context) => contexts[*context].pop()
+                        .and_then(|symbol| VALENCE_SYMBOLS.get(symbol as usize).copied())
+                        .unwrap_or(INVALID),
+                    None => SYMBOL_E,
+                };
+                *last
+            }
+        }
+    }
+
+    /// Tracks the valences the last symbol added, and picks the next symbol's context.
+    fn corner_reached(&mut self, table: &CornerTable, corner: u32) {
+        let Self::Valence { valences, context, last, .. } = self else {
+            return;
+        };
+        let (next, previous) = (next(corner), previous(corner));
+        let added = match *last {
+            SYMBOL_C | SYMBOL_S => [0, 1, 1],
+            SYMBOL_R => [1, 1, 2],
+            SYMBOL_L => [1, 2, 1],
+            _ => [2, 2, 2],
+        };
+        for (corner, added) in [corner, next, previous].into_iter().zip(added) {
+            if let Some(valence) = valences.get_mut(table.vertex(corner) as usize) {
+                *valence += added;
+            }
+        }
+        let valence = valences.get(table.vertex(next) as usize).copied().unwrap_or_default();
+        *context = Some((valence.clamp(MIN_VALENCE, MAX_VALENCE) - MIN_VALENCE) as usize);
+    }
+
+    fn merge_vertices(&mut self, into: u32, from: u32) {
+        if let Self::Valence { valences, .. } = self {
+            let from = valences.get(from as usize).copied().unwrap_or_default();
+            if let Some(valence) = valences.get_mut(into as usize) {
+                *valence += from;
+            }
+        }
+    }
+}
+
+/// Where the traversal splits off a part of the mesh it comes back to later.
+struct Split {
+    source: u32,
+    target: u32,
+    right: bool,
+}
+
+/// Skips the bit decoders of attribute seams, which only attributes with their own
+/// connectivity use.
+fn skip_seams(buffer: &mut Buffer, num_attribute_data: u8) -> io::Result<()> {
+    for _ in 0..num_attribute_data {
+        buffer.u8()?;
+        let size = buffer.varint()?;
+        buffer.bytes(size as usize)?;
+    }
+    Ok(())
+}
+
+fn decode_edgebreaker(buffer: &mut Buffer) -> io::Result<CornerTable> {
+    let traversal = buffer.u8()?;
+    let num_encoded_vertices = buffer.varint()?;
+    let num_faces = buffer.varint()?;
+    let num_attribute_data = buffer.u8()?;
+    let num_symbols = buffer.varint()?;
+    let num_split_symbols = buffer.varint()?;
+    if num_faces > INVALID / 3 || num_symbols > num_faces || num_split_symbols > num_symbols {
+        return Err(invalid("too many faces"));
+    }
+    let max_vertices = num_encoded_vertices.checked_add(num_split_symbols)
+        .ok_or_else(|| invalid("too many vertices"))? as usize;
+
+    let num_splits = buffer.varint()?;
+    if num_splits > num_faces {
+        return Err(invalid("too many splits"));
+    }
+    let mut splits = Vec::with_capacity(num_splits as usize);
+    let mut source = 0u32;
+    for _ in 0..num_splits {
+        source = source.checked_add(buffer.varint()?).ok_or_else(broken)?;
+        let target = source.checked_sub(buffer.varint()?).ok_or_else(broken)?;
+        splits.push(Split { source, target, right: false });
+    }
+    let mut bits = buffer.bits();
+    for split in &mut splits {
+        split.right = bits.read(1) == 1;
+    }
+    buffer.end_bits(bits);
+
+    let (mut symbols, mut start_faces) = match traversal {
+        0 => {
+            let size = buffer.varint()?;
+            let symbols = Symbols::Standard(buffer.bits());
+            buffer.bytes(size as usize)?;
+            let start_faces = BitDecoder::read(buffer)?;
+            skip_seams(buffer, num_attribute_data)?;
+            (symbols, start_faces)
+        }
+        2 => {
+            let start_faces = BitDecoder::read(buffer)?;
+            skip_seams(buffer, num_attribute_data)?;
+            let contexts = (MIN_VALENCE..=MAX_VALENCE)
+                .map(|_| {
+                    let count = buffer.varint()?;
+                    if count > num_faces {
+                        return Err(invalid("too many symbols"));
+                    }
+                    decode_symbols(buffer, count as usize, 1)
+                })
+                .collect::<io::Result<_>>()?;
+            let symbols = Symbols::Valence { contexts, valences: vec![0; max_vertices], context: None, last: SYMBOL_E };
+            (symbols, start_faces)
+        }
+        traversal => return Err(invalid(&format!("unsupported traversal {traversal}"))),
+    };
+
+    // Faces are rebuilt in the reverse of the order the encoder visited them, each symbol
+    // attaching one face to the edge on top of the stack.
+    let mut table = CornerTable::new(num_faces);
+    let mut active: Vec<u32> = Vec::new();
+    let mut split_corners: HashMap<u32, u32> = HashMap::new();
+    for symbol_id in 0..num_symbols {
+        let corner = 3 * symbol_id;
+        let mut check_splits = false;
+        match symbols.next() {
+            SYMBOL_C => {
+                let &corner_a = active.last().ok_or_else(broken)?;
+                let vertex_x = table.vertex(next(corner_a));
+                let corner_b = next(table.left_most(vertex_x));
+                if corner_b == INVALID
+                    || corner_a == corner_b
+                    || table.opposite(corner_a) != INVALID
+                    || table.opposite(corner_b) != INVALID
+                {
+                    return Err(broken());
+                }
+                table.set_opposites(corner_a, corner + 1)?;
+                table.set_opposites(corner_b, corner + 2)?;
+                let vertex_a_previous = table.vertex(previous(corner_a));
+                let vertex_b_next = table.vertex(next(corner_b));
+                if vertex_x == vertex_a_previous || vertex_x == vertex_b_next {
+                    return Err(broken());
+                }
+                table.set_vertex(corner, vertex_x);
+                table.set_vertex(corner + 1, vertex_b_next);
+                table.set_vertex(corner + 2, vertex_a_previous);
+                table.set_left_most(vertex_a_previous, corner + 2);
+                *active.last_mut().unwrap() = corner;
+            }
+            symbol @ (SYMBOL_R | SYMBOL_L) => {
+                let &corner_a = active.last().ok_or_else(broken)?;
+                if table.opposite(corner_a) != INVALID {
+                    return Err(broken());
+                }
+                let (opposite, corner_l, corner_r) = if symbol == SYMBOL_R {
+                    (corner + 2, corner + 1, corner)
+                } else {
+                    (corner + 1, corner, corner + 2)
+                };
+                table.set_opposites(opposite, corner_a)?;
+                let vertex = table.add_vertex();
+                if table.num_vertices() > max_vertices {
+                    return Err(broken());
+                }
+                table.set_vertex(opposite, vertex);
+                table.set_left_most(vertex, opposite);
+                let vertex_r = table.vertex(previous(corner_a));
+                table.set_vertex(corner_r, vertex_r);
+                table.set_left_most(vertex_r, corner_r);
+                table.set_vertex(corner_l, table.vertex(next(corner_a)));
+                *active.last_mut().unwrap() = corner;
+                check_splits = true;
+            }
+            SYMBOL_S => {
+                let corner_b = active.pop().ok_or_else(broken)?;
+                if let Some(&corner) = split_corners.get(&symbol_id) {
+                    active.push(corner);
+                }
+                let &corner_a = active.last().ok_or_else(broken)?;
+                if table.opposite(corner_a) != INVALID || table.opposite(corner_b) != INVALID {
+                    return Err(broken());
+                }
+                table.set_opposites(corner_a, corner + 2)?;
+                table.set_opposites(corner_b, corner + 1)?;
+                let vertex_p = table.vertex(previous(corner_a));
+                table.set_vertex(corner, vertex_p);
+                table.set_vertex(corner + 1, table.vertex(next(corner_a)));
+                let vertex_b_previous = table.vertex(previous(corner_b));
+                table.set_vertex(corner + 2, vertex_b_previous);
+                table.set_left_most(vertex_b_previous, corner + 2);
+                // The vertex after corner b closes onto vertex p.
+                let first = next(corner_b);
+                let vertex_n = table.vertex(first);
+                symbols.merge_vertices(vertex_p, vertex_n);
+                table.set_left_most(vertex_p, table.left_most(vertex_n));
+                let mut corner_n = first;
+                while corner_n != INVALID {
+                    table.set_vertex(corner_n, vertex_p);
+                    corner_n = table.swing_left(corner_n);
+                    if corner_n == first {
+                        return Err(broken());
+                    }
+                }
+                table.set_left_most(vertex_n, INVALID);
+                *active.last_mut().unwrap() = corner;
+            }
+            SYMBOL_E => {
+                for i in 0..3 {
+                    let vertex = table.add_vertex();
+                    table.set_vertex(corner + i, vertex);
+                    table.set_left_most(vertex, corner + i);
+                }
+                if table.num_vertices() > max_vertices {
+                    return Err(broken());
+                }
+                active.push(corner);
+                check_splits = true;
+            }
+            _ => return Err(invalid("invalid symbol")),
+        }
+        symbols.corner_reached(&table, *active.last().unwrap());
+
+        if check_splits {
+            let encoder_symbol_id = num_symbols - symbol_id - 1;
+            while let Some(split) = splits.last() {
+                if split.source > encoder_symbol_id {
+                    return Err(broken());
+                }
+                if split.source != encoder_symbol_id {
+                    break;
+                }
+                let split = splits.pop().unwrap();
+                if split.target >= num_symbols {
+                    return Err(broken());
+                }
+                let top = *active.last().unwrap();
+                let corner = if split.right { next(top) } else { previous(top) };
+                split_corners.insert(num_symbols - split.target - 1, corner);
+            }
+        }
+    }
+
+    // Faces the encoder started from, closing holes left in the middle of the mesh.
+    let mut num_decoded_faces = num_symbols;
+    while let Some(corner) = active.pop() {
+        if !start_faces.bit() {
+            continue;
+        }
+        if num_decoded_faces >= num_faces {
+            return Err(broken());
+        }
+        let vertex_n = table.vertex(next(corner));
+        let corner_b = next(table.left_most(vertex_n));
+        let vertex_x = table.vertex(next(corner_b));
+        let corner_c = next(table.left_most(vertex_x));
+        let vertex_p = table.vertex(next(corner_c));
+        let first = 3 * num_decoded_faces;
+        num_decoded_faces += 1;
+        table.set_opposites(first, corner)?;
+        table.set_opposites(first + 1, corner_b)?;
+        table.set_opposites(first + 2, corner_c)?;
+        table.set_vertex(first, vertex_x);
+        table.set_vertex(first + 1, vertex_p);
+        table.set_vertex(first + 2, vertex_n);
+    }
+    if num_decoded_faces != num_faces {
+        return Err(broken());
+    }
+    Ok(table)
+}
+
+/// The order a traversal first reaches vertices in, which is the order their values are stored.
+struct Sequence {
+    /// Corner each value was reached through.
+    value_corners: Vec<u32>,
+    vertex_values: Vec<u32>,
+}
+
+struct Mesh<'a> {
+    table: &'a CornerTable,
+    sequence: Sequence,
+}
+
+impl Mesh<'_> {
+    /// Index of the value at the vertex of `corner`.
+    fn value(&self, corner: u32) -> usize {
+        let vertex = self.table.vertex(corner);
+        self.sequence.vertex_values.get(vertex as usize).copied().unwrap_or(INVALID) as usize
+    }
+}
+
+struct Traversal<'a> {
+    table: &'a CornerTable,
+    visited_faces: Vec<bool>,
+    visited_vertices: Vec<bool>,
+    sequence: Sequence,
+}
+
+/// Traverses every face of `table` with the depth-first (0) or prediction degree (1) method.
+fn traverse(table: &CornerTable, method: u8) -> io::Result<Sequence> {
+    let mut traversal = Traversal {
+        table,
+        visited_faces: vec![false; table.vertices.len() / 3],
+        visited_vertices: vec![false; table.num_vertices()],
+        sequence: Sequence {
+            value_corners: Vec::new(),
+            vertex_values: vec![INVALID; table.num_vertices()],
+        },
+    };
+    let mut degrees = vec![0u32; table.num_vertices()];
+    for face in 0..traversal.visited_faces.len() as u32 {
+        match method {
+            0 => traversal.depth_first(3 * face)?,
+            1 => traversal.prediction_degree(3 * face, &mut degrees)?,
+            method => return Err(invalid(&format!("unknown traversal {method}"))),
+        }
+    }
+    Ok(traversal.sequence)
+}
+
+impl Traversal<'_> {
+    fn face_visited(&self, corner: u32) -> bool {
+        corner == INVALID || self.visited_faces[(corner / 3) as usize]
+    }
+
+    fn vertex_visited(&self, vertex: u32) -> bool {
+        self.visited_vertices.get(vertex as usize).copied().unwrap_or(true)
+    }
+
+    fn visit_vertex(&mut self, corner: u32) -> io::Result<()> {
+        let vertex = self.table.vertex(corner);
+        let visited = self.visited_vertices.get_mut(vertex as usize).ok_or_else(broken)?;
+        if !*visited {
+            *visited = true;
+            self.sequence.vertex_values[vertex as usize] = self.sequence.value_corners.len() as u32;
+            self.sequence.value_corners.push(corner);
+        }
+        Ok(())
+    }
+
+    fn depth_first(&mut self, start: u32) -> io::Result<()> {
+        if self.face_visited(start) {
+            return Ok(());
+        }
+        self.visit_vertex(next(start))?;
+        self.visit_vertex(previous(start))?;
+        let mut stack = vec![start];
+        while let Some(&top) = stack.last() {
+            if self.face_visited(top) {
+                stack.pop();
+                continue;
+            }
+            let mut corner = top;
+            loop {
+                if corner == INVALID {
+                    return Err(broken());
+                }
+                self.visited_faces[(corner / 3) as usize] = true;
+                let vertex = self.table.vertex(corner);
+                if !self.vertex_visited(vertex) {
+                    let on_border = self.table.on_border(vertex);
+                    self.visit_vertex(corner)?;
+                    if !on_border {
+                        corner = self.table.right_corner(corner);
+                        continue;
+                    }
+                }
+                let right = self.table.right_corner(corner);
+                let left = self.table.left_corner(corner);
+                match (self.face_visited(right), self.face_visited(left)) {
+                    (true, true) => {
+                        stack.pop();
+                        break;
+                    }
+                    (true, false) => corner = left,
+                    (false, true) => corner = right,
+                    (false, false) => {
+                        *stack.last_mut().unwrap() = left;
+                        stack.push(right);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prefers faces whose new vertex has the most neighbours decoded already.
+    fn prediction_degree(&mut self, start: u32, degrees: &mut [u32]) -> io::Result<()> {
+        let mut stacks: [Vec<u32>; 3] = Default::default();
+        let mut best = 0;
+        stacks[0].push(start);
+        self.visit_vertex(next(start))?;
+        self.visit_vertex(previous(start))?;
+        self.visit_vertex(start)?;
+        loop {
+            let Some(priority) = (best..stacks.len()).find(|&priority| !stacks[priority].is_empty()) else {
+                return Ok(());
+            };
+            best = priority;
+            let mut corner = stacks[priority].pop().unwrap();
+            if self.face_visited(corner) {
+                continue;
+            }
+            loop {
+                self.visited_faces[(corner / 3) as usize] = true;
+                self.visit_vertex(corner)?;
+                let right = self.table.right_corner(corner);
+                let left = self.table.left_corner(corner);
+                let right_visited = self.face_visited(right);
+                if !self.face_visited(left) {
+                    let priority = self.priority(left, degrees);
+                    if right_visited && priority <= best {
+                        corner = left;
+                        continue;
+                    }
+                    stacks[priority].push(left);
+                    best = best.min(priority);
+                }
+                if !right_visited {
+                    let priority = self.priority(right, degrees);
+                    if priority <= best {
+                        corner = right;
+                        continue;
+                    }
+                    stacks[priority].push(right);
+                    best = best.min(priority);
+                }
+                break;
+            }
+        }
+    }
+
+    fn priority(&self, corner: u32, degrees: &mut [u32]) -> usize {
+        let vertex = self.table.vertex(corner);
+        if self.vertex_visited(vertex) {
+            return 0;
+        }
+        let degree = &mut degrees[vertex as usize];
+        *degree += 1;
+        if *degree > 1 { 1 } else { 2 }
+    }
+}
+
+/// An attribute as its decoder describes it.
+struct Attribute {
+    data_type: u8,
+    components: usize,
+    unique_id: u32,
+    /// How its values are coded.
+    decoder: u8,
+}
+
+fn read_attributes(buffer: &mut Buffer) -> io::Result<Vec<Attribute>> {
+    let count = buffer.varint()?;
+    if count == 0 {
+        return Err(invalid("empty attribute decoder"));
+    }
+    let mut attributes = Vec::new();
+    for _ in 0..count {
+        let kind = buffer.u8()?;
+        let data_type = buffer.u8()?;
+        let components = buffer.u8()? as usize;
+        buffer.u8()?;
+        let unique_id = buffer.varint()?;
+        if kind >= 5 || components == 0 {
+            return Err(invalid("invalid attribute"));
+        }
+        data_type_size(data_type)?;
+        attributes.push(Attribute { data_type, components, unique_id, decoder: 0 });
+    }
+    for attribute in &mut attributes {
+        attribute.decoder = buffer.u8()?;
+    }
+    Ok(attributes)
+}
+
+fn data_type_size(data_type: u8) -> io::Result<usize> {
+    match data_type {
+        1 | 2 | 11 => Ok(1),
+        3 | 4 => Ok(2),
+        5 | 6 | 9 => Ok(4),
+        7 | 8 | 10 => Ok(8),
+        _ => Err(invalid(&format!("unknown data type {data_type}"))),
+    }
+}
+
+/// Reads one component of `data_type` from the start of `bytes`.
+fn component(data_type: u8, bytes: &[u8]) -> f32 {
+    let array = |n: usize| {
+        let mut array = [0; 8];
+        array[..n].copy_from_slice(&bytes[..n]);
+        array
+    };
+    match data_type {
+        1 => bytes[0] as i8 as f32,
+        2 | 11 => bytes[0] as f32,
+        3 => i16::from_le_bytes(array(2)[..2].try_into().unwrap()) as f32,
+        4 => u16::from_le_bytes(array(2)[..2].try_into().unwrap()) as f32,
+        5 => i32::from_le_bytes(array(4)[..4].try_into().unwrap()) as f32,
+        6 => u32::from_le_bytes(array(4)[..4].try_into().unwrap()) as f32,
+        7 => i64::from_le_bytes(array(8)) as f32,
+        8 => u64::from_le_bytes(array(8)) as f32,
+        9 => f32::from_le_bytes(array(4)[..4].try_into().unwrap()),
+        _ => f64::from_le_bytes(array(8)) as f32,
+    }
+}
+
+enum Values<'a> {
+    Raw(&'a [u8]),
+    Integers(Vec<i32>),
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Scheme {
+    Delta,
+    Parallelogram,
+    MultiParallelogram,
+    ConstrainedMultiParallelogram,
+    TexCoords,
+    GeometricNormal,
+}
+
+/// Keeps predicted values in range by wrapping corrections around it.
+struct Wrap {
+    min: i32,
+    max: i32,
+}
+
+impl Wrap {
+    fn read(buffer: &mut Buffer) -> io::Result<Self> {
+        let (min, max) = (buffer.i32()?, buffer.i32()?);
+        if min > max || i64::from(max) - i64::from(min) + 1 >= i64::from(i32::MAX) {
+            return Err(invalid("invalid prediction range"));
+        }
+        Ok(Self { min, max })
+    }
+
+    fn apply(&self, predicted: &[i32], corrections: &[i32], out: &mut [i32]) {
+        let span = self.max - self.min + 1;
+        for ((out, &predicted), &correction) in out.iter_mut().zip(predicted).zip(corrections) {
+            let value = predicted.clamp(self.min, self.max).wrapping_add(correction);
+            *out = if value > self.max {
+                value.wrapping_sub(span)
+            } else if value < self.min {
+                value.wrapping_add(span)
+            } else {
+                value
+            };
+        }
+    }
+}
+
+/// Decodes `entries` integer values of `components` each. Only when `restore` is set are the
+/// values worked back from their predictions; otherwise the prediction data is just read past.
+fn decode_integers(
+    buffer: &mut Buffer,
+    decoder: u8,
+    components: usize,
+    entries: usize,
+    mesh: Option<&Mesh>,
+    restore: bool,
+) -> io::Result<Vec<i32>> {
+    let method = buffer.i8()?;
+    let scheme = if method == PREDICTION_NONE {
+        None
+    } else {
+        let transform = buffer.i8()?;
+        if !(-1..=6).contains(&method) || !(-1..=3).contains(&transform) {
+            return Err(invalid("unknown prediction"));
+        }
+        scheme(method, transform, decoder, mesh.is_some())?.map(|scheme| (scheme, transform))
+    };
+
+    let num_values = entries.checked_mul(components).ok_or_else(|| invalid("too many values"))?;
+    let symbols = if buffer.u8()? > 0 {
+        decode_symbols(buffer, num_values, components)?
+    } else {
+        let size = buffer.u8()? as usize;
+        if !(1..=4).contains(&size) {
+            return Err(invalid("invalid value size"));
+        }
+        let bytes = buffer.bytes(size.checked_mul(num_values).ok_or_else(|| invalid("too many values"))?)?;
+        bytes.chunks_exact(size)
+            .map(|value| value.iter().rev().fold(0, |value, &byte| value << 8 | u32::from(byte)))
+            .collect()
+    };
+    // Normal transforms keep corrections positive; everything else is stored zigzag encoded.
+    let positive = matches!(scheme, Some((_, TRANSFORM_OCTAHEDRON | TRANSFORM_OCTAHEDRON_CANONICALIZED)));
+    let corrections: Vec<i32> = symbols.into_iter()
+        .map(|value| match value & 1 {
+            _ if positive => value as i32,
+            0 => (value >> 1) as i32,
+            _ => -((value >> 1) as i32) - 1,
+        })
+        .collect();
+
+    let Some((scheme, transform)) = scheme else {
+        return Ok(corrections);
+    };
+    let mut creases: [Vec<bool>; MAX_PARALLELOGRAMS] = Default::default();
+    match scheme {
+        Scheme::ConstrainedMultiParallelogram => {
+            for creases in &mut creases {
+                let count = buffer.varint()?;
+                if count > 0 {
+                    let mut bits = BitDecoder::read(buffer)?;
+                    if restore {
+                        *creases = (0..count).map(|_| bits.bit()).collect();
+                    }
+                }
+            }
+        }
+        Scheme::TexCoords => {
+            if buffer.i32()? < 0 {
+                return Err(invalid("invalid prediction data"));
+            }
+            BitDecoder::read(buffer)?;
+        }
+        Scheme::GeometricNormal => {
+            buffer.i32()?;
+            if transform == TRANSFORM_OCTAHEDRON_CANONICALIZED {
+                buffer.i32()?;
+            }
+            BitDecoder::read(buffer)?;
+            return Ok(corrections);
+        }
+        _ => (),
+    }
+    if transform != TRANSFORM_WRAP {
+        buffer.i32()?;
+        if transform == TRANSFORM_OCTAHEDRON_CANONICALIZED {
+            buffer.i32()?;
+        }
+        return Ok(corrections);
+    }
+    let wrap = Wrap::read(buffer)?;
+    if !restore || corrections.is_empty() {
+        return Ok(corrections);
+    }
+    if scheme == Scheme::TexCoords {
+        return Err(invalid("texture coordinate prediction of positions is not supported"));
+    }
+    predict(scheme, &wrap, &corrections, components, mesh, &creases)
+}
+
+/// The prediction scheme the decoder builds for `method`, if any.
+fn scheme(method: i8, transform: i8, decoder: u8, mesh: bool) -> io::Result<Option<Scheme>> {
+    let normal = matches!(transform, TRANSFORM_OCTAHEDRON | TRANSFORM_OCTAHEDRON_CANONICALIZED);
+    let supported = match decoder {
+        DECODER_NORMALS => normal,
+        _ => transform == TRANSFORM_WRAP,
+    };
+    if !supported {
+        return Ok(None);
+    }
+    if !mesh {
+        return Ok(Some(Scheme::Delta));
+    }
+    Ok(Some(match (method, normal) {
+        (PREDICTION_PARALLELOGRAM, false) => Scheme::Parallelogram,
+        (PREDICTION_MULTI_PARALLELOGRAM, false) => Scheme::MultiParallelogram,
+        (PREDICTION_TEX_COORDS_DEPRECATED, false) => {
+            return Err(invalid("deprecated texture coordinate prediction is not supported"));
+        }
+        (PREDICTION_CONSTRAINED_MULTI_PARALLELOGRAM, false) => Scheme::ConstrainedMultiParallelogram,
+        (PREDICTION_TEX_COORDS_PORTABLE, false) => Scheme::TexCoords,
+        (PREDICTION_GEOMETRIC_NORMAL, true) => Scheme::GeometricNormal,
+        _ => Scheme::Delta,
+    }))
+}
+
+/// Predicts the value at `corner` from the triangle across from it, completing a parallelogram,
+/// when all three of its values come before `entry`.
+fn parallelogram(mesh: &Mesh, entry: usize, corner: u32, values: &[i32], components: usize, out: &mut [i32]) -> bool {
+    let opposite = mesh.table.opposite(corner);
+    if opposite == INVALID {
+        return false;
+    }
+    let across = mesh.value(opposite);
+    let next = mesh.value(next(opposite));
+    let previous = mesh.value(previous(opposite));
+    if across >= entry || next >= entry || previous >= entry {
+        return false;
+    }
+    for (c, out) in out.iter_mut().enumerate() {
+        let value = |entry: usize| i64::from(values[entry * components + c]);
+        *out = (value(next) + value(previous) - value(across)) as i32;
+    }
+    true
+}
+
+/// Works values back from `corrections` and the predictions of `scheme`.
+fn predict(
+    scheme: Scheme,
+    wrap: &Wrap,
+    corrections: &[i32],
+    components: usize,
+    mesh: Option<&Mesh>,
+    creases: &[Vec<bool>; MAX_PARALLELOGRAMS],
+) -> io::Result<Vec<i32>> {
+    let mut values = vec![0; corrections.len()];
+    wrap.apply(&vec![0; components], &corrections[..components], &mut values[..components]);
+    let mut prediction = vec![0; components];
+    let mut candidates = vec![vec![0; components]; MAX_PARALLELOGRAMS];
+    let mut crease_positions = [0; MAX_PARALLELOGRAMS];
+    let entries = corrections.len() / components;
+    for entry in 1..entries {
+        let predicted = match (scheme, mesh) {
+            (Scheme::Delta, _) | (_, None) => false,
+            (Scheme::Parallelogram, Some(mesh)) => {
+                let corner = mesh.sequence.value_corners[entry];
+                parallelogram(mesh, entry, corner, &values, components, &mut prediction)
+            }
+            (Scheme::MultiParallelogram, Some(mesh)) => {
+                // Average over every triangle around the vertex.
+                let start = mesh.sequence.value_corners[entry];
+                let mut corner = start;
+                let mut count = 0;
+                prediction.fill(0);
+                while corner != INVALID {
+                    if parallelogram(mesh, entry, corner, &values, components, &mut candidates[0]) {
+                        for (sum, &value) in prediction.iter_mut().zip(&candidates[0]) {
+                            *sum = sum.wrapping_add(value);
+                        }
+                        count += 1;
+                    }
+                    corner = previous(mesh.table.opposite(previous(corner)));
+                    if corner == start {
+                        break;
+                    }
+                }
+                for sum in &mut prediction {
+                    *sum /= count.max(1);
+                }
+                count > 0
+            }
+            (_, Some(mesh)) => {
+                // Average over up to four triangles around the vertex, skipping those whose
+                // shared edge the encoder flagged as a crease.
+                let start = mesh.sequence.value_corners[entry];
+                let mut corner = start;
+                let mut count = 0;
+                let mut swinging_left = true;
+                while corner != INVALID {
+                    if parallelogram(mesh, entry, corner, &values, components, &mut candidates[count]) {
+                        count += 1;
+                        if count == MAX_PARALLELOGRAMS {
+                            break;
+                        }
+                    }
+                    corner = if swinging_left {
+                        mesh.table.swing_left(corner)
+                    } else {
+                        previous(mesh.table.opposite(previous(corner)))
+                    };
+                    if corner == start {
+                        break;
+                    }
+                    if corner == INVALID && swinging_left {
+                        swinging_left = false;
+                        corner = previous(mesh.table.opposite(previous(start)));
+                    }
+                }
+                let mut used = 0;
+                prediction.fill(0);
+                if count > 0 {
+                    let context = count - 1;
+                    for candidate in &candidates[..count] {
+                        let crease = creases[context].get(crease_positions[context]).copied()
+                            .ok_or_else(|| invalid("missing crease flags"))?;
+                        crease_positions[context] += 1;
+                        if !crease {
+                            used += 1;
+                            for (sum, &value) in prediction.iter_mut().zip(candidate) {
+                                *sum = sum.wrapping_add(value);
+                            }
+                        }
+                    }
+                }
+                for sum in &mut prediction {
+                    *sum /= used.max(1);
+                }
+                used > 0
+            }
+        };
+        let (done, rest) = values.split_at_mut(entry * components);
+        let reference = if predicted { &prediction[..] } else { &done[(entry - 1) * components..] };
+        wrap.apply(reference, &corrections[entry * components..][..components], &mut rest[..components]);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::*;
+    use crate::geometry::primitives;
+
+    // Streams written by Google's reference encoder from the primitives below, with a
+    // triangle soup of float positions as the only attribute, unique id 0.
+    const CUBE_SEQUENTIAL: &[u8] = include_bytes!("../res/draco/cube_sequential.drc");
+    const CUBE_SEQUENTIAL_QUANTIZED: &[u8] = include_bytes!("../res/draco/cube_sequential_quantized.drc");
+    const SPHERE_VALENCE: &[u8] = include_bytes!("../res/draco/sphere_valence.drc");
+    const SPHERE_PARALLELOGRAM: &[u8] = include_bytes!("../res/draco/sphere_parallelogram.drc");
+    const TORUS_EDGEBREAKER: &[u8] = include_bytes!("../res/draco/torus_edgebreaker.drc");
+    const OPEN_UNPREDICTED: &[u8] = include_bytes!("../res/draco/open_unpredicted.drc");
+    const PARTS_EDGEBREAKER: &[u8] = include_bytes!("../res/draco/parts_edgebreaker.drc");
+    // With flat normals as well, which split the edgebreaker meshes along seams.
+    const CUBE_NORMALS_AFTER: &[u8] = include_bytes!("../res/draco/cube_normals_after.drc");
+    const SPHERE_NORMALS_VALENCE: &[u8] = include_bytes!("../res/draco/sphere_normals_valence.drc");
+    // Sequential, with the normals as attribute 0 and the positions as attribute 1.
+    const CUBE_NORMALS_BEFORE: &[u8] = include_bytes!("../res/draco/cube_normals_before.drc");
+
+    const FIXTURES: [&[u8]; 10] = [
+        CUBE_SEQUENTIAL,
+        CUBE_SEQUENTIAL_QUANTIZED,
+        SPHERE_VALENCE,
+        SPHERE_PARALLELOGRAM,
+        TORUS_EDGEBREAKER,
+        OPEN_UNPREDICTED,
+        PARTS_EDGEBREAKER,
+        CUBE_NORMALS_AFTER,
+        SPHERE_NORMALS_VALENCE,
+        CUBE_NORMALS_BEFORE,
+    ];
+
+    fn cube() -> Vec<Vec3> {
+        primitives::cuboid(vec3![0.0, 0.0, 0.0], vec3![1.0, 2.0, 3.0])
+    }
+
+    fn sphere() -> Vec<Vec3> {
+        primitives::icosphere(vec3![0.0, 0.0, 0.0], 1.0, 2)
+    }
+
+    /// Asserts `decoded` holds the triangles of `expected`, in any order and starting at any
+    /// corner but with the same winding, each position within `tolerance`.
+    fn assert_triangles(decoded: &[Vec3], expected: &[Vec3], tolerance: f32) {
+        assert_eq!(decoded.len(), expected.len());
+        let close = |a: &[Vec3], b: &[Vec3]| a.iter().zip(b).all(|(a, b)| (*a - *b).length() <= tolerance);
+        let mut unmatched: Vec<&[Vec3]> = expected.chunks(3).collect();
+        for triangle in decoded.chunks(3) {
+            let rotations = [[0, 1, 2], [1, 2, 0], [2, 0, 1]].map(|order| order.map(|i| triangle[i]));
+            let Some(found) = unmatched.iter().position(|candidate| rotations.iter().any(|rotation| close(rotation, candidate))) else {
+                panic!("decoded triangle {triangle:?} is not expected");
+            };
+            unmatched.swap_remove(found);
+        }
+    }
+
+    #[test]
+    fn sequential_floats() {
+        assert_triangles(&decode(CUBE_SEQUENTIAL, 0).unwrap(), &cube(), 0.0);
+    }
+
+    #[test]
+    fn sequential_quantized() {
+        // 11 bits over the cube's longest side of 3.
+        assert_triangles(&decode(CUBE_SEQUENTIAL_QUANTIZED, 0).unwrap(), &cube(), 3.0 / 2047.0);
+    }
+
+    #[test]
+    fn edgebreaker_valence_with_constrained_multi_parallelogram() {
+        assert_triangles(&decode(SPHERE_VALENCE, 0).unwrap(), &sphere(), 2.0 / 16383.0);
+    }
+
+    #[test]
+    fn edgebreaker_with_parallelogram() {
+        assert_triangles(&decode(SPHERE_PARALLELOGRAM, 0).unwrap(), &sphere(), 2.0 / 2047.0);
+    }
+
+    #[test]
+    fn edgebreaker_floats_around_a_handle() {
+        let torus = primitives::torus(vec3![0.0, 0.0, 0.0], 1.0, 0.25, 16, 8);
+        assert_triangles(&decode(TORUS_EDGEBREAKER, 0).unwrap(), &torus, 0.0);
+    }
+
+    #[test]
+    fn edgebreaker_with_a_hole_and_no_prediction() {
+        let open = &primitives::icosphere(vec3![0.0, 0.0, 0.0], 1.0, 1)[12..];
+        assert_triangles(&decode(OPEN_UNPREDICTED, 0).unwrap(), open, 2.0 / 2047.0);
+    }
+
+    #[test]
+    fn edgebreaker_with_separate_parts() {
+        let mut parts = primitives::cuboid(vec3![0.0, 0.0, 0.0], vec3![1.0, 1.0, 1.0]);
+        parts.extend(primitives::icosphere(vec3![3.0, 0.0, 0.0], 1.0, 1));
+        assert_triangles(&decode(PARTS_EDGEBREAKER, 0).unwrap(), &parts, 4.5 / 4095.0);
+    }
+
+    #[test]
+    fn normals_around_the_positions() {
+        assert_triangles(&decode(CUBE_NORMALS_AFTER, 0).unwrap(), &cube(), 3.0 / 2047.0);
+        assert_triangles(&decode(SPHERE_NORMALS_VALENCE, 0).unwrap(), &sphere(), 2.0 / 2047.0);
+        assert_triangles(&decode(CUBE_NORMALS_BEFORE, 1).unwrap(), &cube(), 3.0 / 2047.0);
+    }
+
+    #[test]
+    fn rejects_other_streams() {
+        let with_header = |header: &[u8]| {
+            let mut bytes = CUBE_SEQUENTIAL.to_vec();
+            bytes[..header.len()].copy_from_slice(header);
+            decode(&bytes, 0).unwrap_err().to_string()
+        };
+        assert_eq!(with_header(b"DRACU"), "Draco: not a Draco stream");
+        assert_eq!(with_header(b"DRACO\x02\x01"), "Draco: unsupported version 2.1");
+        assert_eq!(with_header(b"DRACO\x02\x02\x00"), "Draco: not a triangle mesh");
+        assert_eq!(with_header(b"DRACO\x02\x02\x01\x02"), "Draco: unknown connectivity method 2");
+        assert_eq!(decode(CUBE_SEQUENTIAL, 1).unwrap_err().to_string(), "Draco: positions are not in the first attribute decoder");
+    }
+
+    /// Decodes `bytes`, failing the test with `what` if that panics.
+    fn decode_without_panicking(bytes: &[u8], what: &str) -> io::Result<Vec<Vec3>> {
+        panic::catch_unwind(|| decode(bytes, 0)).unwrap_or_else(|_| panic!("decoding {what} panicked"))
+    }
+
+    #[test]
+    fn truncated_streams_fail() {
+        for (fixture, bytes) in FIXTURES.iter().enumerate() {
+            // Cuts into the normals after the positions only lose what isn't decoded.
+            let ends_with_positions = ![CUBE_NORMALS_AFTER, SPHERE_NORMALS_VALENCE].contains(bytes);
+            for len in 0..bytes.len() {
+                let what = format!("fixture {fixture} cut to {len} bytes");
+                let decoded = decode_without_panicking(&bytes[..len], &what);
+                assert!(decoded.is_err() || !ends_with_positions, "{what} decoded");
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_streams_do_not_panic() {
+        for (fixture, bytes) in FIXTURES.iter().enumerate() {
+            for offset in 0..bytes.len() {
+                for value in [0x00, 0x01, 0x7f, 0x80, 0xff, bytes[offset] ^ 0x01, bytes[offset] ^ 0x10] {
+                    let mut corrupt = bytes.to_vec();
+                    corrupt[offset] = value;
+                    let _ = decode_without_panicking(&corrupt, &format!("fixture {fixture} with byte {offset} set to {value:#x}"));
+                }
+            }
+        }
+    }
+}
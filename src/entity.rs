@@ -0,0 +1,271 @@
+//! Scene objects as entities carrying components.
+//!
+//! An entity is only an id; what it is comes from the components attached to it: a name, a
+//! place in the hierarchy, a transform, a mesh to draw, the materials it uses, a light, a
+//! camera or a rigid body. Systems such as animation, physics or scripting work on the components they care
+//! about without knowing what else an entity holds. Geometry and the material palette stay
+//! shared assets of the [`Scene`](crate::Scene), referred to by index.
+
+use crate::{
+    Camera,
+    Light,
+    RigidBody,
+    Visibility,
+};
+
+/// Identifies an entity of a [`World`]. Ids of despawned entities are never valid again, even
+/// once their slot is reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    /// Position of the entity's slot. Entities loaded from a glTF file keep the index of their
+    /// node.
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+/// Draws one of the scene's meshes for the entity.
+///
+/// The renderer draws each mesh once, for the first entity rendering it; a mesh no entity
+/// renders is not drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Renderable {
+    /// Index of the mesh, see [`Scene::meshes`](crate::Scene::meshes).
+    pub mesh: usize,
+    pub visibility: Visibility,
+    /// Render layers the mesh is tagged with; untagged meshes are in the default layer.
+    pub layers: Vec<String>,
+}
+
+impl Renderable {
+    pub fn new(mesh: usize) -> Self {
+        Self {
+            mesh,
+            visibility: Visibility::default(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+/// One kind of component, stored densely in the order it was first attached.
+#[derive(Clone, Debug)]
+pub struct Components<T> {
+    /// Position in `values` of each entity's component, by entity index.
+    slots: Vec<Option<usize>>,
+    entities: Vec<Entity>,
+    values: Vec<T>,
+}
+
+impl<T> Default for Components<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            entities: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T> Components<T> {
+    fn position(&self, entity: Entity) -> Option<usize> {
+        let position = (*self.slots.get(entity.index())?)?;
+        (self.entities[position] == entity).then_some(position)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.position(entity).map(|position| &self.values[position])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.position(entity).map(|position| &mut self.values[position])
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.position(entity).is_some()
+    }
+
+    /// Attaches `value` to `entity`, returning the component it replaces. A replaced component
+    /// keeps its place in the order.
+    pub fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        if let Some(position) = self.position(entity) {
+            return Some(std::mem::replace(&mut self.values[position], value));
+        }
+        if self.slots.len() <= entity.index() {
+            self.slots.resize(entity.index() + 1, None);
+        }
+        self.slots[entity.index()] = Some(self.values.len());
+        self.entities.push(entity);
+        self.values.push(value);
+        None
+    }
+
+    /// Detaches the component of `entity`, keeping the others in order.
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let position = self.position(entity)?;
+        self.slots[entity.index()] = None;
+        self.entities.remove(position);
+        for later in &self.entities[position..] {
+            self.slots[later.index()] = self.slots[later.index()].map(|slot| slot - 1);
+        }
+        Some(self.values.remove(position))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.entities.iter().copied().zip(&self.values)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.entities.iter().copied().zip(&mut self.values)
+    }
+
+    /// The components in order, without their entities.
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// The entities of a scene and their components.
+#[derive(Clone, Debug, Default)]
+pub struct World {
+    /// Current generation of each slot, odd while the slot's entity is alive.
+    generations: Vec<u32>,
+    /// Slots of despawned entities, reused by the next spawns.
+    free: Vec<u32>,
+    pub names: Components<String>,
+    /// The entity above each one in the hierarchy; roots have none.
+    pub parents: Components<Entity>,
+    /// Column-major world transform from the scene file's hierarchy. Meshes are drawn from
+    /// their vertices as loaded, without it.
+    pub transforms: Components<[[f32; 4]; 4]>,
+    pub renderables: Components<Renderable>,
+    /// Indices of the materials the entity's mesh uses, see
+    /// [`Scene::materials`](crate::Scene::materials).
+    pub materials: Components<Vec<usize>>,
+    pub lights: Components<Light>,
+    /// The first camera attached is the one the viewer starts from.
+    pub cameras: Components<Camera>,
+    /// Bodies simulating the entity's mesh, see [`RayTracer::set_physics`](crate::RayTracer::set_physics).
+    pub bodies: Components<RigidBody>,
+}
+
+impl World {
+    pub fn spawn(&mut self) -> Entity {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.generations.len() as u32 - 1
+            }
+        };
+        self.generations[index as usize] += 1;
+        Entity {
+            index,
+            generation: self.generations[index as usize],
+        }
+    }
+
+    /// Removes `entity` and its components. Its children become roots. Returns whether it was
+    /// alive.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        self.names.remove(entity);
+        self.parents.remove(entity);
+        self.transforms.remove(entity);
+        self.renderables.remove(entity);
+        self.materials.remove(entity);
+        self.lights.remove(entity);
+        self.cameras.remove(entity);
+        self.bodies.remove(entity);
+        let children: Vec<Entity> = self.children(entity).collect();
+        for child in children {
+            self.parents.remove(child);
+        }
+        self.generations[entity.index()] += 1;
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.generations.get(entity.index()) == Some(&entity.generation)
+    }
+
+    /// Every live entity, by index.
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.generations.iter()
+            .enumerate()
+            .filter(|(_, generation)| *generation % 2 == 1)
+            .map(|(index, &generation)| Entity { index: index as u32, generation })
+    }
+
+    /// Entities whose parent is `entity`, in the order they were parented.
+    pub fn children(&self, entity: Entity) -> impl Iterator<Item = Entity> + '_ {
+        self.parents.iter().filter(move |(_, parent)| **parent == entity).map(|(child, _)| child)
+    }
+
+    /// Entities called `name`.
+    pub fn find<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Entity> + 'a {
+        self.names.iter().filter(move |(_, other)| *other == name).map(|(entity, _)| entity)
+    }
+
+    /// The first entity rendering mesh `mesh`.
+    pub fn rendering(&self, mesh: usize) -> Option<Entity> {
+        self.renderables.iter().find(|(_, renderable)| renderable.mesh == mesh).map(|(entity, _)| entity)
+    }
+
+    /// Moves the entities of `other` into this world, returning where each of its slots went
+    /// by index. An entity with a live counterpart, by index in `counterparts`, has its
+    /// components put on that entity instead of a new one, replacing those it has.
+    pub(crate) fn append(&mut self, other: World, counterparts: &[Option<Entity>]) -> Vec<Option<Entity>> {
+        let mut moved = vec![None; other.generations.len()];
+        for entity in other.entities() {
+            let counterpart = counterparts.get(entity.index()).copied().flatten().filter(|&counterpart| self.is_alive(counterpart));
+            moved[entity.index()] = Some(counterpart.unwrap_or_else(|| self.spawn()));
+        }
+        let to = |entity: Entity| moved[entity.index()].unwrap();
+        let World { names, parents, transforms, renderables, materials, lights, cameras, bodies, .. } = other;
+        for (entity, name) in names.entities.into_iter().zip(names.values) {
+            self.names.insert(to(entity), name);
+        }
+        for (entity, parent) in parents.entities.into_iter().zip(parents.values) {
+            self.parents.insert(to(entity), to(parent));
+        }
+        for (entity, transform) in transforms.entities.into_iter().zip(transforms.values) {
+            self.transforms.insert(to(entity), transform);
+        }
+        for (entity, renderable) in renderables.entities.into_iter().zip(renderables.values) {
+            self.renderables.insert(to(entity), renderable);
+        }
+        for (entity, used) in materials.entities.into_iter().zip(materials.values) {
+            self.materials.insert(to(entity), used);
+        }
+        for (entity, light) in lights.entities.into_iter().zip(lights.values) {
+            self.lights.insert(to(entity), light);
+        }
+        for (entity, camera) in cameras.entities.into_iter().zip(cameras.values) {
+            self.cameras.insert(to(entity), camera);
+        }
+        for (entity, body) in bodies.entities.into_iter().zip(bodies.values) {
+            self.bodies.insert(to(entity), body);
+        }
+        moved
+    }
+}
@@ -0,0 +1,339 @@
+//! Writer for glTF 2.0, as a `.glb` binary or a `.gltf` file with its buffer in a `.bin` beside it.
+//!
+//! Meshes are written at their finest level of detail with their vertices in world space, so
+//! every node has an identity transform; coarser levels are generated again on load. Cameras
+//! and lights get nodes of their own. Names are kept so the file can be merged as a layer.
+//! Visibility, layers, the atmosphere and material overrides go into `extras` the way the loader
+//! reads them.
+
+use std::{
+    fs,
+    io,
+    path::Path,
+};
+
+use serde_json::{Value, json};
+
+use crate::{
+    Camera,
+    Light,
+    LightKind,
+    Scene,
+    Vec3,
+    camera::{Mat4, Projection},
+    scene::Visibility,
+};
+
+/// Chunk types of a GLB container.
+const GLB_JSON: u32 = 0x4E4F534A;
+const GLB_BIN: u32 = 0x004E4942;
+
+/// glTF enums for float components and vertex buffer views.
+const FLOAT: u32 = 5126;
+const ARRAY_BUFFER: u32 = 34962;
+
+/// Largest field of view a glTF perspective camera can have, in degrees.
+const MAX_YFOV: f32 = 179.0;
+
+/// Writes `scene` to `path`, as GLB when the extension is `glb` and as glTF otherwise.
+pub(crate) fn write(scene: &Scene, path: &Path) -> io::Result<()> {
+    if !scene.splats.is_empty() || scene.volume.is_some() {
+        log::warn!("glTF export skips splats and volumes");
+    }
+    if scene.material_graph.is_some() || scene.custom_shading.is_some() {
+        log::warn!("glTF export skips material graphs and custom shading");
+    }
+
+    let mut buffer = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+    for (mesh, renderable) in scene.meshes.iter().zip(scene.mesh_renderables()) {
+        let lod = &mesh.lods[0];
+        let first = lod.first_vertex as usize;
+        let vertices = &scene.vertices[first..first + lod.num_vertices as usize];
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": vertices.len() * 12,
+            "target": ARRAY_BUFFER,
+        }));
+        buffer.extend_from_slice(bytemuck::cast_slice(vertices));
+        accessors.push(json!({
+            "bufferView": buffer_views.len() - 1,
+            "componentType": FLOAT,
+            "count": vertices.len(),
+            "type": "VEC3",
+            "min": [mesh.min.x, mesh.min.y, mesh.min.z],
+            "max": [mesh.max.x, mesh.max.y, mesh.max.z],
+        }));
+        // The renderer colours every surface with the first material.
+        let mut primitive = json!({ "attributes": { "POSITION": accessors.len() - 1 } });
+        if !scene.materials.is_empty() {
+            primitive["material"] = json!(0);
+        }
+        meshes.push(json!({ "primitives": [primitive] }));
+
+        let mut node = json!({ "mesh": meshes.len() - 1 });
+        if let Some(name) = &mesh.name {
+            node["name"] = json!(name);
+        }
+        let mut extras = serde_json::Map::new();
+        if renderable.visibility != Visibility::default() {
+            extras.insert("visibility".into(), json!(renderable.visibility));
+        }
+        if !renderable.layers.is_empty() {
+            extras.insert("layers".into(), json!(renderable.layers));
+        }
+        if !extras.is_empty() {
+            node["extras"] = Value::Object(extras);
+        }
+        nodes.push(node);
+    }
+
+    let cameras: Vec<Value> = scene.cameras().iter().map(camera).collect();
+    for (index, camera) in scene.cameras().iter().enumerate() {
+        nodes.push(json!({
+            "camera": index,
+            "matrix": flatten(&placement(camera.eye, camera.target - camera.eye, camera.up)),
+        }));
+    }
+    let lights: Vec<Value> = scene.lights().iter().map(light).collect();
+    for (index, light) in scene.lights().iter().enumerate() {
+        nodes.push(json!({
+            "extensions": { "KHR_lights_punctual": { "light": index } },
+            "matrix": flatten(&placement(light.position, light.direction, vec3![0.0, 1.0, 0.0])),
+        }));
+    }
+
+    let materials: Vec<Value> = scene.materials.iter()
+        .enumerate()
+        .map(|(index, material)| {
+            let mut value = json!({ "pbrMetallicRoughness": { "baseColorFactor": material.ambient } });
+            if let Some(Some(name)) = scene.material_names.get(index) {
+                value["name"] = json!(name);
+            }
+            value
+        })
+        .collect();
+
+    let mut scene_extras = serde_json::Map::new();
+    if let Some(atmosphere) = &scene.atmosphere {
+        scene_extras.insert("atmosphere".into(), json!(atmosphere));
+    }
+    if !scene.overrides.is_empty() {
+        scene_extras.insert("overrides".into(), json!(scene.overrides));
+    }
+    let mut gltf_scene = json!({});
+    if !nodes.is_empty() {
+        gltf_scene["nodes"] = json!((0..nodes.len()).collect::<Vec<_>>());
+    }
+    if !scene_extras.is_empty() {
+        gltf_scene["extras"] = Value::Object(scene_extras);
+    }
+
+    let glb = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("glb"));
+    let mut gltf_buffer = json!({ "byteLength": buffer.len() });
+    let bin_path = path.with_extension("bin");
+    if !glb && !buffer.is_empty() {
+        let uri = bin_path.file_name().unwrap_or_default().to_string_lossy();
+        gltf_buffer["uri"] = json!(uri);
+    }
+
+    let mut root = json!({
+        "asset": { "version": "2.0", "generator": "ray-tracer" },
+        "scene": 0,
+        "scenes": [gltf_scene],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "cameras": cameras,
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+        "buffers": if buffer.is_empty() { json!([]) } else { json!([gltf_buffer]) },
+    });
+    if !lights.is_empty() {
+        root["extensionsUsed"] = json!(["KHR_lights_punctual"]);
+        root["extensions"] = json!({ "KHR_lights_punctual": { "lights": lights } });
+    }
+    // glTF forbids empty top-level arrays.
+    if let Value::Object(root) = &mut root {
+        root.retain(|_, value| value.as_array().is_none_or(|array| !array.is_empty()));
+    }
+
+    let json = serde_json::to_vec(&root).map_err(io::Error::other)?;
+    if glb {
+        fs::write(path, glb_container(json, buffer))
+    } else {
+        if !buffer.is_empty() {
+            fs::write(bin_path, &buffer)?;
+        }
+        fs::write(path, json)
+    }
+}
+
+/// Packs the JSON and binary chunks into a GLB file, padding each to four bytes.
+fn glb_container(mut json: Vec<u8>, mut bin: Vec<u8>) -> Vec<u8> {
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+    let mut chunks = vec![(GLB_JSON, json)];
+    if !bin.is_empty() {
+        chunks.push((GLB_BIN, bin));
+    }
+    let length = 12 + chunks.iter().map(|(_, data)| 8 + data.len()).sum::<usize>();
+
+    let mut glb = Vec::with_capacity(length);
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(length as u32).to_le_bytes());
+    for (kind, data) in chunks {
+        glb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&kind.to_le_bytes());
+        glb.extend_from_slice(&data);
+    }
+    glb
+}
+
+/// Transform of an object at `position` looking down its -Z axis along `forward`, +Y up.
+fn placement(position: Vec3, forward: Vec3, up: Vec3) -> Mat4 {
+    let f = forward.normalize();
+    // Objects looking straight along `up` take +Z as up instead.
+    let up = if f.cross(up).length() < 1e-6 { vec3![0.0, 0.0, 1.0] } else { up };
+    let s = f.cross(up).normalize();
+    let u = s.cross(f);
+    [
+        [s.x, s.y, s.z, 0.0],
+        [u.x, u.y, u.z, 0.0],
+        [-f.x, -f.y, -f.z, 0.0],
+        [position.x, position.y, position.z, 1.0],
+    ]
+}
+
+fn flatten(matrix: &Mat4) -> Vec<f32> {
+    matrix.iter().flatten().copied().collect()
+}
+
+/// Fisheye and panoramic cameras have no glTF equivalent and are written as perspective ones.
+fn camera(camera: &Camera) -> Value {
+    match camera.projection {
+        Projection::Orthographic => {
+            let ymag = (camera.target - camera.eye).length() * (camera.fovy.to_radians() / 2.0).tan();
+            json!({
+                "type": "orthographic",
+                "orthographic": { "xmag": ymag, "ymag": ymag, "znear": camera.znear, "zfar": camera.zfar },
+            })
+        }
+        _ => json!({
+            "type": "perspective",
+            "perspective": {
+                "yfov": camera.fovy.min(MAX_YFOV).to_radians(),
+                "znear": camera.znear,
+                "zfar": camera.zfar,
+            },
+        }),
+    }
+}
+
+fn light(light: &Light) -> Value {
+    let mut value = json!({
+        "color": light.color,
+        "intensity": light.intensity,
+    });
+    match light.kind {
+        LightKind::Directional => value["type"] = json!("directional"),
+        LightKind::Point => value["type"] = json!("point"),
+        LightKind::Spot { inner_cone, outer_cone } => {
+            value["type"] = json!("spot");
+            value["spot"] = json!({ "innerConeAngle": inner_cone, "outerConeAngle": outer_cone });
+        }
+    }
+    if let Some(range) = light.range {
+        value["range"] = json!(range);
+    }
+    if let Some(name) = &light.name {
+        value["name"] = json!(name);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::primitives;
+
+    /// Exports `scene` to a temporary file with `extension` and imports it back with `gltf`.
+    fn round_trip(scene: &Scene, extension: &str) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let path = std::env::temp_dir().join(format!("export-{}-{extension}.{extension}", std::process::id()));
+        write(scene, &path).unwrap();
+        let imported = if extension == "glb" {
+            gltf::import_slice(fs::read(&path).unwrap())
+        } else {
+            gltf::import(&path)
+        };
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("bin")).ok();
+        let (document, buffers, _) = imported.unwrap();
+        (document, buffers)
+    }
+
+    fn scene() -> Scene {
+        let mut scene = Scene::default();
+        let sphere = scene.add_mesh(vec![primitives::icosphere(Vec3::default(), 1.0, 1)]).unwrap();
+        scene.set_name(sphere, "sphere");
+        scene.add_mesh(vec![primitives::cuboid(vec3![3.0, 0.0, 0.0], vec3![1.0, 2.0, 3.0])]);
+        scene
+    }
+
+    fn check(scene: &Scene, document: &gltf::Document, buffers: &[gltf::buffer::Data]) {
+        assert_eq!(document.meshes().len(), scene.meshes.len());
+        for (index, node) in document.nodes().filter(|node| node.mesh().is_some()).enumerate() {
+            assert_eq!(node.name(), scene.meshes[index].name.as_deref());
+            let primitive = node.mesh().unwrap().primitives().next().unwrap();
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<Vec3> = reader.read_positions().unwrap().map(Vec3::from).collect();
+            assert_eq!(positions.len() % 3, 0);
+            assert_eq!(positions.len() / 3, scene.mesh_vertices(index).len() / 3, "triangles of mesh {index}");
+            assert_eq!(positions, scene.mesh_vertices(index), "mesh {index}");
+        }
+    }
+
+    #[test]
+    fn glb_round_trips() {
+        let scene = scene();
+        let (document, buffers) = round_trip(&scene, "glb");
+        check(&scene, &document, &buffers);
+    }
+
+    #[test]
+    fn gltf_round_trips() {
+        let scene = scene();
+        let (document, buffers) = round_trip(&scene, "gltf");
+        check(&scene, &document, &buffers);
+    }
+
+    #[test]
+    fn exported_scenes_load_back() {
+        let scene = scene();
+        let path = std::env::temp_dir().join(format!("export-{}-load.glb", std::process::id()));
+        write(&scene, &path).unwrap();
+        let loaded = Scene::from_gltf_bytes(&fs::read(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.meshes.len(), scene.meshes.len());
+        for index in 0..scene.meshes.len() {
+            assert_eq!(loaded.meshes[index].name, scene.meshes[index].name);
+            let mut expected = scene.mesh_vertices(index).to_vec();
+            let mut actual = loaded.mesh_vertices(index).to_vec();
+            // Loading builds meshlets, which reorder the triangles.
+            for vertices in [&mut expected, &mut actual] {
+                let mut triangles: Vec<[[u32; 3]; 3]> = vertices.chunks_exact(3)
+                    .map(|t| [0, 1, 2].map(|i| [t[i].x, t[i].y, t[i].z].map(f32::to_bits)))
+                    .collect();
+                triangles.sort();
+                *vertices = triangles.into_iter().flatten().map(|[x, y, z]| vec3![f32::from_bits(x), f32::from_bits(y), f32::from_bits(z)]).collect();
+            }
+            assert_eq!(actual, expected, "mesh {index}");
+        }
+    }
+}
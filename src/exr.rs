@@ -0,0 +1,199 @@
+//! Writer for uncompressed OpenEXR scanline images, enough to hand renders with alpha to a
+//! compositor without going through 8-bit straight alpha.
+
+use std::io::{self, Write};
+
+use image::RgbaImage;
+
+/// Pixel type of 32-bit float channels.
+const FLOAT: i32 = 2;
+
+/// Writes `image`, sRGB with premultiplied alpha, as linear premultiplied RGBA floats.
+pub(crate) fn write(image: &RgbaImage, out: &mut impl Write) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let mut header = vec![];
+    header.extend([0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+    // Channels are stored in alphabetical order.
+    let mut channels = vec![];
+    for name in ["A", "B", "G", "R"] {
+        channels.extend(name.bytes());
+        channels.push(0);
+        channels.extend(FLOAT.to_le_bytes());
+        channels.extend([0; 4]);
+        channels.extend(1i32.to_le_bytes());
+        channels.extend(1i32.to_le_bytes());
+    }
+    channels.push(0);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|v| v.to_le_bytes()).collect();
+    let attributes: [(&str, &str, &[u8]); 8] = [
+        ("channels", "chlist", &channels),
+        ("compression", "compression", &[0]),
+        ("dataWindow", "box2i", &window),
+        ("displayWindow", "box2i", &window),
+        ("lineOrder", "lineOrder", &[0]),
+        ("pixelAspectRatio", "float", &1.0f32.to_le_bytes()),
+        ("screenWindowCenter", "v2f", &[0; 8]),
+        ("screenWindowWidth", "float", &1.0f32.to_le_bytes()),
+    ];
+    for (name, kind, value) in attributes {
+        header.extend(name.bytes());
+        header.push(0);
+        header.extend(kind.bytes());
+        header.push(0);
+        header.extend((value.len() as i32).to_le_bytes());
+        header.extend(value);
+    }
+    header.push(0);
+
+    // One line per chunk: its y, its size, then each channel's row.
+    let row_bytes = width as usize * 4 * 4;
+    let chunk_bytes = (8 + row_bytes) as u64;
+    let first_chunk = (header.len() + height as usize * 8) as u64;
+    for y in 0..height as u64 {
+        header.extend((first_chunk + y * chunk_bytes).to_le_bytes());
+    }
+    out.write_all(&header)?;
+
+    let mut chunk = Vec::with_capacity(8 + row_bytes);
+    for (y, row) in image.rows().enumerate() {
+        chunk.clear();
+        chunk.extend((y as i32).to_le_bytes());
+        chunk.extend((row_bytes as i32).to_le_bytes());
+        let pixels: Vec<[f32; 4]> = row.map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            [a as f32 / 255.0, srgb_to_linear(b), srgb_to_linear(g), srgb_to_linear(r)]
+        }).collect();
+        for channel in 0..4 {
+            chunk.extend(pixels.iter().flat_map(|pixel| pixel[channel].to_le_bytes()));
+        }
+        out.write_all(&chunk)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+pub(crate) fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let encoded = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (encoded * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the attributes of the header of `exr` and returns them with the offset past it.
+    fn attributes(exr: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+        let mut at = 8;
+        let string = |at: &mut usize| {
+            let end = *at + exr[*at..].iter().position(|&b| b == 0).unwrap();
+            let text = String::from_utf8(exr[*at..end].to_vec()).unwrap();
+            *at = end + 1;
+            text
+        };
+        let mut attributes = vec![];
+        loop {
+            let name = string(&mut at);
+            if name.is_empty() {
+                return (attributes, at);
+            }
+            let kind = string(&mut at);
+            let size = i32::from_le_bytes(exr[at..at + 4].try_into().unwrap()) as usize;
+            attributes.push((name, kind, exr[at + 4..at + 4 + size].to_vec()));
+            at += 4 + size;
+        }
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    fn i32_at(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn f32_at(bytes: &[u8], at: usize) -> f32 {
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn image() -> RgbaImage {
+        RgbaImage::from_raw(2, 2, vec![
+            255, 0, 0, 255,     0, 255, 0, 255,
+            0, 0, 255, 255,     0, 0, 0, 0,
+        ]).unwrap()
+    }
+
+    #[test]
+    fn header_describes_uncompressed_float_rgba() {
+        let mut exr = vec![];
+        write(&image(), &mut exr).unwrap();
+        assert_eq!(exr[..4], [0x76, 0x2f, 0x31, 0x01]);
+        // Version 2, single-part scanline.
+        assert_eq!(exr[4..8], [2, 0, 0, 0]);
+
+        let (attributes, _) = attributes(&exr);
+        let names: Vec<&str> = attributes.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(names, [
+            "channels", "compression", "dataWindow", "displayWindow", "lineOrder", "pixelAspectRatio",
+            "screenWindowCenter", "screenWindowWidth",
+        ]);
+        let channels = &attributes[0];
+        assert_eq!(channels.1, "chlist");
+        // Each channel is its name, then pixel type, linearity, reserved bytes and sampling.
+        let mut expected = vec![];
+        for name in [b'A', b'B', b'G', b'R'] {
+            expected.extend([name, 0]);
+            expected.extend(FLOAT.to_le_bytes());
+            expected.extend([0, 0, 0, 0]);
+            expected.extend([1, 0, 0, 0, 1, 0, 0, 0]);
+        }
+        expected.push(0);
+        assert_eq!(channels.2, expected);
+        assert_eq!(attributes[1].2, [0]);
+        let window: Vec<i32> = (0..4).map(|i| i32_at(&attributes[2].2, i * 4)).collect();
+        assert_eq!(window, [0, 0, 1, 1]);
+        assert_eq!(attributes[3].2, attributes[2].2);
+    }
+
+    #[test]
+    fn line_offsets_point_at_each_line() {
+        let mut exr = vec![];
+        write(&image(), &mut exr).unwrap();
+        let (_, table) = attributes(&exr);
+        let row_bytes: usize = 2 * 4 * 4;
+        let offsets = [u64_at(&exr, table), u64_at(&exr, table + 8)];
+        assert_eq!(offsets[0], (table + 16) as u64);
+        assert_eq!(offsets[1], offsets[0] + 8 + row_bytes as u64);
+        assert_eq!(exr.len() as u64, offsets[1] + 8 + row_bytes as u64);
+
+        for (y, &offset) in offsets.iter().enumerate() {
+            let offset = offset as usize;
+            assert_eq!(i32_at(&exr, offset), y as i32);
+            assert_eq!(i32_at(&exr, offset + 4), row_bytes as i32);
+        }
+        // Each line holds the A, B, G and R rows in turn, linear and premultiplied.
+        let line = |y: usize| -> Vec<f32> {
+            let start = offsets[y] as usize + 8;
+            (0..8).map(|i| f32_at(&exr, start + i * 4)).collect()
+        };
+        assert_eq!(line(0), [1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(line(1), [1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn srgb_round_trips() {
+        for value in 0..=255 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        }
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        assert!((srgb_to_linear(188) - 0.5).abs() < 0.005);
+        assert_eq!(linear_to_srgb(2.0), 255);
+        assert_eq!(linear_to_srgb(-1.0), 0);
+    }
+}
@@ -0,0 +1,265 @@
+//! C API for embedding the renderer in applications not written in Rust, declared in
+//! `include/ray_tracer.h`.
+//!
+//! A renderer is created with [`rt_renderer_create`], given a scene with
+//! [`rt_renderer_load_scene`] and draws into a buffer the caller owns with
+//! [`rt_renderer_render`]. Functions that can fail return zero on success and a negative
+//! value otherwise, with the reason from [`rt_last_error`]. Panics are caught at the boundary
+//! and reported the same way.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    slice,
+};
+
+use wgpu::{
+    Extent3d,
+    Texture,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureUsages,
+    TextureViewDescriptor,
+};
+
+use crate::{
+    Camera,
+    Scene,
+    Settings,
+    headless,
+    renderer::{self, Renderer},
+};
+
+thread_local! {
+    /// Why the last failing call on this thread failed.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A camera as C sees it, see [`Camera`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RtCamera {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    /// Vertical field of view in degrees.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl From<Camera> for RtCamera {
+    fn from(camera: Camera) -> Self {
+        let array = |v: crate::Vec3| [v.x, v.y, v.z];
+        Self {
+            eye: array(camera.eye),
+            target: array(camera.target),
+            up: array(camera.up),
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar,
+        }
+    }
+}
+
+impl RtCamera {
+    /// `camera` moved and reshaped to this one, keeping its projection.
+    fn apply(&self, camera: Camera) -> Camera {
+        Camera {
+            eye: self.eye.into(),
+            target: self.target.into(),
+            up: self.up.into(),
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar,
+            ..camera
+        }
+    }
+}
+
+/// A renderer owned by C code. The GPU device is only opened once a scene is loaded.
+pub struct RtRenderer {
+    renderer: Option<Renderer>,
+    /// Last target drawn to, reused while the size stays the same.
+    target: Option<Texture>,
+    /// Camera set before a scene was loaded, used instead of the scene's own.
+    camera: Option<Camera>,
+}
+
+/// Records `message` for [`rt_last_error`] and returns -1.
+fn fail(message: impl Into<String>) -> c_int {
+    let message = CString::new(message.into().replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+    -1
+}
+
+/// Runs `call`, returning 0 if it succeeds and failing with its error or panic otherwise.
+fn guard(call: impl FnOnce() -> Result<(), String>) -> c_int {
+    let result = panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|panic| {
+        let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "the renderer panicked".to_owned());
+        Err(message)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(message) => fail(message),
+    }
+}
+
+/// Creates a renderer without a scene. Free it with [`rt_renderer_destroy`].
+#[unsafe(no_mangle)]
+pub extern "C" fn rt_renderer_create() -> *mut RtRenderer {
+    Box::into_raw(Box::new(RtRenderer {
+        renderer: None,
+        target: None,
+        camera: None,
+    }))
+}
+
+/// Frees a renderer from [`rt_renderer_create`]. Null is ignored.
+///
+/// # Safety
+///
+/// `renderer` must be null or a renderer from [`rt_renderer_create`] not yet destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rt_renderer_destroy(renderer: *mut RtRenderer) {
+    if !renderer.is_null() {
+        drop(unsafe { Box::from_raw(renderer) });
+    }
+}
+
+/// Loads the scene file at `path`, replacing any scene loaded before. The camera moves to
+/// the scene's own unless one was set before the first scene was loaded.
+///
+/// # Safety
+///
+/// `renderer` must come from [`rt_renderer_create`] and `path` must be a NUL-terminated
+/// UTF-8 string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rt_renderer_load_scene(renderer: *mut RtRenderer, path: *const c_char) -> c_int {
+    let Some(handle) = (unsafe { renderer.as_mut() }) else {
+        return fail("renderer is null");
+    };
+    if path.is_null() {
+        return fail("path is null");
+    }
+    let path = unsafe { CStr::from_ptr(path) };
+    guard(|| {
+        let path = path.to_str().map_err(|_| "path is not UTF-8".to_owned())?;
+        let scene = Scene::load(path).map_err(|err| format!("failed to load {path}: {err}"))?;
+        let camera = handle.camera.take().unwrap_or_else(|| scene.default_camera());
+        match &mut handle.renderer {
+            Some(renderer) => renderer.reload(scene),
+            None => {
+                let (device, queue, max_buffer_size) = renderer::headless_device();
+                let format = TextureFormat::Rgba8UnormSrgb;
+                handle.renderer = Some(Renderer::new(device, queue, format, max_buffer_size, scene, Settings::default()));
+            }
+        }
+        handle.renderer.as_mut().unwrap().camera = camera;
+        Ok(())
+    })
+}
+
+/// Moves the camera. Its projection is kept.
+///
+/// # Safety
+///
+/// `renderer` must come from [`rt_renderer_create`] and `camera` must point to an
+/// [`RtCamera`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rt_renderer_set_camera(renderer: *mut RtRenderer, camera: *const RtCamera) -> c_int {
+    let (Some(handle), Some(camera)) = (unsafe { renderer.as_mut() }, unsafe { camera.as_ref() }) else {
+        return fail("renderer or camera is null");
+    };
+    match &mut handle.renderer {
+        Some(renderer) => renderer.camera = camera.apply(renderer.camera),
+        None => handle.camera = Some(camera.apply(handle.camera.unwrap_or_default())),
+    }
+    0
+}
+
+/// Writes the current camera to `camera`.
+///
+/// # Safety
+///
+/// `renderer` must come from [`rt_renderer_create`] and `camera` must point to writable
+/// memory for an [`RtCamera`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rt_renderer_get_camera(renderer: *const RtRenderer, camera: *mut RtCamera) -> c_int {
+    let (Some(handle), false) = (unsafe { renderer.as_ref() }, camera.is_null()) else {
+        return fail("renderer or camera is null");
+    };
+    let current = match &handle.renderer {
+        Some(renderer) => renderer.camera,
+        None => handle.camera.unwrap_or_default(),
+    };
+    unsafe { camera.write(current.into()) };
+    0
+}
+
+/// Draws a `width` by `height` frame of the loaded scene into `pixels`, as rows of sRGB RGBA
+/// bytes with premultiplied alpha, `stride` bytes apart from the top row down.
+///
+/// # Safety
+///
+/// `renderer` must come from [`rt_renderer_create`] and `pixels` must point to `length`
+/// writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rt_renderer_render(
+    renderer: *mut RtRenderer,
+    width: u32,
+    height: u32,
+    pixels: *mut u8,
+    stride: usize,
+    length: usize,
+) -> c_int {
+    let Some(handle) = (unsafe { renderer.as_mut() }) else {
+        return fail("renderer is null");
+    };
+    let row = width as usize * 4;
+    let needed = if height == 0 { 0 } else { stride * (height as usize - 1) + row };
+    if pixels.is_null() || width == 0 || height == 0 || stride < row || length < needed {
+        return fail(format!("a {width}x{height} frame needs {needed} bytes with rows of at least {row}"));
+    }
+    let pixels = unsafe { slice::from_raw_parts_mut(pixels, needed) };
+    guard(|| {
+        let RtRenderer { renderer: Some(renderer), target, .. } = handle else {
+            return Err("no scene is loaded".to_owned());
+        };
+        if target.as_ref().is_none_or(|target| (target.width(), target.height()) != (width, height)) {
+            *target = Some(renderer.device.create_texture(&TextureDescriptor {
+                label: Some("FFI target"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+                view_formats: &[],
+            }));
+        }
+        let target = target.as_ref().unwrap();
+        renderer.render(&target.create_view(&TextureViewDescriptor::default()), width, height, None);
+        let image = headless::read_back(renderer, target);
+        for (source, destination) in image.chunks_exact(row).zip(pixels.chunks_mut(stride)) {
+            destination[..row].copy_from_slice(source);
+        }
+        Ok(())
+    })
+}
+
+/// Why the last failing call on this thread failed, or null if none has. The string stays
+/// valid until the next failing call on the thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn rt_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
@@ -0,0 +1,89 @@
+//! Flies the viewer's camera with a gamepad: the left stick moves, the right stick looks around,
+//! the right trigger speeds up and the left slows down.
+//!
+//! Pads are read with `gilrs`, which maps the sticks and triggers of common controllers the
+//! same way on every platform it supports, and picks up pads plugged in while the viewer runs.
+
+use std::time::{Duration, Instant};
+
+use gilrs::{Axis, Button, EventType, Gamepad, Gilrs};
+
+use crate::Camera;
+
+/// Longest step a frame moves the camera by, so a stalled frame doesn't jump it.
+const MAX_STEP: Duration = Duration::from_millis(100);
+
+/// Fraction of a stick's travel ignored around its centre, where worn sticks rest.
+const DEAD_ZONE: f32 = 0.15;
+
+/// Degrees per second the view turns with the right stick fully over.
+const LOOK_SPEED: f32 = 120.0;
+
+/// Scene diagonals per second the camera moves with the left stick fully over.
+const MOVE_SPEED: f32 = 0.5;
+
+/// Pads being read.
+pub(crate) struct Gamepads {
+    gilrs: Gilrs,
+    last_step: Instant,
+}
+
+impl Gamepads {
+    /// Starts reading pads, or returns `None` where `gilrs` can't.
+    pub(crate) fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(Self {
+                gilrs,
+                last_step: Instant::now(),
+            }),
+            Err(err) => {
+                log::warn!("Can't read gamepads: {err}");
+                None
+            }
+        }
+    }
+
+    /// Moves `camera` by the pads' sticks for the time since the last call, in a scene
+    /// `size` across. Returns `None` while the sticks are at rest.
+    pub(crate) fn fly(&mut self, camera: Camera, size: f32) -> Option<Camera> {
+        let now = Instant::now();
+        let step = (now - self.last_step).min(MAX_STEP).as_secs_f32();
+        self.last_step = now;
+        // Events update the state the pads are read from below.
+        while let Some(event) = self.gilrs.next_event() {
+            let name = || self.gilrs.gamepad(event.id).name().to_owned();
+            match event.event {
+                EventType::Connected => log::info!("Reading gamepad {}", name()),
+                EventType::Disconnected => log::info!("Gamepad {} was unplugged", name()),
+                _ => {}
+            }
+        }
+
+        // The pad pushed furthest steers, so one left lying around doesn't hold the camera.
+        let (_, pad) = self.gilrs.gamepads().max_by(|(_, a), (_, b)| deflection(a).total_cmp(&deflection(b)))?;
+        let stick = |axis: Axis| {
+            let value = pad.value(axis);
+            let travel = ((value.abs() - DEAD_ZONE) / (1.0 - DEAD_ZONE)).max(0.0);
+            travel.copysign(value)
+        };
+        let trigger = |button: Button| pad.button_data(button).map_or(0.0, |data| data.value());
+        let (look_x, look_y) = (stick(Axis::RightStickX), stick(Axis::RightStickY));
+        let (move_x, move_y) = (stick(Axis::LeftStickX), stick(Axis::LeftStickY));
+        if [look_x, look_y, move_x, move_y].iter().all(|&value| value == 0.0) {
+            return None;
+        }
+        let speed = (1.0 + 3.0 * trigger(Button::RightTrigger2)) * (1.0 - 0.75 * trigger(Button::LeftTrigger2));
+        let look = LOOK_SPEED * step;
+        let distance = MOVE_SPEED * size * speed * step;
+        let camera = camera.orbit(camera.eye, -look_x * look).tilt(camera.eye, -look_y * look);
+        Some(camera.walk(move_y * distance, move_x * distance))
+    }
+}
+
+/// How far `pad`'s sticks are pushed, the furthest of their axes.
+fn deflection(pad: &Gamepad) -> f32 {
+    [Axis::LeftStickX, Axis::LeftStickY, Axis::RightStickX, Axis::RightStickY]
+        .map(|axis| pad.value(axis).abs())
+        .into_iter()
+        .fold(0.0, f32::max)
+}
@@ -0,0 +1,195 @@
+//! Repair of dirty triangle lists: welding, degenerate removal and consistent winding.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::Vec3;
+
+/// Welds vertices of `triangles` closer than `epsilon`, drops triangles that collapse or have
+/// no area, and flips triangles so that neighbours across every edge wind the same way.
+///
+/// Each connected piece keeps the winding most of its triangles had, except closed pieces,
+/// which are turned to face outward. Edges shared by more than two triangles don't carry
+/// winding between them. An `epsilon` of zero only welds identical positions.
+pub fn clean(triangles: &[Vec3], epsilon: f32) -> Vec<Vec3> {
+    let (positions, indices) = weld(triangles, epsilon);
+    let mut faces: Vec<[usize; 3]> = indices.chunks_exact(3)
+        .map(|corners| [corners[0], corners[1], corners[2]])
+        .filter(|&[a, b, c]| {
+            let (e1, e2) = (positions[b] - positions[a], positions[c] - positions[a]);
+            let longest = e1.dot(e1).max(e2.dot(e2)).max((e2 - e1).dot(e2 - e1));
+            a != b && b != c && c != a && e1.cross(e2).length() > f32::EPSILON * longest
+        })
+        .collect();
+    orient(&positions, &mut faces);
+    faces.iter()
+        .flat_map(|face| face.map(|vertex| positions[vertex]))
+        .collect()
+}
+
+/// Merges corners into shared vertices, each placed where the first of its corners was.
+/// Returns the vertex positions and the vertex of every corner.
+fn weld(triangles: &[Vec3], epsilon: f32) -> (Vec<Vec3>, Vec<usize>) {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut indices = Vec::with_capacity(triangles.len());
+    if epsilon <= 0.0 {
+        let mut ids = HashMap::new();
+        for v in triangles {
+            let id = *ids.entry([v.x.to_bits(), v.y.to_bits(), v.z.to_bits()]).or_insert_with(|| {
+                positions.push(*v);
+                positions.len() - 1
+            });
+            indices.push(id);
+        }
+        return (positions, indices);
+    }
+
+    let cell = |v: Vec3| [v.x, v.y, v.z].map(|axis| (axis / epsilon).floor() as i64);
+    let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    for &v in triangles {
+        let [x, y, z] = cell(v);
+        let near = (-1..=1)
+            .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [x + dx, y + dy, z + dz])))
+            .filter_map(|key| grid.get(&key))
+            .flatten()
+            .copied()
+            .find(|&id| (positions[id] - v).length() <= epsilon);
+        let id = near.unwrap_or_else(|| {
+            positions.push(v);
+            grid.entry([x, y, z]).or_default().push(positions.len() - 1);
+            positions.len() - 1
+        });
+        indices.push(id);
+    }
+    (positions, indices)
+}
+
+/// Flips faces to agree with their neighbours, one connected piece at a time.
+fn orient(positions: &[Vec3], faces: &mut [[usize; 3]]) {
+    let edges = |[a, b, c]: [usize; 3]| [(a, b), (b, c), (c, a)];
+    let mut shared: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (face, &corners) in faces.iter().enumerate() {
+        for (u, v) in edges(corners) {
+            shared.entry((u.min(v), u.max(v))).or_default().push(face);
+        }
+    }
+
+    let mut visited = vec![false; faces.len()];
+    for seed in 0..faces.len() {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        let mut piece = vec![seed];
+        let mut flipped = vec![];
+        let mut closed = true;
+        let mut queue = VecDeque::from([seed]);
+        while let Some(face) = queue.pop_front() {
+            for (u, v) in edges(faces[face]) {
+                let neighbours = &shared[&(u.min(v), u.max(v))];
+                if neighbours.len() != 2 {
+                    closed = false;
+                    continue;
+                }
+                let other = neighbours[0] + neighbours[1] - face;
+                if visited[other] {
+                    continue;
+                }
+                visited[other] = true;
+                // Neighbours agree when they run along the shared edge in opposite directions.
+                if edges(faces[other]).contains(&(u, v)) {
+                    faces[other].swap(1, 2);
+                    flipped.push(other);
+                }
+                piece.push(other);
+                queue.push_back(other);
+            }
+        }
+
+        let flip_all = if closed {
+            let volume: f32 = piece.iter()
+                .map(|&face| {
+                    let [a, b, c] = faces[face].map(|vertex| positions[vertex]);
+                    a.dot(b.cross(c))
+                })
+                .sum();
+            volume < 0.0
+        } else {
+            flipped.len() * 2 > piece.len()
+        };
+        if flip_all {
+            for &face in &piece {
+                faces[face].swap(1, 2);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::primitives::{cuboid, plane};
+
+    fn flip(triangle: &mut [Vec3]) {
+        triangle.swap(1, 2);
+    }
+
+    fn volume(triangles: &[Vec3]) -> f32 {
+        triangles.chunks_exact(3).map(|t| t[0].dot(t[1].cross(t[2])) / 6.0).sum()
+    }
+
+    #[test]
+    fn zero_epsilon_welds_identical_positions_only() {
+        let (positions, indices) = weld(&[vec3![0.0, 0.0, 0.0], vec3![1e-7, 0.0, 0.0], vec3![0.0, 0.0, 0.0]], 0.0);
+        assert_eq!(positions.len(), 2);
+        assert_eq!(indices, [0, 1, 0]);
+    }
+
+    #[test]
+    fn welds_within_epsilon_across_cells() {
+        // Either side of a grid cell boundary, and the first corner's position is kept.
+        let corners = [vec3![0.0099, 0.0, 0.0], vec3![0.0101, 0.0, 0.0], vec3![0.05, 0.0, 0.0], vec3![0.0, 0.0, 0.0]];
+        let (positions, indices) = weld(&corners, 0.01);
+        assert_eq!(indices, [0, 0, 1, 0]);
+        assert_eq!(positions, [corners[0], corners[2]]);
+    }
+
+    #[test]
+    fn degenerate_triangles_are_dropped() {
+        let mut triangles = plane(Vec3::default(), 1.0, 1.0);
+        triangles.extend([
+            // Collapses once welded.
+            vec3![0.0, 0.0, 0.0], vec3![0.001, 0.0, 0.0], vec3![0.0, 0.0, 1.0],
+            // No area.
+            vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0], vec3![2.0, 0.0, 0.0],
+        ]);
+        assert_eq!(clean(&triangles, 0.01).len(), 6);
+        assert_eq!(clean(&triangles, 0.0).len(), 9);
+    }
+
+    #[test]
+    fn closed_meshes_face_outward() {
+        let cube = cuboid(Vec3::default(), vec3![1.0, 1.0, 1.0]);
+        let mut inside_out = cube.clone();
+        inside_out.chunks_exact_mut(3).for_each(flip);
+        let mut one_flipped = cube.clone();
+        flip(&mut one_flipped[3..6]);
+        for triangles in [cube, inside_out, one_flipped] {
+            let cleaned = clean(&triangles, 0.0);
+            assert_eq!(cleaned.len(), 36);
+            assert!((volume(&cleaned) - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn open_pieces_keep_their_majority_winding() {
+        let mut triangles = plane(Vec3::default(), 1.0, 1.0);
+        triangles.extend(plane(vec3![1.0, 0.0, 0.0], 1.0, 1.0));
+        let expected = triangles.clone();
+        flip(&mut triangles[6..9]);
+        assert_eq!(clean(&triangles, 0.0), expected);
+        // Mostly flipped pieces stay flipped.
+        triangles.chunks_exact_mut(3).for_each(flip);
+        let cleaned = clean(&triangles, 0.0);
+        assert!(cleaned.chunks_exact(3).all(|t| (t[1] - t[0]).cross(t[2] - t[0]).y < 0.0));
+    }
+}
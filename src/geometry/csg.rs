@@ -0,0 +1,336 @@
+//! Boolean operations on closed triangle meshes using BSP trees (after Evan Wallace's csg.js).
+//!
+//! Inputs must be watertight and wound counter-clockwise from outside. The result is a triangle
+//! list in the same convention. Trees are built recursively, so this is meant for modelled shapes
+//! of up to a few thousand triangles rather than scanned meshes.
+
+use serde::Deserialize;
+
+use crate::Vec3;
+
+/// Distance within which a point counts as lying on a splitting plane.
+const EPSILON: f32 = 1e-5;
+
+const COPLANAR: u8 = 0;
+const FRONT: u8 = 1;
+const BACK: u8 = 2;
+const SPANNING: u8 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// Combines two closed meshes.
+pub fn apply(operation: Operation, a: &[Vec3], b: &[Vec3]) -> Vec<Vec3> {
+    let mut a = Node::new(polygons(a));
+    let mut b = Node::new(polygons(b));
+    match operation {
+        Operation::Union => {
+            a.clip_to(&b);
+            b.clip_to(&a);
+            b.invert();
+            b.clip_to(&a);
+            b.invert();
+            a.build(b.all_polygons());
+        }
+        Operation::Intersection => {
+            a.invert();
+            b.clip_to(&a);
+            b.invert();
+            a.clip_to(&b);
+            b.clip_to(&a);
+            a.build(b.all_polygons());
+            a.invert();
+        }
+        Operation::Difference => {
+            a.invert();
+            a.clip_to(&b);
+            b.clip_to(&a);
+            b.invert();
+            b.clip_to(&a);
+            b.invert();
+            a.build(b.all_polygons());
+            a.invert();
+        }
+    }
+    triangulate(a.all_polygons())
+}
+
+pub fn union(a: &[Vec3], b: &[Vec3]) -> Vec<Vec3> {
+    apply(Operation::Union, a, b)
+}
+
+pub fn intersection(a: &[Vec3], b: &[Vec3]) -> Vec<Vec3> {
+    apply(Operation::Intersection, a, b)
+}
+
+pub fn difference(a: &[Vec3], b: &[Vec3]) -> Vec<Vec3> {
+    apply(Operation::Difference, a, b)
+}
+
+#[derive(Copy, Clone)]
+struct Plane {
+    normal: Vec3,
+    w: f32,
+}
+
+impl Plane {
+    fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Option<Self> {
+        let normal = (b - a).cross(c - a);
+        if normal.length() == 0.0 {
+            return None;
+        }
+        let normal = normal.normalize();
+        Some(Self {
+            normal,
+            w: normal.dot(a),
+        })
+    }
+
+    fn flip(&mut self) {
+        self.normal = self.normal * -1.0;
+        self.w = -self.w;
+    }
+
+    /// Sorts `polygon` into the lists by which side of the plane it lies on, splitting it where
+    /// it spans the plane.
+    fn split(
+        &self,
+        polygon: Polygon,
+        coplanar_front: &mut Vec<Polygon>,
+        coplanar_back: &mut Vec<Polygon>,
+        front: &mut Vec<Polygon>,
+        back: &mut Vec<Polygon>,
+    ) {
+        let sides: Vec<u8> = polygon.vertices.iter()
+            .map(|vertex| {
+                let distance = self.normal.dot(*vertex) - self.w;
+                if distance < -EPSILON {
+                    BACK
+                } else if distance > EPSILON {
+                    FRONT
+                } else {
+                    COPLANAR
+                }
+            })
+            .collect();
+        match sides.iter().fold(COPLANAR, |side, vertex| side | vertex) {
+            COPLANAR if self.normal.dot(polygon.plane.normal) > 0.0 => coplanar_front.push(polygon),
+            COPLANAR => coplanar_back.push(polygon),
+            FRONT => front.push(polygon),
+            BACK => back.push(polygon),
+            _ => {
+                let (mut f, mut b) = (Vec::new(), Vec::new());
+                let count = polygon.vertices.len();
+                for i in 0..count {
+                    let j = (i + 1) % count;
+                    let (vi, vj) = (polygon.vertices[i], polygon.vertices[j]);
+                    if sides[i] != BACK {
+                        f.push(vi);
+                    }
+                    if sides[i] != FRONT {
+                        b.push(vi);
+                    }
+                    if sides[i] | sides[j] == SPANNING {
+                        let t = (self.w - self.normal.dot(vi)) / self.normal.dot(vj - vi);
+                        let v = vi + (vj - vi) * t;
+                        f.push(v);
+                        b.push(v);
+                    }
+                }
+                if f.len() >= 3 {
+                    front.push(Polygon { vertices: f, plane: polygon.plane });
+                }
+                if b.len() >= 3 {
+                    back.push(Polygon { vertices: b, plane: polygon.plane });
+                }
+            }
+        }
+    }
+}
+
+/// A convex planar polygon.
+#[derive(Clone)]
+struct Polygon {
+    vertices: Vec<Vec3>,
+    plane: Plane,
+}
+
+impl Polygon {
+    fn flip(&mut self) {
+        self.vertices.reverse();
+        self.plane.flip();
+    }
+}
+
+/// A BSP tree node. Polygons in front of `plane` are outside the solid, those behind are inside.
+#[derive(Default)]
+struct Node {
+    plane: Option<Plane>,
+    front: Option<Box<Node>>,
+    back: Option<Box<Node>>,
+    polygons: Vec<Polygon>,
+}
+
+impl Node {
+    fn new(polygons: Vec<Polygon>) -> Self {
+        let mut node = Self::default();
+        node.build(polygons);
+        node
+    }
+
+    /// Swaps the solid's inside and outside.
+    fn invert(&mut self) {
+        for polygon in &mut self.polygons {
+            polygon.flip();
+        }
+        if let Some(plane) = &mut self.plane {
+            plane.flip();
+        }
+        for child in [&mut self.front, &mut self.back].into_iter().flatten() {
+            child.invert();
+        }
+        std::mem::swap(&mut self.front, &mut self.back);
+    }
+
+    /// Removes the parts of `polygons` inside this tree's solid.
+    fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+        let Some(plane) = self.plane else {
+            return polygons;
+        };
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        for polygon in polygons {
+            let (mut coplanar_front, mut coplanar_back) = (Vec::new(), Vec::new());
+            plane.split(polygon, &mut coplanar_front, &mut coplanar_back, &mut front, &mut back);
+            front.append(&mut coplanar_front);
+            back.append(&mut coplanar_back);
+        }
+        let mut front = match &self.front {
+            Some(node) => node.clip_polygons(front),
+            None => front,
+        };
+        if let Some(node) = &self.back {
+            front.extend(node.clip_polygons(back));
+        }
+        front
+    }
+
+    /// Removes the parts of this tree's polygons inside `bsp`.
+    fn clip_to(&mut self, bsp: &Node) {
+        self.polygons = bsp.clip_polygons(std::mem::take(&mut self.polygons));
+        for child in [&mut self.front, &mut self.back].into_iter().flatten() {
+            child.clip_to(bsp);
+        }
+    }
+
+    fn all_polygons(&self) -> Vec<Polygon> {
+        let mut polygons = self.polygons.clone();
+        for child in [&self.front, &self.back].into_iter().flatten() {
+            polygons.extend(child.all_polygons());
+        }
+        polygons
+    }
+
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        if polygons.is_empty() {
+            return;
+        }
+        let plane = *self.plane.get_or_insert(polygons[0].plane);
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        for polygon in polygons {
+            let (mut coplanar_front, mut coplanar_back) = (Vec::new(), Vec::new());
+            plane.split(polygon, &mut coplanar_front, &mut coplanar_back, &mut front, &mut back);
+            self.polygons.append(&mut coplanar_front);
+            self.polygons.append(&mut coplanar_back);
+        }
+        if !front.is_empty() {
+            self.front.get_or_insert_with(Default::default).build(front);
+        }
+        if !back.is_empty() {
+            self.back.get_or_insert_with(Default::default).build(back);
+        }
+    }
+}
+
+fn polygons(triangles: &[Vec3]) -> Vec<Polygon> {
+    triangles.chunks_exact(3)
+        .filter_map(|triangle| {
+            let plane = Plane::from_points(triangle[0], triangle[1], triangle[2])?;
+            Some(Polygon {
+                vertices: triangle.to_vec(),
+                plane,
+            })
+        })
+        .collect()
+}
+
+/// Fans each convex polygon into triangles.
+fn triangulate(polygons: Vec<Polygon>) -> Vec<Vec3> {
+    let mut triangles = Vec::new();
+    for polygon in polygons {
+        for i in 1..polygon.vertices.len() - 1 {
+            triangles.extend([polygon.vertices[0], polygon.vertices[i], polygon.vertices[i + 1]]);
+        }
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::primitives::cuboid;
+
+    /// Signed volume enclosed by a closed triangle list, positive when wound outwards.
+    fn volume(triangles: &[Vec3]) -> f32 {
+        triangles.chunks_exact(3).map(|t| t[0].dot(t[1].cross(t[2])) / 6.0).sum()
+    }
+
+    fn bounds(triangles: &[Vec3]) -> (Vec3, Vec3) {
+        triangles.iter().fold((triangles[0], triangles[0]), |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)))
+    }
+
+    fn cubes() -> (Vec<Vec3>, Vec<Vec3>) {
+        let size = vec3![2.0, 2.0, 2.0];
+        (cuboid(Vec3::default(), size), cuboid(vec3![1.0, 1.0, 1.0], size))
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn overlapping_cubes() {
+        let (a, b) = cubes();
+        assert_close(volume(&a), 8.0);
+        assert_close(volume(&union(&a, &b)), 15.0);
+        assert_close(volume(&intersection(&a, &b)), 1.0);
+        assert_close(volume(&difference(&a, &b)), 7.0);
+        assert_close(volume(&difference(&b, &a)), 7.0);
+    }
+
+    #[test]
+    fn intersection_bounds() {
+        let (a, b) = cubes();
+        let (min, max) = bounds(&intersection(&a, &b));
+        assert_eq!((min, max), (vec3![0.0, 0.0, 0.0], vec3![1.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn disjoint_cubes() {
+        let a = cuboid(Vec3::default(), vec3![1.0, 1.0, 1.0]);
+        let b = cuboid(vec3![3.0, 0.0, 0.0], vec3![1.0, 1.0, 1.0]);
+        assert_close(volume(&union(&a, &b)), 2.0);
+        assert!(intersection(&a, &b).is_empty());
+        assert_close(volume(&difference(&a, &b)), 1.0);
+    }
+
+    #[test]
+    fn operation_names() {
+        let operation: Operation = serde_json::from_str("\"difference\"").unwrap();
+        assert_eq!(operation, Operation::Difference);
+    }
+}
@@ -0,0 +1,192 @@
+//! Hair and fur curves, tessellated into thin tubes.
+//!
+//! Curves are read from a plain text format with one control point per line, `x y z` or
+//! `x y z width`, and blank lines between strands. Lines starting with `#` are comments.
+
+use std::iter;
+
+use crate::Vec3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Basis {
+    /// Uniform cubic B-spline, clamped so the strand starts and ends on its end points.
+    BSpline,
+    /// Cubic Bézier spans sharing end points, so a strand has `3n + 1` control points.
+    Bezier,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CurveOptions {
+    pub basis: Basis,
+    /// Width of control points that do not give their own.
+    pub width: f32,
+    /// Straight segments each span is split into at the finest level of detail.
+    pub segments: u32,
+    /// Sides of the tube swept along the curve.
+    pub sides: u32,
+}
+
+impl Default for CurveOptions {
+    fn default() -> Self {
+        Self {
+            basis: Basis::BSpline,
+            width: 0.01,
+            segments: 4,
+            sides: 3,
+        }
+    }
+}
+
+/// One strand's control points.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Curve {
+    pub points: Vec<Vec3>,
+    /// Width at each control point; `None` uses [`CurveOptions::width`].
+    pub widths: Vec<Option<f32>>,
+}
+
+/// Parses the text curve format, reporting the first malformed line.
+pub fn parse(text: &str) -> Result<Vec<Curve>, String> {
+    let mut curves = Vec::new();
+    let mut curve = Curve::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.is_empty() {
+            if !curve.points.is_empty() {
+                curves.push(std::mem::take(&mut curve));
+            }
+            continue;
+        }
+        let values: Vec<f32> = line.split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|err| format!("line {}: {err}", number + 1))?;
+        match values[..] {
+            [x, y, z] => {
+                curve.points.push(vec3![x, y, z]);
+                curve.widths.push(None);
+            }
+            [x, y, z, width] => {
+                curve.points.push(vec3![x, y, z]);
+                curve.widths.push(Some(width));
+            }
+            _ => return Err(format!("line {}: expected x y z [width]", number + 1)),
+        }
+    }
+    if !curve.points.is_empty() {
+        curves.push(curve);
+    }
+    Ok(curves)
+}
+
+/// Sweeps a tube along every curve, returning one triangle list per level of detail, finest
+/// first. Each level halves the segments per span, down to straight spans.
+pub fn tessellate(curves: &[Curve], options: &CurveOptions) -> Vec<Vec<Vec3>> {
+    let mut lods = Vec::new();
+    let mut segments = options.segments.max(1);
+    loop {
+        let triangles = curves.iter()
+            .flat_map(|curve| sweep(&sample(curve, options, segments), options.sides.max(3)))
+            .collect();
+        lods.push(triangles);
+        if segments == 1 {
+            return lods;
+        }
+        segments /= 2;
+    }
+}
+
+/// Points along the curve with their widths.
+fn sample(curve: &Curve, options: &CurveOptions, segments: u32) -> Vec<(Vec3, f32)> {
+    let points: Vec<(Vec3, f32)> = curve.points.iter()
+        .zip(&curve.widths)
+        .map(|(point, width)| (*point, width.unwrap_or(options.width)))
+        .collect();
+    if points.len() < 4 {
+        return points;
+    }
+    let weights = |t: f32| match options.basis {
+        Basis::BSpline => [
+            (1.0 - t).powi(3) / 6.0,
+            (3.0 * t * t * t - 6.0 * t * t + 4.0) / 6.0,
+            (-3.0 * t * t * t + 3.0 * t * t + 3.0 * t + 1.0) / 6.0,
+            t * t * t / 6.0,
+        ],
+        Basis::Bezier => [
+            (1.0 - t).powi(3),
+            3.0 * t * (1.0 - t).powi(2),
+            3.0 * t * t * (1.0 - t),
+            t * t * t,
+        ],
+    };
+
+    // Repeating the end points makes the B-spline pass through them. Bézier spans overlap by
+    // their shared end point, and trailing points that do not complete a span are ignored.
+    let (controls, step): (Vec<(Vec3, f32)>, usize) = match options.basis {
+        Basis::BSpline => {
+            let controls = iter::repeat_n(points[0], 2)
+                .chain(points.iter().copied())
+                .chain(iter::repeat_n(points[points.len() - 1], 2))
+                .collect();
+            (controls, 1)
+        }
+        Basis::Bezier => (points, 3),
+    };
+    let mut samples = vec![evaluate(&controls[..4], weights(0.0))];
+    for span in (0..controls.len() - 3).step_by(step) {
+        for segment in 1..=segments {
+            let t = segment as f32 / segments as f32;
+            samples.push(evaluate(&controls[span..span + 4], weights(t)));
+        }
+    }
+    samples
+}
+
+fn evaluate(controls: &[(Vec3, f32)], weights: [f32; 4]) -> (Vec3, f32) {
+    controls.iter()
+        .zip(weights)
+        .fold((Vec3::default(), 0.0), |(point, width), ((control, control_width), weight)| {
+            (point + *control * weight, width + control_width * weight)
+        })
+}
+
+/// Builds a tube of `sides` faces through `samples`, with a frame carried along the curve so
+/// the tube does not twist.
+fn sweep(samples: &[(Vec3, f32)], sides: u32) -> Vec<Vec3> {
+    let mut triangles = Vec::new();
+    if samples.len() < 2 {
+        return triangles;
+    }
+    let tangent = |i: usize| {
+        let (before, after) = (samples[i.saturating_sub(1)].0, samples[(i + 1).min(samples.len() - 1)].0);
+        (after - before).normalize()
+    };
+    let first = tangent(0);
+    let helper = if first.x.abs() < 0.9 { vec3![1.0, 0.0, 0.0] } else { vec3![0.0, 1.0, 0.0] };
+    let mut normal = first.cross(helper).normalize();
+    let mut previous: Option<Vec<Vec3>> = None;
+    for (i, &(center, width)) in samples.iter().enumerate() {
+        let tangent = tangent(i);
+        normal = (normal - tangent * normal.dot(tangent)).normalize();
+        let binormal = tangent.cross(normal);
+        let ring: Vec<Vec3> = (0..sides)
+            .map(|side| {
+                let angle = std::f32::consts::TAU * side as f32 / sides as f32;
+                center + (normal * angle.cos() + binormal * angle.sin()) * (width * 0.5)
+            })
+            .collect();
+        if let Some(previous) = &previous {
+            for side in 0..sides as usize {
+                let next = (side + 1) % sides as usize;
+                let (a, b) = (previous[side], previous[next]);
+                let (c, d) = (ring[side], ring[next]);
+                triangles.extend([a, b, d, a, d, c]);
+            }
+        }
+        previous = Some(ring);
+    }
+    triangles
+}
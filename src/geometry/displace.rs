@@ -0,0 +1,96 @@
+//! Displacement mapping by uniform subdivision at load time.
+
+use serde::Deserialize;
+
+use crate::Vec3;
+
+/// A grayscale texture of heights in `0.0..=1.0`, repeating outside the unit square.
+#[derive(Clone, Debug, Default)]
+pub struct HeightTexture {
+    width: usize,
+    height: usize,
+    texels: Vec<f32>,
+}
+
+impl HeightTexture {
+    /// `texels` are row-major, starting at the top-left like glTF texture coordinates.
+    pub fn new(width: usize, height: usize, texels: Vec<f32>) -> Option<Self> {
+        (width > 0 && height > 0 && texels.len() >= width * height).then_some(Self {
+            width,
+            height,
+            texels,
+        })
+    }
+
+    /// Bilinearly filtered height at `uv`.
+    pub fn sample(&self, [u, v]: [f32; 2]) -> f32 {
+        let x = u.rem_euclid(1.0) * self.width as f32 - 0.5;
+        let y = v.rem_euclid(1.0) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |x: f32, y: f32| {
+            let x = (x as isize).rem_euclid(self.width as isize) as usize;
+            let y = (y as isize).rem_euclid(self.height as isize) as usize;
+            self.texels[y * self.width + x]
+        };
+        let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Displacement {
+    /// Distance along the normal for a height of one.
+    pub scale: f32,
+    /// Height that leaves the surface in place.
+    pub midlevel: f32,
+    /// Times each triangle edge is halved before displacing.
+    pub subdivisions: u32,
+}
+
+impl Default for Displacement {
+    fn default() -> Self {
+        Self {
+            scale: 0.1,
+            midlevel: 0.0,
+            subdivisions: 3,
+        }
+    }
+}
+
+/// Subdivides a triangle list and moves every new vertex along its interpolated normal by the
+/// height sampled at its interpolated texture coordinate.
+///
+/// `normals` and `uvs` hold one entry per vertex of `triangles`. Shared edges are split at the
+/// same points on both sides, so surfaces with smooth normals stay closed.
+pub fn displace(
+    triangles: &[Vec3],
+    normals: &[Vec3],
+    uvs: &[[f32; 2]],
+    heights: &HeightTexture,
+    displacement: &Displacement,
+) -> Vec<Vec3> {
+    let segments = 1usize << displacement.subdivisions.min(8);
+    let mut out = Vec::with_capacity(triangles.len() * segments * segments);
+    for corner in (0..triangles.len().min(normals.len()).min(uvs.len()) / 3).map(|triangle| triangle * 3) {
+        let point = |i: usize, j: usize| {
+            let (b, c) = (i as f32 / segments as f32, j as f32 / segments as f32);
+            let a = 1.0 - b - c;
+            let position = triangles[corner] * a + triangles[corner + 1] * b + triangles[corner + 2] * c;
+            let normal = (normals[corner] * a + normals[corner + 1] * b + normals[corner + 2] * c).normalize();
+            let uv = [0, 1].map(|axis| uvs[corner][axis] * a + uvs[corner + 1][axis] * b + uvs[corner + 2][axis] * c);
+            position + normal * ((heights.sample(uv) - displacement.midlevel) * displacement.scale)
+        };
+        for j in 0..segments {
+            for i in 0..segments - j {
+                out.extend([point(i, j), point(i + 1, j), point(i, j + 1)]);
+                if i + j + 1 < segments {
+                    out.extend([point(i + 1, j), point(i + 1, j + 1), point(i, j + 1)]);
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,8 @@
+pub mod cleanup;
+pub mod csg;
+pub mod curves;
+pub mod displace;
+pub mod primitives;
+pub mod simplify;
+pub mod tangents;
+pub mod terrain;
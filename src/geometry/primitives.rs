@@ -0,0 +1,191 @@
+//! Procedural meshes for building scenes without asset files.
+//!
+//! Every constructor returns a triangle list, three vertices per triangle, wound
+//! counter-clockwise when seen from outside. Add it to a scene with
+//! [`Scene::add_triangles`](crate::Scene::add_triangles).
+
+use std::f32::consts::{PI, TAU};
+
+use crate::Vec3;
+
+/// Icosphere subdivision is capped here; level 7 already has over 300 000 triangles.
+const MAX_SUBDIVISIONS: u32 = 7;
+
+/// A sphere tessellated along lines of latitude and longitude, with its poles on the Y axis.
+pub fn uv_sphere(center: Vec3, radius: f32, segments: u32, rings: u32) -> Vec<Vec3> {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let point = |ring: u32, segment: u32| {
+        let theta = PI * ring as f32 / rings as f32;
+        let phi = TAU * segment as f32 / segments as f32;
+        center + vec3![theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()] * radius
+    };
+    let mut triangles = Vec::new();
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let corners = [
+                point(ring, segment),
+                point(ring, segment + 1),
+                point(ring + 1, segment + 1),
+                point(ring + 1, segment),
+            ];
+            push_quad(&mut triangles, corners, |centroid| centroid - center);
+        }
+    }
+    triangles
+}
+
+/// A sphere made by repeatedly subdividing an icosahedron, giving evenly sized triangles.
+pub fn icosphere(center: Vec3, radius: f32, subdivisions: u32) -> Vec<Vec3> {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let vertices = [
+        vec3![-1.0, t, 0.0], vec3![1.0, t, 0.0], vec3![-1.0, -t, 0.0], vec3![1.0, -t, 0.0],
+        vec3![0.0, -1.0, t], vec3![0.0, 1.0, t], vec3![0.0, -1.0, -t], vec3![0.0, 1.0, -t],
+        vec3![t, 0.0, -1.0], vec3![t, 0.0, 1.0], vec3![-t, 0.0, -1.0], vec3![-t, 0.0, 1.0],
+    ].map(Vec3::normalize);
+    const FACES: [[usize; 3]; 20] = [
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+    let mut faces: Vec<[Vec3; 3]> = FACES.iter()
+        .map(|face| face.map(|index| vertices[index]))
+        .collect();
+    for _ in 0..subdivisions.min(MAX_SUBDIVISIONS) {
+        faces = faces.into_iter()
+            .flat_map(|[a, b, c]| {
+                let ab = ((a + b) * 0.5).normalize();
+                let bc = ((b + c) * 0.5).normalize();
+                let ca = ((c + a) * 0.5).normalize();
+                [[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+            })
+            .collect();
+    }
+    let mut triangles = Vec::with_capacity(faces.len() * 3);
+    for face in faces {
+        let face = face.map(|vertex| center + vertex * radius);
+        push_triangle(&mut triangles, face, centroid(&face) - center);
+    }
+    triangles
+}
+
+/// An axis-aligned box. Named `cuboid` because `box` is a reserved word.
+pub fn cuboid(center: Vec3, size: Vec3) -> Vec<Vec3> {
+    let half = size * 0.5;
+    let corner = |index: usize| center + vec3![
+        if index & 1 == 0 { -half.x } else { half.x },
+        if index & 2 == 0 { -half.y } else { half.y },
+        if index & 4 == 0 { -half.z } else { half.z }
+    ];
+    const FACES: [[usize; 4]; 6] = [
+        [0, 1, 3, 2], [4, 5, 7, 6],
+        [0, 1, 5, 4], [2, 3, 7, 6],
+        [0, 2, 6, 4], [1, 3, 7, 5],
+    ];
+    let mut triangles = Vec::with_capacity(36);
+    for face in FACES {
+        push_quad(&mut triangles, face.map(corner), |centroid| centroid - center);
+    }
+    triangles
+}
+
+/// A rectangle in the XZ plane facing +Y.
+pub fn plane(center: Vec3, width: f32, depth: f32) -> Vec<Vec3> {
+    let (x, z) = (width * 0.5, depth * 0.5);
+    let corners = [
+        center + vec3![-x, 0.0, -z],
+        center + vec3![x, 0.0, -z],
+        center + vec3![x, 0.0, z],
+        center + vec3![-x, 0.0, z],
+    ];
+    let mut triangles = Vec::with_capacity(6);
+    push_quad(&mut triangles, corners, |_| vec3![0.0, 1.0, 0.0]);
+    triangles
+}
+
+/// A torus lying in the XZ plane, with `major_radius` to the centre of the tube.
+pub fn torus(
+    center: Vec3,
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> Vec<Vec3> {
+    let (major_segments, minor_segments) = (major_segments.max(3), minor_segments.max(3));
+    let around = |step: f32| {
+        let angle = TAU * step / major_segments as f32;
+        vec3![angle.cos(), 0.0, angle.sin()]
+    };
+    let point = |major: u32, minor: u32| {
+        let angle = TAU * minor as f32 / minor_segments as f32;
+        let radial = around(major as f32);
+        center + radial * (major_radius + minor_radius * angle.cos()) + vec3![0.0, minor_radius * angle.sin(), 0.0]
+    };
+    let mut triangles = Vec::new();
+    for major in 0..major_segments {
+        // The tube's centre line at the middle of this segment.
+        let spine = center + around(major as f32 + 0.5) * major_radius;
+        for minor in 0..minor_segments {
+            let corners = [
+                point(major, minor),
+                point(major + 1, minor),
+                point(major + 1, minor + 1),
+                point(major, minor + 1),
+            ];
+            push_quad(&mut triangles, corners, |centroid| centroid - spine);
+        }
+    }
+    triangles
+}
+
+/// A capped cylinder along the Y axis.
+pub fn cylinder(center: Vec3, radius: f32, height: f32, segments: u32) -> Vec<Vec3> {
+    let segments = segments.max(3);
+    let point = |segment: u32, y: f32| {
+        let angle = TAU * segment as f32 / segments as f32;
+        center + vec3![radius * angle.cos(), y, radius * angle.sin()]
+    };
+    let (top, bottom) = (height * 0.5, -height * 0.5);
+    let mut triangles = Vec::new();
+    for segment in 0..segments {
+        let corners = [
+            point(segment, bottom),
+            point(segment + 1, bottom),
+            point(segment + 1, top),
+            point(segment, top),
+        ];
+        push_quad(&mut triangles, corners, |centroid| centroid - center);
+        for y in [top, bottom] {
+            let cap = [center + vec3![0.0, y, 0.0], point(segment, y), point(segment + 1, y)];
+            push_triangle(&mut triangles, cap, vec3![0.0, y, 0.0]);
+        }
+    }
+    triangles
+}
+
+fn centroid([a, b, c]: &[Vec3; 3]) -> Vec3 {
+    (*a + *b + *c) * (1.0 / 3.0)
+}
+
+/// Appends a triangle wound counter-clockwise around `outward`, skipping degenerate ones such as
+/// those at the poles of a UV sphere.
+fn push_triangle(triangles: &mut Vec<Vec3>, [a, b, c]: [Vec3; 3], outward: Vec3) {
+    let (ab, ac) = (b - a, c - a);
+    let normal = ab.cross(ac);
+    if normal.length() <= 1e-6 * ab.length() * ac.length() {
+        return;
+    }
+    if normal.dot(outward) < 0.0 {
+        triangles.extend([a, c, b]);
+    } else {
+        triangles.extend([a, b, c]);
+    }
+}
+
+/// Appends a quad as two triangles, given its corners in order around the edge and a function
+/// from each triangle's centroid to a direction pointing out of the surface.
+fn push_quad(triangles: &mut Vec<Vec3>, [a, b, c, d]: [Vec3; 4], outward: impl Fn(Vec3) -> Vec3) {
+    for triangle in [[a, b, c], [a, c, d]] {
+        push_triangle(triangles, triangle, outward(centroid(&triangle)));
+    }
+}
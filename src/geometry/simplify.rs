@@ -0,0 +1,364 @@
+//! Triangle mesh decimation with quadric error metrics (Garland & Heckbert).
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::Vec3;
+
+/// How strongly the planes fencing in open boundary edges resist collapses that would move them.
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+/// Symmetric 4x4 error quadric, stored as its upper triangle.
+#[derive(Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(a: f64, b: f64, c: f64, d: f64) -> Self {
+        Self([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d])
+    }
+
+    fn add(self, other: Self) -> Self {
+        let mut sum = self.0;
+        for (s, o) in sum.iter_mut().zip(other.0) {
+            *s += o;
+        }
+        Self(sum)
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Self(self.0.map(|q| q * factor))
+    }
+
+    fn error(&self, [x, y, z]: [f64; 3]) -> f64 {
+        let q = &self.0;
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// A candidate edge collapse merging `b` into `a`.
+struct Collapse {
+    cost: f64,
+    a: usize,
+    b: usize,
+    position: [f64; 3],
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // Reversed so the binary heap pops the cheapest collapse first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Simplifier {
+    positions: Vec<[f64; 3]>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    adjacency: Vec<Vec<usize>>,
+    triangles: Vec<[usize; 3]>,
+    live: Vec<bool>,
+    live_triangles: usize,
+    heap: BinaryHeap<Collapse>,
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normal([p0, p1, p2]: [[f64; 3]; 3]) -> [f64; 3] {
+    cross(sub(p1, p0), sub(p2, p0))
+}
+
+impl Simplifier {
+    fn new(vertices: &[Vec3]) -> Self {
+        let mut positions = vec![];
+        let mut welded = HashMap::new();
+        let triangles: Vec<[usize; 3]> = vertices.chunks_exact(3)
+            .map(|corners| {
+                let mut triangle = [0; 3];
+                for (index, vertex) in triangle.iter_mut().zip(corners) {
+                    let key = [vertex.x.to_bits(), vertex.y.to_bits(), vertex.z.to_bits()];
+                    *index = *welded.entry(key).or_insert_with(|| {
+                        positions.push([vertex.x as f64, vertex.y as f64, vertex.z as f64]);
+                        positions.len() - 1
+                    });
+                }
+                triangle
+            })
+            .collect();
+
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        let mut adjacency = vec![vec![]; positions.len()];
+        let mut live = vec![false; triangles.len()];
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for [a, b, c] in &triangles {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((*u.min(v), *u.max(v))).or_default() += 1;
+            }
+        }
+        for (t, triangle) in triangles.iter().enumerate() {
+            let [a, b, c] = *triangle;
+            if a == b || b == c || a == c {
+                continue;
+            }
+            live[t] = true;
+            for &v in triangle {
+                adjacency[v].push(t);
+            }
+            let n = normal(triangle.map(|v| positions[v]));
+            let length = dot(n, n).sqrt();
+            if length == 0.0 {
+                continue;
+            }
+            let n = n.map(|x| x / length);
+            let plane = Quadric::from_plane(n[0], n[1], n[2], -dot(n, positions[a]));
+            for &v in triangle {
+                quadrics[v] = quadrics[v].add(plane);
+            }
+            // Open edges get a heavy plane at right angles to the face so the border doesn't erode.
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                if edges[&(u.min(v), u.max(v))] != 1 {
+                    continue;
+                }
+                let edge = sub(positions[v], positions[u]);
+                let fence = cross(edge, n);
+                let length = dot(fence, fence).sqrt();
+                if length == 0.0 {
+                    continue;
+                }
+                let fence = fence.map(|x| x / length);
+                let plane = Quadric::from_plane(fence[0], fence[1], fence[2], -dot(fence, positions[u]))
+                    .scale(BOUNDARY_WEIGHT * dot(edge, edge));
+                quadrics[u] = quadrics[u].add(plane);
+                quadrics[v] = quadrics[v].add(plane);
+            }
+        }
+
+        let mut simplifier = Self {
+            versions: vec![0; positions.len()],
+            positions,
+            quadrics,
+            adjacency,
+            triangles,
+            live_triangles: live.iter().filter(|&&live| live).count(),
+            live,
+            heap: BinaryHeap::new(),
+        };
+        for t in 0..simplifier.triangles.len() {
+            if simplifier.live[t] {
+                let [a, b, c] = simplifier.triangles[t];
+                simplifier.push_edge(a, b);
+                simplifier.push_edge(b, c);
+                simplifier.push_edge(c, a);
+            }
+        }
+        simplifier
+    }
+
+    fn push_edge(&mut self, a: usize, b: usize) {
+        let quadric = self.quadrics[a].add(self.quadrics[b]);
+        let (pa, pb) = (self.positions[a], self.positions[b]);
+        let midpoint = [(pa[0] + pb[0]) / 2.0, (pa[1] + pb[1]) / 2.0, (pa[2] + pb[2]) / 2.0];
+        let (cost, position) = [pa, pb, midpoint].into_iter()
+            .map(|p| (quadric.error(p), p))
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+            .unwrap();
+        self.heap.push(Collapse {
+            cost,
+            a,
+            b,
+            position,
+            versions: (self.versions[a], self.versions[b]),
+        });
+    }
+
+    /// Whether moving `v` to `position` would turn any triangle not shared with `other` inside out.
+    fn flips(&self, v: usize, other: usize, position: [f64; 3]) -> bool {
+        self.adjacency[v].iter()
+            .filter(|&&t| self.live[t] && !self.triangles[t].contains(&other))
+            .any(|&t| {
+                let triangle = self.triangles[t];
+                let before = normal(triangle.map(|i| self.positions[i]));
+                let after = normal(triangle.map(|i| if i == v { position } else { self.positions[i] }));
+                dot(before, after) <= 0.0
+            })
+    }
+
+    fn collapse(&mut self, collapse: &Collapse) {
+        let Collapse { a, b, position, .. } = *collapse;
+        for t in std::mem::take(&mut self.adjacency[b]) {
+            if !self.live[t] {
+                continue;
+            }
+            let triangle = &mut self.triangles[t];
+            for v in triangle.iter_mut() {
+                if *v == b {
+                    *v = a;
+                }
+            }
+            let [x, y, z] = *triangle;
+            if x == y || y == z || x == z {
+                self.live[t] = false;
+                self.live_triangles -= 1;
+            } else {
+                self.adjacency[a].push(t);
+            }
+        }
+        self.positions[a] = position;
+        self.quadrics[a] = self.quadrics[a].add(self.quadrics[b]);
+        self.versions[a] += 1;
+        self.versions[b] += 1;
+
+        let live = &self.live;
+        self.adjacency[a].retain(|&t| live[t]);
+        let neighbours: HashSet<usize> = self.adjacency[a].iter()
+            .flat_map(|&t| self.triangles[t])
+            .filter(|&v| v != a)
+            .collect();
+        for v in neighbours {
+            self.push_edge(a, v);
+        }
+    }
+
+    fn run(mut self, target_triangles: usize) -> Vec<Vec3> {
+        while self.live_triangles > target_triangles {
+            let Some(collapse) = self.heap.pop() else {
+                break;
+            };
+            let (a, b) = (collapse.a, collapse.b);
+            if collapse.versions != (self.versions[a], self.versions[b]) {
+                continue;
+            }
+            if self.flips(a, b, collapse.position) || self.flips(b, a, collapse.position) {
+                continue;
+            }
+            self.collapse(&collapse);
+        }
+
+        self.triangles.iter()
+            .zip(&self.live)
+            .filter(|(_, live)| **live)
+            .flat_map(|(triangle, _)| *triangle)
+            .map(|v| {
+                let [x, y, z] = self.positions[v];
+                Vec3 { x: x as f32, y: y as f32, z: z as f32 }
+            })
+            .collect()
+    }
+}
+
+/// Decimates a triangle list down to at most `target_triangles` triangles where possible.
+///
+/// Vertices with identical positions are welded before simplification, open boundaries are held
+/// in place, and collapses that would flip a triangle are skipped, so the result may keep more
+/// triangles than requested.
+pub fn simplify(vertices: &[Vec3], target_triangles: usize) -> Vec<Vec3> {
+    Simplifier::new(vertices).run(target_triangles)
+}
+
+/// Builds up to `levels` coarser versions of a triangle list, halving the triangle count each time.
+///
+/// Stops early once a level would drop below `min_triangles` or simplification makes no progress.
+pub fn lod_chain(vertices: &[Vec3], levels: usize, min_triangles: usize) -> Vec<Vec<Vec3>> {
+    let mut chain: Vec<Vec<Vec3>> = vec![];
+    for _ in 0..levels {
+        let previous = chain.last().map_or(vertices, |lod| lod.as_slice());
+        let triangles = previous.len() / 3;
+        let target = triangles / 2;
+        if target < min_triangles {
+            break;
+        }
+        let lod = simplify(previous, target);
+        if lod.len() / 3 >= triangles {
+            break;
+        }
+        chain.push(lod);
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::primitives::icosphere;
+
+    /// An `n` by `n` grid of quads in the XZ plane, spanning -1 to 1.
+    fn grid(n: usize) -> Vec<Vec3> {
+        let point = |i: usize, j: usize| vec3![2.0 * i as f32 / n as f32 - 1.0, 0.0, 2.0 * j as f32 / n as f32 - 1.0];
+        (0..n).flat_map(|i| (0..n).map(move |j| (i, j)))
+            .flat_map(|(i, j)| [point(i, j), point(i, j + 1), point(i + 1, j + 1), point(i, j), point(i + 1, j + 1), point(i + 1, j)])
+            .collect()
+    }
+
+    fn area(vertices: &[Vec3]) -> f32 {
+        vertices.chunks_exact(3).map(|t| (t[1] - t[0]).cross(t[2] - t[0]).length() / 2.0).sum()
+    }
+
+    #[test]
+    fn flat_grid_collapses_without_leaving_its_plane() {
+        let lod = simplify(&grid(8), 16);
+        assert!(lod.len() / 3 <= 16);
+        assert!(lod.iter().all(|v| v.y == 0.0 && v.x.abs() <= 1.0 && v.z.abs() <= 1.0));
+        assert!((area(&lod) - 4.0).abs() < 1e-4, "area {}", area(&lod));
+    }
+
+    #[test]
+    fn sphere_keeps_its_shape() {
+        let sphere = icosphere(Vec3::default(), 1.0, 3);
+        let lod = simplify(&sphere, sphere.len() / 3 / 4);
+        assert!(lod.len() < sphere.len() / 2);
+        assert!(lod.iter().all(|v| (v.length() - 1.0).abs() < 0.1));
+        // Every face still points away from the centre.
+        assert!(lod.chunks_exact(3).all(|t| (t[1] - t[0]).cross(t[2] - t[0]).dot(t[0]) > 0.0));
+    }
+
+    #[test]
+    fn target_above_the_triangle_count_keeps_the_mesh() {
+        let mesh = grid(2);
+        assert_eq!(simplify(&mesh, 100), mesh);
+    }
+
+    #[test]
+    fn degenerate_triangles_are_dropped() {
+        let mut mesh = grid(1);
+        mesh.extend([vec3![0.0, 0.0, 0.0], vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0]]);
+        assert_eq!(simplify(&mesh, 100).len(), 6);
+    }
+
+    #[test]
+    fn lod_chain_halves_each_level() {
+        let chain = lod_chain(&grid(8), 8, 8);
+        let counts: Vec<usize> = chain.iter().map(|lod| lod.len() / 3).collect();
+        assert!(counts[0] <= 64);
+        assert!(counts.windows(2).all(|pair| pair[1] <= pair[0] / 2));
+        assert!(counts.iter().all(|&count| count >= 8));
+    }
+}
@@ -0,0 +1,182 @@
+//! Tangent generation following MikkTSpace, the convention glTF expects of normal maps whose
+//! primitives have no `TANGENT` attribute and that Blender, Substance and xNormal bake against.
+
+use std::collections::HashMap;
+
+use crate::Vec3;
+
+/// Per-vertex tangents of a triangle list, as glTF stores them: a unit tangent in `xyz` and the
+/// handedness of the bitangent, `cross(normal, tangent) * w`, in `w`.
+///
+/// `normals` and `uvs` hold one entry per vertex of `triangles`. Corners that share a position,
+/// normal and texture coordinate are averaged over the triangles around them whose UVs wind the
+/// same way, weighted by the corner angle, so mirrored UV islands keep their own tangents.
+/// Triangles without UV or surface area take the tangents of their neighbours.
+pub fn generate(triangles: &[Vec3], normals: &[Vec3], uvs: &[[f32; 2]]) -> Vec<[f32; 4]> {
+    let len = triangles.len().min(normals.len()).min(uvs.len()) / 3 * 3;
+    let key = |i: usize| {
+        let [u, v] = uvs[i];
+        let (p, n) = (triangles[i], normals[i]);
+        [p.x, p.y, p.z, n.x, n.y, n.z, u, v].map(f32::to_bits)
+    };
+
+    // Tangent direction and orientation of each triangle in texture space, when it has one.
+    let faces: Vec<Option<(Vec3, bool)>> = (0..len / 3)
+        .map(|triangle| {
+            let corner = triangle * 3;
+            let (d1, d2) = (triangles[corner + 1] - triangles[corner], triangles[corner + 2] - triangles[corner]);
+            let [u1, v1] = [0, 1].map(|axis| uvs[corner + 1][axis] - uvs[corner][axis]);
+            let [u2, v2] = [0, 1].map(|axis| uvs[corner + 2][axis] - uvs[corner][axis]);
+            let area = u1 * v2 - v1 * u2;
+            let tangent = d1 * v2 - d2 * v1;
+            (area != 0.0 && d1.cross(d2).length() > 0.0 && tangent.length() > 0.0)
+                .then(|| (tangent.normalize() * area.signum(), area > 0.0))
+        })
+        .collect();
+
+    let mut groups: HashMap<([u32; 8], bool), Vec3> = HashMap::new();
+    for (triangle, face) in faces.iter().enumerate() {
+        let Some((tangent, preserving)) = *face else {
+            continue;
+        };
+        for k in 0..3 {
+            let corner = triangle * 3 + k;
+            let normal = normals[corner].normalize();
+            let projected = tangent - normal * normal.dot(tangent);
+            if projected.length() == 0.0 {
+                continue;
+            }
+            let previous = triangles[triangle * 3 + (k + 2) % 3] - triangles[corner];
+            let next = triangles[triangle * 3 + (k + 1) % 3] - triangles[corner];
+            let edge = |edge: Vec3| edge - normal * normal.dot(edge);
+            let (a, b) = (edge(previous), edge(next));
+            let angle = if a.length() > 0.0 && b.length() > 0.0 {
+                a.normalize().dot(b.normalize()).clamp(-1.0, 1.0).acos()
+            } else {
+                0.0
+            };
+            let sum = groups.entry((key(corner), preserving)).or_default();
+            *sum = *sum + projected.normalize() * angle;
+        }
+    }
+
+    (0..len)
+        .map(|corner| {
+            let normal = normals[corner].normalize();
+            let preferred = faces[corner / 3].is_none_or(|(_, preserving)| preserving);
+            let found = [preferred, !preferred].into_iter().find_map(|preserving| {
+                let tangent = *groups.get(&(key(corner), preserving))?;
+                (tangent.length() > 0.0).then(|| (tangent.normalize(), preserving))
+            });
+            let (tangent, preserving) = found.unwrap_or_else(|| (perpendicular(normal), true));
+            let sign = if preserving { 1.0 } else { -1.0 };
+            [tangent.x, tangent.y, tangent.z, sign]
+        })
+        .collect()
+}
+
+/// Any unit vector perpendicular to `normal`, for corners no triangle gives a tangent.
+fn perpendicular(normal: Vec3) -> Vec3 {
+    let axis = if normal.x.abs() < 0.9 { vec3![1.0, 0.0, 0.0] } else { vec3![0.0, 1.0, 0.0] };
+    let tangent = axis - normal * normal.dot(axis);
+    if tangent.length() > 0.0 { tangent.normalize() } else { axis }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles covering the unit square in the XY plane at `x`, facing +Z, with texture
+    /// coordinates from `uv`.
+    fn quad(x: f32, uv: impl Fn(Vec3) -> [f32; 2]) -> (Vec<Vec3>, Vec<Vec3>, Vec<[f32; 2]>) {
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let triangles: Vec<Vec3> = corners.iter().map(|&[cx, cy]| vec3![x + cx, cy, 0.0]).collect();
+        let uvs = triangles.iter().map(|&v| uv(v)).collect();
+        (triangles.clone(), vec![vec3![0.0, 0.0, 1.0]; triangles.len()], uvs)
+    }
+
+    fn assert_tangent(tangent: [f32; 4], expected: [f32; 4]) {
+        let close = tangent.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5);
+        assert!(close, "{tangent:?} isn't {expected:?}");
+    }
+
+    /// The bitangent glTF derives from a normal and a tangent.
+    fn bitangent(normal: Vec3, tangent: [f32; 4]) -> Vec3 {
+        normal.cross(vec3![tangent[0], tangent[1], tangent[2]]) * tangent[3]
+    }
+
+    #[test]
+    fn tangents_follow_u_on_a_flat_plane() {
+        let (triangles, normals, uvs) = quad(0.0, |v| [v.x * 2.0, v.y * 2.0]);
+        let tangents = generate(&triangles, &normals, &uvs);
+        assert_eq!(tangents.len(), 6);
+        for (tangent, normal) in tangents.into_iter().zip(normals) {
+            assert_tangent(tangent, [1.0, 0.0, 0.0, 1.0]);
+            // The bitangent points up the V axis.
+            assert!((bitangent(normal, tangent) - vec3![0.0, 1.0, 0.0]).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_the_handedness() {
+        let (triangles, normals, uvs) = quad(0.0, |v| [-v.x, v.y]);
+        let tangents = generate(&triangles, &normals, &uvs);
+        for (tangent, normal) in tangents.into_iter().zip(normals) {
+            assert_tangent(tangent, [-1.0, 0.0, 0.0, -1.0]);
+            assert!((bitangent(normal, tangent) - vec3![0.0, 1.0, 0.0]).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn mirrored_islands_keep_their_own_tangents() {
+        // Two quads meeting at x = 0 with UVs mirrored across the seam, so the corners on it
+        // share position, normal and texture coordinate.
+        let (mut triangles, mut normals, mut uvs) = quad(-1.0, |v| [-v.x, v.y]);
+        let (right, right_normals, right_uvs) = quad(0.0, |v| [v.x, v.y]);
+        triangles.extend(right);
+        normals.extend(right_normals);
+        uvs.extend(right_uvs);
+
+        let tangents = generate(&triangles, &normals, &uvs);
+        for (corner, tangent) in tangents.into_iter().enumerate() {
+            let expected = if corner < 6 { [-1.0, 0.0, 0.0, -1.0] } else { [1.0, 0.0, 0.0, 1.0] };
+            assert_tangent(tangent, expected);
+        }
+    }
+
+    #[test]
+    fn corners_are_averaged_by_angle() {
+        // A corner shared by two triangles whose tangents are 45 degrees apart, one with a right
+        // angle at the corner and one with 45 degrees.
+        let triangles = [
+            vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0],
+            vec3![0.0, 0.0, 0.0], vec3![1.0, -1.0, 0.0], vec3![1.0, 0.0, 0.0],
+        ];
+        let uvs = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+        let normals = [vec3![0.0, 0.0, 1.0]; 6];
+        let tangents = generate(&triangles, &normals, &uvs);
+
+        // The first triangle's tangent is +X, the second's diagonal, weighted two to one.
+        let expected = (vec3![1.0, 0.0, 0.0] * 2.0 + vec3![1.0, -1.0, 0.0].normalize()).normalize();
+        assert_tangent(tangents[0], [expected.x, expected.y, expected.z, 1.0]);
+        assert_tangent(tangents[3], tangents[0]);
+    }
+
+    #[test]
+    fn triangles_without_uv_area_get_a_perpendicular_tangent() {
+        let (triangles, normals, _) = quad(0.0, |_| [0.0, 0.0]);
+        let tangents = generate(&triangles, &normals, &[[0.5, 0.5]; 6]);
+        for (tangent, normal) in tangents.into_iter().zip(normals) {
+            let direction = vec3![tangent[0], tangent[1], tangent[2]];
+            assert!((direction.length() - 1.0).abs() < 1e-5 && direction.dot(normal).abs() < 1e-5);
+            assert_eq!(tangent[3], 1.0);
+        }
+    }
+
+    #[test]
+    fn incomplete_triangles_are_left_out() {
+        let (triangles, normals, uvs) = quad(0.0, |v| [v.x, v.y]);
+        assert_eq!(generate(&triangles, &normals, &uvs[..5]).len(), 3);
+        assert!(generate(&[], &[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+//! Triangulation of heightfields into chunked terrain meshes.
+
+use std::iter;
+
+use crate::Vec3;
+
+/// How a heightfield is turned into terrain.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TerrainOptions {
+    /// Extent of the longer side of the terrain in scene units. The terrain is centred on the
+    /// origin in the XZ plane.
+    pub size: f32,
+    /// Height of a sample with value `1.0`.
+    pub height_scale: f32,
+    /// Quads along each side of a chunk. Chunks are separate meshes, so distant ones can drop to
+    /// a coarser level of detail.
+    pub chunk_size: usize,
+    /// Levels of detail per chunk, each sampling every other grid line of the one before.
+    pub lods: usize,
+}
+
+impl Default for TerrainOptions {
+    fn default() -> Self {
+        Self {
+            size: 100.0,
+            height_scale: 10.0,
+            chunk_size: 64,
+            lods: 4,
+        }
+    }
+}
+
+/// Triangulates `heights`, a row-major grid of `columns` by `rows` samples in `0.0..=1.0`.
+///
+/// Returns one level-of-detail chain of triangle lists per chunk, finest first. Coarser levels
+/// keep every chunk border vertex on the grid, so neighbouring chunks meet exactly when they are
+/// drawn at the same level.
+pub fn triangulate(heights: &[f32], columns: usize, rows: usize, options: &TerrainOptions) -> Vec<Vec<Vec<Vec3>>> {
+    if columns < 2 || rows < 2 || heights.len() < columns * rows {
+        return Vec::new();
+    }
+    let spacing = options.size / (columns.max(rows) - 1) as f32;
+    let origin_x = -spacing * (columns - 1) as f32 / 2.0;
+    let origin_z = -spacing * (rows - 1) as f32 / 2.0;
+    let point = |column: usize, row: usize| Vec3 {
+        x: origin_x + spacing * column as f32,
+        y: heights[row * columns + column] * options.height_scale,
+        z: origin_z + spacing * row as f32,
+    };
+
+    let chunk_size = options.chunk_size.max(1);
+    let mut chunks = Vec::new();
+    for row in (0..rows - 1).step_by(chunk_size) {
+        for column in (0..columns - 1).step_by(chunk_size) {
+            let end_row = (row + chunk_size).min(rows - 1);
+            let end_column = (column + chunk_size).min(columns - 1);
+            let lods = (0..options.lods.max(1))
+                .map(|level| 1 << level)
+                .take_while(|&stride| stride == 1 || stride <= chunk_size / 2)
+                .map(|stride| {
+                    let columns = grid_lines(column, end_column, stride);
+                    let rows = grid_lines(row, end_row, stride);
+                    let mut triangles = Vec::new();
+                    for z in rows.windows(2) {
+                        for x in columns.windows(2) {
+                            let (p00, p10) = (point(x[0], z[0]), point(x[1], z[0]));
+                            let (p01, p11) = (point(x[0], z[1]), point(x[1], z[1]));
+                            triangles.extend([p00, p01, p10, p10, p01, p11]);
+                        }
+                    }
+                    triangles
+                })
+                .collect();
+            chunks.push(lods);
+        }
+    }
+    chunks
+}
+
+/// Grid indices from `start` to `end` inclusive, `stride` apart except for the last step.
+fn grid_lines(start: usize, end: usize, stride: usize) -> Vec<usize> {
+    (start..end).step_by(stride).chain(iter::once(end)).collect()
+}
@@ -0,0 +1,77 @@
+//! A ground plane generated under the scene, with the shadows of its meshes.
+//!
+//! The rasterizer has no shadow maps, so shadows are the meshes flattened onto the plane along
+//! the light, drawn before the meshes themselves. They blend with a minimum so overlapping
+//! triangles darken the ground only once.
+
+use bytemuck::{Pod, Zeroable};
+
+use wgpu::Color;
+
+use crate::{
+    Settings,
+    Vec3,
+};
+
+/// A horizontal plane under the scene's bounds catching the shadows of its meshes.
+///
+/// The sun of [`Settings::sky`] casts the shadows when it is above the horizon; otherwise they
+/// fall straight down.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GroundPlane {
+    /// Linear RGB colour of the plane.
+    pub color: [f32; 3],
+    /// How much shadows darken what is under them, from 0 (not at all) to 1 (black).
+    pub shadow_opacity: f32,
+    /// Draws only the shadows: the rest of the plane shows the background and keeps its
+    /// alpha, so a product shot can be composited over another image with its shadow.
+    ///
+    /// Shadows darken [`Settings::bg_color`], so over a sky they take its colour rather than
+    /// the sky's. Over a [transparent background](Settings::transparent_background) they are
+    /// black with the shadow opacity as alpha.
+    pub shadow_catcher: bool,
+    /// Side of the plane as a multiple of the larger horizontal side of the scene bounds.
+    pub size: f32,
+}
+
+impl Default for GroundPlane {
+    fn default() -> Self {
+        Self {
+            color: [0.5, 0.5, 0.5],
+            shadow_opacity: 0.6,
+            shadow_catcher: false,
+            size: 4.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+pub(crate) struct GroundUniform {
+    /// Centre of the plane, with half its side in w.
+    plane: [f32; 4],
+    /// Plane colour, with the shadow opacity in w.
+    color: [f32; 4],
+    /// Direction towards the light casting shadows, with w set for a shadow catcher.
+    light: [f32; 4],
+    /// Background colour shadow catchers darken.
+    background: [f32; 4],
+}
+
+impl GroundUniform {
+    /// Places `ground` under the box `min`..`max`.
+    pub(crate) fn new(ground: &GroundPlane, settings: &Settings, min: Vec3, max: Vec3) -> Self {
+        let center = (min + max) * 0.5;
+        let half_size = 0.5 * ground.size * (max.x - min.x).max(max.z - min.z).max(1e-3);
+        let sun = settings.sky.map(|sky| Vec3::from(sky.sun_direction())).filter(|sun| sun.y > 0.05);
+        let light = sun.unwrap_or(vec3![0.0, 1.0, 0.0]).normalize();
+        let [r, g, b] = ground.color;
+        let background = if settings.transparent_background { Color::TRANSPARENT } else { settings.bg_color };
+        Self {
+            plane: [center.x, min.y, center.z, half_size],
+            color: [r, g, b, ground.shadow_opacity.clamp(0.0, 1.0)],
+            light: [light.x, light.y, light.z, if ground.shadow_catcher { 1.0 } else { 0.0 }],
+            background: [background.r, background.g, background.b, background.a].map(|c| c as f32),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    fmt,
+    fs::File,
+    io::BufWriter,
+    ops::Range,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use image::{ImageResult, RgbaImage};
+use wgpu::{
+    Buffer,
+    BufferDescriptor,
+    BufferUsages,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
+    CommandEncoderDescriptor,
+    Extent3d,
+    Maintain,
+    MapMode,
+    Origin3d,
+    TexelCopyBufferInfo,
+    TexelCopyBufferLayout,
+    TexelCopyTextureInfo,
+    Texture,
+    TextureAspect,
+};
+
+use crate::{
+    Camera,
+    Scene,
+    Settings,
+    exr,
+    multi_gpu::MultiGpu,
+    renderer::Renderer,
+};
+
+/// Fewest samples the error estimate is trusted from, as it is noisy itself before.
+const MIN_SAMPLES: u32 = 4;
+
+/// Added to the squared mean of each pixel the relative error is taken against, so dark
+/// pixels don't dominate it.
+const ERROR_EPSILON: f32 = 0.01;
+
+/// What [`render`] draws besides the beauty image.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Also renders each of these layers on its own. Untagged objects are in the `"default"`
+    /// layer.
+    pub layers: Vec<String>,
+    /// Also renders an ID matte, every mesh in a flat colour hashed from its index.
+    pub id_matte: bool,
+    /// Splits each image across every GPU of the machine instead of using the fastest one.
+    pub multi_gpu: bool,
+    /// Frames averaged into each image, each offset by a different fraction of a pixel so edges
+    /// and fine detail are smoothed. With `target_error`, the most that are taken.
+    pub samples: u32,
+    /// Stops taking samples once the image's estimated error, see [`Convergence::error`], is at
+    /// most this.
+    pub target_error: Option<f32>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            layers: Vec::new(),
+            id_matte: false,
+            multi_gpu: true,
+            samples: 1,
+            target_error: None,
+        }
+    }
+}
+
+/// Images produced by [`render`], in sRGB with premultiplied alpha. Write them with [`save`].
+pub struct RenderOutput {
+    /// Every layer selected by the settings.
+    pub image: RgbaImage,
+    /// One image per requested layer, in the order they were requested.
+    pub layers: Vec<(String, RgbaImage)>,
+    pub id_matte: Option<RgbaImage>,
+    /// How far the image had converged when sampling stopped.
+    pub convergence: Convergence,
+}
+
+/// Samples taken for an image and how close their average is to the fully converged image.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Convergence {
+    pub samples: u32,
+    /// Estimated relative mean squared error of the image's linear colours, from how much the
+    /// averages of its even and odd samples differ, or `None` for a single sample.
+    pub error: Option<f32>,
+}
+
+impl fmt::Display for Convergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} samples", self.samples)?;
+        if let Some(error) = self.error {
+            write!(f, ", relative MSE {error:.3e}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders a still of `scene` offscreen, along with any layers and mattes for compositing.
+pub fn render(scene: Scene, settings: Settings, camera: Camera, options: &RenderOptions) -> RenderOutput {
+    let mut gpus = MultiGpu::new(scene, settings, camera, options.width, options.height, options.multi_gpu);
+
+    let (image, convergence) = accumulate(&mut gpus, options.samples.max(1), options.target_error);
+    let selected = gpus.settings().layers.clone();
+    let mut layers = Vec::with_capacity(options.layers.len());
+    for layer in &options.layers {
+        gpus.update_settings(|settings| settings.layers = Some(vec![layer.clone()]));
+        layers.push((layer.clone(), accumulate(&mut gpus, convergence.samples, None).0));
+    }
+    gpus.update_settings(|settings| settings.layers = selected.clone());
+    // Averaging would blend the colours of neighbouring IDs along edges.
+    let id_matte = options.id_matte.then(|| {
+        gpus.update_settings(|settings| settings.id_matte = true);
+        gpus.render()
+    });
+
+    RenderOutput { image, layers, id_matte, convergence }
+}
+
+/// Averages up to `samples` jittered frames, stopping once the error is at most `target_error`.
+fn accumulate(gpus: &mut MultiGpu, samples: u32, target_error: Option<f32>) -> (RgbaImage, Convergence) {
+    if samples == 1 {
+        return (gpus.render(), Convergence { samples: 1, error: None });
+    }
+    let mut accumulator = Accumulator::default();
+    for sample in 1..=samples {
+        gpus.set_jitter([halton(sample, 2) - 0.5, halton(sample, 3) - 0.5]);
+        accumulator.add(&gpus.render());
+        let error = accumulator.error();
+        if let Some(error) = error {
+            log::debug!("Sample {sample}: relative MSE {error:.3e}");
+        }
+        if sample >= MIN_SAMPLES
+            && let (Some(target), Some(error)) = (target_error, error)
+            && error <= target
+        {
+            break;
+        }
+    }
+    gpus.set_jitter([0.0; 2]);
+    let convergence = Convergence {
+        samples: accumulator.samples(),
+        error: accumulator.error(),
+    };
+    (accumulator.image(), convergence)
+}
+
+/// Element `index` of the Halton sequence in `base`, points spread evenly over 0 to 1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let (mut scale, mut value) = (1.0, 0.0);
+    while index > 0 {
+        scale /= base as f32;
+        value += scale * (index % base) as f32;
+        index /= base;
+    }
+    value
+}
+
+/// Sums of samples in linear premultiplied colour, the even and odd ones apart so their
+/// difference shows how noisy the average still is.
+#[derive(Default)]
+struct Accumulator {
+    width: u32,
+    height: u32,
+    sums: [Vec<[f32; 4]>; 2],
+    counts: [u32; 2],
+}
+
+impl Accumulator {
+    fn add(&mut self, image: &RgbaImage) {
+        (self.width, self.height) = image.dimensions();
+        let half = (self.samples() % 2) as usize;
+        let sum = &mut self.sums[half];
+        sum.resize(image.len() / 4, [0.0; 4]);
+        for (sum, pixel) in sum.iter_mut().zip(image.pixels()) {
+            let [r, g, b, a] = pixel.0;
+            let sample = [exr::srgb_to_linear(r), exr::srgb_to_linear(g), exr::srgb_to_linear(b), a as f32 / 255.0];
+            for (sum, value) in sum.iter_mut().zip(sample) {
+                *sum += value;
+            }
+        }
+        self.counts[half] += 1;
+    }
+
+    fn samples(&self) -> u32 {
+        self.counts[0] + self.counts[1]
+    }
+
+    fn image(&self) -> RgbaImage {
+        let samples = self.samples() as f32;
+        let mut image = RgbaImage::new(self.width, self.height);
+        for (i, pixel) in image.pixels_mut().enumerate() {
+            let [r, g, b, a] = [0, 1, 2, 3].map(|c| (self.sums[0][i][c] + self.sums[1].get(i).map_or(0.0, |sum| sum[c])) / samples);
+            pixel.0 = [exr::linear_to_srgb(r), exr::linear_to_srgb(g), exr::linear_to_srgb(b), (a * 255.0).round() as u8];
+        }
+        image
+    }
+
+    /// Relative MSE of the average of all samples, or `None` before both halves have one.
+    ///
+    /// The halves' averages differ by noise with a variance of `1/even + 1/odd` times that of
+    /// one sample, where the average of all has `1/samples` times it.
+    fn error(&self) -> Option<f32> {
+        let [even, odd] = self.counts.map(|count| count as f32);
+        if odd == 0.0 {
+            return None;
+        }
+        let scale = 1.0 / (even + odd) / (1.0 / even + 1.0 / odd);
+        let total: f32 = self.sums[0].iter().zip(&self.sums[1])
+            .flat_map(|(a, b)| (0..3).map(move |c| {
+                let (a, b) = (a[c] / even, b[c] / odd);
+                let mean = (a * even + b * odd) / (even + odd);
+                (a - b) * (a - b) / (mean * mean + ERROR_EPSILON)
+            }))
+            .sum();
+        Some(scale * total / (3 * self.sums[0].len()).max(1) as f32)
+    }
+}
+
+/// Writes an image from [`render`] to `path`: as OpenEXR when it ends in `.exr`, otherwise in
+/// the format its extension names.
+///
+/// EXR keeps the premultiplied alpha, in linear floats. Other formats get straight alpha, as PNG
+/// requires, so colours are divided by alpha on the way out; opaque pixels are unchanged.
+pub fn save<P: AsRef<Path>>(image: &RgbaImage, path: P) -> ImageResult<()> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("exr")) {
+        let mut out = BufWriter::new(File::create(path)?);
+        exr::write(image, &mut out)?;
+        return Ok(());
+    }
+    straight_alpha(image).save(path)
+}
+
+/// `image` with its colours divided by alpha, as PNG and JPEG expect.
+pub(crate) fn straight_alpha(image: &RgbaImage) -> RgbaImage {
+    let mut straight = image.clone();
+    for pixel in straight.pixels_mut().filter(|pixel| pixel[3] != 255) {
+        let [r, g, b, a] = pixel.0;
+        let alpha = a as f32 / 255.0;
+        let unpremultiply = |c: u8| if a == 0 { 0 } else { exr::linear_to_srgb(exr::srgb_to_linear(c) / alpha) };
+        pixel.0 = [unpremultiply(r), unpremultiply(g), unpremultiply(b), a];
+    }
+    straight
+}
+
+/// Copies an `Rgba8` texture back to the CPU.
+pub(crate) fn read_back(renderer: &Renderer, texture: &Texture) -> RgbaImage {
+    let readback = Readback::start(renderer, texture, 0..texture.height());
+    renderer.device.poll(Maintain::Wait);
+    readback.finish()
+}
+
+/// Rows of an `Rgba8` texture being copied back to the CPU.
+pub(crate) struct Readback {
+    buffer: Buffer,
+    width: u32,
+    height: u32,
+    padded_row_bytes: u32,
+    mapped: Arc<AtomicBool>,
+}
+
+impl Readback {
+    /// Submits a copy of `rows` of `texture` and maps it once the device gets to it.
+    pub(crate) fn start(renderer: &Renderer, texture: &Texture, rows: Range<u32>) -> Self {
+        let width = texture.width();
+        let height = rows.len() as u32;
+        // Buffer rows must be padded to the copy alignment; the padding is dropped again below.
+        let padded_row_bytes = (width * 4).div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = renderer.device.create_buffer(&BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row_bytes * height) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = renderer.device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: Origin3d { x: 0, y: rows.start, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        renderer.queue.submit([encoder.finish()]);
+
+        let mapped = Arc::new(AtomicBool::new(false));
+        let flag = mapped.clone();
+        buffer.slice(..).map_async(MapMode::Read, move |result| {
+            result.unwrap();
+            flag.store(true, Ordering::Release);
+        });
+        Self { buffer, width, height, padded_row_bytes, mapped }
+    }
+
+    /// Whether the copy has landed, once the device has been polled.
+    pub(crate) fn is_ready(&self) -> bool {
+        self.mapped.load(Ordering::Acquire)
+    }
+
+    /// The copied rows. The device must have been polled until [`Readback::is_ready`].
+    pub(crate) fn finish(self) -> RgbaImage {
+        let row_bytes = (self.width * 4) as usize;
+        let pixels = self.buffer.slice(..).get_mapped_range()
+            .chunks_exact(self.padded_row_bytes as usize)
+            .flat_map(|row| &row[..row_bytes])
+            .copied()
+            .collect();
+        self.buffer.unmap();
+        RgbaImage::from_raw(self.width, self.height, pixels).unwrap()
+    }
+}
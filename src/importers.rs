@@ -0,0 +1,159 @@
+//! One entry point for every scene format, dispatching on the file extension.
+//!
+//! Files are read through an [`AssetSource`], so scenes can come from an archive or a server as
+//! well as from disk. Scenes can also be imported from bytes already in memory, given the
+//! extension their file would have.
+//!
+//! Every importer produces a [`Scene`], the one representation the renderer draws, so a new
+//! format only needs an [`Importer`].
+
+use std::{
+    io,
+    path::Path,
+};
+
+use crate::{
+    LoadError,
+    Scene,
+    assets::{AssetSource, FileSystem},
+    geometry::{curves::CurveOptions, terrain::TerrainOptions},
+    loader,
+    stl::StlOptions,
+};
+
+/// Reads files of some format into a [`Scene`].
+pub trait Importer {
+    /// Lowercase file extensions the importer reads, without the dot.
+    fn extensions(&self) -> &[&str];
+
+    /// Imports the file at `path` in `source`, reading any files it refers to from `source`.
+    fn import(&self, source: &dyn AssetSource, path: &Path) -> Result<Scene, LoadError>;
+
+    /// Imports a file's contents from memory. Formats that need other files beside the scene,
+    /// or importers that only read files, can leave this unsupported.
+    fn import_bytes(&self, bytes: &[u8]) -> Result<Scene, LoadError> {
+        let _ = bytes;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "importing from memory is not supported").into())
+    }
+}
+
+/// An importer made of a function, for the built-in formats.
+struct FnImporter {
+    extensions: &'static [&'static str],
+    import: fn(&dyn AssetSource, &Path) -> Result<Scene, LoadError>,
+    import_bytes: fn(&[u8]) -> Result<Scene, LoadError>,
+}
+
+impl Importer for FnImporter {
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+
+    fn import(&self, source: &dyn AssetSource, path: &Path) -> Result<Scene, LoadError> {
+        (self.import)(source, path)
+    }
+
+    fn import_bytes(&self, bytes: &[u8]) -> Result<Scene, LoadError> {
+        (self.import_bytes)(bytes)
+    }
+}
+
+/// The importers to choose from by extension. Files with an extension no importer claims are
+/// read as glTF.
+pub struct Importers {
+    importers: Vec<Box<dyn Importer>>,
+}
+
+impl Default for Importers {
+    /// The built-in formats. Terrain, hair and STL use default options.
+    fn default() -> Self {
+        let builtin: [FnImporter; 7] = [
+            FnImporter {
+                extensions: &["gltf", "glb"],
+                import: |source, path| Ok(loader::load_gltf_from(source, path)?),
+                import_bytes: |bytes| Ok(loader::load_gltf_bytes(bytes)?),
+            },
+            FnImporter {
+                extensions: &["usd", "usda", "usdz"],
+                import: |source, path| Ok(loader::load_usd_bytes(&source.read(path)?)?),
+                import_bytes: |bytes| Ok(loader::load_usd_bytes(bytes)?),
+            },
+            FnImporter {
+                extensions: &["ply"],
+                import: |source, path| Ok(loader::load_ply_bytes(&source.read(path)?)?),
+                import_bytes: |bytes| Ok(loader::load_ply_bytes(bytes)?),
+            },
+            FnImporter {
+                extensions: &["stl"],
+                import: |source, path| Ok(loader::load_stl_bytes(&source.read(path)?, &StlOptions::default())?),
+                import_bytes: |bytes| Ok(loader::load_stl_bytes(bytes, &StlOptions::default())?),
+            },
+            FnImporter {
+                extensions: &["vol"],
+                import: |source, path| Ok(loader::load_volume_bytes(&source.read(path)?)?),
+                import_bytes: |bytes| Ok(loader::load_volume_bytes(bytes)?),
+            },
+            FnImporter {
+                extensions: &["png", "jpg", "jpeg"],
+                import: |source, path| Ok(loader::load_heightmap_bytes(&source.read(path)?, &TerrainOptions::default())?),
+                import_bytes: |bytes| Ok(loader::load_heightmap_bytes(bytes, &TerrainOptions::default())?),
+            },
+            FnImporter {
+                extensions: &["curves"],
+                import: |source, path| Ok(loader::load_curves_bytes(&source.read(path)?, &CurveOptions::default())?),
+                import_bytes: |bytes| Ok(loader::load_curves_bytes(bytes, &CurveOptions::default())?),
+            },
+        ];
+        Self {
+            importers: builtin.into_iter()
+                .map(|importer| Box::new(importer) as Box<dyn Importer>)
+                .collect(),
+        }
+    }
+}
+
+impl Importers {
+    /// Adds an importer, taking precedence over earlier ones for the extensions it claims.
+    pub fn register(&mut self, importer: Box<dyn Importer>) {
+        self.importers.insert(0, importer);
+    }
+
+    /// Extensions some importer reads.
+    pub fn extensions(&self) -> impl Iterator<Item = &str> {
+        self.importers.iter().flat_map(|importer| importer.extensions().iter().copied())
+    }
+
+    /// The importer for files with `extension`, if any.
+    fn find(&self, extension: &str) -> Option<&dyn Importer> {
+        let extension = extension.to_ascii_lowercase();
+        self.importers.iter()
+            .find(|importer| importer.extensions().contains(&extension.as_str()))
+            .map(Box::as_ref)
+    }
+
+    /// Imports `path` from disk with the importer for its extension.
+    pub fn import(&self, path: &Path) -> Result<Scene, LoadError> {
+        self.import_from(&FileSystem::default(), path)
+    }
+
+    /// Imports `path` in `source` with the importer for its extension.
+    pub fn import_from(&self, source: &dyn AssetSource, path: &Path) -> Result<Scene, LoadError> {
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        let mut scene = match self.find(extension) {
+            Some(importer) => importer.import(source, path)?,
+            None => loader::load_gltf_from(source, path)?,
+        };
+        scene.sources.insert(0, path.to_owned());
+        Ok(scene)
+    }
+
+    /// Imports the contents of a file with `extension`, such as `"glb"`, from memory.
+    pub fn import_bytes(&self, bytes: &[u8], extension: &str) -> Result<Scene, LoadError> {
+        match self.find(extension) {
+            Some(importer) => importer.import_bytes(bytes),
+            None => Ok(loader::load_gltf_bytes(bytes)?),
+        }
+    }
+}
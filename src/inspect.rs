@@ -0,0 +1,100 @@
+use std::fmt;
+
+use crate::Vec3;
+
+/// What the camera ray through one pixel hits.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PixelInfo {
+    pub x: u32,
+    pub y: u32,
+    /// Index of the nearest mesh the ray hits.
+    pub mesh: Option<usize>,
+    /// Distance from the camera to the hit along the ray.
+    pub depth: Option<f32>,
+    pub position: Option<Vec3>,
+    /// Surface colour before fog and clamping, with any material override applied.
+    pub color: Option<[f32; 4]>,
+    /// Samples taken for the pixel. The rasterizer takes one per frame.
+    pub samples: u32,
+}
+
+impl fmt::Display for PixelInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pixel ({}, {}): ", self.x, self.y)?;
+        match (self.mesh, self.depth, self.position, self.color) {
+            (Some(mesh), Some(depth), Some(position), Some([r, g, b, a])) => write!(
+                f,
+                "mesh {mesh}, depth {depth:.4}, position ({:.4}, {:.4}, {:.4}), color ({r:.4}, {g:.4}, {b:.4}, {a:.4}), {} sample(s)",
+                position.x, position.y, position.z, self.samples,
+            ),
+            _ => write!(f, "background"),
+        }
+    }
+}
+
+/// A camera ray captured for display in the viewport.
+///
+/// The rasterizer traces no secondary rays, so a captured path is the primary ray alone.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DebugRay {
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// Where the ray hits the scene, or where it leaves the camera's far plane.
+    pub end: Vec3,
+    /// Mesh the ray hits.
+    pub mesh: Option<usize>,
+}
+
+impl DebugRay {
+    /// Line list vertices drawing the ray, with a small cross marking a hit.
+    pub(crate) fn lines(&self) -> Vec<Vec3> {
+        let mut lines = vec![self.origin, self.end];
+        if self.mesh.is_some() {
+            let size = (self.end - self.origin).length() * 0.01;
+            for axis in [vec3![size, 0.0, 0.0], vec3![0.0, size, 0.0], vec3![0.0, 0.0, size]] {
+                lines.extend([self.end - axis, self.end + axis]);
+            }
+        }
+        lines
+    }
+}
+
+/// Distance along the ray to triangle `abc`, by Möller-Trumbore. Both faces count as hits.
+pub(crate) fn intersect_triangle(origin: Vec3, direction: Vec3, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = direction.cross(ac);
+    let determinant = ab.dot(p);
+    if determinant.abs() < 1e-9 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let to_origin = origin - a;
+    let u = to_origin.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(ab);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = ac.dot(q) * inverse;
+    (t > 0.0).then_some(t)
+}
+
+/// Whether the ray enters the box between `min` and `max`, by the slab test.
+pub(crate) fn hits_bounds(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> bool {
+    let mut near = 0.0f32;
+    let mut far = f32::INFINITY;
+    for (o, d, lo, hi) in [
+        (origin.x, direction.x, min.x, max.x),
+        (origin.y, direction.y, min.y, max.y),
+        (origin.z, direction.z, min.z, max.z),
+    ] {
+        let (t0, t1) = ((lo - o) / d, (hi - o) / d);
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    near <= far
+}
@@ -1,116 +1,651 @@
+macro_rules! vec3 {
+    [$x:expr, $y:expr, $z:expr] => {
+        Vec3 { x: $x, y: $y, z: $z }
+    };
+}
+
+pub mod assets;
+pub mod bench;
+pub mod compare;
+pub mod distributed;
+pub mod ffi;
+pub mod geometry;
+pub mod headless;
+pub mod importers;
+pub mod preview;
+pub mod stream;
+
+mod analysis;
+mod atmosphere;
+mod audio;
+mod backplate;
+mod blit;
+mod bookmarks;
+mod callbacks;
+mod camera;
+mod clip;
+mod clipboard;
+mod control;
+mod convert;
+#[cfg(feature = "draco")]
+mod draco;
+mod entity;
+mod export;
+mod exr;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod ground;
+mod inspect;
+mod light;
+mod loader;
+mod material_graph;
+mod materialx;
+mod measure;
+mod memory;
+mod meshlet;
+mod multi_gpu;
+mod nan;
+mod optimize;
+mod outline;
+mod overlay;
+mod overrides;
+mod particles;
+mod physics;
+mod pipelines;
+mod plugin;
+mod ply;
+mod procedural;
+mod profiler;
+mod quantize;
+#[cfg(feature = "physics")]
+mod rapier;
+mod remote;
+mod renderer;
+mod scene;
+mod script;
+mod session;
+mod shaders;
+mod sky;
+#[cfg(feature = "spacemouse")]
+mod spacemouse;
+mod text;
+mod timeline;
+mod title;
+mod stats;
+mod stereo;
+mod stl;
+mod trace;
+mod upload;
+mod usd;
+mod volume;
+mod watch;
+
 use std::{
+    io,
     iter,
-    sync::Arc
+    mem,
+    net::SocketAddr,
+    ops::{Add, Mul, Range, Sub},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use winit::{
     application::ApplicationHandler,
-    dpi::PhysicalSize,
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
     error::EventLoopError,
-    event::WindowEvent,
+    event::{ElementState, KeyEvent, MouseButton, Touch, TouchPhase, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop},
-    window::{Window, WindowId},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+    window::{Fullscreen, Window, WindowId},
 };
 
 use wgpu::{
-    util::{
-        BufferInitDescriptor,
-        DeviceExt,
-    },
     Backends,
-    BindGroup,
-    BindGroupDescriptor,
-    BindGroupEntry,
-    BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry,
-    BindingType,
-    BlendState,
-    Buffer,
     BufferAddress,
-    BufferBindingType,
-    BufferUsages,
     Color,
-    ColorTargetState,
-    ColorWrites,
     CommandEncoderDescriptor,
+    CreateSurfaceError,
     Device,
-    DeviceDescriptor,
-    Face,
-    Features,
-    FragmentState,
-    FrontFace,
+    Extent3d,
     Instance,
     InstanceDescriptor,
-    Limits,
-    LoadOp,
-    MultisampleState,
-    Operations,
-    PipelineCompilationOptions,
-    PipelineLayoutDescriptor,
-    PolygonMode,
     PowerPreference,
-    PrimitiveState,
-    PrimitiveTopology,
     Queue,
-    RenderPassColorAttachment,
-    RenderPassDescriptor,
-    RenderPipeline,
-    RenderPipelineDescriptor,
     RequestAdapterOptions,
-    ShaderStages,
-    StoreOp,
     Surface,
     SurfaceConfiguration,
     SurfaceError,
+    SurfaceTargetUnsafe,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
     TextureUsages,
+    TextureView,
     TextureViewDescriptor,
     VertexAttribute,
     VertexBufferLayout,
-    VertexState,
     VertexStepMode,
-    include_wgsl,
     vertex_attr_array,
 };
 
 use pollster::block_on;
 
+use image::{ImageResult, RgbaImage};
+
+use serde::{Deserialize, Serialize};
+
+pub use analysis::AnalysisView;
+pub use atmosphere::Atmosphere;
+pub use audio::{AudioMapping, AudioReactive, AudioTarget};
+pub use bookmarks::Bookmark;
+pub use callbacks::FrameEvent;
+pub use camera::{Camera, Projection};
+pub use clip::{ClipPlane, MAX_CLIP_PLANES};
+pub use compare::{CompareMode, ImageMetrics};
+pub use ground::GroundPlane;
+pub use convert::{ImportOptions, Units};
+pub use entity::{Components, Entity, Renderable, World};
+pub use inspect::{DebugRay, PixelInfo};
+pub use light::{Light, LightKind};
+pub use loader::LoadError;
+pub use material_graph::{GraphError, GraphNode, MaterialGraph, MathOp};
+pub use materialx::MaterialXError;
+pub use measure::Measurement;
+pub use outline::{MaterialInfo, MeshInfo, ObjectId, RayHit};
+pub use overlay::Overlays;
+pub use overrides::MaterialOverride;
+pub use particles::{Emitter, ParticleShape};
+pub use physics::{BodyKind, Collider, ColliderShape, PhysicsBody, PhysicsEngine, Pose, RigidBody};
+pub use plugin::{AnimationFrame, AnimationPass, FrameInfo, PluginContext, RenderPlugin, SceneBuffers};
+pub use procedural::ProceduralTexture;
+#[cfg(feature = "physics")]
+pub use rapier::RapierEngine;
+pub use remote::MidiControl;
+pub use scene::{Scene, Visibility};
+pub use script::{Script, ScriptError};
+pub use session::{Session, SessionSettings};
+pub use sky::Sky;
+pub use text::Annotation;
+pub use timeline::Timeline;
+pub use stats::{MemoryUsage, PassTiming, RenderStats, SceneStats};
+pub use stereo::{Stereo, StereoMode};
+pub use stl::{StlOptions, StlUnits};
+pub use volume::VolumeShading;
+/// The wgpu version plugins are built against.
+pub use wgpu;
+/// Window icons, see [`RayTracer::set_icon`].
+pub use winit::window::Icon;
+
+use audio::AudioDriver;
+use blit::{BlitSource, Blitter};
+use bookmarks::Bookmarks;
+use callbacks::Callbacks;
+use compare::Comparison;
+use control::ControlServer;
+#[cfg(feature = "gamepad")]
+use gamepad::Gamepads;
+use assets::{AssetSource, FileSystem};
+use ground::GroundUniform;
+use importers::{Importer, Importers};
+use remote::Remote;
+use physics::Physics;
+use renderer::Renderer;
+#[cfg(feature = "spacemouse")]
+use spacemouse::SpaceMice;
+use timeline::TimeStep;
+use title::TitleBar;
+use watch::FileWatcher;
+
 const GLTF_PATH: &str = "res/triangle.gltf";
 
+/// Change in [`Settings::explode`] per press of the minus and equals keys.
+const EXPLODE_STEP: f32 = 0.25;
+
+/// Frames spent at each resolution step while refining after interaction stops.
+const REFINE_STEP_FRAMES: u32 = 8;
+
+/// Longest time between two taps that make a double tap.
+const DOUBLE_TAP_TIME: Duration = Duration::from_millis(300);
+
+/// Farthest in logical pixels a finger can move for its touch to still count as a tap.
+const TAP_SLOP: f64 = 24.0;
+
+/// Scenes kept in the recent list, one per number key.
+const MAX_RECENT_SCENES: usize = 9;
+
+/// Imported scenes kept in memory: the shown one and the one before, enough to flip between two
+/// versions of an asset.
+const MAX_LOADED_SCENES: usize = 2;
+
+#[derive(Clone, Debug)]
 pub struct Settings {
     pub bg_color: Color,
+    /// Upper bound applied to every shaded radiance sample. `None` disables clamping.
+    pub max_radiance: Option<f32>,
+    /// GPU memory budget in bytes. `None` uses the adapter's maximum buffer size.
+    pub memory_budget: Option<u64>,
+    /// Writes a Chrome trace of the first `trace_frames` frames to this file.
+    pub trace_path: Option<PathBuf>,
+    pub trace_frames: u32,
+    /// Fraction of the full resolution rendered while the view is changing.
+    ///
+    /// The image is refined back to full resolution once interaction stops. `1.0` disables this.
+    pub interactive_quality: f32,
+    /// Restricts drawing to this region of the frame.
+    pub crop: Option<CropRect>,
+    /// Degrees the turntable turns the camera each frame.
+    pub turntable_speed: f32,
+    /// Renders separate left and right eye views when set.
+    pub stereo: Option<Stereo>,
+    /// Draws a procedural sky behind the scene instead of clearing to `bg_color`.
+    pub sky: Option<Sky>,
+    /// Image filling the frame behind the scene in place of `bg_color`, such as a photograph to
+    /// composite a product into. It hides the sky, which still places the sun.
+    pub backplate: Option<PathBuf>,
+    /// Clears to transparent black instead of `bg_color` and leaves the sky and backplate out,
+    /// so nothing but surfaces covers the output's alpha. Colours are premultiplied by it, see
+    /// [`headless::save`].
+    pub transparent_background: bool,
+    /// Puts a plane under the scene that its meshes cast shadows on.
+    pub ground: Option<GroundPlane>,
+    /// Planes cutting away part of the scene, up to [`MAX_CLIP_PLANES`]. In the viewer C cycles
+    /// the first through the axes and the bracket keys move it.
+    pub clip_planes: Vec<ClipPlane>,
+    /// Colours the inside of meshes seen through [clipping planes](Settings::clip_planes) in
+    /// this linear RGB, as if the cut were capped. The caps are the meshes' back faces, so
+    /// meshes must be closed and consistently wound.
+    pub section_caps: Option<[f32; 3]>,
+    /// Pulls the scene apart by moving each mesh away from the centre of the scene bounds by
+    /// this multiple of its own centre's distance from it, e.g. to review a CAD assembly. Zero
+    /// keeps meshes in place. Changes ease in over a few frames.
+    pub explode: f32,
+    /// Reference grid, axes and selection bounds drawn over the render.
+    pub overlays: Overlays,
+    /// Labels pinned to points in the scene, drawn over the render.
+    pub annotations: Vec<Annotation>,
+    /// Height fog and aerial perspective. `None` uses the scene's own atmosphere, if any.
+    pub atmosphere: Option<Atmosphere>,
+    /// Multiplier on the radius of point cloud splats.
+    pub splat_scale: f32,
+    /// Shading of the scene's voxel volume, if it has one.
+    pub volume_shading: VolumeShading,
+    /// Draws only objects in these render layers. Untagged objects are in the `"default"` layer.
+    pub layers: Option<Vec<String>>,
+    /// Draws every mesh in a flat colour hashed from its index, for picking objects out in
+    /// compositing.
+    pub id_matte: bool,
+    /// Name of the [`MaterialOverride`] replacing every material, built in or from the scene.
+    pub material_override: Option<String>,
+    /// Draws surfaces in an analysis view instead of their shaded colour.
+    pub analysis: Option<AnalysisView>,
+    /// Draws fragments whose colour is NaN or infinite in magenta and counts them in
+    /// [`RenderStats::non_finite_fragments`].
+    pub nan_check: bool,
+    /// Reference image laid over the viewer's view to check renders against, such as one from
+    /// before a change to the renderer. Each finished frame's RMSE and FLIP error against it are
+    /// logged.
+    pub compare: Option<PathBuf>,
+    /// How [`Settings::compare`]'s image is laid over the view. V cycles through the modes.
+    pub compare_mode: CompareMode,
+    /// Reloads the scene when a file it was read from changes on disk, such as a glTF file
+    /// re-exported from a modelling tool, its buffers and textures, or a scene layer.
+    pub hot_reload: bool,
+    /// Physical pixels per logical pixel the HUD and labels are sized in, so they keep their
+    /// size on HiDPI displays. The viewer follows its window's scale factor.
+    pub ui_scale: f32,
+    /// Fills a monitor with the viewer's window. F11 toggles borderless fullscreen, Shift+F11
+    /// exclusive fullscreen and Escape leaves it.
+    pub fullscreen: Option<FullscreenMode>,
+    /// Index of the monitor to go fullscreen on, among those the system lists. `None` uses the
+    /// one the window is on.
+    pub monitor: Option<usize>,
+    /// Flies the viewer's camera with a gamepad: the left stick moves, the right stick looks
+    /// around and the triggers speed up and slow down. Needs the `gamepad` feature.
+    pub gamepad: bool,
+    /// Moves the viewer's camera with a 3Dconnexion 3D mouse: twisting and tilting the cap
+    /// turns the scene, sliding it pans and pushing or pulling it zooms. Needs the `spacemouse`
+    /// feature.
+    pub spacemouse: bool,
+    /// Drives the parameters the scene's `extras.audio` maps to the audio of the default input
+    /// device, see [`AudioReactive`]. Needs the `audio` feature.
+    pub audio: bool,
+    /// Listens for OSC messages setting the viewer's settings and camera on this UDP address,
+    /// e.g. `127.0.0.1:9000`, or `0.0.0.0:9000` for controller apps on the network.
+    pub osc: Option<SocketAddr>,
+    /// MIDI control changes setting the viewer's settings and camera.
+    pub midi: Vec<MidiControl>,
+    /// Serves an HTTP and WebSocket API on this address for loading scenes, moving the camera,
+    /// changing settings and taking screenshots, e.g. `127.0.0.1:8090` for test harnesses. It
+    /// opens any scene file the viewer can read, so only expose it on trusted networks.
+    pub control: Option<SocketAddr>,
+    /// Saves the viewer's scene, camera, window size and view settings to this file on exit,
+    /// see [`Session`].
+    pub session: Option<PathBuf>,
+    /// Stores vertex positions in 16 bits per axis across each mesh's bounding box, taking two
+    /// thirds of the memory of full-precision positions. Scenes with more than 65536 meshes keep
+    /// full precision.
+    pub quantize_vertices: bool,
+    /// Reorders triangles after loading the scene path to speed up drawing heavy meshes, see
+    /// [`Scene::optimize_meshes`].
+    pub optimize_meshes: bool,
+    /// Welds vertices closer than this after loading the scene path, dropping degenerate
+    /// triangles and fixing winding, see [`Scene::clean_meshes`].
+    pub weld_epsilon: Option<f32>,
+    /// Units, up axis and placement of the scene path, applied after merging its layers.
+    pub import: ImportOptions,
 }
 
-pub struct State {
-    surface: Surface<'static>,
-    device: Device,
-    queue: Queue,
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            bg_color: Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+            max_radiance: None,
+            memory_budget: None,
+            trace_path: None,
+            trace_frames: 120,
+            interactive_quality: 0.5,
+            crop: None,
+            turntable_speed: 0.5,
+            stereo: None,
+            sky: None,
+            backplate: None,
+            transparent_background: false,
+            ground: None,
+            clip_planes: Vec::new(),
+            section_caps: None,
+            explode: 0.0,
+            overlays: Overlays::default(),
+            annotations: Vec::new(),
+            atmosphere: None,
+            splat_scale: 1.0,
+            volume_shading: VolumeShading::default(),
+            layers: None,
+            id_matte: false,
+            material_override: None,
+            analysis: None,
+            nan_check: false,
+            compare: None,
+            compare_mode: CompareMode::default(),
+            hot_reload: false,
+            ui_scale: 1.0,
+            fullscreen: None,
+            monitor: None,
+            gamepad: false,
+            spacemouse: false,
+            audio: false,
+            osc: None,
+            midi: Vec::new(),
+            control: None,
+            session: None,
+            quantize_vertices: false,
+            optimize_meshes: false,
+            weld_epsilon: None,
+            import: ImportOptions::default(),
+        }
+    }
+}
+
+/// How the viewer's window fills a monitor.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FullscreenMode {
+    /// A borderless window covering the monitor, quick to switch in and out of.
+    #[default]
+    Borderless,
+    /// Takes the monitor over at its largest video mode, falling back to borderless where
+    /// that isn't possible, e.g. on Wayland.
+    Exclusive,
+}
+
+impl FromStr for FullscreenMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "borderless" => Ok(FullscreenMode::Borderless),
+            "exclusive" => Ok(FullscreenMode::Exclusive),
+            _ => Err(format!("unknown fullscreen mode {s:?}, expected borderless or exclusive")),
+        }
+    }
+}
+
+/// A rectangle of the frame in normalized coordinates, with the origin at the top left.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CropRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl CropRect {
+    /// Builds the rectangle spanned by two corners given in pixels of a `size` frame.
+    fn from_corners(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>, size: PhysicalSize<u32>) -> Self {
+        let (width, height) = (size.width as f64, size.height as f64);
+        Self {
+            x: (a.x.min(b.x) / width) as f32,
+            y: (a.y.min(b.y) / height) as f32,
+            width: ((a.x - b.x).abs() / width) as f32,
+            height: ((a.y - b.y).abs() / height) as f32,
+        }
+    }
+
+    /// Pixel bounds `(x, y, width, height)` of the rectangle in a `width` by `height` target.
+    ///
+    /// The result is clamped to the target and is never empty.
+    pub(crate) fn to_pixels(self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let x = ((self.x.clamp(0.0, 1.0) * width as f32) as u32).min(width - 1);
+        let y = ((self.y.clamp(0.0, 1.0) * height as f32) as u32).min(height - 1);
+        let w = ((self.width.max(0.0) * width as f32).round() as u32).clamp(1, width - x);
+        let h = ((self.height.max(0.0) * height as f32).round() as u32).clamp(1, height - y);
+        (x, y, w, h)
+    }
+}
+
+impl FromStr for CropRect {
+    type Err = String;
+
+    /// Parses `x,y,width,height` in normalized coordinates.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values: Vec<f32> = s.split(',')
+            .map(|value| value.trim().parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|err| format!("invalid crop rectangle {s:?}: {err}"))?;
+        let [x, y, width, height] = values[..] else {
+            return Err(format!("crop rectangle {s:?} needs four values: x,y,width,height"));
+        };
+        Ok(Self { x, y, width, height })
+    }
+}
+
+/// A window frames are presented to, with its surface.
+struct WindowSurface {
+    /// `None` for a window of the host application, see [`State::from_raw_handle`].
+    window: Option<Arc<Window>>,
+    /// `None` while the app is suspended, when mobile platforms take the window's surface away.
+    surface: Option<Surface<'static>>,
     config: SurfaceConfiguration,
+    /// Instance the surface is recreated with on resuming.
+    instance: Instance,
+}
+
+/// A renderer with its viewer state: the camera, interaction and progressive refinement.
+pub struct State {
+    /// `None` when drawing into textures of the host application, see [`State::from_device`].
+    window: Option<WindowSurface>,
     size: PhysicalSize<u32>,
-    window: Arc<Window>,
-    render_pipeline: RenderPipeline,
-    vertex_buffer: Buffer,
-    material_bind_group: BindGroup,
-    num_vertices: u32,
-    settings: Settings,
+    /// Format of the frames drawn.
+    format: TextureFormat,
+    renderer: Renderer,
+    blitter: Blitter,
+    low_res: Option<BlitSource>,
+    /// Reference image laid over the view, see [`Settings::compare`].
+    comparison: Option<Comparison>,
+    frames_since_interaction: u32,
+    /// Frames drawn since the window opened.
+    frame: u64,
+    /// Whether the image has been drawn at full resolution since the last interaction.
+    finished: bool,
+    cursor: PhysicalPosition<f64>,
+    modifiers: ModifiersState,
+    /// Where a shift-drag crop selection started.
+    crop_start: Option<PhysicalPosition<f64>>,
+    /// Camera the turntable started from and the angle turned since.
+    turntable: Option<(Camera, f32)>,
+    /// Logs what is under the cursor whenever it moves.
+    inspecting: bool,
+    /// Clicks pick the ends of measurements.
+    measuring: bool,
+    /// First end of the measurement being picked.
+    measure_start: Option<Vec3>,
+    /// Fingers on the view by id, where each was last seen.
+    touches: Vec<(u64, PhysicalPosition<f64>)>,
+    /// When and where the last tap started, while it may still become a double tap.
+    last_tap: Option<(Instant, PhysicalPosition<f64>)>,
+    timeline: Timeline,
 }
 
-#[derive(Default)]
 pub struct RayTracer {
     state: Option<State>,
+    settings: Settings,
+    /// Camera to start from, or `None` for the scene's first camera.
+    camera: Option<Camera>,
+    scene_path: PathBuf,
+    /// Scene given directly, shown instead of loading `scene_path`.
+    scene: Option<Scene>,
+    /// Files merged over the loaded scene in order, see [`Scene::merge`].
+    scene_layers: Vec<PathBuf>,
+    /// Watches the files of a scene loaded from `scene_path` when hot reloading.
+    watcher: Option<FileWatcher>,
+    /// Readers `scene_path` is loaded with.
+    importers: Importers,
+    /// Where `scene_path`, its layers and the files they refer to are read from.
+    assets: Box<dyn AssetSource>,
+    bookmarks: Bookmarks,
+    /// Plugins added before the window opened, handed to the renderer once it exists.
+    plugins: Vec<Box<dyn RenderPlugin>>,
+    /// Animation passes added before the window opened, handed over like `plugins`.
+    animations: Vec<Box<dyn AnimationPass>>,
+    callbacks: Callbacks,
+    /// Sets up the scene once it is loaded, then runs before every frame.
+    script: Option<Script>,
+    /// Engine moving the scene's rigid bodies every frame, `RapierEngine` in builds with the
+    /// `physics` feature.
+    physics: Option<Physics>,
+    /// Clock of the animation passes, particles, physics, turntable and script.
+    timeline: Timeline,
+    /// Pads flying the camera, see [`Settings::gamepad`].
+    #[cfg(feature = "gamepad")]
+    gamepads: Option<Gamepads>,
+    /// 3D mice moving the camera, see [`Settings::spacemouse`].
+    #[cfg(feature = "spacemouse")]
+    space_mice: Option<SpaceMice>,
+    /// Audio driving scene parameters, see [`Settings::audio`].
+    audio: Option<AudioDriver>,
+    /// OSC and MIDI listeners, see [`Settings::osc`] and [`Settings::midi`].
+    remote: Option<Remote>,
+    /// Automation API, see [`Settings::control`].
+    control: Option<ControlServer>,
+    title: TitleBar,
+    /// Icon of the window, or `None` for the default one.
+    icon: Option<Icon>,
+    /// Inner size the window opens with, or `None` for the platform's default.
+    window_size: Option<PhysicalSize<u32>>,
+    /// Scene paths opened, most recent first.
+    recent_scenes: Vec<PathBuf>,
+    /// Scenes imported most recently, most recent first.
+    loaded_scenes: Vec<LoadedScene>,
+}
+
+/// A scene as imported from its path and layers, kept to switch back to without reading it
+/// again.
+struct LoadedScene {
+    path: PathBuf,
+    layers: Vec<PathBuf>,
+    scene: Scene,
+    /// Notices when the files the scene was read from change.
+    watcher: FileWatcher,
+}
+
+impl Default for RayTracer {
+    fn default() -> Self {
+        Self::new(Settings::default())
+    }
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vec3 {
-    x: f32,
-    y: f32,
-    z: f32,
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
 }
 
-macro_rules! vec3 {
-    [$x:expr, $y:expr, $z:expr] => {
-        Vec3 { x: $x, y: $y, z: $z }
-    };
+impl Vec3 {
+    fn min(a: Self, b: Self) -> Self {
+        vec3![a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)]
+    }
+
+    fn max(a: Self, b: Self) -> Self {
+        vec3![a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)]
+    }
+
+    fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn cross(self, other: Self) -> Self {
+        vec3![
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x
+        ]
+    }
+
+    fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    fn normalize(self) -> Self {
+        self * (1.0 / self.length())
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        vec3![self.x + other.x, self.y + other.y, self.z + other.z]
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        vec3![self.x - other.x, self.y - other.y, self.z - other.z]
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, scale: f32) -> Self {
+        vec3![self.x * scale, self.y * scale, self.z * scale]
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from(v: [f32; 3]) -> Self {
+        vec3![v[0], v[1], v[2]]
+    }
 }
 
 #[repr(C)]
@@ -121,6 +656,77 @@ struct Material {
     specular: [f32; 4],
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SettingsUniform {
+    max_radiance: f32,
+    splat_scale: f32,
+    /// Checkerboard cells per scene unit from the material override.
+    checker: f32,
+    _padding: f32,
+    /// Atmosphere extinction per channel, with the height falloff in w. Zero disables fog.
+    extinction: [f32; 4],
+    /// Atmosphere scattered colour, with the sun glow strength in w.
+    inscatter: [f32; 4],
+    /// Direction towards the sun.
+    sun: [f32; 4],
+    /// Placement of the ground plane, filled in once the scene bounds are known.
+    ground: GroundUniform,
+    clip_planes: [[f32; 4]; MAX_CLIP_PLANES],
+    /// Section cap colour.
+    section_caps: [f32; 4],
+}
+
+impl From<&Settings> for SettingsUniform {
+    fn from(settings: &Settings) -> Self {
+        let atmosphere = settings.atmosphere.unwrap_or(Atmosphere {
+            density: 0.0,
+            ..Atmosphere::default()
+        });
+        let [r, g, b] = atmosphere.extinction();
+        let [sun_x, sun_y, sun_z] = settings.sky.map_or([0.0; 3], |sky| sky.sun_direction());
+        let sun_glow = if settings.sky.is_some() { atmosphere.sun_glow } else { 0.0 };
+        Self {
+            max_radiance: settings.max_radiance.unwrap_or(f32::MAX),
+            splat_scale: settings.splat_scale,
+            checker: 0.0,
+            _padding: 0.0,
+            extinction: [r, g, b, atmosphere.falloff],
+            inscatter: [atmosphere.color[0], atmosphere.color[1], atmosphere.color[2], sun_glow],
+            sun: [sun_x, sun_y, sun_z, 0.0],
+            ground: GroundUniform::default(),
+            clip_planes: clip::uniforms(&settings.clip_planes),
+            section_caps: match settings.section_caps {
+                Some([r, g, b]) => [r, g, b, 1.0],
+                None => [0.0; 4],
+            },
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraUniform {
+    view: camera::Mat4,
+    proj: camera::Mat4,
+    projection: u32,
+    fov: f32,
+    aspect: f32,
+    znear: f32,
+    zfar: f32,
+    _padding: [f32; 3],
+}
+
+impl CameraUniform {
+    /// Shifts the image by `x` and `y` in normalized device coordinates.
+    fn offset(&mut self, x: f32, y: f32) {
+        for column in &mut self.proj {
+            column[0] += x * column[3];
+            column[1] += y * column[3];
+        }
+    }
+}
+
 trait Desc {
     const ATTRIBS: [VertexAttribute; 1];
     fn desc() -> VertexBufferLayout<'static>;
@@ -139,37 +745,73 @@ impl Desc for Vec3 {
 }
 
 impl State {
-    fn new(window: Arc<Window>, vertices: Vec<Vec3>, materials: Vec<Material>) -> Self {
+    fn new(window: Arc<Window>, scene: Scene, mut settings: Settings) -> Self {
         let size = window.inner_size();
+        settings.ui_scale = window.scale_factor() as f32;
+        let instance = Self::instance();
+        let surface = instance.create_surface(window.clone()).unwrap();
+        Self::on_surface(instance, surface, Some(window), size, scene, settings)
+    }
+
+    /// Presents to a window the host application owns, such as a Qt or GTK widget or an editor
+    /// panel, instead of one the viewer opens. Draw frames with [`State::present`] and pass
+    /// size changes to [`State::resize`].
+    ///
+    /// # Safety
+    ///
+    /// The handles must be valid to create a surface on and stay valid until the state is
+    /// dropped.
+    pub unsafe fn from_raw_handle(
+        display: wgpu::rwh::RawDisplayHandle,
+        window: wgpu::rwh::RawWindowHandle,
+        width: u32,
+        height: u32,
+        scene: Scene,
+        settings: Settings,
+    ) -> Result<Self, CreateSurfaceError> {
+        let instance = Self::instance();
+        let surface = unsafe {
+            instance.create_surface_unsafe(SurfaceTargetUnsafe::RawHandle {
+                raw_display_handle: display,
+                raw_window_handle: window,
+            })?
+        };
+        let camera = scene.default_camera();
+        let mut state = Self::on_surface(instance, surface, None, PhysicalSize::new(width, height), scene, settings);
+        state.resize(PhysicalSize::new(width, height));
+        state.set_camera(camera);
+        Ok(state)
+    }
 
+    fn instance() -> Instance {
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = Instance::new(&InstanceDescriptor {
+        Instance::new(&InstanceDescriptor {
             #[cfg(not(target_arch = "wasm32"))]
             backends: Backends::PRIMARY,
             #[cfg(target_arch = "wasm32")]
             backends: Backends::BROWSER_WEBGPU,
             ..Default::default()
-        });
-
-        let surface = instance.create_surface(window.clone()).unwrap();
+        })
+    }
 
+    /// Sets up a device that can present to `surface`, which is configured once the size is
+    /// known.
+    fn on_surface(
+        instance: Instance,
+        surface: Surface<'static>,
+        window: Option<Arc<Window>>,
+        size: PhysicalSize<u32>,
+        scene: Scene,
+        settings: Settings,
+    ) -> Self {
         let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
             power_preference: PowerPreference::default(),
             compatible_surface: Some(&surface),
             force_fallback_adapter: false,
         })).unwrap();
 
-        let (device, queue) = block_on(adapter.request_device(&DeviceDescriptor {
-            required_features: Features::empty(),
-            required_limits: if cfg!(target_arch = "wasm32") {
-                Limits::downlevel_webgl2_defaults()
-            } else {
-                Limits::default()
-            },
-            label: None,
-            memory_hints: Default::default(),
-        }, None)).unwrap();
+        let (device, queue) = renderer::request_device(&adapter);
 
         let surface_caps = surface.get_capabilities(&adapter);
 
@@ -189,255 +831,1553 @@ impl State {
             desired_maximum_frame_latency: 2,
         };
 
-        let shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
-
-        let material_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Material buffer"),
-            contents: bytemuck::cast_slice(&materials),
-            usage: BufferUsages::STORAGE,
-        });
+        let renderer = Renderer::new(
+            device,
+            queue,
+            config.format,
+            adapter.limits().max_buffer_size,
+            scene,
+            settings,
+        );
 
-        let material_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Storage {
-                        read_only: true
-                    },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: Some("material_bind_group_layout"),
-        });
+        let format = config.format;
+        let surface = Some(surface);
+        Self::with_renderer(renderer, format, size, Some(WindowSurface { window, surface, config, instance }))
+    }
 
-        let material_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            layout: &material_bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: material_buffer.as_entire_binding(),
-            }],
-            label: Some("material_bind_group"),
-        });
+    /// Draws on a device the host application already owns into the texture views it passes to
+    /// [`State::render_to`], so the tracer can be one view inside a larger app.
+    ///
+    /// The views must be `width` by `height` until [`State::resize`] and of `format`. The
+    /// device should have been requested with the adapter's `MULTI_DRAW_INDIRECT` and
+    /// `TIMESTAMP_QUERY` features where available, which speed up drawing and profiling.
+    pub fn from_device(
+        device: Device,
+        queue: Queue,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        scene: Scene,
+        settings: Settings,
+    ) -> Self {
+        let camera = scene.default_camera();
+        let max_buffer_size = device.limits().max_buffer_size;
+        let renderer = Renderer::new(device, queue, format, max_buffer_size, scene, settings);
+        let mut state = Self::with_renderer(renderer, format, PhysicalSize::new(width.max(1), height.max(1)), None);
+        state.set_camera(camera);
+        state
+    }
 
-        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&material_bind_group_layout],
-            push_constant_ranges: &[],
-        });
+    fn with_renderer(
+        renderer: Renderer,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+        window: Option<WindowSurface>,
+    ) -> Self {
+        let blitter = Blitter::new(&renderer.device, format);
+        let mut state = Self {
+            window,
+            size,
+            format,
+            renderer,
+            blitter,
+            low_res: None,
+            comparison: None,
+            frames_since_interaction: 0,
+            frame: 0,
+            finished: false,
+            cursor: PhysicalPosition::new(0.0, 0.0),
+            modifiers: ModifiersState::empty(),
+            crop_start: None,
+            turntable: None,
+            inspecting: false,
+            measuring: false,
+            measure_start: None,
+            touches: Vec::new(),
+            last_tap: None,
+            timeline: Timeline::default(),
+        };
+        if let Some(path) = state.renderer.settings.compare.clone()
+            && let Err(err) = state.compare_with(Some(&path))
+        {
+            log::warn!("Ignoring reference image {}: {err}", path.display());
+        }
+        state
+    }
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[
-                    Vec3::desc(),
-                ],
-                compilation_options: PipelineCompilationOptions::default(),
-            },
-            fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(ColorTargetState {
-                    format: config.format,
-                    blend: Some(BlendState::ALPHA_BLENDING),
-                    write_mask: ColorWrites::ALL,
-                })],
-                compilation_options: PipelineCompilationOptions::default(),
-            }),
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                polygon_mode: PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+    /// Changes the size frames are drawn at. Empty sizes, as of a minimized window, are
+    /// ignored.
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            if let Some(WindowSurface { surface, config, .. }) = &mut self.window {
+                config.width = new_size.width;
+                config.height = new_size.height;
+                if let Some(surface) = surface {
+                    surface.configure(&self.renderer.device, config);
+                }
+            }
+        }
+    }
 
-        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vector buffer"),
-            contents: bytemuck::cast_slice(&vertices),
-            usage: BufferUsages::VERTEX,
-        });
+    /// Size frames are drawn at, in physical pixels, which picking and inspecting take too.
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
 
-        let num_vertices = vertices.len() as u32;
+    /// Size frames are drawn at, in the logical pixels of the display's scale factor.
+    pub fn logical_size(&self) -> LogicalSize<f64> {
+        self.size.to_logical(self.renderer.settings.ui_scale as f64)
+    }
 
-        let settings = Settings {
-            bg_color: Color {
-                r: 0.1,
-                g: 0.2,
-                b: 0.3,
-                a: 1.0,
-            },
-        };
+    /// Sizes the HUD and labels for a display with `scale_factor` physical pixels per logical
+    /// pixel. Frames stay drawn at the full physical size.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.renderer.settings.ui_scale = scale_factor as f32;
+    }
 
-        Self {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-            window,
-            render_pipeline,
-            vertex_buffer,
-            material_bind_group,
-            num_vertices,
-            settings
+    /// Drops the window's surface, which mobile platforms destroy when the app goes to the
+    /// background. Nothing is drawn until [`State::resume`].
+    fn suspend(&mut self) {
+        if let Some(window) = &mut self.window {
+            window.surface = None;
         }
     }
 
-    fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-        }
+    /// Recreates the window's surface after [`State::suspend`], at the window's current size.
+    fn resume(&mut self) {
+        let Some(WindowSurface { window: Some(window), surface: surface @ None, instance, .. }) = &mut self.window else {
+            return;
+        };
+        let size = window.inner_size();
+        *surface = Some(instance.create_surface(window.clone()).unwrap());
+        // The new surface is configured even if the size stayed the same.
+        self.resize(if size.width > 0 && size.height > 0 { size } else { self.size });
     }
 
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        match event {
+    /// Whether frames can be drawn, which they can't while the app is suspended.
+    fn can_draw(&self) -> bool {
+        self.window.as_ref().is_none_or(|window| window.surface.is_some())
+    }
+
+    /// Handles a mouse, keyboard or touch event over the view, returning whether it changed
+    /// what is drawn.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let changed = match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.modifiers.shift_key() => {
+                self.crop_start = Some(self.cursor);
+                true
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.modifiers.alt_key() => {
+                let (x, y) = self.cursor_pixel();
+                let ray = self.renderer.capture_ray(x, y, self.size.width, self.size.height);
+                log::info!("Captured ray {ray:?}");
+                true
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.measuring => {
+                let (x, y) = self.cursor_pixel();
+                match (self.measure_start, self.renderer.pick(x, y, self.size.width, self.size.height)) {
+                    (_, None) => log::info!("Nothing to measure to at ({x}, {y})"),
+                    (None, Some(start)) => {
+                        log::info!("Measuring from ({:.4}, {:.4}, {:.4})", start.x, start.y, start.z);
+                        self.measure_start = Some(start);
+                    }
+                    (Some(start), Some(end)) => {
+                        let measurement = Measurement { start, end };
+                        log::info!("{measurement}");
+                        self.renderer.add_measurement(measurement);
+                        self.measure_start = None;
+                    }
+                }
+                true
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.renderer.settings.overlays.bounds || self.renderer.settings.overlays.labels => {
+                // Ctrl-click adds or removes the mesh under the cursor, a plain click selects
+                // only it, or nothing over the background.
+                let mut selection = match self.modifiers.control_key() {
+                    true => self.renderer.selection().to_vec(),
+                    false => vec![],
+                };
+                if let Some(mesh) = self.inspect().mesh {
+                    match selection.iter().position(|&index| index == mesh) {
+                        Some(position) => {
+                            selection.remove(position);
+                        }
+                        None => selection.push(mesh),
+                    }
+                }
+                self.renderer.select(selection);
+                true
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } if self.crop_start.is_some() => {
+                let start = self.crop_start.take().unwrap();
+                let crop = CropRect::from_corners(start, self.cursor, self.size);
+                // A shift-click without dragging clears the crop.
+                self.renderer.settings.crop = (crop.width > 0.0 && crop.height > 0.0).then_some(crop);
+                true
+            }
             WindowEvent::CursorMoved {
                 device_id: _,
                 position
             } => {
-                self.settings.bg_color.r = position.x / self.size.width as f64;
-                self.settings.bg_color.g = position.y / self.size.height as f64;
+                self.cursor = *position;
+                if let Some(start) = self.crop_start {
+                    self.renderer.settings.crop = Some(CropRect::from_corners(start, *position, self.size));
+                } else if self.inspecting {
+                    log::info!("{}", self.inspect());
+                } else {
+                    self.renderer.settings.bg_color.r = position.x / self.size.width as f64;
+                    self.renderer.settings.bg_color.g = position.y / self.size.height as f64;
+                }
                 true
             }
+            WindowEvent::Touch(Touch { id, phase, location, .. }) => self.touch(*id, *phase, *location),
             _ => false,
+        };
+        if changed {
+            self.frames_since_interaction = 0;
+            self.finished = false;
+        }
+        changed
+    }
+
+    /// Follows a finger: dragging one orbits the camera around its target, dragging two pans
+    /// and pinching them zooms, and double-tapping frames the scene. Returns whether the
+    /// camera moved.
+    fn touch(&mut self, id: u64, phase: TouchPhase, location: PhysicalPosition<f64>) -> bool {
+        let finger = self.touches.iter().position(|&(finger, _)| finger == id);
+        let slop = TAP_SLOP * self.renderer.settings.ui_scale as f64;
+        let near = |a: PhysicalPosition<f64>, b: PhysicalPosition<f64>| (a.x - b.x).hypot(a.y - b.y) <= slop;
+        let (finger, previous) = match (phase, finger) {
+            (TouchPhase::Started, _) => {
+                self.touches.push((id, location));
+                if self.touches.len() > 1 {
+                    self.last_tap = None;
+                    return false;
+                }
+                let now = Instant::now();
+                match self.last_tap.take() {
+                    Some((time, start)) if now - time <= DOUBLE_TAP_TIME && near(start, location) => {
+                        let stats = &self.renderer.scene_stats;
+                        let camera = self.renderer.camera.frame(stats.min, stats.max);
+                        self.set_camera(camera);
+                        return true;
+                    }
+                    _ => self.last_tap = Some((now, location)),
+                }
+                return false;
+            }
+            (TouchPhase::Ended | TouchPhase::Cancelled, Some(finger)) => {
+                self.touches.remove(finger);
+                return false;
+            }
+            (TouchPhase::Moved, Some(finger)) => (finger, self.touches[finger].1),
+            _ => return false,
+        };
+        self.touches[finger].1 = location;
+        if self.last_tap.is_some_and(|(_, start)| !near(start, location)) {
+            self.last_tap = None;
         }
+        let camera = self.renderer.camera;
+        let camera = match self.touches[..] {
+            [_] => {
+                // A drag across the whole view turns half way round.
+                let degrees = |pixels: f64, size: u32| (pixels / size as f64 * 180.0) as f32;
+                camera.orbit(camera.target, -degrees(location.x - previous.x, self.size.width))
+                    .tilt(camera.target, degrees(location.y - previous.y, self.size.height))
+            }
+            [(_, a), (_, b)] => {
+                let other = if finger == 0 { b } else { a };
+                let distance = |p: PhysicalPosition<f64>| (p.x - other.x).hypot(p.y - other.y).max(1.0);
+                // The point between the fingers moves half as far as the finger and drags the
+                // scene with it.
+                let height = self.size.height as f64;
+                let x = (previous.x - location.x) / 2.0 / height;
+                let y = (location.y - previous.y) / 2.0 / height;
+                camera.pan(x as f32, y as f32).dolly((distance(previous) / distance(location)) as f32)
+            }
+            _ => return false,
+        };
+        self.set_camera(camera);
+        true
     }
 
-    fn update(&mut self) {
-        // Update the state of the application
+    /// Resolution scale for this frame: reduced while interacting, then doubled every few frames.
+    fn render_scale(&self) -> f32 {
+        let steps = (self.frames_since_interaction / REFINE_STEP_FRAMES).min(16);
+        (self.renderer.settings.interactive_quality * (1 << steps) as f32).min(1.0)
     }
 
-    fn render(&mut self) -> Result<(), SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&TextureViewDescriptor::default());
-        let mut encoder = self.device.create_command_encoder(&CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
-        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[Some(RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: Operations {
-                    load: LoadOp::Clear(self.settings.bg_color),
-                    store: StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            occlusion_query_set: None,
-            timestamp_writes: None,
-        });
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.material_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.draw(0..self.num_vertices, 0..1);
-        drop(render_pass);
-        self.queue.submit(iter::once(encoder.finish()));
-        output.present();
+    pub fn camera(&self) -> Camera {
+        self.renderer.camera
+    }
 
-        Ok(())
+    /// Moves the camera, stopping the turntable and restarting refinement.
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.renderer.camera = camera;
+        self.turntable = None;
+        self.frames_since_interaction = 0;
+        self.finished = false;
     }
-}
 
-impl ApplicationHandler for RayTracer {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window = Arc::new(event_loop.create_window(Window::default_attributes()).unwrap());
-        let (doc, buffers, _) = gltf::import(GLTF_PATH).unwrap();
-        let mut vertices = vec![];
-        let mut materials = vec![];
-        for mesh in doc.meshes() {
-            for primitive in mesh.primitives() {
-                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-                if let Some(positions) = reader.read_positions() {
-                    let corners: Vec<[f32; 3]> = positions.collect();
-                    for vertex in corners {
-                        vertices.push(vec3![vertex[0], vertex[1], vertex[2]]);
-                    }
-                }
-            }
-        }
-        for material in doc.materials() {
-            let material = material.pbr_metallic_roughness();
-            let base_color = material.base_color_factor();
-            materials.push(Material {
-                ambient: [base_color[0], base_color[1], base_color[2], base_color[3]],
-                diffuse: [0.0, 0.0, 0.0, 0.0],
-                specular: [0.0, 0.0, 0.0, 0.0],
-            });
-        }
-        self.state = Some(State::new(window, vertices, materials));
+    fn toggle_turntable(&mut self) {
+        self.turntable = match self.turntable {
+            Some(_) => None,
+            None => Some((self.renderer.camera, 0.0)),
+        };
     }
 
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
-        if self.get_state().input(&event) {
-            return;
-        }
-        match event {
-            WindowEvent::CloseRequested => {
-                println!("The close button was pressed; stopping");
-                event_loop.exit();
-            },
-            WindowEvent::RedrawRequested => {
-                if self.state.is_none() {
-                    return;
-                }
-                self.get_window().request_redraw();
-                let state = self.get_state();
-                state.update();
-                match state.render() {
-                    Ok(_) => (),
-                    Err(SurfaceError::Lost | SurfaceError::Outdated) => {
-                        state.resize(state.size);
-                    },
-                    Err(SurfaceError::OutOfMemory | SurfaceError::Other) => {
-                        log::error!("OutOfMemory");
-                        event_loop.exit();
-                    },
-                    Err(SurfaceError::Timeout) => {
-                        log::warn!("Surface timeout");
-                    }
+    /// Cuts the scene through its centre across X, then Y, then Z, then not at all, replacing
+    /// the first clipping plane.
+    fn cycle_clip_plane(&mut self) {
+        let stats = &self.renderer.scene_stats;
+        let planes = &mut self.renderer.settings.clip_planes;
+        let axes = [vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], vec3![0.0, 0.0, 1.0]];
+        let next = match planes.first() {
+            None => Some(axes[0]),
+            Some(plane) => axes.iter()
+                .position(|&axis| plane.normal == axis)
+                .and_then(|axis| axes.get(axis + 1).copied()),
+        };
+        match next {
+            Some(normal) => {
+                let plane = ClipPlane { point: stats.center(), normal };
+                match planes.first_mut() {
+                    Some(first) => *first = plane,
+                    None => planes.push(plane),
                 }
             }
-            WindowEvent::Resized(physical_size) => {
-                self.get_state().resize(physical_size);
+            None => {
+                planes.remove(0);
             }
-            _ => (),
         }
     }
-}
 
-impl RayTracer {
-    pub fn run(&mut self) -> Result<(), EventLoopError> {
-        let event_loop = EventLoop::new().unwrap();
-        //event_loop.set_control_flow(ControlFlow::Poll);
-        event_loop.run_app(self)
+    /// Moves the first clipping plane along its normal by `steps` fiftieths of the scene's
+    /// diagonal.
+    fn move_clip_plane(&mut self, steps: f32) {
+        let stats = &self.renderer.scene_stats;
+        let step = (stats.max - stats.min).length() / 50.0;
+        if let Some(plane) = self.renderer.settings.clip_planes.first_mut() {
+            plane.point = plane.point + plane.normal.normalize() * (step * steps);
+        }
     }
 
-    pub fn get_window(&self) -> Arc<Window> {
-        self.state.as_ref().unwrap().window.clone()
+    /// Moves the timeline on and the turntable and renderer with it.
+    fn update(&mut self) -> TimeStep {
+        let time = self.timeline.tick();
+        if let Some((camera, angle)) = &mut self.turntable {
+            *angle = (*angle + self.renderer.settings.turntable_speed * time.speed) % 360.0;
+            self.renderer.camera = camera.orbit(self.renderer.scene_stats.center(), *angle);
+        }
+        self.renderer.advance(time);
+        time
+    }
+
+    /// Play, pause and seek controls over animation, particles, physics and the turntable.
+    pub fn timeline_mut(&mut self) -> &mut Timeline {
+        &mut self.timeline
+    }
+
+    /// Steps the timeline one frame back or forward and pauses it there.
+    fn step_timeline(&mut self, frames: f32) {
+        self.timeline.pause();
+        let time = self.timeline.time() + frames * timeline::FRAME_STEP;
+        self.timeline.seek(time);
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        self.renderer.stats()
+    }
+
+    /// Lays the image at `reference` over the view to compare renders with, see
+    /// [`Settings::compare`], or stops comparing with `None`.
+    pub fn compare_with(&mut self, reference: Option<&Path>) -> ImageResult<()> {
+        self.comparison = match reference {
+            Some(path) => Some(Comparison::load(path, &self.renderer.device, &self.renderer.queue, &self.blitter)?),
+            None => None,
+        };
+        self.renderer.settings.compare = reference.map(Path::to_owned);
+        if self.finished {
+            self.measure_comparison();
+        }
+        Ok(())
+    }
+
+    /// How far the last finished frame is from the reference image, or `None` while there is
+    /// none or it is still being measured.
+    pub fn comparison_metrics(&self) -> Option<ImageMetrics> {
+        self.comparison.as_ref().and_then(Comparison::metrics)
+    }
+
+    /// Starts measuring the finished frame against the reference image, if there is one.
+    fn measure_comparison(&mut self) {
+        if self.comparison.is_none() {
+            return;
+        }
+        let image = headless::straight_alpha(&self.capture());
+        self.comparison.as_mut().unwrap().measure(image);
+    }
+
+    /// Settings of the renderer, applied from the next frame.
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.renderer.settings
+    }
+
+    /// Reports what is under the cursor.
+    pub fn inspect(&self) -> PixelInfo {
+        let (x, y) = self.cursor_pixel();
+        self.renderer.inspect(x, y, self.size.width, self.size.height)
+    }
+
+    fn cursor_pixel(&self) -> (u32, u32) {
+        let x = (self.cursor.x.max(0.0) as u32).min(self.size.width - 1);
+        let y = (self.cursor.y.max(0.0) as u32).min(self.size.height - 1);
+        (x, y)
+    }
+
+    fn render(&mut self) -> Result<(), SurfaceError> {
+        let frame_start = self.renderer.clock();
+        let surface = self.window.as_ref().expect("a state drawing into textures has no surface")
+            .surface.as_ref().expect("the app is suspended");
+        let output = surface.get_current_texture()?;
+        let view = output.texture.create_view(&TextureViewDescriptor::default());
+        self.draw(&view, frame_start);
+        output.present();
+
+        Ok(())
+    }
+
+    /// Advances the timeline and turntable and draws the next frame to the window, for a state made with
+    /// [`State::from_raw_handle`].
+    pub fn present(&mut self) -> Result<(), SurfaceError> {
+        self.update();
+        self.frame += 1;
+        self.render()
+    }
+
+    /// Advances the timeline and turntable and draws the next frame into `view`, for a state made with
+    /// [`State::from_device`]. The frame's work has been submitted to the queue when this
+    /// returns.
+    pub fn render_to(&mut self, view: &TextureView) {
+        let frame_start = self.renderer.clock();
+        self.update();
+        self.frame += 1;
+        self.draw(view, frame_start);
+    }
+
+    /// Draws the current view at full resolution offscreen and reads it back, in sRGB with
+    /// premultiplied alpha.
+    pub fn capture(&mut self) -> RgbaImage {
+        let (width, height) = (self.size.width, self.size.height);
+        let texture = self.renderer.device.create_texture(&TextureDescriptor {
+            label: Some("Capture target"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: self.format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        self.renderer.render(&texture.create_view(&TextureViewDescriptor::default()), width, height, None);
+        let mut image = headless::read_back(&self.renderer, &texture);
+        if matches!(self.format, TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb) {
+            for pixel in image.pixels_mut() {
+                pixel.0.swap(0, 2);
+            }
+        }
+        image
+    }
+
+    /// Draws a frame into `view`, at reduced resolution while interacting.
+    fn draw(&mut self, view: &TextureView, frame_start: Option<Instant>) {
+        let scale = self.render_scale();
+        if scale < 1.0 {
+            let width = ((self.size.width as f32 * scale) as u32).max(1);
+            let height = ((self.size.height as f32 * scale) as u32).max(1);
+            if self.low_res.as_ref().is_none_or(|source| (source.width, source.height) != (width, height)) {
+                self.low_res = Some(self.blitter.source(&self.renderer.device, width, height));
+            }
+            let low_res = self.low_res.as_ref().unwrap();
+            self.renderer.render(&low_res.view, width, height, frame_start);
+            let mut encoder = self.renderer.device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Blit Encoder"),
+            });
+            self.blitter.blit(&mut encoder, low_res, view);
+            self.renderer.queue.submit(iter::once(encoder.finish()));
+        } else {
+            self.low_res = None;
+            self.renderer.render(view, self.size.width, self.size.height, frame_start);
+        }
+        if let Some(comparison) = &mut self.comparison {
+            if !self.finished {
+                comparison.invalidate();
+            }
+            if let Some(metrics) = comparison.poll(&self.renderer.device, &self.renderer.queue, &self.blitter) {
+                log::info!("{metrics}");
+            }
+            let mut encoder = self.renderer.device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Comparison Encoder"),
+            });
+            let wipe = self.cursor.x.max(0.0) as u32;
+            let size = (self.size.width, self.size.height);
+            comparison.draw(&mut encoder, &self.blitter, view, size, self.renderer.settings.compare_mode, wipe);
+            self.renderer.queue.submit(iter::once(encoder.finish()));
+        }
+        self.frames_since_interaction = self.frames_since_interaction.saturating_add(1);
+    }
+}
+
+impl ApplicationHandler for RayTracer {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        // Mobile platforms resume apps they sent to the background with a new surface.
+        if let Some(state) = &mut self.state {
+            state.resume();
+            self.get_window().request_redraw();
+            return;
+        }
+        let attributes = Window::default_attributes()
+            .with_title(title::scene_name(&self.scene_path))
+            .with_window_icon(Some(self.icon.clone().unwrap_or_else(title::default_icon)));
+        let attributes = match self.window_size {
+            Some(size) => attributes.with_inner_size(size),
+            None => attributes,
+        };
+        let window = Arc::new(event_loop.create_window(attributes).unwrap());
+        let mut scene = match self.scene.take() {
+            Some(mut scene) => {
+                for layer in &self.scene_layers {
+                    scene.merge(self.importers.import_from(self.assets.as_ref(), layer).unwrap());
+                }
+                scene
+            }
+            None => {
+                let scene = self.load_scene().unwrap();
+                if self.settings.hot_reload {
+                    self.watcher = Some(FileWatcher::new(scene.sources.clone(), self.assets.as_ref()));
+                }
+                self.remember_scene();
+                scene
+            }
+        };
+        self.run_script(&mut scene);
+        if let Some(physics) = &mut self.physics {
+            physics.load(&scene);
+        }
+        if self.settings.audio {
+            self.audio = AudioDriver::new();
+        }
+        if let Some(audio) = &mut self.audio {
+            audio.load(&scene);
+        }
+        let camera = self.camera.unwrap_or_else(|| scene.default_camera());
+        let mut state = State::new(window, scene, self.settings.clone());
+        state.set_camera(camera);
+        state.timeline = self.timeline.clone();
+        for plugin in self.plugins.drain(..) {
+            state.renderer.add_plugin(plugin);
+        }
+        for pass in self.animations.drain(..) {
+            state.renderer.add_animation(pass);
+        }
+        self.callbacks.scene_loaded(&state.renderer.scene_stats);
+        self.state = Some(state);
+        self.bookmarks = Bookmarks::load(&self.scene_path);
+        if let Some(mode) = self.settings.fullscreen {
+            self.set_fullscreen(Some(mode));
+        }
+        if self.settings.gamepad {
+            #[cfg(feature = "gamepad")]
+            {
+                self.gamepads = Gamepads::new();
+            }
+            #[cfg(not(feature = "gamepad"))]
+            log::warn!("Gamepads need the viewer built with the `gamepad` feature");
+        }
+        if self.settings.osc.is_some() || !self.settings.midi.is_empty() {
+            self.remote = Some(Remote::new(self.settings.osc, self.settings.midi.clone()));
+        }
+        if self.settings.spacemouse {
+            #[cfg(feature = "spacemouse")]
+            {
+                self.space_mice = SpaceMice::new();
+            }
+            #[cfg(not(feature = "spacemouse"))]
+            log::warn!("3D mice need the viewer built with the `spacemouse` feature");
+        }
+        if let Some(address) = self.settings.control
+            && self.control.is_none()
+        {
+            match ControlServer::new(address) {
+                Ok(server) => self.control = Some(server),
+                Err(err) => log::error!("Failed to serve the control API on {address}: {err}"),
+            }
+        }
+    }
+
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(state) = &mut self.state {
+            state.suspend();
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        let (Some(path), Some(session)) = (&self.settings.session, self.session()) else {
+            return;
+        };
+        if let Err(err) = session.save(path) {
+            log::warn!("Failed to save the session to {}: {err}", path.display());
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+        if self.get_state().input(&event) {
+            return;
+        }
+        match event {
+            WindowEvent::CloseRequested => {
+                println!("The close button was pressed; stopping");
+                event_loop.exit();
+            },
+            WindowEvent::RedrawRequested => {
+                if !self.state.as_ref().is_some_and(State::can_draw) {
+                    return;
+                }
+                self.get_window().request_redraw();
+                self.reload_changed_scene();
+                let time = self.get_state().update();
+                self.run_frame_script(time);
+                self.remote_control();
+                self.serve_control();
+                self.fly_camera();
+                self.step_physics(time);
+                self.drive_audio();
+                let state = self.state.as_mut().unwrap();
+                state.frame += 1;
+                let frame = FrameEvent {
+                    frame: state.frame,
+                    scale: state.render_scale(),
+                };
+                self.callbacks.frame_start(frame);
+                match state.render() {
+                    Ok(_) => {
+                        let window = state.window.as_ref().and_then(|window| window.window.as_ref()).unwrap();
+                        self.title.frame(window, &self.scene_path, frame.scale);
+                        self.callbacks.sample_complete(frame);
+                        if frame.scale >= 1.0 && !state.finished {
+                            state.finished = true;
+                            state.measure_comparison();
+                            self.callbacks.render_finished(frame);
+                        }
+                    }
+                    Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+                        state.resize(state.size);
+                    },
+                    Err(SurfaceError::OutOfMemory | SurfaceError::Other) => {
+                        log::error!("OutOfMemory");
+                        event_loop.exit();
+                    },
+                    Err(SurfaceError::Timeout) => {
+                        log::warn!("Surface timeout");
+                    }
+                }
+            }
+            WindowEvent::Resized(physical_size) => {
+                self.get_state().resize(physical_size);
+            }
+            WindowEvent::DroppedFile(path) => {
+                if let Err(err) = self.open_scene(&path) {
+                    log::error!("Failed to open {}: {err}", path.display());
+                }
+            }
+            // The window is resized to match in a following event.
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.get_state().set_scale_factor(scale_factor);
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(code),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                if code == KeyCode::F11 {
+                    let shift = self.get_state().modifiers.shift_key();
+                    self.toggle_fullscreen(if shift { FullscreenMode::Exclusive } else { FullscreenMode::Borderless });
+                } else if code == KeyCode::Escape && self.fullscreen().is_some() {
+                    self.set_fullscreen(None);
+                } else if code == KeyCode::KeyT {
+                    self.get_state().toggle_turntable();
+                } else if code == KeyCode::KeyM {
+                    self.get_state().renderer.cycle_material_override();
+                } else if code == KeyCode::KeyX {
+                    let renderer = &mut self.get_state().renderer;
+                    renderer.clear_rays();
+                    renderer.clear_measurements();
+                } else if code == KeyCode::KeyC && self.get_state().modifiers.control_key() {
+                    match self.copy_to_clipboard() {
+                        Ok(()) => log::info!("Copied the frame to the clipboard"),
+                        Err(err) => log::error!("Failed to copy the frame: {err}"),
+                    }
+                } else if code == KeyCode::KeyC {
+                    self.get_state().cycle_clip_plane();
+                } else if code == KeyCode::BracketLeft {
+                    self.get_state().move_clip_plane(-1.0);
+                } else if code == KeyCode::BracketRight {
+                    self.get_state().move_clip_plane(1.0);
+                } else if code == KeyCode::KeyE {
+                    let settings = &mut self.get_state().renderer.settings;
+                    settings.explode = if settings.explode > 0.0 { 0.0 } else { 1.0 };
+                } else if code == KeyCode::Minus || code == KeyCode::Equal {
+                    let settings = &mut self.get_state().renderer.settings;
+                    let step = if code == KeyCode::Minus { -EXPLODE_STEP } else { EXPLODE_STEP };
+                    settings.explode = (settings.explode + step).max(0.0);
+                } else if code == KeyCode::KeyR {
+                    let state = self.get_state();
+                    state.measuring = !state.measuring;
+                    state.measure_start = None;
+                    log::info!("Measuring {}", if state.measuring { "on" } else { "off" });
+                } else if code == KeyCode::KeyI {
+                    let state = self.get_state();
+                    state.inspecting = !state.inspecting;
+                } else if code == KeyCode::Space {
+                    let timeline = &mut self.get_state().timeline;
+                    timeline.toggle();
+                    log::info!("{}", if timeline.is_playing() { "Playing" } else { "Paused" });
+                } else if code == KeyCode::Comma || code == KeyCode::Period {
+                    // Shift changes the playback speed, otherwise a frame is stepped.
+                    let state = self.get_state();
+                    let forward = code == KeyCode::Period;
+                    if state.modifiers.shift_key() {
+                        let speed = state.timeline.speed() * if forward { 2.0 } else { 0.5 };
+                        state.timeline.set_speed(speed);
+                        log::info!("Playing at {}x", state.timeline.speed());
+                    } else {
+                        state.step_timeline(if forward { 1.0 } else { -1.0 });
+                        log::info!("Time {:.3} s", state.timeline.time());
+                    }
+                } else if code == KeyCode::Home {
+                    let timeline = &mut self.get_state().timeline;
+                    timeline.seek(timeline.loop_range().map_or(0.0, |range| range.start));
+                } else if let Some(toggle) = overlay_toggle(code) {
+                    toggle(&mut self.get_state().renderer.settings.overlays);
+                } else if code == KeyCode::KeyF {
+                    let settings = &mut self.get_state().renderer.settings;
+                    settings.analysis = match settings.analysis {
+                        Some(_) => None,
+                        None => Some(AnalysisView::Luminance),
+                    };
+                    if let Some(analysis) = settings.analysis {
+                        log::info!("{analysis}");
+                    }
+                } else if code == KeyCode::KeyV && self.get_state().comparison.is_some() {
+                    let settings = &mut self.get_state().renderer.settings;
+                    settings.compare_mode = settings.compare_mode.next();
+                    log::info!("Comparing by {}", settings.compare_mode);
+                } else if code == KeyCode::KeyO {
+                    for (index, path) in self.recent_scenes.iter().enumerate() {
+                        log::info!("Alt+{}: {}", index + 1, path.display());
+                    }
+                } else if let Some(slot) = bookmark_slot(code) {
+                    // Ctrl+digit stores the current view, a plain digit jumps to it and Alt+digit
+                    // opens a recent scene instead.
+                    if self.get_state().modifiers.alt_key() {
+                        self.open_recent_scene(slot);
+                    } else if self.get_state().modifiers.control_key() {
+                        let camera = self.get_state().renderer.camera;
+                        self.bookmarks.set_slot(slot, camera);
+                    } else if let Some(bookmark) = self.bookmarks.entries().get(slot) {
+                        let camera = bookmark.camera;
+                        self.get_state().set_camera(camera);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// `mode` on the `monitor`th monitor of `window`'s system, or the one the window is on.
+fn fullscreen(window: &Window, mode: FullscreenMode, monitor: Option<usize>) -> Fullscreen {
+    let monitor = monitor.and_then(|index| window.available_monitors().nth(index)).or_else(|| window.current_monitor());
+    if mode == FullscreenMode::Exclusive {
+        let video_mode = monitor.as_ref().and_then(|monitor| {
+            monitor.video_modes().max_by_key(|mode| (mode.size().width * mode.size().height, mode.refresh_rate_millihertz()))
+        });
+        match video_mode {
+            Some(video_mode) => return Fullscreen::Exclusive(video_mode),
+            None => log::warn!("Exclusive fullscreen isn't available; going borderless"),
+        }
+    }
+    Fullscreen::Borderless(monitor)
+}
+
+/// Bookmark index bound to a number key, `1` being the first.
+fn bookmark_slot(code: KeyCode) -> Option<usize> {
+    let slot = match code {
+        KeyCode::Digit1 => 0,
+        KeyCode::Digit2 => 1,
+        KeyCode::Digit3 => 2,
+        KeyCode::Digit4 => 3,
+        KeyCode::Digit5 => 4,
+        KeyCode::Digit6 => 5,
+        KeyCode::Digit7 => 6,
+        KeyCode::Digit8 => 7,
+        KeyCode::Digit9 => 8,
+        _ => return None,
+    };
+    Some(slot)
+}
+
+/// Overlay switched by a key: G for the grid, A for the axes, B for selection bounds, H for
+/// the HUD and L for selection labels.
+fn overlay_toggle(code: KeyCode) -> Option<fn(&mut Overlays)> {
+    let toggle: fn(&mut Overlays) = match code {
+        KeyCode::KeyG => |overlays| overlays.grid = !overlays.grid,
+        KeyCode::KeyA => |overlays| overlays.axes = !overlays.axes,
+        KeyCode::KeyB => |overlays| overlays.bounds = !overlays.bounds,
+        KeyCode::KeyH => |overlays| overlays.hud = !overlays.hud,
+        KeyCode::KeyL => |overlays| overlays.labels = !overlays.labels,
+        _ => return None,
+    };
+    Some(toggle)
+}
+
+impl RayTracer {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            state: None,
+            settings,
+            camera: None,
+            scene_path: PathBuf::from(GLTF_PATH),
+            scene: None,
+            scene_layers: Vec::new(),
+            watcher: None,
+            importers: Importers::default(),
+            assets: Box::new(FileSystem::default()),
+            bookmarks: Bookmarks::default(),
+            plugins: Vec::new(),
+            animations: Vec::new(),
+            callbacks: Callbacks::default(),
+            script: None,
+            #[cfg(feature = "physics")]
+            physics: Some(Physics::new(Box::new(RapierEngine::default()))),
+            #[cfg(not(feature = "physics"))]
+            physics: None,
+            timeline: Timeline::default(),
+            #[cfg(feature = "gamepad")]
+            gamepads: None,
+            #[cfg(feature = "spacemouse")]
+            space_mice: None,
+            audio: None,
+            remote: None,
+            control: None,
+            title: TitleBar::new(title::DEFAULT_TITLE.to_owned()),
+            icon: None,
+            window_size: None,
+            recent_scenes: Vec::new(),
+            loaded_scenes: Vec::new(),
+        }
+    }
+
+    pub fn scene_path(&self) -> &Path {
+        &self.scene_path
+    }
+
+    /// Titles the window with `template`, in which `{scene}` is replaced by the scene's file
+    /// name, `{fps}` by the frame rate and `{progress}` by the percentage of the full
+    /// resolution refinement has reached. Applies within half a second while running.
+    pub fn set_title(&mut self, template: impl Into<String>) {
+        self.title.template = template.into();
+    }
+
+    /// Opens the window with an inner size of `width` by `height` physical pixels.
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        self.window_size = Some(PhysicalSize::new(width, height));
+    }
+
+    /// What the viewer shows, to carry on from on the next launch. `None` before the window
+    /// opens.
+    pub fn session(&self) -> Option<Session> {
+        let state = self.state.as_ref()?;
+        let size = state.size();
+        Some(Session {
+            scene: Some(self.scene_path.clone()),
+            camera: Some(state.renderer.camera),
+            window_size: (self.fullscreen().is_none() && size.width > 0 && size.height > 0)
+                .then_some([size.width, size.height]),
+            settings: SessionSettings::from(&state.renderer.settings),
+            recent: self.recent_scenes.clone(),
+        })
+    }
+
+    /// Replaces the window's default icon.
+    pub fn set_icon(&mut self, icon: Icon) {
+        if let Some(window) = self.state.as_ref().and_then(|state| state.window.as_ref()?.window.clone()) {
+            window.set_window_icon(Some(icon.clone()));
+        }
+        self.icon = Some(icon);
+    }
+
+    pub fn set_scene_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.scene_path = path.into();
+    }
+
+    /// Shows `scene` instead of loading one from the scene path, e.g. one built from
+    /// [`geometry::primitives`].
+    pub fn set_scene(&mut self, scene: Scene) {
+        self.scene = Some(scene);
+    }
+
+    /// Scene paths opened, most recent first, the shown one included.
+    pub fn recent_scenes(&self) -> &[PathBuf] {
+        &self.recent_scenes
+    }
+
+    /// Replaces the recent scenes, e.g. with a [`Session`]'s, keeping the first nine.
+    pub fn set_recent_scenes(&mut self, mut scenes: Vec<PathBuf>) {
+        scenes.truncate(MAX_RECENT_SCENES);
+        self.recent_scenes = scenes;
+    }
+
+    /// Moves the scene path to the front of the recent scenes.
+    fn remember_scene(&mut self) {
+        self.recent_scenes.retain(|path| path != &self.scene_path);
+        self.recent_scenes.insert(0, self.scene_path.clone());
+        self.recent_scenes.truncate(MAX_RECENT_SCENES);
+    }
+
+    /// Shows the scene at `path` in place of the current one, without its layers, from its
+    /// default camera. The current scene's GPU resources are released or reused for the new
+    /// one. Before the window opens this sets the scene path.
+    ///
+    /// The scene shown before stays in memory, so switching back to it is quick unless its
+    /// files changed.
+    ///
+    /// If the scene fails to load the current one stays.
+    pub fn open_scene<P: Into<PathBuf>>(&mut self, path: P) -> Result<(), LoadError> {
+        let path = path.into();
+        if self.state.is_none() {
+            self.scene_path = path;
+            self.scene = None;
+            return Ok(());
+        }
+        let previous = mem::replace(&mut self.scene_path, path);
+        let layers = mem::take(&mut self.scene_layers);
+        let started = Instant::now();
+        let mut scene = match self.load_scene() {
+            Ok(scene) => scene,
+            Err(err) => {
+                self.scene_path = previous;
+                self.scene_layers = layers;
+                return Err(err);
+            }
+        };
+        self.watcher = self.settings.hot_reload
+            .then(|| FileWatcher::new(scene.sources.clone(), self.assets.as_ref()));
+        let state = self.state.as_mut().unwrap();
+        let mut camera = scene.default_camera();
+        if let Some(script) = &mut self.script {
+            let mut settings = state.renderer.settings.clone();
+            match script.run(&mut scene, &mut settings, &mut camera) {
+                Ok(()) => state.renderer.settings = settings,
+                Err(err) => {
+                    log::error!("Script failed: {err}");
+                    self.script = None;
+                }
+            }
+        }
+        // What belonged to the previous scene goes with it.
+        state.renderer.settings.atmosphere = self.settings.atmosphere.or(scene.atmosphere);
+        state.renderer.select(Vec::new());
+        state.renderer.clear_rays();
+        state.renderer.clear_measurements();
+        state.measure_start = None;
+        if let Some(physics) = &mut self.physics {
+            physics.load(&scene);
+        }
+        if let Some(audio) = &mut self.audio {
+            audio.load(&scene);
+        }
+        state.renderer.reload(scene);
+        state.set_camera(camera);
+        self.callbacks.scene_loaded(&state.renderer.scene_stats);
+        self.bookmarks = Bookmarks::load(&self.scene_path);
+        self.remember_scene();
+        log::info!("Opened {} in {:.1?}", self.scene_path.display(), started.elapsed());
+        Ok(())
+    }
+
+    /// Opens the `index`th recent scene, `0` being the shown one, logging a failure to load.
+    fn open_recent_scene(&mut self, index: usize) {
+        let Some(path) = self.recent_scenes.get(index).cloned() else {
+            return;
+        };
+        if let Err(err) = self.open_scene(&path) {
+            log::error!("Failed to open {}: {err}", path.display());
+        }
+    }
+
+    /// Merges the scene at `path` over the shown one when it opens, after any earlier layers,
+    /// e.g. per-shot tweaks to a shared environment. See [`Scene::merge`].
+    pub fn add_scene_layer<P: Into<PathBuf>>(&mut self, path: P) {
+        self.scene_layers.push(path.into());
+    }
+
+    /// Loads the scene path with the scene layers merged over it.
+    fn import_scene(&self) -> Result<Scene, LoadError> {
+        let mut scene = self.importers.import_from(self.assets.as_ref(), &self.scene_path)?;
+        for layer in &self.scene_layers {
+            scene.merge(self.importers.import_from(self.assets.as_ref(), layer)?);
+        }
+        scene.convert(&self.settings.import);
+        if let Some(epsilon) = self.settings.weld_epsilon {
+            scene.clean_meshes(epsilon);
+        }
+        if self.settings.optimize_meshes {
+            scene.optimize_meshes();
+        }
+        Ok(scene)
+    }
+
+    /// Imports the scene path with the scene layers, or copies it from the scenes imported
+    /// before if none of its files changed since.
+    fn load_scene(&mut self) -> Result<Scene, LoadError> {
+        let index = self.loaded_scenes.iter()
+            .position(|loaded| loaded.path == self.scene_path && loaded.layers == self.scene_layers);
+        if let Some(index) = index {
+            let mut loaded = self.loaded_scenes.remove(index);
+            if !loaded.watcher.changed(self.assets.as_ref()) {
+                let scene = loaded.scene.clone();
+                self.loaded_scenes.insert(0, loaded);
+                return Ok(scene);
+            }
+        }
+        let scene = self.import_scene()?;
+        self.loaded_scenes.insert(0, LoadedScene {
+            path: self.scene_path.clone(),
+            layers: self.scene_layers.clone(),
+            scene: scene.clone(),
+            watcher: FileWatcher::new(scene.sources.clone(), self.assets.as_ref()),
+        });
+        self.loaded_scenes.truncate(MAX_LOADED_SCENES);
+        Ok(scene)
+    }
+
+    /// Reloads the scene if a file it was read from changed, keeping the current view.
+    ///
+    /// A scene that fails to load, e.g. because it is still being written, is skipped until the
+    /// next change.
+    fn reload_changed_scene(&mut self) {
+        if !self.watcher.as_mut().is_some_and(|watcher| watcher.changed(self.assets.as_ref())) {
+            return;
+        }
+        let mut scene = match self.import_scene() {
+            Ok(scene) => scene,
+            Err(err) => {
+                log::warn!("Failed to reload {}: {err}", self.scene_path.display());
+                return;
+            }
+        };
+        self.watcher = Some(FileWatcher::new(scene.sources.clone(), self.assets.as_ref()));
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        // Only the script's changes to the scene apply; the view and settings stay as they are.
+        if let Some(script) = &mut self.script {
+            let (mut settings, mut camera) = (state.renderer.settings.clone(), state.renderer.camera);
+            if let Err(err) = script.run(&mut scene, &mut settings, &mut camera) {
+                log::error!("Script failed: {err}");
+                self.script = None;
+            }
+        }
+        if let Some(physics) = &mut self.physics {
+            physics.load(&scene);
+        }
+        if let Some(audio) = &mut self.audio {
+            audio.load(&scene);
+        }
+        state.renderer.reload(scene);
+        state.finished = false;
+        log::info!("Reloaded {}", self.scene_path.display());
+        self.callbacks.scene_loaded(&state.renderer.scene_stats);
+    }
+
+    /// Runs the script's top-level statements on `scene` before it is shown, taking the camera
+    /// they move as the one to start from. A failing script is dropped.
+    fn run_script(&mut self, scene: &mut Scene) {
+        let Some(script) = &mut self.script else {
+            return;
+        };
+        let start = self.camera.unwrap_or_else(|| scene.default_camera());
+        let mut camera = start;
+        match script.run(scene, &mut self.settings, &mut camera) {
+            Ok(()) if camera != start => self.camera = Some(camera),
+            Ok(()) => {}
+            Err(err) => {
+                log::error!("Script failed: {err}");
+                self.script = None;
+            }
+        }
+    }
+
+    /// Calls the script's `on_frame` for the frame about to be drawn, unless the timeline is
+    /// standing still. A failing script is dropped.
+    fn run_frame_script(&mut self, time: TimeStep) {
+        let (Some(script), Some(state)) = (&mut self.script, &mut self.state) else {
+            return;
+        };
+        if time.step == 0.0 && !time.restarted {
+            return;
+        }
+        let mut camera = state.renderer.camera;
+        match script.on_frame(state.frame + 1, time.time, &mut state.renderer.settings, &mut camera) {
+            Ok(()) if camera != state.renderer.camera => state.set_camera(camera),
+            Ok(()) => {}
+            Err(err) => {
+                log::error!("Script failed: {err}");
+                self.script = None;
+            }
+        }
+    }
+
+    /// Applies the OSC messages and MIDI control changes received since the last frame.
+    fn remote_control(&mut self) {
+        let (Some(remote), Some(state)) = (&mut self.remote, &mut self.state) else {
+            return;
+        };
+        let mut camera = state.renderer.camera;
+        if remote.poll(&mut state.renderer.settings, &mut camera) && camera != state.renderer.camera {
+            state.set_camera(camera);
+        }
+    }
+
+    /// Answers the requests made to the control API since the last frame.
+    fn serve_control(&mut self) {
+        let Some(server) = self.control.take() else {
+            return;
+        };
+        for (request, reply) in server.pending() {
+            let _ = reply.send(control::handle(self, &request));
+        }
+        self.control = Some(server);
+    }
+
+    /// Moves the camera by the gamepad sticks and 3D mouse caps, if any are pushed.
+    fn fly_camera(&mut self) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        let start = state.renderer.camera;
+        let camera = start;
+        #[cfg(feature = "gamepad")]
+        let camera = {
+            let stats = &state.renderer.scene_stats;
+            let size = (stats.max - stats.min).length();
+            self.gamepads.as_mut().and_then(|gamepads| gamepads.fly(camera, size)).unwrap_or(camera)
+        };
+        #[cfg(feature = "spacemouse")]
+        let camera = self.space_mice.as_mut().and_then(|space_mice| space_mice.fly(camera)).unwrap_or(camera);
+        if camera != start {
+            state.set_camera(camera);
+        }
+    }
+
+    /// Steps the physics engine by the timeline's step, starting it over if the timeline went
+    /// back, and moves the meshes of the bodies it moved.
+    fn step_physics(&mut self, time: TimeStep) {
+        let (Some(physics), Some(state)) = (&mut self.physics, &mut self.state) else {
+            return;
+        };
+        if time.restarted {
+            physics.restart();
+        }
+        let moved = physics.step(time.step);
+        if moved.is_empty() {
+            return;
+        }
+        for (mesh, positions) in moved {
+            state.renderer.update_vertices(mesh, 0..positions.len(), &positions);
+        }
+        state.finished = false;
+    }
+
+    /// Sets the parameters mapped to the audio for its latest levels.
+    fn drive_audio(&mut self) {
+        let (Some(audio), Some(state)) = (&mut self.audio, &mut self.state) else {
+            return;
+        };
+        let frame = audio.drive();
+        if frame.explode.is_none() && frame.colors.is_empty() && frame.meshes.is_empty() {
+            return;
+        }
+        if let Some(explode) = frame.explode {
+            state.renderer.settings.explode = explode;
+        }
+        for (material, color) in frame.colors {
+            state.renderer.set_material_color(material, color);
+        }
+        for (mesh, positions) in frame.meshes {
+            state.renderer.update_vertices(mesh, 0..positions.len(), &positions);
+        }
+        state.finished = false;
+    }
+
+    /// Reads the scene path, its layers and the files they refer to from `source` instead of
+    /// the working directory, e.g. a [`ZipArchive`](assets::ZipArchive) or an
+    /// [`Http`](assets::Http) server.
+    pub fn set_asset_source(&mut self, source: Box<dyn AssetSource>) {
+        self.assets = source;
+    }
+
+    /// Adds a reader for another scene format, used when loading the scene path.
+    pub fn add_importer(&mut self, importer: Box<dyn Importer>) {
+        self.importers.register(importer);
+    }
+
+    pub fn run(&mut self) -> Result<(), EventLoopError> {
+        let event_loop = EventLoop::new().unwrap();
+        //event_loop.set_control_flow(ControlFlow::Poll);
+        event_loop.run_app(self)
+    }
+
+    /// Runs the viewer in an Android app, started by its `NativeActivity`. Scene paths are
+    /// relative to the app's internal storage.
+    #[cfg(target_os = "android")]
+    pub fn run_android(&mut self, app: winit::platform::android::activity::AndroidApp) -> Result<(), EventLoopError> {
+        use winit::platform::android::EventLoopBuilderExtAndroid;
+
+        if let Some(data) = app.internal_data_path() {
+            self.scene_path = data.join(&self.scene_path);
+        }
+        let event_loop = EventLoop::builder().with_android_app(app).build().unwrap();
+        event_loop.run_app(self)
+    }
+
+    /// How the window fills a monitor, `None` when windowed.
+    pub fn fullscreen(&self) -> Option<FullscreenMode> {
+        match &self.state {
+            Some(state) => state.renderer.settings.fullscreen,
+            None => self.settings.fullscreen,
+        }
+    }
+
+    /// Makes the window fill the [chosen monitor](Settings::monitor) in `mode`, or windowed
+    /// with `None`. Before the window opens this sets how it opens.
+    pub fn set_fullscreen(&mut self, mode: Option<FullscreenMode>) {
+        self.settings_mut().fullscreen = mode;
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        let Some(window) = state.window.as_ref().and_then(|window| window.window.clone()) else {
+            return;
+        };
+        let monitor = state.renderer.settings.monitor;
+        window.set_fullscreen(mode.map(|mode| fullscreen(&window, mode, monitor)));
+        // Platforms that resize the window right away may not report it before the next
+        // frame, which would be drawn to a surface of the old size.
+        state.resize(window.inner_size());
+    }
+
+    /// Goes fullscreen in `mode`, or back to a window if already fullscreen.
+    pub fn toggle_fullscreen(&mut self, mode: FullscreenMode) {
+        self.set_fullscreen(match self.fullscreen() {
+            Some(_) => None,
+            None => Some(mode),
+        });
+    }
+
+    pub fn get_window(&self) -> Arc<Window> {
+        self.state.as_ref().unwrap().window.as_ref().and_then(|window| window.window.clone()).unwrap()
+    }
+
+    /// Settings of the running renderer, or the ones it will start with.
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        match &mut self.state {
+            Some(state) => &mut state.renderer.settings,
+            None => &mut self.settings,
+        }
+    }
+
+    /// Timeline of the running renderer, or the one it will start with.
+    pub fn timeline_mut(&mut self) -> &mut Timeline {
+        match &mut self.state {
+            Some(state) => &mut state.timeline,
+            None => &mut self.timeline,
+        }
+    }
+
+    /// Level of each band of the scene's [`AudioReactive`] mapping from zero to one, for
+    /// driving what the scene file can't map, such as lights. Empty without [`Settings::audio`].
+    pub fn audio_levels(&self) -> &[f32] {
+        self.audio.as_ref().map_or(&[], |audio| audio.levels())
+    }
+
+    /// Camera of the running renderer, or the one it will start with.
+    pub fn camera(&self) -> Camera {
+        match &self.state {
+            Some(state) => state.renderer.camera,
+            None => self.camera.unwrap_or_default(),
+        }
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        match &mut self.state {
+            Some(state) => state.set_camera(camera),
+            None => self.camera = Some(camera),
+        }
+    }
+
+    /// Sets which rays see mesh `index`, in the running renderer or the scene given to
+    /// [`RayTracer::set_scene`].
+    pub fn set_visibility(&mut self, index: usize, visibility: Visibility) {
+        match (&mut self.state, &mut self.scene) {
+            (Some(state), _) => state.renderer.set_visibility(index, visibility),
+            (None, Some(scene)) => scene.set_visibility(index, visibility),
+            (None, None) => {}
+        }
+    }
+
+    /// Moves vertices of mesh `index` in the running renderer or the scene given to
+    /// [`RayTracer::set_scene`], see [`Scene::update_vertices`]. Only the vertices moved are
+    /// uploaded, so meshes can be animated every frame.
+    pub fn update_vertices(&mut self, index: usize, range: Range<usize>, positions: &[Vec3]) {
+        match (&mut self.state, &mut self.scene) {
+            (Some(state), _) => {
+                state.renderer.update_vertices(index, range, positions);
+                state.finished = false;
+            }
+            (None, Some(scene)) => scene.update_vertices(index, range, positions),
+            (None, None) => {}
+        }
+    }
+
+    /// Starts or stops orbiting the camera around the scene.
+    pub fn toggle_turntable(&mut self) {
+        if let Some(state) = &mut self.state {
+            state.toggle_turntable();
+        }
+    }
+
+    /// Bookmarks of the loaded scene, in hotkey order.
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        self.bookmarks.entries()
+    }
+
+    /// Saves `camera` as the bookmark `name`, replacing an existing one with that name.
+    pub fn set_bookmark(&mut self, name: &str, camera: Camera) {
+        self.bookmarks.set(name.to_owned(), camera);
+    }
+
+    /// Saves the current camera as the bookmark `name`.
+    pub fn save_bookmark(&mut self, name: &str) {
+        self.set_bookmark(name, self.camera());
+    }
+
+    /// Moves the camera to the bookmark `name`, returning whether it exists.
+    pub fn go_to_bookmark(&mut self, name: &str) -> bool {
+        let Some(camera) = self.bookmarks.get(name).map(|bookmark| bookmark.camera) else {
+            return false;
+        };
+        self.set_camera(camera);
+        true
+    }
+
+    pub fn remove_bookmark(&mut self, name: &str) -> bool {
+        self.bookmarks.remove(name)
+    }
+
+    /// Reports what is under pixel (`x`, `y`) of the window, once it is open.
+    pub fn inspect(&self, x: u32, y: u32) -> Option<PixelInfo> {
+        let state = self.state.as_ref()?;
+        Some(state.renderer.inspect(x, y, state.size.width, state.size.height))
+    }
+
+    /// Called before each frame is drawn.
+    pub fn on_frame_start(&mut self, callback: impl FnMut(FrameEvent) + 'static) {
+        self.callbacks.frame_start = Some(Box::new(callback));
+    }
+
+    /// Called once the scene is loaded and uploaded, with its statistics.
+    pub fn on_scene_loaded(&mut self, callback: impl FnMut(&SceneStats) + 'static) {
+        self.callbacks.scene_loaded = Some(Box::new(callback));
+    }
+
+    /// Called after each frame is submitted. The rasterizer takes one sample per pixel per frame.
+    pub fn on_sample_complete(&mut self, callback: impl FnMut(FrameEvent) + 'static) {
+        self.callbacks.sample_complete = Some(Box::new(callback));
+    }
+
+    /// Called when the image is first drawn at full resolution after opening or an interaction,
+    /// e.g. to capture a settled frame.
+    pub fn on_render_finished(&mut self, callback: impl FnMut(FrameEvent) + 'static) {
+        self.callbacks.render_finished = Some(Box::new(callback));
+    }
+
+    /// Runs `script` on the scene once it is loaded, and its `on_frame` before every frame.
+    /// Replaces any earlier script.
+    pub fn set_script(&mut self, script: Script) {
+        self.script = Some(script);
+    }
+
+    /// Adds a pass run after the scene is drawn every frame.
+    pub fn add_plugin(&mut self, plugin: Box<dyn RenderPlugin>) {
+        match &mut self.state {
+            Some(state) => state.renderer.add_plugin(plugin),
+            None => self.plugins.push(plugin),
+        }
+    }
+
+    /// Simulates the scene's [`RigidBody`] nodes with `engine`, stepped before every frame,
+    /// from the next scene loaded or reloaded. Set it before [`RayTracer::run`] for the first.
+    /// Builds with the `physics` feature simulate them with `RapierEngine` until then.
+    pub fn set_physics(&mut self, engine: Box<dyn PhysicsEngine>) {
+        self.physics = Some(Physics::new(engine));
+    }
+
+    /// Adds a compute pass moving the scene's vertices or splats before every frame.
+    pub fn add_animation(&mut self, pass: Box<dyn AnimationPass>) {
+        match &mut self.state {
+            Some(state) => state.renderer.add_animation(pass),
+            None => self.animations.push(pass),
+        }
+    }
+
+    /// Captures the camera ray through pixel (`x`, `y`) of the window and draws it in the
+    /// viewport until [`RayTracer::clear_rays`].
+    pub fn capture_ray(&mut self, x: u32, y: u32) -> Option<DebugRay> {
+        let state = self.state.as_mut()?;
+        Some(state.renderer.capture_ray(x, y, state.size.width, state.size.height))
+    }
+
+    /// Meshes outlined by [`Overlays::bounds`], by index into the scene's meshes.
+    pub fn selection(&self) -> &[usize] {
+        self.state.as_ref().map_or(&[], |state| state.renderer.selection())
+    }
+
+    /// Selects the meshes at `meshes` for [`Overlays::bounds`] to outline, once the window is
+    /// open. In the viewer, clicking a mesh selects it while the bounds are shown.
+    pub fn select(&mut self, meshes: Vec<usize>) {
+        if let Some(state) = &mut self.state {
+            state.renderer.select(meshes);
+        }
+    }
+
+    /// Measures between the surfaces under pixels `from` and `to` of the window and draws the
+    /// measurement until [`RayTracer::clear_measurements`]. `None` if either pixel shows the
+    /// background.
+    pub fn measure(&mut self, from: (u32, u32), to: (u32, u32)) -> Option<Measurement> {
+        let state = self.state.as_mut()?;
+        let (width, height) = (state.size.width, state.size.height);
+        let measurement = Measurement {
+            start: state.renderer.pick(from.0, from.1, width, height)?,
+            end: state.renderer.pick(to.0, to.1, width, height)?,
+        };
+        state.renderer.add_measurement(measurement);
+        Some(measurement)
+    }
+
+    pub fn measurements(&self) -> &[Measurement] {
+        self.state.as_ref().map_or(&[], |state| state.renderer.measurements())
+    }
+
+    pub fn clear_measurements(&mut self) {
+        if let Some(state) = &mut self.state {
+            state.renderer.clear_measurements();
+        }
+    }
+
+    pub fn clear_rays(&mut self) {
+        if let Some(state) = &mut self.state {
+            state.renderer.clear_rays();
+        }
+    }
+
+    /// Copies the current view, drawn at full resolution with its overlays, to the system
+    /// clipboard. Ctrl+C in the viewer.
+    pub fn copy_to_clipboard(&mut self) -> io::Result<()> {
+        let state = self.state.as_mut().ok_or_else(|| io::Error::other("the window isn't open"))?;
+        clipboard::copy_image(&state.capture())
+    }
+
+    pub fn stats(&self) -> Option<RenderStats> {
+        self.state.as_ref().map(State::stats)
+    }
+
+    pub fn scene_stats(&self) -> Option<&SceneStats> {
+        self.state.as_ref().map(|state| &state.renderer.scene_stats)
     }
 
     pub fn get_state(&mut self) -> &mut State {
         self.state.as_mut().unwrap()
     }
 }
+
+/// Where Android starts the app built from this library, showing the default scene.
+#[cfg(target_os = "android")]
+#[unsafe(no_mangle)]
+fn android_main(app: winit::platform::android::activity::AndroidApp) {
+    RayTracer::default().run_android(app).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_rect_parses_four_values() {
+        let crop: CropRect = " 0.25, 0.5,0.5 ,0.25".parse().unwrap();
+        assert_eq!(crop, CropRect { x: 0.25, y: 0.5, width: 0.5, height: 0.25 });
+        for s in ["", "0.1,0.2,0.3", "0.1,0.2,0.3,0.4,0.5", "0.1,0.2,half,0.4", "0.1;0.2;0.3;0.4"] {
+            assert!(s.parse::<CropRect>().is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn crop_rect_from_corners_in_any_order() {
+        let size = PhysicalSize::new(200, 100);
+        let crop = CropRect::from_corners(PhysicalPosition::new(150.0, 20.0), PhysicalPosition::new(50.0, 70.0), size);
+        assert_eq!(crop, CropRect { x: 0.25, y: 0.2, width: 0.5, height: 0.5 });
+    }
+
+    #[test]
+    fn crop_rect_pixels_are_clamped_and_never_empty() {
+        let pixels = |x, y, width, height| CropRect { x, y, width, height }.to_pixels(200, 100);
+        assert_eq!(pixels(0.0, 0.0, 1.0, 1.0), (0, 0, 200, 100));
+        assert_eq!(pixels(0.25, 0.5, 0.5, 0.25), (50, 50, 100, 25));
+        assert_eq!(pixels(-1.0, 0.5, 3.0, 2.0), (0, 50, 200, 50));
+        assert_eq!(pixels(1.0, 1.0, 0.5, 0.5), (199, 99, 1, 1));
+        assert_eq!(pixels(0.5, 0.5, 0.0, -1.0), (100, 50, 1, 1));
+    }
+}
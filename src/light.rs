@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Vec3;
+
+/// The shape of a [`Light`]'s emission.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LightKind {
+    /// Infinitely far away, shining along its direction.
+    Directional,
+    /// Shining in every direction from its position.
+    Point,
+    /// Shining in a cone around its direction, fading between the cone angles in radians.
+    Spot { inner_cone: f32, outer_cone: f32 },
+}
+
+/// A light imported with a scene, in world space.
+///
+/// The raster path shades from material colours alone; lights are kept with the scene for
+/// renderers and plugins that light it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    /// Name in the scene file, by which [`Scene::merge`](crate::Scene::merge) replaces it.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub kind: LightKind,
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// Linear RGB colour.
+    pub color: [f32; 3],
+    /// Candela for point and spot lights, lux for directional ones.
+    pub intensity: f32,
+    /// Distance beyond which the light has no effect, or `None` for no limit.
+    pub range: Option<f32>,
+}
@@ -1,3 +1,1022 @@
-fn main() -> Result<()> {
-    let gltf = gltf::import("res/triangle.glb")?;
-}
\ No newline at end of file
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    error::Error,
+    fmt,
+    fs,
+    io,
+    iter,
+    path::{Path, PathBuf},
+};
+
+use gltf::{
+    accessor::{self, Accessor, DataType},
+    buffer::Data,
+    image::{Data as ImageData, Format},
+    Document,
+    Gltf,
+    Node,
+    Semantic,
+};
+
+use image::DynamicImage;
+
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+};
+
+use crate::{
+    Atmosphere,
+    AudioReactive,
+    Camera,
+    Emitter,
+    Light,
+    LightKind,
+    Material,
+    MaterialGraph,
+    MaterialOverride,
+    ProceduralTexture,
+    Projection,
+    RigidBody,
+    Vec3,
+    assets::AssetSource,
+    camera::{self, Mat4},
+    geometry::{
+        csg,
+        curves::{self, CurveOptions},
+        displace::{self, Displacement, HeightTexture},
+        simplify,
+        tangents,
+        terrain::{self, TerrainOptions},
+    },
+    entity::Entity,
+    ply::{self, PlyData},
+    scene::{self, Scene, Visibility},
+    stl::{self, StlOptions},
+    usd,
+    volume::Volume,
+};
+
+#[cfg(feature = "draco")]
+use crate::draco;
+
+/// Levels generated for meshes that do not ship their own `MSFT_lod` chain.
+pub(crate) const GENERATED_LODS: usize = 3;
+
+/// Meshes are not decimated below this many triangles.
+pub(crate) const MIN_LOD_TRIANGLES: usize = 64;
+
+/// Colour given to heightmap terrain, which has no materials of its own.
+const TERRAIN_COLOR: [f32; 4] = [0.45, 0.5, 0.35, 1.0];
+
+/// Colour given to curve files, which have no materials of their own.
+const HAIR_COLOR: [f32; 4] = [0.35, 0.22, 0.12, 1.0];
+
+/// Why a scene file could not be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    Gltf(gltf::Error),
+    Image(image::ImageError),
+    Io(io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Gltf(err) => write!(f, "{err}"),
+            LoadError::Image(err) => write!(f, "{err}"),
+            LoadError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Gltf(err) => Some(err),
+            LoadError::Image(err) => Some(err),
+            LoadError::Io(err) => Some(err),
+        }
+    }
+}
+
+impl From<gltf::Error> for LoadError {
+    fn from(err: gltf::Error) -> Self {
+        LoadError::Gltf(err)
+    }
+}
+
+impl From<image::ImageError> for LoadError {
+    fn from(err: image::ImageError) -> Self {
+        LoadError::Image(err)
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+/// Renderer settings read from the glTF scene's `extras`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct SceneExtras {
+    atmosphere: Option<Atmosphere>,
+    audio: Option<AudioReactive>,
+    csg: Vec<CsgMesh>,
+    overrides: Vec<MaterialOverride>,
+    particles: Vec<Emitter>,
+    /// WGSL file defining `custom_shade`, relative to the glTF file.
+    shader: Option<String>,
+    texture: Option<ProceduralTexture>,
+    /// Material graph, applied instead of `texture` when both are set.
+    material: Option<MaterialGraph>,
+    /// MaterialX document relative to the glTF file, applied instead of `material` when both
+    /// are set.
+    materialx: Option<String>,
+}
+
+/// Per-material settings read from the material's `extras`, e.g.
+/// `{"displacement": {"texture": 0, "scale": 0.05, "subdivisions": 3}, "visibility": {"camera": false}}`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct MaterialExtras {
+    displacement: Option<DisplacementExtras>,
+    visibility: Option<Visibility>,
+}
+
+/// Per-node settings read from the node's `extras`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct NodeExtras {
+    visibility: Option<Visibility>,
+    /// Render layers, e.g. `["characters", "foreground"]`.
+    layers: Vec<String>,
+    physics: Option<RigidBody>,
+}
+
+#[derive(Deserialize)]
+struct DisplacementExtras {
+    /// Index of the glTF texture holding heights in its first channel.
+    texture: usize,
+    #[serde(flatten)]
+    displacement: Displacement,
+}
+
+/// A mesh built at load time by combining the meshes of `nodes` in order, e.g.
+/// `{"operation": "difference", "nodes": [0, 1]}`. The listed nodes are not drawn themselves.
+#[derive(Deserialize)]
+struct CsgMesh {
+    operation: csg::Operation,
+    nodes: Vec<usize>,
+}
+
+/// Builds a scene from the glTF file at `path` in `source`, reading its external buffers and
+/// images, and the files its extras name, from `source` too.
+pub fn load_gltf_from(source: &dyn AssetSource, path: &Path) -> gltf::Result<Scene> {
+    let bytes = source.read(path).map_err(gltf::Error::Io)?;
+    let files = Some((source, path.parent().unwrap_or(Path::new(""))));
+    let (doc, buffers, images) = import_gltf(&bytes, files)?;
+    build_gltf(doc, buffers, images, files)
+}
+
+/// Builds a scene from a GLB file or a glTF file with its buffers and images embedded.
+///
+/// Files the scene extras point to, such as custom shaders, cannot be found without a
+/// directory and are skipped.
+pub fn load_gltf_bytes(bytes: &[u8]) -> gltf::Result<Scene> {
+    let (doc, buffers, images) = import_gltf(bytes, None)?;
+    build_gltf(doc, buffers, images, None)
+}
+
+/// Required extensions the loader reads itself, which the gltf crate would reject.
+const HANDLED_EXTENSIONS: &[&str] = &[
+    "KHR_mesh_quantization",
+    #[cfg(feature = "draco")]
+    "KHR_draco_mesh_compression",
+];
+
+/// Where the files a glTF document refers to are read from: a source and the document's
+/// directory in it.
+type GltfFiles<'a> = Option<(&'a dyn AssetSource, &'a Path)>;
+
+/// Parses a glTF or GLB file and reads its buffers and images, the external ones from `files`.
+fn import_gltf(bytes: &[u8], files: GltfFiles) -> gltf::Result<(Document, Vec<Data>, Vec<ImageData>)> {
+    let Gltf { document, mut blob } = Gltf::from_slice_without_validation(bytes)?;
+    let mut json = document.into_json();
+    json.extensions_required.retain(|name| !HANDLED_EXTENSIONS.contains(&name.as_str()));
+    let document = Document::from_json(json)?;
+    let read = |uri: &str| {
+        let (source, dir) = files.ok_or(gltf::Error::ExternalReferenceInSliceImport)?;
+        source.read(&dir.join(uri_path(uri))).map_err(gltf::Error::Io)
+    };
+
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => {
+                let mut data = read(uri)?;
+                data.resize(data.len().next_multiple_of(4), 0);
+                Data(data)
+            }
+            source => Data::from_source_and_blob(source, None, &mut blob)?,
+        };
+        if data.len() < buffer.length() {
+            return Err(gltf::Error::BufferLength {
+                buffer: buffer.index(),
+                expected: buffer.length(),
+                actual: data.len(),
+            });
+        }
+        buffers.push(data);
+    }
+
+    let mut images = Vec::new();
+    for image in document.images() {
+        let data = match image.source() {
+            gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                image_data(image::load_from_memory(&read(uri)?)?)
+            }
+            // Embedded images are decoded in memory; the base path only marks the import as
+            // allowed to see them.
+            source => ImageData::from_source(source, Some(Path::new("")), &buffers)?,
+        };
+        images.push(data);
+    }
+    Ok((document, buffers, images))
+}
+
+/// The relative path a glTF URI refers to, with percent escapes decoded.
+fn uri_path(uri: &str) -> PathBuf {
+    PathBuf::from(urlencoding::decode(uri).unwrap_or(Cow::Borrowed(uri)).as_ref())
+}
+
+/// Pixels of a decoded image in the layout glTF importers produce, converting formats glTF has
+/// no name for to 8-bit RGBA.
+fn image_data(image: DynamicImage) -> ImageData {
+    let (width, height) = (image.width(), image.height());
+    let (format, image) = match image {
+        DynamicImage::ImageLuma8(_) => (Format::R8, image),
+        DynamicImage::ImageLumaA8(_) => (Format::R8G8, image),
+        DynamicImage::ImageRgb8(_) => (Format::R8G8B8, image),
+        DynamicImage::ImageRgba8(_) => (Format::R8G8B8A8, image),
+        DynamicImage::ImageLuma16(_) => (Format::R16, image),
+        DynamicImage::ImageLumaA16(_) => (Format::R16G16, image),
+        DynamicImage::ImageRgb16(_) => (Format::R16G16B16, image),
+        DynamicImage::ImageRgba16(_) => (Format::R16G16B16A16, image),
+        DynamicImage::ImageRgb32F(_) => (Format::R32G32B32FLOAT, image),
+        DynamicImage::ImageRgba32F(_) => (Format::R32G32B32A32FLOAT, image),
+        image => (Format::R8G8B8A8, DynamicImage::ImageRgba8(image.to_rgba8())),
+    };
+    ImageData {
+        pixels: image.into_bytes(),
+        format,
+        width,
+        height,
+    }
+}
+
+/// Reads the text file `name` in directory `dir` of `source`, returning its path alongside.
+fn read_text(source: &dyn AssetSource, dir: &Path, name: &str) -> gltf::Result<(PathBuf, String)> {
+    let path = dir.join(name);
+    let text = source.read(&path)
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)))
+        .map_err(gltf::Error::Io)?;
+    Ok((path, text))
+}
+
+/// Builds a scene from an imported glTF document, reading the files its extras refer to from
+/// `files`.
+fn build_gltf(doc: Document, buffers: Vec<Data>, images: Vec<ImageData>, files: GltfFiles) -> gltf::Result<Scene> {
+    let extras = scene_extras(&doc);
+    let mut scene = Scene {
+        sources: files.map(|(_, dir)| external_files(&doc, dir)).unwrap_or_default(),
+        textures: doc.textures().len(),
+        atmosphere: extras.atmosphere,
+        overrides: extras.overrides,
+        emitters: extras.particles,
+        audio: extras.audio,
+        ..Scene::default()
+    };
+    if let Some(texture) = extras.texture {
+        scene.set_procedural_texture(texture);
+    }
+    if let Some(material) = &extras.material
+        && let Err(err) = scene.set_material_graph(material)
+    {
+        log::warn!("Ignoring invalid material graph: {err}");
+    }
+    if files.is_none() && (extras.materialx.is_some() || extras.shader.is_some()) {
+        log::warn!("Ignoring files referenced by the extras of a scene loaded from memory");
+    }
+    if let (Some(materialx), Some((source, dir))) = (&extras.materialx, files) {
+        let (materialx_path, xml) = read_text(source, dir, materialx)?;
+        scene.sources.push(materialx_path.clone());
+        let result = MaterialGraph::from_materialx(&xml)
+            .map_err(|err| err.to_string())
+            .and_then(|graph| scene.set_material_graph(&graph).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            log::warn!("Ignoring {}: {err}", materialx_path.display());
+        }
+    }
+    if let (Some(shader), Some((source, dir))) = (&extras.shader, files) {
+        let (shader_path, code) = read_text(source, dir, shader)?;
+        scene.set_custom_shading(code);
+        scene.sources.push(shader_path);
+    }
+    for primitive in doc.meshes().flat_map(|mesh| mesh.primitives()) {
+        if primitive.get(&Semantic::Normals).is_none() {
+            scene.primitives_without_normals += 1;
+        }
+        if primitive.get(&Semantic::TexCoords(0)).is_none() {
+            scene.primitives_without_uvs += 1;
+        }
+        if primitive.material().normal_texture().is_some() && primitive.get(&Semantic::Tangents).is_none() {
+            scene.primitives_without_tangents += 1;
+        }
+    }
+
+    // Nodes referenced as a lower level of detail are drawn through their primary node.
+    let lod_nodes: HashSet<usize> = doc.nodes()
+        .flat_map(|node| lod_ids(&node))
+        .collect();
+
+    let materials: Vec<MaterialExtras> = doc.materials()
+        .map(|material| parse_extras(material.extras(), "material"))
+        .collect();
+    let displacements = displacements(&doc, &materials, &images);
+
+    // Every node is an entity with the node's index.
+    let world = &mut scene.world;
+    let entities: Vec<Entity> = doc.nodes().map(|_| world.spawn()).collect();
+    for node in doc.nodes() {
+        let entity = entities[node.index()];
+        if let Some(name) = node.name() {
+            world.names.insert(entity, name.to_owned());
+        }
+        for child in node.children() {
+            world.parents.insert(entities[child.index()], entity);
+        }
+    }
+
+    // Cameras and lights are placed by the node hierarchy of the scene.
+    for (node, transform) in world_transforms(&doc) {
+        let entity = entities[node.index()];
+        if let Some(camera) = node.camera() {
+            world.cameras.insert(entity, read_camera(&camera, &transform));
+        }
+        if let Some(light) = node.light() {
+            world.lights.insert(entity, read_light(&light, &transform));
+        }
+        world.transforms.insert(entity, transform);
+    }
+
+    let csg_nodes: HashSet<usize> = extras.csg.iter()
+        .flat_map(|csg| csg.nodes.iter().copied())
+        .collect();
+
+    for node in doc.nodes() {
+        if lod_nodes.contains(&node.index()) || csg_nodes.contains(&node.index()) {
+            continue;
+        }
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+        let (vertices, tangents) = read_mesh(&doc, &mesh, &buffers, &displacements);
+        let mut lods = vec![vertices];
+        for id in lod_ids(&node) {
+            if let Some(lod) = doc.nodes().nth(id).and_then(|node| node.mesh()) {
+                lods.push(read_mesh(&doc, &lod, &buffers, &displacements).0);
+            }
+        }
+        if lods.len() == 1 {
+            let generated = simplify::lod_chain(&lods[0], GENERATED_LODS, MIN_LOD_TRIANGLES);
+            lods.extend(generated);
+        }
+        // A mesh is hidden from a kind of ray when its node or any of its materials hides it.
+        let node_extras: NodeExtras = parse_extras(node.extras(), "node");
+        let visibility = mesh.primitives()
+            .filter_map(|primitive| primitive.material().index())
+            .filter_map(|index| materials.get(index).and_then(|extras| extras.visibility))
+            .fold(node_extras.visibility.unwrap_or_default(), Visibility::intersect);
+        let entity = entities[node.index()];
+        if let Some(index) = scene.add_mesh_to(entity, lods, tangents) {
+            scene.set_visibility(index, visibility);
+            scene.set_layers(index, node_extras.layers);
+            if let Some(name) = node.name().or(mesh.name()) {
+                scene.set_name(index, name);
+            }
+            let mut used: Vec<usize> = mesh.primitives().filter_map(|primitive| primitive.material().index()).collect();
+            used.sort_unstable();
+            used.dedup();
+            scene.world.materials.insert(entity, used);
+            if let Some(body) = node_extras.physics {
+                scene.world.bodies.insert(entity, body);
+            }
+        }
+    }
+
+    for csg in &extras.csg {
+        let mut operands = csg.nodes.iter().filter_map(|&id| {
+            let mesh = doc.nodes().nth(id).and_then(|node| node.mesh());
+            if mesh.is_none() {
+                log::warn!("CSG node {id} has no mesh");
+            }
+            mesh.map(|mesh| read_mesh(&doc, &mesh, &buffers, &displacements).0)
+        });
+        let Some(first) = operands.next() else {
+            continue;
+        };
+        let combined = operands.fold(first, |a, b| csg::apply(csg.operation, &a, &b));
+        let generated = simplify::lod_chain(&combined, GENERATED_LODS, MIN_LOD_TRIANGLES);
+        scene.add_mesh(iter::once(combined).chain(generated).collect());
+    }
+
+    for material in doc.materials() {
+        scene.material_names.push(material.name().map(str::to_owned));
+        let material = material.pbr_metallic_roughness();
+        let base_color = material.base_color_factor();
+        scene.materials.push(Material {
+            ambient: [base_color[0], base_color[1], base_color[2], base_color[3]],
+            diffuse: [0.0, 0.0, 0.0, 0.0],
+            specular: [0.0, 0.0, 0.0, 0.0],
+        });
+    }
+
+    Ok(scene)
+}
+
+/// Builds a terrain scene from a grayscale heightmap, where white is `options.height_scale`.
+///
+/// Colour images are converted to luminance. 16-bit images keep their full precision.
+pub fn load_heightmap<P: AsRef<Path>>(path: P, options: &TerrainOptions) -> image::ImageResult<Scene> {
+    build_heightmap(image::open(path)?, options)
+}
+
+/// Builds a terrain scene from an encoded heightmap image, guessing its format from its contents.
+pub fn load_heightmap_bytes(bytes: &[u8], options: &TerrainOptions) -> image::ImageResult<Scene> {
+    build_heightmap(image::load_from_memory(bytes)?, options)
+}
+
+fn build_heightmap(image: DynamicImage, options: &TerrainOptions) -> image::ImageResult<Scene> {
+    let image = image.into_luma16();
+    let (columns, rows) = (image.width() as usize, image.height() as usize);
+    let heights: Vec<f32> = image.pixels()
+        .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+        .collect();
+
+    let mut scene = Scene::default();
+    for lods in terrain::triangulate(&heights, columns, rows, options) {
+        scene.add_mesh(lods);
+    }
+    scene.materials.push(Material {
+        ambient: TERRAIN_COLOR,
+        diffuse: [0.0, 0.0, 0.0, 0.0],
+        specular: [0.0, 0.0, 0.0, 0.0],
+    });
+    Ok(scene)
+}
+
+/// Builds a scene from a text curve file, drawing every strand as a thin tube in one mesh.
+pub fn load_curves<P: AsRef<Path>>(path: P, options: &CurveOptions) -> io::Result<Scene> {
+    load_curves_bytes(&fs::read(path)?, options)
+}
+
+pub fn load_curves_bytes(bytes: &[u8], options: &CurveOptions) -> io::Result<Scene> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let strands = curves::parse(text)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut scene = Scene::default();
+    scene.add_mesh(curves::tessellate(&strands, options));
+    scene.materials.push(Material {
+        ambient: HAIR_COLOR,
+        diffuse: [0.0, 0.0, 0.0, 0.0],
+        specular: [0.0, 0.0, 0.0, 0.0],
+    });
+    Ok(scene)
+}
+
+/// Builds a scene from a PLY file, as a mesh if it has faces or as splats otherwise.
+pub fn load_ply_bytes(bytes: &[u8]) -> io::Result<Scene> {
+    let mut scene = Scene::default();
+    match ply::parse(bytes)? {
+        PlyData::Mesh(triangles) => {
+            let generated = simplify::lod_chain(&triangles, GENERATED_LODS, MIN_LOD_TRIANGLES);
+            scene.add_mesh(iter::once(triangles).chain(generated).collect());
+        }
+        PlyData::Points(splats) => scene.splats = splats,
+    }
+    scene.materials.push(Material {
+        ambient: scene::DEFAULT_COLOR,
+        diffuse: [0.0, 0.0, 0.0, 0.0],
+        specular: [0.0, 0.0, 0.0, 0.0],
+    });
+    Ok(scene)
+}
+
+/// Builds a scene from an STL mesh, see [`StlOptions`].
+pub fn load_stl<P: AsRef<Path>>(path: P, options: &StlOptions) -> io::Result<Scene> {
+    load_stl_bytes(&fs::read(path)?, options)
+}
+
+pub fn load_stl_bytes(bytes: &[u8], options: &StlOptions) -> io::Result<Scene> {
+    let triangles = stl::parse(bytes, options)?;
+    let generated = simplify::lod_chain(&triangles, GENERATED_LODS, MIN_LOD_TRIANGLES);
+    let mut scene = Scene::default();
+    scene.add_mesh(iter::once(triangles).chain(generated).collect());
+    scene.materials.push(Material {
+        ambient: scene::DEFAULT_COLOR,
+        diffuse: [0.0, 0.0, 0.0, 0.0],
+        specular: [0.0, 0.0, 0.0, 0.0],
+    });
+    Ok(scene)
+}
+
+/// Builds a scene from a text USD layer or a USDZ package, see [`usd`].
+pub fn load_usd_bytes(bytes: &[u8]) -> io::Result<Scene> {
+    let data = usd::parse(bytes)?;
+    let mut scene = Scene::default();
+    for camera in data.cameras {
+        let entity = scene.world.spawn();
+        scene.world.cameras.insert(entity, camera);
+    }
+    for light in data.lights {
+        let entity = scene.world.spawn();
+        if let Some(name) = &light.name {
+            scene.world.names.insert(entity, name.clone());
+        }
+        scene.world.lights.insert(entity, light);
+    }
+    for (name, triangles) in data.meshes {
+        let generated = simplify::lod_chain(&triangles, GENERATED_LODS, MIN_LOD_TRIANGLES);
+        if let Some(index) = scene.add_mesh(iter::once(triangles).chain(generated).collect()) {
+            scene.set_name(index, name);
+        }
+    }
+    let materials = if data.materials.is_empty() {
+        vec![(None, scene::DEFAULT_COLOR)]
+    } else {
+        data.materials.into_iter().map(|(name, color)| (Some(name), color)).collect()
+    };
+    for (name, color) in materials {
+        scene.material_names.push(name);
+        scene.materials.push(Material {
+            ambient: color,
+            diffuse: [0.0, 0.0, 0.0, 0.0],
+            specular: [0.0, 0.0, 0.0, 0.0],
+        });
+    }
+    Ok(scene)
+}
+
+/// Builds a scene holding only a voxel volume from a Mitsuba `.vol` grid.
+pub fn load_volume_bytes(bytes: &[u8]) -> io::Result<Scene> {
+    let mut scene = Scene {
+        volume: Some(Volume::parse(bytes)?),
+        ..Scene::default()
+    };
+    scene.materials.push(Material {
+        ambient: scene::DEFAULT_COLOR,
+        diffuse: [0.0, 0.0, 0.0, 0.0],
+        specular: [0.0, 0.0, 0.0, 0.0],
+    });
+    Ok(scene)
+}
+
+/// Buffers and images the document references by file name, relative to its directory `dir`.
+fn external_files(doc: &Document, dir: &Path) -> Vec<PathBuf> {
+    let buffers = doc.buffers().filter_map(|buffer| match buffer.source() {
+        gltf::buffer::Source::Uri(uri) => Some(uri),
+        gltf::buffer::Source::Bin => None,
+    });
+    let images = doc.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    buffers.chain(images)
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| dir.join(uri_path(uri)))
+        .collect()
+}
+
+fn scene_extras(doc: &Document) -> SceneExtras {
+    doc.default_scene()
+        .or_else(|| doc.scenes().next())
+        .map(|scene| parse_extras(scene.extras(), "scene"))
+        .unwrap_or_default()
+}
+
+/// Parses a glTF object's `extras`, falling back to defaults when they are absent or invalid.
+fn parse_extras<T: DeserializeOwned + Default>(extras: &gltf::json::Extras, object: &str) -> T {
+    let Some(extras) = extras else {
+        return T::default();
+    };
+    serde_json::from_str(extras.get()).unwrap_or_else(|err| {
+        log::warn!("Ignoring invalid {object} extras: {err}");
+        T::default()
+    })
+}
+
+/// Nodes of the default scene and their world transforms.
+fn world_transforms(doc: &Document) -> Vec<(Node<'_>, Mat4)> {
+    fn visit<'a>(node: Node<'a>, parent: &Mat4, out: &mut Vec<(Node<'a>, Mat4)>) {
+        let transform = camera::mul(parent, &node.transform().matrix());
+        for child in node.children() {
+            visit(child, &transform, out);
+        }
+        out.push((node, transform));
+    }
+    let mut out = vec![];
+    if let Some(scene) = doc.default_scene().or_else(|| doc.scenes().next()) {
+        for node in scene.nodes() {
+            visit(node, &camera::IDENTITY, &mut out);
+        }
+    }
+    out
+}
+
+fn read_camera(camera: &gltf::Camera, transform: &Mat4) -> Camera {
+    // glTF cameras have no focus distance, so orbiting pivots around the origin.
+    let eye = camera::transform_point(transform, vec3![0.0, 0.0, 0.0]);
+    let defaults = Camera::default();
+    match camera.projection() {
+        gltf::camera::Projection::Perspective(perspective) => {
+            let distance = eye.length().max(perspective.znear());
+            Camera {
+                fovy: perspective.yfov().to_degrees(),
+                znear: perspective.znear(),
+                zfar: perspective.zfar().unwrap_or(defaults.zfar),
+                ..Camera::from_transform(transform, distance)
+            }
+        }
+        gltf::camera::Projection::Orthographic(orthographic) => {
+            let distance = eye.length().max(orthographic.znear()).max(1e-3);
+            Camera {
+                fovy: 2.0 * (orthographic.ymag() / distance).atan().to_degrees(),
+                znear: orthographic.znear(),
+                zfar: orthographic.zfar(),
+                projection: Projection::Orthographic,
+                ..Camera::from_transform(transform, distance)
+            }
+        }
+    }
+}
+
+fn read_light(light: &gltf::khr_lights_punctual::Light, transform: &Mat4) -> Light {
+    let position = camera::transform_point(transform, vec3![0.0, 0.0, 0.0]);
+    let direction = camera::transform_point(transform, vec3![0.0, 0.0, -1.0]) - position;
+    Light {
+        name: light.name().map(str::to_owned),
+        kind: match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+            gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+            gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } => LightKind::Spot {
+                inner_cone: inner_cone_angle,
+                outer_cone: outer_cone_angle,
+            },
+        },
+        position,
+        direction: direction.normalize(),
+        color: light.color(),
+        intensity: light.intensity(),
+        range: light.range(),
+    }
+}
+
+/// Node indices listed by the node's `MSFT_lod` extension, finest first.
+fn lod_ids(node: &Node) -> Vec<usize> {
+    node.extensions()
+        .and_then(|extensions| extensions.get("MSFT_lod"))
+        .and_then(|lod| lod.get("ids"))
+        .and_then(|ids| ids.as_array())
+        .map(|ids| ids.iter()
+            .filter_map(|id| id.as_u64())
+            .map(|id| id as usize)
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Reads every primitive of a mesh into a flat triangle list.
+/// Displacement settings and height texture per material index.
+type Displacements = Vec<Option<(Displacement, HeightTexture)>>;
+
+fn displacements(doc: &Document, materials: &[MaterialExtras], images: &[ImageData]) -> Displacements {
+    materials.iter()
+        .map(|extras| {
+            let displacement = extras.displacement.as_ref()?;
+            let image = doc.textures()
+                .nth(displacement.texture)
+                .and_then(|texture| images.get(texture.source().index()));
+            let Some(heights) = image.and_then(height_texture) else {
+                log::warn!("Displacement texture {} is missing or unsupported", displacement.texture);
+                return None;
+            };
+            Some((displacement.displacement, heights))
+        })
+        .collect()
+}
+
+/// Reads the first channel of an 8 or 16-bit image as heights.
+fn height_texture(image: &ImageData) -> Option<HeightTexture> {
+    let (channels, bytes) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        _ => return None,
+    };
+    let texels = image.pixels.chunks_exact(channels * bytes)
+        .map(|texel| match bytes {
+            1 => texel[0] as f32 / u8::MAX as f32,
+            _ => u16::from_le_bytes([texel[0], texel[1]]) as f32 / u16::MAX as f32,
+        })
+        .collect();
+    HeightTexture::new(image.width as usize, image.height as usize, texels)
+}
+
+/// Reads the triangles of `mesh`, three vertices each, and their tangents when any primitive is
+/// normal mapped: from the `TANGENT` attribute, or generated in MikkTSpace from the normals and
+/// texture coordinates. Vertices of other primitives, and of displaced or Draco-compressed ones,
+/// get zero tangents.
+fn read_mesh(doc: &Document, mesh: &gltf::Mesh, buffers: &[Data], displacements: &Displacements) -> (Vec<Vec3>, Vec<[f32; 4]>) {
+    let mut vertices = vec![];
+    let mut tangents = vec![];
+    let mut normal_mapped = false;
+    for primitive in mesh.primitives() {
+        tangents.resize(vertices.len(), [0.0; 4]);
+        normal_mapped |= primitive.material().normal_texture().is_some();
+        if let Some(triangles) = read_draco(doc, &primitive, buffers) {
+            vertices.extend(triangles);
+            continue;
+        }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions = primitive.get(&Semantic::Positions).and_then(|positions| read_floats::<3>(positions, buffers));
+        let Some(corners) = positions else {
+            continue;
+        };
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..corners.len() as u32).collect(),
+        };
+        let triangles: Option<Vec<Vec3>> = indices.iter()
+            .map(|&index| corners.get(index as usize).copied().map(Vec3::from))
+            .collect();
+        let Some(triangles) = triangles else {
+            log::warn!("Skipping primitive {} of mesh {}: an index is past its vertices", primitive.index(), mesh.index());
+            continue;
+        };
+
+        let displacement = primitive.material().index()
+            .and_then(|index| displacements.get(index))
+            .and_then(Option::as_ref);
+        let normals: Option<Vec<Vec3>> = primitive.get(&Semantic::Normals)
+            .and_then(|normals| read_floats::<3>(normals, buffers))
+            .and_then(|normals| indices.iter().map(|&index| normals.get(index as usize).copied().map(Vec3::from)).collect());
+        let uvs: Option<Vec<[f32; 2]>> = primitive.get(&Semantic::TexCoords(0))
+            .and_then(|uvs| read_floats::<2>(uvs, buffers))
+            .and_then(|uvs| indices.iter().map(|&index| uvs.get(index as usize).copied()).collect());
+        if let (Some((displacement, heights)), Some(normals), Some(uvs)) = (displacement, &normals, &uvs) {
+            vertices.extend(displace::displace(&triangles, normals, uvs, heights, displacement));
+            continue;
+        }
+        if primitive.material().normal_texture().is_some() {
+            let stored = primitive.get(&Semantic::Tangents)
+                .and_then(|tangents| read_floats::<4>(tangents, buffers))
+                .and_then(|stored| indices.iter().map(|&index| stored.get(index as usize).copied()).collect::<Option<Vec<_>>>());
+            let generated = || Some(tangents::generate(&triangles, normals.as_ref()?, uvs.as_ref()?));
+            tangents.extend(stored.or_else(generated).unwrap_or_default());
+        }
+        vertices.extend(triangles);
+    }
+    if normal_mapped {
+        tangents.resize(vertices.len(), [0.0; 4]);
+    } else {
+        tangents.clear();
+    }
+    (vertices, tangents)
+}
+
+/// Decodes the triangles of a primitive compressed with `KHR_draco_mesh_compression`, or `None`
+/// when it is not compressed. Primitives that fail to decode are left out.
+#[cfg(feature = "draco")]
+fn read_draco(doc: &Document, primitive: &gltf::Primitive, buffers: &[Data]) -> Option<Vec<Vec3>> {
+    let extension = primitive.extensions().and_then(|extensions| extensions.get("KHR_draco_mesh_compression"))?;
+    let view = extension.get("bufferView")
+        .and_then(|view| view.as_u64())
+        .and_then(|view| doc.views().nth(view as usize));
+    let position = extension.get("attributes")
+        .and_then(|attributes| attributes.get("POSITION"))
+        .and_then(|position| position.as_u64());
+    let bytes = view.and_then(|view| {
+        buffers.get(view.buffer().index())?.get(view.offset()..view.offset() + view.length())
+    });
+    let (Some(bytes), Some(position)) = (bytes, position) else {
+        log::warn!("Skipping Draco primitive without a buffer view or positions");
+        return Some(Vec::new());
+    };
+    match draco::decode(bytes, position as u32) {
+        Ok(triangles) => Some(triangles),
+        Err(err) => {
+            log::warn!("Skipping Draco primitive: {err}");
+            Some(Vec::new())
+        }
+    }
+}
+
+#[cfg(not(feature = "draco"))]
+fn read_draco(_: &Document, _: &gltf::Primitive, _: &[Data]) -> Option<Vec<Vec3>> {
+    None
+}
+
+/// Reads a vertex attribute as floats, including the integer component types
+/// `KHR_mesh_quantization` allows, which are scaled to `-1..=1` or `0..=1` when normalized.
+///
+/// Sparse accessors are only read with float components.
+fn read_floats<const N: usize>(accessor: Accessor, buffers: &[Data]) -> Option<Vec<[f32; N]>>
+where
+    [f32; N]: accessor::Item,
+{
+    let data_type = accessor.data_type();
+    if data_type == DataType::F32 {
+        let iter = accessor::Iter::new(accessor, |buffer| Some(&buffers[buffer.index()]))?;
+        return Some(iter.collect());
+    }
+    if accessor.sparse().is_some() {
+        log::warn!("Skipping sparse accessor {} with {data_type:?} components", accessor.index());
+        return None;
+    }
+    let view = accessor.view()?;
+    let size = data_type.size();
+    let stride = view.stride().unwrap_or(size * N);
+    let start = view.offset() + accessor.offset();
+    let data = buffers.get(view.buffer().index())?.get(start..start + view.length())?;
+    let normalized = accessor.normalized();
+    let component = |bytes: &[u8]| {
+        let (value, max) = match data_type {
+            DataType::I8 => (bytes[0] as i8 as f32, i8::MAX as f32),
+            DataType::U8 => (bytes[0] as f32, u8::MAX as f32),
+            DataType::I16 => (i16::from_le_bytes([bytes[0], bytes[1]]) as f32, i16::MAX as f32),
+            DataType::U16 => (u16::from_le_bytes([bytes[0], bytes[1]]) as f32, u16::MAX as f32),
+            DataType::U32 => (u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32, u32::MAX as f32),
+            DataType::F32 => unreachable!(),
+        };
+        if normalized { (value / max).max(-1.0) } else { value }
+    };
+    (0..accessor.count())
+        .map(|i| {
+            let item = data.get(i * stride..i * stride + size * N)?;
+            Some(std::array::from_fn(|c| component(&item[c * size..])))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    /// The binary chunk of a GLB file being built, with the views and accessors into it.
+    #[derive(Default)]
+    struct Bin {
+        data: Vec<u8>,
+        views: Vec<Value>,
+        accessors: Vec<Value>,
+    }
+
+    impl Bin {
+        /// Adds `bytes` as a buffer view and returns its index.
+        fn view(&mut self, bytes: &[u8]) -> usize {
+            self.data.resize(self.data.len().next_multiple_of(4), 0);
+            self.views.push(json!({"buffer": 0, "byteOffset": self.data.len(), "byteLength": bytes.len()}));
+            self.data.extend_from_slice(bytes);
+            self.views.len() - 1
+        }
+
+        /// Adds a float accessor over `items` and returns its index.
+        fn floats<const N: usize>(&mut self, items: &[[f32; N]]) -> usize {
+            let view = self.view(bytemuck::cast_slice(items));
+            let kind = ["SCALAR", "VEC2", "VEC3", "VEC4"][N - 1];
+            let min: Vec<f32> = (0..N).map(|i| items.iter().map(|item| item[i]).fold(f32::MAX, f32::min)).collect();
+            let max: Vec<f32> = (0..N).map(|i| items.iter().map(|item| item[i]).fold(f32::MIN, f32::max)).collect();
+            self.accessors.push(json!({
+                "bufferView": view, "componentType": 5126, "count": items.len(), "type": kind, "min": min, "max": max,
+            }));
+            self.accessors.len() - 1
+        }
+
+        /// Adds an accessor over 32-bit `indices` and returns its index.
+        fn indices(&mut self, indices: &[u32]) -> usize {
+            let view = self.view(bytemuck::cast_slice(indices));
+            self.accessors.push(json!({"bufferView": view, "componentType": 5125, "count": indices.len(), "type": "SCALAR"}));
+            self.accessors.len() - 1
+        }
+
+        /// A GLB file of `json`, given the views and accessors, with this as its binary chunk.
+        fn glb(mut self, mut json: Value) -> Vec<u8> {
+            self.data.resize(self.data.len().next_multiple_of(4), 0);
+            json["buffers"] = json!([{"byteLength": self.data.len()}]);
+            json["bufferViews"] = self.views.into();
+            json["accessors"] = self.accessors.into();
+            json["asset"] = json!({"version": "2.0"});
+            let mut json = json.to_string().into_bytes();
+            json.resize(json.len().next_multiple_of(4), b' ');
+
+            let length = 12 + 8 + json.len() + 8 + self.data.len();
+            let mut glb = Vec::with_capacity(length);
+            glb.extend(b"glTF");
+            glb.extend(2u32.to_le_bytes());
+            glb.extend((length as u32).to_le_bytes());
+            glb.extend((json.len() as u32).to_le_bytes());
+            glb.extend(b"JSON");
+            glb.extend(json);
+            glb.extend((self.data.len() as u32).to_le_bytes());
+            glb.extend(b"BIN\0");
+            glb.extend(self.data);
+            glb
+        }
+    }
+
+    fn assert_tangents(tangents: &[[f32; 4]], expected: [f32; 4]) {
+        let close = |tangent: &[f32; 4]| tangent.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5);
+        assert!(tangents.iter().all(close), "{tangents:?} aren't all {expected:?}");
+    }
+
+    #[test]
+    fn normal_mapped_primitives_get_tangents() {
+        // Two quads facing +Z either side of x = 0, with their UVs mirrored across it.
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let positions: Vec<[f32; 3]> = [-1.0, 0.0].into_iter()
+            .flat_map(|x| corners.map(|[cx, cy]| [x + cx, cy, 0.0]))
+            .collect();
+        let uvs: Vec<[f32; 2]> = positions.iter().map(|p| [p[0].abs(), p[1]]).collect();
+        let mut png = Vec::new();
+        image::RgbaImage::new(1, 1)
+            .write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let mut bin = Bin::default();
+        let attributes = json!({
+            "POSITION": bin.floats(&positions),
+            "NORMAL": bin.floats(&[[0.0, 0.0, 1.0]; 12]),
+            "TEXCOORD_0": bin.floats(&uvs),
+        });
+        let mut with_tangents = attributes.clone();
+        with_tangents["TANGENT"] = bin.floats(&[[0.0, 1.0, 0.0, 1.0]; 12]).into();
+        let image = bin.view(&png);
+        let glb = bin.glb(json!({
+            "images": [{"bufferView": image, "mimeType": "image/png"}],
+            "textures": [{"source": 0}],
+            "materials": [{"normalTexture": {"index": 0}}],
+            "meshes": [
+                {"primitives": [{"attributes": attributes, "material": 0}]},
+                {"primitives": [{"attributes": attributes}]},
+                {"primitives": [{"attributes": with_tangents, "material": 0}]},
+            ],
+            "nodes": [{"mesh": 0}, {"mesh": 1}, {"mesh": 2}],
+            "scenes": [{"nodes": [0, 1, 2]}],
+            "scene": 0,
+        }));
+        let scene = Scene::from_gltf_bytes(&glb).unwrap();
+        assert_eq!(scene.primitives_without_tangents, 1);
+
+        // Generated tangents follow their triangles however the mesh reorders them.
+        let (vertices, tangents) = (scene.mesh_vertices(0), scene.mesh_tangents(0));
+        assert_eq!(tangents.len(), vertices.len());
+        for (triangle, tangents) in vertices.chunks_exact(3).zip(tangents.chunks_exact(3)) {
+            let mirrored = triangle.iter().map(|v| v.x).sum::<f32>() < 0.0;
+            assert_tangents(tangents, if mirrored { [-1.0, 0.0, 0.0, -1.0] } else { [1.0, 0.0, 0.0, 1.0] });
+        }
+        assert!(scene.mesh_tangents(1).is_empty());
+        assert_eq!(scene.mesh_tangents(2).len(), 12);
+        assert_tangents(scene.mesh_tangents(2), [0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn primitives_indexing_past_their_vertices_are_skipped() {
+        let mut bin = Bin::default();
+        let positions = bin.floats(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        let normals = bin.floats(&[[0.0, 0.0, 1.0]; 2]);
+        let valid = bin.indices(&[0, 1, 2]);
+        let invalid = bin.indices(&[0, 1, 3]);
+        let glb = bin.glb(json!({
+            "meshes": [{"primitives": [
+                {"attributes": {"POSITION": positions}, "indices": invalid},
+                {"attributes": {"POSITION": positions, "NORMAL": normals}, "indices": valid},
+            ]}],
+            "nodes": [{"mesh": 0}],
+            "scenes": [{"nodes": [0]}],
+            "scene": 0,
+        }));
+        let scene = Scene::from_gltf_bytes(&glb).unwrap();
+        // Only the valid primitive is left, despite the normals it is short of.
+        assert_eq!(scene.mesh_vertices(0), [vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0]]);
+    }
+}
@@ -1,6 +1,642 @@
-use ray_tracer::RayTracer;
+use std::{
+    env,
+    iter,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process,
+};
+
+use ray_tracer::{
+    bench::{self, BenchOptions},
+    compare,
+    distributed::{self, DistributedOptions},
+    headless::{self, RenderOptions},
+    preview::{self, PreviewOptions},
+    stream::{self, StreamOptions, StreamOutput},
+    Annotation,
+    Atmosphere,
+    Camera,
+    ClipPlane,
+    GroundPlane,
+    Icon,
+    MidiControl,
+    RayTracer,
+    Scene,
+    Script,
+    Session,
+    Settings,
+    Sky,
+    Stereo,
+    Vec3,
+};
+
+fn load_scene(path: &str) -> Scene {
+    Scene::load(path).unwrap_or_else(|err| {
+        eprintln!("Failed to load {path}: {err}");
+        process::exit(1);
+    })
+}
+
+fn load_script(path: &str) -> Script {
+    Script::load(path).unwrap_or_else(|err| {
+        eprintln!("Failed to load {path}: {err}");
+        process::exit(1);
+    })
+}
+
+/// Loads `path` with `layers` merged over it.
+fn load_layered(path: &str, layers: &[String]) -> Scene {
+    Scene::load_layered(path, layers).unwrap_or_else(|err| {
+        eprintln!("Failed to load {path}: {err}");
+        process::exit(1);
+    })
+}
+
+/// Reads the window icon at `path`, exiting with an error if it can't be decoded.
+fn load_icon(path: &str) -> Icon {
+    let image = match image::open(path) {
+        Ok(image) => image.into_rgba8(),
+        Err(err) => {
+            eprintln!("Failed to load {path}: {err}");
+            process::exit(1);
+        }
+    };
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height).unwrap()
+}
+
+/// Parses `count` comma-separated numbers, exiting with a usage error otherwise.
+fn parse_numbers(flag: &str, value: Option<String>, count: usize) -> Vec<f32> {
+    let numbers: Vec<f32> = value.unwrap_or_default()
+        .split(',')
+        .filter_map(|number| number.trim().parse().ok())
+        .collect();
+    if numbers.len() != count {
+        eprintln!("{flag} expects {count} comma-separated numbers");
+        process::exit(2);
+    }
+    numbers
+}
+
+/// Parses the `X,Y,Z TEXT` arguments of `--annotate`.
+fn parse_annotation(args: &mut impl Iterator<Item = String>) -> Annotation {
+    let numbers = parse_numbers("--annotate", args.next(), 3);
+    Annotation::new(Vec3::from([numbers[0], numbers[1], numbers[2]]), args.next().unwrap_or_default())
+}
+
+/// Parses the `X,Y,Z,NX,NY,NZ` point and normal of `--clip`.
+fn parse_clip_plane(value: Option<String>) -> ClipPlane {
+    let numbers = parse_numbers("--clip", value, 6);
+    ClipPlane {
+        point: Vec3::from([numbers[0], numbers[1], numbers[2]]),
+        normal: Vec3::from([numbers[3], numbers[4], numbers[5]]),
+    }
+}
+
+/// Parses the `CONTROLLER PATH MIN,MAX` arguments of `--midi-cc`.
+fn parse_midi_control(args: &mut impl Iterator<Item = String>) -> MidiControl {
+    let Some(controller) = args.next().and_then(|controller| controller.parse().ok()) else {
+        eprintln!("--midi-cc expects a controller number from 0 to 127");
+        process::exit(2);
+    };
+    let path = args.next().unwrap_or_default();
+    let range = parse_numbers("--midi-cc", args.next(), 2);
+    MidiControl {
+        channel: None,
+        controller,
+        path,
+        min: range[0],
+        max: range[1],
+    }
+}
+
+/// Red section caps for `--section-caps`.
+const SECTION_CAPS: [f32; 3] = [0.8, 0.2, 0.15];
+
+/// Frame rate `--frames` sequences are timed at, for scripts' `time`.
+const FPS: f32 = 30.0;
+
+/// Most samples `--target-error` takes unless `--samples` says otherwise.
+const MAX_SAMPLES: u32 = 256;
+
+fn run_bench(mut args: impl Iterator<Item = String>) {
+    let mut options = BenchOptions::default();
+    let mut scene_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => options.frames = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.frames),
+            "--csv" => options.csv_path = args.next().map(Into::into),
+            "--turntable" => options.turntable = args.next().and_then(|degrees| degrees.parse().ok()),
+            _ => scene_path = Some(arg),
+        }
+    }
+    let Some(scene_path) = scene_path else {
+        eprintln!("Usage: ray-tracer bench <scene.gltf> [--frames N] [--csv FILE] [--turntable DEGREES]");
+        process::exit(2);
+    };
+    match bench::run(load_scene(&scene_path), Settings::default(), &options) {
+        Ok(report) => println!("{report}"),
+        Err(err) => {
+            eprintln!("Benchmark failed: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// `out.png` with `suffix` inserted before the extension, e.g. `out.id.png`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map_or("png".into(), |extension| extension.to_string_lossy());
+    path.with_file_name(format!("{stem}.{suffix}.{extension}"))
+}
+
+fn run_render(mut args: impl Iterator<Item = String>) {
+    let mut options = RenderOptions::default();
+    let mut settings = Settings::default();
+    let mut scene_path = None;
+    let mut output = PathBuf::from("render.png");
+    let mut script = None;
+    let mut frames = None;
+    let mut samples = None;
+    let mut turntable = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = args.next().map(Into::into).unwrap_or(output),
+            "--width" => options.width = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.width),
+            "--height" => options.height = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.height),
+            "--script" => script = args.next().map(|path| load_script(&path)),
+            "--frames" => frames = args.next().and_then(|n| n.parse::<u64>().ok()),
+            "--turntable" => turntable = args.next().and_then(|degrees| degrees.parse::<f32>().ok()),
+            "--layer" => options.layers.extend(args.next()),
+            "--id-matte" => options.id_matte = true,
+            "--transparent" => settings.transparent_background = true,
+            "--grid" => settings.overlays.grid = true,
+            "--axes" => settings.overlays.axes = true,
+            "--annotate" => settings.annotations.push(parse_annotation(&mut args)),
+            "--clip" => settings.clip_planes.push(parse_clip_plane(args.next())),
+            "--section-caps" => settings.section_caps = Some(SECTION_CAPS),
+            "--explode" => settings.explode = args.next().and_then(|factor| factor.parse().ok()).unwrap_or(1.0),
+            "--backplate" => settings.backplate = args.next().map(Into::into),
+            "--single-gpu" => options.multi_gpu = false,
+            "--samples" => samples = args.next().and_then(|n| n.parse().ok()),
+            "--target-error" => options.target_error = args.next().and_then(|error| error.parse().ok()),
+            _ => scene_path = Some(arg),
+        }
+    }
+    let Some(scene_path) = scene_path else {
+        eprintln!("Usage: ray-tracer render <scene.gltf> [-o FILE] [--width W] [--height H] [--layer NAME]... [--id-matte] [--transparent] [--backplate IMAGE] [--grid] [--axes] [--annotate X,Y,Z TEXT]... [--clip X,Y,Z,NX,NY,NZ]... [--section-caps] [--explode FACTOR] [--single-gpu] [--samples N] [--target-error E] [--script FILE] [--frames N] [--turntable DEGREES]");
+        process::exit(2);
+    };
+    options.samples = samples.unwrap_or(if options.target_error.is_some() { MAX_SAMPLES } else { 1 });
+    let mut scene = load_scene(&scene_path);
+    let mut camera = scene.default_camera();
+    if let Some(script) = &mut script
+        && let Err(err) = script.run(&mut scene, &mut settings, &mut camera)
+    {
+        eprintln!("Script failed: {err}");
+        process::exit(1);
+    }
+    let Some(frames) = frames else {
+        write_render(&output, headless::render(scene, settings, camera, &options));
+        return;
+    };
+    // Numbered frames, each after the script's `on_frame` has run for it and
+    // orbited `--turntable` degrees over the sequence about the scene's centre.
+    let center = scene.stats().center();
+    for frame in 1..=frames {
+        if let Some(script) = &mut script
+            && let Err(err) = script.on_frame(frame, (frame - 1) as f32 / FPS, &mut settings, &mut camera)
+        {
+            eprintln!("Script failed: {err}");
+            process::exit(1);
+        }
+        let camera = match turntable {
+            Some(degrees) => camera.orbit(center, degrees * (frame - 1) as f32 / frames as f32),
+            None => camera,
+        };
+        let out = headless::render(scene.clone(), settings.clone(), camera, &options);
+        write_render(&with_suffix(&output, &format!("{frame:04}")), out);
+    }
+}
+
+/// Writes `out` to `output`, its layers and ID matte next to it.
+fn write_render(output: &Path, out: headless::RenderOutput) {
+    if out.convergence.samples > 1 {
+        println!("{}: {}", output.display(), out.convergence);
+    }
+    let images = iter::once((output.to_owned(), out.image))
+        .chain(out.layers.into_iter().map(|(layer, image)| (with_suffix(output, &layer), image)))
+        .chain(out.id_matte.map(|image| (with_suffix(output, "id"), image)));
+    for (path, image) in images {
+        if let Err(err) = headless::save(&image, &path) {
+            eprintln!("Failed to write {}: {err}", path.display());
+            process::exit(1);
+        }
+    }
+}
+
+fn run_distribute(mut args: impl Iterator<Item = String>) {
+    let mut options = DistributedOptions::default();
+    let mut scene_path = None;
+    let mut output = PathBuf::from("render.png");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = args.next().map(Into::into).unwrap_or(output),
+            "--width" => options.width = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.width),
+            "--height" => options.height = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.height),
+            "--tile" => options.tile_size = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.tile_size),
+            "--retries" => options.retries = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.retries),
+            "--workers" => options.workers.extend(args.next().unwrap_or_default().split(',').map(str::to_owned)),
+            _ => scene_path = Some(arg),
+        }
+    }
+    let Some(scene_path) = scene_path else {
+        eprintln!("Usage: ray-tracer distribute <scene.gltf> --workers HOST:PORT,... [-o FILE] [--width W] [--height H] [--tile N] [--retries N]");
+        process::exit(2);
+    };
+    let camera = load_scene(&scene_path).default_camera();
+    let image = distributed::render(scene_path.into(), camera, &options).unwrap_or_else(|err| {
+        eprintln!("Distributed render failed: {err}");
+        process::exit(1);
+    });
+    if let Err(err) = headless::save(&image, &output) {
+        eprintln!("Failed to write {}: {err}", output.display());
+        process::exit(1);
+    }
+}
+
+fn run_worker(mut args: impl Iterator<Item = String>) {
+    let mut address = "127.0.0.1:7878".to_owned();
+    let mut root = PathBuf::from(".");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--address" => address = args.next().unwrap_or(address),
+            "--root" => root = args.next().map(Into::into).unwrap_or(root),
+            _ => {}
+        }
+    }
+    if let Err(err) = distributed::work(&address, &root, Settings::default()) {
+        eprintln!("Failed to take jobs on {address}: {err}");
+        process::exit(1);
+    }
+}
+
+fn run_serve(mut args: impl Iterator<Item = String>) {
+    let mut options = PreviewOptions::default();
+    let mut scene_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--address" => options.address = args.next().unwrap_or(options.address),
+            "--width" => options.width = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.width),
+            "--height" => options.height = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.height),
+            "--format" => match args.next().unwrap_or_default().parse() {
+                Ok(format) => options.format = format,
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+            },
+            "--quality" => options.quality = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.quality),
+            _ => scene_path = Some(arg),
+        }
+    }
+    let Some(scene_path) = scene_path else {
+        eprintln!("Usage: ray-tracer serve <scene.gltf> [--address HOST:PORT] [--width W] [--height H] [--format jpeg|png] [--quality Q]");
+        process::exit(2);
+    };
+    let scene = load_scene(&scene_path);
+    let camera = scene.default_camera();
+    if let Err(err) = preview::serve(scene, Settings::default(), camera, &options) {
+        eprintln!("Failed to serve on {}: {err}", options.address);
+        process::exit(1);
+    }
+}
+
+fn run_compare(mut args: impl Iterator<Item = String>) {
+    let mut images = Vec::new();
+    let mut heatmap = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => heatmap = args.next().map(PathBuf::from),
+            _ => images.push(arg),
+        }
+    }
+    let [reference, image] = &images[..] else {
+        eprintln!("Usage: ray-tracer compare <reference.png> <image.png> [-o HEATMAP]");
+        process::exit(2);
+    };
+    let [reference, image] = [reference, image].map(|path| match image::open(path) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => {
+            eprintln!("Failed to read {path}: {err}");
+            process::exit(1);
+        }
+    });
+    let difference = compare::difference(&reference, &image);
+    println!("{}", difference.metrics);
+    if let Some(path) = heatmap
+        && let Err(err) = difference.heatmap.save(&path)
+    {
+        eprintln!("Failed to write {}: {err}", path.display());
+        process::exit(1);
+    }
+}
+
+fn run_stream(mut args: impl Iterator<Item = String>) {
+    let mut options = StreamOptions::default();
+    let mut scene_path = None;
+    let mut shm_path = None;
+    let mut slots = 3;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => options.width = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.width),
+            "--height" => options.height = args.next().and_then(|n| n.parse().ok()).unwrap_or(options.height),
+            "--shm" => shm_path = args.next().map(PathBuf::from),
+            "--slots" => slots = args.next().and_then(|n| n.parse().ok()).unwrap_or(slots),
+            "--fps" => options.fps = args.next().and_then(|fps| fps.parse().ok()),
+            "--frames" => options.frames = args.next().and_then(|n| n.parse().ok()),
+            "--turntable" => options.turntable = args.next().and_then(|degrees| degrees.parse().ok()),
+            "--script" => options.script = args.next().map(|path| load_script(&path)),
+            _ => scene_path = Some(arg),
+        }
+    }
+    let Some(scene_path) = scene_path else {
+        eprintln!("Usage: ray-tracer stream <scene.gltf> [--width W] [--height H] [--shm FILE] [--slots N] [--fps N] [--frames N] [--turntable DEGREES] [--script FILE]");
+        process::exit(2);
+    };
+    if let Some(path) = shm_path {
+        options.output = StreamOutput::SharedMemory { path, slots };
+    }
+    let mut scene = load_scene(&scene_path);
+    let mut settings = Settings::default();
+    let mut camera = scene.default_camera();
+    if let Some(script) = &mut options.script
+        && let Err(err) = script.run(&mut scene, &mut settings, &mut camera)
+    {
+        eprintln!("Script failed: {err}");
+        process::exit(1);
+    }
+    if let Err(err) = stream::stream(scene, settings, camera, options) {
+        eprintln!("Streaming failed: {err}");
+        process::exit(1);
+    }
+}
 
 fn main() {
-    let mut tracer = RayTracer::default();
+    // The viewer reports what its hotkeys do through the log, so it shows by default.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("ray_tracer=info")).init();
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "bench") {
+        args.next();
+        run_bench(args);
+        return;
+    }
+    if args.peek().is_some_and(|arg| arg == "render") {
+        args.next();
+        run_render(args);
+        return;
+    }
+    if args.peek().is_some_and(|arg| arg == "distribute") {
+        args.next();
+        run_distribute(args);
+        return;
+    }
+    if args.peek().is_some_and(|arg| arg == "worker") {
+        args.next();
+        run_worker(args);
+        return;
+    }
+    if args.peek().is_some_and(|arg| arg == "serve") {
+        args.next();
+        run_serve(args);
+        return;
+    }
+    if args.peek().is_some_and(|arg| arg == "compare") {
+        args.next();
+        run_compare(args);
+        return;
+    }
+    if args.peek().is_some_and(|arg| arg == "stream") {
+        args.next();
+        run_stream(args);
+        return;
+    }
+
+    // The last session's settings go first so flags override them.
+    let mut settings = Settings::default();
+    if !env::args().any(|arg| arg == "--no-session") {
+        settings.session = Session::default_path();
+    }
+    let session = settings.session.as_ref().map(Session::load).unwrap_or_default();
+    session.settings.apply(&mut settings);
+    let mut projection = None;
+    let mut scene_path = None;
+    let mut print_stats = false;
+    let mut print_outline = false;
+    let mut export_path = None;
+    let mut scene_layers = Vec::new();
+    let mut script = None;
+    let mut title = None;
+    let mut icon = None;
+    // Clipping planes given as flags replace the session's rather than adding to them.
+    let mut clip_planes = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stats" => print_stats = true,
+            "--no-session" => {}
+            "--outline" => print_outline = true,
+            "--export" => export_path = args.next(),
+            "--over" => scene_layers.extend(args.next()),
+            "--script" => script = args.next().map(|path| load_script(&path)),
+            "--trace" => settings.trace_path = args.next().map(Into::into),
+            "--crop" => match args.next().unwrap_or_default().parse() {
+                Ok(crop) => settings.crop = Some(crop),
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+            },
+            "--projection" => match args.next().unwrap_or_default().parse() {
+                Ok(parsed) => projection = Some(parsed),
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+            },
+            "--fullscreen" => match args.next().unwrap_or_default().parse() {
+                Ok(mode) => settings.fullscreen = Some(mode),
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+            },
+            "--monitor" => settings.monitor = args.next().and_then(|index| index.parse().ok()),
+            "--stereo" => match args.next().unwrap_or_default().parse() {
+                Ok(mode) => settings.stereo = Some(Stereo::new(mode)),
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+            },
+            "--ipd" => {
+                let ipd = args.next().and_then(|ipd| ipd.parse().ok());
+                if let (Some(stereo), Some(ipd)) = (&mut settings.stereo, ipd) {
+                    stereo.ipd = ipd;
+                }
+            }
+            "--sky" => settings.sky = Some(settings.sky.unwrap_or_default()),
+            "--backplate" => settings.backplate = args.next().map(Into::into),
+            "--grid" => settings.overlays.grid = true,
+            "--axes" => settings.overlays.axes = true,
+            "--bounds" => settings.overlays.bounds = true,
+            "--hud" => settings.overlays.hud = true,
+            "--labels" => settings.overlays.labels = true,
+            "--annotate" => settings.annotations.push(parse_annotation(&mut args)),
+            "--clip" => clip_planes.push(parse_clip_plane(args.next())),
+            "--section-caps" => settings.section_caps = Some(SECTION_CAPS),
+            "--explode" => settings.explode = args.next().and_then(|factor| factor.parse().ok()).unwrap_or(1.0),
+            "--ground" => settings.ground = Some(settings.ground.unwrap_or_default()),
+            "--shadow-catcher" => {
+                settings.ground.get_or_insert_with(GroundPlane::default).shadow_catcher = true;
+            }
+            "--sun" => {
+                let numbers = parse_numbers("--sun", args.next(), 2);
+                let sky = settings.sky.get_or_insert_with(Sky::default);
+                sky.azimuth = numbers[0];
+                sky.elevation = numbers[1];
+            }
+            "--location" => {
+                let numbers = parse_numbers("--location", args.next(), 4);
+                let sky = Sky::at_location(numbers[0], numbers[1], numbers[2] as u32, numbers[3]);
+                let turbidity = settings.sky.map_or(sky.turbidity, |sky| sky.turbidity);
+                settings.sky = Some(Sky { turbidity, ..sky });
+            }
+            "--turbidity" => {
+                let turbidity = args.next().and_then(|turbidity| turbidity.parse().ok());
+                if let Some(turbidity) = turbidity {
+                    settings.sky.get_or_insert_with(Sky::default).turbidity = turbidity;
+                }
+            }
+            "--fog" => {
+                let density = args.next().and_then(|density| density.parse().ok());
+                if let Some(density) = density {
+                    settings.atmosphere.get_or_insert_with(Atmosphere::default).density = density;
+                }
+            }
+            "--nan-check" => settings.nan_check = true,
+            "--watch" => settings.hot_reload = true,
+            "--gamepad" => settings.gamepad = true,
+            "--title" => title = args.next(),
+            "--icon" => icon = args.next().map(|path| load_icon(&path)),
+            "--spacemouse" => settings.spacemouse = true,
+            "--audio" => settings.audio = true,
+            // A port listens on loopback only; an address such as `0.0.0.0:9000` opens it to the network.
+            "--osc" => {
+                let arg = args.next().unwrap_or_default();
+                settings.osc = arg.parse().ok()
+                    .or_else(|| arg.parse().ok().map(|port: u16| SocketAddr::from(([127, 0, 0, 1], port))));
+            }
+            "--control" => {
+                let port: Option<u16> = args.next().and_then(|port| port.parse().ok());
+                settings.control = port.map(|port| SocketAddr::from(([127, 0, 0, 1], port)));
+            }
+            "--midi-cc" => settings.midi.push(parse_midi_control(&mut args)),
+            "--quantize" => settings.quantize_vertices = true,
+            "--optimize" => settings.optimize_meshes = true,
+            "--units" => match args.next().unwrap_or_default().parse() {
+                Ok(units) => settings.import.units = Some(units),
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+            },
+            "--z-up" => settings.import.z_up = true,
+            "--center" => settings.import.center = true,
+            "--fit" => settings.import.fit = args.next().and_then(|size| size.parse().ok()),
+            // The tolerance is optional, so a scene path after the flag is left for the next arm.
+            "--weld" => {
+                let epsilon = args.next_if(|epsilon| epsilon.parse::<f32>().is_ok());
+                settings.weld_epsilon = Some(epsilon.and_then(|epsilon| epsilon.parse().ok()).unwrap_or(0.0));
+            }
+            "--override" => settings.material_override = args.next(),
+            "--compare" => settings.compare = args.next().map(Into::into),
+            "--compare-mode" => match args.next().unwrap_or_default().parse() {
+                Ok(mode) => settings.compare_mode = mode,
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+            },
+            "--analysis" => match args.next().unwrap_or_default().parse() {
+                Ok(analysis) => settings.analysis = Some(analysis),
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+            },
+            _ => scene_path = Some(arg),
+        }
+    }
+    if !clip_planes.is_empty() {
+        settings.clip_planes = clip_planes;
+    }
+
+    let mut tracer = RayTracer::new(settings);
+    if let Some(projection) = projection {
+        tracer.set_camera(Camera {
+            projection,
+            ..Camera::default()
+        });
+    }
+    match (scene_path, &session.scene) {
+        (Some(path), _) => tracer.set_scene_path(path),
+        (None, Some(path)) if path.exists() => tracer.set_scene_path(path),
+        _ => {}
+    }
+    // The last camera only fits the scene it was looking at.
+    if let Some(camera) = session.camera
+        && projection.is_none()
+        && session.scene.as_deref() == Some(tracer.scene_path())
+    {
+        tracer.set_camera(camera);
+    }
+    tracer.set_recent_scenes(session.recent);
+    if let Some([width, height]) = session.window_size {
+        tracer.set_window_size(width, height);
+    }
+    for layer in &scene_layers {
+        tracer.add_scene_layer(layer);
+    }
+    if let Some(script) = script {
+        tracer.set_script(script);
+    }
+    if let Some(title) = title {
+        tracer.set_title(title);
+    }
+    if let Some(icon) = icon {
+        tracer.set_icon(icon);
+    }
+
+    if let Some(export_path) = export_path {
+        let scene = load_layered(&tracer.scene_path().to_string_lossy(), &scene_layers);
+        if let Err(err) = scene.export_gltf(&export_path) {
+            eprintln!("Failed to write {export_path}: {err}");
+            process::exit(1);
+        }
+        return;
+    }
+
+    if print_stats {
+        let scene = load_layered(&tracer.scene_path().to_string_lossy(), &scene_layers);
+        println!("{}", scene.stats());
+        return;
+    }
+
+    if print_outline {
+        let scene = load_layered(&tracer.scene_path().to_string_lossy(), &scene_layers);
+        print!("{}", scene.outline());
+        return;
+    }
+
     tracer.run().unwrap();
 }
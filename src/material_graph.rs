@@ -0,0 +1,140 @@
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ProceduralTexture,
+    procedural::vec,
+};
+
+/// A surface colour computed by a graph of nodes, compiled to WGSL when it is set on a scene.
+///
+/// Every node produces a `vec4f`, and inputs refer to earlier nodes by index, so graphs are
+/// acyclic by construction. Scenes set one as `extras.material` on their glTF scene, e.g.
+///
+/// ```json
+/// {"nodes": [
+///     {"type": "material"},
+///     {"type": "texture", "texture": {"type": "noise", "scale": 4}},
+///     {"type": "math", "op": "multiply", "a": 0, "b": 1}
+/// ], "output": 2}
+/// ```
+///
+/// Like [`ProceduralTexture`], the graph colours every surface and is hidden while a material
+/// override is active.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MaterialGraph {
+    pub nodes: Vec<GraphNode>,
+    /// Node whose value is the surface colour.
+    pub output: usize,
+}
+
+/// A node of a [`MaterialGraph`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum GraphNode {
+    Constant { value: [f32; 4] },
+    /// World position of the surface, with `w` set to one.
+    Position,
+    /// Colour of the surface's material.
+    Material,
+    /// `texture` evaluated at the position, or at the `xyz` of node `coordinates`.
+    Texture {
+        texture: ProceduralTexture,
+        #[serde(default)]
+        coordinates: Option<usize>,
+    },
+    /// `a op b`, component-wise.
+    Math { op: MathOp, a: usize, b: usize },
+    /// Blends from `a` to `b` by the `x` of node `factor`.
+    Mix { a: usize, b: usize, factor: usize },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Min,
+    Max,
+    Power,
+}
+
+/// Why a [`MaterialGraph`] could not be compiled.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GraphError {
+    /// `node` has an input that is not an earlier node.
+    InvalidInput { node: usize, input: usize },
+    /// The output is not a node of the graph.
+    InvalidOutput(usize),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::InvalidInput { node, input } => {
+                write!(f, "node {node} reads node {input}, which is not an earlier node")
+            }
+            GraphError::InvalidOutput(output) => write!(f, "output {output} is not a node"),
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+impl From<ProceduralTexture> for MaterialGraph {
+    /// A graph of just `texture`.
+    fn from(texture: ProceduralTexture) -> Self {
+        Self {
+            nodes: vec![GraphNode::Texture { texture, coordinates: None }],
+            output: 0,
+        }
+    }
+}
+
+impl MaterialGraph {
+    /// WGSL defining `fn material_graph(color: vec4f, p: vec3f) -> vec4f`, which maps the
+    /// material colour and world position of a surface to its colour.
+    pub(crate) fn compile(&self) -> Result<String, GraphError> {
+        if self.output >= self.nodes.len() {
+            return Err(GraphError::InvalidOutput(self.output));
+        }
+        let mut wgsl = String::from("fn material_graph(color: vec4f, p: vec3f) -> vec4f {\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let input = |input: usize| if input < index {
+                Ok(format!("n{input}"))
+            } else {
+                Err(GraphError::InvalidInput { node: index, input })
+            };
+            let value = match node {
+                GraphNode::Constant { value } => vec(value),
+                GraphNode::Position => "vec4f(p, 1.0)".into(),
+                GraphNode::Material => "color".into(),
+                GraphNode::Texture { texture, coordinates: None } => texture.expression("p"),
+                GraphNode::Texture { texture, coordinates: Some(coordinates) } => {
+                    texture.expression(&format!("{}.xyz", input(*coordinates)?))
+                }
+                GraphNode::Math { op, a, b } => {
+                    let (a, b) = (input(*a)?, input(*b)?);
+                    match op {
+                        MathOp::Add => format!("{a} + {b}"),
+                        MathOp::Subtract => format!("{a} - {b}"),
+                        MathOp::Multiply => format!("{a} * {b}"),
+                        MathOp::Divide => format!("{a} / {b}"),
+                        MathOp::Min => format!("min({a}, {b})"),
+                        MathOp::Max => format!("max({a}, {b})"),
+                        MathOp::Power => format!("pow({a}, {b})"),
+                    }
+                }
+                GraphNode::Mix { a, b, factor } => {
+                    format!("mix({}, {}, {}.x)", input(*a)?, input(*b)?, input(*factor)?)
+                }
+            };
+            wgsl.push_str(&format!("    let n{index} = {value};\n"));
+        }
+        wgsl.push_str(&format!("    return n{};\n}}\n", self.output));
+        Ok(wgsl)
+    }
+}
@@ -0,0 +1,537 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
+
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+
+use crate::{
+    GraphNode,
+    MaterialGraph,
+    MathOp,
+    ProceduralTexture,
+};
+
+/// Surface shader categories and the input holding their base colour.
+const SHADERS: [(&str, &str); 4] = [
+    ("standard_surface", "base_color"),
+    ("open_pbr_surface", "base_color"),
+    ("gltf_pbr", "base_color"),
+    ("UsdPreviewSurface", "diffuseColor"),
+];
+
+/// Why a MaterialX document could not be turned into a [`MaterialGraph`].
+#[derive(Debug)]
+pub enum MaterialXError {
+    Xml(quick_xml::Error),
+    /// A node category, or a connection, outside the supported subset.
+    Unsupported(String),
+    /// A connection to a node that is not in the document.
+    MissingNode(String),
+    /// A node that feeds into itself.
+    Cycle(String),
+    /// An input value that is not a list of numbers.
+    InvalidValue(String),
+    /// No surface shader with a base colour was found.
+    NoSurface,
+}
+
+impl fmt::Display for MaterialXError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaterialXError::Xml(err) => write!(f, "{err}"),
+            MaterialXError::Unsupported(what) => write!(f, "unsupported {what}"),
+            MaterialXError::MissingNode(name) => write!(f, "no node named {name:?}"),
+            MaterialXError::Cycle(name) => write!(f, "node {name:?} feeds into itself"),
+            MaterialXError::InvalidValue(value) => write!(f, "invalid value {value:?}"),
+            MaterialXError::NoSurface => write!(f, "no supported surface shader"),
+        }
+    }
+}
+
+impl Error for MaterialXError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MaterialXError::Xml(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<quick_xml::Error> for MaterialXError {
+    fn from(err: quick_xml::Error) -> Self {
+        MaterialXError::Xml(err)
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for MaterialXError {
+    fn from(err: quick_xml::events::attributes::AttrError) -> Self {
+        MaterialXError::Xml(err.into())
+    }
+}
+
+/// Nodes are named within their node graph, or at the top level of the document.
+type Key = (Option<String>, String);
+
+struct Input {
+    value: Option<String>,
+    nodename: Option<String>,
+    nodegraph: Option<String>,
+    output: Option<String>,
+    interfacename: Option<String>,
+}
+
+struct Node {
+    category: String,
+    inputs: HashMap<String, Input>,
+}
+
+#[derive(Default)]
+struct Document {
+    nodes: HashMap<Key, Node>,
+    /// Node graph outputs and the node each one reads.
+    outputs: HashMap<(String, String), String>,
+    /// Top-level nodes in document order, to find the material by.
+    order: Vec<String>,
+}
+
+impl MaterialGraph {
+    /// Converts the base colour of the first material in a MaterialX document.
+    ///
+    /// The surface shader can be `standard_surface`, `open_pbr_surface`, `gltf_pbr` or
+    /// `UsdPreviewSurface`, and the base colour can be a constant or a network of `constant`,
+    /// `position`, `add`, `subtract`, `multiply`, `divide`, `min`, `max`, `power`, `mix`,
+    /// `noise3d`, `fractal3d`, `worleynoise3d` and `checkerboard` nodes, flat or in node graphs.
+    /// Textures are evaluated at the world position, like other [`ProceduralTexture`]s.
+    pub fn from_materialx(xml: &str) -> Result<Self, MaterialXError> {
+        let document = Document::parse(xml)?;
+        let mut builder = Builder {
+            document: &document,
+            graph: MaterialGraph {
+                nodes: vec![],
+                output: 0,
+            },
+            built: HashMap::new(),
+            visiting: HashSet::new(),
+        };
+        let default = [0.8, 0.8, 0.8, 1.0];
+        builder.graph.output = match document.base_color()? {
+            (key, Some(input)) => builder.input(&key, input, default)?,
+            (_, None) => builder.push(GraphNode::Constant { value: default }),
+        };
+        Ok(builder.graph)
+    }
+}
+
+impl Document {
+    fn parse(xml: &str) -> Result<Self, MaterialXError> {
+        let mut document = Document::default();
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+        let mut graph: Option<String> = None;
+        // The node whose inputs are being read.
+        let mut node: Option<Key> = None;
+        loop {
+            let (element, empty) = match reader.read_event()? {
+                Event::Start(element) => (element, false),
+                Event::Empty(element) => (element, true),
+                Event::End(element) => {
+                    match element.name().as_ref() {
+                        b"nodegraph" => graph = None,
+                        b"input" | b"output" => {}
+                        _ => node = None,
+                    }
+                    continue;
+                }
+                Event::Eof => return Ok(document),
+                _ => continue,
+            };
+            let mut attributes = attributes(&element)?;
+            let name = attributes.remove("name").unwrap_or_default();
+            match element.name().as_ref() {
+                b"materialx" => {}
+                b"nodegraph" => if !empty {
+                    graph = Some(name);
+                },
+                b"output" => if let (Some(graph), Some(nodename)) = (&graph, attributes.remove("nodename")) {
+                    document.outputs.insert((graph.clone(), name), nodename);
+                },
+                b"input" => if let Some(node) = node.as_ref().and_then(|key| document.nodes.get_mut(key)) {
+                    node.inputs.insert(name, Input {
+                        value: attributes.remove("value"),
+                        nodename: attributes.remove("nodename"),
+                        nodegraph: attributes.remove("nodegraph"),
+                        output: attributes.remove("output"),
+                        interfacename: attributes.remove("interfacename"),
+                    });
+                },
+                category => {
+                    let key = (graph.clone(), name.clone());
+                    document.nodes.insert(key.clone(), Node {
+                        category: String::from_utf8_lossy(category).into_owned(),
+                        inputs: HashMap::new(),
+                    });
+                    if graph.is_none() {
+                        document.order.push(name);
+                    }
+                    if !empty {
+                        node = Some(key);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The shader of the first `surfacematerial`, or else the first shader, and its base colour.
+    fn base_color(&self) -> Result<(Key, Option<&Input>), MaterialXError> {
+        let top_level = |name: &String| (None, name.clone());
+        let shader = self.order.iter()
+            .map(top_level)
+            .find(|key| self.nodes[key].category == "surfacematerial")
+            .and_then(|key| self.nodes[&key].inputs.get("surfaceshader")?.nodename.clone())
+            .map(|name| (None, name))
+            .or_else(|| self.order.iter()
+                .map(top_level)
+                .find(|key| SHADERS.iter().any(|(category, _)| self.nodes[key].category == *category)))
+            .ok_or(MaterialXError::NoSurface)?;
+        let node = self.nodes.get(&shader).ok_or_else(|| MaterialXError::MissingNode(shader.1.clone()))?;
+        let (_, base_color) = SHADERS.iter()
+            .find(|(category, _)| node.category == *category)
+            .ok_or_else(|| MaterialXError::Unsupported(format!("surface shader {}", node.category)))?;
+        let input = node.inputs.get(*base_color);
+        Ok((shader, input))
+    }
+}
+
+fn attributes(element: &BytesStart) -> Result<HashMap<String, String>, MaterialXError> {
+    let mut attributes = HashMap::new();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+        attributes.insert(key, attribute.unescape_value()?.into_owned());
+    }
+    Ok(attributes)
+}
+
+/// Parses a MaterialX `float`, `vector` or `color` value into a graph colour. Scalars fill
+/// the colour channels, and alpha defaults to one.
+fn parse_value(value: &str) -> Result<[f32; 4], MaterialXError> {
+    let numbers = value.split(',')
+        .map(|number| number.trim().parse())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|_| MaterialXError::InvalidValue(value.into()))?;
+    Ok(match numbers[..] {
+        [v] => [v, v, v, 1.0],
+        [x, y] => [x, y, 0.0, 1.0],
+        [x, y, z] => [x, y, z, 1.0],
+        [x, y, z, w] => [x, y, z, w],
+        _ => return Err(MaterialXError::InvalidValue(value.into())),
+    })
+}
+
+struct Builder<'a> {
+    document: &'a Document,
+    graph: MaterialGraph,
+    built: HashMap<Key, usize>,
+    visiting: HashSet<Key>,
+}
+
+impl Builder<'_> {
+    fn push(&mut self, node: GraphNode) -> usize {
+        self.graph.nodes.push(node);
+        self.graph.nodes.len() - 1
+    }
+
+    /// The graph node giving the value of `input` of the node at `key`.
+    fn input(&mut self, key: &Key, input: &Input, default: [f32; 4]) -> Result<usize, MaterialXError> {
+        if input.interfacename.is_some() {
+            return Err(MaterialXError::Unsupported(format!("node graph interface input on {:?}", key.1)));
+        }
+        if let Some(nodename) = &input.nodename {
+            return self.node((key.0.clone(), nodename.clone()));
+        }
+        if let (Some(nodegraph), Some(output)) = (&input.nodegraph, &input.output) {
+            let nodename = self.document.outputs.get(&(nodegraph.clone(), output.clone()))
+                .ok_or_else(|| MaterialXError::MissingNode(format!("{nodegraph}.{output}")))?;
+            return self.node((Some(nodegraph.clone()), nodename.clone()));
+        }
+        let value = input.value.as_deref().map(parse_value).transpose()?.unwrap_or(default);
+        Ok(self.push(GraphNode::Constant { value }))
+    }
+
+    /// The graph node for input `name` of `node`, or a constant `default` when it is unset.
+    fn connection(&mut self, key: &Key, node: &Node, name: &str, default: f32) -> Result<usize, MaterialXError> {
+        match node.inputs.get(name) {
+            Some(input) => self.input(key, input, [default, default, default, 1.0]),
+            None => Ok(self.push(GraphNode::Constant { value: [default, default, default, 1.0] })),
+        }
+    }
+
+    /// The first component of input `name` of `node`, which must not be connected.
+    fn parameter(&self, key: &Key, node: &Node, name: &str, default: f32) -> Result<f32, MaterialXError> {
+        let Some(input) = node.inputs.get(name) else {
+            return Ok(default);
+        };
+        let Some(value) = &input.value else {
+            return Err(MaterialXError::Unsupported(format!("connected {name} input on {:?}", key.1)));
+        };
+        Ok(parse_value(value)?[0])
+    }
+
+    /// The graph node reading `position` of `node`, or `None` for the world position.
+    fn coordinates(&mut self, key: &Key, node: &Node) -> Result<Option<usize>, MaterialXError> {
+        node.inputs.get("position")
+            .map(|input| self.input(key, input, [0.0; 4]))
+            .transpose()
+    }
+
+    fn node(&mut self, key: Key) -> Result<usize, MaterialXError> {
+        if let Some(&index) = self.built.get(&key) {
+            return Ok(index);
+        }
+        let document = self.document;
+        let node = document.nodes.get(&key).ok_or_else(|| MaterialXError::MissingNode(key.1.clone()))?;
+        if !self.visiting.insert(key.clone()) {
+            return Err(MaterialXError::Cycle(key.1));
+        }
+        let math = |op| match op {
+            MathOp::Multiply | MathOp::Divide | MathOp::Power => 1.0,
+            _ => 0.0,
+        };
+        let graph_node = match node.category.as_str() {
+            "constant" => {
+                let index = self.connection(&key, node, "value", 0.0)?;
+                self.visiting.remove(&key);
+                self.built.insert(key, index);
+                return Ok(index);
+            }
+            "position" => GraphNode::Position,
+            category @ ("add" | "subtract" | "multiply" | "divide" | "min" | "max" | "power") => {
+                let op = match category {
+                    "add" => MathOp::Add,
+                    "subtract" => MathOp::Subtract,
+                    "multiply" => MathOp::Multiply,
+                    "divide" => MathOp::Divide,
+                    "min" => MathOp::Min,
+                    "max" => MathOp::Max,
+                    _ => MathOp::Power,
+                };
+                let a = self.connection(&key, node, "in1", 0.0)?;
+                let b = self.connection(&key, node, "in2", math(op))?;
+                GraphNode::Math { op, a, b }
+            }
+            "mix" => {
+                // MaterialX blends from the background to the foreground.
+                let a = self.connection(&key, node, "bg", 0.0)?;
+                let b = self.connection(&key, node, "fg", 0.0)?;
+                let factor = self.connection(&key, node, "mix", 0.0)?;
+                GraphNode::Mix { a, b, factor }
+            }
+            // Noise is in [-amplitude, amplitude] around the pivot, where ours is in [0, 1].
+            "noise3d" | "fractal3d" => {
+                let amplitude = self.parameter(&key, node, "amplitude", 1.0)?;
+                let pivot = self.parameter(&key, node, "pivot", 0.0)?;
+                let octaves = match node.category.as_str() {
+                    "fractal3d" => self.parameter(&key, node, "octaves", 3.0)? as u32,
+                    _ => 1,
+                };
+                let (low, high) = (pivot - amplitude, pivot + amplitude);
+                GraphNode::Texture {
+                    texture: ProceduralTexture::Noise {
+                        scale: 1.0,
+                        octaves,
+                        colors: [[low, low, low, 1.0], [high, high, high, 1.0]],
+                    },
+                    coordinates: self.coordinates(&key, node)?,
+                }
+            }
+            "worleynoise3d" => GraphNode::Texture {
+                texture: ProceduralTexture::Voronoi {
+                    scale: 1.0,
+                    colors: [[0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0]],
+                },
+                coordinates: self.coordinates(&key, node)?,
+            },
+            "checkerboard" => {
+                let color = |name| node.inputs.get(name)
+                    .and_then(|input| input.value.as_deref())
+                    .map(parse_value)
+                    .transpose();
+                GraphNode::Texture {
+                    texture: ProceduralTexture::Checker {
+                        scale: self.parameter(&key, node, "uvtiling", 8.0)?,
+                        colors: [
+                            color("color1")?.unwrap_or([1.0; 4]),
+                            color("color2")?.unwrap_or([0.0, 0.0, 0.0, 1.0]),
+                        ],
+                    },
+                    coordinates: None,
+                }
+            }
+            category => return Err(MaterialXError::Unsupported(format!("node {category}"))),
+        };
+        let index = self.push(graph_node);
+        self.visiting.remove(&key);
+        self.built.insert(key, index);
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(body: &str) -> Result<MaterialGraph, MaterialXError> {
+        MaterialGraph::from_materialx(&format!(r#"<?xml version="1.0"?><materialx version="1.38">{body}</materialx>"#))
+    }
+
+    fn constant(value: [f32; 4]) -> GraphNode {
+        GraphNode::Constant { value }
+    }
+
+    #[test]
+    fn constant_base_colours() {
+        for (category, input) in SHADERS {
+            let graph = graph(&format!(r#"<{category} name="SR"><input name="{input}" type="color3" value="0.1, 0.2, 0.3" /></{category}>"#)).unwrap();
+            assert_eq!(graph, MaterialGraph { nodes: vec![constant([0.1, 0.2, 0.3, 1.0])], output: 0 });
+        }
+    }
+
+    #[test]
+    fn unset_base_colour_is_the_default() {
+        let graph = graph(r#"<standard_surface name="SR" type="surfaceshader" />"#).unwrap();
+        assert_eq!(graph.nodes, [constant([0.8, 0.8, 0.8, 1.0])]);
+    }
+
+    #[test]
+    fn surface_material_picks_its_shader() {
+        let graph = graph(r#"
+            <gltf_pbr name="Unused"><input name="base_color" type="color3" value="1, 0, 0" /></gltf_pbr>
+            <standard_surface name="Used"><input name="base_color" type="color3" value="0, 0, 1" /></standard_surface>
+            <surfacematerial name="M"><input name="surfaceshader" type="surfaceshader" nodename="Used" /></surfacematerial>
+        "#).unwrap();
+        assert_eq!(graph.nodes, [constant([0.0, 0.0, 1.0, 1.0])]);
+    }
+
+    #[test]
+    fn node_graphs_through_outputs() {
+        let graph = graph(r#"
+            <nodegraph name="NG">
+                <position name="P" type="vector3" />
+                <multiply name="Scaled" type="vector3">
+                    <input name="in1" type="vector3" nodename="P" />
+                    <input name="in2" type="float" value="0.5" />
+                </multiply>
+                <mix name="Blend" type="color3">
+                    <input name="bg" type="color3" value="0, 0, 0" />
+                    <input name="fg" type="color3" nodename="Scaled" />
+                    <input name="mix" type="float" nodename="Scaled" />
+                </mix>
+                <output name="out" type="color3" nodename="Blend" />
+            </nodegraph>
+            <standard_surface name="SR">
+                <input name="base_color" type="color3" nodegraph="NG" output="out" />
+            </standard_surface>
+        "#).unwrap();
+        // The shared multiply is built once.
+        assert_eq!(graph.nodes, [
+            constant([0.0, 0.0, 0.0, 1.0]),
+            GraphNode::Position,
+            constant([0.5, 0.5, 0.5, 1.0]),
+            GraphNode::Math { op: MathOp::Multiply, a: 1, b: 2 },
+            GraphNode::Mix { a: 0, b: 3, factor: 3 },
+        ]);
+        assert_eq!(graph.output, 4);
+        assert!(graph.compile().is_ok());
+    }
+
+    #[test]
+    fn unset_math_inputs_are_identities() {
+        let graph = graph(r#"
+            <divide name="D" type="color3" />
+            <standard_surface name="SR"><input name="base_color" type="color3" nodename="D" /></standard_surface>
+        "#).unwrap();
+        assert_eq!(graph.nodes, [
+            constant([0.0, 0.0, 0.0, 1.0]),
+            constant([1.0, 1.0, 1.0, 1.0]),
+            GraphNode::Math { op: MathOp::Divide, a: 0, b: 1 },
+        ]);
+    }
+
+    #[test]
+    fn textures() {
+        let surface = |node: &str| graph(&format!(r#"{node}
+            <standard_surface name="SR"><input name="base_color" type="color3" nodename="T" /></standard_surface>"#)).unwrap();
+        let noise = surface(r#"<fractal3d name="T" type="color3">
+            <input name="amplitude" type="float" value="0.25" />
+            <input name="pivot" type="float" value="0.5" />
+            <input name="octaves" type="integer" value="5" />
+        </fractal3d>"#);
+        assert_eq!(noise.nodes, [GraphNode::Texture {
+            texture: ProceduralTexture::Noise {
+                scale: 1.0,
+                octaves: 5,
+                colors: [[0.25, 0.25, 0.25, 1.0], [0.75, 0.75, 0.75, 1.0]],
+            },
+            coordinates: None,
+        }]);
+        let checker = surface(r#"<checkerboard name="T" type="color3">
+            <input name="color1" type="color3" value="1, 0, 0" />
+            <input name="uvtiling" type="vector2" value="4, 4" />
+        </checkerboard>"#);
+        assert_eq!(checker.nodes, [GraphNode::Texture {
+            texture: ProceduralTexture::Checker { scale: 4.0, colors: [[1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 0.0, 1.0]] },
+            coordinates: None,
+        }]);
+        let worley = surface(r#"<worleynoise3d name="T" type="float"><input name="position" type="vector3" nodename="P" /></worleynoise3d>
+            <position name="P" type="vector3" />"#);
+        assert_eq!(worley.nodes[0], GraphNode::Position);
+        assert!(matches!(worley.nodes[1], GraphNode::Texture { texture: ProceduralTexture::Voronoi { .. }, coordinates: Some(0) }));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_value("0.5").unwrap(), [0.5, 0.5, 0.5, 1.0]);
+        assert_eq!(parse_value("1, 2").unwrap(), [1.0, 2.0, 0.0, 1.0]);
+        assert_eq!(parse_value(" 1,2 ,3,4 ").unwrap(), [1.0, 2.0, 3.0, 4.0]);
+        for value in ["", "red", "1, 2, 3, 4, 5", "1,,2"] {
+            assert!(matches!(parse_value(value), Err(MaterialXError::InvalidValue(_))), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let surface = |nodes: &str| graph(&format!(r#"{nodes}
+            <standard_surface name="SR"><input name="base_color" type="color3" nodename="A" /></standard_surface>"#));
+        assert!(matches!(graph(""), Err(MaterialXError::NoSurface)));
+        assert!(matches!(graph("<standard_surface name=\"SR\">"), Err(MaterialXError::Xml(_))));
+        assert!(matches!(surface(""), Err(MaterialXError::MissingNode(name)) if name == "A"));
+        assert!(matches!(surface(r#"<image name="A" type="color3" />"#), Err(MaterialXError::Unsupported(_))));
+        assert!(matches!(
+            surface(r#"<add name="A" type="color3"><input name="in1" type="color3" nodename="B" /></add>
+                <add name="B" type="color3"><input name="in1" type="color3" nodename="A" /></add>"#),
+            Err(MaterialXError::Cycle(_))
+        ));
+        assert!(matches!(
+            surface(r#"<constant name="A" type="color3"><input name="value" type="color3" value="x" /></constant>"#),
+            Err(MaterialXError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            surface(r#"<noise3d name="A" type="float"><input name="amplitude" type="float" nodename="B" /></noise3d>
+                <constant name="B" type="float" />"#),
+            Err(MaterialXError::Unsupported(_))
+        ));
+        assert!(matches!(
+            graph(r#"<standard_surface name="SR"><input name="base_color" type="color3" interfacename="tint" /></standard_surface>"#),
+            Err(MaterialXError::Unsupported(_))
+        ));
+        assert!(matches!(
+            graph(r#"<surfacematerial name="M"><input name="surfaceshader" type="surfaceshader" nodename="Gone" /></surfacematerial>"#),
+            Err(MaterialXError::MissingNode(_))
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt;
+
+use crate::Vec3;
+
+/// A straight-line measurement between two points picked on surfaces.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub start: Vec3,
+    pub end: Vec3,
+}
+
+impl Measurement {
+    /// Distance between the points in scene units, metres once imported with
+    /// [`ImportOptions`](crate::ImportOptions).
+    pub fn distance(&self) -> f32 {
+        (self.end - self.start).length()
+    }
+
+    /// Degrees the line rises from `start` to `end` above the horizontal plane, negative when it
+    /// falls.
+    pub fn elevation(&self) -> f32 {
+        let delta = self.end - self.start;
+        delta.y.atan2(delta.x.hypot(delta.z)).to_degrees()
+    }
+
+    /// Line list vertices drawing the measurement, with a small cross at each end.
+    pub(crate) fn lines(&self) -> Vec<Vec3> {
+        let mut lines = vec![self.start, self.end];
+        let size = self.distance() * 0.02;
+        for point in [self.start, self.end] {
+            for axis in [vec3![size, 0.0, 0.0], vec3![0.0, size, 0.0], vec3![0.0, 0.0, size]] {
+                lines.extend([point - axis, point + axis]);
+            }
+        }
+        lines
+    }
+
+    /// Short label drawn at the middle of the line.
+    pub(crate) fn label(&self) -> String {
+        format!("{:.3}\n{:.1}°", self.distance(), self.elevation())
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let delta = self.end - self.start;
+        write!(
+            f,
+            "distance {:.4} (dx {:.4}, dy {:.4}, dz {:.4}), elevation {:.2}°",
+            self.distance(),
+            delta.x,
+            delta.y,
+            delta.z,
+            self.elevation(),
+        )
+    }
+}
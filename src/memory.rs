@@ -0,0 +1,52 @@
+use wgpu::Buffer;
+
+use crate::stats::MemoryUsage;
+
+/// Keeps a running total of GPU allocations against the memory budget.
+pub(crate) struct MemoryTracker {
+    usage: MemoryUsage,
+}
+
+impl MemoryTracker {
+    pub(crate) fn new(budget: u64) -> Self {
+        Self {
+            usage: MemoryUsage {
+                allocated: 0,
+                budget,
+            },
+        }
+    }
+
+    pub(crate) fn track(&mut self, buffer: &Buffer) {
+        self.track_bytes(buffer.size());
+    }
+
+    /// Counts an allocation that is not a buffer, such as a texture.
+    pub(crate) fn track_bytes(&mut self, bytes: u64) {
+        self.usage.allocated += bytes;
+        if self.usage.over_budget() {
+            log::warn!(
+                "GPU memory over budget: {} of {} bytes allocated",
+                self.usage.allocated,
+                self.usage.budget,
+            );
+        }
+    }
+
+    /// Uncounts a buffer that has been replaced or dropped.
+    pub(crate) fn release(&mut self, buffer: &Buffer) {
+        self.release_bytes(buffer.size());
+    }
+
+    pub(crate) fn release_bytes(&mut self, bytes: u64) {
+        self.usage.allocated = self.usage.allocated.saturating_sub(bytes);
+    }
+
+    pub(crate) fn remaining(&self) -> u64 {
+        self.usage.budget.saturating_sub(self.usage.allocated)
+    }
+
+    pub(crate) fn usage(&self) -> MemoryUsage {
+        self.usage
+    }
+}
@@ -0,0 +1,135 @@
+//! Clusters of nearby triangles, each with its own bounds.
+//!
+//! Every level of detail is split into meshlets when a mesh is added. The raster path skips
+//! meshlets outside the view, and picking tests a meshlet's bounds before its triangles.
+
+use std::ops::Range;
+
+use crate::{
+    Vec3,
+    camera::{self, Mat4},
+    scene::Lod,
+};
+
+/// Most triangles in one meshlet.
+pub(crate) const MESHLET_TRIANGLES: usize = 64;
+
+/// A run of triangles within a level of detail and the box around them.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Meshlet {
+    /// First vertex, counted from the start of the level of detail.
+    pub(crate) first_vertex: u32,
+    pub(crate) num_vertices: u32,
+    pub(crate) min: Vec3,
+    pub(crate) max: Vec3,
+}
+
+impl Meshlet {
+    /// Whether any of the meshlet's bounds may be inside the view frustum of `view_proj`.
+    ///
+    /// A box is outside when all its corners lie beyond the same clip plane.
+    pub(crate) fn in_view(&self, view_proj: &Mat4) -> bool {
+        let mut outside = [true; 6];
+        for corner in 0..8 {
+            let p = vec3![
+                if corner & 1 == 0 { self.min.x } else { self.max.x },
+                if corner & 2 == 0 { self.min.y } else { self.max.y },
+                if corner & 4 == 0 { self.min.z } else { self.max.z }
+            ];
+            let [x, y, z, w] = camera::transform(view_proj, p);
+            let beyond = [x < -w, x > w, y < -w, y > w, z < 0.0, z > w];
+            for (outside, beyond) in outside.iter_mut().zip(beyond) {
+                *outside &= beyond;
+            }
+        }
+        !outside.contains(&true)
+    }
+}
+
+/// Reorders the triangle list `vertices` along a Morton curve through the triangle centres, so
+/// consecutive triangles lie close together, and splits it into meshlets.
+pub(crate) fn build(vertices: &mut [Vec3]) -> Vec<Meshlet> {
+    build_with(vertices, &mut [])
+}
+
+/// Builds meshlets as [`build`] does, moving `tangents`, one per vertex or none at all, along
+/// with the vertices.
+pub(crate) fn build_with(vertices: &mut [Vec3], tangents: &mut [[f32; 4]]) -> Vec<Meshlet> {
+    let Some(&first) = vertices.first() else {
+        return Vec::new();
+    };
+    let (min, max) = vertices.iter().fold((first, first), |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)));
+    let extent = max - min;
+    let cell = |value: f32, low: f32, size: f32| if size > 0.0 { ((value - low) / size * 1023.0) as u32 } else { 0 };
+
+    let mut order: Vec<(u32, usize)> = vertices.chunks_exact(3)
+        .enumerate()
+        .map(|(i, t)| {
+            let center = (t[0] + t[1] + t[2]) * (1.0 / 3.0);
+            let code = morton(
+                cell(center.x, min.x, extent.x),
+                cell(center.y, min.y, extent.y),
+                cell(center.z, min.z, extent.z),
+            );
+            (code, i)
+        })
+        .collect();
+    order.sort_by_key(|(code, _)| *code);
+    permute_triangles(vertices, &order);
+    if tangents.len() == vertices.len() {
+        permute_triangles(tangents, &order);
+    }
+
+    vertices.chunks(MESHLET_TRIANGLES * 3)
+        .enumerate()
+        .map(|(i, cluster)| {
+            let (min, max) = cluster.iter()
+                .fold((cluster[0], cluster[0]), |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)));
+            Meshlet {
+                first_vertex: (i * MESHLET_TRIANGLES * 3) as u32,
+                num_vertices: cluster.len() as u32,
+                min,
+                max,
+            }
+        })
+        .collect()
+}
+
+/// Moves the triangles of the triangle list `corners` into the order of the triangle indices in
+/// `order`.
+fn permute_triangles<T: Copy>(corners: &mut [T], order: &[(u32, usize)]) {
+    let original = corners.to_vec();
+    for (out, &(_, triangle)) in corners.chunks_exact_mut(3).zip(order) {
+        out.copy_from_slice(&original[3 * triangle..3 * triangle + 3]);
+    }
+}
+
+/// Interleaves the bits of three 10-bit coordinates.
+fn morton(x: u32, y: u32, z: u32) -> u32 {
+    let spread = |mut v: u32| {
+        v &= 0x3ff;
+        v = (v | (v << 16)) & 0x0300_00ff;
+        v = (v | (v << 8)) & 0x0300_f00f;
+        v = (v | (v << 4)) & 0x030c_30c3;
+        (v | (v << 2)) & 0x0924_9249
+    };
+    spread(x) | (spread(y) << 1) | (spread(z) << 2)
+}
+
+/// Vertex ranges of the meshlets of `lod` that `view_proj` may see, neighbours merged into one
+/// range. Everything is drawn when `view_proj` is `None`.
+pub(crate) fn visible_runs(lod: &Lod, view_proj: Option<&Mat4>) -> Vec<Range<u32>> {
+    let Some(view_proj) = view_proj else {
+        return vec![Range { start: lod.first_vertex, end: lod.first_vertex + lod.num_vertices }];
+    };
+    let mut runs: Vec<Range<u32>> = Vec::new();
+    for meshlet in lod.meshlets.iter().filter(|meshlet| meshlet.in_view(view_proj)) {
+        let start = lod.first_vertex + meshlet.first_vertex;
+        let end = start + meshlet.num_vertices;
+        match runs.last_mut() {
+            Some(run) if run.end == start => run.end = end,
+            _ => runs.push(start..end),
+        }
+    }
+    runs
+}
@@ -0,0 +1,191 @@
+//! Splits offscreen frames into scanline bands drawn on every GPU of the machine at once.
+//!
+//! Every device holds the whole scene and draws its band through the crop rectangle. Band
+//! heights follow how many rows each device finished per second on the last frame, so a faster
+//! GPU takes a larger share.
+
+use std::{
+    ops::Range,
+    thread,
+    time::Instant,
+};
+
+use image::{RgbaImage, imageops};
+use wgpu::{
+    Extent3d,
+    Maintain,
+    Texture,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureUsages,
+    TextureViewDescriptor,
+};
+
+use crate::{
+    Camera,
+    CropRect,
+    Scene,
+    Settings,
+    headless::Readback,
+    renderer::{self, Renderer},
+};
+
+/// Weight of the last frame's speed against the running estimate when balancing.
+const SPEED_SMOOTHING: f64 = 0.5;
+
+struct Gpu {
+    renderer: Renderer,
+    target: Texture,
+    /// Rows drawn per second, `None` until the first frame is measured.
+    speed: Option<f64>,
+}
+
+/// A renderer per GPU drawing a `width` by `height` frame together.
+pub(crate) struct MultiGpu {
+    gpus: Vec<Gpu>,
+    width: u32,
+    height: u32,
+}
+
+impl MultiGpu {
+    /// Renderers on every GPU, or only the preferred one when `all` is `false`.
+    pub(crate) fn new(scene: Scene, settings: Settings, camera: Camera, width: u32, height: u32, all: bool) -> Self {
+        let devices = if all {
+            renderer::headless_devices()
+        } else {
+            vec![renderer::headless_device()]
+        };
+        let format = TextureFormat::Rgba8UnormSrgb;
+        let count = devices.len();
+        let mut scene = Some(scene);
+        let gpus = devices.into_iter()
+            .enumerate()
+            .map(|(i, (device, queue, max_buffer_size))| {
+                let scene = if i + 1 == count { scene.take().unwrap() } else { scene.clone().unwrap() };
+                let target = device.create_texture(&TextureDescriptor {
+                    label: Some("Headless target"),
+                    size: Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                });
+                let mut renderer = Renderer::new(device, queue, format, max_buffer_size, scene, settings.clone());
+                renderer.camera = camera;
+                Gpu { renderer, target, speed: None }
+            })
+            .collect();
+        if count > 1 {
+            log::info!("Rendering on {count} GPUs");
+        }
+        Self { gpus, width, height }
+    }
+
+    /// Applies `change` to the settings of every renderer.
+    pub(crate) fn update_settings(&mut self, mut change: impl FnMut(&mut Settings)) {
+        for gpu in &mut self.gpus {
+            change(&mut gpu.renderer.settings);
+        }
+    }
+
+    /// Offsets the image of every GPU by `jitter` pixels.
+    pub(crate) fn set_jitter(&mut self, jitter: [f32; 2]) {
+        for gpu in &mut self.gpus {
+            gpu.renderer.jitter = jitter;
+        }
+    }
+
+    pub(crate) fn settings(&self) -> &Settings {
+        &self.gpus[0].renderer.settings
+    }
+
+    /// Draws a frame, each GPU its band, and assembles it.
+    ///
+    /// A crop set in the settings is drawn on one GPU, as the bands are drawn through it.
+    pub(crate) fn render(&mut self) -> RgbaImage {
+        let bands = if self.settings().crop.is_some() {
+            vec![Range { start: 0, end: self.height }]
+        } else {
+            self.bands()
+        };
+        let (width, height) = (self.width, self.height);
+        let split = bands.len() > 1;
+
+        let start = Instant::now();
+        let mut readbacks = Vec::with_capacity(bands.len());
+        for (gpu, band) in self.gpus.iter_mut().zip(&bands) {
+            if band.is_empty() {
+                continue;
+            }
+            let crop = gpu.renderer.settings.crop;
+            if split {
+                gpu.renderer.settings.crop = Some(CropRect {
+                    x: 0.0,
+                    y: band.start as f32 / height as f32,
+                    width: 1.0,
+                    height: band.len() as f32 / height as f32,
+                });
+            }
+            let view = gpu.target.create_view(&TextureViewDescriptor::default());
+            gpu.renderer.render(&view, width, height, None);
+            gpu.renderer.settings.crop = crop;
+            let readback = Readback::start(&gpu.renderer, &gpu.target, band.clone());
+            readbacks.push((gpu, band.clone(), readback, None));
+        }
+
+        // Poll every device until its band lands, noting when, so slower GPUs get fewer rows.
+        while readbacks.iter().any(|(_, _, _, finished)| finished.is_none()) {
+            for (gpu, _, readback, finished) in &mut readbacks {
+                if finished.is_none() {
+                    gpu.renderer.device.poll(Maintain::Poll);
+                    if readback.is_ready() {
+                        *finished = Some(start.elapsed().as_secs_f64());
+                    }
+                }
+            }
+            thread::yield_now();
+        }
+
+        let mut image = RgbaImage::new(width, height);
+        for (gpu, band, readback, finished) in readbacks {
+            imageops::replace(&mut image, &readback.finish(), 0, band.start as i64);
+            if split {
+                let speed = band.len() as f64 / finished.unwrap().max(1e-6);
+                gpu.speed = Some(match gpu.speed {
+                    Some(last) => last * (1.0 - SPEED_SMOOTHING) + speed * SPEED_SMOOTHING,
+                    None => speed,
+                });
+            }
+        }
+        image
+    }
+
+    /// Rows of the frame each GPU draws, in proportion to its speed.
+    fn bands(&self) -> Vec<Range<u32>> {
+        let speed = |gpu: &Gpu| gpu.speed.unwrap_or(1.0);
+        let total: f64 = self.gpus.iter().map(speed).sum();
+        let mut start = 0;
+        let mut covered = 0.0;
+        self.gpus.iter()
+            .enumerate()
+            .map(|(i, gpu)| {
+                covered += speed(gpu);
+                let end = if i + 1 == self.gpus.len() {
+                    self.height
+                } else {
+                    ((covered / total * self.height as f64).round() as u32).clamp(start, self.height)
+                };
+                let band = start..end;
+                start = end;
+                band
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use wgpu::{
+    Buffer,
+    BufferAddress,
+    BufferDescriptor,
+    BufferUsages,
+    CommandEncoder,
+    Device,
+    Maintain,
+    MapMode,
+};
+
+use crate::memory::MemoryTracker;
+
+const COUNTER_SIZE: BufferAddress = std::mem::size_of::<u32>() as BufferAddress;
+
+/// Counts fragments whose shaded colour is NaN or infinite, when [`Settings::nan_check`] is on.
+///
+/// The shader increments the counter and draws those fragments magenta. Counts are read back
+/// asynchronously like the profiler's timings, so they lag a frame or more behind.
+///
+/// [`Settings::nan_check`]: crate::Settings::nan_check
+pub(crate) struct NanCounter {
+    pub(crate) buffer: Buffer,
+    readback_buffer: Buffer,
+    recording: bool,
+    mapped: Option<Arc<AtomicBool>>,
+    count: u32,
+}
+
+impl NanCounter {
+    pub(crate) fn new(device: &Device, memory: &mut MemoryTracker) -> Self {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("NaN counter buffer"),
+            size: COUNTER_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        memory.track(&buffer);
+        let readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("NaN counter readback buffer"),
+            size: COUNTER_SIZE,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        memory.track(&readback_buffer);
+        Self {
+            buffer,
+            readback_buffer,
+            recording: false,
+            mapped: None,
+            count: 0,
+        }
+    }
+
+    /// Collects the previous readback if it has landed and, unless one is still in flight,
+    /// resets the counter for this frame.
+    pub(crate) fn begin_frame(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        let landed = self.mapped.as_ref().is_some_and(|mapped| {
+            device.poll(Maintain::Poll);
+            mapped.load(Ordering::Acquire)
+        });
+        if landed {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            self.count = bytemuck::cast_slice::<u8, u32>(&data)[0];
+            drop(data);
+            self.readback_buffer.unmap();
+            self.mapped = None;
+        }
+        self.recording = self.mapped.is_none();
+        if self.recording {
+            encoder.clear_buffer(&self.buffer, 0, None);
+        }
+    }
+
+    /// Records copying this frame's count into the readback buffer.
+    pub(crate) fn resolve(&self, encoder: &mut CommandEncoder) {
+        if self.recording {
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, &self.readback_buffer, 0, COUNTER_SIZE);
+        }
+    }
+
+    /// Starts mapping the readback buffer once the frame has been submitted.
+    pub(crate) fn end_frame(&mut self) {
+        if !self.recording {
+            return;
+        }
+        let mapped = Arc::new(AtomicBool::new(false));
+        let flag = mapped.clone();
+        self.readback_buffer.slice(..).map_async(MapMode::Read, move |result| {
+            if result.is_ok() {
+                flag.store(true, Ordering::Release);
+            }
+        });
+        self.mapped = Some(mapped);
+    }
+
+    /// NaN or infinite fragments in the last frame read back.
+    pub(crate) fn count(&self) -> u32 {
+        self.count
+    }
+}
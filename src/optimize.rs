@@ -0,0 +1,141 @@
+//! Triangle reordering in the manner of meshoptimizer, for a faster raster preview of heavy
+//! meshes.
+//!
+//! Meshes are drawn as triangle lists without an index buffer, so every corner is shaded on
+//! its own and there is no post-transform cache for a vertex cache optimizer to feed. What is
+//! left to gain is overdraw: meshlets are drawn outward-facing first, so they hide more of what
+//! lies behind them from the depth test.
+
+use crate::{
+    Vec3,
+    scene::Lod,
+};
+
+/// Reorders the meshlets of `lod`, whose vertices are `vertices`, and their triangles to match,
+/// moving the level's `tangents`, one per vertex or none at all, along with them.
+pub(crate) fn optimize_lod(vertices: &mut [Vec3], lod: &mut Lod, tangents: &mut [[f32; 4]]) {
+    overdraw(vertices, lod, tangents);
+}
+
+/// Orders the meshlets of `lod` by how far they face away from its centre, outermost first.
+fn overdraw(vertices: &mut [Vec3], lod: &mut Lod, tangents: &mut [[f32; 4]]) {
+    let start = lod.first_vertex as usize;
+    let run = &mut vertices[start..start + lod.num_vertices as usize];
+    let weighted = |triangles: &[Vec3]| {
+        triangles.chunks_exact(3).fold((Vec3::default(), Vec3::default(), 0.0), |(normal, centroid, area), t| {
+            let cross = (t[1] - t[0]).cross(t[2] - t[0]);
+            let weight = cross.length();
+            (normal + cross, centroid + (t[0] + t[1] + t[2]) * (weight / 3.0), area + weight)
+        })
+    };
+    let (_, centroid, area) = weighted(run);
+    if area <= 0.0 {
+        return;
+    }
+    let center = centroid * (1.0 / area);
+
+    let mut keyed: Vec<(f32, usize)> = lod.meshlets.iter()
+        .enumerate()
+        .map(|(i, meshlet)| {
+            let start = meshlet.first_vertex as usize;
+            let (normal, centroid, area) = weighted(&run[start..start + meshlet.num_vertices as usize]);
+            let facing = if area > 0.0 && normal.length() > 0.0 {
+                (centroid * (1.0 / area) - center).dot(normal.normalize())
+            } else {
+                f32::NEG_INFINITY
+            };
+            (facing, i)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let original = run.to_vec();
+    let original_tangents = (tangents.len() == run.len()).then(|| tangents.to_vec());
+    let meshlets = std::mem::take(&mut lod.meshlets);
+    let mut offset = 0;
+    for (_, i) in keyed {
+        let mut meshlet = meshlets[i];
+        let from = meshlet.first_vertex as usize;
+        let count = meshlet.num_vertices as usize;
+        run[offset..offset + count].copy_from_slice(&original[from..from + count]);
+        if let Some(original) = &original_tangents {
+            tangents[offset..offset + count].copy_from_slice(&original[from..from + count]);
+        }
+        meshlet.first_vertex = offset as u32;
+        offset += count;
+        lod.meshlets.push(meshlet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        geometry::primitives,
+        meshlet::{self, Meshlet},
+    };
+
+    fn lod(vertices: &mut [Vec3]) -> Lod {
+        Lod {
+            first_vertex: 0,
+            num_vertices: vertices.len() as u32,
+            meshlets: meshlet::build(vertices),
+        }
+    }
+
+    fn sorted_triangles(vertices: &[Vec3]) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<_> = vertices.chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]].map(|v| [v.x, v.y, v.z].map(f32::to_bits)))
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn triangles_are_only_reordered() {
+        let mut vertices = primitives::torus(vec3![0.0, 0.0, 0.0], 2.0, 0.5, 48, 24);
+        let mut lod = lod(&mut vertices);
+        let before = sorted_triangles(&vertices);
+        let meshlets = lod.meshlets.len();
+
+        optimize_lod(&mut vertices, &mut lod, &mut []);
+        assert_eq!(sorted_triangles(&vertices), before);
+        assert_eq!(lod.meshlets.len(), meshlets);
+        // The meshlets still cover the whole level, one after another.
+        let mut next = 0;
+        for meshlet in &lod.meshlets {
+            assert_eq!(meshlet.first_vertex, next);
+            let run = &vertices[meshlet.first_vertex as usize..(meshlet.first_vertex + meshlet.num_vertices) as usize];
+            let (min, max) = run.iter().fold((run[0], run[0]), |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)));
+            assert_eq!((meshlet.min, meshlet.max), (min, max));
+            next += meshlet.num_vertices;
+        }
+        assert_eq!(next, lod.num_vertices);
+    }
+
+    #[test]
+    fn outward_meshlets_come_first() {
+        // A sphere inside a bigger one turned inside out: the outer sphere's meshlets face the
+        // centre and are drawn after the inner sphere's, which face away from it.
+        let inner = primitives::icosphere(vec3![0.0, 0.0, 0.0], 1.0, 3);
+        let mut outer = primitives::icosphere(vec3![0.0, 0.0, 0.0], 4.0, 3);
+        for triangle in outer.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+        let mut vertices: Vec<Vec3> = outer.iter().chain(&inner).copied().collect();
+        let mut lod = lod(&mut vertices);
+
+        optimize_lod(&mut vertices, &mut lod, &mut []);
+        let run = |meshlet: &Meshlet| &vertices[meshlet.first_vertex as usize..(meshlet.first_vertex + meshlet.num_vertices) as usize];
+        assert!(run(&lod.meshlets[0]).iter().all(|v| v.length() < 1.001));
+        assert!(run(lod.meshlets.last().unwrap()).iter().all(|v| v.length() > 3.999));
+    }
+
+    #[test]
+    fn flat_levels_are_left_alone() {
+        let mut vertices = vec![vec3![0.0, 0.0, 0.0]; 6];
+        let mut lod = lod(&mut vertices);
+        optimize_lod(&mut vertices, &mut lod, &mut []);
+        assert_eq!(vertices, vec![vec3![0.0, 0.0, 0.0]; 6]);
+    }
+}
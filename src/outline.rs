@@ -0,0 +1,92 @@
+//! What a scene holds, for outliners and tools: its entities, meshes and materials with their
+//! names, and lookups by name or ray.
+
+use crate::{
+    Vec3,
+    Visibility,
+    entity::{Entity, World},
+};
+
+/// A mesh of the scene, as drawn at its finest level of detail.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshInfo<'a> {
+    /// The index the renderer and [`RayTracer::select`](crate::RayTracer::select) know it by.
+    pub index: usize,
+    /// Name of its glTF node, or of the glTF mesh when the node has none.
+    pub name: Option<&'a str>,
+    /// The entity rendering it, if any.
+    pub entity: Option<Entity>,
+    pub min: Vec3,
+    pub max: Vec3,
+    pub triangles: usize,
+    /// Indices of the materials its primitives use, see [`Scene::materials`](crate::Scene::materials).
+    pub materials: &'a [usize],
+    pub visibility: Visibility,
+    pub layers: &'a [String],
+}
+
+/// A material of the scene.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MaterialInfo<'a> {
+    pub index: usize,
+    pub name: Option<&'a str>,
+    /// Linear RGBA base colour.
+    pub color: [f32; 4],
+}
+
+/// Refers to one object of a [`Scene`](crate::Scene).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectId {
+    Entity(Entity),
+    /// Index of a mesh, see [`Scene::meshes`](crate::Scene::meshes).
+    Mesh(usize),
+    /// Index of a material, see [`Scene::materials`](crate::Scene::materials).
+    Material(usize),
+}
+
+/// Where a ray first meets a mesh, see [`Scene::raycast`](crate::Scene::raycast).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit {
+    pub mesh: usize,
+    /// The entity rendering the mesh, if any.
+    pub entity: Option<Entity>,
+    /// Distance along the ray, in multiples of its direction's length.
+    pub distance: f32,
+    pub position: Vec3,
+}
+
+/// Indented tree of the entities of `world` with the names of what they hold, one entity per
+/// line, followed by the meshes no entity renders.
+pub(crate) fn tree(world: &World, meshes: &[MeshInfo]) -> String {
+    fn visit(world: &World, meshes: &[MeshInfo], entity: Entity, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(world.names.get(entity).map_or("(unnamed)", String::as_str));
+        out.push_str(&format!(" [entity {}]", entity.index()));
+        if let Some(mesh) = world.renderables.get(entity).and_then(|renderable| meshes.get(renderable.mesh)) {
+            out.push_str(&format!(" mesh {}: {} triangles", mesh.index, mesh.triangles));
+        }
+        if world.cameras.contains(entity) {
+            out.push_str(" camera");
+        }
+        if let Some(light) = world.lights.get(entity) {
+            out.push_str(&format!(" light {}", light.name.as_deref().unwrap_or("(unnamed)")));
+        }
+        out.push('\n');
+        for child in world.children(entity) {
+            visit(world, meshes, child, depth + 1, out);
+        }
+    }
+    let mut out = String::new();
+    for entity in world.entities().filter(|&entity| !world.parents.contains(entity)) {
+        visit(world, meshes, entity, 0, &mut out);
+    }
+    for mesh in meshes.iter().filter(|mesh| mesh.entity.is_none()) {
+        out.push_str(&format!(
+            "{} [mesh {}] {} triangles\n",
+            mesh.name.unwrap_or("(unnamed)"),
+            mesh.index,
+            mesh.triangles,
+        ));
+    }
+    out
+}
@@ -0,0 +1,273 @@
+//! Reference lines drawn over the render: a grid, the world axes, the bounds of selected
+//! meshes, measurements and clipping planes.
+
+use bytemuck::{Pod, Zeroable};
+
+use serde::{Deserialize, Serialize};
+
+use wgpu::{
+    util::{
+        BufferInitDescriptor,
+        DeviceExt,
+    },
+    BindGroup,
+    BindGroupLayout,
+    BlendState,
+    Buffer,
+    BufferAddress,
+    BufferUsages,
+    ColorTargetState,
+    Device,
+    FragmentState,
+    MultisampleState,
+    PipelineCompilationOptions,
+    PipelineLayoutDescriptor,
+    PrimitiveState,
+    PrimitiveTopology,
+    RenderPass,
+    RenderPipeline,
+    RenderPipelineDescriptor,
+    TextureFormat,
+    VertexAttribute,
+    VertexBufferLayout,
+    VertexState,
+    VertexStepMode,
+    vertex_attr_array,
+};
+
+use crate::{
+    clip::ClipPlane,
+    measure::Measurement,
+    memory::MemoryTracker,
+    shaders,
+    stereo::Channels,
+    Vec3,
+};
+
+const GRID_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 0.35];
+const AXIS_COLORS: [[f32; 4]; 3] = [[1.0, 0.2, 0.2, 1.0], [0.2, 1.0, 0.2, 1.0], [0.3, 0.4, 1.0, 1.0]];
+const BOUNDS_COLOR: [f32; 4] = [1.0, 0.8, 0.1, 1.0];
+pub(crate) const MEASUREMENT_COLOR: [f32; 4] = [0.2, 0.9, 1.0, 1.0];
+const CLIP_PLANE_COLOR: [f32; 4] = [1.0, 0.4, 0.1, 0.8];
+
+/// Guides and labels drawn on top of the scene in any render mode, each switched on
+/// separately.
+///
+/// They are not depth tested, so they show through surfaces. Annotations are drawn whatever
+/// these are, see [`Settings::annotations`](crate::Settings::annotations).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Overlays {
+    /// A grid on the ground plane y = 0 around the origin, covering the scene bounds with a
+    /// power of ten spacing.
+    pub grid: bool,
+    /// The X, Y and Z axes from the origin in red, green and blue.
+    pub axes: bool,
+    /// Boxes around the meshes chosen with [`RayTracer::select`](crate::RayTracer::select).
+    pub bounds: bool,
+    /// Frame time, scene size and GPU memory in the top-left corner.
+    pub hud: bool,
+    /// Names of the selected meshes above them.
+    pub labels: bool,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl LineVertex {
+    const ATTRIBS: [VertexAttribute; 2] = vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    fn new(position: Vec3, color: [f32; 4]) -> Self {
+        Self {
+            position: [position.x, position.y, position.z],
+            color,
+        }
+    }
+
+    fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Everything the overlay lines are built from.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OverlayContent {
+    pub(crate) overlays: Overlays,
+    /// Bounds of the scene, which the grid and axes cover.
+    pub(crate) bounds: (Vec3, Vec3),
+    /// Bounds of the selected meshes.
+    pub(crate) selected: Vec<(Vec3, Vec3)>,
+    /// Drawn whatever the overlays are.
+    pub(crate) measurements: Vec<Measurement>,
+    /// Outlined whatever the overlays are, as a gizmo showing where they cut.
+    pub(crate) clip_planes: Vec<ClipPlane>,
+}
+
+fn lines(content: &OverlayContent) -> Vec<LineVertex> {
+    let OverlayContent { overlays, bounds: (min, max), .. } = *content;
+    let mut lines = vec![];
+    // Half the side of a square around the origin that holds the scene's footprint.
+    let reach = [min.x, max.x, min.z, max.z].map(f32::abs).into_iter().fold(1e-3, f32::max);
+    if overlays.grid {
+        let spacing = 10f32.powf((reach * 0.5).log10().floor());
+        let count = (reach / spacing).ceil() as i32;
+        let half = count as f32 * spacing;
+        for i in -count..=count {
+            let offset = i as f32 * spacing;
+            lines.push(LineVertex::new(vec3![offset, 0.0, -half], GRID_COLOR));
+            lines.push(LineVertex::new(vec3![offset, 0.0, half], GRID_COLOR));
+            lines.push(LineVertex::new(vec3![-half, 0.0, offset], GRID_COLOR));
+            lines.push(LineVertex::new(vec3![half, 0.0, offset], GRID_COLOR));
+        }
+    }
+    if overlays.axes {
+        let length = reach.max(max.y.abs()).max(min.y.abs());
+        let axes = [vec3![length, 0.0, 0.0], vec3![0.0, length, 0.0], vec3![0.0, 0.0, length]];
+        for (axis, color) in axes.into_iter().zip(AXIS_COLORS) {
+            lines.push(LineVertex::new(Vec3::default(), color));
+            lines.push(LineVertex::new(axis, color));
+        }
+    }
+    if overlays.bounds {
+        for &(min, max) in &content.selected {
+            let corner = |i: usize| vec3![
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z }
+            ];
+            // Each edge joins two corners that differ in one axis bit.
+            for i in 0..8 {
+                for bit in [1, 2, 4] {
+                    if i & bit == 0 {
+                        lines.push(LineVertex::new(corner(i), BOUNDS_COLOR));
+                        lines.push(LineVertex::new(corner(i | bit), BOUNDS_COLOR));
+                    }
+                }
+            }
+        }
+    }
+    for measurement in &content.measurements {
+        lines.extend(measurement.lines().into_iter().map(|point| LineVertex::new(point, MEASUREMENT_COLOR)));
+    }
+    for plane in content.clip_planes.iter().filter(|plane| plane.normal != Vec3::default()) {
+        lines.extend(plane_outline(plane, min, max).into_iter().map(|point| LineVertex::new(point, CLIP_PLANE_COLOR)));
+    }
+    lines
+}
+
+/// Line list of a square on `plane` as wide as the box `min`..`max`, centred where the box's
+/// centre projects onto it, with a stroke along the normal.
+fn plane_outline(plane: &ClipPlane, min: Vec3, max: Vec3) -> Vec<Vec3> {
+    let normal = plane.normal.normalize();
+    let center = (min + max) * 0.5;
+    let center = center - normal * (center - plane.point).dot(normal);
+    let half = ((max - min).length() * 0.5).max(1e-3);
+    let helper = if normal.x.abs() < 0.9 { vec3![1.0, 0.0, 0.0] } else { vec3![0.0, 1.0, 0.0] };
+    let u = normal.cross(helper).normalize() * half;
+    let v = normal.cross(u);
+    let corners = [center + u + v, center - u + v, center - u - v, center + u - v];
+    let mut lines = vec![];
+    for i in 0..4 {
+        lines.extend([corners[i], corners[(i + 1) % 4]]);
+    }
+    lines.extend([center, center + normal * (half * 0.2)]);
+    lines
+}
+
+/// Draws [`Overlays`] as lines after everything else.
+pub(crate) struct OverlayPass {
+    /// One pipeline per [`Channels`] variant.
+    pipelines: [RenderPipeline; 3],
+    buffer: Option<Buffer>,
+    vertex_count: u32,
+    /// What the buffer was built from.
+    built: Option<OverlayContent>,
+}
+
+impl OverlayPass {
+    pub(crate) fn new(device: &Device, format: TextureFormat, camera_bind_group_layout: &BindGroupLayout) -> Self {
+        let shader = shaders::create_module(device, "Overlay shader", include_str!("overlay.wgsl"), &[], &[]);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = Channels::ALL.map(|channels| device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[LineVertex::desc()],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: channels.write_mask(),
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        }));
+
+        Self {
+            pipelines,
+            buffer: None,
+            vertex_count: 0,
+            built: None,
+        }
+    }
+
+    /// Rebuilds the lines if `content` changed since the last call.
+    pub(crate) fn update(&mut self, device: &Device, memory: &mut MemoryTracker, content: OverlayContent) {
+        if self.built.as_ref() == Some(&content) {
+            return;
+        }
+        let lines = lines(&content);
+        if let Some(buffer) = self.buffer.take() {
+            memory.release(&buffer);
+        }
+        self.buffer = (!lines.is_empty()).then(|| {
+            let buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Overlay buffer"),
+                contents: bytemuck::cast_slice(&lines),
+                usage: BufferUsages::VERTEX,
+            });
+            memory.track(&buffer);
+            buffer
+        });
+        self.vertex_count = lines.len() as u32;
+        self.built = Some(content);
+    }
+
+    pub(crate) fn draw(&self, render_pass: &mut RenderPass, camera_bind_group: &BindGroup, channels: Channels) {
+        let Some(buffer) = &self.buffer else {
+            return;
+        };
+        render_pass.set_pipeline(&self.pipelines[channels as usize]);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
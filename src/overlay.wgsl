@@ -0,0 +1,26 @@
+#include "camera.wgsl"
+
+struct LineInput {
+    @location(0) position: vec3f,
+    @location(1) color: vec4f,
+};
+
+struct LineOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec4f,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+@vertex
+fn vs_main(line: LineInput) -> LineOutput {
+    var out: LineOutput;
+    out.clip_position = project((camera.view * vec4f(line.position, 1.0)).xyz);
+    out.color = line.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: LineOutput) -> @location(0) vec4f {
+    return vec4f(in.color.rgb * in.color.a, in.color.a);
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Material;
+
+/// A named look that replaces every material in the scene, for checking lighting or geometry
+/// without the scene's own materials getting in the way.
+///
+/// Scenes can add their own as `extras.overrides` on their glTF scene, e.g.
+/// `[{"name": "red", "color": [1, 0, 0, 1]}]`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialOverride {
+    pub name: String,
+    /// Colour given to every material.
+    pub color: [f32; 4],
+    /// Cells per scene unit of a checkerboard darkening the colour. `0.0` disables it.
+    ///
+    /// Vertices carry no texture coordinates, so the checkerboard is laid out in world space.
+    pub checker: f32,
+    /// Background colour while the override is active, replacing [`Settings::bg_color`](crate::Settings::bg_color).
+    pub background: Option<[f32; 4]>,
+}
+
+impl Default for MaterialOverride {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            color: [0.8, 0.8, 0.8, 1.0],
+            checker: 0.0,
+            background: None,
+        }
+    }
+}
+
+impl MaterialOverride {
+    /// Overrides available in every scene: `clay`, `checker` and `white-furnace`.
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self {
+                name: "clay".into(),
+                ..Self::default()
+            },
+            Self {
+                name: "checker".into(),
+                checker: 4.0,
+                ..Self::default()
+            },
+            // White surfaces against a white background: with energy-conserving shading every
+            // object should disappear.
+            Self {
+                name: "white-furnace".into(),
+                color: [1.0; 4],
+                background: Some([1.0; 4]),
+                ..Self::default()
+            },
+        ]
+    }
+
+    pub(crate) fn material(&self) -> Material {
+        Material {
+            ambient: self.color,
+            diffuse: [0.0, 0.0, 0.0, 0.0],
+            specular: [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
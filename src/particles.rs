@@ -0,0 +1,423 @@
+//! Particles such as sparks, snow or dust, simulated in a compute pass every frame and drawn
+//! with the scene as camera-facing quads or spheres.
+//!
+//! Each emitter keeps a ring of particles, as many as live at once at its rate. New particles
+//! take the slots of the oldest, which have died by then.
+
+use std::f32::consts::TAU;
+
+use bytemuck::{Pod, Zeroable};
+
+use serde::{Deserialize, Serialize};
+
+use wgpu::{
+    util::{
+        BufferInitDescriptor,
+        DeviceExt,
+    },
+    BindGroup,
+    BindGroupDescriptor,
+    BindGroupEntry,
+    BindGroupLayout,
+    BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry,
+    BindingType,
+    Buffer,
+    BufferAddress,
+    BufferBindingType,
+    BufferUsages,
+    CommandEncoder,
+    ComputePassDescriptor,
+    ComputePipeline,
+    ComputePipelineDescriptor,
+    Device,
+    PipelineCompilationOptions,
+    PipelineLayoutDescriptor,
+    Queue,
+    ShaderStages,
+    VertexAttribute,
+    VertexBufferLayout,
+    VertexStepMode,
+    vertex_attr_array,
+};
+
+use crate::{
+    Vec3,
+    memory::MemoryTracker,
+    shaders,
+};
+
+/// Most particles an emitter keeps alive at once.
+const MAX_PARTICLES: u32 = 1 << 20;
+
+/// Longest step in seconds a frame advances the particles by, beyond which integrating them
+/// in one go gets inaccurate.
+const MAX_STEP: f32 = 0.1;
+
+/// Particles simulated by each invocation group, see `particles.wgsl`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// How a particle is drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParticleShape {
+    /// A quad facing the camera, fading out towards its edges, for sparks, dust and snow.
+    #[default]
+    Quad,
+    /// A shaded ball, for droplets and debris.
+    Sphere,
+}
+
+/// A source of particles. Scenes can supply them as `extras.particles` on their glTF scene, a
+/// list of emitters, e.g. snow falling over a 4 m square:
+/// `[{"position": {"x": 0, "y": 3, "z": 0}, "extent": {"x": 4, "y": 0, "z": 4}, "velocity": {"x": 0, "y": -0.5, "z": 0}, "acceleration": {"x": 0, "y": 0, "z": 0}, "spread": 0.2, "lifetime": 6, "color": [1, 1, 1, 1]}]`.
+///
+/// Emitters start warmed up, with a lifetime's worth of particles already in flight.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Emitter {
+    /// Centre of the box particles start in.
+    pub position: Vec3,
+    /// Size of the box particles start in, zero for a point, e.g. a wide flat box for snow.
+    pub extent: Vec3,
+    /// Particles started per second.
+    pub rate: f32,
+    /// Seconds each particle lives.
+    pub lifetime: f32,
+    /// Velocity particles start with, in scene units per second.
+    pub velocity: Vec3,
+    /// Largest random change to the starting velocity, in any direction.
+    pub spread: f32,
+    /// Change in velocity per second, such as gravity.
+    pub acceleration: Vec3,
+    /// Radius of each particle.
+    pub size: f32,
+    /// Linear RGBA colour.
+    pub color: [f32; 4],
+    /// Fades particles out over their lifetime.
+    pub fade: bool,
+    pub shape: ParticleShape,
+}
+
+impl Default for Emitter {
+    /// A fountain of sparks at the origin.
+    fn default() -> Self {
+        Self {
+            position: Vec3::default(),
+            extent: Vec3::default(),
+            rate: 200.0,
+            lifetime: 1.5,
+            velocity: vec3![0.0, 3.0, 0.0],
+            spread: 1.0,
+            acceleration: vec3![0.0, -9.81, 0.0],
+            size: 0.02,
+            color: [1.0, 0.6, 0.2, 1.0],
+            fade: true,
+            shape: ParticleShape::Quad,
+        }
+    }
+}
+
+impl Emitter {
+    /// Particles alive at once, the slots of its ring.
+    fn capacity(&self) -> u32 {
+        (self.rate.max(0.0) * self.lifetime.max(0.0)).ceil().clamp(1.0, MAX_PARTICLES as f32) as u32
+    }
+
+    /// Where a particle started from `seed` is after `age` seconds, and its velocity then.
+    fn launch(&self, seed: &mut u32, age: f32) -> (Vec3, Vec3) {
+        let mut random = || {
+            *seed = hash(*seed);
+            *seed as f32 / u32::MAX as f32
+        };
+        let offset = vec3![random() - 0.5, random() - 0.5, random() - 0.5];
+        let start = self.position + vec3![offset.x * self.extent.x, offset.y * self.extent.y, offset.z * self.extent.z];
+        let (z, angle, length) = (random() * 2.0 - 1.0, random() * TAU, random().cbrt());
+        let across = (1.0 - z * z).sqrt();
+        let velocity = self.velocity + vec3![across * angle.cos(), across * angle.sin(), z] * (length * self.spread);
+        let position = start + velocity * age + self.acceleration * (0.5 * age * age);
+        (position, velocity + self.acceleration * age)
+    }
+}
+
+/// PCG hash, as `particles.wgsl` uses for its random numbers.
+fn hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// A particle as drawn: one instance of the particle pipelines.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+pub(crate) struct ParticleInstance {
+    position: [f32; 3],
+    /// Zero for a dead particle, which draws nothing.
+    radius: f32,
+    color: [f32; 4],
+    /// 0 for a quad, 1 for a sphere.
+    shape: u32,
+    _padding: [u32; 3],
+}
+
+impl ParticleInstance {
+    const ATTRIBS: [VertexAttribute; 4] = vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32,
+        2 => Float32x4,
+        3 => Uint32,
+    ];
+
+    /// Each instance draws a six-vertex quad.
+    pub(crate) fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// What only the simulation needs of a particle.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+struct ParticleState {
+    velocity: [f32; 3],
+    age: f32,
+}
+
+/// An emitter and the step to simulate, see `Emitter` in `particles.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+struct EmitterUniform {
+    position: [f32; 4],
+    /// With the velocity spread in w.
+    extent: [f32; 4],
+    /// With the lifetime in w.
+    velocity: [f32; 4],
+    /// With the particle radius in w.
+    acceleration: [f32; 4],
+    color: [f32; 4],
+    /// First slot to start particles in, how many to start, the slot count and the shape.
+    spawn: [u32; 4],
+    /// Seconds to advance.
+    step: f32,
+    /// 1 if particles fade, otherwise 0.
+    fade: f32,
+    seed: u32,
+    _padding: u32,
+}
+
+impl EmitterUniform {
+    fn new(emitter: &Emitter, first: u32, count: u32, step: f32, seed: u32) -> Self {
+        let vector = |v: Vec3, w: f32| [v.x, v.y, v.z, w];
+        Self {
+            position: vector(emitter.position, 0.0),
+            extent: vector(emitter.extent, emitter.spread),
+            velocity: vector(emitter.velocity, emitter.lifetime),
+            acceleration: vector(emitter.acceleration, emitter.size),
+            color: emitter.color,
+            spawn: [first, count, emitter.capacity(), emitter.shape as u32],
+            step,
+            fade: if emitter.fade { 1.0 } else { 0.0 },
+            seed,
+            _padding: 0,
+        }
+    }
+}
+
+/// The GPU side of one emitter.
+struct EmitterBuffers {
+    emitter: Emitter,
+    uniform: Buffer,
+    instances: Buffer,
+    bind_group: BindGroup,
+    /// Particles started so far, which places the next in the ring.
+    started: u64,
+    /// Fraction of a particle due to start, carried to the next step.
+    pending: f32,
+}
+
+/// Simulates the scene's emitters and holds the particles the particle pipelines draw.
+pub(crate) struct Particles {
+    pipeline: ComputePipeline,
+    emitters: Vec<EmitterBuffers>,
+    /// Bytes of GPU memory the particles take.
+    pub(crate) allocated: u64,
+}
+
+impl Particles {
+    /// Sets up `emitters` with their particles warmed up, or returns `None` without any.
+    pub(crate) fn new(device: &Device, memory: &mut MemoryTracker, emitters: &[Emitter]) -> Option<Self> {
+        if emitters.is_empty() {
+            return None;
+        }
+        let shader = shaders::create_module(device, "Particle shader", include_str!("particles.wgsl"), &[], &[]);
+        let storage = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1),
+                storage(2),
+            ],
+            label: Some("particle_bind_group_layout"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Particle Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Particle Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("simulate"),
+            compilation_options: PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        let allocated_before = memory.usage().allocated;
+        let emitters = emitters.iter()
+            .map(|&emitter| EmitterBuffers::new(device, &bind_group_layout, memory, emitter))
+            .collect();
+        Some(Self {
+            pipeline,
+            emitters,
+            allocated: memory.usage().allocated - allocated_before,
+        })
+    }
+
+    /// Records a compute pass moving the particles on by `step` seconds of the timeline.
+    pub(crate) fn simulate(&mut self, queue: &Queue, encoder: &mut CommandEncoder, step: f32) {
+        let step = step.min(MAX_STEP);
+        if step <= 0.0 {
+            return;
+        }
+        for buffers in &mut self.emitters {
+            buffers.advance(queue, step);
+        }
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Particle Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        for buffers in &self.emitters {
+            pass.set_bind_group(0, &buffers.bind_group, &[]);
+            pass.dispatch_workgroups(buffers.emitter.capacity().div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    pub(crate) fn emitters(&self) -> Vec<Emitter> {
+        self.emitters.iter().map(|buffers| buffers.emitter).collect()
+    }
+
+    /// Each emitter's instance buffer and the number of instances in it.
+    pub(crate) fn instances(&self) -> impl Iterator<Item = (&Buffer, u32)> {
+        self.emitters.iter().map(|buffers| (&buffers.instances, buffers.emitter.capacity()))
+    }
+}
+
+impl EmitterBuffers {
+    fn new(device: &Device, layout: &BindGroupLayout, memory: &mut MemoryTracker, emitter: Emitter) -> Self {
+        // Slot i holds the particle started (capacity - i) / rate seconds ago; the next starts in
+        // slot zero.
+        let capacity = emitter.capacity();
+        let (instances, states): (Vec<_>, Vec<_>) = (0..capacity)
+            .map(|slot| {
+                let age = (capacity - 1 - slot) as f32 / emitter.rate.max(f32::MIN_POSITIVE);
+                let (position, velocity) = emitter.launch(&mut hash(slot), age);
+                let alive = age < emitter.lifetime;
+                let fade = if emitter.fade { 1.0 - age / emitter.lifetime } else { 1.0 };
+                let [r, g, b, a] = emitter.color;
+                let instance = ParticleInstance {
+                    position: [position.x, position.y, position.z],
+                    radius: if alive { emitter.size } else { 0.0 },
+                    color: [r, g, b, a * fade],
+                    shape: emitter.shape as u32,
+                    _padding: [0; 3],
+                };
+                let state = ParticleState {
+                    velocity: [velocity.x, velocity.y, velocity.z],
+                    age,
+                };
+                (instance, state)
+            })
+            .unzip();
+
+        let uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Emitter buffer"),
+            contents: bytemuck::bytes_of(&EmitterUniform::new(&emitter, 0, 0, 0.0, 0)),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let instances = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Particle buffer"),
+            contents: bytemuck::cast_slice(&instances),
+            usage: BufferUsages::VERTEX | BufferUsages::STORAGE,
+        });
+        let states = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Particle state buffer"),
+            contents: bytemuck::cast_slice(&states),
+            usage: BufferUsages::STORAGE,
+        });
+        for buffer in [&uniform, &instances, &states] {
+            memory.track(buffer);
+        }
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: instances.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: states.as_entire_binding(),
+                },
+            ],
+            label: Some("particle_bind_group"),
+        });
+        Self {
+            emitter,
+            uniform,
+            instances,
+            bind_group,
+            started: capacity as u64,
+            pending: 0.0,
+        }
+    }
+
+    /// Writes the uniform for a step of `step` seconds, starting the particles due in it.
+    fn advance(&mut self, queue: &Queue, step: f32) {
+        let capacity = self.emitter.capacity();
+        self.pending += self.emitter.rate.max(0.0) * step;
+        let count = (self.pending.floor() as u32).min(capacity);
+        self.pending -= self.pending.floor();
+        let first = (self.started % capacity as u64) as u32;
+        let seed = hash(self.started as u32 ^ (self.started >> 32) as u32);
+        self.started += count as u64;
+        let uniform = EmitterUniform::new(&self.emitter, first, count, step, seed);
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&uniform));
+    }
+}
@@ -0,0 +1,96 @@
+// Moves an emitter's particles on by a step, starting new ones in the slots of the oldest. See
+// particles.rs.
+
+// See `ParticleInstance` in particles.rs.
+struct Particle {
+    position: vec3f,
+    // Zero for a dead particle.
+    radius: f32,
+    color: vec4f,
+    shape: u32,
+};
+
+struct ParticleState {
+    velocity: vec3f,
+    age: f32,
+};
+
+// See `EmitterUniform` in particles.rs.
+struct Emitter {
+    position: vec4f,
+    // With the velocity spread in w.
+    extent: vec4f,
+    // With the lifetime in w.
+    velocity: vec4f,
+    // With the particle radius in w.
+    acceleration: vec4f,
+    color: vec4f,
+    // First slot to start particles in, how many to start, the slot count and the shape.
+    spawn: vec4u,
+    step: f32,
+    fade: f32,
+    seed: u32,
+};
+
+const TAU: f32 = 6.283185307;
+
+@group(0) @binding(0) var<uniform> emitter: Emitter;
+@group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(2) var<storage, read_write> states: array<ParticleState>;
+
+var<private> rng: u32;
+
+// PCG hash, matching `hash` in particles.rs.
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random() -> f32 {
+    rng = hash(rng);
+    return f32(rng) / 4294967295.0;
+}
+
+@compute @workgroup_size(64)
+fn simulate(@builtin(global_invocation_id) id: vec3u) {
+    let slot = id.x;
+    let capacity = emitter.spawn.z;
+    if slot >= capacity {
+        return;
+    }
+    let lifetime = emitter.velocity.w;
+    let acceleration = emitter.acceleration.xyz;
+    var particle = particles[slot];
+    var state = states[slot];
+
+    let started = (slot + capacity - emitter.spawn.x) % capacity;
+    if started < emitter.spawn.y {
+        // Started partway through the step, the later slots later, as `Emitter::launch` does.
+        rng = hash(emitter.seed ^ slot);
+        let age = emitter.step * (f32(emitter.spawn.y - started) - 0.5) / f32(emitter.spawn.y);
+        let offset = vec3f(random(), random(), random()) - 0.5;
+        let start = emitter.position.xyz + offset * emitter.extent.xyz;
+        let z = random() * 2.0 - 1.0;
+        let angle = random() * TAU;
+        let length = pow(random(), 1.0 / 3.0);
+        let across = sqrt(1.0 - z * z);
+        let velocity = emitter.velocity.xyz
+            + vec3f(across * cos(angle), across * sin(angle), z) * (length * emitter.extent.w);
+        particle.position = start + velocity * age + acceleration * (0.5 * age * age);
+        state.velocity = velocity + acceleration * age;
+        state.age = age;
+    } else {
+        state.velocity += acceleration * emitter.step;
+        particle.position += state.velocity * emitter.step;
+        state.age += emitter.step;
+    }
+
+    let alive = state.age < lifetime;
+    let fade = select(1.0, 1.0 - state.age / lifetime, emitter.fade > 0.0);
+    particle.radius = select(0.0, emitter.acceleration.w, alive);
+    particle.color = vec4f(emitter.color.rgb, emitter.color.a * clamp(fade, 0.0, 1.0));
+    particle.shape = emitter.spawn.w;
+    particles[slot] = particle;
+    states[slot] = state;
+}
@@ -0,0 +1,262 @@
+//! Rigid-body physics for the scene's meshes, through `RapierEngine` in builds with the
+//! `physics` feature or an engine the application supplies.
+//!
+//! Nodes opt in with a [`RigidBody`] in their `extras.physics`. Colliders are generated from the
+//! meshes they draw, in world space, and the engine is stepped every frame. Meshes of bodies
+//! that moved have their vertices rewritten, which refits their bounds for culling.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Scene,
+    Vec3,
+    entity::Entity,
+};
+
+/// Seconds the engine is advanced by in each step, as engines are most stable with a fixed one.
+const FIXED_STEP: f32 = 1.0 / 60.0;
+
+/// Most seconds a frame catches up on, so a fast timeline doesn't run a burst of steps.
+const MAX_STEP: f32 = 0.1;
+
+/// Whether a body moves.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyKind {
+    /// Falls and collides.
+    #[default]
+    Dynamic,
+    /// Stays where it is, for floors and walls.
+    Fixed,
+}
+
+/// Shape of the collider generated from a body's mesh.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColliderShape {
+    /// The mesh's bounding box.
+    Box,
+    /// The convex hull of the mesh's vertices.
+    #[default]
+    Hull,
+    /// The triangles themselves, which engines usually only collide fixed bodies with.
+    Mesh,
+}
+
+/// How a node's mesh moves under physics, read from the node's `extras.physics`, e.g.
+/// `{"physics": {"body": "fixed", "collider": "mesh"}}` for a floor.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RigidBody {
+    pub body: BodyKind,
+    pub collider: ColliderShape,
+    /// Mass per cubic metre.
+    pub density: f32,
+    pub friction: f32,
+    /// Bounciness, from 0 for none to 1 for a perfectly elastic collision.
+    pub restitution: f32,
+    /// Velocity the body starts with, in metres per second.
+    pub velocity: Vec3,
+}
+
+impl Default for RigidBody {
+    fn default() -> Self {
+        Self {
+            body: BodyKind::Dynamic,
+            collider: ColliderShape::Hull,
+            density: 1000.0,
+            friction: 0.5,
+            restitution: 0.0,
+            velocity: Vec3::default(),
+        }
+    }
+}
+
+/// Collision geometry generated from a mesh, in world space.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Collider {
+    Box {
+        center: Vec3,
+        half_extents: Vec3,
+    },
+    /// Points to take the convex hull of.
+    Hull(Vec<Vec3>),
+    Mesh {
+        vertices: Vec<Vec3>,
+        triangles: Vec<[u32; 3]>,
+    },
+}
+
+impl Collider {
+    /// Builds a collider of `shape` from a triangle list, three vertices per triangle.
+    fn new(shape: ColliderShape, triangles: &[Vec3]) -> Self {
+        match shape {
+            ColliderShape::Box => {
+                let (min, max) = triangles.iter().fold(
+                    (vec3![f32::MAX, f32::MAX, f32::MAX], vec3![f32::MIN, f32::MIN, f32::MIN]),
+                    |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)),
+                );
+                Self::Box {
+                    center: (min + max) * 0.5,
+                    half_extents: (max - min) * 0.5,
+                }
+            }
+            ColliderShape::Hull => Self::Hull(weld(triangles).0),
+            ColliderShape::Mesh => {
+                let (vertices, indices) = weld(triangles);
+                let triangles = indices.chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect();
+                Self::Mesh { vertices, triangles }
+            }
+        }
+    }
+}
+
+/// Merges vertices of a triangle list at exactly the same position, returning the distinct
+/// positions and each corner's index into them.
+fn weld(triangles: &[Vec3]) -> (Vec<Vec3>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut seen = HashMap::new();
+    let indices = triangles.iter()
+        .map(|&v| *seen.entry([v.x, v.y, v.z].map(f32::to_bits)).or_insert_with(|| {
+            vertices.push(v);
+            vertices.len() as u32 - 1
+        }))
+        .collect();
+    (vertices, indices)
+}
+
+/// A body for the engine to add: a mesh of the scene and the node it belongs to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicsBody {
+    /// Index of the mesh, which [`PhysicsEngine::pose`] is asked about.
+    pub mesh: usize,
+    pub entity: Entity,
+    pub body: RigidBody,
+    pub collider: Collider,
+}
+
+/// Where a body has moved to since it was loaded: a rotation about the origin followed by a
+/// translation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pose {
+    /// Unit quaternion as x, y, z and w.
+    pub rotation: [f32; 4],
+    pub translation: Vec3,
+}
+
+impl Pose {
+    pub const IDENTITY: Self = Self {
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        translation: vec3![0.0, 0.0, 0.0],
+    };
+
+    pub fn apply(&self, point: Vec3) -> Vec3 {
+        let [x, y, z, w] = self.rotation;
+        let axis = vec3![x, y, z];
+        // v + 2w(q × v) + 2q × (q × v)
+        let t = axis.cross(point) * 2.0;
+        point + t * w + axis.cross(t) + self.translation
+    }
+}
+
+/// A rigid-body engine moving the scene's meshes, such as `RapierEngine` in builds with the
+/// `physics` feature.
+///
+/// Colliders are in world space and bodies start at the origin, so a body's position is
+/// already the pose of its mesh.
+pub trait PhysicsEngine {
+    /// Replaces any bodies with those of a newly loaded scene.
+    fn load(&mut self, bodies: &[PhysicsBody]);
+
+    /// Advances the simulation by `step` seconds.
+    fn step(&mut self, step: f32);
+
+    /// Where the body of mesh `mesh` is, or `None` if it has none.
+    fn pose(&self, mesh: usize) -> Option<Pose>;
+}
+
+/// A dynamic body's mesh as loaded and the pose it was last drawn in.
+struct MovingMesh {
+    mesh: usize,
+    vertices: Vec<Vec3>,
+    pose: Pose,
+}
+
+/// Steps the engine in fixed increments and tracks which meshes it moves.
+pub(crate) struct Physics {
+    engine: Box<dyn PhysicsEngine>,
+    /// Bodies of the loaded scene, handed to the engine again to start over.
+    bodies: Vec<PhysicsBody>,
+    moving: Vec<MovingMesh>,
+    /// Seconds not yet stepped, under a fixed step.
+    pending: f32,
+}
+
+impl Physics {
+    pub(crate) fn new(engine: Box<dyn PhysicsEngine>) -> Self {
+        Self {
+            engine,
+            bodies: Vec::new(),
+            moving: Vec::new(),
+            pending: 0.0,
+        }
+    }
+
+    /// Hands the engine the bodies of `scene`: one for each entity with a [`RigidBody`]
+    /// drawing a mesh.
+    pub(crate) fn load(&mut self, scene: &Scene) {
+        let world = &scene.world;
+        let bodies: Vec<PhysicsBody> = world.bodies.iter()
+            .filter_map(|(entity, &body)| {
+                let mesh = world.renderables.get(entity)?.mesh;
+                Some(PhysicsBody {
+                    mesh,
+                    entity,
+                    body,
+                    collider: Collider::new(body.collider, scene.mesh_vertices(mesh)),
+                })
+            })
+            .collect();
+        self.moving = bodies.iter()
+            .filter(|body| body.body.body == BodyKind::Dynamic)
+            .map(|body| MovingMesh {
+                mesh: body.mesh,
+                vertices: scene.mesh_vertices(body.mesh).to_vec(),
+                pose: Pose::IDENTITY,
+            })
+            .collect();
+        if !bodies.is_empty() {
+            log::info!("Simulating {} rigid bodies", bodies.len());
+        }
+        self.bodies = bodies;
+        self.restart();
+    }
+
+    /// Hands the engine the bodies again, as they were loaded.
+    pub(crate) fn restart(&mut self) {
+        self.engine.load(&self.bodies);
+        self.pending = 0.0;
+    }
+
+    /// Steps the engine on by `step` seconds of the timeline and returns the meshes it moved,
+    /// each with its vertices in their new place.
+    pub(crate) fn step(&mut self, step: f32) -> Vec<(usize, Vec<Vec3>)> {
+        self.pending += step.min(MAX_STEP);
+        while self.pending >= FIXED_STEP {
+            self.engine.step(FIXED_STEP);
+            self.pending -= FIXED_STEP;
+        }
+        let mut moved = Vec::new();
+        for moving in &mut self.moving {
+            let pose = self.engine.pose(moving.mesh).unwrap_or(Pose::IDENTITY);
+            if pose == moving.pose {
+                continue;
+            }
+            moving.pose = pose;
+            moved.push((moving.mesh, moving.vertices.iter().map(|&v| pose.apply(v)).collect()));
+        }
+        moved
+    }
+}
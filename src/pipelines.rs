@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use pollster::block_on;
+
+use wgpu::{
+    BlendComponent,
+    BlendFactor,
+    BlendOperation,
+    BlendState,
+    ColorTargetState,
+    Device,
+    ErrorFilter,
+    Face,
+    FragmentState,
+    FrontFace,
+    MultisampleState,
+    PipelineCompilationOptions,
+    PipelineLayout,
+    PolygonMode,
+    PrimitiveState,
+    PrimitiveTopology,
+    RenderPipeline,
+    RenderPipelineDescriptor,
+    ShaderModule,
+    TextureFormat,
+    VertexBufferLayout,
+    VertexState,
+};
+
+use crate::{
+    AnalysisView,
+    Desc,
+    Settings,
+    Vec3,
+    particles::ParticleInstance,
+    quantize::QuantizedVertex,
+    scene::Splat,
+    shaders,
+    stereo::Channels,
+};
+
+/// Shader features compiled into a set of pipelines, so features that are off cost nothing
+/// in the shader.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Permutation {
+    pub(crate) id_matte: bool,
+    pub(crate) analysis: Option<AnalysisView>,
+    pub(crate) nan_check: bool,
+    /// Whether the active material override draws a checkerboard.
+    pub(crate) checker: bool,
+    /// Whether the scene's material graph is drawn.
+    pub(crate) material_graph: bool,
+    /// Whether mesh vertices are 16-bit positions to dequantize.
+    pub(crate) quantized: bool,
+}
+
+impl Permutation {
+    pub(crate) fn new(settings: &Settings, checker: bool, material_graph: bool) -> Self {
+        Self {
+            id_matte: settings.id_matte,
+            analysis: settings.analysis,
+            nan_check: settings.nan_check,
+            checker,
+            material_graph,
+            quantized: false,
+        }
+    }
+
+    /// Preprocessor defines selecting the permutation in `shader.wgsl`.
+    fn defines(&self) -> Vec<&'static str> {
+        let mut defines = vec![];
+        if self.id_matte {
+            defines.push("ID_MATTE");
+        }
+        if self.analysis == Some(AnalysisView::Luminance) {
+            defines.push("FALSE_COLOR");
+        }
+        if self.nan_check {
+            defines.push("NAN_CHECK");
+        }
+        if self.checker {
+            defines.push("CHECKER");
+        }
+        if self.material_graph {
+            defines.push("MATERIAL_GRAPH");
+        }
+        if self.quantized {
+            defines.push("QUANTIZED");
+        }
+        defines
+    }
+}
+
+/// Pipelines of one [`Permutation`], each indexed by [`Channels`] so anaglyph eyes can mask
+/// their writes.
+pub(crate) struct Pipelines {
+    pub(crate) meshes: [RenderPipeline; 3],
+    pub(crate) splats: [RenderPipeline; 3],
+    /// Line pipelines drawing debug rays.
+    pub(crate) rays: [RenderPipeline; 3],
+    /// The ground plane, drawn from six vertices without a buffer.
+    pub(crate) ground: [RenderPipeline; 3],
+    /// Mesh shadows flattened onto the ground plane.
+    pub(crate) shadows: [RenderPipeline; 3],
+    /// Mesh back faces in the section cap colour.
+    pub(crate) sections: [RenderPipeline; 3],
+    pub(crate) particles: [RenderPipeline; 3],
+    /// Particle shadows flattened onto the ground plane.
+    pub(crate) particle_shadows: [RenderPipeline; 3],
+}
+
+/// Keeps the darkest colour and the most opaque alpha, so overlapping shadows don't add up.
+const SHADOW_BLENDING: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Min,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Max,
+    },
+};
+
+/// Pipelines built so far, keyed by permutation. Switching back to a permutation reuses them.
+pub(crate) struct PipelineCache {
+    layout: PipelineLayout,
+    format: TextureFormat,
+    /// WGSL from the scene defining `custom_shade`, see [`Scene::set_custom_shading`].
+    ///
+    /// [`Scene::set_custom_shading`]: crate::Scene::set_custom_shading
+    custom_shading: Option<String>,
+    /// WGSL defining `material_graph`, see [`MaterialGraph::compile`].
+    ///
+    /// [`MaterialGraph::compile`]: crate::MaterialGraph::compile
+    material_graph: Option<String>,
+    pipelines: HashMap<Permutation, Pipelines>,
+}
+
+impl PipelineCache {
+    pub(crate) fn new(
+        layout: PipelineLayout,
+        format: TextureFormat,
+        custom_shading: Option<String>,
+        material_graph: Option<String>,
+    ) -> Self {
+        Self {
+            layout,
+            format,
+            custom_shading,
+            material_graph,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Replaces the scene's shading code, dropping the cached pipelines if it changed.
+    pub(crate) fn set_shading(&mut self, custom_shading: Option<String>, material_graph: Option<String>) {
+        if custom_shading != self.custom_shading || material_graph != self.material_graph {
+            self.custom_shading = custom_shading;
+            self.material_graph = material_graph;
+            self.pipelines.clear();
+        }
+    }
+
+    /// Builds the pipelines of `permutation` unless they are cached already.
+    pub(crate) fn prepare(&mut self, device: &Device, permutation: Permutation) {
+        if self.pipelines.contains_key(&permutation) {
+            return;
+        }
+        log::info!("Building pipelines for {permutation:?}");
+        let shader = self.shader_module(device, permutation);
+        // Meshes, splats, particles, debug rays and the ground share the shader module and bind
+        // groups.
+        let build = |
+            label: &str,
+            entry_points: (&str, &str),
+            buffers: &[VertexBufferLayout<'static>],
+            topology: PrimitiveTopology,
+            cull_mode: Option<Face>,
+            blend: BlendState,
+            channels: Channels,
+        | device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&self.layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some(entry_points.0),
+                buffers,
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some(entry_points.1),
+                targets: &[Some(ColorTargetState {
+                    format: self.format,
+                    blend: Some(blend),
+                    write_mask: channels.write_mask(),
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState {
+                topology,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode,
+                polygon_mode: PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+        let mesh_vertex = [if permutation.quantized { QuantizedVertex::desc() } else { Vec3::desc() }];
+        self.pipelines.insert(permutation, Pipelines {
+            meshes: Channels::ALL.map(|channels| build(
+                "Render Pipeline",
+                ("vs_main", "fs_main"),
+                &mesh_vertex,
+                PrimitiveTopology::TriangleList,
+                Some(Face::Back),
+                BlendState::ALPHA_BLENDING,
+                channels,
+            )),
+            splats: Channels::ALL.map(|channels| build(
+                "Splat Pipeline",
+                ("vs_splat", "fs_splat"),
+                &[Splat::desc()],
+                PrimitiveTopology::TriangleList,
+                None,
+                BlendState::ALPHA_BLENDING,
+                channels,
+            )),
+            rays: Channels::ALL.map(|channels| build(
+                "Debug Ray Pipeline",
+                ("vs_ray", "fs_ray"),
+                &[Vec3::desc()],
+                PrimitiveTopology::LineList,
+                None,
+                BlendState::ALPHA_BLENDING,
+                channels,
+            )),
+            ground: Channels::ALL.map(|channels| build(
+                "Ground Pipeline",
+                ("vs_ground", "fs_ground"),
+                &[],
+                PrimitiveTopology::TriangleList,
+                None,
+                BlendState::ALPHA_BLENDING,
+                channels,
+            )),
+            shadows: Channels::ALL.map(|channels| build(
+                "Shadow Pipeline",
+                ("vs_shadow", "fs_shadow"),
+                &mesh_vertex,
+                PrimitiveTopology::TriangleList,
+                None,
+                SHADOW_BLENDING,
+                channels,
+            )),
+            sections: Channels::ALL.map(|channels| build(
+                "Section Pipeline",
+                ("vs_main", "fs_section"),
+                &mesh_vertex,
+                PrimitiveTopology::TriangleList,
+                Some(Face::Front),
+                BlendState::ALPHA_BLENDING,
+                channels,
+            )),
+            particles: Channels::ALL.map(|channels| build(
+                "Particle Render Pipeline",
+                ("vs_particle", "fs_particle"),
+                &[ParticleInstance::desc()],
+                PrimitiveTopology::TriangleList,
+                None,
+                BlendState::ALPHA_BLENDING,
+                channels,
+            )),
+            particle_shadows: Channels::ALL.map(|channels| build(
+                "Particle Shadow Pipeline",
+                ("vs_particle_shadow", "fs_particle_shadow"),
+                &[ParticleInstance::desc()],
+                PrimitiveTopology::TriangleList,
+                None,
+                SHADOW_BLENDING,
+                channels,
+            )),
+        });
+    }
+
+    /// Whether the scene has a material graph to draw.
+    pub(crate) fn has_material_graph(&self) -> bool {
+        self.material_graph.is_some()
+    }
+
+    /// Compiles `shader.wgsl` for `permutation`, with the scene's custom shading if it has any.
+    ///
+    /// Custom shading that fails to preprocess or compile is logged and dropped for good.
+    fn shader_module(&mut self, device: &Device, permutation: Permutation) -> ShaderModule {
+        let mut defines = permutation.defines();
+        let mut modules: Vec<(&str, &str)> = vec![];
+        let mut failed = false;
+        if let Some(material_graph) = self.material_graph.as_deref().filter(|_| permutation.material_graph) {
+            modules.push(("material.wgsl", material_graph));
+        }
+        if let Some(custom_shading) = &self.custom_shading {
+            defines.push("CUSTOM_SHADING");
+            modules.push(("custom.wgsl", custom_shading.as_str()));
+            device.push_error_scope(ErrorFilter::Validation);
+            let shader = shaders::try_create_module(
+                device,
+                "Shader",
+                include_str!("shader.wgsl"),
+                &defines,
+                &modules,
+            );
+            let err = match (shader, block_on(device.pop_error_scope())) {
+                (Ok(shader), None) => return shader,
+                (Err(err), _) => err,
+                (Ok(_), Some(err)) => err.to_string(),
+            };
+            log::warn!("Custom shading failed to compile, using the built-in shading: {err}");
+            failed = true;
+            defines.pop();
+            modules.pop();
+        }
+        let shader = shaders::create_module(device, "Shader", include_str!("shader.wgsl"), &defines, &modules);
+        if failed {
+            self.custom_shading = None;
+        }
+        shader
+    }
+
+    /// Pipelines of `permutation`, which must have been prepared.
+    pub(crate) fn get(&self, permutation: &Permutation) -> &Pipelines {
+        &self.pipelines[permutation]
+    }
+}
@@ -0,0 +1,93 @@
+use std::ops::Range;
+
+use wgpu::{
+    Buffer,
+    CommandEncoder,
+    Device,
+    Queue,
+    TextureFormat,
+    TextureView,
+};
+
+use crate::Camera;
+
+/// GPU objects a [`RenderPlugin`] can build its resources with.
+pub struct PluginContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    /// Format of the targets the plugin draws into.
+    pub format: TextureFormat,
+}
+
+/// The frame a [`RenderPlugin`] is encoding into.
+pub struct FrameInfo<'a> {
+    /// The scene as drawn into the target, ready for post effects or overlays to load.
+    pub target: &'a TextureView,
+    pub width: u32,
+    pub height: u32,
+    pub camera: Camera,
+    /// Column-major view-projection matrix of the camera for this frame.
+    pub view_proj: [[f32; 4]; 4],
+}
+
+/// An extra pass run after the scene is drawn every frame, such as a post effect, an overlay or
+/// an exporter.
+///
+/// Plugins are run in the order they were added.
+pub trait RenderPlugin {
+    /// Called once when the plugin is added to a renderer, to create pipelines and buffers.
+    fn setup(&mut self, _context: &PluginContext) {}
+
+    /// Called before the first frame and whenever the target size changes.
+    fn resize(&mut self, _context: &PluginContext, _width: u32, _height: u32) {}
+
+    /// Records the plugin's work for this frame into `encoder`, which is submitted with the
+    /// scene's passes.
+    fn encode(&mut self, context: &PluginContext, encoder: &mut CommandEncoder, frame: &FrameInfo);
+}
+
+/// The scene's GPU buffers an [`AnimationPass`] can write. They are replaced when the scene is
+/// reloaded, so bind groups made from them are best made each frame.
+pub struct SceneBuffers<'a> {
+    /// Every mesh's vertex positions, three per triangle: three `f32`s each, or with
+    /// [`Settings::quantize_vertices`](crate::Settings::quantize_vertices) four `u16`s, the
+    /// position in steps across the mesh's bounds and the mesh's index.
+    pub vertices: &'a Buffer,
+    pub quantized: bool,
+    /// Vertices of the finest level of detail of each mesh in `vertices`, by mesh index.
+    pub meshes: &'a [Range<u32>],
+    /// One instance per point cloud splat: position, radius, normal and RGBA colour, eleven
+    /// `f32`s in all. `None` without a point cloud.
+    pub splats: Option<&'a Buffer>,
+}
+
+/// When an [`AnimationPass`] runs.
+pub struct AnimationFrame {
+    /// Frames animated before this one.
+    pub frame: u64,
+    /// Position of the viewer's [`Timeline`](crate::Timeline) in seconds.
+    pub time: f32,
+    /// Seconds the timeline played since the pass last ran, zero after a seek while paused.
+    pub step: f32,
+}
+
+/// A compute pass moving the scene's vertices or splats on the GPU before every frame is drawn,
+/// such as ocean waves or a cloth solver's output. Passes only run while the timeline moves.
+///
+/// Passes are run in the order they were added, in the frame's command encoder, so what they
+/// write is what the frame draws. Meshes a pass moves are drawn whole at their finest level of
+/// detail, since their bounds no longer say where they are; picking and measuring still see
+/// them where they were loaded.
+pub trait AnimationPass {
+    /// Called once when the pass is added to a renderer, to create pipelines and buffers.
+    fn setup(&mut self, _context: &PluginContext) {}
+
+    /// Indices of the meshes the pass moves.
+    fn meshes(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /// Records the pass's compute work for this frame into `encoder`. Buffers are bound as
+    /// storage; the renderer orders the writes before the frame's draws.
+    fn encode(&mut self, context: &PluginContext, encoder: &mut CommandEncoder, buffers: &SceneBuffers, frame: &AnimationFrame);
+}
@@ -0,0 +1,352 @@
+//! Reader for PLY files in ASCII or binary form.
+//!
+//! Files with faces become a triangle mesh. Files with only vertices are point clouds, drawn as
+//! splats. Vertex normals (`nx ny nz`), colours (`red green blue`) and per-point `radius` are
+//! used when present.
+
+use std::{
+    io,
+    str::SplitAsciiWhitespace,
+};
+
+use crate::{
+    Vec3,
+    scene::Splat,
+};
+
+/// Colour of points without one of their own.
+const DEFAULT_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+/// Splat radius relative to the average point spacing, when the file gives none.
+const RADIUS_SCALE: f32 = 0.75;
+
+pub(crate) enum PlyData {
+    Mesh(Vec<Vec3>),
+    Points(Vec<Splat>),
+}
+
+#[derive(Copy, Clone)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<(String, Property)>,
+}
+
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Body<'_> {
+    fn read(&mut self, kind: Scalar) -> io::Result<f64> {
+        match self {
+            Body::Ascii(tokens) => tokens.next()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| invalid("truncated or malformed ASCII data")),
+            Body::Binary { bytes, big_endian } => {
+                let size = kind.size();
+                if bytes.len() < size {
+                    return Err(invalid("truncated binary data"));
+                }
+                let (value, rest) = bytes.split_at(size);
+                *bytes = rest;
+                let mut buf = [0; 8];
+                buf[..size].copy_from_slice(value);
+                if *big_endian {
+                    buf[..size].reverse();
+                }
+                Ok(match kind {
+                    Scalar::I8 => buf[0] as i8 as f64,
+                    Scalar::U8 => buf[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("PLY: {message}"))
+}
+
+pub(crate) fn parse(bytes: &[u8]) -> io::Result<PlyData> {
+    let header_end = bytes.windows(10)
+        .position(|window| window == b"end_header")
+        .ok_or_else(|| invalid("missing end_header"))?;
+    let body_start = bytes[header_end..].iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(bytes.len(), |newline| header_end + newline + 1);
+    let header = std::str::from_utf8(&bytes[..header_end]).map_err(|_| invalid("header is not UTF-8"))?;
+
+    let mut lines = header.lines().map(str::split_whitespace);
+    if lines.next().and_then(|mut line| line.next()) != Some("ply") {
+        return Err(invalid("not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for mut words in lines {
+        match words.next() {
+            Some("format") => format = words.next(),
+            Some("element") => {
+                let (Some(name), Some(count)) = (words.next(), words.next().and_then(|count| count.parse().ok())) else {
+                    return Err(invalid("malformed element"));
+                };
+                elements.push(Element {
+                    name: name.to_owned(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            Some("property") => {
+                let words: Vec<&str> = words.collect();
+                let property = match words[..] {
+                    ["list", count, item, name] => Scalar::parse(count).zip(Scalar::parse(item))
+                        .map(|(count, item)| (name, Property::List { count, item })),
+                    [kind, name] => Scalar::parse(kind).map(|kind| (name, Property::Scalar(kind))),
+                    _ => None,
+                };
+                let (name, property) = property.ok_or_else(|| invalid("malformed property"))?;
+                elements.last_mut()
+                    .ok_or_else(|| invalid("property before element"))?
+                    .properties.push((name.to_owned(), property));
+            }
+            _ => {}
+        }
+    }
+
+    let mut body = match format {
+        Some("ascii") => {
+            let text = std::str::from_utf8(&bytes[body_start..]).map_err(|_| invalid("body is not UTF-8"))?;
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        Some("binary_little_endian") => Body::Binary { bytes: &bytes[body_start..], big_endian: false },
+        Some("binary_big_endian") => Body::Binary { bytes: &bytes[body_start..], big_endian: true },
+        _ => return Err(invalid("unsupported format")),
+    };
+
+    let mut splats = Vec::new();
+    let mut faces: Vec<Vec<usize>> = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut splat = Splat {
+                position: [0.0; 3],
+                radius: 0.0,
+                normal: [0.0; 3],
+                color: DEFAULT_COLOR,
+            };
+            for (name, property) in &element.properties {
+                match property {
+                    Property::Scalar(kind) => {
+                        let value = body.read(*kind)?;
+                        // Integer colours are 0-255, floating point ones 0-1.
+                        let color = match kind {
+                            Scalar::F32 | Scalar::F64 => value as f32,
+                            _ => value as f32 / 255.0,
+                        };
+                        match name.as_str() {
+                            "x" => splat.position[0] = value as f32,
+                            "y" => splat.position[1] = value as f32,
+                            "z" => splat.position[2] = value as f32,
+                            "nx" => splat.normal[0] = value as f32,
+                            "ny" => splat.normal[1] = value as f32,
+                            "nz" => splat.normal[2] = value as f32,
+                            "red" => splat.color[0] = color,
+                            "green" => splat.color[1] = color,
+                            "blue" => splat.color[2] = color,
+                            "alpha" => splat.color[3] = color,
+                            "radius" => splat.radius = value as f32,
+                            _ => {}
+                        }
+                    }
+                    Property::List { count, item } => {
+                        let count = body.read(*count)? as usize;
+                        let indices = (0..count)
+                            .map(|_| body.read(*item).map(|index| index as usize))
+                            .collect::<io::Result<Vec<_>>>()?;
+                        if element.name == "face" && matches!(name.as_str(), "vertex_indices" | "vertex_index") {
+                            faces.push(indices);
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                splats.push(splat);
+            }
+        }
+    }
+
+    if !faces.is_empty() {
+        let mut triangles = Vec::new();
+        for face in faces {
+            for i in 1..face.len().saturating_sub(1) {
+                for index in [face[0], face[i], face[i + 1]] {
+                    let splat = splats.get(index).ok_or_else(|| invalid("face index out of range"))?;
+                    triangles.push(Vec3::from(splat.position));
+                }
+            }
+        }
+        return Ok(PlyData::Mesh(triangles));
+    }
+
+    // Without radii, size splats to roughly cover the surface the points were sampled from.
+    if splats.iter().any(|splat| splat.radius <= 0.0) {
+        let (min, max) = splats.iter()
+            .map(|splat| Vec3::from(splat.position))
+            .fold((Vec3::from([f32::MAX; 3]), Vec3::from([f32::MIN; 3])), |(min, max), p| {
+                (Vec3::min(min, p), Vec3::max(max, p))
+            });
+        let spacing = (max - min).length() / (splats.len() as f32).sqrt();
+        for splat in splats.iter_mut().filter(|splat| splat.radius <= 0.0) {
+            splat.radius = spacing * RADIUS_SCALE;
+        }
+    }
+    Ok(PlyData::Points(splats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh(bytes: &[u8]) -> Vec<Vec3> {
+        match parse(bytes).unwrap() {
+            PlyData::Mesh(triangles) => triangles,
+            PlyData::Points(_) => panic!("expected a mesh"),
+        }
+    }
+
+    fn points(bytes: &[u8]) -> Vec<Splat> {
+        match parse(bytes).unwrap() {
+            PlyData::Points(splats) => splats,
+            PlyData::Mesh(_) => panic!("expected points"),
+        }
+    }
+
+    const QUAD: &str = "ply
+format ascii 1.0
+comment a unit square
+element vertex 4
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0
+1 0 0
+1 1 0
+0 1 0
+4 0 1 2 3
+";
+
+    /// Two points with `uchar` colours, in the given binary format.
+    fn binary_points(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = format!(
+            "ply\nformat {format} 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\nproperty double radius\nend_header\n"
+        ).into_bytes();
+        for (position, color, radius) in [([1.0f32, 2.0, 3.0], [255u8, 0, 51], 0.5f64), ([-1.0, 0.0, 0.0], [0, 255, 0], 0.25)] {
+            for c in position {
+                bytes.extend(if big_endian { c.to_be_bytes() } else { c.to_le_bytes() });
+            }
+            bytes.extend(color);
+            bytes.extend(if big_endian { radius.to_be_bytes() } else { radius.to_le_bytes() });
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_faces_are_fanned() {
+        let triangles = mesh(QUAD.as_bytes());
+        let corners = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]].map(Vec3::from);
+        assert_eq!(triangles, [corners[0], corners[1], corners[2], corners[0], corners[2], corners[3]]);
+    }
+
+    #[test]
+    fn binary_points_in_either_byte_order() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let splats = points(&binary_points(format, big_endian));
+            assert_eq!(splats.len(), 2);
+            assert_eq!(splats[0].position, [1.0, 2.0, 3.0]);
+            assert_eq!(splats[0].color, [1.0, 0.0, 0.2, 1.0]);
+            assert_eq!(splats[0].radius, 0.5);
+            assert_eq!(splats[1].position, [-1.0, 0.0, 0.0]);
+            assert_eq!(splats[1].radius, 0.25);
+        }
+    }
+
+    #[test]
+    fn points_without_radii_are_sized_by_spacing() {
+        let splats = points(b"ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+            property float z\nproperty float nx\nproperty float ny\nproperty float nz\n\
+            property float red\nend_header\n0 0 0 0 0 1 0.5\n3 0 0 0 0 1 0.5\n0 4 0 0 0 1 0.5\n3 4 0 0 0 1 0.5\n");
+        // The bounds' diagonal is 5, over the square root of four points.
+        assert!(splats.iter().all(|splat| splat.radius == 2.5 * RADIUS_SCALE));
+        assert_eq!(splats[0].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(splats[0].color, [0.5, 0.8, 0.8, 1.0]);
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let truncated = binary_points("binary_little_endian", false);
+        for bytes in [
+            &b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n"[..],
+            b"obj\nend_header\n",
+            b"ply\nformat binary_middle_endian 1.0\nend_header\n",
+            b"ply\nformat ascii 1.0\nproperty float x\nend_header\n",
+            b"ply\nformat ascii 1.0\nelement vertex\nend_header\n",
+            b"ply\nformat ascii 1.0\nelement vertex 1\nproperty quad x\nend_header\n",
+            b"ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nend_header\n1\n",
+            b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nelement face 1\n\
+              property list uchar int vertex_indices\nend_header\n0\n3 0 1 2\n",
+            &truncated[..truncated.len() - 1],
+        ] {
+            assert_eq!(parse(bytes).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        }
+    }
+}
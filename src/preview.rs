@@ -0,0 +1,346 @@
+//! Streams renders of a scene over HTTP, so a headless render node can be watched and steered
+//! from a browser.
+//!
+//! `GET /` serves a page showing the stream, which drag turns the camera on. The endpoints
+//! underneath are:
+//!
+//! - `GET /stream`: a `multipart/x-mixed-replace` stream of frames, shown by any `<img>`.
+//! - `GET /frame`: the latest frame alone.
+//! - `GET /camera`: the camera as JSON. `POST /camera` replaces it with the JSON body.
+//! - `POST /orbit`: turns the camera around the scene by the degrees in the body.
+//!
+//! After every camera change the frame is first streamed at the settings' interactive quality,
+//! then refined up to full resolution.
+
+use std::{
+    io::{self, BufRead, BufReader, Cursor, Read, Write},
+    net::{TcpListener, TcpStream},
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+use image::{
+    DynamicImage,
+    ImageFormat,
+    RgbaImage,
+    codecs::jpeg::JpegEncoder,
+    imageops::{self, FilterType},
+};
+use wgpu::{
+    Extent3d,
+    Texture,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureUsages,
+    TextureViewDescriptor,
+};
+
+use crate::{
+    Camera,
+    Scene,
+    Settings,
+    Vec3,
+    headless,
+    renderer::{self, Renderer},
+};
+
+/// Boundary between the parts of the frame stream.
+const BOUNDARY: &str = "frame";
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<title>ray-tracer preview</title>
+<style>body { margin: 0; background: #111; } img { width: 100vw; height: 100vh; object-fit: contain; }</style>
+<img src="/stream" draggable="false">
+<script>
+let last = null;
+addEventListener("pointerdown", event => last = event.clientX);
+addEventListener("pointerup", () => last = null);
+addEventListener("pointermove", event => {
+    if (last === null) return;
+    fetch("/orbit", { method: "POST", body: String((event.clientX - last) * 0.5) });
+    last = event.clientX;
+});
+</script>
+"#;
+
+/// How streamed frames are encoded.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// Small frames for watching over slow links.
+    #[default]
+    Jpeg,
+    /// Lossless frames, for checking the exact output.
+    Png,
+}
+
+impl FrameFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            FrameFormat::Jpeg => "image/jpeg",
+            FrameFormat::Png => "image/png",
+        }
+    }
+}
+
+impl FromStr for FrameFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jpeg" | "jpg" => Ok(FrameFormat::Jpeg),
+            "png" => Ok(FrameFormat::Png),
+            _ => Err(format!("unknown frame format {s:?}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PreviewOptions {
+    /// Address the server listens on, e.g. `0.0.0.0:8080` to accept other machines.
+    pub address: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: FrameFormat,
+    /// JPEG quality from 1 to 100.
+    pub quality: u8,
+}
+
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:8080".to_owned(),
+            width: 1280,
+            height: 720,
+            format: FrameFormat::default(),
+            quality: 85,
+        }
+    }
+}
+
+/// The latest encoded frame, numbered so streams can wait for the next one.
+#[derive(Default)]
+struct Frame {
+    number: u64,
+    bytes: Arc<Vec<u8>>,
+}
+
+/// State shared between the render loop and the connections.
+struct Shared {
+    frame: Mutex<Frame>,
+    /// Notified when a new frame is encoded.
+    frame_ready: Condvar,
+    camera: Mutex<Camera>,
+    /// Notified when a client changes the camera.
+    steered: Condvar,
+    /// Centre the camera orbits around.
+    center: Vec3,
+    format: FrameFormat,
+}
+
+/// Renders `scene` offscreen and serves the frames on `options.address` until the process exits.
+///
+/// Returns early only if the address cannot be bound.
+pub fn serve(scene: Scene, settings: Settings, camera: Camera, options: &PreviewOptions) -> io::Result<()> {
+    let listener = TcpListener::bind(&options.address)?;
+    log::info!("Serving the preview on http://{}", listener.local_addr()?);
+
+    let (device, queue, max_buffer_size) = renderer::headless_device();
+    let format = TextureFormat::Rgba8UnormSrgb;
+    let mut renderer = Renderer::new(device, queue, format, max_buffer_size, scene, settings);
+    renderer.camera = camera;
+
+    let shared = Arc::new(Shared {
+        frame: Mutex::default(),
+        frame_ready: Condvar::new(),
+        camera: Mutex::new(camera),
+        steered: Condvar::new(),
+        center: renderer.scene_stats.center(),
+        format: options.format,
+    });
+    let accepting = shared.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let shared = accepting.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle(stream, &shared) {
+                    log::debug!("Preview connection closed: {err}");
+                }
+            });
+        }
+    });
+
+    let mut targets: Vec<Texture> = Vec::new();
+    loop {
+        // Refine from the interactive quality up to full resolution, starting over whenever the
+        // camera changes.
+        renderer.camera = *shared.camera.lock().unwrap();
+        let mut scale = renderer.settings.interactive_quality.clamp(0.01, 1.0);
+        loop {
+            let width = ((options.width as f32 * scale) as u32).max(1);
+            let height = ((options.height as f32 * scale) as u32).max(1);
+            if !targets.iter().any(|target| (target.width(), target.height()) == (width, height)) {
+                targets.push(create_target(&renderer, format, width, height));
+            }
+            let target = targets.iter().find(|target| (target.width(), target.height()) == (width, height)).unwrap();
+            let view = target.create_view(&TextureViewDescriptor::default());
+            renderer.render(&view, width, height, None);
+            let mut image = headless::read_back(&renderer, target);
+            if scale < 1.0 {
+                image = imageops::resize(&image, options.width, options.height, FilterType::Nearest);
+            }
+            publish(&shared, encode(&image, options));
+
+            if scale >= 1.0 || *shared.camera.lock().unwrap() != renderer.camera {
+                break;
+            }
+            scale = (scale * 2.0).min(1.0);
+        }
+
+        let rendered = renderer.camera;
+        let camera = shared.camera.lock().unwrap();
+        drop(shared.steered.wait_while(camera, |camera| *camera == rendered).unwrap());
+    }
+}
+
+fn create_target(renderer: &Renderer, format: TextureFormat, width: u32, height: u32) -> Texture {
+    renderer.device.create_texture(&TextureDescriptor {
+        label: Some("Preview target"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+fn encode(image: &RgbaImage, options: &PreviewOptions) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let result = match options.format {
+        // JPEG has no alpha channel.
+        FrameFormat::Jpeg => JpegEncoder::new_with_quality(&mut bytes, options.quality.clamp(1, 100))
+            .encode_image(&DynamicImage::ImageRgba8(image.clone()).to_rgb8()),
+        FrameFormat::Png => image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png),
+    };
+    if let Err(err) = result {
+        log::error!("Failed to encode a preview frame: {err}");
+    }
+    bytes
+}
+
+fn publish(shared: &Shared, bytes: Vec<u8>) {
+    let mut frame = shared.frame.lock().unwrap();
+    frame.number += 1;
+    frame.bytes = Arc::new(bytes);
+    shared.frame_ready.notify_all();
+}
+
+/// Replaces the camera clients steer and wakes the render loop.
+fn steer(shared: &Shared, update: impl FnOnce(&Camera) -> Camera) {
+    let mut camera = shared.camera.lock().unwrap();
+    *camera = update(&camera);
+    shared.steered.notify_all();
+}
+
+/// Serves one request on `stream`, which for `/stream` lasts until the client disconnects.
+fn handle(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body);
+
+    let mut stream = stream;
+    let path = target.split('?').next().unwrap_or_default();
+    match (method, path) {
+        ("GET", "/") => respond(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE.as_bytes()),
+        ("GET", "/stream") => stream_frames(&mut stream, shared),
+        ("GET", "/frame") => {
+            let bytes = latest_frame(shared, 0).1;
+            respond(&mut stream, "200 OK", shared.format.content_type(), &bytes)
+        }
+        ("GET", "/camera") => {
+            let json = serde_json::to_string(&*shared.camera.lock().unwrap()).unwrap();
+            respond(&mut stream, "200 OK", "application/json", json.as_bytes())
+        }
+        ("POST", "/camera") => match serde_json::from_str::<Camera>(&body) {
+            Ok(camera) => {
+                steer(shared, |_| camera);
+                respond(&mut stream, "204 No Content", "text/plain", b"")
+            }
+            Err(err) => respond(&mut stream, "400 Bad Request", "text/plain", err.to_string().as_bytes()),
+        },
+        ("POST", "/orbit") => match body.trim().parse::<f32>() {
+            Ok(degrees) => {
+                steer(shared, |camera| camera.orbit(shared.center, degrees));
+                respond(&mut stream, "204 No Content", "text/plain", b"")
+            }
+            Err(err) => respond(&mut stream, "400 Bad Request", "text/plain", err.to_string().as_bytes()),
+        },
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        body.len(),
+    )?;
+    stream.write_all(body)
+}
+
+/// Waits for a frame newer than `after`, returning its number and bytes.
+fn latest_frame(shared: &Shared, after: u64) -> (u64, Arc<Vec<u8>>) {
+    let frame = shared.frame.lock().unwrap();
+    let frame = shared.frame_ready.wait_while(frame, |frame| frame.number <= after).unwrap();
+    (frame.number, frame.bytes.clone())
+}
+
+/// Writes every new frame as a part of a multipart response until the client goes away.
+fn stream_frames(stream: &mut TcpStream, shared: &Shared) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+    )?;
+    let mut number = 0;
+    loop {
+        let (latest, bytes) = latest_frame(shared, number);
+        number = latest;
+        write!(
+            stream,
+            "--{BOUNDARY}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            shared.format.content_type(),
+            bytes.len(),
+        )?;
+        stream.write_all(&bytes)?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+/// A texture computed in the shader from the world position, so test scenes need no images.
+///
+/// Scenes set one as `extras.texture` on their glTF scene, e.g.
+/// `{"type": "noise", "scale": 2, "octaves": 5, "colors": [[0, 0, 0, 1], [1, 1, 1, 1]]}`.
+/// It replaces the colour of every surface, and is hidden while a material override is active.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ProceduralTexture {
+    /// Alternating cubes, `scale` per scene unit.
+    Checker {
+        #[serde(default = "default_scale")]
+        scale: f32,
+        #[serde(default = "default_colors")]
+        colors: [[f32; 4]; 2],
+    },
+    /// Fractal value noise with `octaves` layers, the coarsest `scale` features per scene unit.
+    Noise {
+        #[serde(default = "default_scale")]
+        scale: f32,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default = "default_colors")]
+        colors: [[f32; 4]; 2],
+    },
+    /// Blends from the first colour at `start` to the second at `end` along `axis`.
+    Gradient {
+        #[serde(default = "default_axis")]
+        axis: [f32; 3],
+        #[serde(default)]
+        start: f32,
+        #[serde(default = "default_scale")]
+        end: f32,
+        #[serde(default = "default_colors")]
+        colors: [[f32; 4]; 2],
+    },
+    /// Cells around scattered points, `scale` per scene unit, the first colour at the points.
+    Voronoi {
+        #[serde(default = "default_scale")]
+        scale: f32,
+        #[serde(default = "default_colors")]
+        colors: [[f32; 4]; 2],
+    },
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_octaves() -> u32 {
+    4
+}
+
+fn default_axis() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_colors() -> [[f32; 4]; 2] {
+    [[0.1, 0.1, 0.1, 1.0], [0.9, 0.9, 0.9, 1.0]]
+}
+
+impl ProceduralTexture {
+    /// WGSL expression evaluating the texture at the `vec3f` expression `p`, built on
+    /// `shaders/procedural.wgsl` with the parameters baked in as constants.
+    pub(crate) fn expression(&self, p: &str) -> String {
+        let (t, colors) = match self {
+            ProceduralTexture::Checker { scale, colors } => {
+                (format!("checker({p} * {})", float(*scale)), colors)
+            }
+            ProceduralTexture::Noise { scale, octaves, colors } => {
+                (format!("fbm({p} * {}, {}u)", float(*scale), (*octaves).max(1)), colors)
+            }
+            ProceduralTexture::Gradient { axis, start, end, colors } => {
+                let range = if end == start { 1.0 } else { end - start };
+                (format!(
+                    "clamp((dot({p}, {}) - {}) / {}, 0.0, 1.0)",
+                    vec(axis),
+                    float(*start),
+                    float(range),
+                ), colors)
+            }
+            ProceduralTexture::Voronoi { scale, colors } => {
+                (format!("voronoi({p} * {})", float(*scale)), colors)
+            }
+        };
+        format!("mix({}, {}, {t})", vec(&colors[0]), vec(&colors[1]))
+    }
+}
+
+/// A WGSL float literal; `Debug` always prints a decimal point.
+pub(crate) fn float(value: f32) -> String {
+    format!("{value:?}")
+}
+
+pub(crate) fn vec<const N: usize>(values: &[f32; N]) -> String {
+    let values: Vec<_> = values.iter().map(|&value| float(value)).collect();
+    format!("vec{N}f({})", values.join(", "))
+}
@@ -0,0 +1,166 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use wgpu::{
+    Buffer,
+    BufferAddress,
+    BufferDescriptor,
+    BufferUsages,
+    CommandEncoder,
+    Device,
+    Features,
+    Maintain,
+    MapMode,
+    QuerySet,
+    QuerySetDescriptor,
+    QueryType,
+    Queue,
+    RenderPassTimestampWrites,
+};
+
+use crate::{memory::MemoryTracker, stats::PassTiming};
+
+/// Most passes that can be timed in a single frame.
+const MAX_PASSES: u32 = 8;
+
+const QUERY_SIZE: BufferAddress = std::mem::size_of::<u64>() as BufferAddress;
+
+/// Measures per-pass GPU time with timestamp queries.
+///
+/// Results are read back asynchronously, so timings lag a frame or more behind. Frames are not
+/// timed while the previous readback is still mapped.
+pub(crate) struct Profiler {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    recording: bool,
+    passes: Vec<&'static str>,
+    in_flight: Vec<&'static str>,
+    mapped: Option<Arc<AtomicBool>>,
+    timings: Vec<PassTiming>,
+}
+
+impl Profiler {
+    /// Returns `None` when the device was created without `Features::TIMESTAMP_QUERY`.
+    pub(crate) fn new(device: &Device, queue: &Queue, memory: &mut MemoryTracker) -> Option<Self> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("Profiler queries"),
+            ty: QueryType::Timestamp,
+            count: MAX_PASSES * 2,
+        });
+        let size = (MAX_PASSES * 2) as BufferAddress * QUERY_SIZE;
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Profiler resolve buffer"),
+            size,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        memory.track(&resolve_buffer);
+        let readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Profiler readback buffer"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        memory.track(&readback_buffer);
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            recording: false,
+            passes: vec![],
+            in_flight: vec![],
+            mapped: None,
+            timings: vec![],
+        })
+    }
+
+    /// Collects the previous readback if it has landed and decides whether this frame is timed.
+    ///
+    /// Returns `true` when new timings were read back.
+    pub(crate) fn begin_frame(&mut self, device: &Device) -> bool {
+        let landed = self.mapped.as_ref().is_some_and(|mapped| {
+            device.poll(Maintain::Poll);
+            mapped.load(Ordering::Acquire)
+        });
+        if landed {
+            self.read_timings();
+            self.mapped = None;
+        }
+        self.recording = self.mapped.is_none();
+        self.passes.clear();
+        landed
+    }
+
+    /// Timestamp writes for a pass named `name`, or `None` if this frame is not being timed.
+    pub(crate) fn timestamp_writes(&mut self, name: &'static str) -> Option<RenderPassTimestampWrites<'_>> {
+        if !self.recording || self.passes.len() as u32 >= MAX_PASSES {
+            return None;
+        }
+        let index = self.passes.len() as u32 * 2;
+        self.passes.push(name);
+        Some(RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    /// Records copying this frame's timestamps into the readback buffer.
+    pub(crate) fn resolve(&self, encoder: &mut CommandEncoder) {
+        if !self.recording || self.passes.is_empty() {
+            return;
+        }
+        let count = self.passes.len() as u32 * 2;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            count as BufferAddress * QUERY_SIZE,
+        );
+    }
+
+    /// Starts mapping the readback buffer once the frame has been submitted.
+    pub(crate) fn end_frame(&mut self) {
+        if !self.recording || self.passes.is_empty() {
+            return;
+        }
+        let mapped = Arc::new(AtomicBool::new(false));
+        let flag = mapped.clone();
+        self.readback_buffer.slice(..).map_async(MapMode::Read, move |result| {
+            if result.is_ok() {
+                flag.store(true, Ordering::Release);
+            }
+        });
+        self.in_flight = std::mem::take(&mut self.passes);
+        self.mapped = Some(mapped);
+    }
+
+    fn read_timings(&mut self) {
+        let data = self.readback_buffer.slice(..).get_mapped_range();
+        let ticks: &[u64] = bytemuck::cast_slice(&data);
+        self.timings = self.in_flight.iter()
+            .enumerate()
+            .map(|(i, &name)| PassTiming {
+                name,
+                milliseconds: ticks[i * 2 + 1].wrapping_sub(ticks[i * 2]) as f64 * self.period as f64 / 1e6,
+            })
+            .collect();
+        drop(data);
+        self.readback_buffer.unmap();
+    }
+
+    pub(crate) fn timings(&self) -> &[PassTiming] {
+        &self.timings
+    }
+}
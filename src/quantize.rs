@@ -0,0 +1,177 @@
+//! 16-bit vertex positions, for scenes whose vertex buffer should take less memory.
+//!
+//! Each mesh's positions are stored as steps across its bounding box and turned back into
+//! world space in the vertex shader. The spare fourth component holds the mesh's index, so
+//! indirect draws, which all start at instance zero, can still find its box.
+
+use wgpu::{
+    BufferAddress,
+    VertexAttribute,
+    VertexBufferLayout,
+    VertexStepMode,
+    vertex_attr_array,
+};
+
+use crate::{
+    Vec3,
+    scene::Scene,
+};
+
+/// Most meshes a quantized vertex can name.
+const MAX_MESHES: usize = u16::MAX as usize + 1;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct QuantizedVertex {
+    position: [u16; 3],
+    mesh: u16,
+}
+
+impl QuantizedVertex {
+    const ATTRIBS: [VertexAttribute; 1] = vertex_attr_array![0 => Uint16x4];
+
+    pub(crate) fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Turns a mesh's quantized positions back into world space: `offset + position * step`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct Dequantization {
+    offset: [f32; 4],
+    step: [f32; 4],
+}
+
+impl Dequantization {
+    /// Steps across the box from `min` to `max`.
+    pub(crate) fn around(min: Vec3, max: Vec3) -> Self {
+        let step = (max - min) * (1.0 / u16::MAX as f32);
+        Self {
+            offset: [min.x, min.y, min.z, 0.0],
+            step: [step.x, step.y, step.z, 0.0],
+        }
+    }
+
+    /// `position` of mesh `mesh` as steps across the box.
+    pub(crate) fn quantize(&self, position: Vec3, mesh: usize) -> QuantizedVertex {
+        let quantize = |value: f32, low: f32, step: f32| {
+            if step > 0.0 { ((value - low) / step).round() as u16 } else { 0 }
+        };
+        QuantizedVertex {
+            position: [
+                quantize(position.x, self.offset[0], self.step[0]),
+                quantize(position.y, self.offset[1], self.step[1]),
+                quantize(position.z, self.offset[2], self.step[2]),
+            ],
+            mesh: mesh as u16,
+        }
+    }
+}
+
+/// Whether `scene` can be quantized, which takes a mesh index that fits in 16 bits.
+pub(crate) fn fits(scene: &Scene) -> bool {
+    scene.meshes.len() <= MAX_MESHES
+}
+
+/// Bytes a vertex takes on the GPU.
+pub(crate) fn vertex_size(quantized: bool) -> usize {
+    if quantized {
+        std::mem::size_of::<QuantizedVertex>()
+    } else {
+        std::mem::size_of::<Vec3>()
+    }
+}
+
+/// Quantizes the vertices of every level of detail of every mesh in `scene` to the box around
+/// the mesh. Positions are off by at most half a step, 1/131070 of the box's size on each axis.
+pub(crate) fn quantize(scene: &Scene) -> (Vec<QuantizedVertex>, Vec<Dequantization>) {
+    let mut vertices = vec![QuantizedVertex::default(); scene.vertices.len()];
+    let mut dequantizations = Vec::with_capacity(scene.meshes.len().max(1));
+    for (index, mesh) in scene.meshes.iter().enumerate() {
+        let ranges = || mesh.lods.iter()
+            .map(|lod| lod.first_vertex as usize..(lod.first_vertex + lod.num_vertices) as usize);
+        let Some(&first) = ranges().flat_map(|range| &scene.vertices[range]).next() else {
+            dequantizations.push(Dequantization::default());
+            continue;
+        };
+        let (min, max) = ranges()
+            .flat_map(|range| &scene.vertices[range])
+            .fold((first, first), |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)));
+        let dequantization = Dequantization::around(min, max);
+        for range in ranges() {
+            for (out, &v) in vertices[range.clone()].iter_mut().zip(&scene.vertices[range]) {
+                *out = dequantization.quantize(v, index);
+            }
+        }
+        dequantizations.push(dequantization);
+    }
+    // Storage buffers cannot be empty.
+    if dequantizations.is_empty() {
+        dequantizations.push(Dequantization::default());
+    }
+    (vertices, dequantizations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::primitives::{icosphere, plane};
+
+    /// What the vertex shader computes from a quantized position.
+    fn dequantize(vertex: &QuantizedVertex, dequantization: &Dequantization) -> Vec3 {
+        let axis = |i: usize| dequantization.offset[i] + vertex.position[i] as f32 * dequantization.step[i];
+        vec3![axis(0), axis(1), axis(2)]
+    }
+
+    #[test]
+    fn round_trip_is_within_half_a_step() {
+        let mut scene = Scene::default();
+        scene.add_triangles(plane(vec3![5.0, -2.0, 0.0], 4.0, 4.0)).unwrap();
+        scene.add_triangles(icosphere(vec3![100.0, 0.0, -50.0], 3.0, 3)).unwrap();
+        let (vertices, dequantizations) = quantize(&scene);
+        assert_eq!(vertices.len(), scene.vertices.len());
+        assert_eq!(dequantizations.len(), 2);
+
+        for (index, mesh) in scene.meshes.iter().enumerate() {
+            let size = mesh.max - mesh.min;
+            let tolerance = size * (0.5 / u16::MAX as f32) + vec3![1e-5, 1e-5, 1e-5] * size.length().max(1.0);
+            for lod in &mesh.lods {
+                let range = lod.first_vertex as usize..(lod.first_vertex + lod.num_vertices) as usize;
+                for (quantized, &original) in vertices[range.clone()].iter().zip(&scene.vertices[range]) {
+                    assert_eq!(quantized.mesh as usize, index);
+                    let error = dequantize(quantized, &dequantizations[index]) - original;
+                    assert!(error.x.abs() <= tolerance.x && error.y.abs() <= tolerance.y && error.z.abs() <= tolerance.z, "{error:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn box_corners_use_the_full_range() {
+        let dequantization = Dequantization::around(vec3![-1.0, 0.0, 2.0], vec3![1.0, 0.0, 4.0]);
+        assert_eq!(dequantization.quantize(vec3![-1.0, 0.0, 2.0], 7).position, [0, 0, 0]);
+        // A flat axis has no steps and comes back exactly.
+        let vertex = dequantization.quantize(vec3![1.0, 0.0, 4.0], 7);
+        assert_eq!((vertex.position, vertex.mesh), ([u16::MAX, 0, u16::MAX], 7));
+        assert_eq!(dequantize(&vertex, &dequantization).y, 0.0);
+    }
+
+    #[test]
+    fn empty_scenes_still_have_a_dequantization() {
+        let (vertices, dequantizations) = quantize(&Scene::default());
+        assert!(vertices.is_empty());
+        assert_eq!(dequantizations.len(), 1);
+        assert!(fits(&Scene::default()));
+    }
+
+    #[test]
+    fn quantized_vertices_are_smaller() {
+        assert_eq!(vertex_size(true), 8);
+        assert_eq!(vertex_size(false), 12);
+    }
+}
@@ -0,0 +1,141 @@
+//! Simulates the scene's rigid bodies with `rapier3d`, the engine the viewer steps unless the
+//! application sets its own with [`RayTracer::set_physics`](crate::RayTracer::set_physics).
+//!
+//! Each [`PhysicsBody`] becomes a body at the origin carrying its world-space collider, so the
+//! body's position is the pose of its mesh.
+
+use std::collections::HashMap;
+
+use rapier3d::prelude::{ColliderBuilder, PhysicsWorld, RigidBodyBuilder, RigidBodyHandle, Vector};
+
+use crate::{BodyKind, Collider, PhysicsBody, PhysicsEngine, Pose, Vec3};
+
+/// A [`PhysicsEngine`] over `rapier3d`, with gravity pulling along -Y.
+#[derive(Default)]
+pub struct RapierEngine {
+    world: PhysicsWorld,
+    /// Body of each mesh simulated.
+    handles: HashMap<usize, RigidBodyHandle>,
+}
+
+impl PhysicsEngine for RapierEngine {
+    fn load(&mut self, bodies: &[PhysicsBody]) {
+        self.world = PhysicsWorld::default();
+        self.handles.clear();
+        for body in bodies {
+            let Some(collider) = collider(&body.collider) else {
+                log::warn!("Mesh {} isn't simulated: it has no volume to collide with", body.mesh);
+                continue;
+            };
+            let collider = collider
+                .density(body.body.density)
+                .friction(body.body.friction)
+                .restitution(body.body.restitution);
+            let rigid_body = match body.body.body {
+                BodyKind::Dynamic => RigidBodyBuilder::dynamic().linvel(vector(body.body.velocity)),
+                BodyKind::Fixed => RigidBodyBuilder::fixed(),
+            };
+            let (handle, _) = self.world.insert(rigid_body, collider);
+            self.handles.insert(body.mesh, handle);
+        }
+    }
+
+    fn step(&mut self, step: f32) {
+        self.world.integration_parameters.dt = step;
+        self.world.step();
+    }
+
+    fn pose(&self, mesh: usize) -> Option<Pose> {
+        let position = self.world.bodies.get(*self.handles.get(&mesh)?)?.position();
+        let translation = position.translation;
+        Some(Pose {
+            rotation: position.rotation.to_array(),
+            translation: vec3![translation.x, translation.y, translation.z],
+        })
+    }
+}
+
+/// Builds the `rapier3d` collider of `collider`, or `None` for a hull or mesh too degenerate to
+/// have one.
+fn collider(collider: &Collider) -> Option<ColliderBuilder> {
+    match collider {
+        Collider::Box { center, half_extents } => {
+            Some(ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z).translation(vector(*center)))
+        }
+        Collider::Hull(points) => ColliderBuilder::convex_hull(&points.iter().map(|&v| vector(v)).collect::<Vec<_>>()),
+        Collider::Mesh { vertices, triangles } => {
+            ColliderBuilder::trimesh(vertices.iter().map(|&v| vector(v)).collect(), triangles.clone()).ok()
+        }
+    }
+}
+
+fn vector(v: Vec3) -> Vector {
+    Vector::new(v.x, v.y, v.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RigidBody, entity::World};
+
+    fn body(mesh: usize, body: BodyKind, collider: Collider) -> PhysicsBody {
+        PhysicsBody {
+            mesh,
+            entity: World::default().spawn(),
+            body: RigidBody {
+                body,
+                ..RigidBody::default()
+            },
+            collider,
+        }
+    }
+
+    fn cube(center: Vec3) -> Collider {
+        let corners = (0..8)
+            .map(|i| center + vec3![(i & 1) as f32 - 0.5, (i >> 1 & 1) as f32 - 0.5, (i >> 2) as f32 - 0.5])
+            .collect();
+        Collider::Hull(corners)
+    }
+
+    #[test]
+    fn dynamic_bodies_fall_onto_fixed_ones() {
+        let floor = Collider::Box {
+            center: vec3![0.0, -0.5, 0.0],
+            half_extents: vec3![10.0, 0.5, 10.0],
+        };
+        let mut engine = RapierEngine::default();
+        engine.load(&[body(0, BodyKind::Fixed, floor), body(1, BodyKind::Dynamic, cube(vec3![0.0, 3.0, 0.0]))]);
+        assert_eq!(engine.pose(1), Some(Pose::IDENTITY));
+
+        for _ in 0..240 {
+            engine.step(1.0 / 60.0);
+        }
+        assert_eq!(engine.pose(0), Some(Pose::IDENTITY));
+        // The cube's centre started 3 m up and rests half its size above the floor.
+        let pose = engine.pose(1).unwrap();
+        assert!((pose.apply(vec3![0.0, 3.0, 0.0]).y - 0.5).abs() < 0.05, "{pose:?}");
+        assert!(pose.translation.x.abs() < 0.01 && pose.translation.z.abs() < 0.01, "{pose:?}");
+    }
+
+    #[test]
+    fn loading_starts_over() {
+        let mut engine = RapierEngine::default();
+        engine.load(&[body(0, BodyKind::Dynamic, cube(Vec3::default()))]);
+        for _ in 0..10 {
+            engine.step(1.0 / 60.0);
+        }
+        assert_ne!(engine.pose(0), Some(Pose::IDENTITY));
+
+        engine.load(&[body(0, BodyKind::Dynamic, cube(Vec3::default()))]);
+        assert_eq!(engine.pose(0), Some(Pose::IDENTITY));
+        engine.load(&[]);
+        assert_eq!(engine.pose(0), None);
+    }
+
+    #[test]
+    fn degenerate_hulls_are_skipped() {
+        let mut engine = RapierEngine::default();
+        engine.load(&[body(0, BodyKind::Dynamic, Collider::Hull(vec![Vec3::default(); 3]))]);
+        assert_eq!(engine.pose(0), None);
+    }
+}
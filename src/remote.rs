@@ -0,0 +1,429 @@
+//! Tweaks the viewer's settings and camera from hardware controllers and apps, over OSC and
+//! MIDI control changes.
+//!
+//! OSC messages set what scripts can assign, their address naming it with slashes for dots:
+//! `/settings/explode 0.5`, `/settings/sky/exposure 0.1` or `/camera/eye 0 1 5`, several
+//! arguments making an array. Addresses naming a script function call it instead, e.g.
+//! `/orbit 15` or `/look_at [0 1 5] [0 0 0]`.
+//!
+//! MIDI controllers are read through the Linux raw MIDI devices, `/dev/snd/midiC*D*`, each
+//! control change going to the setting [`Settings::midi`] maps it to. Devices plugged in while
+//! the viewer runs are picked up; on other platforms none are found.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Read},
+    net::{SocketAddr, UdpSocket},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Camera,
+    Settings,
+    script::{self, Value},
+};
+
+/// Time between looks for newly plugged in MIDI devices.
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Largest OSC packet read, more than a UDP datagram on most networks carries.
+const MAX_PACKET: usize = 65536;
+
+/// Highest value of a MIDI control change.
+const MAX_CONTROL: f32 = 127.0;
+
+/// A MIDI control change mapped to a setting, e.g. a fader on controller 7 setting
+/// `settings.sky.exposure` from 0 to 0.2.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiControl {
+    /// Channel from 1 to 16, or `None` for any.
+    pub channel: Option<u8>,
+    pub controller: u8,
+    /// What a script would assign, such as `settings.explode`, or a function taking one
+    /// number, such as `orbit`.
+    pub path: String,
+    /// Value at the bottom of the control's travel.
+    pub min: f32,
+    /// Value at the top.
+    pub max: f32,
+}
+
+impl MidiControl {
+    /// Value of `self` for a control change to `value`, from 0 to 127.
+    fn value(&self, value: u8) -> f32 {
+        self.min + (self.max - self.min) * value as f32 / MAX_CONTROL
+    }
+}
+
+enum Event {
+    /// A control change on a channel from 1 to 16.
+    Control { channel: u8, controller: u8, value: u8 },
+    /// The device was unplugged.
+    Closed(PathBuf),
+}
+
+/// The OSC socket and MIDI devices being listened to.
+pub(crate) struct Remote {
+    socket: Option<UdpSocket>,
+    midi: Vec<MidiControl>,
+    events: Receiver<Event>,
+    sender: Sender<Event>,
+    open: HashSet<PathBuf>,
+    last_scan: Option<Instant>,
+}
+
+impl Remote {
+    /// Listens for OSC on `osc` and to the MIDI devices if any `midi` controls are mapped.
+    pub(crate) fn new(osc: Option<SocketAddr>, midi: Vec<MidiControl>) -> Self {
+        let socket = osc.and_then(|address| {
+            let socket = UdpSocket::bind(address).and_then(|socket| socket.set_nonblocking(true).map(|()| socket));
+            match socket {
+                Ok(socket) => {
+                    log::info!("Listening for OSC on {address}");
+                    Some(socket)
+                }
+                Err(err) => {
+                    log::error!("Failed to listen for OSC on {address}: {err}");
+                    None
+                }
+            }
+        });
+        let (sender, events) = mpsc::channel();
+        Self {
+            socket,
+            midi,
+            events,
+            sender,
+            open: HashSet::new(),
+            last_scan: None,
+        }
+    }
+
+    /// Opens MIDI devices plugged in since the last look, each read on its own thread.
+    fn scan(&mut self) {
+        for path in devices() {
+            if self.open.contains(&path) {
+                continue;
+            }
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+            log::info!("Reading MIDI device {}", path.display());
+            self.open.insert(path.clone());
+            let sender = self.sender.clone();
+            thread::spawn(move || read(file, path, sender));
+        }
+    }
+
+    /// Applies the messages received since the last call to `settings` and `camera`. Returns
+    /// whether any arrived.
+    pub(crate) fn poll(&mut self, settings: &mut Settings, camera: &mut Camera) -> bool {
+        let mut received = false;
+        let mut packet = vec![0; MAX_PACKET];
+        while let Some(socket) = &self.socket {
+            let size = match socket.recv(&mut packet) {
+                Ok(size) => size,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                // Errors such as an unreachable port from an earlier send leave the socket usable.
+                Err(err) if err.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(err) => {
+                    log::error!("Stopped listening for OSC: {err}");
+                    self.socket = None;
+                    break;
+                }
+            };
+            let mut messages = Vec::new();
+            if let Err(err) = parse_packet(&packet[..size], &mut messages) {
+                log::warn!("Ignoring malformed OSC packet: {err}");
+            }
+            for (address, args) in messages {
+                received = true;
+                let path = address.trim_start_matches('/').replace('/', ".");
+                if let Err(err) = script::control(&path, args, settings, camera) {
+                    log::warn!("OSC {address}: {err}");
+                }
+            }
+        }
+
+        if self.midi.is_empty() {
+            return received;
+        }
+        let now = Instant::now();
+        if self.last_scan.is_none_or(|last_scan| now - last_scan >= SCAN_INTERVAL) {
+            self.last_scan = Some(now);
+            self.scan();
+        }
+        for event in self.events.try_iter() {
+            match event {
+                Event::Control { channel, controller, value } => {
+                    let controls = self.midi.iter().filter(|control| {
+                        control.controller == controller && control.channel.is_none_or(|wanted| wanted == channel)
+                    });
+                    for control in controls {
+                        received = true;
+                        let args = vec![Value::Number(control.value(value) as f64)];
+                        if let Err(err) = script::control(&control.path, args, settings, camera) {
+                            log::warn!("MIDI controller {controller}: {err}");
+                        }
+                    }
+                }
+                Event::Closed(path) => {
+                    log::info!("MIDI device {} was unplugged", path.display());
+                    self.open.remove(&path);
+                }
+            }
+        }
+        received
+    }
+}
+
+/// Raw MIDI devices currently plugged in.
+#[cfg(target_os = "linux")]
+fn devices() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir("/dev/snd") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("midi"))
+        .map(|entry| entry.path())
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn devices() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Sends the control changes of the device at `path` until it is unplugged or nothing listens.
+fn read(mut file: File, path: PathBuf, sender: Sender<Event>) {
+    let mut byte = [0];
+    // Messages may leave out a status byte repeating the last one.
+    let mut status = 0;
+    let mut data = Vec::with_capacity(2);
+    while file.read_exact(&mut byte).is_ok() {
+        match byte[0] {
+            // Real-time messages such as clock ticks may come between any two bytes.
+            0xf8.. => continue,
+            byte @ 0x80.. => {
+                status = byte;
+                data.clear();
+            }
+            byte => data.push(byte),
+        }
+        if status & 0xf0 != 0xb0 || data.len() < 2 {
+            continue;
+        }
+        let event = Event::Control {
+            channel: (status & 0x0f) + 1,
+            controller: data[0],
+            value: data[1],
+        };
+        data.clear();
+        if sender.send(event).is_err() {
+            return;
+        }
+    }
+    let _ = sender.send(Event::Closed(path));
+}
+
+/// Appends the messages of an OSC packet, a message or a bundle of them, to `messages`.
+fn parse_packet(packet: &[u8], messages: &mut Vec<(String, Vec<Value>)>) -> Result<(), String> {
+    let mut reader = OscReader { bytes: packet };
+    if let Some(bundle) = packet.strip_prefix(b"#bundle\0") {
+        // Bundles are applied when they arrive, whatever their time tag.
+        let mut reader = OscReader { bytes: bundle.get(8..).ok_or("bundle without a time tag")? };
+        while !reader.bytes.is_empty() {
+            let size = reader.int()?;
+            let element = reader.take(usize::try_from(size).map_err(|_| "negative bundle element size")?)?;
+            parse_packet(element, messages)?;
+        }
+        return Ok(());
+    }
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return Err(format!("address {address:?} doesn't start with a slash"));
+    }
+    // Messages from old senders may leave out the type tags, and so have no arguments.
+    let tags = if reader.bytes.is_empty() { ",".to_owned() } else { reader.string()? };
+    let tags = tags.strip_prefix(',').ok_or("type tags don't start with a comma")?;
+    // Arrays open with `[` and close with `]`, and may nest.
+    let mut arrays: Vec<Vec<Value>> = vec![Vec::new()];
+    for tag in tags.chars() {
+        let value = match tag {
+            'i' => Value::Number(reader.int()? as f64),
+            'f' => Value::Number(f32::from_bits(reader.int()? as u32) as f64),
+            'h' => Value::Number(reader.long()? as f64),
+            'd' => Value::Number(f64::from_bits(reader.long()? as u64)),
+            's' | 'S' => Value::Text(reader.string()?),
+            'T' => Value::Bool(true),
+            'F' => Value::Bool(false),
+            'N' | 'I' => Value::Nil,
+            'b' => {
+                let size = usize::try_from(reader.int()?).map_err(|_| "negative blob size")?;
+                reader.take(size.next_multiple_of(4))?;
+                continue;
+            }
+            '[' => {
+                arrays.push(Vec::new());
+                continue;
+            }
+            ']' if arrays.len() > 1 => Value::Array(arrays.pop().unwrap()),
+            tag => return Err(format!("unsupported type tag {tag:?}")),
+        };
+        arrays.last_mut().unwrap().push(value);
+    }
+    if arrays.len() > 1 {
+        return Err("array left open".to_owned());
+    }
+    messages.push((address, arrays.pop().unwrap()));
+    Ok(())
+}
+
+/// Reads the big-endian, four-byte aligned fields of an OSC packet.
+struct OscReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> OscReader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], String> {
+        if size > self.bytes.len() {
+            return Err("packet ends early".to_owned());
+        }
+        let (taken, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn int(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn long(&mut self) -> Result<i64, String> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// A null-terminated string padded with nulls to a multiple of four bytes.
+    fn string(&mut self) -> Result<String, String> {
+        let end = self.bytes.iter().position(|&byte| byte == 0).ok_or("string without a terminating null")?;
+        let text = String::from_utf8(self.bytes[..end].to_vec()).map_err(|_| "string isn't UTF-8")?;
+        self.take((end + 1).next_multiple_of(4))?;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `text` null-terminated and padded to four bytes, as OSC strings are.
+    fn string(text: &str) -> Vec<u8> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize((bytes.len() + 1).next_multiple_of(4), 0);
+        bytes
+    }
+
+    fn message(address: &str, tags: &str, args: &[&[u8]]) -> Vec<u8> {
+        let mut packet = string(address);
+        packet.extend(string(tags));
+        for arg in args {
+            packet.extend(*arg);
+        }
+        packet
+    }
+
+    fn parse(packet: &[u8]) -> Result<Vec<(String, Vec<Value>)>, String> {
+        let mut messages = Vec::new();
+        parse_packet(packet, &mut messages).map(|()| messages)
+    }
+
+    #[test]
+    fn strings_are_padded_to_four_bytes() {
+        // Lengths that fill a word exactly still need a null, and so another word.
+        for (address, size) in [("/abc", 8), ("/ab", 4), ("/abcdefg", 12)] {
+            let packet = message(address, ",s", &[&string("x")]);
+            assert_eq!(packet.len(), size + 4 + 4, "{address}");
+            assert_eq!(parse(&packet).unwrap(), [(address.to_owned(), vec![Value::Text("x".into())])]);
+        }
+        let mut unpadded = string("/orbit");
+        unpadded.extend(b",i\0");
+        assert_eq!(parse(&unpadded).unwrap_err(), "packet ends early");
+    }
+
+    #[test]
+    fn type_tags_are_read() {
+        let packet = message("/camera/eye", ",ifhdsTFN", &[
+            &7i32.to_be_bytes(),
+            &0.5f32.to_be_bytes(),
+            &(-3i64).to_be_bytes(),
+            &0.25f64.to_be_bytes(),
+            &string("on"),
+        ]);
+        let args = vec![
+            Value::Number(7.0),
+            Value::Number(0.5),
+            Value::Number(-3.0),
+            Value::Number(0.25),
+            Value::Text("on".into()),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Nil,
+        ];
+        assert_eq!(parse(&packet).unwrap(), [("/camera/eye".to_owned(), args)]);
+    }
+
+    #[test]
+    fn arrays_nest() {
+        let one = 1i32.to_be_bytes();
+        let packet = message("/look_at", ",[i[i]]i", &[&one, &one, &one]);
+        let one = || Value::Number(1.0);
+        let args = vec![Value::Array(vec![one(), Value::Array(vec![one()])]), one()];
+        assert_eq!(parse(&packet).unwrap(), [("/look_at".to_owned(), args)]);
+        assert_eq!(parse(&message("/look_at", ",[i", &[&1i32.to_be_bytes()])).unwrap_err(), "array left open");
+    }
+
+    #[test]
+    fn blobs_are_skipped() {
+        // A 5 byte blob is padded to 8.
+        let packet = message("/settings/explode", ",bf", &[&5i32.to_be_bytes(), b"abcde\0\0\0", &0.5f32.to_be_bytes()]);
+        assert_eq!(parse(&packet).unwrap(), [("/settings/explode".to_owned(), vec![Value::Number(0.5)])]);
+        let truncated = message("/settings/explode", ",b", &[&5i32.to_be_bytes(), b"abcde"]);
+        assert_eq!(parse(&truncated).unwrap_err(), "packet ends early");
+        let negative = message("/settings/explode", ",b", &[&(-1i32).to_be_bytes()]);
+        assert_eq!(parse(&negative).unwrap_err(), "negative blob size");
+    }
+
+    #[test]
+    fn bundles_hold_messages_and_bundles() {
+        let element = |packet: Vec<u8>| [(packet.len() as i32).to_be_bytes().to_vec(), packet].concat();
+        let bundle = |elements: &[Vec<u8>]| [b"#bundle\0".to_vec(), 1u64.to_be_bytes().to_vec(), elements.concat()].concat();
+        let orbit = message("/orbit", ",i", &[&15i32.to_be_bytes()]);
+        let inner = bundle(&[element(message("/settings/explode", ",f", &[&0.5f32.to_be_bytes()]))]);
+        let packet = bundle(&[element(orbit.clone()), element(inner)]);
+        assert_eq!(parse(&packet).unwrap(), [
+            ("/orbit".to_owned(), vec![Value::Number(15.0)]),
+            ("/settings/explode".to_owned(), vec![Value::Number(0.5)]),
+        ]);
+
+        let mut cut = bundle(&[element(orbit)]);
+        cut.pop();
+        assert_eq!(parse(&cut).unwrap_err(), "packet ends early");
+        assert_eq!(parse(b"#bundle\0\0\0\0\0").unwrap_err(), "bundle without a time tag");
+    }
+
+    #[test]
+    fn malformed_messages_are_errors() {
+        // Old senders leave out the type tags.
+        assert_eq!(parse(&string("/reset")).unwrap(), [("/reset".to_owned(), Vec::new())]);
+        assert_eq!(parse(&message("orbit", ",", &[])).unwrap_err(), "address \"orbit\" doesn't start with a slash");
+        assert_eq!(parse(&message("/orbit", "i", &[])).unwrap_err(), "type tags don't start with a comma");
+        assert_eq!(parse(&message("/orbit", ",r", &[&[0; 4]])).unwrap_err(), "unsupported type tag 'r'");
+        assert_eq!(parse(b"/orbit").unwrap_err(), "string without a terminating null");
+    }
+}
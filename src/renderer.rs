@@ -0,0 +1,1474 @@
+use std::{
+    collections::HashSet,
+    iter,
+    ops::Range,
+    time::Instant,
+};
+
+use wgpu::{
+    util::{
+        BufferInitDescriptor,
+        DeviceExt,
+        DrawIndirectArgs,
+    },
+    Adapter,
+    Backends,
+    BindGroup,
+    BindGroupDescriptor,
+    BindGroupEntry,
+    BindGroupLayout,
+    BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry,
+    BindingType,
+    Buffer,
+    BufferBindingType,
+    BufferDescriptor,
+    BufferUsages,
+    Color,
+    CommandEncoder,
+    CommandEncoderDescriptor,
+    Device,
+    DeviceDescriptor,
+    DeviceType,
+    Features,
+    Instance,
+    InstanceDescriptor,
+    Limits,
+    LoadOp,
+    Operations,
+    PipelineLayoutDescriptor,
+    PowerPreference,
+    Queue,
+    RenderPassColorAttachment,
+    RenderPassDescriptor,
+    RequestAdapterOptions,
+    ShaderStages,
+    StoreOp,
+    TextureFormat,
+    TextureView,
+};
+
+use pollster::block_on;
+
+use crate::{
+    Emitter,
+    Material,
+    MaterialOverride,
+    Projection,
+    RenderStats,
+    Scene,
+    SceneStats,
+    Settings,
+    SettingsUniform,
+    Vec3,
+    MAX_CLIP_PLANES,
+    backplate::BackplatePass,
+    camera::{Camera, Mat4},
+    clip,
+    ground::GroundUniform,
+    inspect::{DebugRay, PixelInfo},
+    memory::MemoryTracker,
+    measure::Measurement,
+    meshlet,
+    nan::NanCounter,
+    overlay::{self, OverlayContent, OverlayPass},
+    particles::Particles,
+    pipelines::{PipelineCache, Permutation},
+    plugin::{AnimationFrame, AnimationPass, FrameInfo, PluginContext, RenderPlugin, SceneBuffers},
+    profiler::Profiler,
+    quantize::{self, Dequantization},
+    entity::Renderable,
+    scene::{DEFAULT_LAYER, Lod, Mesh, Visibility},
+    sky::{SkyPass, SkyUniform},
+    text::TextPass,
+    timeline::TimeStep,
+    stereo::{self, Eye},
+    trace::TraceRecorder,
+    upload::Uploader,
+    volume::VolumePass,
+};
+
+/// Requests a device with the optional features the renderer can make use of.
+///
+/// Adapters that can't meet the default limits, such as most phone and tablet GPUs, get the
+/// downlevel ones, raised to the adapter's own texture sizes so full-resolution screens fit.
+pub(crate) fn request_device(adapter: &Adapter) -> (Device, Queue) {
+    let limits = adapter.limits();
+    block_on(adapter.request_device(&DeviceDescriptor {
+        required_features: adapter.features() & (Features::MULTI_DRAW_INDIRECT | Features::TIMESTAMP_QUERY),
+        required_limits: if cfg!(target_arch = "wasm32") {
+            Limits::downlevel_webgl2_defaults()
+        } else if Limits::default().check_limits(&limits) {
+            Limits::default()
+        } else {
+            Limits::downlevel_defaults().using_resolution(limits)
+        },
+        label: None,
+        memory_hints: Default::default(),
+    }, None)).unwrap()
+}
+
+/// Creates an adapter and device without a surface, for offscreen rendering.
+///
+/// Returns the adapter's maximum buffer size alongside, as the default memory budget.
+pub(crate) fn headless_device() -> (Device, Queue, u64) {
+    let instance = headless_instance();
+    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
+        power_preference: PowerPreference::HighPerformance,
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    })).unwrap();
+
+    let (device, queue) = request_device(&adapter);
+    (device, queue, adapter.limits().max_buffer_size)
+}
+
+/// Creates a device on every GPU for offscreen rendering, each with its maximum buffer size.
+///
+/// A GPU reachable through several backends is used through the backend listing the most GPUs,
+/// and software adapters are skipped when there is hardware. Falls back to
+/// [`headless_device`] when no adapter can be listed, e.g. on the web.
+pub(crate) fn headless_devices() -> Vec<(Device, Queue, u64)> {
+    let adapters = headless_instance().enumerate_adapters(Backends::PRIMARY);
+    let hardware = |adapter: &&Adapter| adapter.get_info().device_type != DeviceType::Cpu;
+    let adapters: Vec<_> = if adapters.iter().any(|adapter| hardware(&adapter)) {
+        adapters.iter().filter(hardware).collect()
+    } else {
+        adapters.iter().collect()
+    };
+    let backend = [Backends::VULKAN, Backends::METAL, Backends::DX12]
+        .into_iter()
+        .max_by_key(|&backend| {
+            adapters.iter().filter(|adapter| Backends::from(adapter.get_info().backend) == backend).count()
+        })
+        .unwrap();
+    let devices: Vec<_> = adapters.into_iter()
+        .filter(|adapter| Backends::from(adapter.get_info().backend) == backend)
+        .map(|adapter| {
+            let (device, queue) = request_device(adapter);
+            (device, queue, adapter.limits().max_buffer_size)
+        })
+        .collect();
+    if devices.is_empty() {
+        return vec![headless_device()];
+    }
+    devices
+}
+
+fn headless_instance() -> Instance {
+    Instance::new(&InstanceDescriptor {
+        backends: Backends::PRIMARY,
+        ..Default::default()
+    })
+}
+
+/// Bytes of indirect draws `scene` can need in a frame: one per meshlet of each mesh's largest
+/// level of detail.
+fn indirect_size(scene: &Scene) -> u64 {
+    let draws: usize = scene.meshes.iter()
+        .map(|mesh| mesh.lods.iter().map(|lod| lod.meshlets.len()).max().unwrap_or(0))
+        .sum();
+    (draws.max(1) * std::mem::size_of::<DrawIndirectArgs>()) as u64
+}
+
+/// The scene's vertices, with 16-bit positions when `quantized`, and the per-mesh transforms
+/// back to full precision, which hold one unused entry for full-precision vertices.
+fn vertex_data(scene: &Scene, quantized: bool) -> (Vec<u8>, Vec<Dequantization>) {
+    if quantized {
+        let (vertices, dequantizations) = quantize::quantize(scene);
+        (bytemuck::cast_slice(&vertices).to_vec(), dequantizations)
+    } else {
+        (bytemuck::cast_slice(&scene.vertices).to_vec(), vec![Dequantization::default()])
+    }
+}
+
+/// Creates the vertex and dequantization buffers holding [`vertex_data`].
+fn vertex_buffers(device: &Device, vertices: &[u8], dequantizations: &[Dequantization]) -> (Buffer, Buffer) {
+    let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Vector buffer"),
+        contents: vertices,
+        usage: BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    });
+    let dequantization_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Dequantization buffer"),
+        contents: bytemuck::cast_slice(dequantizations),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    });
+    (vertex_buffer, dequantization_buffer)
+}
+
+/// Whether `contents` can be written over `buffer` in place, which takes the same size. Buffers
+/// are padded to the copy alignment, so contents that fit are aligned too.
+fn fits_exactly(buffer: &Buffer, contents: &[u8]) -> bool {
+    contents.len() as u64 == buffer.size()
+}
+
+/// Creates the buffer of per-mesh offsets of the exploded view, zero until written.
+fn mesh_offset_buffer(device: &Device, meshes: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("Mesh offset buffer"),
+        size: (meshes.max(1) * std::mem::size_of::<[f32; 4]>()) as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn material_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    materials: &Buffer,
+    dequantization: &Buffer,
+    mesh_offsets: &Buffer,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: materials.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: dequantization.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: mesh_offsets.as_entire_binding(),
+            },
+        ],
+        label: Some("material_bind_group"),
+    })
+}
+
+/// Fraction of the way to [`Settings::explode`] the exploded view moves each frame.
+const EXPLODE_EASING: f32 = 0.15;
+
+/// Draws a scene into any texture view, independently of where the frame ends up.
+pub(crate) struct Renderer {
+    pub(crate) device: Device,
+    pub(crate) queue: Queue,
+    pipelines: PipelineCache,
+    splat_buffer: Option<Buffer>,
+    splat_count: u32,
+    debug_rays: Vec<DebugRay>,
+    ray_buffer: Option<Buffer>,
+    ray_vertex_count: u32,
+    sky: SkyPass,
+    backplate: Option<BackplatePass>,
+    volume: Option<VolumePass>,
+    particles: Option<Particles>,
+    overlay: OverlayPass,
+    text: TextPass,
+    /// When the last frame was rendered and a running average of the time between frames in
+    /// seconds, measured while the HUD is shown.
+    last_frame: Option<Instant>,
+    frame_time: f32,
+    /// Meshes whose bounds the overlay outlines.
+    selection: Vec<usize>,
+    measurements: Vec<Measurement>,
+    vertex_buffer: Buffer,
+    dequantization_buffer: Buffer,
+    /// Offset of each mesh in the exploded view.
+    mesh_offset_buffer: Buffer,
+    /// Exploded view factor shown this frame, easing towards [`Settings::explode`].
+    explode: f32,
+    /// Factor the mesh offset buffer was written for, or `None` when it must be rewritten.
+    applied_explode: Option<f32>,
+    /// Whether the vertex buffer holds 16-bit positions.
+    quantized: bool,
+    /// CPU copy of the vertex buffer, for picking.
+    vertices: Vec<Vec3>,
+    material_buffer: Buffer,
+    /// The scene's own materials, restored when an override is switched off.
+    materials: Vec<Material>,
+    /// Built-in overrides followed by the scene's.
+    material_overrides: Vec<MaterialOverride>,
+    /// Override currently written to the material buffer.
+    applied_override: Option<String>,
+    material_bind_group_layout: BindGroupLayout,
+    material_bind_group: BindGroup,
+    settings_buffer: Buffer,
+    settings_bind_group: BindGroup,
+    camera_buffer: Buffer,
+    camera_bind_group_layout: BindGroupLayout,
+    camera_bind_group: BindGroup,
+    pub(crate) camera: Camera,
+    /// Offset of the image in pixels, for jittered supersampling. The spherical projections
+    /// aren't offset.
+    pub(crate) jitter: [f32; 2],
+    indirect_buffer: Buffer,
+    /// Draws written to the indirect buffer this frame.
+    draw_count: u32,
+    multi_draw: bool,
+    uploader: Uploader,
+    memory: MemoryTracker,
+    profiler: Option<Profiler>,
+    nan_counter: NanCounter,
+    trace: Option<TraceRecorder>,
+    pub(crate) scene_stats: SceneStats,
+    meshes: Vec<Mesh>,
+    /// What each mesh is drawn with, from the scene's entities.
+    renderables: Vec<Renderable>,
+    pub(crate) settings: Settings,
+    format: TextureFormat,
+    plugins: Vec<Box<dyn RenderPlugin>>,
+    /// Size of the last frame's target, to tell plugins when it changes.
+    target_size: Option<(u32, u32)>,
+    /// Compute passes moving the scene before each frame, with the frames each has animated.
+    animations: Vec<(Box<dyn AnimationPass>, u64)>,
+    /// Meshes the animation passes move.
+    animated_meshes: HashSet<usize>,
+    /// How the timeline moved since the last frame, see [`Renderer::advance`].
+    time: TimeStep,
+    /// Timeline position the animation passes last ran at.
+    animated_time: Option<f32>,
+}
+
+impl Renderer {
+    pub(crate) fn new(
+        device: Device,
+        queue: Queue,
+        format: TextureFormat,
+        memory_budget: u64,
+        mut scene: Scene,
+        mut settings: Settings,
+    ) -> Self {
+        if settings.atmosphere.is_none() {
+            settings.atmosphere = scene.atmosphere;
+        }
+        let mut memory = MemoryTracker::new(settings.memory_budget.unwrap_or(memory_budget));
+
+        let material_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Material buffer"),
+            contents: bytemuck::cast_slice(&scene.materials),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        memory.track(&material_buffer);
+        let mut material_overrides = MaterialOverride::builtin();
+        material_overrides.append(&mut scene.overrides);
+        if let Some(name) = &settings.material_override
+            && !material_overrides.iter().any(|material_override| &material_override.name == name)
+        {
+            log::warn!("Unknown material override {name:?}");
+        }
+
+        let material_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage {
+                            read_only: true
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage {
+                            read_only: true
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage {
+                            read_only: true
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("material_bind_group_layout"),
+        });
+
+        let settings_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Settings buffer"),
+            contents: bytemuck::cast_slice(&[SettingsUniform::from(&settings)]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        memory.track(&settings_buffer);
+
+        let nan_counter = NanCounter::new(&device, &mut memory);
+
+        let settings_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage {
+                            read_only: false
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("settings_bind_group_layout"),
+        });
+
+        let settings_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &settings_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: settings_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: nan_counter.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("settings_bind_group"),
+        });
+
+        let camera = scene.default_camera();
+
+        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Camera buffer"),
+            contents: bytemuck::cast_slice(&[camera.uniform(1.0)]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        memory.track(&camera_buffer);
+
+        let camera_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("camera_bind_group_layout"),
+        });
+
+        let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[
+                &material_bind_group_layout,
+                &settings_bind_group_layout,
+                &camera_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = PipelineCache::new(
+            render_pipeline_layout,
+            format,
+            scene.custom_shading.take(),
+            scene.material_graph.take(),
+        );
+
+        let sky = SkyPass::new(&device, format, &camera_bind_group_layout, &mut memory);
+        let backplate = settings.backplate.as_deref().and_then(|path| BackplatePass::load(
+            &device,
+            &queue,
+            format,
+            &camera_bind_group_layout,
+            &mut memory,
+            path,
+        ));
+        let volume = scene.volume.as_ref().map(|volume| VolumePass::new(
+            &device,
+            &queue,
+            format,
+            &camera_bind_group_layout,
+            &mut memory,
+            volume,
+            &settings.volume_shading,
+        ));
+        let particles = Particles::new(&device, &mut memory, &scene.emitters);
+        let overlay = OverlayPass::new(&device, format, &camera_bind_group_layout);
+        let text = TextPass::new(&device, &queue, format, &camera_bind_group_layout, &mut memory);
+
+        let profiler = Profiler::new(&device, &queue, &mut memory);
+
+        let trace = settings.trace_path.clone()
+            .map(|path| TraceRecorder::new(path, settings.trace_frames));
+
+        let scene_stats = scene.stats();
+
+        let quantized = settings.quantize_vertices && quantize::fits(&scene);
+        let indirect_size = indirect_size(&scene);
+        scene.fit_budget(memory.remaining().saturating_sub(indirect_size), quantize::vertex_size(quantized));
+
+        let (vertices, dequantizations) = vertex_data(&scene, quantized);
+        let (vertex_buffer, dequantization_buffer) = vertex_buffers(&device, &vertices, &dequantizations);
+        memory.track(&vertex_buffer);
+        memory.track(&dequantization_buffer);
+        let mesh_offset_buffer = mesh_offset_buffer(&device, scene.meshes.len());
+        memory.track(&mesh_offset_buffer);
+        let material_bind_group = material_bind_group(
+            &device,
+            &material_bind_group_layout,
+            &material_buffer,
+            &dequantization_buffer,
+            &mesh_offset_buffer,
+        );
+
+        let splat_buffer = (!scene.splats.is_empty()).then(|| {
+            let buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Splat buffer"),
+                contents: bytemuck::cast_slice(&scene.splats),
+                usage: BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            });
+            memory.track(&buffer);
+            buffer
+        });
+
+        // Indirect draws rewritten every frame with the meshlets in view of each mesh's selected
+        // level of detail.
+        let indirect_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Indirect buffer"),
+            size: indirect_size,
+            usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        memory.track(&indirect_buffer);
+
+        let multi_draw = device.features().contains(Features::MULTI_DRAW_INDIRECT);
+
+        Self {
+            device,
+            queue,
+            pipelines,
+            splat_buffer,
+            splat_count: scene.splats.len() as u32,
+            sky,
+            backplate,
+            volume,
+            particles,
+            overlay,
+            text,
+            last_frame: None,
+            frame_time: 0.0,
+            selection: Vec::new(),
+            measurements: Vec::new(),
+            vertex_buffer,
+            dequantization_buffer,
+            mesh_offset_buffer,
+            // Headless renders show the exploded view at once rather than its first frame.
+            explode: settings.explode,
+            applied_explode: None,
+            quantized,
+            material_bind_group_layout,
+            material_bind_group,
+            settings_buffer,
+            settings_bind_group,
+            camera_buffer,
+            camera_bind_group_layout,
+            camera_bind_group,
+            camera,
+            jitter: [0.0; 2],
+            indirect_buffer,
+            draw_count: 0,
+            multi_draw,
+            uploader: Uploader::new(),
+            memory,
+            profiler,
+            nan_counter,
+            trace,
+            scene_stats,
+            renderables: scene.mesh_renderables(),
+            meshes: scene.meshes,
+            debug_rays: Vec::new(),
+            ray_buffer: None,
+            ray_vertex_count: 0,
+            vertices: scene.vertices,
+            material_buffer,
+            materials: scene.materials,
+            material_overrides,
+            applied_override: None,
+            settings,
+            format,
+            plugins: Vec::new(),
+            target_size: None,
+            animations: Vec::new(),
+            time: TimeStep::default(),
+            animated_time: None,
+            animated_meshes: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> RenderStats {
+        RenderStats {
+            memory: self.memory.usage(),
+            passes: self.profiler.as_ref()
+                .map(|profiler| profiler.timings().to_vec())
+                .unwrap_or_default(),
+            non_finite_fragments: if self.settings.nan_check { self.nan_counter.count() } else { 0 },
+        }
+    }
+
+    /// Swaps in a reloaded version of the scene, keeping the camera and settings.
+    ///
+    /// Only GPU resources whose contents changed are rebuilt: the vertex buffer when any mesh
+    /// changed, the material buffer when a material did, and the pipelines when the shading did.
+    pub(crate) fn reload(&mut self, mut scene: Scene) {
+        self.scene_stats = scene.stats();
+        self.renderables = scene.mesh_renderables();
+
+        let quantized = self.settings.quantize_vertices && quantize::fits(&scene);
+        let indirect_size = indirect_size(&scene);
+        let replaceable = self.vertex_buffer.size() + self.dequantization_buffer.size() + self.indirect_buffer.size();
+        scene.fit_budget(
+            (self.memory.remaining() + replaceable).saturating_sub(indirect_size),
+            quantize::vertex_size(quantized),
+        );
+
+        // Buffers the new scene's data fits exactly are written over rather than replaced, so
+        // switching between versions of an asset doesn't reallocate them.
+        let mut rebind_materials = false;
+        if scene.vertices != self.vertices || quantized != self.quantized {
+            let (vertices, dequantizations) = vertex_data(&scene, quantized);
+            let dequantizations: &[u8] = bytemuck::cast_slice(&dequantizations);
+            if fits_exactly(&self.vertex_buffer, &vertices) && fits_exactly(&self.dequantization_buffer, dequantizations) {
+                self.queue.write_buffer(&self.vertex_buffer, 0, &vertices);
+                self.queue.write_buffer(&self.dequantization_buffer, 0, dequantizations);
+            } else {
+                self.memory.release(&self.vertex_buffer);
+                self.memory.release(&self.dequantization_buffer);
+                (self.vertex_buffer, self.dequantization_buffer) =
+                    vertex_buffers(&self.device, &vertices, bytemuck::cast_slice(dequantizations));
+                self.memory.track(&self.vertex_buffer);
+                self.memory.track(&self.dequantization_buffer);
+                rebind_materials = true;
+            }
+            self.vertices = scene.vertices;
+            self.quantized = quantized;
+        }
+        if indirect_size != self.indirect_buffer.size() {
+            self.memory.release(&self.indirect_buffer);
+            self.indirect_buffer = self.device.create_buffer(&BufferDescriptor {
+                label: Some("Indirect buffer"),
+                size: indirect_size,
+                usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.memory.track(&self.indirect_buffer);
+        }
+        self.meshes = scene.meshes;
+        self.selection.retain(|&index| index < self.meshes.len());
+        let offsets_size = (self.meshes.len().max(1) * std::mem::size_of::<[f32; 4]>()) as u64;
+        if offsets_size != self.mesh_offset_buffer.size() {
+            self.memory.release(&self.mesh_offset_buffer);
+            self.mesh_offset_buffer = mesh_offset_buffer(&self.device, self.meshes.len());
+            self.memory.track(&self.mesh_offset_buffer);
+            rebind_materials = true;
+        }
+        self.applied_explode = None;
+
+        let splats: &[u8] = bytemuck::cast_slice(&scene.splats);
+        match &self.splat_buffer {
+            Some(buffer) if fits_exactly(buffer, splats) => self.queue.write_buffer(buffer, 0, splats),
+            _ if splats.is_empty() && self.splat_buffer.is_none() => {}
+            _ => {
+                if let Some(buffer) = &self.splat_buffer {
+                    self.memory.release(buffer);
+                }
+                self.splat_buffer = (!splats.is_empty()).then(|| {
+                    let buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+                        label: Some("Splat buffer"),
+                        contents: splats,
+                        usage: BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                    });
+                    self.memory.track(&buffer);
+                    buffer
+                });
+            }
+        }
+        self.splat_count = scene.splats.len() as u32;
+
+        if bytemuck::cast_slice::<Material, u8>(&scene.materials) != bytemuck::cast_slice::<Material, u8>(&self.materials) {
+            self.materials = scene.materials;
+            let materials = self.shown_materials();
+            if materials.len() * std::mem::size_of::<Material>() == self.material_buffer.size() as usize {
+                self.queue.write_buffer(&self.material_buffer, 0, bytemuck::cast_slice(&materials));
+            } else {
+                self.memory.release(&self.material_buffer);
+                self.material_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Material buffer"),
+                    contents: bytemuck::cast_slice(&materials),
+                    usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                });
+                self.memory.track(&self.material_buffer);
+                rebind_materials = true;
+            }
+        }
+        if rebind_materials {
+            self.material_bind_group = material_bind_group(
+                &self.device,
+                &self.material_bind_group_layout,
+                &self.material_buffer,
+                &self.dequantization_buffer,
+                &self.mesh_offset_buffer,
+            );
+        }
+        self.material_overrides = MaterialOverride::builtin();
+        self.material_overrides.append(&mut scene.overrides);
+
+        // The volume pass keeps its pipelines and sampler for a new grid.
+        match (&mut self.volume, &scene.volume) {
+            (Some(pass), Some(volume)) => pass.set_volume(&self.device, &self.queue, &mut self.memory, volume),
+            (pass, None) => {
+                if let Some(pass) = pass.take() {
+                    self.memory.release_bytes(pass.allocated);
+                }
+            }
+            (pass @ None, Some(volume)) => *pass = Some(VolumePass::new(
+                &self.device,
+                &self.queue,
+                self.format,
+                &self.camera_bind_group_layout,
+                &mut self.memory,
+                volume,
+                &self.settings.volume_shading,
+            )),
+        }
+
+        // Particles start over, warmed up, whether or not the emitters changed.
+        self.set_emitters(&scene.emitters);
+
+        self.pipelines.set_shading(scene.custom_shading, scene.material_graph);
+    }
+
+    /// The scene's materials, or the active override in place of each.
+    fn shown_materials(&self) -> Vec<Material> {
+        match self.material_override() {
+            Some(material_override) => vec![material_override.material(); self.materials.len()],
+            None => self.materials.clone(),
+        }
+    }
+
+    pub(crate) fn set_visibility(&mut self, index: usize, visibility: Visibility) {
+        if let Some(renderable) = self.renderables.get_mut(index) {
+            renderable.visibility = visibility;
+        }
+    }
+
+    /// Recolours material `index`, shown once no material override is active.
+    pub(crate) fn set_material_color(&mut self, index: usize, color: [f32; 4]) {
+        let Some(material) = self.materials.get_mut(index) else {
+            return;
+        };
+        material.ambient = color;
+        let material = *material;
+        if self.material_override().is_none() {
+            let offset = (index * std::mem::size_of::<Material>()) as u64;
+            self.queue.write_buffer(&self.material_buffer, offset, bytemuck::bytes_of(&material));
+        }
+    }
+
+    /// Moves vertices of mesh `index`, see [`Scene::update_vertices`], writing just them into
+    /// the vertex buffer. Quantized meshes are requantized to their new bounds whole.
+    pub(crate) fn update_vertices(&mut self, index: usize, range: Range<usize>, positions: &[Vec3]) {
+        let mesh = &mut self.meshes[index];
+        let written = mesh.update_vertices(&mut self.vertices, range, positions);
+        if self.quantized {
+            let lod = &mesh.lods[0];
+            let first = lod.first_vertex as usize;
+            let dequantization = Dequantization::around(mesh.min, mesh.max);
+            let vertices: Vec<_> = self.vertices[first..first + lod.num_vertices as usize].iter()
+                .map(|&position| dequantization.quantize(position, index))
+                .collect();
+            let vertex_size = quantize::vertex_size(true);
+            self.queue.write_buffer(&self.vertex_buffer, (first * vertex_size) as u64, bytemuck::cast_slice(&vertices));
+            let offset = index * std::mem::size_of::<Dequantization>();
+            self.queue.write_buffer(&self.dequantization_buffer, offset as u64, bytemuck::bytes_of(&dequantization));
+        } else {
+            let offset = written.start * quantize::vertex_size(false);
+            self.queue.write_buffer(&self.vertex_buffer, offset as u64, bytemuck::cast_slice(&self.vertices[written]));
+        }
+        // The exploded view moves meshes by their centres.
+        self.applied_explode = None;
+    }
+
+    fn plugin_context(&self) -> PluginContext<'_> {
+        PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            format: self.format,
+        }
+    }
+
+    pub(crate) fn add_plugin(&mut self, mut plugin: Box<dyn RenderPlugin>) {
+        plugin.setup(&self.plugin_context());
+        if let Some((width, height)) = self.target_size {
+            plugin.resize(&self.plugin_context(), width, height);
+        }
+        self.plugins.push(plugin);
+    }
+
+    pub(crate) fn add_animation(&mut self, mut pass: Box<dyn AnimationPass>) {
+        pass.setup(&self.plugin_context());
+        self.animated_meshes.extend(pass.meshes());
+        self.animations.push((pass, 0));
+    }
+
+    /// Moves the animation passes and particles on with the timeline for the next frame.
+    pub(crate) fn advance(&mut self, time: TimeStep) {
+        self.time = TimeStep {
+            time: time.time,
+            step: self.time.step + time.step,
+            speed: time.speed,
+            restarted: self.time.restarted || time.restarted,
+        };
+    }
+
+    /// Replaces the particles with freshly warmed up ones from `emitters`.
+    fn set_emitters(&mut self, emitters: &[Emitter]) {
+        if let Some(particles) = self.particles.take() {
+            self.memory.release_bytes(particles.allocated);
+        }
+        self.particles = Particles::new(&self.device, &mut self.memory, emitters);
+    }
+
+    /// Level of detail of mesh `index` to draw and the view to cull its meshlets to, if any.
+    /// Animated meshes are drawn whole at their finest level, their bounds being out of date.
+    fn drawn_lod<'a>(&self, index: usize, mesh: &'a Mesh, view_proj: &'a Mat4) -> (&'a Lod, Option<&'a Mat4>) {
+        if self.animated_meshes.contains(&index) {
+            return (&mesh.lods[0], None);
+        }
+        (mesh.select_lod(view_proj), self.culls().then_some(view_proj))
+    }
+
+    /// Camera ray through the centre of pixel (`x`, `y`) of a `width` by `height` frame.
+    fn pixel_ray(&self, x: u32, y: u32, width: u32, height: u32) -> (Vec3, Vec3) {
+        let ndc = [
+            2.0 * (x as f32 + 0.5) / width as f32 - 1.0,
+            1.0 - 2.0 * (y as f32 + 0.5) / height as f32,
+        ];
+        self.camera.ray(ndc, width as f32 / height as f32)
+    }
+
+    /// Nearest mesh the ray hits and the distance to it, testing the finest level of detail of
+    /// every drawn mesh. Hits cut away by clipping planes are passed through.
+    fn cast(&self, origin: Vec3, direction: Vec3) -> Option<(usize, f32)> {
+        self.meshes.iter()
+            .enumerate()
+            .filter(|&(index, _)| self.is_drawn(index))
+            .filter_map(|(index, mesh)| {
+                // Meshes moved by the exploded view are hit by the ray moved back.
+                mesh.hits(&self.vertices, origin - self.mesh_offset(index), direction)
+                    .filter(|&depth| !clip::clipped(&self.settings.clip_planes, origin + direction * depth))
+                    .min_by(f32::total_cmp)
+                    .map(|depth| (index, depth))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    pub(crate) fn inspect(&self, x: u32, y: u32, width: u32, height: u32) -> PixelInfo {
+        let (origin, direction) = self.pixel_ray(x, y, width, height);
+        let hit = self.cast(origin, direction);
+        let color = match self.material_override() {
+            Some(material_override) => material_override.color,
+            None => self.materials.first().map_or([0.0; 4], |material| material.ambient),
+        };
+        PixelInfo {
+            x,
+            y,
+            mesh: hit.map(|(index, _)| index),
+            depth: hit.map(|(_, depth)| depth),
+            position: hit.map(|(_, depth)| origin + direction * depth),
+            color: hit.map(|_| color),
+            samples: 1,
+        }
+    }
+
+    /// Captures the camera ray through pixel (`x`, `y`) and draws it from then on.
+    pub(crate) fn capture_ray(&mut self, x: u32, y: u32, width: u32, height: u32) -> DebugRay {
+        let (origin, direction) = self.pixel_ray(x, y, width, height);
+        let hit = self.cast(origin, direction);
+        let ray = DebugRay {
+            origin,
+            direction,
+            end: origin + direction * hit.map_or(self.camera.zfar, |(_, depth)| depth),
+            mesh: hit.map(|(index, _)| index),
+        };
+        self.debug_rays.push(ray);
+        self.update_ray_buffer();
+        ray
+    }
+
+    pub(crate) fn clear_rays(&mut self) {
+        self.debug_rays.clear();
+        self.update_ray_buffer();
+    }
+
+    fn update_ray_buffer(&mut self) {
+        let lines: Vec<Vec3> = self.debug_rays.iter().flat_map(DebugRay::lines).collect();
+        self.ray_buffer = (!lines.is_empty()).then(|| self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Debug ray buffer"),
+            contents: bytemuck::cast_slice(&lines),
+            usage: BufferUsages::VERTEX,
+        }));
+        self.ray_vertex_count = lines.len() as u32;
+    }
+
+    /// How far the exploded view moves mesh `index`: away from the centre of the scene, by the
+    /// current factor times its centre's distance from it.
+    fn mesh_offset(&self, index: usize) -> Vec3 {
+        let mesh = &self.meshes[index];
+        ((mesh.min + mesh.max) * 0.5 - self.scene_stats.center()) * self.explode
+    }
+
+    /// Position of the nearest surface under pixel (`x`, `y`).
+    pub(crate) fn pick(&self, x: u32, y: u32, width: u32, height: u32) -> Option<Vec3> {
+        let (origin, direction) = self.pixel_ray(x, y, width, height);
+        self.cast(origin, direction).map(|(_, depth)| origin + direction * depth)
+    }
+
+    pub(crate) fn measurements(&self) -> &[Measurement] {
+        &self.measurements
+    }
+
+    /// Draws `measurement` with its length from then on.
+    pub(crate) fn add_measurement(&mut self, measurement: Measurement) {
+        self.measurements.push(measurement);
+    }
+
+    pub(crate) fn clear_measurements(&mut self) {
+        self.measurements.clear();
+    }
+
+    pub(crate) fn selection(&self) -> &[usize] {
+        &self.selection
+    }
+
+    /// Outlines the meshes at `selection` when [`Overlays::bounds`](crate::Overlays::bounds) is
+    /// on. Indices past the last mesh are ignored.
+    pub(crate) fn select(&mut self, mut selection: Vec<usize>) {
+        selection.retain(|&index| index < self.meshes.len());
+        self.selection = selection;
+    }
+
+    fn permutation(&self) -> Permutation {
+        let material_override = self.material_override();
+        let checker = material_override.is_some_and(|material_override| material_override.checker > 0.0);
+        let material_graph = material_override.is_none() && self.pipelines.has_material_graph();
+        Permutation {
+            quantized: self.quantized,
+            ..Permutation::new(&self.settings, checker, material_graph)
+        }
+    }
+
+    fn material_override(&self) -> Option<&MaterialOverride> {
+        let name = self.settings.material_override.as_ref()?;
+        self.material_overrides.iter().find(|material_override| &material_override.name == name)
+    }
+
+    /// Switches to the next material override, then back to the scene's own materials.
+    pub(crate) fn cycle_material_override(&mut self) {
+        let next = match self.material_override() {
+            Some(current) => self.material_overrides.iter()
+                .skip_while(|material_override| material_override.name != current.name)
+                .nth(1),
+            None => self.material_overrides.first(),
+        };
+        self.settings.material_override = next.map(|material_override| material_override.name.clone());
+        log::info!("Material override: {}", self.settings.material_override.as_deref().unwrap_or("none"));
+    }
+
+    /// Whether an object tagged with `layers` belongs to a selected layer. Untagged objects are in
+    /// [`DEFAULT_LAYER`].
+    fn in_selected_layers(&self, layers: &[String]) -> bool {
+        let Some(selected) = &self.settings.layers else {
+            return true;
+        };
+        if layers.is_empty() {
+            selected.iter().any(|layer| layer == DEFAULT_LAYER)
+        } else {
+            layers.iter().any(|layer| selected.contains(layer))
+        }
+    }
+
+    fn is_drawn(&self, index: usize) -> bool {
+        let renderable = &self.renderables[index];
+        renderable.visibility.camera && self.in_selected_layers(&renderable.layers)
+    }
+
+    /// Records this frame's uniform and indirect buffer uploads into `encoder`.
+    fn upload(&mut self, encoder: &mut CommandEncoder, view_proj: &Mat4) {
+        let stats = &self.scene_stats;
+        let settings = SettingsUniform {
+            checker: self.material_override().map_or(0.0, |material_override| material_override.checker),
+            ground: self.settings.ground
+                .map(|ground| GroundUniform::new(&ground, &self.settings, stats.min, stats.max))
+                .unwrap_or_default(),
+            ..SettingsUniform::from(&self.settings)
+        };
+        self.uploader.write(
+            &self.device,
+            encoder,
+            &self.settings_buffer,
+            0,
+            bytemuck::cast_slice(&[settings]),
+        );
+
+        self.explode += (self.settings.explode - self.explode) * EXPLODE_EASING;
+        if (self.settings.explode - self.explode).abs() < 1e-3 {
+            self.explode = self.settings.explode;
+        }
+        if self.applied_explode != Some(self.explode) {
+            let offsets: Vec<[f32; 4]> = (0..self.meshes.len())
+                .map(|index| {
+                    let offset = self.mesh_offset(index);
+                    [offset.x, offset.y, offset.z, 0.0]
+                })
+                .collect();
+            self.uploader.write(&self.device, encoder, &self.mesh_offset_buffer, 0, bytemuck::cast_slice(&offsets));
+            self.applied_explode = Some(self.explode);
+        }
+
+        if self.settings.material_override != self.applied_override {
+            let materials = self.shown_materials();
+            self.uploader.write(&self.device, encoder, &self.material_buffer, 0, bytemuck::cast_slice(&materials));
+            self.applied_override = self.settings.material_override.clone();
+        }
+
+        if let Some(sky) = &self.settings.sky {
+            self.uploader.write(
+                &self.device,
+                encoder,
+                &self.sky.buffer,
+                0,
+                bytemuck::cast_slice(&[SkyUniform::from(sky)]),
+            );
+        }
+
+        if let Some(volume) = &self.volume {
+            self.uploader.write(
+                &self.device,
+                encoder,
+                &volume.buffer,
+                0,
+                bytemuck::cast_slice(&[volume.uniform(&self.settings.volume_shading, &self.settings.clip_planes)]),
+            );
+        }
+
+        let overlay = OverlayContent {
+            overlays: self.settings.overlays,
+            bounds: (stats.min, stats.max),
+            selected: self.selection.iter()
+                .map(|&index| {
+                    let offset = self.mesh_offset(index);
+                    (self.meshes[index].min + offset, self.meshes[index].max + offset)
+                })
+                .collect(),
+            measurements: self.measurements.clone(),
+            clip_planes: self.settings.clip_planes.iter().take(MAX_CLIP_PLANES).copied().collect(),
+        };
+        self.overlay.update(&self.device, &mut self.memory, overlay);
+
+        self.queue_text();
+        self.text.upload(&self.device, encoder, &mut self.uploader, &mut self.memory);
+
+        if self.multi_draw && !self.meshes.is_empty() {
+            let draws: Vec<u8> = self.meshes.iter()
+                .enumerate()
+                .filter(|&(index, _)| self.is_drawn(index))
+                .flat_map(|(index, mesh)| {
+                    let (lod, cull) = self.drawn_lod(index, mesh, view_proj);
+                    meshlet::visible_runs(lod, cull)
+                })
+                .flat_map(|run| {
+                    DrawIndirectArgs {
+                        vertex_count: run.len() as u32,
+                        instance_count: 1,
+                        first_vertex: run.start,
+                        first_instance: 0,
+                    }.as_bytes().to_vec()
+                })
+                .collect();
+            self.draw_count = (draws.len() / std::mem::size_of::<DrawIndirectArgs>()) as u32;
+            if !draws.is_empty() {
+                self.uploader.write(&self.device, encoder, &self.indirect_buffer, 0, &draws);
+            }
+        }
+    }
+
+    /// Queues this frame's HUD and labels.
+    fn queue_text(&mut self) {
+        let scale = self.settings.ui_scale;
+        self.text.set_scale(&self.device, &self.queue, &mut self.memory, scale);
+        self.text.clear();
+        let overlays = self.settings.overlays;
+        if overlays.hud {
+            let hud = self.hud();
+            let margin = (8.0 * scale).round();
+            self.text.screen(&hud, margin, margin, [1.0; 4]);
+        }
+        if overlays.labels {
+            for &index in &self.selection {
+                let mesh = &self.meshes[index];
+                let name = mesh.name.clone().unwrap_or_else(|| format!("Mesh {index}"));
+                let top = vec3![(mesh.min.x + mesh.max.x) * 0.5, mesh.max.y, (mesh.min.z + mesh.max.z) * 0.5]
+                    + self.mesh_offset(index);
+                self.text.world(&name, top, [1.0, 0.8, 0.1, 1.0]);
+            }
+        }
+        for measurement in &self.measurements {
+            let middle = (measurement.start + measurement.end) * 0.5;
+            self.text.world(&measurement.label(), middle, overlay::MEASUREMENT_COLOR);
+        }
+        for annotation in &self.settings.annotations {
+            self.text.world(&annotation.text, annotation.position, annotation.color);
+        }
+    }
+
+    fn hud(&self) -> String {
+        let stats = &self.scene_stats;
+        let memory = self.memory.usage();
+        let megabytes = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        let mut hud = format!(
+            "{:.1} ms ({:.0} fps)\n{} meshes, {} triangles, {} points\nGPU memory {:.0} / {:.0} MB",
+            self.frame_time * 1000.0,
+            if self.frame_time > 0.0 { 1.0 / self.frame_time } else { 0.0 },
+            stats.meshes,
+            stats.triangles,
+            stats.points,
+            megabytes(memory.allocated),
+            megabytes(memory.budget),
+        );
+        if !self.animations.is_empty() || self.particles.is_some() {
+            hud += &match self.time.speed {
+                0.0 => format!("\nTime {:.2} s, paused", self.time.time),
+                speed => format!("\nTime {:.2} s at {speed}x", self.time.time),
+            };
+        }
+        for pass in self.profiler.iter().flat_map(|profiler| profiler.timings()) {
+            hud += &format!("\n{} {:.2} ms", pass.name, pass.milliseconds);
+        }
+        hud
+    }
+
+    /// Whether meshlets outside the view are skipped. The spherical projections have no
+    /// frustum, stereo eyes look past the centre camera's, and the exploded view moves meshes
+    /// away from their bounds.
+    fn culls(&self) -> bool {
+        matches!(self.camera.projection, Projection::Perspective | Projection::Orthographic)
+            && self.settings.stereo.is_none()
+            && self.explode == 0.0
+    }
+
+    /// Records one eye's pass. Only the first pass of a frame clears the target.
+    fn draw(&mut self, encoder: &mut CommandEncoder, view: &TextureView, eye: &Eye, clear: bool, view_proj: &Mat4) {
+        let (x, y, width, height) = eye.viewport;
+        let mut camera = eye.camera.uniform(width as f32 / height as f32);
+        camera.offset(2.0 * self.jitter[0] / width as f32, -2.0 * self.jitter[1] / height as f32);
+        self.uploader.write(&self.device, encoder, &self.camera_buffer, 0, bytemuck::cast_slice(&[camera]));
+        self.text.set_viewport(&self.device, encoder, &mut self.uploader, width, height);
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(eye.label),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: match (clear, self.settings.id_matte) {
+                        (true, false) => LoadOp::Clear(self.background()),
+                        (true, true) => LoadOp::Clear(Color::BLACK),
+                        (false, _) => LoadOp::Load,
+                    },
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: self.profiler.as_mut()
+                .and_then(|profiler| profiler.timestamp_writes(eye.label)),
+        });
+        render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+        let (crop_x, crop_y, crop_width, crop_height) = match self.settings.crop {
+            Some(crop) => crop.to_pixels(width, height),
+            None => (0, 0, width, height),
+        };
+        render_pass.set_scissor_rect(x + crop_x, y + crop_y, crop_width, crop_height);
+        let id_matte = self.settings.id_matte;
+        let pipelines = self.pipelines.get(&self.permutation());
+        if !id_matte && !self.settings.transparent_background {
+            if let Some(backplate) = &self.backplate {
+                backplate.draw(&mut render_pass, &self.camera_bind_group, eye.channels);
+            } else if self.settings.sky.is_some() {
+                self.sky.draw(&mut render_pass, &self.camera_bind_group, eye.channels);
+            }
+        }
+        render_pass.set_bind_group(0, &self.material_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.settings_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        // Nothing is depth tested, so the ground and its shadows go under the meshes by coming
+        // first. Shadows are cast from the coarsest level of detail, whether or not in view.
+        if let Some(ground) = &self.settings.ground
+            && !id_matte
+        {
+            if !ground.shadow_catcher {
+                render_pass.set_pipeline(&pipelines.ground[eye.channels as usize]);
+                render_pass.draw(0..6, 0..1);
+            }
+            render_pass.set_pipeline(&pipelines.shadows[eye.channels as usize]);
+            for (index, mesh) in self.meshes.iter().enumerate() {
+                let lod = match self.animated_meshes.contains(&index) {
+                    true => mesh.lods.first(),
+                    false => mesh.lods.last(),
+                };
+                if let Some(lod) = lod
+                    && self.renderables[index].visibility.shadow
+                    && self.in_selected_layers(&self.renderables[index].layers)
+                {
+                    let index = index as u32;
+                    render_pass.draw(lod.first_vertex..lod.first_vertex + lod.num_vertices, index..index + 1);
+                }
+            }
+            if let Some(particles) = &self.particles
+                && self.in_selected_layers(&[])
+            {
+                render_pass.set_pipeline(&pipelines.particle_shadows[eye.channels as usize]);
+                for (buffer, count) in particles.instances() {
+                    render_pass.set_vertex_buffer(0, buffer.slice(..));
+                    render_pass.draw(0..6, 0..count);
+                }
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            }
+        }
+        // Back faces in the cap colour go under the meshes too, showing where their front faces
+        // are cut away.
+        if self.settings.section_caps.is_some() && clip::any_active(&self.settings.clip_planes) && !id_matte {
+            render_pass.set_pipeline(&pipelines.sections[eye.channels as usize]);
+            for (index, mesh) in self.meshes.iter().enumerate().filter(|&(index, _)| self.is_drawn(index)) {
+                let (lod, cull) = self.drawn_lod(index, mesh, view_proj);
+                let index = index as u32;
+                for run in meshlet::visible_runs(lod, cull) {
+                    render_pass.draw(run, index..index + 1);
+                }
+            }
+        }
+        render_pass.set_pipeline(&pipelines.meshes[eye.channels as usize]);
+        // Indirect draws all start at instance zero, so ID mattes and exploded views draw each
+        // mesh with its index as the instance.
+        if self.multi_draw && !id_matte && self.explode == 0.0 {
+            if self.draw_count > 0 {
+                render_pass.multi_draw_indirect(&self.indirect_buffer, 0, self.draw_count);
+            }
+        } else {
+            for (index, mesh) in self.meshes.iter().enumerate().filter(|&(index, _)| self.is_drawn(index)) {
+                let (lod, cull) = self.drawn_lod(index, mesh, view_proj);
+                let index = index as u32;
+                for run in meshlet::visible_runs(lod, cull) {
+                    render_pass.draw(run, index..index + 1);
+                }
+            }
+        }
+        // Point clouds, particles and volumes are untagged, so they are only drawn with the default
+        // layer.
+        if id_matte {
+            return;
+        }
+        if self.in_selected_layers(&[]) {
+            if let Some(splat_buffer) = &self.splat_buffer {
+                render_pass.set_pipeline(&pipelines.splats[eye.channels as usize]);
+                render_pass.set_vertex_buffer(0, splat_buffer.slice(..));
+                render_pass.draw(0..6, 0..self.splat_count);
+            }
+            if let Some(particles) = &self.particles {
+                render_pass.set_pipeline(&pipelines.particles[eye.channels as usize]);
+                for (buffer, count) in particles.instances() {
+                    render_pass.set_vertex_buffer(0, buffer.slice(..));
+                    render_pass.draw(0..6, 0..count);
+                }
+            }
+            if let Some(volume) = &self.volume {
+                volume.draw(&mut render_pass, &self.camera_bind_group, eye.channels);
+            }
+        }
+        // Debug rays go on top of everything, including the volume.
+        if let Some(ray_buffer) = &self.ray_buffer {
+            render_pass.set_pipeline(&pipelines.rays[eye.channels as usize]);
+            render_pass.set_bind_group(0, &self.material_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.settings_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, ray_buffer.slice(..));
+            render_pass.draw(0..self.ray_vertex_count, 0..1);
+        }
+        self.overlay.draw(&mut render_pass, &self.camera_bind_group, eye.channels);
+        self.text.draw(&mut render_pass, &self.camera_bind_group, eye.channels);
+    }
+
+    fn background(&self) -> Color {
+        if self.settings.transparent_background {
+            return Color::TRANSPARENT;
+        }
+        match self.material_override().and_then(|material_override| material_override.background) {
+            Some([r, g, b, a]) => Color { r: r as f64, g: g as f64, b: b as f64, a: a as f64 },
+            None => self.settings.bg_color,
+        }
+    }
+
+    /// Returns the current time while a trace is being captured.
+    pub(crate) fn clock(&self) -> Option<Instant> {
+        self.trace.as_ref().map(|_| Instant::now())
+    }
+
+    /// Runs the animation passes if the timeline moved, including by seeking while paused.
+    fn encode_animations(&mut self, encoder: &mut CommandEncoder, time: TimeStep) {
+        if self.animations.is_empty() || (time.step == 0.0 && !time.restarted && self.animated_time == Some(time.time)) {
+            return;
+        }
+        self.animated_time = Some(time.time);
+        let mut animations = std::mem::take(&mut self.animations);
+        let meshes: Vec<Range<u32>> = self.meshes.iter()
+            .map(|mesh| mesh.lods.first().map_or(0..0, |lod| lod.first_vertex..lod.first_vertex + lod.num_vertices))
+            .collect();
+        let buffers = SceneBuffers {
+            vertices: &self.vertex_buffer,
+            quantized: self.quantized,
+            meshes: &meshes,
+            splats: self.splat_buffer.as_ref(),
+        };
+        let context = self.plugin_context();
+        for (pass, frames) in &mut animations {
+            let frame = AnimationFrame {
+                frame: *frames,
+                time: time.time,
+                step: time.step,
+            };
+            pass.encode(&context, encoder, &buffers, &frame);
+            *frames += 1;
+        }
+        self.animations = animations;
+    }
+
+    fn encode_plugins(&mut self, encoder: &mut CommandEncoder, view: &TextureView, width: u32, height: u32, view_proj: Mat4) {
+        let mut plugins = std::mem::take(&mut self.plugins);
+        let context = self.plugin_context();
+        if self.target_size != Some((width, height)) {
+            for plugin in &mut plugins {
+                plugin.resize(&context, width, height);
+            }
+        }
+        let frame = FrameInfo {
+            target: view,
+            width,
+            height,
+            camera: self.camera,
+            view_proj,
+        };
+        for plugin in &mut plugins {
+            plugin.encode(&context, encoder, &frame);
+        }
+        self.plugins = plugins;
+        self.target_size = Some((width, height));
+    }
+
+    /// Encodes and submits a frame into `view`, a `width` by `height` target.
+    ///
+    /// `frame_start` is when the caller began the frame, so time spent acquiring the target is traced.
+    pub(crate) fn render(&mut self, view: &TextureView, width: u32, height: u32, frame_start: Option<Instant>) {
+        let encode_start = self.clock();
+        if self.settings.overlays.hud {
+            let now = Instant::now();
+            if let Some(last_frame) = self.last_frame {
+                let elapsed = (now - last_frame).as_secs_f32();
+                self.frame_time = if self.frame_time > 0.0 { self.frame_time * 0.9 + elapsed * 0.1 } else { elapsed };
+            }
+            self.last_frame = Some(now);
+        } else {
+            self.last_frame = None;
+        }
+        let mut encoder = self.device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        let view_proj = self.camera.view_proj(width as f32 / height as f32);
+        self.upload(&mut encoder, &view_proj);
+        // Compute passes come first in the encoder, which makes their writes visible to the draws.
+        let time = self.time;
+        self.time.step = 0.0;
+        self.time.restarted = false;
+        self.encode_animations(&mut encoder, time);
+        if time.restarted
+            && let Some(emitters) = self.particles.as_ref().map(Particles::emitters)
+        {
+            self.set_emitters(&emitters);
+        }
+        if let Some(particles) = &mut self.particles {
+            particles.simulate(&self.queue, &mut encoder, time.step);
+        }
+        self.pipelines.prepare(&self.device, self.permutation());
+        if let Some(profiler) = &mut self.profiler {
+            let landed = profiler.begin_frame(&self.device);
+            if let (true, Some(trace), Some(start)) = (landed, &mut self.trace, frame_start) {
+                trace.gpu(profiler.timings(), start);
+            }
+        }
+        if self.settings.nan_check {
+            self.nan_counter.begin_frame(&self.device, &mut encoder);
+        }
+        let eyes = stereo::eyes(&self.camera, self.settings.stereo, width, height);
+        for (i, eye) in eyes.iter().enumerate() {
+            self.draw(&mut encoder, view, eye, i == 0, &view_proj);
+        }
+        self.encode_plugins(&mut encoder, view, width, height, view_proj);
+        if let Some(profiler) = &self.profiler {
+            profiler.resolve(&mut encoder);
+        }
+        if self.settings.nan_check {
+            self.nan_counter.resolve(&mut encoder);
+        }
+        self.uploader.finish();
+        let submit_start = self.clock();
+        self.queue.submit(iter::once(encoder.finish()));
+        self.uploader.recall();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_frame();
+        }
+        if self.settings.nan_check {
+            self.nan_counter.end_frame();
+        }
+
+        if let (Some(trace), Some(frame_start), Some(encode_start), Some(submit_start)) =
+            (&mut self.trace, frame_start, encode_start, submit_start)
+        {
+            trace.cpu("Acquire", frame_start, encode_start);
+            trace.cpu("Encode", encode_start, submit_start);
+            trace.cpu("Submit", submit_start, Instant::now());
+            if trace.end_frame() {
+                self.trace = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,1021 @@
+use std::{
+    collections::HashSet,
+    io::{self, Read},
+    iter,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use wgpu::{
+    BufferAddress,
+    VertexAttribute,
+    VertexBufferLayout,
+    VertexStepMode,
+    vertex_attr_array,
+};
+
+use crate::{
+    Atmosphere,
+    AudioReactive,
+    Camera,
+    Emitter,
+    GraphError,
+    Light,
+    Material,
+    MaterialGraph,
+    MaterialOverride,
+    ProceduralTexture,
+    SceneStats,
+    Vec3,
+    assets::AssetSource,
+    camera::{self, Mat4},
+    convert::{self, ImportOptions},
+    entity::{Entity, Renderable, World},
+    export,
+    geometry::{
+        cleanup,
+        curves::CurveOptions,
+        simplify,
+        terrain::TerrainOptions,
+    },
+    importers::Importers,
+    inspect,
+    loader::{self, LoadError},
+    meshlet::{self, Meshlet},
+    optimize,
+    outline::{self, MaterialInfo, MeshInfo, ObjectId, RayHit},
+    stl::StlOptions,
+    volume::Volume,
+};
+
+/// Layer of objects that are not tagged with any.
+pub(crate) const DEFAULT_LAYER: &str = "default";
+
+/// Colour of meshes added to a scene that has no materials.
+pub(crate) const DEFAULT_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+/// Screen coverage ratio between two consecutive levels of detail.
+const LOD_COVERAGE_STEP: f32 = 0.25;
+
+/// A contiguous run of vertices in the scene vertex buffer.
+#[derive(Clone, Debug)]
+pub(crate) struct Lod {
+    pub(crate) first_vertex: u32,
+    pub(crate) num_vertices: u32,
+    /// The run split into clusters of nearby triangles, in order.
+    pub(crate) meshlets: Vec<Meshlet>,
+}
+
+/// Which kinds of rays see an object.
+///
+/// The raster path only has camera rays; the other flags are kept with the scene for renderers
+/// that trace shadows and reflections.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadow: bool,
+    pub reflection: bool,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self {
+            camera: true,
+            shadow: true,
+            reflection: true,
+        }
+    }
+}
+
+impl Visibility {
+    /// Seen by no ray.
+    pub const HIDDEN: Self = Self {
+        camera: false,
+        shadow: false,
+        reflection: false,
+    };
+
+    /// Visible only to rays that both `self` and `other` are visible to.
+    pub fn intersect(self, other: Self) -> Self {
+        Self {
+            camera: self.camera && other.camera,
+            shadow: self.shadow && other.shadow,
+            reflection: self.reflection && other.reflection,
+        }
+    }
+}
+
+/// A mesh and its level-of-detail chain, finest level first, drawn for the entity whose
+/// [`Renderable`] refers to it.
+#[derive(Clone, Debug)]
+pub(crate) struct Mesh {
+    pub(crate) lods: Vec<Lod>,
+    pub(crate) min: Vec3,
+    pub(crate) max: Vec3,
+    /// Name in the scene file, by which [`Scene::merge`] replaces it.
+    pub(crate) name: Option<String>,
+    /// Tangents of the finest level's vertices in the order they are drawn, see
+    /// [`Scene::mesh_tangents`].
+    pub(crate) tangents: Vec<[f32; 4]>,
+}
+
+/// A point drawn as a disk: facing the camera, or in the plane of its normal when it has one.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct Splat {
+    pub(crate) position: [f32; 3],
+    pub(crate) radius: f32,
+    /// Zero for points without a normal.
+    pub(crate) normal: [f32; 3],
+    pub(crate) color: [f32; 4],
+}
+
+impl Splat {
+    const ATTRIBS: [VertexAttribute; 4] = vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32,
+        2 => Float32x3,
+        3 => Float32x4,
+    ];
+
+    /// Splats are per-instance data; each instance draws a six-vertex quad.
+    pub(crate) fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Scene {
+    pub(crate) vertices: Vec<Vec3>,
+    pub(crate) materials: Vec<Material>,
+    /// Names of the first materials in the scene file; later materials are unnamed.
+    pub(crate) material_names: Vec<Option<String>>,
+    pub(crate) meshes: Vec<Mesh>,
+    pub(crate) textures: usize,
+    pub(crate) primitives_without_normals: usize,
+    pub(crate) primitives_without_uvs: usize,
+    /// Normal-mapped primitives whose tangents were generated by [`tangents::generate`].
+    ///
+    /// [`tangents::generate`]: crate::geometry::tangents::generate
+    pub(crate) primitives_without_tangents: usize,
+    /// Point cloud drawn as splats, alongside the meshes.
+    pub(crate) splats: Vec<Splat>,
+    /// Voxel grid ray marched over the rest of the scene.
+    pub(crate) volume: Option<Volume>,
+    /// Particle sources simulated alongside the scene.
+    pub(crate) emitters: Vec<Emitter>,
+    /// Parameters following the system's audio, see [`Settings::audio`].
+    ///
+    /// [`Settings::audio`]: crate::Settings::audio
+    pub(crate) audio: Option<AudioReactive>,
+    /// Atmosphere requested by the scene file.
+    pub(crate) atmosphere: Option<Atmosphere>,
+    /// Material overrides defined by the scene file, alongside the built-in ones.
+    pub(crate) overrides: Vec<MaterialOverride>,
+    /// WGSL compiled into the surface shader, see [`Scene::set_custom_shading`].
+    pub(crate) custom_shading: Option<String>,
+    /// Compiled [`MaterialGraph`] colouring every surface.
+    pub(crate) material_graph: Option<String>,
+    /// Objects of the scene file, drawing the meshes and holding its cameras and lights.
+    pub(crate) world: World,
+    /// Files the scene was read from, watched when [`Settings::hot_reload`] is on.
+    ///
+    /// [`Settings::hot_reload`]: crate::Settings::hot_reload
+    pub(crate) sources: Vec<PathBuf>,
+}
+
+impl Mesh {
+    /// Fraction of the viewport covered by the projected mesh bounds.
+    fn coverage(&self, view_proj: &Mat4) -> f32 {
+        let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+        let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+        for corner in 0..8 {
+            let p = vec3![
+                if corner & 1 == 0 { self.min.x } else { self.max.x },
+                if corner & 2 == 0 { self.min.y } else { self.max.y },
+                if corner & 4 == 0 { self.min.z } else { self.max.z }
+            ];
+            let [x, y, _, w] = camera::transform(view_proj, p);
+            // Bounds reaching behind the camera are treated as filling the view.
+            if w <= 0.0 {
+                return 1.0;
+            }
+            min_x = min_x.min(x / w);
+            min_y = min_y.min(y / w);
+            max_x = max_x.max(x / w);
+            max_y = max_y.max(y / w);
+        }
+        let width = (max_x.min(1.0) - min_x.max(-1.0)).max(0.0);
+        let height = (max_y.min(1.0) - min_y.max(-1.0)).max(0.0);
+        width * height / 4.0
+    }
+
+    /// Picks the coarsest level whose coverage threshold the mesh still falls under.
+    pub(crate) fn select_lod(&self, view_proj: &Mat4) -> &Lod {
+        let coverage = self.coverage(view_proj);
+        let mut level = 0;
+        let mut threshold = LOD_COVERAGE_STEP;
+        while level + 1 < self.lods.len() && coverage < threshold {
+            level += 1;
+            threshold *= LOD_COVERAGE_STEP;
+        }
+        &self.lods[level]
+    }
+
+    /// Moves the finest level's vertices in `range`, counted from its first vertex, to
+    /// `positions` in the scene's `vertices`, and refits the bounds of the meshlets they fall in
+    /// and of the mesh. Coarser levels, which would no longer match, are dropped. Returns the
+    /// range of `vertices` written.
+    ///
+    /// Panics if `range` reaches past the finest level or `positions` is a different length.
+    pub(crate) fn update_vertices(&mut self, vertices: &mut [Vec3], range: Range<usize>, positions: &[Vec3]) -> Range<usize> {
+        self.lods.truncate(1);
+        let lod = &mut self.lods[0];
+        assert!(
+            range.end <= lod.num_vertices as usize && range.len() == positions.len(),
+            "{} positions for vertices {range:?} of a mesh with {}",
+            positions.len(),
+            lod.num_vertices,
+        );
+        let first = lod.first_vertex as usize;
+        let written = first + range.start..first + range.end;
+        vertices[written.clone()].copy_from_slice(positions);
+
+        let level = &vertices[first..first + lod.num_vertices as usize];
+        for meshlet in &mut lod.meshlets {
+            let run = meshlet.first_vertex as usize..(meshlet.first_vertex + meshlet.num_vertices) as usize;
+            if run.start < range.end && range.start < run.end {
+                let start = level[run.start];
+                (meshlet.min, meshlet.max) = level[run].iter()
+                    .fold((start, start), |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)));
+            }
+        }
+        if let Some(first) = lod.meshlets.first() {
+            (self.min, self.max) = lod.meshlets.iter()
+                .fold((first.min, first.max), |(min, max), meshlet| (Vec3::min(min, meshlet.min), Vec3::max(max, meshlet.max)));
+        }
+        written
+    }
+
+    /// Distances along the ray to the triangles of the finest level it hits. Mesh bounds, then
+    /// meshlet bounds, narrow down the triangles tested.
+    pub(crate) fn hits<'a>(
+        &'a self,
+        vertices: &'a [Vec3],
+        origin: Vec3,
+        direction: Vec3,
+    ) -> impl Iterator<Item = f32> + 'a {
+        let lod = self.lods.first().filter(|_| inspect::hits_bounds(origin, direction, self.min, self.max));
+        lod.into_iter()
+            .flat_map(|lod| lod.meshlets.iter().map(move |meshlet| (lod, meshlet)))
+            .filter(move |(_, meshlet)| inspect::hits_bounds(origin, direction, meshlet.min, meshlet.max))
+            .flat_map(move |(lod, meshlet)| {
+                let first = (lod.first_vertex + meshlet.first_vertex) as usize;
+                vertices[first..first + meshlet.num_vertices as usize].chunks_exact(3)
+            })
+            .filter_map(move |triangle| {
+                inspect::intersect_triangle(origin, direction, [triangle[0], triangle[1], triangle[2]])
+            })
+    }
+}
+
+impl Scene {
+    /// Compiles `code` into the surface shader to adjust every surface's colour.
+    ///
+    /// `code` must define `fn custom_shade(color: vec4f, world_position: vec3f) -> vec4f`, which
+    /// gets the material colour before fog and returns the colour to use, e.g. a procedural
+    /// texture. It can read the shader's `settings`, `camera` and `materials` bindings. Code
+    /// that fails to compile is logged and ignored.
+    pub fn set_custom_shading(&mut self, code: impl Into<String>) {
+        self.custom_shading = Some(code.into());
+    }
+
+    /// Colours every surface with `texture` instead of its material.
+    pub fn set_procedural_texture(&mut self, texture: ProceduralTexture) {
+        // A lone texture node has no inputs to get wrong.
+        self.material_graph = MaterialGraph::from(texture).compile().ok();
+    }
+
+    /// Colours every surface with `graph`, replacing any earlier graph or procedural texture.
+    pub fn set_material_graph(&mut self, graph: &MaterialGraph) -> Result<(), GraphError> {
+        self.material_graph = Some(graph.compile()?);
+        Ok(())
+    }
+
+    /// Tags mesh `index` with render layers, replacing its previous ones.
+    pub fn set_layers(&mut self, index: usize, layers: Vec<String>) {
+        if let Some(renderable) = self.renderable_mut(index) {
+            renderable.layers = layers;
+        }
+    }
+
+    /// Names mesh `index`, so that a layer merged over the scene can replace it.
+    pub fn set_name(&mut self, index: usize, name: impl Into<String>) {
+        if let Some(mesh) = self.meshes.get_mut(index) {
+            mesh.name = Some(name.into());
+        }
+    }
+
+    /// Loads a glTF scene, a USD layer or USDZ package, a PLY mesh or point cloud, an STL mesh,
+    /// a Mitsuba `.vol` voxel grid, a heightmap image as terrain, or a `.curves` file as hair.
+    /// STL, terrain and hair use default options.
+    ///
+    /// Other formats can be added through [`Importers`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        Importers::default().import(path.as_ref())
+    }
+
+    /// Loads the scene at `path` in `source`, such as a [`ZipArchive`] packaging it with its
+    /// buffers and textures. The formats are those of [`Scene::load`].
+    ///
+    /// [`ZipArchive`]: crate::assets::ZipArchive
+    pub fn load_from<P: AsRef<Path>>(source: &dyn AssetSource, path: P) -> Result<Self, LoadError> {
+        Importers::default().import_from(source, path.as_ref())
+    }
+
+    /// Loads a scene from the contents of a file with `extension`, e.g. one fetched over the
+    /// network or embedded with `include_bytes!`. The formats are those of [`Scene::load`].
+    pub fn from_bytes(bytes: &[u8], extension: &str) -> Result<Self, LoadError> {
+        Importers::default().import_bytes(bytes, extension)
+    }
+
+    /// Reads a scene file's contents from `reader`, see [`Scene::from_bytes`].
+    pub fn from_reader<R: Read>(mut reader: R, extension: &str) -> Result<Self, LoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes, extension)
+    }
+
+    /// Loads a GLB file, or a glTF file whose buffers and images are embedded as data URIs.
+    pub fn from_gltf_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        Ok(loader::load_gltf_bytes(bytes)?)
+    }
+
+    /// Loads an encoded heightmap image as chunked terrain, see [`Scene::load_heightmap`].
+    pub fn from_heightmap_bytes(bytes: &[u8], options: &TerrainOptions) -> Result<Self, LoadError> {
+        Ok(loader::load_heightmap_bytes(bytes, options)?)
+    }
+
+    /// Loads an STL mesh from memory, see [`Scene::load_stl`].
+    pub fn from_stl_bytes(bytes: &[u8], options: &StlOptions) -> Result<Self, LoadError> {
+        Ok(loader::load_stl_bytes(bytes, options)?)
+    }
+
+    /// Loads a text curve file from memory, see [`Scene::load_curves`].
+    pub fn from_curves_bytes(bytes: &[u8], options: &CurveOptions) -> Result<Self, LoadError> {
+        Ok(loader::load_curves_bytes(bytes, options)?)
+    }
+
+    /// Loads `base` and merges each of `layers` over it in order, see [`Scene::merge`].
+    pub fn load_layered<P: AsRef<Path>, L: AsRef<Path>>(base: P, layers: &[L]) -> Result<Self, LoadError> {
+        let importers = Importers::default();
+        let mut scene = importers.import(base.as_ref())?;
+        for layer in layers {
+            scene.merge(importers.import(layer.as_ref())?);
+        }
+        Ok(scene)
+    }
+
+    /// Composes `layer` over the scene, the way a USD layer overrides the ones below it.
+    ///
+    /// Meshes, materials, lights and material overrides with the same name as one in the scene
+    /// replace it in place; unnamed or new ones are added. The layer's entities join the
+    /// scene's, except that one drawing a replaced mesh or holding a replaced light takes over
+    /// the entity that had it. The layer's cameras come first, so a shot layer decides the
+    /// starting view, and its atmosphere, volume and shading replace the scene's when it has
+    /// them.
+    pub fn merge(&mut self, layer: Scene) {
+        let Scene {
+            vertices: layer_vertices,
+            materials,
+            material_names,
+            meshes: layer_meshes,
+            textures,
+            primitives_without_normals,
+            primitives_without_uvs,
+            primitives_without_tangents,
+            splats,
+            volume,
+            emitters,
+            audio,
+            atmosphere,
+            overrides,
+            custom_shading,
+            material_graph,
+            world: mut layer_world,
+            sources,
+        } = layer;
+
+        // Vertices are rebuilt in mesh order, taking each mesh's runs from the scene it came from.
+        let base_vertices = std::mem::take(&mut self.vertices);
+        let base_mesh_entities = self.mesh_entities();
+        let mut layer_meshes: Vec<Option<Mesh>> = layer_meshes.into_iter().map(Some).collect();
+        // Where each of the layer's meshes and materials ends up in the scene.
+        let mut mesh_indices = vec![0; layer_meshes.len()];
+        let mut meshes = Vec::with_capacity(self.meshes.len() + layer_meshes.len());
+        for mesh in std::mem::take(&mut self.meshes) {
+            let replacement = mesh.name.as_ref().and_then(|name| {
+                layer_meshes.iter().position(|other| other.as_ref().is_some_and(|other| other.name.as_ref() == Some(name)))
+            });
+            match replacement {
+                Some(replacement) => {
+                    mesh_indices[replacement] = meshes.len();
+                    meshes.push((layer_meshes[replacement].take().unwrap(), &layer_vertices));
+                }
+                None => meshes.push((mesh, &base_vertices)),
+            }
+        }
+        for (index, mesh) in layer_meshes.into_iter().enumerate() {
+            if let Some(mesh) = mesh {
+                mesh_indices[index] = meshes.len();
+                meshes.push((mesh, &layer_vertices));
+            }
+        }
+        for (mut mesh, vertices) in meshes {
+            for lod in &mut mesh.lods {
+                let first = lod.first_vertex as usize;
+                lod.first_vertex = self.vertices.len() as u32;
+                self.vertices.extend_from_slice(&vertices[first..first + lod.num_vertices as usize]);
+            }
+            self.meshes.push(mesh);
+        }
+
+        self.material_names.resize(self.materials.len(), None);
+        let mut material_indices = Vec::with_capacity(materials.len());
+        for (index, material) in materials.into_iter().enumerate() {
+            let name = material_names.get(index).cloned().flatten();
+            let existing = name.as_ref()
+                .and_then(|name| self.material_names.iter().position(|other| other.as_ref() == Some(name)));
+            match existing {
+                Some(existing) => {
+                    self.materials[existing] = material;
+                    material_indices.push(existing);
+                }
+                None => {
+                    material_indices.push(self.materials.len());
+                    self.materials.push(material);
+                    self.material_names.push(name);
+                }
+            }
+        }
+
+        // The layer's entities join the scene's. One drawing a mesh or holding a light that
+        // replaces one of the scene's takes over the entity that had it.
+        for renderable in layer_world.renderables.values_mut() {
+            renderable.mesh = mesh_indices[renderable.mesh];
+        }
+        for used in layer_world.materials.values_mut() {
+            for material in used {
+                *material = material_indices[*material];
+            }
+        }
+        let mut counterparts = vec![None; layer_world.entities().map(|entity| entity.index() + 1).max().unwrap_or(0)];
+        for (entity, renderable) in layer_world.renderables.iter() {
+            counterparts[entity.index()] = base_mesh_entities.get(renderable.mesh).copied().flatten();
+        }
+        for (entity, light) in layer_world.lights.iter() {
+            let existing = light.name.as_ref().and_then(|name| {
+                self.world.lights.iter().find(|(_, other)| other.name.as_ref() == Some(name)).map(|(existing, _)| existing)
+            });
+            counterparts[entity.index()] = counterparts[entity.index()].or(existing);
+        }
+        let moved: HashSet<Entity> = self.world.append(layer_world, &counterparts).into_iter().flatten().collect();
+        // The layer's cameras come first.
+        let cameras: Vec<(Entity, Camera)> = self.world.cameras.iter().map(|(entity, &camera)| (entity, camera)).collect();
+        let (layer_cameras, base_cameras): (Vec<_>, Vec<_>) = cameras.into_iter().partition(|(entity, _)| moved.contains(entity));
+        self.world.cameras = Default::default();
+        for (entity, camera) in layer_cameras.into_iter().chain(base_cameras) {
+            self.world.cameras.insert(entity, camera);
+        }
+
+        for material_override in overrides {
+            match self.overrides.iter().position(|other| other.name == material_override.name) {
+                Some(existing) => self.overrides[existing] = material_override,
+                None => self.overrides.push(material_override),
+            }
+        }
+
+        self.sources.extend(sources);
+        self.splats.extend(splats);
+        self.emitters.extend(emitters);
+        self.textures += textures;
+        self.primitives_without_normals += primitives_without_normals;
+        self.primitives_without_uvs += primitives_without_uvs;
+        self.primitives_without_tangents += primitives_without_tangents;
+        self.volume = volume.or(self.volume.take());
+        self.audio = audio.or(self.audio.take());
+        self.atmosphere = atmosphere.or(self.atmosphere);
+        self.custom_shading = custom_shading.or(self.custom_shading.take());
+        self.material_graph = material_graph.or(self.material_graph.take());
+    }
+
+    /// Loads a grayscale heightmap image as chunked terrain.
+    pub fn load_heightmap<P: AsRef<Path>>(path: P, options: &TerrainOptions) -> Result<Self, LoadError> {
+        Ok(loader::load_heightmap(path, options)?)
+    }
+
+    /// Loads an STL mesh, scaled from `options.units` to metres.
+    pub fn load_stl<P: AsRef<Path>>(path: P, options: &StlOptions) -> Result<Self, LoadError> {
+        Ok(loader::load_stl(path, options)?)
+    }
+
+    /// Loads strands from a text curve file as tubes; see [`geometry::curves`](crate::geometry::curves).
+    pub fn load_curves<P: AsRef<Path>>(path: P, options: &CurveOptions) -> Result<Self, LoadError> {
+        Ok(loader::load_curves(path, options)?)
+    }
+
+    /// Cameras defined by the scene file, in file order. The viewer starts from the first.
+    pub fn cameras(&self) -> &[Camera] {
+        self.world.cameras.values()
+    }
+
+    /// The first camera of the scene file, or when it has none the default camera moved to
+    /// frame everything in the scene, see [`Camera::frame`].
+    pub fn default_camera(&self) -> Camera {
+        if let Some(&camera) = self.cameras().first() {
+            return camera;
+        }
+        match self.bounds() {
+            Some((min, max)) => Camera::default().frame(min, max),
+            None => Camera::default(),
+        }
+    }
+
+    /// The box around the meshes, splats and volume.
+    pub(crate) fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let meshes = self.meshes.iter().map(|mesh| (mesh.min, mesh.max));
+        let splats = self.splats.iter().map(|splat| (Vec3::from(splat.position), Vec3::from(splat.position)));
+        let volume = self.volume.iter().map(|volume| (volume.min, volume.max));
+        meshes.chain(splats).chain(volume)
+            .reduce(|(min, max), (lo, hi)| (Vec3::min(min, lo), Vec3::max(max, hi)))
+    }
+
+    /// Lights defined by the scene file, in world space.
+    pub fn lights(&self) -> &[Light] {
+        self.world.lights.values()
+    }
+
+    /// The meshes, in the order the renderer indexes them.
+    pub fn meshes(&self) -> impl Iterator<Item = MeshInfo<'_>> {
+        self.meshes.iter().zip(self.mesh_entities()).enumerate().map(|(index, (mesh, entity))| {
+            let renderable = entity.and_then(|entity| self.world.renderables.get(entity));
+            MeshInfo {
+                index,
+                name: mesh.name.as_deref(),
+                entity,
+                min: mesh.min,
+                max: mesh.max,
+                triangles: mesh.lods.first().map_or(0, |lod| lod.num_vertices as usize / 3),
+                materials: entity.and_then(|entity| self.world.materials.get(entity)).map_or(&[], Vec::as_slice),
+                visibility: renderable.map_or(Visibility::HIDDEN, |renderable| renderable.visibility),
+                layers: renderable.map_or(&[], |renderable| &renderable.layers),
+            }
+        })
+    }
+
+    pub fn materials(&self) -> impl Iterator<Item = MaterialInfo<'_>> {
+        self.materials.iter().enumerate().map(|(index, material)| MaterialInfo {
+            index,
+            name: self.material_names.get(index).and_then(Option::as_deref),
+            color: material.ambient,
+        })
+    }
+
+    /// Every entity, mesh and material called `name`, including entities holding a light of
+    /// that name.
+    pub fn find(&self, name: &str) -> Vec<ObjectId> {
+        let named = |other: Option<&str>| other == Some(name);
+        let lights = self.world.lights.iter()
+            .filter(|(_, light)| named(light.name.as_deref()))
+            .map(|(entity, _)| entity);
+        let mut entities: Vec<Entity> = self.world.find(name).chain(lights).collect();
+        entities.sort_unstable();
+        entities.dedup();
+        let meshes = self.meshes.iter().enumerate()
+            .filter(|(_, mesh)| named(mesh.name.as_deref()))
+            .map(|(index, _)| ObjectId::Mesh(index));
+        let materials = self.material_names.iter().enumerate()
+            .filter(|(_, other)| named(other.as_deref()))
+            .map(|(index, _)| ObjectId::Material(index));
+        entities.into_iter().map(ObjectId::Entity).chain(meshes).chain(materials).collect()
+    }
+
+    /// The nearest mesh the ray from `origin` along `direction` hits, testing every mesh at its
+    /// finest level of detail whatever its visibility. Both faces of triangles count.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<RayHit> {
+        let (mesh, distance) = self.meshes.iter()
+            .enumerate()
+            .filter_map(|(index, mesh)| {
+                mesh.hits(&self.vertices, origin, direction).min_by(f32::total_cmp).map(|distance| (index, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))?;
+        Some(RayHit {
+            mesh,
+            entity: self.world.rendering(mesh),
+            distance,
+            position: origin + direction * distance,
+        })
+    }
+
+    /// The entity hierarchy as an indented tree, one entity per line with the mesh, camera or
+    /// light it holds, followed by the meshes no entity renders.
+    pub fn outline(&self) -> String {
+        outline::tree(&self.world, &self.meshes().collect::<Vec<_>>())
+    }
+
+    /// Writes the scene as glTF, or as GLB when `path` ends in `.glb`, including edits made
+    /// since loading such as visibility and layers.
+    ///
+    /// Meshes are written at their finest level of detail in world space, coloured with the first
+    /// material as the renderer draws them. Cameras, lights, the atmosphere and material
+    /// overrides are kept; splats, volumes, material graphs and custom shading are not.
+    pub fn export_gltf<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        export::write(self, path.as_ref())
+    }
+
+    pub fn stats(&self) -> SceneStats {
+        SceneStats::new(self)
+    }
+
+    /// Adds a triangle list as a new mesh, such as one from [`geometry::primitives`](crate::geometry::primitives).
+    ///
+    /// Coarser levels of detail are generated for it. Returns the mesh's index, or `None` if
+    /// `triangles` is empty.
+    pub fn add_triangles(&mut self, triangles: Vec<Vec3>) -> Option<usize> {
+        let lods = simplify::lod_chain(&triangles, loader::GENERATED_LODS, loader::MIN_LOD_TRIANGLES);
+        if self.materials.is_empty() {
+            self.materials.push(Material {
+                ambient: DEFAULT_COLOR,
+                diffuse: [0.0, 0.0, 0.0, 0.0],
+                specular: [0.0, 0.0, 0.0, 0.0],
+            });
+        }
+        self.add_mesh(iter::once(triangles).chain(lods).collect())
+    }
+
+    /// Adds a source of particles, simulated and drawn from when the scene is loaded.
+    pub fn add_emitter(&mut self, emitter: Emitter) {
+        self.emitters.push(emitter);
+    }
+
+    /// Particle sources, from the scene file's `particles` extras and [`Scene::add_emitter`].
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    /// Maps bands of the system's audio to parameters of the scene, replacing the scene
+    /// file's `audio` extras.
+    pub fn set_audio(&mut self, audio: Option<AudioReactive>) {
+        self.audio = audio;
+    }
+
+    pub fn audio(&self) -> Option<&AudioReactive> {
+        self.audio.as_ref()
+    }
+
+    /// Scales the scene to metres, turns it to Y-up and centres or fits it as `options` ask,
+    /// moving its cameras and lights along with the geometry.
+    pub fn convert(&mut self, options: &ImportOptions) {
+        convert::convert(self, options);
+    }
+
+    /// Reorders the triangles of every mesh for faster rasterization: outward-facing meshlets
+    /// are drawn first to cut overdraw. The triangles themselves are unchanged.
+    pub fn optimize_meshes(&mut self) {
+        for mesh in &mut self.meshes {
+            for (level, lod) in mesh.lods.iter_mut().enumerate() {
+                let tangents = if level == 0 { &mut mesh.tangents[..] } else { &mut [] };
+                optimize::optimize_lod(&mut self.vertices, lod, tangents);
+            }
+        }
+    }
+
+    /// Welds vertices of every mesh closer than `epsilon`, removes degenerate triangles and
+    /// makes winding consistent, see [`cleanup::clean`]. Meshes keep their indices, even when no
+    /// triangles are left, but lose their tangents.
+    pub fn clean_meshes(&mut self, epsilon: f32) {
+        let vertices = std::mem::take(&mut self.vertices);
+        let mut removed = 0;
+        for mesh in &mut self.meshes {
+            for (level, lod) in mesh.lods.iter_mut().enumerate() {
+                let first = lod.first_vertex as usize;
+                let mut cleaned = cleanup::clean(&vertices[first..first + lod.num_vertices as usize], epsilon);
+                if level == 0 {
+                    removed += (lod.num_vertices as usize - cleaned.len()) / 3;
+                }
+                lod.first_vertex = self.vertices.len() as u32;
+                lod.num_vertices = cleaned.len() as u32;
+                lod.meshlets = meshlet::build(&mut cleaned);
+                self.vertices.extend(cleaned);
+            }
+            mesh.tangents.clear();
+            let first = mesh.lods[0].first_vertex as usize;
+            let finest = &self.vertices[first..first + mesh.lods[0].num_vertices as usize];
+            if let Some(&start) = finest.first() {
+                (mesh.min, mesh.max) = finest.iter().fold((start, start), |(min, max), &v| (Vec3::min(min, v), Vec3::max(max, v)));
+            }
+        }
+        if removed > 0 {
+            log::info!("Removed {removed} degenerate triangles");
+        }
+    }
+
+    /// Vertices of the finest level of mesh `index`, three per triangle. Triangles are kept in
+    /// the order they are drawn in, which may not be the order they were added or read in.
+    pub fn mesh_vertices(&self, index: usize) -> &[Vec3] {
+        let Some(lod) = self.meshes.get(index).and_then(|mesh| mesh.lods.first()) else {
+            return &[];
+        };
+        &self.vertices[lod.first_vertex as usize..(lod.first_vertex + lod.num_vertices) as usize]
+    }
+
+    /// Tangents of the vertices [`Scene::mesh_vertices`] returns for mesh `index`, as glTF
+    /// stores them: a unit tangent in `xyz` and the handedness of the bitangent in `w`. Read
+    /// from the `TANGENT` attribute of normal-mapped primitives or generated in MikkTSpace where
+    /// they have none; other vertices have zero tangents. Empty for meshes without a normal
+    /// map, and for meshes whose vertices were welded or dropped to a coarser level.
+    pub fn mesh_tangents(&self, index: usize) -> &[[f32; 4]] {
+        self.meshes.get(index).map_or(&[], |mesh| &mesh.tangents)
+    }
+
+    /// Moves the vertices in `range` of the finest level of mesh `index`, as
+    /// [`Scene::mesh_vertices`] orders them, to `positions`, e.g. to animate a soft body or
+    /// procedural geometry. The bounds used for culling and picking follow, and the mesh's
+    /// coarser levels of detail are dropped so it is always drawn as moved.
+    ///
+    /// Panics if there is no mesh `index`, `range` reaches past its vertices or `positions` is a
+    /// different length.
+    pub fn update_vertices(&mut self, index: usize, range: Range<usize>, positions: &[Vec3]) {
+        self.meshes[index].update_vertices(&mut self.vertices, range, positions);
+    }
+
+    /// Sets which rays see mesh `index`, as returned by [`Scene::add_triangles`].
+    pub fn set_visibility(&mut self, index: usize, visibility: Visibility) {
+        if let Some(renderable) = self.renderable_mut(index) {
+            renderable.visibility = visibility;
+        }
+    }
+
+    /// The entities of the scene and their components.
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// The entities of the scene, to add, remove or change objects. Changes are drawn once the
+    /// scene is handed to the renderer again.
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// The renderable of the first entity drawing mesh `index`.
+    fn renderable_mut(&mut self, index: usize) -> Option<&mut Renderable> {
+        let entity = self.world.rendering(index)?;
+        self.world.renderables.get_mut(entity)
+    }
+
+    /// The first entity rendering each mesh, by mesh index.
+    fn mesh_entities(&self) -> Vec<Option<Entity>> {
+        let mut entities = vec![None; self.meshes.len()];
+        for (entity, renderable) in self.world.renderables.iter() {
+            if let Some(slot @ None) = entities.get_mut(renderable.mesh) {
+                *slot = Some(entity);
+            }
+        }
+        entities
+    }
+
+    /// What the renderer draws each mesh with, by mesh index. Meshes no entity renders are
+    /// hidden.
+    pub(crate) fn mesh_renderables(&self) -> Vec<Renderable> {
+        self.mesh_entities().into_iter()
+            .enumerate()
+            .map(|(mesh, entity)| match entity.and_then(|entity| self.world.renderables.get(entity)) {
+                Some(renderable) => renderable.clone(),
+                None => Renderable {
+                    visibility: Visibility::HIDDEN,
+                    ..Renderable::new(mesh)
+                },
+            })
+            .collect()
+    }
+
+    /// Adds a mesh built from triangle lists, one per level of detail, finest first, with a new
+    /// entity rendering it.
+    ///
+    /// Returns the new mesh's index, or `None` if there were no triangles.
+    pub(crate) fn add_mesh(&mut self, lods: Vec<Vec<Vec3>>) -> Option<usize> {
+        if lods.is_empty() || lods[0].is_empty() {
+            return None;
+        }
+        let entity = self.world.spawn();
+        self.add_mesh_to(entity, lods, Vec::new())
+    }
+
+    /// Adds a mesh as [`Scene::add_mesh`] does, rendered by `entity`, with `tangents` for the
+    /// vertices of its finest level or none.
+    pub(crate) fn add_mesh_to(&mut self, entity: Entity, lods: Vec<Vec<Vec3>>, mut tangents: Vec<[f32; 4]>) -> Option<usize> {
+        if lods.is_empty() || lods[0].is_empty() {
+            return None;
+        }
+        if tangents.len() != lods[0].len() {
+            tangents.clear();
+        }
+        let mut min = lods[0][0];
+        let mut max = lods[0][0];
+        for vertex in &lods[0] {
+            min = Vec3::min(min, *vertex);
+            max = Vec3::max(max, *vertex);
+        }
+        let lods = lods.into_iter()
+            .enumerate()
+            .map(|(level, mut vertices)| {
+                let tangents = if level == 0 { &mut tangents[..] } else { &mut [] };
+                let lod = Lod {
+                    first_vertex: self.vertices.len() as u32,
+                    num_vertices: vertices.len() as u32,
+                    meshlets: meshlet::build_with(&mut vertices, tangents),
+                };
+                self.vertices.extend(vertices);
+                lod
+            })
+            .collect();
+        self.meshes.push(Mesh {
+            lods,
+            min,
+            max,
+            name: None,
+            tangents,
+        });
+        self.world.renderables.insert(entity, Renderable::new(self.meshes.len() - 1));
+        Some(self.meshes.len() - 1)
+    }
+
+    /// Drops the finest level of every mesh that has a coarser one and compacts the vertices.
+    ///
+    /// Returns whether any level was dropped.
+    fn drop_finest_lods(&mut self) -> bool {
+        if self.meshes.iter().all(|mesh| mesh.lods.len() < 2) {
+            return false;
+        }
+        let vertices = std::mem::take(&mut self.vertices);
+        for mesh in &mut self.meshes {
+            if mesh.lods.len() > 1 {
+                mesh.lods.remove(0);
+                mesh.tangents.clear();
+            }
+            for lod in &mut mesh.lods {
+                let first = lod.first_vertex as usize;
+                lod.first_vertex = self.vertices.len() as u32;
+                self.vertices.extend_from_slice(&vertices[first..first + lod.num_vertices as usize]);
+            }
+        }
+        true
+    }
+
+    /// Sheds detail until the vertex data, `vertex_size` bytes per vertex, fits in `budget` bytes
+    /// or no coarser level is left.
+    pub(crate) fn fit_budget(&mut self, budget: u64, vertex_size: usize) {
+        let size = |scene: &Self| (scene.vertices.len() * vertex_size) as u64;
+        while size(self) > budget {
+            if !self.drop_finest_lods() {
+                log::warn!("Scene vertices ({} bytes) exceed the memory budget", size(self));
+                return;
+            }
+            log::warn!("Dropped finest level of detail to fit the memory budget");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LightKind;
+
+    fn triangle(x: f32) -> Vec<Vec3> {
+        vec![vec3![x, 0.0, 0.0], vec3![x + 1.0, 0.0, 0.0], vec3![x, 1.0, 0.0]]
+    }
+
+    /// A scene of single-triangle meshes at `x`, each with its name if it has one.
+    fn scene(meshes: &[(Option<&str>, f32)]) -> Scene {
+        let mut scene = Scene::default();
+        for &(name, x) in meshes {
+            let index = scene.add_mesh(vec![triangle(x)]).unwrap();
+            if let Some(name) = name {
+                scene.set_name(index, name);
+            }
+        }
+        scene
+    }
+
+    fn material(scene: &mut Scene, name: Option<&str>, color: [f32; 4]) -> usize {
+        scene.materials.push(Material { ambient: color, diffuse: [0.0; 4], specular: [0.0; 4] });
+        scene.material_names.resize(scene.materials.len() - 1, None);
+        scene.material_names.push(name.map(Into::into));
+        scene.materials.len() - 1
+    }
+
+    fn light(scene: &mut Scene, name: &str, intensity: f32) -> Entity {
+        let entity = scene.world.spawn();
+        scene.world.lights.insert(entity, Light {
+            name: Some(name.into()),
+            kind: LightKind::Point,
+            position: Vec3::default(),
+            direction: vec3![0.0, -1.0, 0.0],
+            color: [1.0; 3],
+            intensity,
+            range: None,
+        });
+        entity
+    }
+
+    fn camera(scene: &mut Scene, fovy: f32) {
+        let entity = scene.world.spawn();
+        scene.world.cameras.insert(entity, Camera { fovy, ..Camera::default() });
+    }
+
+    #[test]
+    fn named_meshes_are_replaced_in_place() {
+        let mut base = scene(&[(Some("floor"), 0.0), (Some("chair"), 10.0), (None, 20.0)]);
+        let floor = base.world.rendering(0).unwrap();
+        base.merge(scene(&[(Some("extra"), 30.0), (Some("floor"), 40.0), (None, 50.0)]));
+
+        let names: Vec<_> = base.meshes().map(|mesh| mesh.name).collect();
+        assert_eq!(names, [Some("floor"), Some("chair"), None, Some("extra"), None]);
+        for (index, x) in [40.0, 10.0, 20.0, 30.0, 50.0].into_iter().enumerate() {
+            assert_eq!(base.mesh_vertices(index), triangle(x), "mesh {index}");
+        }
+        assert_eq!(base.meshes[0].min.x, 40.0);
+        // The layer's floor took over the entity that drew the old one.
+        assert_eq!(base.world.rendering(0), Some(floor));
+        assert_eq!(base.world.renderables.len(), 5);
+    }
+
+    #[test]
+    fn named_materials_are_replaced_and_references_follow() {
+        let mut base = scene(&[(None, 0.0)]);
+        material(&mut base, Some("paint"), [1.0, 0.0, 0.0, 1.0]);
+        let mut layer = scene(&[(None, 1.0)]);
+        let new = material(&mut layer, Some("metal"), [0.5; 4]);
+        let paint = material(&mut layer, Some("paint"), [0.0, 0.0, 1.0, 1.0]);
+        let entity = layer.world.rendering(0).unwrap();
+        layer.world.materials.insert(entity, vec![new, paint]);
+        base.merge(layer);
+
+        let materials: Vec<_> = base.materials().map(|material| (material.name, material.color)).collect();
+        assert_eq!(materials, [(Some("paint"), [0.0, 0.0, 1.0, 1.0]), (Some("metal"), [0.5; 4])]);
+        assert_eq!(base.meshes().nth(1).unwrap().materials, [1, 0]);
+    }
+
+    #[test]
+    fn named_lights_are_replaced_on_their_entity() {
+        let mut base = Scene::default();
+        let sun = light(&mut base, "sun", 1.0);
+        light(&mut base, "fill", 2.0);
+        let mut layer = Scene::default();
+        light(&mut layer, "sun", 5.0);
+        light(&mut layer, "rim", 3.0);
+        base.merge(layer);
+
+        let lights: Vec<_> = base.lights().iter().map(|light| (light.name.as_deref().unwrap(), light.intensity)).collect();
+        assert_eq!(lights, [("sun", 5.0), ("fill", 2.0), ("rim", 3.0)]);
+        assert_eq!(base.world.lights.get(sun).unwrap().intensity, 5.0);
+    }
+
+    #[test]
+    fn layer_cameras_come_first() {
+        let mut base = Scene::default();
+        camera(&mut base, 30.0);
+        let mut layer = Scene::default();
+        camera(&mut layer, 60.0);
+        base.merge(layer);
+        let fovs: Vec<f32> = base.cameras().iter().map(|camera| camera.fovy).collect();
+        assert_eq!(fovs, [60.0, 30.0]);
+        assert_eq!(base.default_camera().fovy, 60.0);
+    }
+
+    #[test]
+    fn overrides_and_scene_wide_settings() {
+        let mut base = Scene::default();
+        base.overrides.push(MaterialOverride { name: "clay".into(), checker: 1.0, ..Default::default() });
+        base.custom_shading = Some("base".into());
+        base.material_graph = Some("graph".into());
+        let mut layer = Scene::default();
+        layer.overrides.push(MaterialOverride { name: "clay".into(), checker: 2.0, ..Default::default() });
+        layer.overrides.push(MaterialOverride { name: "red".into(), ..Default::default() });
+        layer.custom_shading = Some("layer".into());
+        base.merge(layer);
+
+        let overrides: Vec<_> = base.overrides.iter().map(|o| (o.name.as_str(), o.checker)).collect();
+        assert_eq!(overrides, [("clay", 2.0), ("red", 0.0)]);
+        assert_eq!(base.custom_shading.as_deref(), Some("layer"));
+        // Left alone where the layer has none.
+        assert_eq!(base.material_graph.as_deref(), Some("graph"));
+    }
+}
@@ -0,0 +1,409 @@
+//! What scripts, the control API and remote controllers can reach of the renderer: the camera,
+//! the render settings and, while the scene is being set up, its meshes.
+
+use std::fmt;
+
+use crate::{
+    Annotation,
+    Camera,
+    ClipPlane,
+    Scene,
+    Settings,
+    Sky,
+    Vec3,
+    Visibility,
+    geometry::primitives,
+};
+
+/// Subdivisions of the icospheres `add_sphere` makes.
+const SPHERE_SUBDIVISIONS: u32 = 3;
+
+/// Segments around the cylinders and tori scripts add.
+const SEGMENTS: u32 = 32;
+
+/// Functions [`Bindings::call`] knows, with the number of arguments each takes.
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub(super) const FUNCTIONS: &[(&str, usize)] = &[
+    ("orbit", 1),
+    ("look_at", 2),
+    ("annotate", 2),
+    ("clear_annotations", 0),
+    ("clip", 2),
+    ("clear_clips", 0),
+    ("add_sphere", 2),
+    ("add_box", 4),
+    ("add_plane", 3),
+    ("add_cylinder", 3),
+    ("add_torus", 3),
+    ("mesh_count", 0),
+    ("find", 1),
+    ("set_name", 2),
+    ("set_visible", 2),
+    ("set_layers", 2),
+    ("frame_all", 0),
+];
+
+/// A value read from or given to the bindings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) enum Value {
+    #[default]
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub(super) fn number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub(super) fn numbers(&self) -> Option<Vec<f64>> {
+        match self {
+            Value::Array(items) => items.iter().map(Value::number).collect(),
+            _ => None,
+        }
+    }
+
+    pub(super) fn vec3(&self) -> Option<Vec3> {
+        match self.numbers()?[..] {
+            [x, y, z] => Some(vec3![x as f32, y as f32, z as f32]),
+            _ => None,
+        }
+    }
+}
+
+impl From<f32> for Value {
+    fn from(number: f32) -> Self {
+        Value::Number(number.into())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<Vec3> for Value {
+    fn from(v: Vec3) -> Self {
+        Value::Array(vec![v.x.into(), v.y.into(), v.z.into()])
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Number(number) => write!(f, "{number}"),
+            Value::Text(text) => write!(f, "{text}"),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+pub(super) struct Bindings<'a> {
+    /// `None` once the scene is on the GPU, when meshes can no longer be added.
+    pub(super) scene: Option<&'a mut Scene>,
+    pub(super) settings: &'a mut Settings,
+    pub(super) camera: &'a mut Camera,
+}
+
+fn number(path: &str, value: &Value) -> Result<f32, String> {
+    value.number().map(|number| number as f32).ok_or_else(|| format!("{path} must be a number"))
+}
+
+fn boolean(path: &str, value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        _ => Err(format!("{path} must be true or false")),
+    }
+}
+
+fn vector(path: &str, value: &Value) -> Result<Vec3, String> {
+    value.vec3().ok_or_else(|| format!("{path} must be an array of three numbers"))
+}
+
+fn text(path: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::Text(text) => Ok(text.clone()),
+        _ => Err(format!("{path} must be a string")),
+    }
+}
+
+/// `nil` for `None`, or `value` converted by `convert`.
+fn optional<T>(value: &Value, convert: impl FnOnce(&Value) -> Result<T, String>) -> Result<Option<T>, String> {
+    match value {
+        Value::Nil => Ok(None),
+        value => convert(value).map(Some),
+    }
+}
+
+impl Bindings<'_> {
+    fn overlay(&mut self, name: &str) -> Option<&mut bool> {
+        let overlays = &mut self.settings.overlays;
+        Some(match name {
+            "grid" => &mut overlays.grid,
+            "axes" => &mut overlays.axes,
+            "bounds" => &mut overlays.bounds,
+            "hud" => &mut overlays.hud,
+            "labels" => &mut overlays.labels,
+            _ => return None,
+        })
+    }
+
+    /// A setting of the sky, `None` without a sky or for a name it doesn't have.
+    fn sky(&mut self, name: &str) -> Option<&mut f32> {
+        let sky = self.settings.sky.as_mut()?;
+        Some(match name {
+            "turbidity" => &mut sky.turbidity,
+            "azimuth" => &mut sky.azimuth,
+            "elevation" => &mut sky.elevation,
+            "exposure" => &mut sky.exposure,
+            _ => return None,
+        })
+    }
+
+    fn scene(&mut self, function: &str) -> Result<&mut Scene, String> {
+        self.scene.as_deref_mut().ok_or_else(|| format!("{function} can only be called while setting up the scene"))
+    }
+
+    /// Adds a mesh made by `triangles` from the numbers after the centre in `args`.
+    fn add(
+        &mut self,
+        function: &str,
+        args: &[Value],
+        triangles: impl FnOnce(Vec3, &[f32]) -> Vec<Vec3>,
+        count: usize,
+    ) -> Result<Value, String> {
+        let usage = || format!("{function} takes a centre and {count} numbers");
+        let (center, rest) = args.split_first().ok_or_else(usage)?;
+        let center = vector(function, center)?;
+        let sizes = rest.iter().map(|arg| number(function, arg)).collect::<Result<Vec<_>, _>>().map_err(|_| usage())?;
+        if sizes.len() != count {
+            return Err(usage());
+        }
+        let mesh = self.scene(function)?.add_triangles(triangles(center, &sizes));
+        Ok(mesh.map_or(Value::Nil, |mesh| Value::Number(mesh as f64)))
+    }
+
+    /// Index of an existing mesh given as the first of `args`.
+    fn mesh(&mut self, function: &str, args: &[Value]) -> Result<usize, String> {
+        let count = self.scene(function)?.meshes().count();
+        match args.first().and_then(Value::number) {
+            Some(index) if index >= 0.0 && (index as usize) < count => Ok(index as usize),
+            _ => Err(format!("{function} expects the index of one of the {count} meshes")),
+        }
+    }
+
+    /// The value at a dotted path such as `camera.eye`, or `None` if there is none.
+    pub(super) fn get(&self, path: &str) -> Option<Value> {
+        let settings = &self.settings;
+        let overlays = &settings.overlays;
+        Some(match path {
+            "camera.eye" => self.camera.eye.into(),
+            "camera.target" => self.camera.target.into(),
+            "camera.up" => self.camera.up.into(),
+            "camera.fovy" => self.camera.fovy.into(),
+            "camera.znear" => self.camera.znear.into(),
+            "camera.zfar" => self.camera.zfar.into(),
+            "settings.explode" => settings.explode.into(),
+            "settings.splat_scale" => settings.splat_scale.into(),
+            "settings.turntable_speed" => settings.turntable_speed.into(),
+            "settings.interactive_quality" => settings.interactive_quality.into(),
+            "settings.max_radiance" => settings.max_radiance.map_or(Value::Nil, Value::from),
+            "settings.bg_color" => {
+                let color = settings.bg_color;
+                Value::Array([color.r, color.g, color.b, color.a].map(Value::Number).into())
+            }
+            "settings.transparent_background" => settings.transparent_background.into(),
+            "settings.id_matte" => settings.id_matte.into(),
+            "settings.nan_check" => settings.nan_check.into(),
+            "settings.material_override" => settings.material_override.clone().map_or(Value::Nil, Value::Text),
+            "settings.layers" => settings.layers.as_ref().map_or(Value::Nil, |layers| {
+                Value::Array(layers.iter().cloned().map(Value::Text).collect())
+            }),
+            "settings.overlays.grid" => overlays.grid.into(),
+            "settings.overlays.axes" => overlays.axes.into(),
+            "settings.overlays.bounds" => overlays.bounds.into(),
+            "settings.overlays.hud" => overlays.hud.into(),
+            "settings.overlays.labels" => overlays.labels.into(),
+            "settings.sky" => settings.sky.is_some().into(),
+            _ => {
+                let sky = settings.sky.as_ref();
+                match path.strip_prefix("settings.sky.")? {
+                    "turbidity" => sky.map_or(Value::Nil, |sky| sky.turbidity.into()),
+                    "azimuth" => sky.map_or(Value::Nil, |sky| sky.azimuth.into()),
+                    "elevation" => sky.map_or(Value::Nil, |sky| sky.elevation.into()),
+                    "exposure" => sky.map_or(Value::Nil, |sky| sky.exposure.into()),
+                    _ => return None,
+                }
+            }
+        })
+    }
+
+    /// Stores `value` at a path [`Bindings::get`] knows, or explains why it can't.
+    pub(super) fn set(&mut self, path: &str, value: Value) -> Result<(), String> {
+        let settings = &mut *self.settings;
+        match path {
+            "camera.eye" => self.camera.eye = vector(path, &value)?,
+            "camera.target" => self.camera.target = vector(path, &value)?,
+            "camera.up" => self.camera.up = vector(path, &value)?,
+            "camera.fovy" => self.camera.fovy = number(path, &value)?,
+            "camera.znear" => self.camera.znear = number(path, &value)?,
+            "camera.zfar" => self.camera.zfar = number(path, &value)?,
+            "settings.explode" => settings.explode = number(path, &value)?.max(0.0),
+            "settings.splat_scale" => settings.splat_scale = number(path, &value)?,
+            "settings.turntable_speed" => settings.turntable_speed = number(path, &value)?,
+            "settings.interactive_quality" => settings.interactive_quality = number(path, &value)?.clamp(0.0, 1.0),
+            "settings.max_radiance" => settings.max_radiance = optional(&value, |value| number(path, value))?,
+            "settings.bg_color" => match value.numbers().as_deref() {
+                Some(&[r, g, b, a]) => settings.bg_color = wgpu::Color { r, g, b, a },
+                _ => return Err(format!("{path} must be an array of four numbers")),
+            },
+            "settings.transparent_background" => settings.transparent_background = boolean(path, &value)?,
+            "settings.id_matte" => settings.id_matte = boolean(path, &value)?,
+            "settings.nan_check" => settings.nan_check = boolean(path, &value)?,
+            "settings.material_override" => settings.material_override = optional(&value, |value| text(path, value))?,
+            "settings.layers" => {
+                settings.layers = optional(&value, |value| match value {
+                    Value::Array(items) => items.iter().map(|item| text(path, item)).collect(),
+                    _ => Err(format!("{path} must be an array of strings or nil")),
+                })?;
+            }
+            "settings.sky" => {
+                let on = boolean(path, &value)?;
+                if on != settings.sky.is_some() {
+                    settings.sky = on.then(Sky::default);
+                }
+            }
+            _ => {
+                if let Some(overlay) = path.strip_prefix("settings.overlays.").and_then(|name| self.overlay(name)) {
+                    *overlay = boolean(path, &value)?;
+                } else if let Some(name) = path.strip_prefix("settings.sky.") {
+                    let number = number(path, &value)?;
+                    if self.settings.sky.is_none() {
+                        return Err(format!("{path} needs settings.sky on"));
+                    }
+                    *self.sky(name).ok_or_else(|| format!("{path} can't be set"))? = number;
+                } else {
+                    return Err(format!("{path} can't be set"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls function `name`, one of [`FUNCTIONS`], or returns `None` if there is no such
+    /// function.
+    pub(super) fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+        let result = match name {
+            "orbit" => args.first().ok_or_else(|| "orbit takes an angle in degrees".to_owned())
+                .and_then(|degrees| number(name, degrees))
+                .map(|degrees| {
+                    *self.camera = self.camera.orbit(self.camera.target, degrees);
+                    Value::Nil
+                }),
+            "look_at" => match args {
+                [eye, target] => vector(name, eye).and_then(|eye| {
+                    self.camera.eye = eye;
+                    self.camera.target = vector(name, target)?;
+                    Ok(Value::Nil)
+                }),
+                _ => Err(format!("{name} takes an eye and a target")),
+            },
+            "annotate" => match args {
+                [position, label] => vector(name, position).map(|position| {
+                    self.settings.annotations.push(Annotation::new(position, label.to_string()));
+                    Value::Nil
+                }),
+                _ => Err(format!("{name} takes a position and a label")),
+            },
+            "clear_annotations" => {
+                self.settings.annotations.clear();
+                Ok(Value::Nil)
+            }
+            "clip" => match args {
+                [point, normal] => vector(name, point).and_then(|point| {
+                    let normal = vector(name, normal)?;
+                    self.settings.clip_planes.push(ClipPlane { point, normal });
+                    Ok(Value::Nil)
+                }),
+                _ => Err(format!("{name} takes a point and a normal")),
+            },
+            "clear_clips" => {
+                self.settings.clip_planes.clear();
+                Ok(Value::Nil)
+            }
+            "add_sphere" => self.add(name, args, |center, sizes| {
+                primitives::icosphere(center, sizes[0], SPHERE_SUBDIVISIONS)
+            }, 1),
+            "add_box" => self.add(name, args, |center, sizes| {
+                primitives::cuboid(center, vec3![sizes[0], sizes[1], sizes[2]])
+            }, 3),
+            "add_plane" => self.add(name, args, |center, sizes| {
+                primitives::plane(center, sizes[0], sizes[1])
+            }, 2),
+            "add_cylinder" => self.add(name, args, |center, sizes| {
+                primitives::cylinder(center, sizes[0], sizes[1], SEGMENTS)
+            }, 2),
+            "add_torus" => self.add(name, args, |center, sizes| {
+                primitives::torus(center, sizes[0], sizes[1], SEGMENTS, SEGMENTS / 2)
+            }, 2),
+            "mesh_count" => self.scene(name).map(|scene| Value::Number(scene.meshes().count() as f64)),
+            "find" => match args {
+                [Value::Text(mesh)] => self.scene(name).map(|scene| {
+                    scene.meshes()
+                        .find(|info| info.name == Some(mesh.as_str()))
+                        .map_or(Value::Nil, |info| Value::Number(info.index as f64))
+                }),
+                _ => Err(format!("{name} takes a mesh name")),
+            },
+            "set_name" => self.mesh(name, args).and_then(|mesh| match args {
+                [_, Value::Text(text)] => {
+                    self.scene(name)?.set_name(mesh, text.clone());
+                    Ok(Value::Nil)
+                }
+                _ => Err(format!("{name} takes a mesh and a name")),
+            }),
+            "set_visible" => self.mesh(name, args).and_then(|mesh| match args {
+                [_, Value::Bool(visible)] => {
+                    let visibility = if *visible { Visibility::default() } else { Visibility::HIDDEN };
+                    self.scene(name)?.set_visibility(mesh, visibility);
+                    Ok(Value::Nil)
+                }
+                _ => Err(format!("{name} takes a mesh and true or false")),
+            }),
+            "set_layers" => self.mesh(name, args).and_then(|mesh| match args {
+                [_, Value::Array(layers)] => {
+                    let layers = layers.iter().map(|layer| text(name, layer)).collect::<Result<_, _>>()?;
+                    self.scene(name)?.set_layers(mesh, layers);
+                    Ok(Value::Nil)
+                }
+                _ => Err(format!("{name} takes a mesh and an array of layer names")),
+            }),
+            "frame_all" => self.scene(name).map(|scene| scene.bounds()).map(|bounds| {
+                if let Some((min, max)) = bounds {
+                    *self.camera = self.camera.frame(min, max);
+                }
+                Value::Nil
+            }),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
@@ -0,0 +1,517 @@
+//! Runs scripts with Rhai, reaching the renderer through [`Bindings`].
+
+use std::{
+    any::TypeId,
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    mem,
+    rc::Rc,
+};
+
+use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FLOAT, FuncRegistration, INT, Scope};
+
+use crate::{Camera, Scene, Settings};
+
+use super::{
+    ON_FRAME,
+    ScriptError,
+    TIME,
+    bindings::{Bindings, FUNCTIONS, Value},
+};
+
+/// Operations one call into a script may take before it is stopped as stuck in a loop.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// How deeply script functions may call each other.
+const MAX_CALL_LEVELS: usize = 64;
+
+/// Rhai's name for index setters, which assigning a field without a setter of its own calls.
+const INDEX_SET: &str = "index$set$";
+
+/// Fields that are parts of the renderer with fields of their own.
+const NODES: &[&str] = &["settings.overlays", "settings.sky"];
+
+/// A part of the renderer, such as `settings` or `settings.sky`, whose fields are paths of
+/// [`Bindings`].
+#[derive(Clone)]
+struct Node(String);
+
+/// What a script's functions share: the renderer's state, moved in for the length of a call,
+/// and the variables the top-level statements left.
+#[derive(Default)]
+struct Shared {
+    /// The scene while it is being set up.
+    scene: Option<Scene>,
+    settings: Settings,
+    camera: Camera,
+    /// The timeline's position during `on_frame`.
+    time: Option<f64>,
+    globals: HashMap<String, Dynamic>,
+}
+
+impl Shared {
+    fn bindings(&mut self) -> Bindings<'_> {
+        Bindings {
+            scene: self.scene.as_mut(),
+            settings: &mut self.settings,
+            camera: &mut self.camera,
+        }
+    }
+}
+
+/// A compiled script and the engine running it.
+pub(super) struct Host {
+    engine: Engine,
+    ast: AST,
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl Host {
+    pub(super) fn parse(source: &str) -> Result<Self, ScriptError> {
+        let shared = Rc::default();
+        let engine = engine(&shared);
+        let ast = engine.compile(source).map_err(|err| ScriptError::Syntax {
+            line: err.position().line().unwrap_or(0),
+            message: err.err_type().to_string(),
+        })?;
+        Ok(Self { engine, ast, shared })
+    }
+
+    pub(super) fn run(&mut self, scene: &mut Scene, settings: &mut Settings, camera: &mut Camera) -> Result<(), ScriptError> {
+        self.shared.borrow_mut().globals.clear();
+        let mut scope = Scope::new();
+        let result = self.with(Some(scene), settings, camera, None, |engine, ast| engine.run_ast_with_scope(&mut scope, ast));
+        self.shared.borrow_mut().globals = scope.iter().map(|(name, _, value)| (name.to_owned(), value)).collect();
+        result.map_err(|err| runtime(*err))
+    }
+
+    pub(super) fn has_on_frame(&self) -> bool {
+        self.ast.iter_functions().any(|function| function.name == ON_FRAME && function.params.len() == 1)
+    }
+
+    pub(super) fn on_frame(&mut self, frame: u64, time: f32, settings: &mut Settings, camera: &mut Camera) -> Result<(), ScriptError> {
+        if !self.has_on_frame() {
+            return Ok(());
+        }
+        // The top-level statements ran when the scene was set up.
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.with(None, settings, camera, Some(time.into()), |engine, ast| {
+            engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, ON_FRAME, (frame as INT,))
+        });
+        result.map(|_| ()).map_err(|err| runtime(*err))
+    }
+
+    /// The value the top-level statements left in variable `name`.
+    #[cfg(test)]
+    fn global(&self, name: &str) -> Option<Dynamic> {
+        self.shared.borrow().globals.get(name).cloned()
+    }
+
+    /// Calls `call` with the renderer's state moved into the shared state, moving it back after.
+    fn with<T>(
+        &self,
+        mut scene: Option<&mut Scene>,
+        settings: &mut Settings,
+        camera: &mut Camera,
+        time: Option<f64>,
+        call: impl FnOnce(&Engine, &AST) -> T,
+    ) -> T {
+        {
+            let mut shared = self.shared.borrow_mut();
+            shared.scene = scene.as_deref_mut().map(mem::take);
+            mem::swap(&mut shared.settings, settings);
+            shared.camera = *camera;
+            shared.time = time;
+        }
+        let result = call(&self.engine, &self.ast);
+        let mut shared = self.shared.borrow_mut();
+        if let (Some(scene), Some(taken)) = (scene, shared.scene.take()) {
+            *scene = taken;
+        }
+        mem::swap(&mut shared.settings, settings);
+        *camera = shared.camera;
+        result
+    }
+}
+
+impl Clone for Host {
+    fn clone(&self) -> Self {
+        let shared = Rc::new(RefCell::new(Shared {
+            globals: self.shared.borrow().globals.clone(),
+            ..Shared::default()
+        }));
+        Self {
+            engine: engine(&shared),
+            ast: self.ast.clone(),
+            shared,
+        }
+    }
+}
+
+impl fmt::Debug for Host {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Host").field("ast", &self.ast).finish_non_exhaustive()
+    }
+}
+
+/// An engine whose `settings`, `camera`, `time` and functions reach the renderer through
+/// `shared`, and whose functions can read the variables the top-level statements left.
+fn engine(shared: &Rc<RefCell<Shared>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS).set_max_call_levels(MAX_CALL_LEVELS);
+    engine.on_print(|text| log::info!("{text}"));
+    engine.on_debug(|text, _, _| log::debug!("{text}"));
+    engine.register_type_with_name::<Node>("Node");
+
+    let state = shared.clone();
+    // Rhai marks variable resolvers as volatile rather than deprecated.
+    #[allow(deprecated)]
+    engine.on_var(move |name, _, context| {
+        if context.scope().contains(name) {
+            return Ok(None);
+        }
+        let shared = state.borrow();
+        Ok(match name {
+            "settings" | "camera" => Some(Dynamic::from(Node(name.to_owned()))),
+            TIME => shared.time.map(|time| Dynamic::from_float(time as FLOAT)),
+            _ => shared.globals.get(name).cloned(),
+        })
+    });
+
+    // Fields without getters and setters of their own go to the indexers.
+    let state = shared.clone();
+    engine.register_indexer_get(move |node: &mut Node, name: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+        let path = format!("{}.{name}", node.0);
+        if NODES.contains(&path.as_str()) {
+            return Ok(Dynamic::from(Node(path)));
+        }
+        match state.borrow_mut().bindings().get(&path) {
+            Some(value) => Ok(to_dynamic(value)),
+            None => Err(format!("{path} doesn't exist").into()),
+        }
+    });
+    let state = shared.clone();
+    // Pure so it may be called on `settings` and `camera`, which are constants: it changes the
+    // renderer rather than the node.
+    FuncRegistration::new(INDEX_SET).with_purity(true).register_into_engine(
+        &mut engine,
+        move |node: &mut Node, name: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            // Assigning a field of a nested node writes the node back, which changes nothing.
+            if value.is::<Node>() {
+                return Ok(());
+            }
+            let path = format!("{}.{name}", node.0);
+            let value = from_dynamic(value).map_err(|err| format!("{path} can't be {err}"))?;
+            state.borrow_mut().bindings().set(&path, value).map_err(Into::into)
+        },
+    );
+
+    for &(name, arity) in FUNCTIONS {
+        let state = shared.clone();
+        engine.register_raw_fn(name, vec![TypeId::of::<Dynamic>(); arity], move |_, args| -> Result<Dynamic, Box<EvalAltResult>> {
+            let args = args.iter()
+                // Arguments may be the script's own variables, passed by reference.
+                .map(|arg| from_dynamic((*arg).clone()).map_err(|err| format!("{name} can't take {err}")))
+                .collect::<Result<Vec<_>, _>>()?;
+            let result = state.borrow_mut().bindings().call(name, &args);
+            match result.unwrap_or_else(|| Err(format!("{name} doesn't exist")))? {
+                // Functions return mesh indices and counts, which scripts may index arrays with.
+                Value::Number(number) => Ok((number as INT).into()),
+                value => Ok(to_dynamic(value)),
+            }
+        });
+    }
+    engine
+}
+
+fn to_dynamic(value: Value) -> Dynamic {
+    match value {
+        Value::Nil => Dynamic::UNIT,
+        Value::Bool(value) => value.into(),
+        Value::Number(number) => Dynamic::from_float(number as FLOAT),
+        Value::Text(text) => text.into(),
+        Value::Array(items) => items.into_iter().map(to_dynamic).collect::<Array>().into(),
+    }
+}
+
+/// `value` as the bindings take it, or the name of its type if they can't.
+fn from_dynamic(value: Dynamic) -> Result<Value, String> {
+    if value.is_unit() {
+        return Ok(Value::Nil);
+    }
+    if let Ok(value) = value.as_bool() {
+        return Ok(Value::Bool(value));
+    }
+    if let Ok(number) = value.as_int() {
+        return Ok(Value::Number(number as f64));
+    }
+    if let Ok(number) = value.as_float() {
+        return Ok(Value::Number(number));
+    }
+    if value.is_string() {
+        return Ok(Value::Text(value.into_string().unwrap_or_default()));
+    }
+    match value.into_array() {
+        Ok(items) => items.into_iter().map(from_dynamic).collect::<Result<_, _>>().map(Value::Array),
+        Err(type_name) => Err(format!("a value of type {type_name}")),
+    }
+}
+
+/// The error a script stopped with, on the line inside the function that raised it.
+fn runtime(mut err: EvalAltResult) -> ScriptError {
+    while let EvalAltResult::ErrorInFunctionCall(.., inner, _) = err {
+        err = *inner;
+    }
+    let line = err.take_position().line().unwrap_or(0);
+    let message = match err {
+        EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+        err => err.to_string(),
+    };
+    ScriptError::Runtime { line, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClipPlane, Vec3, Visibility};
+
+    /// Runs `source` on an empty scene, returning what it changed.
+    fn run(source: &str) -> Result<(Host, Scene, Settings, Camera), ScriptError> {
+        let (mut scene, mut settings, mut camera) = (Scene::default(), Settings::default(), Camera::default());
+        let mut host = Host::parse(source)?;
+        host.run(&mut scene, &mut settings, &mut camera)?;
+        Ok((host, scene, settings, camera))
+    }
+
+    /// Message of the runtime error `source` stops with.
+    fn error(source: &str) -> String {
+        match run(source) {
+            Err(ScriptError::Runtime { message, .. }) => message,
+            Err(err) => panic!("expected a runtime error, found {err}"),
+            Ok(_) => panic!("{source} ran without an error"),
+        }
+    }
+
+    /// The value the top-level statements left in `name`, as the bindings see it.
+    fn global(host: &Host, name: &str) -> Value {
+        from_dynamic(host.global(name).unwrap()).unwrap()
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn camera_paths() {
+        let (host, _, _, camera) = run("
+            camera.eye = [1, 2, 3];
+            camera.target = [0, 1, 0];
+            camera.up = [0, 0, 1];
+            camera.fovy = 30;
+            camera.znear = 0.5;
+            camera.zfar = camera.znear * 100;
+            let eye = camera.eye;
+        ").unwrap();
+        assert_eq!((camera.eye, camera.target, camera.up), (vec3![1.0, 2.0, 3.0], vec3![0.0, 1.0, 0.0], vec3![0.0, 0.0, 1.0]));
+        assert_eq!((camera.fovy, camera.znear, camera.zfar), (30.0, 0.5, 50.0));
+        assert_eq!(global(&host, "eye"), Value::from(vec3![1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn settings_paths() {
+        let (_, _, settings, _) = run("
+            settings.explode = -1;
+            settings.splat_scale = 2;
+            settings.turntable_speed = 15;
+            settings.interactive_quality = 3;
+            settings.max_radiance = 10;
+            settings.bg_color = [0.1, 0.2, 0.3, 1];
+            settings.transparent_background = true;
+            settings.id_matte = true;
+            settings.nan_check = true;
+            settings.material_override = \"clay\";
+            settings.layers = [\"props\"];
+            settings.overlays.grid = true;
+            settings.overlays.axes = true;
+            settings.overlays.bounds = true;
+            settings.overlays.hud = true;
+            settings.overlays.labels = true;
+            settings.sky = true;
+            settings.sky.turbidity = 4;
+            settings.sky.azimuth = 90;
+            settings.sky.elevation = 30;
+            settings.sky.exposure = settings.sky.elevation / 10;
+        ").unwrap();
+        assert_eq!((settings.explode, settings.splat_scale, settings.turntable_speed), (0.0, 2.0, 15.0));
+        assert_eq!((settings.interactive_quality, settings.max_radiance), (1.0, Some(10.0)));
+        assert_eq!(settings.bg_color, wgpu::Color { r: 0.1, g: 0.2, b: 0.3, a: 1.0 });
+        assert!(settings.transparent_background && settings.id_matte && settings.nan_check);
+        assert_eq!(settings.material_override.as_deref(), Some("clay"));
+        assert_eq!(settings.layers, Some(vec!["props".to_owned()]));
+        let overlays = settings.overlays;
+        assert!(overlays.grid && overlays.axes && overlays.bounds && overlays.hud && overlays.labels);
+        let sky = settings.sky.unwrap();
+        assert_eq!((sky.turbidity, sky.azimuth, sky.elevation, sky.exposure), (4.0, 90.0, 30.0, 3.0));
+    }
+
+    #[test]
+    fn optional_settings_clear_with_unit() {
+        let (host, _, settings, _) = run("
+            settings.max_radiance = 1;
+            settings.max_radiance = ();
+            settings.layers = ();
+            settings.sky = false;
+            let exposure = settings.sky.exposure;
+        ").unwrap();
+        assert_eq!((settings.max_radiance, settings.layers, settings.sky.is_some()), (None, None, false));
+        assert_eq!(global(&host, "exposure"), Value::Nil);
+    }
+
+    #[test]
+    fn bad_assignments() {
+        assert_eq!(error("camera.eye = 1;"), "camera.eye must be an array of three numbers");
+        assert_eq!(error("camera.fovy = \"wide\";"), "camera.fovy must be a number");
+        assert_eq!(error("settings.bg_color = [1, 1, 1];"), "settings.bg_color must be an array of four numbers");
+        assert_eq!(error("settings.id_matte = 1;"), "settings.id_matte must be true or false");
+        assert_eq!(error("settings.material_override = 1;"), "settings.material_override must be a string");
+        assert_eq!(error("settings.layers = \"props\";"), "settings.layers must be an array of strings or nil");
+        assert_eq!(error("settings.sky = false;\nsettings.sky.azimuth = 1;"), "settings.sky.azimuth needs settings.sky on");
+        assert_eq!(error("settings.overlays.fog = true;"), "settings.overlays.fog can't be set");
+        assert_eq!(error("camera.zoom = 2;"), "camera.zoom can't be set");
+        assert_eq!(error("let zoom = camera.zoom;"), "camera.zoom doesn't exist");
+        assert_eq!(error("settings.explode = #{};"), "settings.explode can't be a value of type map");
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        match run("let a = 1;\n\ncamera.fovy = \"wide\";").err() {
+            Some(ScriptError::Runtime { line: 3, message }) => assert_eq!(message, "camera.fovy must be a number"),
+            other => panic!("{other:?}"),
+        }
+        match run("let a = 1;\nlet b = ;").err() {
+            Some(ScriptError::Syntax { line: 2, .. }) => {}
+            other => panic!("{other:?}"),
+        }
+        assert_eq!(error("throw \"stop\";"), "stop");
+    }
+
+    #[test]
+    fn endless_loops_are_stopped() {
+        assert!(matches!(run("loop {}"), Err(ScriptError::Runtime { .. })));
+        assert!(matches!(run("fn f() { f() } f();"), Err(ScriptError::Runtime { .. })));
+    }
+
+    #[test]
+    fn camera_functions() {
+        let (_, _, _, camera) = run("look_at([0, 0, 4], [0, 0, 0]);\norbit(90);").unwrap();
+        assert!(close(camera.eye, vec3![4.0, 0.0, 0.0]) || close(camera.eye, vec3![-4.0, 0.0, 0.0]), "{:?}", camera.eye);
+        assert_eq!(camera.target, vec3![0.0, 0.0, 0.0]);
+        assert!(error("orbit();").starts_with("Function not found: orbit"));
+        assert_eq!(error("look_at([0, 0, 1], 1);"), "look_at must be an array of three numbers");
+    }
+
+    #[test]
+    fn annotations_and_clip_planes() {
+        let (_, _, settings, _) = run("
+            annotate([1, 2, 3], \"peak\");
+            annotate([0, 0, 0], 5);
+            clip([0, 0, 0], [1, 0, 0]);
+        ").unwrap();
+        let texts: Vec<_> = settings.annotations.iter().map(|annotation| annotation.text.as_str()).collect();
+        assert_eq!(texts, ["peak", "5"]);
+        assert_eq!(settings.annotations[0].position, vec3![1.0, 2.0, 3.0]);
+        assert_eq!(settings.clip_planes, [ClipPlane { point: vec3![0.0, 0.0, 0.0], normal: vec3![1.0, 0.0, 0.0] }]);
+        let (_, _, settings, _) = run("annotate([0, 0, 0], \"a\");\nclip([0, 0, 0], [0, 1, 0]);\nclear_annotations();\nclear_clips();").unwrap();
+        assert!(settings.annotations.is_empty() && settings.clip_planes.is_empty());
+        assert_eq!(error("annotate(1, \"peak\");"), "annotate must be an array of three numbers");
+        assert_eq!(error("clip([0, 0, 0], 1);"), "clip must be an array of three numbers");
+    }
+
+    #[test]
+    fn adding_meshes() {
+        let (host, scene, _, camera) = run("
+            let sphere = add_sphere([0, 0, 0], 1);
+            let cube = add_box([3, 0, 0], 1, 2, 3);
+            add_plane([0, -1, 0], 10, 10);
+            add_cylinder([0, 2, 0], 0.5, 2);
+            add_torus([0, 0, 5], 1, 0.25);
+            let count = mesh_count();
+            frame_all();
+        ").unwrap();
+        assert_eq!((global(&host, "sphere"), global(&host, "cube")), (Value::Number(0.0), Value::Number(1.0)));
+        assert_eq!(global(&host, "count"), Value::Number(5.0));
+        let cube = scene.meshes().nth(1).unwrap();
+        assert!(close(cube.min, vec3![2.5, -1.0, -1.5]) && close(cube.max, vec3![3.5, 1.0, 1.5]));
+        assert_ne!(camera, Camera::default());
+        assert_eq!(error("add_box([0, 0, 0], 1, 2, ());"), "add_box takes a centre and 3 numbers");
+        assert_eq!(error("add_sphere(1, 1);"), "add_sphere must be an array of three numbers");
+        assert_eq!(error("add_plane([0, 0, 0], 1, \"2\");"), "add_plane takes a centre and 2 numbers");
+    }
+
+    #[test]
+    fn naming_hiding_and_layering_meshes() {
+        let (host, scene, _, _) = run("
+            let cube = add_box([0, 0, 0], 1, 1, 1);
+            add_sphere([2, 0, 0], 1);
+            set_name(cube, \"Cube\");
+            set_visible(1, false);
+            set_layers(cube, [\"props\", \"hero\"]);
+            let found = find(\"Cube\");
+            let missing = find(\"Sphere\");
+            let layers = [];
+            for mesh in 0..mesh_count() { layers.push(mesh); }
+        ").unwrap();
+        assert_eq!((global(&host, "found"), global(&host, "missing")), (Value::Number(0.0), Value::Nil));
+        assert_eq!(global(&host, "layers"), Value::Array(vec![Value::Number(0.0), Value::Number(1.0)]));
+        let meshes: Vec<_> = scene.meshes().collect();
+        assert_eq!(meshes[0].name, Some("Cube"));
+        assert_eq!(meshes[0].layers, ["props", "hero"]);
+        assert_eq!((meshes[0].visibility, meshes[1].visibility), (Visibility::default(), Visibility::HIDDEN));
+        assert_eq!(error("set_name(0, \"Cube\");"), "set_name expects the index of one of the 0 meshes");
+        assert_eq!(error("add_sphere([0, 0, 0], 1);\nset_visible(0, 1);"), "set_visible takes a mesh and true or false");
+        assert_eq!(error("add_sphere([0, 0, 0], 1);\nset_layers(0, \"a\");"), "set_layers takes a mesh and an array of layer names");
+        assert_eq!(error("find(1);"), "find takes a mesh name");
+    }
+
+    #[test]
+    fn on_frame_sees_globals_and_time_but_not_meshes() {
+        let (mut host, _, mut settings, mut camera) = run("
+            let speed = 2;
+            settings.explode += 0.25;
+            fn on_frame(frame) { settings.explode = frame + time * speed; }
+        ").unwrap();
+        assert!(host.has_on_frame());
+        host.on_frame(3, 0.5, &mut settings, &mut camera).unwrap();
+        assert_eq!(settings.explode, 4.0);
+
+        let (mut host, _, mut settings, mut camera) = run("fn on_frame(frame) {\n    add_sphere([0, 0, 0], 1);\n}").unwrap();
+        match host.on_frame(1, 0.0, &mut settings, &mut camera) {
+            Err(ScriptError::Runtime { line: 2, message }) => {
+                assert_eq!(message, "add_sphere can only be called while setting up the scene");
+            }
+            other => panic!("{other:?}"),
+        }
+        let (mut host, ..) = run("").unwrap();
+        assert!(!host.has_on_frame());
+        host.on_frame(1, 0.0, &mut settings, &mut camera).unwrap();
+    }
+
+    #[test]
+    fn top_level_statements_run_once() {
+        let (mut host, _, mut settings, mut camera) = run("
+            settings.explode += 0.25;
+            fn on_frame(frame) { settings.splat_scale = frame; }
+        ").unwrap();
+        host.on_frame(2, 0.0, &mut settings, &mut camera).unwrap();
+        assert_eq!((settings.explode, settings.splat_scale), (0.25, 2.0));
+    }
+
+    #[test]
+    fn clones_keep_globals() {
+        let (host, _, mut settings, mut camera) = run("let speed = 3;\nfn on_frame(frame) { settings.explode = speed / 10.0; }").unwrap();
+        let mut clone = host.clone();
+        clone.on_frame(0, 0.0, &mut settings, &mut camera).unwrap();
+        assert!((settings.explode - 0.3).abs() < 1e-6);
+    }
+}
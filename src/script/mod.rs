@@ -0,0 +1,229 @@
+//! Scripts automating scene setup, turntables and parameter sweeps without recompiling,
+//! written in [Rhai](https://rhai.rs). Running them needs the `scripting` feature.
+//!
+//! A script's top-level statements run once on the loaded scene, and may add meshes to it and
+//! change the camera and render settings. A function `on_frame(frame)` it defines is then
+//! called before every frame is drawn while the timeline plays, with the camera and settings
+//! but no longer the meshes, and `time` set to the timeline's position in seconds:
+//!
+//! ```text
+//! let ball = add_sphere([0, 1, 0], 0.5);
+//! set_name(ball, "Ball");
+//! frame_all();
+//!
+//! fn on_frame(frame) {
+//!     orbit(1);
+//!     settings.explode = (1 - cos(time)) / 2;
+//! }
+//! ```
+//!
+//! `on_frame` can read the variables the top-level statements defined but not assign them.
+//! Vectors and colours are arrays of numbers, `()` stands for no value, and `print` writes to
+//! the log. The rest of the language and its maths functions are Rhai's.
+//!
+//! Scripts read and assign `camera.eye`, `target`, `up`, `fovy`, `znear` and `zfar`, and
+//! `settings.explode`, `splat_scale`, `turntable_speed`, `interactive_quality`,
+//! `max_radiance`, `bg_color`, `transparent_background`, `id_matte`, `nan_check`,
+//! `material_override`, `layers`, `overlays.grid`, `axes`, `bounds`, `hud` and `labels`, and
+//! `sky.turbidity`, `azimuth`, `elevation` and `exposure`, which read as `()` without a sky.
+//! Assigning `settings.sky` true or false turns the sky on or off. They can call:
+//!
+//! - `orbit(degrees)` and `look_at(eye, target)` to move the camera;
+//! - `annotate(position, text)`, `clear_annotations()`, `clip(point, normal)` and
+//!   `clear_clips()`;
+//! - while setting up the scene only: `add_sphere(centre, radius)`, `add_box(centre, x, y, z)`,
+//!   `add_plane(centre, width, depth)`, `add_cylinder(centre, radius, height)` and
+//!   `add_torus(centre, major, minor)`, which return the new mesh's index, `mesh_count()`,
+//!   `find(name)`, `set_name(mesh, name)`, `set_visible(mesh, visible)`,
+//!   `set_layers(mesh, layers)` and `frame_all()`.
+//!
+//! The control API and remote controllers set the same paths and call the same functions.
+
+mod bindings;
+#[cfg(feature = "scripting")]
+mod host;
+
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::{Camera, Scene, Settings};
+
+use bindings::Bindings;
+#[cfg(feature = "scripting")]
+use host::Host;
+
+pub(crate) use bindings::Value;
+
+/// Name of the function called before each frame.
+#[cfg(feature = "scripting")]
+const ON_FRAME: &str = "on_frame";
+
+/// Variable holding the timeline's position during `on_frame`.
+#[cfg(feature = "scripting")]
+const TIME: &str = "time";
+
+/// Why a script could not be loaded or failed while running.
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    /// The script is not valid, at this line.
+    Syntax { line: usize, message: String },
+    /// The script failed while running this line.
+    Runtime { line: usize, message: String },
+    /// Scripts need the viewer built with the `scripting` feature.
+    Unsupported,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Io(err) => write!(f, "{err}"),
+            ScriptError::Syntax { line, message } => write!(f, "syntax error on line {line}: {message}"),
+            ScriptError::Runtime { line, message } => write!(f, "error on line {line}: {message}"),
+            ScriptError::Unsupported => write!(f, "scripts need the viewer built with the `scripting` feature"),
+        }
+    }
+}
+
+impl Error for ScriptError {}
+
+impl From<io::Error> for ScriptError {
+    fn from(err: io::Error) -> Self {
+        ScriptError::Io(err)
+    }
+}
+
+/// A compiled script with the variables its top-level statements defined. Without the
+/// `scripting` feature none can be made.
+#[derive(Clone, Debug)]
+pub struct Script {
+    #[cfg(feature = "scripting")]
+    host: Host,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Self, ScriptError> {
+        #[cfg(feature = "scripting")]
+        return Host::parse(source).map(|host| Self { host });
+        #[cfg(not(feature = "scripting"))]
+        {
+            let _ = source;
+            Err(ScriptError::Unsupported)
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScriptError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Runs the top-level statements, which may add meshes to `scene` and change `settings`
+    /// and `camera`. Variables they define stay visible to `on_frame`; those of an earlier
+    /// run are forgotten.
+    pub fn run(&mut self, scene: &mut Scene, settings: &mut Settings, camera: &mut Camera) -> Result<(), ScriptError> {
+        #[cfg(feature = "scripting")]
+        return self.host.run(scene, settings, camera);
+        #[cfg(not(feature = "scripting"))]
+        {
+            let _ = (scene, settings, camera);
+            Ok(())
+        }
+    }
+
+    /// Whether the script defines `on_frame`.
+    pub fn has_on_frame(&self) -> bool {
+        #[cfg(feature = "scripting")]
+        return self.host.has_on_frame();
+        #[cfg(not(feature = "scripting"))]
+        false
+    }
+
+    /// Calls the script's `on_frame` with the number of the frame about to be drawn, if it
+    /// defines one, and `time` set to `time` seconds.
+    pub fn on_frame(
+        &mut self,
+        frame: u64,
+        time: f32,
+        settings: &mut Settings,
+        camera: &mut Camera,
+    ) -> Result<(), ScriptError> {
+        #[cfg(feature = "scripting")]
+        return self.host.on_frame(frame, time, settings, camera);
+        #[cfg(not(feature = "scripting"))]
+        {
+            let _ = (frame, time, settings, camera);
+            Ok(())
+        }
+    }
+}
+
+/// Value of `path`, such as `settings.explode` or `camera.fovy`, as a script reads it.
+pub(crate) fn query(path: &str, settings: &mut Settings, camera: &mut Camera) -> Option<Value> {
+    Bindings {
+        scene: None,
+        settings,
+        camera,
+    }.get(path)
+}
+
+/// Sets `path`, such as `settings.explode` or `camera.fovy`, to `args` as a script assigning it
+/// would, a single value as itself and several as an array. A `path` naming a function, such
+/// as `orbit`, calls it with `args` instead.
+pub(crate) fn control(path: &str, args: Vec<Value>, settings: &mut Settings, camera: &mut Camera) -> Result<(), String> {
+    let mut bindings = Bindings {
+        scene: None,
+        settings,
+        camera,
+    };
+    if let Some(result) = bindings.call(path, &args) {
+        return result.map(|_| ());
+    }
+    let value = match <[Value; 1]>::try_from(args) {
+        Ok([value]) => value,
+        Err(args) => Value::Array(args),
+    };
+    bindings.set(path, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn query_and_control() {
+        let (mut settings, mut camera) = (Settings::default(), Camera::default());
+        control("camera.eye", vec![Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)], &mut settings, &mut camera).unwrap();
+        control("settings.explode", vec![Value::Number(0.5)], &mut settings, &mut camera).unwrap();
+        control("orbit", vec![Value::Number(180.0)], &mut settings, &mut camera).unwrap();
+        assert!(close(camera.eye, vec3![-1.0, 2.0, -3.0]), "{:?}", camera.eye);
+        assert_eq!(query("settings.explode", &mut settings, &mut camera), Some(Value::Number(0.5)));
+        assert_eq!(query("settings.nothing", &mut settings, &mut camera), None);
+        assert!(control("mesh_count", vec![], &mut settings, &mut camera).is_err());
+    }
+
+    #[test]
+    fn bad_values_are_refused() {
+        let (mut settings, mut camera) = (Settings::default(), Camera::default());
+        let mut set = |path: &str, value: Value| control(path, vec![value], &mut settings, &mut camera).unwrap_err();
+        assert_eq!(set("camera.eye", Value::Number(1.0)), "camera.eye must be an array of three numbers");
+        assert_eq!(set("camera.fovy", Value::Text("wide".into())), "camera.fovy must be a number");
+        assert_eq!(set("settings.id_matte", Value::Number(1.0)), "settings.id_matte must be true or false");
+        assert_eq!(set("settings.sky.azimuth", Value::Number(1.0)), "settings.sky.azimuth needs settings.sky on");
+        assert_eq!(set("camera.zoom", Value::Number(2.0)), "camera.zoom can't be set");
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    #[test]
+    fn scripts_need_the_feature() {
+        assert!(matches!(Script::parse("frame_all();"), Err(ScriptError::Unsupported)));
+    }
+}
@@ -0,0 +1,224 @@
+//! What the viewer was showing when it closed, so the next launch can carry on from there,
+//! and the scenes it showed before.
+
+use std::{
+    env,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Camera, ClipPlane, Overlays, Settings};
+
+/// The viewer's scene, camera, window size and the settings worth keeping between launches,
+/// stored as JSON.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Scene path last opened.
+    pub scene: Option<PathBuf>,
+    /// Camera looking at `scene`.
+    pub camera: Option<Camera>,
+    /// Inner size of the window in physical pixels, unless it was fullscreen.
+    pub window_size: Option<[u32; 2]>,
+    pub settings: SessionSettings,
+    /// Scene paths opened, most recent first.
+    pub recent: Vec<PathBuf>,
+}
+
+/// The [`Settings`] a session keeps: how the scene is viewed rather than how it is loaded.
+/// Flags for a single run, such as layers, material overrides or NaN checks, aren't kept.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionSettings {
+    pub bg_color: [f64; 4],
+    pub interactive_quality: f32,
+    pub turntable_speed: f32,
+    pub explode: f32,
+    pub splat_scale: f32,
+    pub overlays: Overlays,
+    pub clip_planes: Vec<ClipPlane>,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self::from(&Settings::default())
+    }
+}
+
+impl From<&Settings> for SessionSettings {
+    fn from(settings: &Settings) -> Self {
+        let color = settings.bg_color;
+        Self {
+            bg_color: [color.r, color.g, color.b, color.a],
+            interactive_quality: settings.interactive_quality,
+            turntable_speed: settings.turntable_speed,
+            explode: settings.explode,
+            splat_scale: settings.splat_scale,
+            overlays: settings.overlays,
+            clip_planes: settings.clip_planes.clone(),
+        }
+    }
+}
+
+impl SessionSettings {
+    /// Sets these in `settings`, leaving the rest as they are. The clipping planes replace those
+    /// in `settings`.
+    pub fn apply(&self, settings: &mut Settings) {
+        let [r, g, b, a] = self.bg_color;
+        settings.bg_color = wgpu::Color { r, g, b, a };
+        settings.interactive_quality = self.interactive_quality.clamp(0.0, 1.0);
+        settings.turntable_speed = self.turntable_speed;
+        settings.explode = self.explode.max(0.0);
+        settings.splat_scale = self.splat_scale;
+        settings.overlays = self.overlays;
+        settings.clip_planes = self.clip_planes.clone();
+    }
+}
+
+impl Session {
+    /// Where sessions are kept by default: `ray-tracer/session.json` in the user's
+    /// configuration directory. `None` if the platform doesn't say where that is.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = if cfg!(windows) {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            env::var_os("HOME").map(|home| Path::new(&home).join("Library/Application Support"))
+        } else {
+            env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        };
+        config.map(|config| config.join("ray-tracer").join("session.json"))
+    }
+
+    /// Reads the session at `path`. A missing or unreadable session is logged and reads as
+    /// empty.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                log::warn!("Ignoring invalid session in {}: {err}", path.display());
+                Self::default()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                log::warn!("Failed to read session from {}: {err}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    /// Writes the session to `path`, creating its directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("session-{}-{name}", std::process::id())).join("session.json")
+    }
+
+    fn plane(x: f32) -> ClipPlane {
+        ClipPlane { point: vec3![x, 0.0, 0.0], normal: vec3![1.0, 0.0, 0.0] }
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        let settings = Settings {
+            explode: 0.5,
+            turntable_speed: 30.0,
+            clip_planes: vec![plane(1.0)],
+            ..Settings::default()
+        };
+        let session = Session {
+            scene: Some("scenes/box.gltf".into()),
+            camera: Some(Camera { fovy: 30.0, ..Camera::default() }),
+            window_size: Some([1280, 720]),
+            settings: SessionSettings::from(&settings),
+            recent: vec!["scenes/box.gltf".into(), "scenes/car.glb".into()],
+        };
+        // Saving creates the directory.
+        let path = temp_path("round-trip");
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded, session);
+    }
+
+    #[test]
+    fn missing_and_invalid_sessions_load_empty() {
+        let path = temp_path("invalid");
+        assert_eq!(Session::load(&path), Session::default());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{ not json").unwrap();
+        let invalid = Session::load(&path);
+        // Fields left out take their defaults.
+        fs::write(&path, r#"{ "window_size": [800, 600], "settings": { "explode": 2.0 } }"#).unwrap();
+        let partial = Session::load(&path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(invalid, Session::default());
+        assert_eq!(partial.window_size, Some([800, 600]));
+        assert_eq!(partial.settings, SessionSettings { explode: 2.0, ..SessionSettings::default() });
+    }
+
+    #[test]
+    fn flags_for_one_run_are_not_kept() {
+        let settings = Settings {
+            nan_check: true,
+            transparent_background: true,
+            material_override: Some("clay".into()),
+            layers: Some(vec!["walls".into()]),
+            ..Settings::default()
+        };
+        let json = serde_json::to_value(SessionSettings::from(&settings)).unwrap();
+        for key in ["nan_check", "transparent_background", "material_override", "layers"] {
+            assert!(json.get(key).is_none(), "{key} was saved");
+        }
+
+        // Sessions saved before they were left out don't bring them back.
+        let old = r#"{ "settings": { "nan_check": true, "material_override": "clay", "layers": ["walls"] } }"#;
+        let session: Session = serde_json::from_str(old).unwrap();
+        let mut restored = Settings::default();
+        session.settings.apply(&mut restored);
+        assert!(!restored.nan_check);
+        assert_eq!(restored.material_override, None);
+        assert_eq!(restored.layers, None);
+    }
+
+    #[test]
+    fn applying_replaces_the_kept_settings() {
+        let mut saved = Settings {
+            explode: -1.0,
+            interactive_quality: 2.0,
+            clip_planes: vec![plane(1.0)],
+            ..Settings::default()
+        };
+        saved.overlays.grid = true;
+        let session = SessionSettings::from(&saved);
+
+        let mut settings = Settings {
+            nan_check: true,
+            clip_planes: vec![plane(2.0), plane(3.0)],
+            ..Settings::default()
+        };
+        // Restoring twice, as on every launch, doesn't pile up clipping planes.
+        session.apply(&mut settings);
+        session.apply(&mut settings);
+        assert_eq!(settings.clip_planes, [plane(1.0)]);
+        assert!(settings.overlays.grid);
+        assert_eq!((settings.explode, settings.interactive_quality), (0.0, 1.0));
+        assert!(settings.nan_check);
+    }
+}
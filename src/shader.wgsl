@@ -1,9 +1,71 @@
+#include "camera.wgsl"
+
 struct VertexInput {
     @location(0) position: vec3f,
 };
 
+// A 16-bit position within the box of mesh `position.w`.
+struct QuantizedVertexInput {
+    @location(0) position: vec4u,
+};
+
+// Turns a mesh's quantized positions back into world space: `offset + position * step`.
+struct Dequantization {
+    offset: vec4f,
+    step: vec4f,
+};
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
+    @location(0) world_position: vec3f,
+    // Index of the mesh being drawn, passed as the instance index.
+    @location(1) @interpolate(flat) mesh: u32,
+};
+
+struct GroundOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) world_position: vec3f,
+};
+
+struct SplatInput {
+    @location(0) center: vec3f,
+    @location(1) radius: f32,
+    @location(2) normal: vec3f,
+    @location(3) color: vec4f,
+};
+
+struct SplatOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) world_position: vec3f,
+    // Position within the splat's disk, which has radius one.
+    @location(1) offset: vec2f,
+    @location(2) color: vec4f,
+};
+
+// See `ParticleInstance` in particles.rs.
+struct ParticleInput {
+    @location(0) position: vec3f,
+    // Zero for a dead particle, which collapses the quad.
+    @location(1) radius: f32,
+    @location(2) color: vec4f,
+    @location(3) shape: u32,
+};
+
+struct ParticleOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) world_position: vec3f,
+    // Position within the particle's disk, which has radius one.
+    @location(1) offset: vec2f,
+    @location(2) color: vec4f,
+    @location(3) @interpolate(flat) shape: u32,
+};
+
+struct ParticleShadowOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) world_position: vec3f,
+    @location(1) offset: vec2f,
+    // Opacity of the particle casting the shadow.
+    @location(2) @interpolate(flat) opacity: f32,
 };
 
 struct Material {
@@ -12,18 +74,395 @@ struct Material {
     specular: vec4f,
 };
 
+// The ground plane, see `GroundUniform` in ground.rs.
+struct Ground {
+    // Centre, with half the side in w.
+    plane: vec4f,
+    // Colour, with the shadow opacity in w.
+    color: vec4f,
+    // Direction towards the light, with w set for a shadow catcher.
+    light: vec4f,
+    background: vec4f,
+};
+
+struct Settings {
+    max_radiance: f32,
+    splat_scale: f32,
+    checker: f32,
+    extinction: vec4f,
+    inscatter: vec4f,
+    sun: vec4f,
+    ground: Ground,
+    // Unit normals with the distance from the origin in w, see clip.rs. Zero planes clip nothing.
+    clip_planes: array<vec4f, 4>,
+    // Colour of back faces seen through a cut.
+    section_caps: vec4f,
+};
+
 @group(0) @binding(0) var<storage, read> materials: array<Material>;
+#ifdef QUANTIZED
+@group(0) @binding(1) var<storage, read> dequantization: array<Dequantization>;
+#endif
+// Offset of each mesh in the exploded view, indexed by the instance index.
+@group(0) @binding(2) var<storage, read> mesh_offsets: array<vec4f>;
+@group(1) @binding(0) var<uniform> settings: Settings;
+#ifdef NAN_CHECK
+@group(1) @binding(1) var<storage, read_write> non_finite_fragments: atomic<u32>;
+#endif
+@group(2) @binding(0) var<uniform> camera: Camera;
+
+#ifdef MATERIAL_GRAPH
+#include "procedural.wgsl"
+#include "material.wgsl"
+#endif
+#ifdef CUSTOM_SHADING
+#include "custom.wgsl"
+#endif
+
+#ifdef QUANTIZED
+fn mesh_position(model: QuantizedVertexInput) -> vec3f {
+    let transform = dequantization[model.position.w];
+    return transform.offset.xyz + vec3f(model.position.xyz) * transform.step.xyz;
+}
+#else
+fn mesh_position(model: VertexInput) -> vec3f {
+    return model.position;
+}
+#endif
 
 @vertex
 fn vs_main(
+    @builtin(instance_index) instance: u32,
+#ifdef QUANTIZED
+    model: QuantizedVertexInput,
+#else
     model: VertexInput,
+#endif
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.clip_position = vec4f(model.position, 1.0);
+    out.mesh = instance;
+    let position = mesh_position(model) + mesh_offsets[instance].xyz;
+    let view_position = camera.view * vec4f(position, 1.0);
+    out.clip_position = project(view_position.xyz);
+    out.world_position = position;
+    return out;
+}
+
+// Attenuates `color` by exponential height fog between the camera and `world_position`, and
+// adds the light the fog scatters towards the camera.
+fn apply_atmosphere(color: vec3f, world_position: vec3f) -> vec3f {
+    let eye = camera_position();
+    let ray = world_position - eye;
+    let distance = length(ray);
+    let falloff = settings.extinction.w;
+
+    // Density integrated along the ray; the closed form of the exponential profile degenerates
+    // for horizontal rays, where the density is constant.
+    let rise = falloff * ray.y;
+    var optical_depth = exp(-falloff * eye.y) * distance;
+    if abs(rise) > 1e-4 {
+        optical_depth *= (1.0 - exp(-rise)) / rise;
+    }
+    let transmittance = exp(-settings.extinction.rgb * optical_depth);
+
+    let toward_sun = max(dot(ray / max(distance, 1e-6), settings.sun.xyz), 0.0);
+    let glow = 1.0 + settings.inscatter.w * pow(toward_sun, 8.0);
+    return color * transmittance + settings.inscatter.rgb * glow * (1.0 - transmittance);
+}
+
+// Stops around middle grey and their colours, matching `FALSE_COLOR_STOPS` in analysis.rs.
+const FALSE_COLOR_STOPS = array<vec4f, 5>(
+    vec4f(0.0, 0.0, 1.0, -4.0),
+    vec4f(0.0, 1.0, 1.0, -2.0),
+    vec4f(0.0, 1.0, 0.0, 0.0),
+    vec4f(1.0, 1.0, 0.0, 2.0),
+    vec4f(1.0, 0.0, 0.0, 4.0),
+);
+
+fn false_color(color: vec3f) -> vec3f {
+    let luminance = dot(color, vec3f(0.2126, 0.7152, 0.0722));
+    let stops = log2(max(luminance, 1e-6) / 0.18);
+    if stops < FALSE_COLOR_STOPS[0].w - 1.0 {
+        return vec3f(0.0);
+    }
+    if stops > FALSE_COLOR_STOPS[4].w + 1.0 {
+        return vec3f(1.0);
+    }
+    var ramp = FALSE_COLOR_STOPS;
+    var out = ramp[0].rgb;
+    for (var i = 1; i < 5; i++) {
+        let t = (stops - ramp[i - 1].w) / (ramp[i].w - ramp[i - 1].w);
+        out = mix(out, ramp[i].rgb, clamp(t, 0.0, 1.0));
+    }
     return out;
 }
 
+fn is_finite(color: vec3f) -> bool {
+    // Compares exponent bits, since `x != x` may be optimized away.
+    let exponent = bitcast<vec3u>(color) & vec3u(0x7f800000u);
+    return all(exponent != vec3u(0x7f800000u));
+}
+
+// Final colour of a surface: clamped radiance, or the selected analysis view of it. With
+// NAN_CHECK, NaN and infinite colours are counted and drawn magenta.
+fn output_color(shaded: vec3f) -> vec3f {
+#ifdef NAN_CHECK
+    if !is_finite(shaded) {
+        atomicAdd(&non_finite_fragments, 1u);
+        return vec3f(1.0, 0.0, 1.0);
+    }
+#endif
+    let clamped = min(shaded, vec3f(settings.max_radiance));
+#ifdef FALSE_COLOR
+    return false_color(clamped);
+#else
+    return clamped;
+#endif
+}
+
+fn clipped(world_position: vec3f) -> bool {
+    for (var i = 0; i < 4; i++) {
+        let plane = settings.clip_planes[i];
+        if dot(plane.xyz, world_position) > plane.w {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Spreads mesh indices over distinct colours (PCG hash).
+fn id_color(id: u32) -> vec3f {
+    var hash = id * 747796405u + 2891336453u;
+    hash = ((hash >> ((hash >> 28u) + 4u)) ^ hash) * 277803737u;
+    hash = (hash >> 22u) ^ hash;
+    return vec3f(vec3u(hash, hash >> 8u, hash >> 16u) & vec3u(255u)) / 255.0;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    return materials[0].ambient;
+    if clipped(in.world_position) {
+        discard;
+    }
+#ifdef ID_MATTE
+    return vec4f(id_color(in.mesh), 1.0);
+#else
+    var color = materials[0].ambient;
+#ifdef MATERIAL_GRAPH
+    color = material_graph(color, in.world_position);
+#endif
+#ifdef CHECKER
+    let cell = vec3i(floor(in.world_position * settings.checker));
+    if ((cell.x + cell.y + cell.z) & 1) != 0 {
+        color = vec4f(color.rgb * 0.5, color.a);
+    }
+#endif
+#ifdef CUSTOM_SHADING
+    color = custom_shade(color, in.world_position);
+#endif
+    let shaded = apply_atmosphere(color.rgb, in.world_position);
+    return vec4f(output_color(shaded), color.a);
+#endif
+}
+
+// Back faces are drawn in the cap colour before the meshes, whose front faces cover them except
+// where a plane cuts them away.
+@fragment
+fn fs_section(in: VertexOutput) -> @location(0) vec4f {
+    if clipped(in.world_position) {
+        discard;
+    }
+    let shaded = apply_atmosphere(settings.section_caps.rgb, in.world_position);
+    return vec4f(output_color(shaded), 1.0);
+}
+
+@vertex
+fn vs_ray(model: VertexInput) -> @builtin(position) vec4f {
+    let view_position = camera.view * vec4f(model.position, 1.0);
+    return project(view_position.xyz);
+}
+
+@fragment
+fn fs_ray() -> @location(0) vec4f {
+    return vec4f(1.0, 0.0, 1.0, 1.0);
+}
+
+@vertex
+fn vs_splat(
+    @builtin(vertex_index) index: u32,
+    splat: SplatInput,
+) -> SplatOutput {
+    var corners = array<vec2f, 6>(
+        vec2f(-1.0, -1.0), vec2f(1.0, -1.0), vec2f(1.0, 1.0),
+        vec2f(-1.0, -1.0), vec2f(1.0, 1.0), vec2f(-1.0, 1.0),
+    );
+    let corner = corners[index];
+    let radius = splat.radius * settings.splat_scale;
+    var out: SplatOutput;
+    var view_position: vec3f;
+    if dot(splat.normal, splat.normal) > 0.0 {
+        // Surfel: a disk in the plane of the point's normal.
+        let normal = normalize(splat.normal);
+        let helper = select(vec3f(0.0, 1.0, 0.0), vec3f(1.0, 0.0, 0.0), abs(normal.x) < 0.9);
+        let tangent = normalize(cross(normal, helper));
+        let bitangent = cross(normal, tangent);
+        out.world_position = splat.center + (tangent * corner.x + bitangent * corner.y) * radius;
+        view_position = (camera.view * vec4f(out.world_position, 1.0)).xyz;
+    } else {
+        // A disk facing the camera.
+        view_position = (camera.view * vec4f(splat.center, 1.0)).xyz + vec3f(corner * radius, 0.0);
+        out.world_position = splat.center;
+    }
+    out.clip_position = project(view_position);
+    out.offset = corner;
+    out.color = splat.color;
+    return out;
+}
+
+@fragment
+fn fs_splat(in: SplatOutput) -> @location(0) vec4f {
+    if dot(in.offset, in.offset) > 1.0 || clipped(in.world_position) {
+        discard;
+    }
+    var color = in.color;
+#ifdef MATERIAL_GRAPH
+    color = material_graph(color, in.world_position);
+#endif
+#ifdef CUSTOM_SHADING
+    color = custom_shade(color, in.world_position);
+#endif
+    let shaded = apply_atmosphere(color.rgb, in.world_position);
+    return vec4f(output_color(shaded), color.a);
+}
+
+@vertex
+fn vs_ground(@builtin(vertex_index) index: u32) -> GroundOutput {
+    var corners = array<vec2f, 6>(
+        vec2f(-1.0, -1.0), vec2f(-1.0, 1.0), vec2f(1.0, 1.0),
+        vec2f(-1.0, -1.0), vec2f(1.0, 1.0), vec2f(1.0, -1.0),
+    );
+    let plane = settings.ground.plane;
+    var out: GroundOutput;
+    out.world_position = vec3f(plane.xz + corners[index] * plane.w, plane.y).xzy;
+    out.clip_position = project((camera.view * vec4f(out.world_position, 1.0)).xyz);
+    return out;
+}
+
+@fragment
+fn fs_ground(in: GroundOutput) -> @location(0) vec4f {
+    let shaded = apply_atmosphere(settings.ground.color.rgb, in.world_position);
+    return vec4f(output_color(shaded), 1.0);
+}
+
+// Flattens a mesh vertex onto the ground plane along the light.
+@vertex
+fn vs_shadow(
+    @builtin(instance_index) instance: u32,
+#ifdef QUANTIZED
+    model: QuantizedVertexInput,
+#else
+    model: VertexInput,
+#endif
+) -> GroundOutput {
+    let ground = settings.ground;
+    let position = mesh_position(model) + mesh_offsets[instance].xyz;
+    // Parts of meshes below the plane are dropped onto it in place.
+    let height = max(position.y - ground.plane.y, 0.0);
+    var out: GroundOutput;
+    out.world_position = position - ground.light.xyz * (height / ground.light.y);
+    out.world_position.y = ground.plane.y;
+    out.clip_position = project((camera.view * vec4f(out.world_position, 1.0)).xyz);
+    return out;
+}
+
+// Shadows are drawn with a minimum blend on colour and a maximum on alpha, so the colour
+// returned here is what any amount of overlapping shadow leaves.
+@fragment
+fn fs_shadow(in: GroundOutput) -> @location(0) vec4f {
+    return shadow(in.world_position, 1.0);
+}
+
+// Ground at `world_position` darkened by `coverage` of a full shadow.
+fn shadow(world_position: vec3f, coverage: f32) -> vec4f {
+    let ground = settings.ground;
+    if any(abs(world_position.xz - ground.plane.xz) > vec2f(ground.plane.w)) {
+        discard;
+    }
+    let opacity = ground.color.a * coverage;
+    if ground.light.w > 0.0 {
+        return vec4f(ground.background.rgb * (1.0 - opacity), opacity);
+    }
+    let shaded = apply_atmosphere(ground.color.rgb * (1.0 - opacity), world_position);
+    return vec4f(output_color(shaded), 1.0);
+}
+
+const PARTICLE_CORNERS = array<vec2f, 6>(
+    vec2f(-1.0, -1.0), vec2f(1.0, -1.0), vec2f(1.0, 1.0),
+    vec2f(-1.0, -1.0), vec2f(1.0, 1.0), vec2f(-1.0, 1.0),
+);
+
+const PARTICLE_SPHERE: u32 = 1u;
+
+// A quad facing the camera, drawn as a soft disk or a shaded ball by `fs_particle`.
+@vertex
+fn vs_particle(
+    @builtin(vertex_index) index: u32,
+    particle: ParticleInput,
+) -> ParticleOutput {
+    var corners = PARTICLE_CORNERS;
+    let corner = corners[index];
+    let view_position = (camera.view * vec4f(particle.position, 1.0)).xyz + vec3f(corner * particle.radius, 0.0);
+    var out: ParticleOutput;
+    out.clip_position = project(view_position);
+    out.world_position = particle.position;
+    out.offset = corner;
+    out.color = particle.color;
+    out.shape = particle.shape;
+    return out;
+}
+
+@fragment
+fn fs_particle(in: ParticleOutput) -> @location(0) vec4f {
+    let distance2 = dot(in.offset, in.offset);
+    if distance2 > 1.0 || clipped(in.world_position) {
+        discard;
+    }
+    var color = in.color;
+    if in.shape == PARTICLE_SPHERE {
+        // Lit from the ground's light, which casts the particles' shadows.
+        let normal = camera_rotation() * vec3f(in.offset, sqrt(1.0 - distance2));
+        let diffuse = max(dot(normal, settings.ground.light.xyz), 0.0);
+        color = vec4f(color.rgb * (0.3 + 0.7 * diffuse), color.a);
+    } else {
+        color.a *= 1.0 - distance2;
+    }
+    let shaded = apply_atmosphere(color.rgb, in.world_position);
+    return vec4f(output_color(shaded), color.a);
+}
+
+// A particle's shadow: a disk of its radius flattened onto the ground plane along the light.
+@vertex
+fn vs_particle_shadow(
+    @builtin(vertex_index) index: u32,
+    particle: ParticleInput,
+) -> ParticleShadowOutput {
+    var corners = PARTICLE_CORNERS;
+    let corner = corners[index];
+    let ground = settings.ground;
+    let height = max(particle.position.y - ground.plane.y, 0.0);
+    var out: ParticleShadowOutput;
+    out.world_position = particle.position - ground.light.xyz * (height / ground.light.y);
+    out.world_position.y = ground.plane.y;
+    out.world_position += vec3f(corner.x, 0.0, corner.y) * particle.radius;
+    out.clip_position = project((camera.view * vec4f(out.world_position, 1.0)).xyz);
+    out.offset = corner;
+    out.opacity = particle.color.a;
+    return out;
+}
+
+@fragment
+fn fs_particle_shadow(in: ParticleShadowOutput) -> @location(0) vec4f {
+    if dot(in.offset, in.offset) > 1.0 {
+        discard;
+    }
+    return shadow(in.world_position, in.opacity);
 }
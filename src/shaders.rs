@@ -0,0 +1,192 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use wgpu::{
+    Device,
+    ShaderModule,
+    ShaderModuleDescriptor,
+    ShaderSource,
+};
+
+/// Modules shaders can pull in with `#include "name"`, embedded at compile time.
+const MODULES: &[(&str, &str)] = &[
+    ("camera.wgsl", include_str!("shaders/camera.wgsl")),
+    ("fullscreen.wgsl", include_str!("shaders/fullscreen.wgsl")),
+    ("procedural.wgsl", include_str!("shaders/procedural.wgsl")),
+];
+
+/// Preprocesses `source` with `defines` and creates a shader module from it.
+///
+/// `modules` can be included alongside the embedded [`MODULES`]. Panics on preprocessor errors,
+/// which are mistakes in the embedded shaders; code from the scene file goes through
+/// [`try_create_module`] instead.
+pub(crate) fn create_module(
+    device: &Device,
+    label: &str,
+    source: &str,
+    defines: &[&str],
+    modules: &[(&str, &str)],
+) -> ShaderModule {
+    try_create_module(device, label, source, defines, modules).unwrap_or_else(|err| panic!("{label}: {err}"))
+}
+
+/// Creates a shader module as [`create_module`] does, or returns the preprocessor error, e.g. of
+/// a module from the scene file. WGSL errors are reported to the device's error scopes as usual.
+pub(crate) fn try_create_module(
+    device: &Device,
+    label: &str,
+    source: &str,
+    defines: &[&str],
+    modules: &[(&str, &str)],
+) -> Result<ShaderModule, String> {
+    let source = preprocess(source, defines, modules)?;
+    Ok(device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(label),
+        source: ShaderSource::Wgsl(Cow::Owned(source)),
+    }))
+}
+
+/// Expands WGSL preprocessor directives, each on a line of its own:
+///
+/// - `#include "name"` inserts one of the [`MODULES`] or `modules`, once per shader however often
+///   it is included.
+/// - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep or drop lines depending on whether
+///   `NAME` is one of `defines`. They can be nested.
+pub(crate) fn preprocess(source: &str, defines: &[&str], modules: &[(&str, &str)]) -> Result<String, String> {
+    let mut out = String::with_capacity(source.len());
+    let mut included = HashSet::new();
+    expand(source, defines, modules, &mut included, &mut out)?;
+    Ok(out)
+}
+
+fn expand<'a>(
+    source: &str,
+    defines: &[&str],
+    modules: &[(&'a str, &'a str)],
+    included: &mut HashSet<&'a str>,
+    out: &mut String,
+) -> Result<(), String> {
+    // Whether each enclosing conditional block is active.
+    let mut active: Vec<bool> = vec![];
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let trimmed = line.trim();
+        let enabled = active.iter().all(|&active| active);
+        if let Some(name) = trimmed.strip_prefix("#ifdef ") {
+            active.push(defines.contains(&name.trim()));
+        } else if let Some(name) = trimmed.strip_prefix("#ifndef ") {
+            active.push(!defines.contains(&name.trim()));
+        } else if trimmed == "#else" {
+            let last = active.last_mut().ok_or_else(|| format!("line {number}: #else without #ifdef"))?;
+            *last = !*last;
+        } else if trimmed == "#endif" {
+            active.pop().ok_or_else(|| format!("line {number}: #endif without #ifdef"))?;
+        } else if let Some(name) = trimmed.strip_prefix("#include ") {
+            if !enabled {
+                continue;
+            }
+            let name = name.trim().trim_matches('"');
+            let &(name, module) = MODULES.iter()
+                .chain(modules)
+                .find(|(module, _)| *module == name)
+                .ok_or_else(|| format!("line {number}: unknown module {name:?}"))?;
+            if included.insert(name) {
+                expand(module, defines, modules, included, out).map_err(|err| format!("{name}: {err}"))?;
+            }
+        } else if trimmed.starts_with('#') {
+            return Err(format!("line {number}: unknown directive {trimmed:?}"));
+        } else if enabled {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    if active.is_empty() {
+        Ok(())
+    } else {
+        Err("unterminated #ifdef".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_in_scene_modules_are_returned() {
+        let source = "#ifdef CUSTOM_SHADING\n#include \"custom.wgsl\"\n#endif\n";
+        for (custom, expected) in [
+            ("#include \"missing.wgsl\"", "custom.wgsl: line 1: unknown module \"missing.wgsl\""),
+            ("#ifdef A\nfn f() {}", "custom.wgsl: unterminated #ifdef"),
+            ("#pragma once", "custom.wgsl: line 1: unknown directive \"#pragma once\""),
+        ] {
+            let err = preprocess(source, &["CUSTOM_SHADING"], &[("custom.wgsl", custom)]).unwrap_err();
+            assert_eq!(err, expected);
+        }
+        // Without the define the broken module isn't read at all.
+        assert_eq!(preprocess(source, &[], &[("custom.wgsl", "#include \"missing.wgsl\"")]), Ok(String::new()));
+    }
+
+    #[test]
+    fn modules_are_included_once() {
+        let modules = [("a.wgsl", "#include \"b.wgsl\"\nfn a() {}"), ("b.wgsl", "fn b() {}")];
+        let source = "#include \"a.wgsl\"\n#include \"b.wgsl\"\n  #include \"a.wgsl\"\nfn main() {}";
+        assert_eq!(preprocess(source, &[], &modules).unwrap(), "fn b() {}\nfn a() {}\nfn main() {}\n");
+    }
+
+    #[test]
+    fn modules_including_each_other_stop() {
+        let modules = [("a.wgsl", "#include \"b.wgsl\"\nfn a() {}"), ("b.wgsl", "#include \"a.wgsl\"\nfn b() {}")];
+        assert_eq!(preprocess("#include \"a.wgsl\"", &[], &modules).unwrap(), "fn b() {}\nfn a() {}\n");
+    }
+
+    #[test]
+    fn embedded_modules_can_be_included() {
+        let out = preprocess("#include \"camera.wgsl\"", &[], &[]).unwrap();
+        assert_eq!(out, preprocess(MODULES[0].1, &[], &[]).unwrap());
+    }
+
+    #[test]
+    fn conditionals_nest() {
+        let source = "\
+#ifdef A
+a
+#ifndef B
+a not b
+#else
+a and b
+#endif
+#else
+not a
+#ifdef B
+not a but b
+#endif
+#endif
+always";
+        let lines = |defines: &[&str]| preprocess(source, defines, &[]).unwrap();
+        assert_eq!(lines(&[]), "not a\nalways\n");
+        assert_eq!(lines(&["A"]), "a\na not b\nalways\n");
+        assert_eq!(lines(&["A", "B"]), "a\na and b\nalways\n");
+        assert_eq!(lines(&["B"]), "not a\nnot a but b\nalways\n");
+    }
+
+    #[test]
+    fn includes_in_dropped_blocks_are_skipped() {
+        let source = "#ifdef A\n#include \"missing.wgsl\"\n#endif\nfn f() {}";
+        assert_eq!(preprocess(source, &[], &[]).unwrap(), "fn f() {}\n");
+        assert_eq!(preprocess(source, &["A"], &[]).unwrap_err(), "line 2: unknown module \"missing.wgsl\"");
+    }
+
+    #[test]
+    fn unbalanced_directives_are_errors() {
+        assert_eq!(preprocess("fn f() {}\n#else", &[], &[]).unwrap_err(), "line 2: #else without #ifdef");
+        assert_eq!(preprocess("#endif", &[], &[]).unwrap_err(), "line 1: #endif without #ifdef");
+        assert_eq!(preprocess("#ifdef A\n#ifdef B\n#endif", &[], &[]).unwrap_err(), "unterminated #ifdef");
+        assert_eq!(preprocess("#define A", &[], &[]).unwrap_err(), "line 1: unknown directive \"#define A\"");
+    }
+
+    #[test]
+    fn embedded_modules_preprocess() {
+        for (name, module) in MODULES {
+            assert!(preprocess(module, &[], &[]).is_ok(), "{name}");
+        }
+    }
+}
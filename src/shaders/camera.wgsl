@@ -0,0 +1,77 @@
+// Camera uniform and projections shared by every pass. The including shader declares the
+// `camera` binding, since each pass binds it in a different group.
+
+struct Camera {
+    view: mat4x4f,
+    proj: mat4x4f,
+    projection: u32,
+    fov: f32,
+    aspect: f32,
+    znear: f32,
+    zfar: f32,
+};
+
+const PI: f32 = 3.14159265358979;
+
+const PROJECTION_ORTHOGRAPHIC: u32 = 1u;
+const PROJECTION_FISHEYE: u32 = 2u;
+const PROJECTION_EQUIRECTANGULAR: u32 = 3u;
+
+// Rotation from view space to world space.
+fn camera_rotation() -> mat3x3f {
+    return transpose(mat3x3f(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz));
+}
+
+fn camera_position() -> vec3f {
+    return -(camera_rotation() * camera.view[3].xyz);
+}
+
+// Projects a view-space position. Perspective and orthographic cameras use the projection
+// matrix; the spherical projections are evaluated per vertex, so edges between vertices stay
+// straight and long triangles should be tessellated.
+fn project(view_position: vec3f) -> vec4f {
+    let distance = length(view_position);
+    let direction = view_position / max(distance, 1e-6);
+    let depth = (distance - camera.znear) / (camera.zfar - camera.znear);
+    switch camera.projection {
+        case PROJECTION_FISHEYE: {
+            // Equidistant fisheye: radius grows linearly with the angle off the view axis.
+            let theta = acos(clamp(-direction.z, -1.0, 1.0));
+            let radius = theta / (camera.fov * 0.5);
+            let radial = direction.xy / max(length(direction.xy), 1e-6);
+            return vec4f(radial.x * radius / camera.aspect, radial.y * radius, depth, 1.0);
+        }
+        case PROJECTION_EQUIRECTANGULAR: {
+            let longitude = atan2(direction.x, -direction.z);
+            let latitude = asin(clamp(direction.y, -1.0, 1.0));
+            return vec4f(longitude / PI, latitude / (PI * 0.5), depth, 1.0);
+        }
+        default: {
+            return camera.proj * vec4f(view_position, 1.0);
+        }
+    }
+}
+
+// Inverts the camera projection for a point on the viewport, giving a view-space direction.
+fn view_direction(ndc: vec2f) -> vec3f {
+    switch camera.projection {
+        case PROJECTION_ORTHOGRAPHIC: {
+            return vec3f(0.0, 0.0, -1.0);
+        }
+        case PROJECTION_FISHEYE: {
+            let offset = ndc * vec2f(camera.aspect, 1.0);
+            let theta = min(length(offset) * camera.fov * 0.5, PI);
+            let radial = offset / max(length(offset), 1e-6);
+            return vec3f(radial * sin(theta), -cos(theta));
+        }
+        case PROJECTION_EQUIRECTANGULAR: {
+            let longitude = ndc.x * PI;
+            let latitude = ndc.y * PI * 0.5;
+            return vec3f(cos(latitude) * sin(longitude), sin(latitude), -cos(latitude) * cos(longitude));
+        }
+        default: {
+            let scale = tan(camera.fov * 0.5);
+            return normalize(vec3f(ndc.x * scale * camera.aspect, ndc.y * scale, -1.0));
+        }
+    }
+}
@@ -0,0 +1,15 @@
+// Fullscreen triangle for passes that shade every pixel of the viewport.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) ndc: vec2f,
+};
+
+// Covers the viewport with one triangle generated from the vertex index, at clip depth `depth`.
+fn fullscreen_triangle(index: u32, depth: f32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    out.ndc = uv * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0);
+    out.clip_position = vec4f(out.ndc, depth, 1.0);
+    return out;
+}
@@ -0,0 +1,56 @@
+// Building blocks of the procedural textures in `procedural.rs`, all evaluated in world space.
+
+fn hash3(p: vec3i) -> f32 {
+    var hash = bitcast<u32>(p.x) * 73856093u ^ bitcast<u32>(p.y) * 19349663u ^ bitcast<u32>(p.z) * 83492791u;
+    hash = (hash ^ (hash >> 16u)) * 2246822519u;
+    hash = hash ^ (hash >> 13u);
+    return f32(hash & 0xffffffu) / f32(0xffffff);
+}
+
+fn checker(p: vec3f) -> f32 {
+    let cell = vec3i(floor(p));
+    return f32((cell.x + cell.y + cell.z) & 1);
+}
+
+// Trilinearly interpolated value noise in [0, 1].
+fn value_noise(p: vec3f) -> f32 {
+    let cell = vec3i(floor(p));
+    let t = fract(p);
+    let s = t * t * (3.0 - 2.0 * t);
+    let x00 = mix(hash3(cell), hash3(cell + vec3i(1, 0, 0)), s.x);
+    let x10 = mix(hash3(cell + vec3i(0, 1, 0)), hash3(cell + vec3i(1, 1, 0)), s.x);
+    let x01 = mix(hash3(cell + vec3i(0, 0, 1)), hash3(cell + vec3i(1, 0, 1)), s.x);
+    let x11 = mix(hash3(cell + vec3i(0, 1, 1)), hash3(cell + vec3i(1, 1, 1)), s.x);
+    return mix(mix(x00, x10, s.y), mix(x01, x11, s.y), s.z);
+}
+
+// Octaves of value noise, each at twice the frequency and half the amplitude of the last.
+fn fbm(p: vec3f, octaves: u32) -> f32 {
+    var sum = 0.0;
+    var amplitude = 0.5;
+    var total = 0.0;
+    var q = p;
+    for (var octave = 0u; octave < octaves; octave++) {
+        sum += amplitude * value_noise(q);
+        total += amplitude;
+        amplitude *= 0.5;
+        q *= 2.0;
+    }
+    return sum / max(total, 1e-6);
+}
+
+// Distance to the nearest of one jittered point per cell, roughly in [0, 1].
+fn voronoi(p: vec3f) -> f32 {
+    let cell = vec3i(floor(p));
+    var nearest = 1e9;
+    for (var z = -1; z <= 1; z++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let neighbour = cell + vec3i(x, y, z);
+                let jitter = vec3f(hash3(neighbour), hash3(neighbour + vec3i(17, 0, 0)), hash3(neighbour + vec3i(0, 31, 0)));
+                nearest = min(nearest, distance(p, vec3f(neighbour) + jitter));
+            }
+        }
+    }
+    return min(nearest, 1.0);
+}
@@ -0,0 +1,244 @@
+use std::f32::consts::PI;
+
+use bytemuck::{Pod, Zeroable};
+
+use wgpu::{
+    util::{
+        BufferInitDescriptor,
+        DeviceExt,
+    },
+    BindGroup,
+    BindGroupDescriptor,
+    BindGroupEntry,
+    BindGroupLayout,
+    BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry,
+    BindingType,
+    Buffer,
+    BufferBindingType,
+    BufferUsages,
+    ColorTargetState,
+    Device,
+    FragmentState,
+    MultisampleState,
+    PipelineCompilationOptions,
+    PipelineLayoutDescriptor,
+    PrimitiveState,
+    RenderPass,
+    RenderPipeline,
+    RenderPipelineDescriptor,
+    ShaderStages,
+    TextureFormat,
+    VertexState,
+};
+
+use crate::{
+    memory::MemoryTracker,
+    shaders,
+    stereo::Channels,
+};
+
+/// A Preetham daylight sky drawn behind the scene in place of the background colour.
+///
+/// Azimuth is measured in degrees clockwise from north, which is the scene's -Z axis, so east
+/// is +X. Elevation is in degrees above the horizon.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sky {
+    /// Haziness of the atmosphere, from about 2 (clear) to 10 (hazy).
+    pub turbidity: f32,
+    pub azimuth: f32,
+    pub elevation: f32,
+    /// Scale from sky luminance in kcd/m² to output radiance.
+    pub exposure: f32,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            turbidity: 3.0,
+            azimuth: 135.0,
+            elevation: 35.0,
+            exposure: 0.08,
+        }
+    }
+}
+
+impl Sky {
+    /// Places the sun for an observer at `latitude` and `longitude` (degrees, north and east
+    /// positive) on `day` of the year at `hour` UTC.
+    ///
+    /// Uses the low-precision solar position, ignoring the equation of time, which is within
+    /// about four degrees.
+    pub fn at_location(latitude: f32, longitude: f32, day: u32, hour: f32) -> Self {
+        let latitude = latitude.to_radians();
+        let declination = (-23.44f32).to_radians() * (2.0 * PI / 365.0 * (day as f32 + 10.0)).cos();
+        let solar_time = hour + longitude / 15.0;
+        let hour_angle = (15.0 * (solar_time - 12.0)).to_radians();
+        let elevation = (latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos())
+            .clamp(-1.0, 1.0)
+            .asin();
+        let azimuth = (-hour_angle.sin() * declination.cos()).atan2(
+            declination.sin() * latitude.cos() - declination.cos() * hour_angle.cos() * latitude.sin(),
+        );
+        Self {
+            azimuth: azimuth.to_degrees().rem_euclid(360.0),
+            elevation: elevation.to_degrees(),
+            ..Self::default()
+        }
+    }
+
+    /// Unit vector pointing towards the sun.
+    pub(crate) fn sun_direction(&self) -> [f32; 3] {
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        [
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub(crate) struct SkyUniform {
+    /// Perez coefficients A through E, each holding the Y, x and y channels.
+    perez: [[f32; 4]; 5],
+    /// Zenith Y, x and y divided by the Perez function at the zenith.
+    zenith: [f32; 4],
+    /// Direction towards the sun, with the exposure in w.
+    sun: [f32; 4],
+}
+
+impl From<&Sky> for SkyUniform {
+    fn from(sky: &Sky) -> Self {
+        let t = sky.turbidity;
+        let perez = [
+            [0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608, 0.0],
+            [-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092, 0.0],
+            [-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102, 0.0],
+            [0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537, 0.0],
+            [-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529, 0.0],
+        ];
+
+        // Sun zenith angle, kept above the horizon where the fit is valid.
+        let theta = (90.0 - sky.elevation.max(1.0)).to_radians();
+        let (theta2, theta3) = (theta * theta, theta * theta * theta);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+        let mut zenith = [luminance.max(0.0), x, y, 0.0];
+        for (channel, value) in zenith.iter_mut().take(3).enumerate() {
+            let [a, b, c, d, e] = perez.map(|coefficient| coefficient[channel]);
+            *value /= (1.0 + a * b.exp()) * (1.0 + c * (d * theta).exp() + e * theta.cos().powi(2));
+        }
+
+        let [sun_x, sun_y, sun_z] = sky.sun_direction();
+        Self {
+            perez,
+            zenith,
+            sun: [sun_x, sun_y, sun_z, sky.exposure],
+        }
+    }
+}
+
+/// Draws the sky as a fullscreen triangle at the start of each pass.
+pub(crate) struct SkyPass {
+    /// One pipeline per [`Channels`] variant.
+    pipelines: [RenderPipeline; 3],
+    pub(crate) buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl SkyPass {
+    pub(crate) fn new(
+        device: &Device,
+        format: TextureFormat,
+        camera_bind_group_layout: &BindGroupLayout,
+        memory: &mut MemoryTracker,
+    ) -> Self {
+        let shader = shaders::create_module(device, "Sky shader", include_str!("sky.wgsl"), &[], &[]);
+
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Sky buffer"),
+            contents: bytemuck::cast_slice(&[SkyUniform::from(&Sky::default())]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        memory.track(&buffer);
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("sky_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("sky_bind_group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = Channels::ALL.map(|channels| device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: channels.write_mask(),
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        }));
+
+        Self {
+            pipelines,
+            buffer,
+            bind_group,
+        }
+    }
+
+    /// Fills the current viewport with the sky seen from the camera bound at `camera_bind_group`.
+    pub(crate) fn draw(&self, render_pass: &mut RenderPass, camera_bind_group: &BindGroup, channels: Channels) {
+        render_pass.set_pipeline(&self.pipelines[channels as usize]);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,63 @@
+#include "camera.wgsl"
+#include "fullscreen.wgsl"
+
+struct Sky {
+    perez: array<vec4f, 5>,
+    zenith: vec4f,
+    sun: vec4f,
+};
+
+// Cosine of the sun's angular radius.
+const SUN_COS_RADIUS: f32 = 0.99999;
+const SUN_INTENSITY: f32 = 20.0;
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> sky: Sky;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+) -> VertexOutput {
+    return fullscreen_triangle(index, 1.0);
+}
+
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3f {
+    let a = sky.perez[0].xyz;
+    let b = sky.perez[1].xyz;
+    let c = sky.perez[2].xyz;
+    let d = sky.perez[3].xyz;
+    let e = sky.perez[4].xyz;
+    return (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn xyy_to_rgb(xyy: vec3f) -> vec3f {
+    let luminance = xyy.x;
+    let xyz = vec3f(
+        xyy.y / xyy.z * luminance,
+        luminance,
+        (1.0 - xyy.y - xyy.z) / xyy.z * luminance,
+    );
+    return vec3f(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let direction = camera_rotation() * view_direction(in.ndc);
+
+    // The model is only defined above the horizon; the ground reflects a darkened horizon.
+    let cos_theta = max(abs(direction.y), 0.01);
+    let cos_gamma = clamp(dot(direction, sky.sun.xyz), -1.0, 1.0);
+    let gamma = acos(cos_gamma);
+    let xyy = sky.zenith.xyz * perez(cos_theta, gamma, cos_gamma);
+    var color = max(xyy_to_rgb(xyy), vec3f(0.0)) * sky.sun.w;
+    if direction.y < 0.0 {
+        color *= 0.3;
+    } else if cos_gamma > SUN_COS_RADIUS {
+        color += vec3f(SUN_INTENSITY);
+    }
+    return vec4f(color, 1.0);
+}
@@ -0,0 +1,193 @@
+//! Moves the viewer's camera with a 3Dconnexion SpaceMouse or other 3D mouse, the way CAD
+//! tools do: twisting and tilting the cap turns the scene around the camera's target, sliding
+//! it pans and pushing or pulling it zooms.
+//!
+//! Devices are read as raw HID reports with `hidapi`, which on Linux needs the user to have
+//! read access to their `/dev/hidraw*` nodes, e.g. through a udev rule. Devices plugged in
+//! while the viewer runs are picked up.
+
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    ops::RangeInclusive,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use hidapi::{HidApi, HidDevice};
+
+use crate::Camera;
+
+/// Time between looks for newly plugged in devices.
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Longest step a frame moves the camera by, so a stalled frame doesn't jump it.
+const MAX_STEP: Duration = Duration::from_millis(100);
+
+/// Reading of an axis pushed all the way.
+const FULL_SCALE: f32 = 350.0;
+
+/// Fraction of an axis' travel ignored, so resting a hand on the cap doesn't drift the view.
+const DEAD_ZONE: f32 = 0.05;
+
+/// Degrees per second the scene turns with the cap fully twisted or tilted.
+const ROTATE_SPEED: f32 = 90.0;
+
+/// View heights per second the camera pans with the cap fully slid.
+const PAN_SPEED: f32 = 1.0;
+
+/// Times per second the distance to the target shrinks with the cap fully pushed.
+const ZOOM_SPEED: f32 = 4.0;
+
+/// USB vendor ids of 3Dconnexion devices: Logitech's for older ones, then their own.
+const VENDORS: [u16; 2] = [0x046d, 0x256f];
+
+/// Products of 3Dconnexion under Logitech's vendor id.
+const LOGITECH_PRODUCTS: RangeInclusive<u16> = 0xc603..=0xc64f;
+
+// Report ids.
+const TRANSLATION: u8 = 1;
+const ROTATION: u8 = 2;
+
+enum Event {
+    /// New translation and rotation readings, each x, y and z from -1 to 1.
+    Motion {
+        translation: Option<[f32; 3]>,
+        rotation: Option<[f32; 3]>,
+    },
+    /// The device at this path was unplugged.
+    Closed(CString),
+}
+
+/// 3D mice being read, with their last readings.
+pub(crate) struct SpaceMice {
+    api: HidApi,
+    events: Receiver<Event>,
+    sender: Sender<Event>,
+    /// Names of the devices being read, by their paths.
+    open: HashMap<CString, String>,
+    translation: [f32; 3],
+    rotation: [f32; 3],
+    last_scan: Option<Instant>,
+    last_step: Instant,
+}
+
+impl SpaceMice {
+    /// Starts looking for 3D mice, or returns `None` where `hidapi` can't.
+    pub(crate) fn new() -> Option<Self> {
+        let api = HidApi::new().map_err(|err| log::warn!("Can't read 3D mice: {err}")).ok()?;
+        let (sender, events) = mpsc::channel();
+        Some(Self {
+            api,
+            events,
+            sender,
+            open: HashMap::new(),
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            last_scan: None,
+            last_step: Instant::now(),
+        })
+    }
+
+    /// Opens devices plugged in since the last look, each read on its own thread.
+    fn scan(&mut self) {
+        if let Err(err) = self.api.refresh_devices() {
+            log::warn!("Can't list 3D mice: {err}");
+            return;
+        }
+        for info in self.api.device_list() {
+            if !is_3d_mouse(info.vendor_id(), info.product_id()) || self.open.contains_key(info.path()) {
+                continue;
+            }
+            let name = info.product_string().unwrap_or("3D mouse").to_owned();
+            let device = match info.open_device(&self.api) {
+                Ok(device) => device,
+                Err(err) => {
+                    log::warn!("Can't read {name}: {err}");
+                    continue;
+                }
+            };
+            log::info!("Reading {name}");
+            let path = info.path().to_owned();
+            self.open.insert(path.clone(), name);
+            let sender = self.sender.clone();
+            thread::spawn(move || read(device, path, sender));
+        }
+    }
+
+    /// Moves `camera` by the caps' readings for the time since the last call. Returns `None`
+    /// while the caps are at rest.
+    pub(crate) fn fly(&mut self, camera: Camera) -> Option<Camera> {
+        let now = Instant::now();
+        let step = (now - self.last_step).min(MAX_STEP).as_secs_f32();
+        self.last_step = now;
+        if self.last_scan.is_none_or(|last_scan| now - last_scan >= SCAN_INTERVAL) {
+            self.last_scan = Some(now);
+            self.scan();
+        }
+        for event in self.events.try_iter() {
+            match event {
+                Event::Motion { translation, rotation } => {
+                    self.translation = translation.unwrap_or(self.translation);
+                    self.rotation = rotation.unwrap_or(self.rotation);
+                }
+                Event::Closed(path) => {
+                    if let Some(name) = self.open.remove(&path) {
+                        log::info!("{name} was unplugged");
+                    }
+                    self.translation = [0.0; 3];
+                    self.rotation = [0.0; 3];
+                }
+            }
+        }
+
+        let axis = |value: f32| {
+            let travel = ((value.abs() - DEAD_ZONE) / (1.0 - DEAD_ZONE)).max(0.0);
+            travel.copysign(value)
+        };
+        // The device's y points away from the user and its z down.
+        let [x, y, z] = self.translation.map(axis);
+        let [pitch, _, yaw] = self.rotation.map(axis);
+        if [x, y, z, pitch, yaw].iter().all(|&value| value == 0.0) {
+            return None;
+        }
+        let target = camera.target;
+        let camera = camera.orbit(target, -yaw * ROTATE_SPEED * step)
+            .tilt(target, -pitch * ROTATE_SPEED * step)
+            .pan(x * PAN_SPEED * step, -z * PAN_SPEED * step);
+        Some(camera.dolly(ZOOM_SPEED.powf(-y * step)))
+    }
+}
+
+/// Whether the USB `vendor` and `product` ids are those of a 3Dconnexion device.
+fn is_3d_mouse(vendor: u16, product: u16) -> bool {
+    match vendor {
+        vendor if vendor == VENDORS[0] => LOGITECH_PRODUCTS.contains(&product),
+        vendor => vendor == VENDORS[1],
+    }
+}
+
+/// Sends the motion reports of `device`, at `path`, until it is unplugged or nothing
+/// listens.
+fn read(device: HidDevice, path: CString, sender: Sender<Event>) {
+    // Each read is one report: an id, then little-endian 16-bit readings. Older devices send
+    // translation and rotation as separate reports, newer ones all six in the first.
+    let mut report = [0; 64];
+    while let Ok(length @ 1..) = device.read(&mut report) {
+        let readings: Vec<f32> = report[1..length]
+            .chunks_exact(2)
+            .map(|bytes| (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / FULL_SCALE).clamp(-1.0, 1.0))
+            .collect();
+        let three = |from: usize| readings.get(from..from + 3).map(|axes| [axes[0], axes[1], axes[2]]);
+        let event = match report[0] {
+            TRANSLATION => Event::Motion { translation: three(0), rotation: three(3) },
+            ROTATION => Event::Motion { translation: None, rotation: three(0) },
+            _ => continue,
+        };
+        if sender.send(event).is_err() {
+            return;
+        }
+    }
+    let _ = sender.send(Event::Closed(path));
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    fmt,
+};
+
+use crate::{
+    Scene,
+    Vec3,
+};
+
+/// GPU memory allocated by the renderer, measured against its budget.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MemoryUsage {
+    pub allocated: u64,
+    pub budget: u64,
+}
+
+impl MemoryUsage {
+    pub fn over_budget(&self) -> bool {
+        self.allocated > self.budget
+    }
+}
+
+/// GPU time spent in one render pass.
+#[derive(Copy, Clone, Debug)]
+pub struct PassTiming {
+    pub name: &'static str,
+    pub milliseconds: f64,
+}
+
+/// Runtime statistics of the renderer.
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub memory: MemoryUsage,
+    /// Per-pass GPU timings, empty when timestamp queries are unsupported.
+    pub passes: Vec<PassTiming>,
+    /// Fragments shaded to NaN or infinity in a recent frame, while `Settings::nan_check` is on.
+    pub non_finite_fragments: u32,
+}
+
+/// Summary and validation report of a loaded scene.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SceneStats {
+    pub meshes: usize,
+    /// Clusters of nearby triangles the finest levels of detail are split into.
+    pub meshlets: usize,
+    pub triangles: usize,
+    /// Points drawn as splats.
+    pub points: usize,
+    pub vertices: usize,
+    pub materials: usize,
+    pub textures: usize,
+    pub degenerate_triangles: usize,
+    /// Edges shared by more than two triangles.
+    pub non_manifold_edges: usize,
+    pub primitives_without_normals: usize,
+    pub primitives_without_uvs: usize,
+    /// Normal-mapped primitives without a `TANGENT` attribute, whose tangents
+    /// [`tangents::generate`](crate::geometry::tangents::generate) derives in MikkTSpace.
+    pub primitives_without_tangents: usize,
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl SceneStats {
+    pub(crate) fn new(scene: &Scene) -> Self {
+        let mut stats = Self {
+            meshes: scene.meshes.len(),
+            points: scene.splats.len(),
+            materials: scene.materials.len(),
+            textures: scene.textures,
+            primitives_without_normals: scene.primitives_without_normals,
+            primitives_without_uvs: scene.primitives_without_uvs,
+            primitives_without_tangents: scene.primitives_without_tangents,
+            ..Default::default()
+        };
+        let mut bounds: Option<(Vec3, Vec3)> = None;
+        for mesh in &scene.meshes {
+            let lod = &mesh.lods[0];
+            let first = lod.first_vertex as usize;
+            let vertices = &scene.vertices[first..first + lod.num_vertices as usize];
+            let mut welded = HashMap::new();
+            let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+            for triangle in vertices.chunks_exact(3) {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|v| {
+                    let key = [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+                    let next = welded.len();
+                    *welded.entry(key).or_insert(next)
+                });
+                let (e1, e2) = (triangle[1] - triangle[0], triangle[2] - triangle[0]);
+                if e1.cross(e2).length() == 0.0 {
+                    stats.degenerate_triangles += 1;
+                }
+                for (u, v) in [(a, b), (b, c), (c, a)] {
+                    *edges.entry((u.min(v), u.max(v))).or_default() += 1;
+                }
+            }
+            stats.meshlets += lod.meshlets.len();
+            stats.triangles += vertices.len() / 3;
+            stats.vertices += welded.len();
+            stats.non_manifold_edges += edges.values().filter(|&&faces| faces > 2).count();
+            bounds = Some(match bounds {
+                Some((min, max)) => (Vec3::min(min, mesh.min), Vec3::max(max, mesh.max)),
+                None => (mesh.min, mesh.max),
+            });
+        }
+        for splat in &scene.splats {
+            let position = Vec3::from(splat.position);
+            bounds = Some(match bounds {
+                Some((min, max)) => (Vec3::min(min, position), Vec3::max(max, position)),
+                None => (position, position),
+            });
+        }
+        if let Some(volume) = &scene.volume {
+            bounds = Some(match bounds {
+                Some((min, max)) => (Vec3::min(min, volume.min), Vec3::max(max, volume.max)),
+                None => (volume.min, volume.max),
+            });
+        }
+        if let Some((min, max)) = bounds {
+            stats.min = min;
+            stats.max = max;
+        }
+        stats
+    }
+
+    /// Centre of the scene bounding box.
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        if self.degenerate_triangles > 0 {
+            warnings.push(format!("{} degenerate triangles", self.degenerate_triangles));
+        }
+        if self.non_manifold_edges > 0 {
+            warnings.push(format!("{} non-manifold edges", self.non_manifold_edges));
+        }
+        if self.primitives_without_normals > 0 {
+            warnings.push(format!("{} primitives without normals", self.primitives_without_normals));
+        }
+        if self.primitives_without_uvs > 0 {
+            warnings.push(format!("{} primitives without UVs", self.primitives_without_uvs));
+        }
+        warnings
+    }
+}
+
+impl fmt::Display for SceneStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "meshes:    {}", self.meshes)?;
+        writeln!(f, "meshlets:  {}", self.meshlets)?;
+        writeln!(f, "triangles: {}", self.triangles)?;
+        if self.points > 0 {
+            writeln!(f, "points:    {}", self.points)?;
+        }
+        writeln!(f, "vertices:  {}", self.vertices)?;
+        writeln!(f, "materials: {}", self.materials)?;
+        writeln!(f, "textures:  {}", self.textures)?;
+        write!(
+            f,
+            "bounds:    ({}, {}, {}) - ({}, {}, {})",
+            self.min.x, self.min.y, self.min.z,
+            self.max.x, self.max.y, self.max.z,
+        )?;
+        for warning in self.warnings() {
+            write!(f, "\nwarning:   {warning}")?;
+        }
+        Ok(())
+    }
+}
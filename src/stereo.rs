@@ -0,0 +1,118 @@
+use std::str::FromStr;
+
+use wgpu::ColorWrites;
+
+use crate::camera::Camera;
+
+/// How the two eye images of a stereo frame are combined.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StereoMode {
+    /// Left eye in the left half of the frame, right eye in the right half.
+    SideBySide,
+    /// Red-cyan anaglyph: the left eye writes red, the right eye green and blue.
+    Anaglyph,
+}
+
+impl FromStr for StereoMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "side-by-side" | "sbs" => Ok(StereoMode::SideBySide),
+            "anaglyph" => Ok(StereoMode::Anaglyph),
+            _ => Err(format!("unknown stereo mode {s:?}")),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stereo {
+    pub mode: StereoMode,
+    /// Interpupillary distance in scene units.
+    pub ipd: f32,
+}
+
+impl Stereo {
+    pub fn new(mode: StereoMode) -> Self {
+        Self {
+            mode,
+            ipd: 0.064,
+        }
+    }
+}
+
+/// Colour channels an eye's pass writes to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Channels {
+    All,
+    Red,
+    Cyan,
+}
+
+impl Channels {
+    pub(crate) const ALL: [Channels; 3] = [Channels::All, Channels::Red, Channels::Cyan];
+
+    pub(crate) fn write_mask(self) -> ColorWrites {
+        match self {
+            Channels::All => ColorWrites::ALL,
+            Channels::Red => ColorWrites::RED,
+            Channels::Cyan => ColorWrites::GREEN | ColorWrites::BLUE,
+        }
+    }
+}
+
+/// One view rendered into part of the frame.
+pub(crate) struct Eye {
+    pub(crate) label: &'static str,
+    pub(crate) camera: Camera,
+    /// Viewport `(x, y, width, height)` in pixels.
+    pub(crate) viewport: (u32, u32, u32, u32),
+    pub(crate) channels: Channels,
+}
+
+/// Views needed for a `width` by `height` frame, in drawing order.
+pub(crate) fn eyes(camera: &Camera, stereo: Option<Stereo>, width: u32, height: u32) -> Vec<Eye> {
+    let Some(stereo) = stereo else {
+        return vec![Eye {
+            label: "Render Pass",
+            camera: *camera,
+            viewport: (0, 0, width, height),
+            channels: Channels::All,
+        }];
+    };
+    let left = camera.shifted(-stereo.ipd / 2.0);
+    let right = camera.shifted(stereo.ipd / 2.0);
+    match stereo.mode {
+        StereoMode::SideBySide => {
+            let half = (width / 2).max(1);
+            vec![
+                Eye {
+                    label: "Render Pass (left)",
+                    camera: left,
+                    viewport: (0, 0, half, height),
+                    channels: Channels::All,
+                },
+                Eye {
+                    label: "Render Pass (right)",
+                    camera: right,
+                    viewport: (half, 0, (width - half).max(1), height),
+                    channels: Channels::All,
+                },
+            ]
+        }
+        StereoMode::Anaglyph => vec![
+            Eye {
+                label: "Render Pass (left)",
+                camera: left,
+                viewport: (0, 0, width, height),
+                channels: Channels::Red,
+            },
+            Eye {
+                label: "Render Pass (right)",
+                camera: right,
+                viewport: (0, 0, width, height),
+                channels: Channels::Cyan,
+            },
+        ],
+    }
+}
@@ -0,0 +1,209 @@
+//! Reader for STL files in ASCII or binary form, as exported by CAD and 3D-printing tools.
+//!
+//! STL has no units; coordinates are scaled to metres from the units given in [`StlOptions`].
+//! Triangles are wound counter-clockwise around their facet normal, generating the normal from
+//! the winding when the file leaves it zero.
+
+use std::{
+    io,
+    str::SplitAsciiWhitespace,
+};
+
+use crate::{
+    Vec3,
+    convert::Units,
+};
+
+/// Bytes before the first facet of a binary STL: an 80-byte header and a triangle count.
+const BINARY_HEADER: usize = 84;
+
+/// Bytes per facet of a binary STL: normal, three vertices and an attribute count.
+const BINARY_FACET: usize = 50;
+
+/// Length unit of an STL file's coordinates.
+pub type StlUnits = Units;
+
+/// How an STL file is placed in the scene.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StlOptions {
+    /// Millimetres by default, the usual unit of CAD and slicer exports.
+    pub units: Units,
+    /// Extra scale applied after converting to metres.
+    pub scale: f32,
+    /// Turns the model from Z-up, the CAD convention, to the renderer's Y-up.
+    pub z_up: bool,
+}
+
+impl Default for StlOptions {
+    fn default() -> Self {
+        Self {
+            units: Units::Millimeters,
+            scale: 1.0,
+            z_up: true,
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("STL: {message}"))
+}
+
+/// Reads the facets of an STL file as a triangle list.
+pub(crate) fn parse(bytes: &[u8], options: &StlOptions) -> io::Result<Vec<Vec3>> {
+    // Binary files may also start with "solid", so their size decides.
+    let binary_count = bytes.get(80..BINARY_HEADER)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize)
+        .filter(|&count| bytes.len() == BINARY_HEADER + count * BINARY_FACET);
+    let facets = match binary_count {
+        Some(count) => read_binary(bytes, count),
+        None if bytes.starts_with(b"solid") => read_ascii(bytes)?,
+        None => return Err(invalid("neither ASCII nor a binary file of the right size")),
+    };
+
+    let scale = options.units.scale() * options.scale;
+    let place = |v: Vec3| if options.z_up {
+        vec3![v.x * scale, v.z * scale, -v.y * scale]
+    } else {
+        v * scale
+    };
+    let mut triangles = Vec::with_capacity(facets.len() * 3);
+    for (normal, [a, b, c]) in facets {
+        let winding = (b - a).cross(c - a);
+        // Facets wound against their stored normal are flipped to face it.
+        if normal.length() > 0.0 && winding.dot(normal) < 0.0 {
+            triangles.extend([place(a), place(c), place(b)]);
+        } else {
+            triangles.extend([place(a), place(b), place(c)]);
+        }
+    }
+    Ok(triangles)
+}
+
+/// Facets as their stored normal and vertices.
+type Facet = (Vec3, [Vec3; 3]);
+
+fn read_binary(bytes: &[u8], count: usize) -> Vec<Facet> {
+    let float = |offset: usize| f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+    let vector = |offset: usize| vec3![float(offset), float(offset + 4), float(offset + 8)];
+    (0..count)
+        .map(|facet| {
+            let start = BINARY_HEADER + facet * BINARY_FACET;
+            (vector(start), [vector(start + 12), vector(start + 24), vector(start + 36)])
+        })
+        .collect()
+}
+
+fn read_ascii(bytes: &[u8]) -> io::Result<Vec<Facet>> {
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid("ASCII file is not UTF-8"))?;
+    let mut words = text.split_ascii_whitespace();
+    let mut facets = Vec::new();
+    while let Some(word) = words.next() {
+        if word != "facet" {
+            continue;
+        }
+        if words.next() != Some("normal") {
+            return Err(invalid("facet without normal"));
+        }
+        let normal = vector(&mut words)?;
+        let mut vertices = [Vec3::default(); 3];
+        for vertex in &mut vertices {
+            if words.find(|&word| word == "vertex").is_none() {
+                return Err(invalid("facet with fewer than three vertices"));
+            }
+            *vertex = vector(&mut words)?;
+        }
+        facets.push((normal, vertices));
+    }
+    Ok(facets)
+}
+
+fn vector(words: &mut SplitAsciiWhitespace) -> io::Result<Vec3> {
+    let mut component = || words.next()
+        .and_then(|word| word.parse().ok())
+        .ok_or_else(|| invalid("malformed vector"));
+    Ok(vec3![component()?, component()?, component()?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "solid part
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1000 0 0
+      vertex 0 1000 0
+    endloop
+  endfacet
+endsolid part
+";
+
+    const METRES: StlOptions = StlOptions { units: Units::Meters, scale: 1.0, z_up: false };
+
+    fn binary(facets: &[Facet]) -> Vec<u8> {
+        let mut bytes = vec![0; 80];
+        bytes.extend((facets.len() as u32).to_le_bytes());
+        for (normal, vertices) in facets {
+            for v in [normal].into_iter().chain(vertices) {
+                bytes.extend([v.x, v.y, v.z].iter().flat_map(|c| c.to_le_bytes()));
+            }
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_millimetres_z_up() {
+        let triangles = parse(ASCII.as_bytes(), &StlOptions::default()).unwrap();
+        assert_eq!(triangles, [vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0], vec3![0.0, 0.0, -1.0]]);
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        let facet = (vec3![0.0, 0.0, 1.0], [vec3![0.0, 0.0, 0.0], vec3![1000.0, 0.0, 0.0], vec3![0.0, 1000.0, 0.0]]);
+        let options = StlOptions::default();
+        assert_eq!(parse(&binary(&[facet]), &options).unwrap(), parse(ASCII.as_bytes(), &options).unwrap());
+    }
+
+    #[test]
+    fn binary_header_may_start_with_solid() {
+        let mut bytes = binary(&[(Vec3::default(), [vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0]])]);
+        bytes[..5].copy_from_slice(b"solid");
+        assert_eq!(parse(&bytes, &METRES).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn facets_wound_against_their_normal_are_flipped() {
+        let vertices = [vec3![0.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], vec3![1.0, 0.0, 0.0]];
+        let triangles = parse(&binary(&[(vec3![0.0, 0.0, 1.0], vertices)]), &METRES).unwrap();
+        assert_eq!(triangles, [vertices[0], vertices[2], vertices[1]]);
+        // A zero normal keeps the file's winding.
+        let triangles = parse(&binary(&[(Vec3::default(), vertices)]), &METRES).unwrap();
+        assert_eq!(triangles, vertices);
+    }
+
+    #[test]
+    fn scale_applies_after_units() {
+        let options = StlOptions { units: Units::Centimeters, scale: 2.0, z_up: false };
+        let triangles = parse(ASCII.as_bytes(), &options).unwrap();
+        assert_eq!(triangles[1], vec3![20.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        for bytes in [
+            &b"not an stl"[..],
+            b"solid x facet vertex 0 0 0",
+            b"solid x facet normal 0 0 1 outer loop vertex 0 0 0 vertex 1 0 0 endloop endfacet",
+            b"solid x facet normal 0 zero 1",
+            &binary(&[])[..83],
+        ] {
+            assert_eq!(parse(bytes, &METRES).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        // A binary file cut short no longer matches its count.
+        let mut bytes = binary(&[(Vec3::default(), [Vec3::default(); 3])]);
+        bytes.pop();
+        assert!(parse(&bytes, &METRES).is_err());
+    }
+}
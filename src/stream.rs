@@ -0,0 +1,223 @@
+//! Renders frames continuously offscreen and hands them to other processes, such as
+//! compositors, encoders and streaming tools, without capturing the screen.
+//!
+//! Frames are `width * height * 4` bytes of sRGB RGBA with premultiplied alpha, in rows from
+//! the top down without padding.
+//!
+//! A shared-memory ring is a file, best placed on a memory-backed file system such as
+//! `/dev/shm`, that consumers map. It starts with a 64-byte little-endian header:
+//!
+//! | offset | type     | field                                                  |
+//! |--------|----------|--------------------------------------------------------|
+//! | 0      | `[u8;8]` | magic `RTFRAMES`                                       |
+//! | 8      | `u32`    | layout version, 1                                      |
+//! | 12     | `u32`    | width                                                  |
+//! | 16     | `u32`    | height                                                 |
+//! | 20     | `u32`    | number of slots                                        |
+//! | 24     | `u64`    | number of the latest complete frame, `u64::MAX` before |
+//!
+//! Slots follow, each the number of the frame it holds as a `u64` and then the frame. Frame
+//! `n` goes into slot `n % slots`. While a slot is being written its number is `u64::MAX`, so a
+//! reader copies a slot and keeps the copy if the slot's number was the same before and after.
+
+use std::{
+    fs::File,
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use wgpu::{
+    Extent3d,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureUsages,
+    TextureViewDescriptor,
+};
+
+use crate::{
+    Camera,
+    Scene,
+    Script,
+    Settings,
+    headless,
+    renderer::{self, Renderer},
+    timeline::{FRAME_STEP, TimeStep},
+};
+
+const MAGIC: &[u8; 8] = b"RTFRAMES";
+const VERSION: u32 = 1;
+const HEADER_BYTES: u64 = 64;
+/// Offset of the latest frame's number in the header.
+const LATEST_OFFSET: u64 = 24;
+/// Frame number marking an empty ring or a slot being written.
+const NO_FRAME: u64 = u64::MAX;
+
+/// Where [`stream`] writes frames.
+#[derive(Clone, Debug)]
+pub enum StreamOutput {
+    /// Raw frames back to back on standard output, e.g. for
+    /// `ffmpeg -f rawvideo -pix_fmt rgba -s WxH -i -`. Anything else printed to standard
+    /// output, such as a script's `print`, ends up between frames.
+    Stdout,
+    /// A ring of the last `slots` frames in the file at `path`, laid out as described in the
+    /// [module documentation](self).
+    SharedMemory { path: PathBuf, slots: u32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamOptions {
+    pub width: u32,
+    pub height: u32,
+    pub output: StreamOutput,
+    /// Frames drawn per second at most. `None` draws them as fast as the GPU allows.
+    pub fps: Option<f32>,
+    /// Stops after this many frames. `None` streams until the process ends or standard output
+    /// is closed.
+    pub frames: Option<u64>,
+    /// Degrees the camera orbits the scene each frame.
+    pub turntable: Option<f32>,
+    /// Script whose `on_frame` runs before each frame, moving the camera and changing settings.
+    pub script: Option<Script>,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            output: StreamOutput::Stdout,
+            fps: None,
+            frames: None,
+            turntable: None,
+            script: None,
+        }
+    }
+}
+
+/// The file a shared-memory ring is written to.
+struct Ring {
+    file: File,
+    slots: u32,
+    frame_bytes: u64,
+}
+
+impl Ring {
+    fn create(path: &Path, width: u32, height: u32, slots: u32) -> io::Result<Self> {
+        let slots = slots.max(1);
+        let frame_bytes = width as u64 * height as u64 * 4;
+        let mut file = File::create(path)?;
+        file.set_len(HEADER_BYTES + slots as u64 * (8 + frame_bytes))?;
+        let mut header = Vec::with_capacity(HEADER_BYTES as usize);
+        header.extend_from_slice(MAGIC);
+        for field in [VERSION, width, height, slots] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        header.extend_from_slice(&NO_FRAME.to_le_bytes());
+        file.write_all(&header)?;
+        for slot in 0..slots {
+            file.seek(SeekFrom::Start(HEADER_BYTES + slot as u64 * (8 + frame_bytes)))?;
+            file.write_all(&NO_FRAME.to_le_bytes())?;
+        }
+        Ok(Self { file, slots, frame_bytes })
+    }
+
+    fn write_at(&mut self, offset: u64, bytes: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(bytes)
+    }
+
+    fn write(&mut self, frame: u64, pixels: &[u8]) -> io::Result<()> {
+        let slot = HEADER_BYTES + (frame % self.slots as u64) * (8 + self.frame_bytes);
+        self.write_at(slot, &NO_FRAME.to_le_bytes())?;
+        self.write_at(slot + 8, pixels)?;
+        self.write_at(slot, &frame.to_le_bytes())?;
+        self.write_at(LATEST_OFFSET, &frame.to_le_bytes())
+    }
+}
+
+/// Renders frames of `scene` offscreen and writes each to `options.output` until
+/// `options.frames` have been written or standard output is closed.
+pub fn stream(scene: Scene, settings: Settings, camera: Camera, mut options: StreamOptions) -> io::Result<()> {
+    let (width, height) = (options.width.max(1), options.height.max(1));
+    let mut ring = match &options.output {
+        StreamOutput::Stdout => None,
+        StreamOutput::SharedMemory { path, slots } => {
+            log::info!("Streaming {width}x{height} frames to {}", path.display());
+            Some(Ring::create(path, width, height, *slots)?)
+        }
+    };
+    let mut stdout = io::stdout().lock();
+
+    let (device, queue, max_buffer_size) = renderer::headless_device();
+    let format = TextureFormat::Rgba8UnormSrgb;
+    let target = device.create_texture(&TextureDescriptor {
+        label: Some("Stream target"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = target.create_view(&TextureViewDescriptor::default());
+    let mut renderer = Renderer::new(device, queue, format, max_buffer_size, scene, settings);
+    renderer.camera = camera;
+    let center = renderer.scene_stats.center();
+
+    let interval = options.fps.filter(|fps| *fps > 0.0).map(|fps| Duration::from_secs_f32(1.0 / fps));
+    let mut next = Instant::now();
+    // Animation moves on by a frame's time at the stream's rate, however long frames take.
+    let step = options.fps.filter(|fps| *fps > 0.0).map_or(FRAME_STEP, |fps| 1.0 / fps);
+    for frame in 1.. {
+        if options.frames.is_some_and(|frames| frame > frames) {
+            break;
+        }
+        let time = TimeStep {
+            time: (frame - 1) as f32 * step,
+            step: if frame == 1 { 0.0 } else { step },
+            speed: 1.0,
+            restarted: false,
+        };
+        renderer.advance(time);
+        if let Some(script) = &mut options.script {
+            let mut camera = renderer.camera;
+            if let Err(err) = script.on_frame(frame, time.time, &mut renderer.settings, &mut camera) {
+                log::error!("Script failed: {err}");
+                options.script = None;
+            }
+            renderer.camera = camera;
+        }
+        if let Some(degrees) = options.turntable {
+            renderer.camera = renderer.camera.orbit(center, degrees);
+        }
+
+        renderer.render(&view, width, height, None);
+        let image = headless::read_back(&renderer, &target);
+        match &mut ring {
+            Some(ring) => ring.write(frame, &image)?,
+            None => match stdout.write_all(&image).and_then(|()| stdout.flush()) {
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => break,
+                result => result?,
+            },
+        }
+
+        if let Some(interval) = interval {
+            next += interval;
+            let now = Instant::now();
+            match next.checked_duration_since(now) {
+                Some(wait) => thread::sleep(wait),
+                // Running behind: pace from now rather than rushing to catch up.
+                None => next = now,
+            }
+        }
+    }
+    Ok(())
+}
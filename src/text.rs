@@ -0,0 +1,508 @@
+//! Text drawn from a glyph atlas: the statistics HUD, names of selected meshes and annotations
+//! pinned to points in the scene.
+//!
+//! Glyphs are rasterized from the bundled Cantarell font (SIL Open Font License, see
+//! `res/fonts/OFL.txt`) at a single size, covering printable ASCII and Latin-1, and again
+//! whenever the UI scale changes. Other characters are drawn as `?`.
+
+use std::collections::HashMap;
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+
+use bytemuck::{Pod, Zeroable};
+
+use wgpu::{
+    util::{
+        BufferInitDescriptor,
+        DeviceExt,
+        TextureDataOrder,
+    },
+    BindGroup,
+    BindGroupDescriptor,
+    BindGroupEntry,
+    BindGroupLayout,
+    BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry,
+    BindingResource,
+    BindingType,
+    BlendState,
+    Buffer,
+    BufferAddress,
+    BufferBindingType,
+    BufferDescriptor,
+    BufferUsages,
+    ColorTargetState,
+    CommandEncoder,
+    Device,
+    Extent3d,
+    FilterMode,
+    FragmentState,
+    MultisampleState,
+    PipelineCompilationOptions,
+    PipelineLayoutDescriptor,
+    PrimitiveState,
+    Queue,
+    RenderPass,
+    RenderPipeline,
+    RenderPipelineDescriptor,
+    Sampler,
+    SamplerBindingType,
+    SamplerDescriptor,
+    ShaderStages,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureSampleType,
+    TextureUsages,
+    TextureViewDescriptor,
+    TextureViewDimension,
+    VertexAttribute,
+    VertexBufferLayout,
+    VertexState,
+    VertexStepMode,
+    vertex_attr_array,
+};
+
+use crate::{
+    memory::MemoryTracker,
+    shaders,
+    stereo::Channels,
+    upload::Uploader,
+    Vec3,
+};
+
+const FONT: &[u8] = include_bytes!("../res/fonts/Cantarell-Regular.ttf");
+/// Height of a line of text in logical pixels.
+const FONT_SIZE: f32 = 16.0;
+const ATLAS_WIDTH: u32 = 512;
+/// Gap around each glyph in the atlas, so linear filtering doesn't bleed between them.
+const PADDING: u32 = 1;
+/// Characters that fall back to this one when the atlas doesn't have them.
+const FALLBACK: char = '?';
+
+/// A label drawn over the render at a point in the scene, whatever is in front of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    pub position: Vec3,
+    /// Text centred above the point. Newlines start new lines.
+    pub text: String,
+    /// Linear RGBA colour.
+    pub color: [f32; 4],
+}
+
+impl Annotation {
+    /// A white annotation.
+    pub fn new(position: Vec3, text: impl Into<String>) -> Self {
+        Self {
+            position,
+            text: text.into(),
+            color: [1.0; 4],
+        }
+    }
+}
+
+/// Where a block of text sits relative to its anchor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Align {
+    /// The anchor is the top-left corner of the first line.
+    TopLeft,
+    /// Centred above the anchor, clear of it by a few pixels.
+    Above,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct TextUniform {
+    viewport: [f32; 2],
+    _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub(crate) struct GlyphInstance {
+    anchor: [f32; 4],
+    offset: [f32; 2],
+    size: [f32; 2],
+    uv: [f32; 4],
+    color: [f32; 4],
+}
+
+impl GlyphInstance {
+    const ATTRIBS: [VertexAttribute; 5] = vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x4,
+        4 => Float32x4,
+    ];
+
+    /// Glyphs are per-instance data; each instance draws a six-vertex quad.
+    fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// A glyph's place in the atlas and how it sits on the baseline.
+#[derive(Copy, Clone, Debug, Default)]
+struct Glyph {
+    /// Top-left corner of the glyph's box from the pen position on the baseline, in pixels.
+    offset: [f32; 2],
+    size: [f32; 2],
+    uv: [f32; 4],
+    advance: f32,
+}
+
+/// Glyph metrics of the atlas, which lay out text without the GPU.
+struct GlyphAtlas {
+    glyphs: HashMap<char, Glyph>,
+    /// Physical pixels per logical pixel the glyphs were rasterized at.
+    scale: f32,
+    ascent: f32,
+    line_height: f32,
+    width: u32,
+    height: u32,
+    /// One coverage byte per pixel.
+    pixels: Vec<u8>,
+}
+
+impl GlyphAtlas {
+    /// Rasterizes the glyphs at `ui_scale` physical pixels per logical pixel.
+    fn new(ui_scale: f32) -> Self {
+        let font = FontRef::try_from_slice(FONT).expect("bundled font is valid");
+        let scale = PxScale::from(FONT_SIZE * ui_scale);
+        let scaled = font.as_scaled(scale);
+        let characters = (' '..='~').chain('\u{a0}'..='\u{ff}');
+
+        // Shelf packing: glyphs fill rows left to right, each row as tall as its tallest glyph.
+        let mut placed = vec![];
+        let (mut x, mut y, mut row_height) = (PADDING, PADDING, 0);
+        for c in characters {
+            let id = font.glyph_id(c);
+            let outline = font.outline_glyph(id.with_scale_and_position(scale, point(0.0, 0.0)));
+            let bounds = outline.as_ref().map(|outline| outline.px_bounds());
+            let (width, height) = bounds.map_or((0, 0), |bounds| (bounds.width() as u32, bounds.height() as u32));
+            if x + width + PADDING > ATLAS_WIDTH {
+                (x, y, row_height) = (PADDING, y + row_height + PADDING, 0);
+            }
+            placed.push((c, scaled.h_advance(id), outline, x, y));
+            x += width + PADDING;
+            row_height = row_height.max(height);
+        }
+        let height = (y + row_height + PADDING).next_power_of_two();
+
+        let mut pixels = vec![0; (ATLAS_WIDTH * height) as usize];
+        let mut glyphs = HashMap::new();
+        for (c, advance, outline, x, y) in placed {
+            let mut glyph = Glyph { advance, ..Default::default() };
+            if let Some(outline) = outline {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    let index = (y + gy) * ATLAS_WIDTH + x + gx;
+                    pixels[index as usize] = (coverage.clamp(0.0, 1.0) * 255.0) as u8;
+                });
+                let (atlas_width, atlas_height) = (ATLAS_WIDTH as f32, height as f32);
+                glyph.offset = [bounds.min.x, bounds.min.y];
+                glyph.size = [bounds.width(), bounds.height()];
+                glyph.uv = [
+                    x as f32 / atlas_width,
+                    y as f32 / atlas_height,
+                    (x as f32 + bounds.width()) / atlas_width,
+                    (y as f32 + bounds.height()) / atlas_height,
+                ];
+            }
+            glyphs.insert(c, glyph);
+        }
+        Self {
+            glyphs,
+            scale: ui_scale,
+            ascent: scaled.ascent(),
+            line_height: scaled.height() + scaled.line_gap(),
+            width: ATLAS_WIDTH,
+            height,
+            pixels,
+        }
+    }
+
+    fn glyph(&self, c: char) -> &Glyph {
+        self.glyphs.get(&c).unwrap_or(&self.glyphs[&FALLBACK])
+    }
+
+    fn line_width(&self, line: &str) -> f32 {
+        line.chars().map(|c| self.glyph(c).advance).sum()
+    }
+
+    /// Lays out `text` at `anchor`, a world position or, with `world` false, pixels from the
+    /// top-left corner of the viewport.
+    fn layout(&self, text: &str, anchor: Vec3, world: bool, align: Align, color: [f32; 4], out: &mut Vec<GlyphInstance>) {
+        let lines: Vec<&str> = text.lines().collect();
+        let top = match align {
+            Align::TopLeft => 0.0,
+            Align::Above => -(lines.len() as f32 * self.line_height) - (4.0 * self.scale).round(),
+        };
+        let anchor = [anchor.x, anchor.y, anchor.z, if world { 1.0 } else { 0.0 }];
+        for (row, line) in lines.iter().enumerate() {
+            let mut pen = match align {
+                Align::TopLeft => 0.0,
+                Align::Above => -(self.line_width(line) * 0.5).round(),
+            };
+            let baseline = top + self.ascent + row as f32 * self.line_height;
+            for c in line.chars() {
+                let glyph = self.glyph(c);
+                if glyph.size[0] > 0.0 {
+                    out.push(GlyphInstance {
+                        anchor,
+                        offset: [(pen + glyph.offset[0]).round(), baseline.round() + glyph.offset[1]],
+                        size: glyph.size,
+                        uv: glyph.uv,
+                        color,
+                    });
+                }
+                pen += glyph.advance;
+            }
+        }
+    }
+}
+
+/// Draws text queued each frame over everything else.
+pub(crate) struct TextPass {
+    /// One pipeline per [`Channels`] variant.
+    pipelines: [RenderPipeline; 3],
+    atlas: GlyphAtlas,
+    uniform_buffer: Buffer,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    instance_buffer: Option<Buffer>,
+    /// Glyphs queued for this frame.
+    instances: Vec<GlyphInstance>,
+}
+
+impl TextPass {
+    pub(crate) fn new(
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        camera_bind_group_layout: &BindGroupLayout,
+        memory: &mut MemoryTracker,
+    ) -> Self {
+        let atlas = GlyphAtlas::new(1.0);
+        let shader = shaders::create_module(device, "Text shader", include_str!("text.wgsl"), &[], &[]);
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Text buffer"),
+            contents: bytemuck::cast_slice(&[TextUniform::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        memory.track(&uniform_buffer);
+
+        // Glyphs land on whole pixels at the size they were rasterized.
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Glyph atlas sampler"),
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("text_bind_group_layout"),
+        });
+
+        let bind_group = Self::atlas_bind_group(device, queue, &bind_group_layout, &uniform_buffer, &sampler, &atlas);
+        memory.track_bytes(atlas.pixels.len() as u64);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = Channels::ALL.map(|channels| device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Text Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[GlyphInstance::desc()],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: channels.write_mask(),
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        }));
+
+        Self {
+            pipelines,
+            atlas,
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            instance_buffer: None,
+            instances: vec![],
+        }
+    }
+
+    /// Uploads `atlas` and binds it with the uniforms and sampler.
+    fn atlas_bind_group(
+        device: &Device,
+        queue: &Queue,
+        bind_group_layout: &BindGroupLayout,
+        uniform_buffer: &Buffer,
+        sampler: &Sampler,
+        atlas: &GlyphAtlas,
+    ) -> BindGroup {
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("Glyph atlas"),
+                size: Extent3d {
+                    width: atlas.width,
+                    height: atlas.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            &atlas.pixels,
+        );
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        device.create_bind_group(&BindGroupDescriptor {
+            layout: bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("text_bind_group"),
+        })
+    }
+
+    /// Rasterizes the glyphs again at `scale` physical pixels per logical pixel, if they
+    /// aren't already.
+    pub(crate) fn set_scale(&mut self, device: &Device, queue: &Queue, memory: &mut MemoryTracker, scale: f32) {
+        if scale == self.atlas.scale || scale <= 0.0 || !scale.is_finite() {
+            return;
+        }
+        memory.release_bytes(self.atlas.pixels.len() as u64);
+        self.atlas = GlyphAtlas::new(scale);
+        memory.track_bytes(self.atlas.pixels.len() as u64);
+        self.bind_group = Self::atlas_bind_group(device, queue, &self.bind_group_layout, &self.uniform_buffer, &self.sampler, &self.atlas);
+    }
+
+    /// Queues `text` at the top-left corner of the viewport, `x` and `y` pixels in.
+    pub(crate) fn screen(&mut self, text: &str, x: f32, y: f32, color: [f32; 4]) {
+        self.atlas.layout(text, vec3![x, y, 0.0], false, Align::TopLeft, color, &mut self.instances);
+    }
+
+    /// Queues `text` centred above `position` in the scene.
+    pub(crate) fn world(&mut self, text: &str, position: Vec3, color: [f32; 4]) {
+        self.atlas.layout(text, position, true, Align::Above, color, &mut self.instances);
+    }
+
+    /// Uploads the text queued since the last upload, growing the instance buffer if it's full.
+    pub(crate) fn upload(&mut self, device: &Device, encoder: &mut CommandEncoder, uploader: &mut Uploader, memory: &mut MemoryTracker) {
+        let bytes: &[u8] = bytemuck::cast_slice(&self.instances);
+        if self.instance_buffer.as_ref().is_none_or(|buffer| buffer.size() < bytes.len() as u64) && !bytes.is_empty() {
+            if let Some(buffer) = self.instance_buffer.take() {
+                memory.release(&buffer);
+            }
+            let buffer = device.create_buffer(&BufferDescriptor {
+                label: Some("Glyph buffer"),
+                size: (bytes.len() as u64).next_power_of_two(),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            memory.track(&buffer);
+            self.instance_buffer = Some(buffer);
+        }
+        if let Some(buffer) = &self.instance_buffer {
+            uploader.write(device, encoder, buffer, 0, bytes);
+        }
+    }
+
+    /// Sets the size of the viewport drawn into next, in pixels.
+    pub(crate) fn set_viewport(&self, device: &Device, encoder: &mut CommandEncoder, uploader: &mut Uploader, width: u32, height: u32) {
+        let uniform = TextUniform {
+            viewport: [width as f32, height as f32],
+            _padding: [0.0; 2],
+        };
+        uploader.write(device, encoder, &self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Draws the uploaded text into the current viewport, whose size was last
+    /// [set](Self::set_viewport).
+    pub(crate) fn draw(&self, render_pass: &mut RenderPass, camera_bind_group: &BindGroup, channels: Channels) {
+        let Some(buffer) = self.instance_buffer.as_ref().filter(|_| !self.instances.is_empty()) else {
+            return;
+        };
+        render_pass.set_pipeline(&self.pipelines[channels as usize]);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        render_pass.draw(0..6, 0..self.instances.len() as u32);
+    }
+
+    /// Empties the queue for the next frame's text.
+    pub(crate) fn clear(&mut self) {
+        self.instances.clear();
+    }
+}
@@ -0,0 +1,68 @@
+#include "camera.wgsl"
+
+struct Text {
+    // Size of the viewport in pixels.
+    viewport: vec2f,
+};
+
+struct GlyphInput {
+    // World position with w = 1, or pixels from the viewport's top-left corner with w = 0.
+    @location(0) anchor: vec4f,
+    // Top-left corner and size of the glyph in pixels from the anchor, y down.
+    @location(1) offset: vec2f,
+    @location(2) size: vec2f,
+    // Atlas coordinates of the top-left and bottom-right corners.
+    @location(3) uv: vec4f,
+    @location(4) color: vec4f,
+};
+
+struct GlyphOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) color: vec4f,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> text: Text;
+@group(1) @binding(1) var atlas: texture_2d<f32>;
+@group(1) @binding(2) var atlas_sampler: sampler;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    glyph: GlyphInput,
+) -> GlyphOutput {
+    var corners = array<vec2f, 6>(
+        vec2f(0.0, 0.0), vec2f(1.0, 0.0), vec2f(1.0, 1.0),
+        vec2f(0.0, 0.0), vec2f(1.0, 1.0), vec2f(0.0, 1.0),
+    );
+    let corner = corners[index];
+    var out: GlyphOutput;
+    out.uv = mix(glyph.uv.xy, glyph.uv.zw, corner);
+    out.color = glyph.color;
+
+    var anchor = glyph.anchor.xy;
+    if glyph.anchor.w > 0.5 {
+        let view_position = (camera.view * vec4f(glyph.anchor.xyz, 1.0)).xyz;
+        let clip = project(view_position);
+        // Perspective is the projection before orthographic.
+        if camera.projection <= PROJECTION_ORTHOGRAPHIC && view_position.z > -camera.znear {
+            // Behind the camera: collapse the quad so nothing is drawn.
+            out.clip_position = vec4f(0.0, 0.0, 0.0, 1.0);
+            return out;
+        }
+        let ndc = clip.xy / clip.w;
+        anchor = (vec2f(ndc.x, -ndc.y) * 0.5 + 0.5) * text.viewport;
+    }
+    // Whole pixels keep the glyphs as sharp as they were rasterized.
+    let pixel = round(anchor) + glyph.offset + corner * glyph.size;
+    let ndc = pixel / text.viewport * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0);
+    out.clip_position = vec4f(ndc, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: GlyphOutput) -> @location(0) vec4f {
+    let alpha = in.color.a * textureSample(atlas, atlas_sampler, in.uv).r;
+    return vec4f(in.color.rgb * alpha, alpha);
+}
@@ -0,0 +1,133 @@
+//! The viewer's clock for everything that moves on its own: animation passes, particles,
+//! physics, the turntable and scripts' `on_frame`. Pausing it freezes them all, and its speed
+//! slows or hurries them together.
+
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// Most time a frame moves the timeline on by, so a stalled frame doesn't jump it.
+const MAX_STEP: Duration = Duration::from_millis(100);
+
+/// Seconds a frame step moves the timeline by while paused, at 30 frames per second.
+pub(crate) const FRAME_STEP: f32 = 1.0 / 30.0;
+
+/// How the timeline moved for a frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub(crate) struct TimeStep {
+    /// Position of the timeline in seconds.
+    pub(crate) time: f32,
+    /// Seconds played since the last frame, zero while paused.
+    pub(crate) step: f32,
+    /// Playback rate, zero while paused.
+    pub(crate) speed: f32,
+    /// Whether the timeline went back, by looping or seeking, so simulations that can only go
+    /// forward start over.
+    pub(crate) restarted: bool,
+}
+
+/// Play, pause and seek controls over the time animations run at.
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    time: f32,
+    playing: bool,
+    speed: f32,
+    loop_range: Option<Range<f32>>,
+    last_tick: Option<Instant>,
+    /// Time moved back since the last tick.
+    rewound: bool,
+}
+
+impl Default for Timeline {
+    /// Playing from zero at normal speed, without looping.
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            playing: true,
+            speed: 1.0,
+            loop_range: None,
+            last_tick: None,
+            rewound: false,
+        }
+    }
+}
+
+impl Timeline {
+    /// Position in seconds.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn toggle(&mut self) {
+        self.playing = !self.playing;
+    }
+
+    /// Moves to `time` seconds, clamped to the loop range if there is one. Simulations start over
+    /// when moving back.
+    pub fn seek(&mut self, time: f32) {
+        let time = match &self.loop_range {
+            Some(range) => time.clamp(range.start, range.end),
+            None => time.max(0.0),
+        };
+        self.rewound |= time < self.time;
+        self.time = time;
+    }
+
+    /// Playback rate, 1 being real time.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Sets the playback rate; the timeline doesn't run backwards, so it is at least zero.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn loop_range(&self) -> Option<Range<f32>> {
+        self.loop_range.clone()
+    }
+
+    /// Plays `range` in seconds over and over, or the whole timeline once with `None`.
+    pub fn set_loop(&mut self, range: Option<Range<f32>>) {
+        self.loop_range = range.filter(|range| range.end > range.start);
+        if let Some(range) = &self.loop_range
+            && !range.contains(&self.time)
+        {
+            self.seek(range.start);
+        }
+    }
+
+    /// Moves the timeline on by the time since the last tick, once per frame.
+    pub(crate) fn tick(&mut self) -> TimeStep {
+        let now = Instant::now();
+        let elapsed = self.last_tick.map_or(0.0, |last_tick| (now - last_tick).min(MAX_STEP).as_secs_f32());
+        self.last_tick = Some(now);
+        let step = if self.playing { elapsed * self.speed } else { 0.0 };
+        self.time += step;
+        if let Some(range) = &self.loop_range
+            && self.time >= range.end
+        {
+            self.time = range.start + (self.time - range.start) % (range.end - range.start);
+            self.rewound = true;
+        }
+        TimeStep {
+            time: self.time,
+            step,
+            speed: if self.playing { self.speed } else { 0.0 },
+            restarted: std::mem::take(&mut self.rewound),
+        }
+    }
+}
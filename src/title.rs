@@ -0,0 +1,86 @@
+//! The viewer window's title, kept up to date with the scene and how drawing is going, and its
+//! icon.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use winit::window::{Icon, Window};
+
+/// Title shown unless another is set, see [`RayTracer::set_title`](crate::RayTracer::set_title).
+pub(crate) const DEFAULT_TITLE: &str = "{scene} - Ray Tracer - {fps} fps, {progress}%";
+
+/// Time between title updates, which some window systems are slow to take.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Side of the default icon in pixels.
+const ICON_SIZE: u32 = 32;
+
+/// Fills in the title's placeholders twice a second.
+pub(crate) struct TitleBar {
+    pub(crate) template: String,
+    /// Title last given to the window.
+    shown: String,
+    frames: u32,
+    since: Instant,
+}
+
+impl TitleBar {
+    pub(crate) fn new(template: String) -> Self {
+        Self {
+            template,
+            shown: String::new(),
+            frames: 0,
+            since: Instant::now(),
+        }
+    }
+
+    /// Counts a frame of the scene at `scene_path` drawn at `scale` of the full resolution and
+    /// retitles `window` if it's time to.
+    pub(crate) fn frame(&mut self, window: &Window, scene_path: &Path, scale: f32) {
+        self.frames += 1;
+        let elapsed = self.since.elapsed();
+        if elapsed < UPDATE_INTERVAL {
+            return;
+        }
+        let fps = self.frames as f32 / elapsed.as_secs_f32();
+        self.frames = 0;
+        self.since = Instant::now();
+        let title = self.template
+            .replace("{scene}", &scene_name(scene_path))
+            .replace("{fps}", &format!("{fps:.0}"))
+            .replace("{progress}", &format!("{:.0}", scale * 100.0));
+        if title != self.shown {
+            window.set_title(&title);
+            self.shown = title;
+        }
+    }
+}
+
+/// File name of the scene at `path`, as titles show it.
+pub(crate) fn scene_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+/// A shaded sphere, so the window isn't left with the system's blank icon.
+pub(crate) fn default_icon() -> Icon {
+    let light = [-0.5f32, -0.5, 0.707];
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let radius = ICON_SIZE as f32 / 2.0;
+            let nx = (x as f32 + 0.5 - radius) / (radius - 1.0);
+            let ny = (y as f32 + 0.5 - radius) / (radius - 1.0);
+            let nz2 = 1.0 - nx * nx - ny * ny;
+            if nz2 < 0.0 {
+                rgba.extend_from_slice(&[0; 4]);
+                continue;
+            }
+            let diffuse = (nx * light[0] + ny * light[1] + nz2.sqrt() * light[2]).max(0.0);
+            let shade = 0.2 + 0.8 * diffuse;
+            rgba.extend_from_slice(&[(80.0 * shade) as u8, (160.0 * shade) as u8, (255.0 * shade) as u8, 255]);
+        }
+    }
+    Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE).expect("icon is the size it says")
+}
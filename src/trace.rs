@@ -0,0 +1,109 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use crate::stats::PassTiming;
+
+#[derive(Copy, Clone, Debug)]
+enum Track {
+    Cpu,
+    Gpu,
+}
+
+struct TraceEvent {
+    name: &'static str,
+    track: Track,
+    start: f64,
+    duration: f64,
+}
+
+/// Records frame timings over a fixed number of frames and writes them as a Chrome trace.
+///
+/// The output loads in `chrome://tracing` and Perfetto. GPU passes are laid out back to back
+/// from the start of the frame they were read back in, since GPU timestamps share no clock
+/// with the CPU.
+pub(crate) struct TraceRecorder {
+    path: PathBuf,
+    frames_left: u32,
+    origin: Instant,
+    events: Vec<TraceEvent>,
+}
+
+impl TraceRecorder {
+    pub(crate) fn new(path: PathBuf, frames: u32) -> Self {
+        Self {
+            path,
+            frames_left: frames,
+            origin: Instant::now(),
+            events: vec![],
+        }
+    }
+
+    fn micros(&self, instant: Instant) -> f64 {
+        instant.duration_since(self.origin).as_secs_f64() * 1e6
+    }
+
+    pub(crate) fn cpu(&mut self, name: &'static str, start: Instant, end: Instant) {
+        self.events.push(TraceEvent {
+            name,
+            track: Track::Cpu,
+            start: self.micros(start),
+            duration: end.duration_since(start).as_secs_f64() * 1e6,
+        });
+    }
+
+    pub(crate) fn gpu(&mut self, timings: &[PassTiming], frame_start: Instant) {
+        let mut start = self.micros(frame_start);
+        for timing in timings {
+            let duration = timing.milliseconds * 1e3;
+            self.events.push(TraceEvent {
+                name: timing.name,
+                track: Track::Gpu,
+                start,
+                duration,
+            });
+            start += duration;
+        }
+    }
+
+    /// Counts down the capture window and returns `true` once it has been written out.
+    pub(crate) fn end_frame(&mut self) -> bool {
+        self.frames_left = self.frames_left.saturating_sub(1);
+        if self.frames_left > 0 {
+            return false;
+        }
+        match self.write() {
+            Ok(()) => log::info!("Wrote frame trace to {}", self.path.display()),
+            Err(err) => log::error!("Failed to write frame trace to {}: {err}", self.path.display()),
+        }
+        true
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        writeln!(out, "{{\"traceEvents\":[")?;
+        for (tid, name) in [(Track::Cpu, "CPU"), (Track::Gpu, "GPU")] {
+            writeln!(
+                out,
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{name}\"}}}},",
+                tid as u32,
+            )?;
+        }
+        for (i, event) in self.events.iter().enumerate() {
+            let separator = if i + 1 < self.events.len() { "," } else { "" };
+            writeln!(
+                out,
+                "{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}}}{separator}",
+                event.name,
+                event.track as u32,
+                event.start,
+                event.duration,
+            )?;
+        }
+        writeln!(out, "]}}")?;
+        out.flush()
+    }
+}
@@ -0,0 +1,49 @@
+use wgpu::{
+    util::StagingBelt,
+    Buffer,
+    BufferAddress,
+    BufferSize,
+    CommandEncoder,
+    Device,
+};
+
+/// Size of each staging chunk. Larger writes get a dedicated chunk of their own.
+const CHUNK_SIZE: BufferAddress = 64 * 1024;
+
+/// Streams per-frame buffer writes through a recycled pool of mapped staging buffers.
+///
+/// Writes are recorded as copies into the frame's encoder. Call [`Uploader::finish`] before
+/// submitting the encoder and [`Uploader::recall`] after, so chunks can be reused next frame.
+pub(crate) struct Uploader {
+    belt: StagingBelt,
+}
+
+impl Uploader {
+    pub(crate) fn new() -> Self {
+        Self {
+            belt: StagingBelt::new(CHUNK_SIZE),
+        }
+    }
+
+    pub(crate) fn write(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        target: &Buffer,
+        offset: BufferAddress,
+        data: &[u8],
+    ) {
+        let Some(size) = BufferSize::new(data.len() as BufferAddress) else {
+            return;
+        };
+        self.belt.write_buffer(encoder, target, offset, size, device).copy_from_slice(data);
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.belt.finish();
+    }
+
+    pub(crate) fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
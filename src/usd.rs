@@ -0,0 +1,784 @@
+//! Reader for a subset of USD: text `.usda` layers, and `.usdz` packages whose root layer is one.
+//!
+//! Meshes are read with their `xformOp` transforms applied, along with cameras and the diffuse
+//! colour of `UsdPreviewSurface` materials. References, variants, time samples and the binary
+//! `.usdc` encoding are not supported.
+
+use std::{
+    collections::HashMap,
+    io,
+};
+
+use crate::{
+    Camera,
+    Projection,
+    Vec3,
+    Light,
+    LightKind,
+    camera::{self, IDENTITY, Mat4},
+};
+
+/// Magic bytes of a binary USD crate file.
+const CRATE_MAGIC: &[u8] = b"PXR-USDC";
+
+pub(crate) struct UsdData {
+    /// World-space triangle lists, one per mesh, with the mesh's prim path.
+    pub(crate) meshes: Vec<(String, Vec<Vec3>)>,
+    /// Diffuse colour of each `UsdPreviewSurface` material, in document order, with the
+    /// material's prim path.
+    pub(crate) materials: Vec<(String, [f32; 4])>,
+    pub(crate) cameras: Vec<Camera>,
+    pub(crate) lights: Vec<Light>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("USD: {message}"))
+}
+
+/// Reads a `.usda` layer or a `.usdz` package.
+pub(crate) fn parse(bytes: &[u8]) -> io::Result<UsdData> {
+    let layer = if bytes.starts_with(b"PK\x03\x04") {
+        root_layer(bytes)?
+    } else {
+        bytes
+    };
+    if layer.starts_with(CRATE_MAGIC) {
+        return Err(invalid("binary .usdc layers are not supported, convert them with usdcat"));
+    }
+    let text = std::str::from_utf8(layer).map_err(|_| invalid("layer is not UTF-8 text"))?;
+    let layer = Parser::new(text).layer()?;
+    Ok(Stage::read(&layer))
+}
+
+/// The first file in a `.usdz` package, which is its root layer.
+///
+/// Packages store their files uncompressed, so the zip local headers can be walked directly.
+fn root_layer(bytes: &[u8]) -> io::Result<&[u8]> {
+    let u16_at = |offset: usize| bytes.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let u32_at = |offset: usize| bytes.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let truncated = || invalid("truncated .usdz package");
+    let compression = u16_at(8).ok_or_else(truncated)?;
+    let size = u32_at(18).ok_or_else(truncated)?;
+    let name_length = u16_at(26).ok_or_else(truncated)?;
+    let extra_length = u16_at(28).ok_or_else(truncated)?;
+    if compression != 0 {
+        return Err(invalid("compressed .usdz packages are not supported"));
+    }
+    let start = 30 + name_length + extra_length;
+    bytes.get(start..start + size).ok_or_else(truncated)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Number(f64),
+    Text(String),
+    /// A `<prim path>`.
+    Path(String),
+    /// An `@asset path@`.
+    Asset(String),
+    Symbol(char),
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(f64),
+    Text(String),
+    /// Tuples and arrays alike.
+    List(Vec<Value>),
+    Other,
+}
+
+impl Value {
+    fn number(&self) -> Option<f32> {
+        match self {
+            Value::Number(number) => Some(*number as f32),
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn list(&self) -> &[Value] {
+        match self {
+            Value::List(values) => values,
+            _ => &[],
+        }
+    }
+
+    fn numbers(&self) -> Vec<f32> {
+        self.list().iter().filter_map(Value::number).collect()
+    }
+
+    fn vec3(&self) -> Option<Vec3> {
+        match self.numbers()[..] {
+            [x, y, z] => Some(vec3![x, y, z]),
+            _ => None,
+        }
+    }
+}
+
+struct Prim {
+    /// Whether the prim is a `class`, only used as a template.
+    class: bool,
+    type_name: String,
+    path: String,
+    /// Properties by name, including suffixes such as `.connect`.
+    properties: HashMap<String, Value>,
+    children: Vec<Prim>,
+}
+
+struct Layer {
+    /// Whether the layer's `upAxis` is Z, rather than Y like the renderer.
+    z_up: bool,
+    prims: Vec<Prim>,
+}
+
+struct Parser {
+    /// Tokens and the line each starts on.
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Self {
+        Self {
+            tokens: tokenize(text),
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.position).map_or(usize::MAX, |&(_, line)| line)
+    }
+
+    fn next(&mut self) -> io::Result<Token> {
+        let token = self.peek().cloned().ok_or_else(|| invalid("unexpected end of file"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, symbol: char) -> io::Result<()> {
+        match self.next()? {
+            Token::Symbol(found) if found == symbol => Ok(()),
+            token => Err(invalid(&format!("expected {symbol:?}, found {token:?}"))),
+        }
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    /// Skips a bracketed group whose opening bracket has been consumed.
+    fn skip_group(&mut self, close: char) -> io::Result<()> {
+        loop {
+            match self.next()? {
+                Token::Symbol(symbol) if symbol == close => return Ok(()),
+                Token::Symbol('(') => self.skip_group(')')?,
+                Token::Symbol('[') => self.skip_group(']')?,
+                Token::Symbol('{') => self.skip_group('}')?,
+                _ => {}
+            }
+        }
+    }
+
+    fn layer(&mut self) -> io::Result<Layer> {
+        let mut layer = Layer {
+            z_up: false,
+            prims: vec![],
+        };
+        if self.eat('(') {
+            while !self.eat(')') {
+                match self.next()? {
+                    Token::Word(word) if word == "upAxis" => {
+                        self.expect('=')?;
+                        layer.z_up = self.value()?.text() == Some("Z");
+                    }
+                    Token::Symbol('(') => self.skip_group(')')?,
+                    Token::Symbol('[') => self.skip_group(']')?,
+                    Token::Symbol('{') => self.skip_group('}')?,
+                    _ => {}
+                }
+            }
+        }
+        while self.peek().is_some() {
+            layer.prims.push(self.prim("")?);
+        }
+        Ok(layer)
+    }
+
+    /// Parses `def Type "name" (metadata) { ... }` below the prim at `parent`.
+    fn prim(&mut self, parent: &str) -> io::Result<Prim> {
+        let class = match self.next()? {
+            Token::Word(specifier) if ["def", "over", "class"].contains(&specifier.as_str()) => specifier == "class",
+            token => return Err(invalid(&format!("expected a prim, found {token:?}"))),
+        };
+        let type_name = match self.peek() {
+            Some(Token::Word(type_name)) => {
+                let type_name = type_name.clone();
+                self.position += 1;
+                type_name
+            }
+            _ => String::new(),
+        };
+        let Token::Text(name) = self.next()? else {
+            return Err(invalid("expected a prim name"));
+        };
+        if self.eat('(') {
+            self.skip_group(')')?;
+        }
+        self.expect('{')?;
+        let mut prim = Prim {
+            class,
+            type_name,
+            path: format!("{parent}/{name}"),
+            properties: HashMap::new(),
+            children: vec![],
+        };
+        while !self.eat('}') {
+            match self.peek() {
+                Some(Token::Word(word)) if ["def", "over", "class"].contains(&word.as_str()) => {
+                    let child = self.prim(&prim.path)?;
+                    prim.children.push(child);
+                }
+                Some(Token::Word(_)) => self.property(&mut prim)?,
+                _ => {
+                    // Variant sets and anything else the subset does not cover.
+                    if let Token::Symbol(open @ ('(' | '[' | '{')) = self.next()? {
+                        self.skip_group(match open {
+                            '(' => ')',
+                            '[' => ']',
+                            _ => '}',
+                        })?;
+                    }
+                }
+            }
+        }
+        Ok(prim)
+    }
+
+    /// Parses `[uniform] type name [= value] [(metadata)]`, or `rel name [= <path>]`, all on
+    /// one line.
+    fn property(&mut self, prim: &mut Prim) -> io::Result<()> {
+        let line = self.line();
+        let mut name = String::new();
+        while let Some(Token::Word(word)) = self.peek() {
+            if self.line() != line {
+                break;
+            }
+            name = word.clone();
+            self.position += 1;
+        }
+        if self.eat('=') {
+            let value = self.value()?;
+            prim.properties.insert(name, value);
+        }
+        if self.line() == line && self.eat('(') {
+            self.skip_group(')')?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> io::Result<Value> {
+        Ok(match self.next()? {
+            Token::Number(number) => Value::Number(number),
+            Token::Text(text) => Value::Text(text),
+            Token::Path(_) | Token::Asset(_) | Token::Word(_) => Value::Other,
+            Token::Symbol(open @ ('(' | '[')) => {
+                let close = if open == '(' { ')' } else { ']' };
+                let mut values = vec![];
+                while !self.eat(close) {
+                    values.push(self.value()?);
+                    self.eat(',');
+                }
+                Value::List(values)
+            }
+            Token::Symbol('{') => {
+                self.skip_group('}')?;
+                Value::Other
+            }
+            token => return Err(invalid(&format!("unexpected {token:?}"))),
+        })
+    }
+}
+
+fn tokenize(text: &str) -> Vec<(Token, usize)> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    let mut line = 1;
+    while let Some(&(start, c)) = chars.peek() {
+        let token_line = line;
+        let token = match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+                continue;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '#' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                continue;
+            }
+            '"' | '\'' => {
+                let rest = &text[start..];
+                let (quote, length) = if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+                    (&rest[..3], 3)
+                } else {
+                    (&rest[..1], 1)
+                };
+                let body = &rest[length..];
+                let end = body.find(quote).unwrap_or(body.len());
+                let string = &body[..end];
+                line += string.matches('\n').count();
+                let consumed = length + end + length.min(body.len() - end);
+                while chars.next_if(|&(index, _)| index < start + consumed).is_some() {}
+                Token::Text(string.into())
+            }
+            '<' | '@' => {
+                let close = if c == '<' { '>' } else { '@' };
+                chars.next();
+                let mut string = String::new();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c != close) {
+                    string.push(c);
+                }
+                chars.next();
+                if c == '<' {
+                    Token::Path(string)
+                } else {
+                    Token::Asset(string)
+                }
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let mut string = String::new();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| {
+                    c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.')
+                }) {
+                    string.push(c);
+                }
+                Token::Number(string.parse().unwrap_or(f64::NAN))
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut string = String::new();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_alphanumeric() || matches!(c, '_' | ':' | '.')) {
+                    string.push(c);
+                }
+                // Array types such as `point3f[]`.
+                if text[start + string.len()..].starts_with("[]") {
+                    chars.next();
+                    chars.next();
+                    string.push_str("[]");
+                }
+                match string.as_str() {
+                    "inf" => Token::Number(f64::INFINITY),
+                    "nan" => Token::Number(f64::NAN),
+                    _ => Token::Word(string),
+                }
+            }
+            c => {
+                chars.next();
+                Token::Symbol(c)
+            }
+        };
+        tokens.push((token, token_line));
+    }
+    tokens
+}
+
+/// Converts parsed prims into renderer data.
+struct Stage {
+    data: UsdData,
+}
+
+impl Stage {
+    fn read(layer: &Layer) -> UsdData {
+        let mut stage = Stage {
+            data: UsdData {
+                meshes: vec![],
+                materials: vec![],
+                cameras: vec![],
+                lights: vec![],
+            },
+        };
+        // Z-up layers are turned to Y-up.
+        let root = if layer.z_up {
+            rotation([-90.0, 0.0, 0.0])
+        } else {
+            IDENTITY
+        };
+        for prim in &layer.prims {
+            stage.visit(prim, &root);
+        }
+        stage.data
+    }
+
+    fn visit(&mut self, prim: &Prim, parent: &Mat4) {
+        // Classes are templates for other prims, not part of the scene.
+        if prim.class {
+            return;
+        }
+        let transform = camera::mul(parent, &local_transform(prim));
+        match prim.type_name.as_str() {
+            "Mesh" => self.mesh(prim, &transform),
+            "Camera" => self.camera(prim, &transform),
+            "DistantLight" => self.light(prim, &transform, LightKind::Directional),
+            "SphereLight" => self.light(prim, &transform, LightKind::Point),
+            "Material" => self.material(prim),
+            _ => {}
+        }
+        for child in &prim.children {
+            self.visit(child, &transform);
+        }
+    }
+
+    fn mesh(&mut self, prim: &Prim, transform: &Mat4) {
+        let property = |name: &str| prim.properties.get(name);
+        let points: Vec<Vec3> = property("points")
+            .map(|points| points.list().iter().filter_map(Value::vec3).collect())
+            .unwrap_or_default();
+        let counts = property("faceVertexCounts").map(Value::numbers).unwrap_or_default();
+        let indices = property("faceVertexIndices").map(Value::numbers).unwrap_or_default();
+        let left_handed = property("orientation").and_then(Value::text) == Some("leftHanded");
+        let world = |index: f32| points.get(index as usize).map(|&point| camera::transform_point(transform, point));
+        let mut triangles = vec![];
+        let mut face = indices.iter().copied();
+        for count in counts {
+            let corners: Vec<_> = face.by_ref().take(count as usize).collect();
+            // Polygons are triangulated as fans.
+            for i in 1..corners.len().saturating_sub(1) {
+                let (b, c) = if left_handed { (i + 1, i) } else { (i, i + 1) };
+                if let (Some(a), Some(b), Some(c)) = (world(corners[0]), world(corners[b]), world(corners[c])) {
+                    triangles.extend([a, b, c]);
+                }
+            }
+        }
+        self.data.meshes.push((prim.path.clone(), triangles));
+    }
+
+    /// Reads the diffuse colour of a material's `UsdPreviewSurface` shader.
+    fn material(&mut self, prim: &Prim) {
+        let Some(shader) = prim.children.iter().find(|child| {
+            child.properties.get("info:id").and_then(Value::text) == Some("UsdPreviewSurface")
+        }) else {
+            return;
+        };
+        let [r, g, b] = match shader.properties.get("inputs:diffuseColor").map(Value::numbers).as_deref() {
+            Some(&[r, g, b]) => [r, g, b],
+            _ => [0.18, 0.18, 0.18],
+        };
+        let opacity = shader.properties.get("inputs:opacity").and_then(Value::number).unwrap_or(1.0);
+        self.data.materials.push((prim.path.clone(), [r, g, b, opacity]));
+    }
+
+    fn camera(&mut self, prim: &Prim, transform: &Mat4) {
+        let property = |name: &str| prim.properties.get(name);
+        let number = |name: &str, default: f32| property(name).and_then(Value::number).unwrap_or(default);
+        let focal_length = number("focalLength", 50.0);
+        let vertical_aperture = number("verticalAperture", 15.2908);
+        let (znear, zfar) = match property("clippingRange").map(Value::numbers).as_deref() {
+            Some(&[znear, zfar]) => (znear, zfar),
+            _ => (1.0, 1_000_000.0),
+        };
+        // Orbiting pivots around the focus distance, or the origin when there is none.
+        let eye = camera::transform_point(transform, vec3![0.0, 0.0, 0.0]);
+        let distance = number("focusDistance", 0.0);
+        let distance = if distance > 0.0 { distance } else { eye.length().max(znear) };
+        self.data.cameras.push(Camera {
+            fovy: 2.0 * (vertical_aperture / (2.0 * focal_length)).atan().to_degrees(),
+            znear,
+            zfar,
+            projection: match property("projection").and_then(Value::text) {
+                Some("orthographic") => Projection::Orthographic,
+                _ => Projection::Perspective,
+            },
+            ..Camera::from_transform(transform, distance)
+        });
+    }
+}
+
+impl Stage {
+    /// Reads a UsdLux light, whose intensity is scaled by two to the power of its exposure.
+    fn light(&mut self, prim: &Prim, transform: &Mat4, kind: LightKind) {
+        // Older files leave out the `inputs:` namespace.
+        let property = |name: &str| prim.properties.get(&format!("inputs:{name}")).or_else(|| prim.properties.get(name));
+        let intensity = property("intensity").and_then(Value::number).unwrap_or(1.0);
+        let exposure = property("exposure").and_then(Value::number).unwrap_or(0.0);
+        let color = match property("color").map(Value::numbers).as_deref() {
+            Some(&[r, g, b]) => [r, g, b],
+            _ => [1.0; 3],
+        };
+        let position = camera::transform_point(transform, vec3![0.0, 0.0, 0.0]);
+        let direction = camera::transform_point(transform, vec3![0.0, 0.0, -1.0]) - position;
+        self.data.lights.push(Light {
+            name: Some(prim.path.clone()),
+            kind,
+            position,
+            direction: direction.normalize(),
+            color,
+            intensity: intensity * exposure.exp2(),
+            range: None,
+        });
+    }
+}
+
+/// The prim's `xformOp`s composed in `xformOpOrder`, the first applied last.
+fn local_transform(prim: &Prim) -> Mat4 {
+    let Some(order) = prim.properties.get("xformOpOrder") else {
+        return IDENTITY;
+    };
+    order.list().iter().filter_map(Value::text).fold(IDENTITY, |transform, op| {
+        let value = prim.properties.get(op);
+        let vector = value.and_then(Value::vec3);
+        let angle = value.and_then(Value::number);
+        let kind = op.trim_start_matches("xformOp:");
+        let kind = kind.split(':').next().unwrap_or(kind);
+        let matrix = match (kind, vector, angle) {
+            ("translate", Some(v), _) => translation(v),
+            ("scale", Some(v), _) => scaling(v),
+            ("rotateXYZ", Some(v), _) => rotation([v.x, v.y, v.z]),
+            ("rotateX", _, Some(angle)) => rotation([angle, 0.0, 0.0]),
+            ("rotateY", _, Some(angle)) => rotation([0.0, angle, 0.0]),
+            ("rotateZ", _, Some(angle)) => rotation([0.0, 0.0, angle]),
+            // Rows of a USD matrix, which transforms row vectors, are our columns.
+            ("transform", _, _) => match value.map(|value| value.list().iter().map(Value::numbers).collect::<Vec<_>>()) {
+                Some(rows) if rows.len() == 4 && rows.iter().all(|row| row.len() == 4) => {
+                    [0, 1, 2, 3].map(|column| [0, 1, 2, 3].map(|row| rows[column][row]))
+                }
+                _ => IDENTITY,
+            },
+            _ => {
+                log::warn!("Ignoring unsupported {op} on {}", prim.path);
+                IDENTITY
+            }
+        };
+        camera::mul(&transform, &matrix)
+    })
+}
+
+fn translation(v: Vec3) -> Mat4 {
+    let mut matrix = IDENTITY;
+    matrix[3] = [v.x, v.y, v.z, 1.0];
+    matrix
+}
+
+fn scaling(v: Vec3) -> Mat4 {
+    let mut matrix = IDENTITY;
+    matrix[0][0] = v.x;
+    matrix[1][1] = v.y;
+    matrix[2][2] = v.z;
+    matrix
+}
+
+/// Rotation by `degrees` about X, then Y, then Z.
+fn rotation(degrees: [f32; 3]) -> Mat4 {
+    let [x, y, z] = degrees.map(f32::to_radians);
+    let (sx, cx) = x.sin_cos();
+    let (sy, cy) = y.sin_cos();
+    let (sz, cz) = z.sin_cos();
+    let rx = [[1.0, 0.0, 0.0, 0.0], [0.0, cx, sx, 0.0], [0.0, -sx, cx, 0.0], [0.0, 0.0, 0.0, 1.0]];
+    let ry = [[cy, 0.0, -sy, 0.0], [0.0, 1.0, 0.0, 0.0], [sy, 0.0, cy, 0.0], [0.0, 0.0, 0.0, 1.0]];
+    let rz = [[cz, sz, 0.0, 0.0], [-sz, cz, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+    camera::mul(&rz, &camera::mul(&ry, &rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(text: &str) -> UsdData {
+        parse(text.as_bytes()).unwrap()
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{a:?} != {b:?}");
+    }
+
+    const QUAD: &str = r#"#usda 1.0
+(
+    defaultPrim = "World"
+    metersPerUnit = 1
+)
+
+def Xform "World"
+{
+    double3 xformOp:translate = (0, 0, 5)
+    uniform token[] xformOpOrder = ["xformOp:translate"]
+
+    def Mesh "Quad" (
+        prepend apiSchemas = ["MaterialBindingAPI"]
+    )
+    {
+        int[] faceVertexCounts = [4]
+        int[] faceVertexIndices = [0, 1, 2, 3]
+        point3f[] points = [(0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0)]
+        rel material:binding = </Looks/Red>
+    }
+}
+"#;
+
+    #[test]
+    fn meshes_are_fanned_and_transformed() {
+        let data = stage(QUAD);
+        let (path, triangles) = &data.meshes[0];
+        assert_eq!(path, "/World/Quad");
+        let expected = [[0.0, 0.0, 5.0], [1.0, 0.0, 5.0], [1.0, 1.0, 5.0], [0.0, 0.0, 5.0], [1.0, 1.0, 5.0], [0.0, 1.0, 5.0]];
+        assert_eq!(triangles.len(), expected.len());
+        for (&vertex, expected) in triangles.iter().zip(expected) {
+            assert_near(vertex, expected.into());
+        }
+    }
+
+    #[test]
+    fn left_handed_meshes_are_rewound() {
+        let data = stage(r#"def Mesh "Tri" {
+            uniform token orientation = "leftHanded"
+            int[] faceVertexCounts = [3]
+            int[] faceVertexIndices = [0, 1, 2]
+            point3f[] points = [(0, 0, 0), (1, 0, 0), (0, 1, 0)]
+        }"#);
+        assert_eq!(data.meshes[0].1, [vec3![0.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], vec3![1.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn transforms_compose_in_op_order() {
+        // Scaled first, then rotated a quarter turn about Y, then moved up.
+        let data = stage(r#"def Xform "A" {
+            double3 xformOp:translate = (0, 1, 0)
+            float xformOp:rotateY = 90
+            float3 xformOp:scale = (2, 2, 2)
+            uniform token[] xformOpOrder = ["xformOp:translate", "xformOp:rotateY", "xformOp:scale"]
+            def Mesh "B" {
+                int[] faceVertexCounts = [3]
+                int[] faceVertexIndices = [0, 1, 2]
+                point3f[] points = [(1, 0, 0), (1, 0, 0), (1, 0, 0)]
+            }
+        }"#);
+        assert_near(data.meshes[0].1[0], vec3![0.0, 1.0, -2.0]);
+    }
+
+    #[test]
+    fn z_up_layers_are_turned_y_up() {
+        let data = stage(r#"#usda 1.0
+            ( upAxis = "Z" )
+            def Mesh "Tri" {
+                int[] faceVertexCounts = [3]
+                int[] faceVertexIndices = [0, 1, 2]
+                point3f[] points = [(0, 0, 1), (0, 0, 1), (0, 0, 1)]
+            }"#);
+        assert_near(data.meshes[0].1[0], vec3![0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn classes_are_not_part_of_the_scene() {
+        let data = stage(r#"class Mesh "Template" {
+            int[] faceVertexCounts = [3]
+            int[] faceVertexIndices = [0, 1, 2]
+            point3f[] points = [(0, 0, 0), (1, 0, 0), (0, 1, 0)]
+        }"#);
+        assert!(data.meshes.is_empty());
+    }
+
+    #[test]
+    fn preview_surface_colours() {
+        let data = stage(r#"def Scope "Looks" {
+            def Material "Red" {
+                token outputs:surface.connect = </Looks/Red/Surface.outputs:surface>
+                def Shader "Surface" {
+                    uniform token info:id = "UsdPreviewSurface"
+                    color3f inputs:diffuseColor = (0.8, 0.1, 0.1)
+                    float inputs:opacity = 0.5
+                }
+            }
+            def Material "Plain" {
+                def Shader "Surface" {
+                    uniform token info:id = "UsdPreviewSurface"
+                }
+            }
+        }"#);
+        assert_eq!(data.materials, [
+            ("/Looks/Red".to_owned(), [0.8, 0.1, 0.1, 0.5]),
+            ("/Looks/Plain".to_owned(), [0.18, 0.18, 0.18, 1.0]),
+        ]);
+    }
+
+    #[test]
+    fn cameras_and_lights() {
+        let data = stage(r#"def Camera "Cam" {
+            float focalLength = 50
+            float verticalAperture = 50
+            float2 clippingRange = (0.1, 100)
+            token projection = "orthographic"
+            double3 xformOp:translate = (0, 0, 10)
+            uniform token[] xformOpOrder = ["xformOp:translate"]
+        }
+        def SphereLight "Bulb" {
+            float inputs:intensity = 10
+            float inputs:exposure = 2
+            color3f inputs:color = (1, 0.5, 0)
+        }
+        def DistantLight "Sun" {
+            float intensity = 3
+        }"#);
+        let camera = &data.cameras[0];
+        assert!((camera.fovy - 2.0 * 0.5f32.atan().to_degrees()).abs() < 1e-4);
+        assert_eq!((camera.znear, camera.zfar, camera.projection), (0.1, 100.0, Projection::Orthographic));
+        let [bulb, sun] = &data.lights[..] else { panic!("expected two lights") };
+        assert_eq!((bulb.kind, bulb.intensity, bulb.color), (LightKind::Point, 40.0, [1.0, 0.5, 0.0]));
+        assert_eq!((sun.kind, sun.intensity), (LightKind::Directional, 3.0));
+        assert_near(sun.direction, vec3![0.0, 0.0, -1.0]);
+    }
+
+    /// A `.usdz` package holding `layer` stored uncompressed.
+    fn package(layer: &[u8], compression: u16) -> Vec<u8> {
+        let name = b"scene.usda";
+        let mut bytes = b"PK\x03\x04".to_vec();
+        bytes.extend([20, 0, 0, 0]);
+        bytes.extend(compression.to_le_bytes());
+        bytes.extend([0; 8]);
+        bytes.extend((layer.len() as u32).to_le_bytes());
+        bytes.extend((layer.len() as u32).to_le_bytes());
+        bytes.extend((name.len() as u16).to_le_bytes());
+        bytes.extend(0u16.to_le_bytes());
+        bytes.extend(name);
+        bytes.extend(layer);
+        bytes
+    }
+
+    #[test]
+    fn usdz_packages_read_their_root_layer() {
+        let data = parse(&package(QUAD.as_bytes(), 0)).unwrap();
+        assert_eq!(data.meshes.len(), 1);
+    }
+
+    #[test]
+    fn unsupported_and_malformed_files_are_errors() {
+        let package = package(QUAD.as_bytes(), 8);
+        for bytes in [
+            &b"PXR-USDC\x00\x00\x00\x00"[..],
+            &package,
+            &package[..20],
+            b"def Mesh \"Open\" {",
+            b"def Mesh {}",
+            b"mesh \"Quad\" {}",
+            b"def Mesh \"Bad\" { float x = ) }",
+            &[0xff, 0xfe],
+        ] {
+            assert_eq!(parse(bytes).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        }
+    }
+}
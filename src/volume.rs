@@ -0,0 +1,520 @@
+//! Dense voxel volumes, ray marched over the scene with emission and absorption.
+
+use std::{cmp::Ordering, io};
+
+use bytemuck::{Pod, Zeroable};
+
+use wgpu::{
+    util::{
+        BufferInitDescriptor,
+        DeviceExt,
+        TextureDataOrder,
+    },
+    AddressMode,
+    BindGroup,
+    BindGroupDescriptor,
+    BindGroupEntry,
+    BindGroupLayout,
+    BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry,
+    BindingResource,
+    BindingType,
+    BlendState,
+    Buffer,
+    BufferBindingType,
+    BufferUsages,
+    ColorTargetState,
+    Device,
+    Extent3d,
+    FilterMode,
+    FragmentState,
+    MultisampleState,
+    PipelineCompilationOptions,
+    PipelineLayoutDescriptor,
+    PrimitiveState,
+    Queue,
+    RenderPass,
+    RenderPipeline,
+    RenderPipelineDescriptor,
+    Sampler,
+    SamplerBindingType,
+    SamplerDescriptor,
+    ShaderStages,
+    TexelCopyBufferLayout,
+    Texture,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureSampleType,
+    TextureUsages,
+    TextureViewDescriptor,
+    TextureViewDimension,
+    VertexState,
+};
+
+use crate::{
+    Vec3,
+    clip::{self, ClipPlane, MAX_CLIP_PLANES},
+    memory::MemoryTracker,
+    shaders,
+    stereo::Channels,
+};
+
+/// Bytes per voxel in the grid texture: half-float density and emission.
+const VOXEL_SIZE: u64 = 4;
+
+/// How a volume's voxels are turned into light.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VolumeShading {
+    /// Extinction per scene unit for a voxel density of one.
+    pub density: f32,
+    /// Colour of light scattered by the medium, as if evenly lit.
+    pub albedo: [f32; 3],
+    /// Radiance emitted per scene unit for a voxel emission of one.
+    pub emission: [f32; 3],
+    /// Samples taken along each ray through the volume's bounds.
+    pub steps: u32,
+}
+
+impl Default for VolumeShading {
+    fn default() -> Self {
+        Self {
+            density: 1.0,
+            albedo: [0.8, 0.8, 0.8],
+            emission: [1.0, 0.5, 0.1],
+            steps: 128,
+        }
+    }
+}
+
+/// A dense voxel grid filling an axis-aligned box.
+#[derive(Clone, Debug)]
+pub(crate) struct Volume {
+    pub(crate) resolution: [u32; 3],
+    pub(crate) min: Vec3,
+    pub(crate) max: Vec3,
+    /// Density and emission per voxel, x varying fastest.
+    pub(crate) voxels: Vec<[f32; 2]>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("volume: {message}"))
+}
+
+impl Volume {
+    /// Reads a Mitsuba `.vol` grid of 32-bit floats. The first channel is density and the
+    /// second, when present, emission.
+    pub(crate) fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 48 || &bytes[..3] != b"VOL" || bytes[3] != 3 {
+            return Err(invalid("not a version 3 .vol file"));
+        }
+        let int = |offset: usize| i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let float = |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        if int(4) != 1 {
+            return Err(invalid("only float32 grids are supported"));
+        }
+        let (x, y, z, channels) = (int(8), int(12), int(16), int(20));
+        if x <= 0 || y <= 0 || z <= 0 || channels <= 0 {
+            return Err(invalid("empty grid"));
+        }
+        let channels = channels as usize;
+        let voxel_count = (x as usize).checked_mul(y as usize).and_then(|count| count.checked_mul(z as usize));
+        let data_size = voxel_count.and_then(|count| count.checked_mul(channels * 4));
+        let (Some(voxel_count), Some(data_size)) = (voxel_count, data_size) else {
+            return Err(invalid("grid too large"));
+        };
+        match (bytes.len() - 48).cmp(&data_size) {
+            Ordering::Less => return Err(invalid("truncated data")),
+            Ordering::Greater => return Err(invalid("more data than the grid's dimensions hold")),
+            Ordering::Equal => {}
+        }
+        let voxels = (0..voxel_count)
+            .map(|voxel| {
+                let offset = 48 + voxel * channels * 4;
+                let emission = if channels > 1 { float(offset + 4) } else { 0.0 };
+                [float(offset), emission]
+            })
+            .collect();
+        Ok(Self {
+            resolution: [x as u32, y as u32, z as u32],
+            min: vec3![float(24), float(28), float(32)],
+            max: vec3![float(36), float(40), float(44)],
+            voxels,
+        })
+    }
+
+    /// Reorders the voxels for bounds turned from Z-up to Y-up, so new y runs along old z and
+    /// new z against old y.
+    pub(crate) fn swap_z_up(&mut self) {
+        let [x, y, z] = self.resolution.map(|n| n as usize);
+        let mut voxels = Vec::with_capacity(self.voxels.len());
+        for new_z in 0..y {
+            for new_y in 0..z {
+                let row = x * (y - 1 - new_z + y * new_y);
+                voxels.extend_from_slice(&self.voxels[row..row + x]);
+            }
+        }
+        self.resolution = [x as u32, z as u32, y as u32];
+        self.voxels = voxels;
+    }
+}
+
+/// Converts to IEEE half precision, truncating the mantissa.
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent >= 31 {
+        sign | 0x7c00
+    } else if exponent <= 0 {
+        if exponent < -10 {
+            sign
+        } else {
+            sign | (((mantissa | 0x80_0000) >> (1 - exponent)) >> 13) as u16
+        }
+    } else {
+        sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub(crate) struct VolumeUniform {
+    /// Bounds minimum, with the step count in w.
+    min: [f32; 4],
+    /// Bounds maximum, with the density scale in w.
+    max: [f32; 4],
+    albedo: [f32; 4],
+    emission: [f32; 4],
+    clip_planes: [[f32; 4]; MAX_CLIP_PLANES],
+}
+
+impl VolumeUniform {
+    fn new(min: Vec3, max: Vec3, shading: &VolumeShading, clip_planes: &[ClipPlane]) -> Self {
+        let [r, g, b] = shading.albedo;
+        let [er, eg, eb] = shading.emission;
+        Self {
+            min: [min.x, min.y, min.z, shading.steps.max(1) as f32],
+            max: [max.x, max.y, max.z, shading.density],
+            albedo: [r, g, b, 0.0],
+            emission: [er, eg, eb, 0.0],
+            clip_planes: clip::uniforms(clip_planes),
+        }
+    }
+}
+
+/// Uploads `volume`'s voxels to a 3D texture.
+fn grid_texture(device: &Device, queue: &Queue, volume: &Volume) -> Texture {
+    let [width, height, depth] = volume.resolution;
+    device.create_texture_with_data(
+        queue,
+        &TextureDescriptor {
+            label: Some("Volume texture"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: depth,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D3,
+            format: TextureFormat::Rg16Float,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        TextureDataOrder::LayerMajor,
+        bytemuck::cast_slice(&texels(volume)),
+    )
+}
+
+/// `volume`'s voxels as half floats.
+fn texels(volume: &Volume) -> Vec<u16> {
+    volume.voxels.iter()
+        .flat_map(|voxel| voxel.map(f16_bits))
+        .collect()
+}
+
+/// Bytes of GPU memory a grid of `resolution` takes.
+fn texture_size(resolution: [u32; 3]) -> u64 {
+    let [width, height, depth] = resolution;
+    width as u64 * height as u64 * depth as u64 * VOXEL_SIZE
+}
+
+fn volume_bind_group(device: &Device, layout: &BindGroupLayout, buffer: &Buffer, texture: &Texture, sampler: &Sampler) -> BindGroup {
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    device.create_bind_group(&BindGroupDescriptor {
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(&view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("volume_bind_group"),
+    })
+}
+
+/// Ray marches a volume over whatever the pass has already drawn.
+pub(crate) struct VolumePass {
+    /// One pipeline per [`Channels`] variant.
+    pipelines: [RenderPipeline; 3],
+    pub(crate) buffer: Buffer,
+    texture: Texture,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    resolution: [u32; 3],
+    min: Vec3,
+    max: Vec3,
+    /// Bytes of GPU memory the pass holds.
+    pub(crate) allocated: u64,
+}
+
+impl VolumePass {
+    pub(crate) fn new(
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        camera_bind_group_layout: &BindGroupLayout,
+        memory: &mut MemoryTracker,
+        volume: &Volume,
+        shading: &VolumeShading,
+    ) -> Self {
+        let shader = shaders::create_module(device, "Volume shader", include_str!("volume.wgsl"), &[], &[]);
+
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Volume buffer"),
+            contents: bytemuck::cast_slice(&[VolumeUniform::new(volume.min, volume.max, shading, &[])]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        memory.track(&buffer);
+
+        let texture = grid_texture(device, queue, volume);
+        let texture_size = texture_size(volume.resolution);
+        memory.track_bytes(texture_size);
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Volume sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("volume_bind_group_layout"),
+        });
+
+        let bind_group = volume_bind_group(device, &bind_group_layout, &buffer, &texture, &sampler);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Volume Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = Channels::ALL.map(|channels| device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Volume Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: channels.write_mask(),
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        }));
+
+        Self {
+            pipelines,
+            allocated: buffer.size() + texture_size,
+            buffer,
+            texture,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            resolution: volume.resolution,
+            min: volume.min,
+            max: volume.max,
+        }
+    }
+
+    /// Shows `volume` instead, writing over the grid texture if it has the same resolution.
+    pub(crate) fn set_volume(&mut self, device: &Device, queue: &Queue, memory: &mut MemoryTracker, volume: &Volume) {
+        self.min = volume.min;
+        self.max = volume.max;
+        if volume.resolution == self.resolution {
+            let [width, height, depth] = volume.resolution;
+            queue.write_texture(
+                self.texture.as_image_copy(),
+                bytemuck::cast_slice(&texels(volume)),
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(width * VOXEL_SIZE as u32),
+                    rows_per_image: Some(height),
+                },
+                Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: depth,
+                },
+            );
+            return;
+        }
+        memory.release_bytes(texture_size(self.resolution));
+        self.texture = grid_texture(device, queue, volume);
+        memory.track_bytes(texture_size(volume.resolution));
+        self.resolution = volume.resolution;
+        self.allocated = self.buffer.size() + texture_size(volume.resolution);
+        self.bind_group = volume_bind_group(device, &self.bind_group_layout, &self.buffer, &self.texture, &self.sampler);
+    }
+
+    pub(crate) fn uniform(&self, shading: &VolumeShading, clip_planes: &[ClipPlane]) -> VolumeUniform {
+        VolumeUniform::new(self.min, self.max, shading, clip_planes)
+    }
+
+    pub(crate) fn draw(&self, render_pass: &mut RenderPass, camera_bind_group: &BindGroup, channels: Channels) {
+        render_pass.set_pipeline(&self.pipelines[channels as usize]);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `.vol` file with a grid of `resolution` and `channels`, followed by `data`.
+    fn vol(resolution: [i32; 3], channels: i32, data: &[f32]) -> Vec<u8> {
+        let mut bytes = b"VOL\x03".to_vec();
+        bytes.extend(1i32.to_le_bytes());
+        for value in resolution.into_iter().chain([channels]) {
+            bytes.extend(value.to_le_bytes());
+        }
+        for value in [-1.0f32, 0.0, 0.0, 1.0, 2.0, 0.5].into_iter().chain(data.iter().copied()) {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_the_grid_and_its_bounds() {
+        let volume = Volume::parse(&vol([2, 1, 1], 2, &[0.25, 1.0, 0.5, 0.0])).unwrap();
+        assert_eq!(volume.resolution, [2, 1, 1]);
+        assert_eq!((volume.min, volume.max), (vec3![-1.0, 0.0, 0.0], vec3![1.0, 2.0, 0.5]));
+        assert_eq!(volume.voxels, [[0.25, 1.0], [0.5, 0.0]]);
+
+        // Density alone has no emission, and channels past the second are skipped.
+        let volume = Volume::parse(&vol([1, 2, 1], 1, &[0.25, 0.5])).unwrap();
+        assert_eq!(volume.voxels, [[0.25, 0.0], [0.5, 0.0]]);
+        let volume = Volume::parse(&vol([1, 1, 1], 3, &[0.25, 0.5, 0.75])).unwrap();
+        assert_eq!(volume.voxels, [[0.25, 0.5]]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut bytes = vol([1, 1, 1], 1, &[1.0]);
+        bytes[3] = 2;
+        assert!(Volume::parse(&bytes).is_err());
+        assert!(Volume::parse(b"PLY").is_err());
+
+        let mut bytes = vol([1, 1, 1], 1, &[1.0]);
+        bytes[4] = 2;
+        let err = Volume::parse(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "volume: only float32 grids are supported");
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let bytes = vol([2, 2, 2], 1, &[1.0; 8]);
+        assert!(Volume::parse(&bytes).is_ok());
+        for length in [0, 20, 47, 48, bytes.len() - 1] {
+            assert!(Volume::parse(&bytes[..length]).is_err(), "{length} bytes");
+        }
+        assert_eq!(Volume::parse(&bytes[..48 + 7 * 4]).unwrap_err().to_string(), "volume: truncated data");
+    }
+
+    #[test]
+    fn data_must_match_the_dimensions() {
+        let err = Volume::parse(&vol([2, 2, 2], 1, &[1.0; 9])).unwrap_err();
+        assert_eq!(err.to_string(), "volume: more data than the grid's dimensions hold");
+        assert!(Volume::parse(&vol([0, 2, 2], 1, &[])).is_err());
+        assert!(Volume::parse(&vol([2, 2, 2], -1, &[])).is_err());
+        // Dimensions whose product overflows are an error rather than a panic.
+        let err = Volume::parse(&vol([i32::MAX; 3], 1, &[1.0])).unwrap_err();
+        assert_eq!(err.to_string(), "volume: grid too large");
+    }
+
+    #[test]
+    fn z_up_grids_are_turned_y_up() {
+        // Voxel values are their old (x, y, z) coordinates packed into one number.
+        let voxels = (0..2).flat_map(|z| (0..3).flat_map(move |y| (0..2).map(move |x| [(x + 10 * y + 100 * z) as f32, 0.0])));
+        let mut volume = Volume {
+            resolution: [2, 3, 2],
+            min: Vec3::default(),
+            max: Vec3::default(),
+            voxels: voxels.collect(),
+        };
+        volume.swap_z_up();
+        assert_eq!(volume.resolution, [2, 2, 3]);
+        // New (x, y, z) is old (x, z, y flipped).
+        let at = |x: usize, y: usize, z: usize| volume.voxels[x + 2 * (y + 2 * z)][0];
+        assert_eq!(at(1, 0, 0), 21.0);
+        assert_eq!(at(0, 1, 0), 120.0);
+        assert_eq!(at(0, 0, 2), 0.0);
+    }
+}
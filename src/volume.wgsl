@@ -0,0 +1,80 @@
+#include "camera.wgsl"
+#include "fullscreen.wgsl"
+
+struct Volume {
+    min: vec4f,
+    max: vec4f,
+    albedo: vec4f,
+    emission: vec4f,
+    // See `clip_planes` in shader.wgsl.
+    clip_planes: array<vec4f, 4>,
+};
+
+// Marching stops once this little light gets through.
+const MIN_TRANSMITTANCE: f32 = 0.01;
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> volume: Volume;
+@group(1) @binding(1) var grid: texture_3d<f32>;
+@group(1) @binding(2) var grid_sampler: sampler;
+
+fn clipped(position: vec3f) -> bool {
+    for (var i = 0; i < 4; i++) {
+        let plane = volume.clip_planes[i];
+        if dot(plane.xyz, position) > plane.w {
+            return true;
+        }
+    }
+    return false;
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+) -> VertexOutput {
+    return fullscreen_triangle(index, 0.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let rotation = camera_rotation();
+    var origin = camera_position();
+    if camera.projection == PROJECTION_ORTHOGRAPHIC {
+        // Orthographic rays are parallel and start across the view plane.
+        origin += rotation * vec3f(in.ndc.x / camera.proj[0][0], in.ndc.y / camera.proj[1][1], 0.0);
+    }
+    let direction = rotation * view_direction(in.ndc);
+
+    // Clip the ray to the volume bounds.
+    let safe = select(direction, vec3f(1e-8), abs(direction) < vec3f(1e-8));
+    let t0 = (volume.min.xyz - origin) / safe;
+    let t1 = (volume.max.xyz - origin) / safe;
+    let near_planes = min(t0, t1);
+    let far_planes = max(t0, t1);
+    let near = max(max(near_planes.x, near_planes.y), max(near_planes.z, 0.0));
+    let far = min(min(far_planes.x, far_planes.y), far_planes.z);
+    if far <= near {
+        discard;
+    }
+
+    let steps = u32(volume.min.w);
+    let step = (far - near) / f32(steps);
+    let size = volume.max.xyz - volume.min.xyz;
+    var transmittance = 1.0;
+    var radiance = vec3f(0.0);
+    for (var i = 0u; i < steps; i++) {
+        let position = origin + direction * (near + (f32(i) + 0.5) * step);
+        if clipped(position) {
+            continue;
+        }
+        let voxel = textureSampleLevel(grid, grid_sampler, (position - volume.min.xyz) / size, 0.0).rg;
+        let step_transmittance = exp(-voxel.r * volume.max.w * step);
+        // Light scattered or emitted within this step, dimmed by the medium in front of it.
+        radiance += transmittance * (volume.albedo.rgb * (1.0 - step_transmittance) + volume.emission.rgb * voxel.g * step);
+        transmittance *= step_transmittance;
+        if transmittance < MIN_TRANSMITTANCE {
+            break;
+        }
+    }
+    return vec4f(radiance, 1.0 - transmittance);
+}
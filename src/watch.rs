@@ -0,0 +1,53 @@
+//! Polls files for changes, so a scene can be reloaded while it is edited in another tool.
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::assets::AssetSource;
+
+/// Time between checks of the watched files.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Files and the modification time their source gave when last checked, `None` if they were
+/// missing or the source cannot tell.
+pub(crate) struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub(crate) fn new(paths: impl IntoIterator<Item = PathBuf>, source: &dyn AssetSource) -> Self {
+        let mut files: Vec<_> = paths.into_iter()
+            .map(|path| {
+                let modified = source.modified(&path);
+                (path, modified)
+            })
+            .collect();
+        files.dedup_by(|a, b| a.0 == b.0);
+        Self {
+            files,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Whether any file was modified, created or removed since the last check. Checks at most
+    /// every [`POLL_INTERVAL`], returning `false` in between.
+    pub(crate) fn changed(&mut self, source: &dyn AssetSource) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let modified = source.modified(path);
+            if modified != *last_modified {
+                log::info!("{} changed", path.display());
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}